@@ -2,7 +2,7 @@ use axum::{
     extract::{State, Path, Query},
     http::StatusCode,
     response::{IntoResponse, Json},
-    routing::get,
+    routing::{get, post},
     Router,
 };
 use serde::{Deserialize, Serialize};
@@ -10,10 +10,46 @@ use serde_json::json;
 use std::sync::Arc;
 use mongodb::bson::{doc, oid::ObjectId, DateTime};
 use crate::features::infrastructure::db::AppState;
-use crate::models::{RevisionMaterial, RevisionMaterialPurchase};
+use crate::models::{LedgerLine, RevisionMaterial, RevisionMaterialPurchase, Transaction};
 use crate::features::auth::auth_service::AuthUser;
+use crate::features::monetization::{entitlements, ledger, payments::{self, PaymentPurpose}};
+use crate::features::social::gamification::{self, Activity};
+use crate::features::social::notifications::create_notification;
 use futures::stream::StreamExt;
 
+/// Percentage of a sale credited to the uploader; the rest is the platform fee.
+/// A checkout this recent may still be paid; STK prompts time out well within it.
+const PENDING_CHECKOUT_MINUTES: i64 = 5;
+
+/// The buyer's material checkouts that may still complete.
+pub fn pending_checkout_filter(user_id: ObjectId, material_id: ObjectId, now: chrono::DateTime<chrono::Utc>) -> mongodb::bson::Document {
+    doc! {
+        "user_id": user_id,
+        "transaction_type": PaymentPurpose::Material.as_str(),
+        "target_id": material_id,
+        "status": "pending",
+        "created_at": { "$gt": DateTime::from_chrono(now - chrono::Duration::minutes(PENDING_CHECKOUT_MINUTES)) },
+    }
+}
+
+/// Whether an existing purchase row was made by this payment (a fulfilment re-run) rather
+/// than an earlier one (the buyer paid twice).
+pub fn same_payment(existing: &RevisionMaterialPurchase, tx: &Transaction) -> bool {
+    tx.id.is_some() && existing.transaction_id == tx.id
+}
+
+pub fn creator_share_percent() -> i64 {
+    std::env::var("MATERIAL_CREATOR_SHARE_PERCENT").ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|p| (0..=100).contains(p))
+        .unwrap_or(70)
+}
+
+/// The uploader's cut of `amount` cents, rounded down so the platform never pays out more than it took.
+pub fn creator_share(amount: i64, percent: i64) -> i64 {
+    amount.max(0) * percent.clamp(0, 100) / 100
+}
+
 #[derive(Deserialize)]
 pub struct MaterialFilter {
     pub category: Option<String>,
@@ -44,9 +80,9 @@ pub struct MaterialResponse {
 
 pub async fn list_materials_handler(
     State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
     Query(params): Query<MaterialFilter>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let user: Option<AuthUser> = None; // For now, don't require auth for listing
     let collection = state.mongo.collection::<RevisionMaterial>("revision_materials");
     let purchases = state.mongo.collection::<RevisionMaterialPurchase>("material_purchases");
 
@@ -90,7 +126,7 @@ pub async fn list_materials_handler(
             let m_id = m.id.unwrap();
             
             let has_purchased = if let Some(ref u) = user {
-                purchases.count_documents(doc! { "material_id": m_id, "user_id": u.user_id }, None).await.unwrap_or(0) > 0
+                m.uploader_id == u.user_id || purchases.count_documents(doc! { "material_id": m_id, "user_id": u.user_id }, None).await.unwrap_or(0) > 0
             } else {
                 false
             };
@@ -131,7 +167,9 @@ pub async fn get_material_handler(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
         .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Material not found"}))))?;
 
-    let has_purchased = purchases.count_documents(doc! { "material_id": oid, "user_id": user.user_id }, None).await.unwrap_or(0) > 0;
+    // Uploaders always see their own material
+    let has_purchased = material.uploader_id == user.user_id
+        || purchases.count_documents(doc! { "material_id": oid, "user_id": user.user_id }, None).await.unwrap_or(0) > 0;
 
     if material.is_locked && !has_purchased {
         return Ok((StatusCode::OK, Json(json!({
@@ -210,15 +248,252 @@ pub async fn create_material_handler(
 
     let result = collection.insert_one(new_material, None).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    let material_id = result.inserted_id.as_object_id().unwrap();
+    gamification::record(&state, user.user_id, Activity::MaterialUpload, material_id.to_hex()).await;
 
     Ok((StatusCode::CREATED, Json(json!({
         "message": "Material uploaded",
-        "id": result.inserted_id.as_object_id().unwrap().to_hex()
+        "id": material_id.to_hex()
+    }))))
+}
+
+#[derive(Deserialize)]
+pub struct PurchaseMaterialRequest {
+    pub phone: String,
+}
+
+/// Starts an STK push for a locked material. Access is granted by the payment callback;
+/// the client polls `/api/payments/status/:checkout_request_id` meanwhile.
+pub async fn purchase_material_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(payload): Json<PurchaseMaterialRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let oid = ObjectId::parse_str(&id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid material ID"}))))?;
+
+    let material = state.mongo.collection::<RevisionMaterial>("revision_materials")
+        .find_one(doc! { "_id": oid }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Material not found"}))))?;
+
+    if !material.is_locked || material.price <= 0 {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "This material is free"}))));
+    }
+    if material.uploader_id == user.user_id {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "You uploaded this material"}))));
+    }
+
+    let purchases = state.mongo.collection::<RevisionMaterialPurchase>("material_purchases");
+    if purchases.count_documents(doc! { "material_id": oid, "user_id": user.user_id }, None).await.unwrap_or(0) > 0 {
+        return Err((StatusCode::CONFLICT, Json(json!({"error": "You already own this material"}))));
+    }
+    let transactions = state.mongo.collection::<Transaction>("transactions");
+    let pending = transactions.count_documents(pending_checkout_filter(user.user_id, oid, chrono::Utc::now()), None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    if pending > 0 {
+        return Err((StatusCode::CONFLICT, Json(json!({"error": "A payment for this material is already in progress"}))));
+    }
+
+    // Premium members get a discount; the uploader's share is taken from what was paid
+    let price = entitlements::entitlements(&state, user.user_id).await
+        .material_price(material.price, entitlements::material_discount_percent());
+    let amount_kes = payments::kes_for_cents(price);
+    let checkout_request_id = payments::start_payment(&state, &payload.phone, amount_kes, "Revision Material").await?;
+
+    let now = DateTime::now();
+    let tx = Transaction {
+        id: None,
+        user_id: user.user_id,
+        phone_number: payload.phone,
+        amount: amount_kes * 100,
+        mpesa_receipt_number: None,
+        checkout_request_id: checkout_request_id.clone(),
+        status: "pending".to_string(),
+        provider: state.payments.name().to_string(),
+        refund_reference: None,
+        refund_due: None,
+        transaction_type: PaymentPurpose::Material.as_str().to_string(),
+        premium_duration: None,
+        target_id: Some(oid),
+        created_at: now,
+        updated_at: now,
+    };
+    transactions.insert_one(tx, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+
+    Ok((StatusCode::OK, Json(json!({
+        "message": "STK Push initiated",
+        "checkout_request_id": checkout_request_id,
+        "amount": amount_kes
+    }))))
+}
+
+/// Fulfilment hook for "material" payments: unlocks the material for the buyer and returns
+/// the uploader's share for the ledger. Safe to run again; the unique (material, buyer)
+/// index keeps it to one purchase. A second payment for a material the buyer already owns
+/// (or one whose purchase can't be recorded) pays the uploader nothing and is flagged for refund.
+pub async fn grant_purchase(state: &Arc<AppState>, tx: &Transaction) -> Vec<LedgerLine> {
+    let Some(material_id) = tx.target_id else {
+        tracing::error!("Material transaction {} has no material", tx.checkout_request_id);
+        return Vec::new();
+    };
+    let material = match state.mongo.collection::<RevisionMaterial>("revision_materials").find_one(doc! { "_id": material_id }, None).await {
+        Ok(Some(m)) => m,
+        _ => {
+            tracing::error!("Paid for missing material {} (checkout {})", material_id, tx.checkout_request_id);
+            return Vec::new();
+        }
+    };
+
+    let creator_amount = creator_share(tx.amount, creator_share_percent());
+    let split = vec![LedgerLine { account: ledger::earnings_account(material.uploader_id), amount: creator_amount }];
+    let purchase = RevisionMaterialPurchase {
+        id: None,
+        material_id,
+        user_id: tx.user_id,
+        amount: tx.amount,
+        creator_amount,
+        transaction_id: tx.id,
+        purchased_at: DateTime::now(),
+    };
+    let purchases = state.mongo.collection::<RevisionMaterialPurchase>("material_purchases");
+    if let Err(e) = purchases.insert_one(purchase, None).await {
+        let reason = if matches!(*e.kind, mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref w)) if w.code == 11000) {
+            match purchases.find_one(doc! { "material_id": material_id, "user_id": tx.user_id }, None).await {
+                // Already granted for this payment and the uploader already told
+                Ok(Some(existing)) if same_payment(&existing, tx) => return split,
+                Ok(_) => "Paid again for a material already owned".to_string(),
+                Err(e) => format!("Couldn't check the existing purchase: {}", e),
+            }
+        } else {
+            format!("Couldn't record the purchase: {}", e)
+        };
+        tracing::error!("Material payment {} by {} needs a refund: {}", tx.checkout_request_id, tx.user_id, reason);
+        flag_for_refund(state, tx, &reason).await;
+        return Vec::new();
+    }
+
+    let _ = create_notification(
+        state,
+        material.uploader_id,
+        tx.user_id,
+        "material_sale",
+        Some(material_id),
+        &format!("bought \"{}\" (KES {:.2} earned)", material.title, creator_amount as f64 / 100.0),
+        true,
+    ).await;
+    split
+}
+
+async fn flag_for_refund(state: &Arc<AppState>, tx: &Transaction, reason: &str) {
+    let transactions = state.mongo.collection::<Transaction>("transactions");
+    if let Err(e) = transactions.update_one(
+        doc! { "checkout_request_id": &tx.checkout_request_id },
+        doc! { "$set": { "refund_due": reason, "updated_at": DateTime::now() } },
+        None,
+    ).await {
+        tracing::error!("Failed to flag {} for refund: {:?}", tx.checkout_request_id, e);
+    }
+}
+
+/// Takes a material back from a buyer whose payment was refunded. Refunding a duplicate
+/// payment leaves the purchase made by the first one alone.
+pub async fn revoke_purchase(state: &Arc<AppState>, tx: &Transaction) {
+    let Some(material_id) = tx.target_id else { return };
+    let purchases = state.mongo.collection::<RevisionMaterialPurchase>("material_purchases");
+    let filter = doc! { "material_id": material_id, "user_id": tx.user_id, "transaction_id": { "$in": [tx.id, null] } };
+    if let Err(e) = purchases.delete_one(filter, None).await {
+        tracing::error!("Failed to revoke {} from {}: {:?}", material_id, tx.user_id, e);
+    }
+}
+
+/// Materials the current user has bought, newest first.
+pub async fn get_library_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let purchases = state.mongo.collection::<RevisionMaterialPurchase>("material_purchases");
+    let collection = state.mongo.collection::<RevisionMaterial>("revision_materials");
+
+    let options = mongodb::options::FindOptions::builder().sort(doc! { "purchased_at": -1 }).build();
+    let mut cursor = purchases.find(doc! { "user_id": user.user_id }, options).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+
+    let mut owned = Vec::new();
+    while let Some(Ok(p)) = cursor.next().await {
+        owned.push(p);
+    }
+
+    let ids: Vec<ObjectId> = owned.iter().map(|p| p.material_id).collect();
+    let mut materials = std::collections::HashMap::new();
+    let mut m_cursor = collection.find(doc! { "_id": { "$in": ids } }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    while let Some(Ok(m)) = m_cursor.next().await {
+        if let Some(id) = m.id {
+            materials.insert(id, m);
+        }
+    }
+
+    let library: Vec<serde_json::Value> = owned.into_iter()
+        .filter_map(|p| {
+            let m = materials.remove(&p.material_id)?;
+            Some(json!({
+                "id": p.material_id.to_hex(),
+                "title": m.title,
+                "course_code": m.course_code,
+                "category": m.category,
+                "material_type": m.material_type,
+                "school": m.school,
+                "programme": m.programme,
+                "year": m.year,
+                "thumbnail_url": m.thumbnail_url,
+                "file_url": m.file_url,
+                "amount_paid": p.amount as f64 / 100.0,
+                "purchased_at": p.purchased_at.to_chrono().to_rfc3339(),
+            }))
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(json!(library))))
+}
+
+/// The current user's earnings from material sales.
+pub async fn get_earnings_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let account = ledger::account(&state, &ledger::earnings_account(user.user_id)).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    let (available, lifetime) = account.map(|a| (a.balance, a.credited)).unwrap_or((0, 0));
+    let sales = state.mongo.collection::<RevisionMaterialPurchase>("material_purchases")
+        .count_documents(doc! { "material_id": { "$in": uploader_material_ids(&state, user.user_id).await } }, None).await
+        .unwrap_or(0);
+
+    Ok((StatusCode::OK, Json(json!({
+        "available": available as f64 / 100.0,
+        "lifetime": lifetime as f64 / 100.0,
+        "sales": sales,
+        "share_percent": creator_share_percent(),
     }))))
 }
 
+async fn uploader_material_ids(state: &Arc<AppState>, uploader_id: ObjectId) -> Vec<ObjectId> {
+    let mut ids = Vec::new();
+    if let Ok(mut cursor) = state.mongo.collection::<RevisionMaterial>("revision_materials").find(doc! { "uploader_id": uploader_id }, None).await {
+        while let Some(Ok(m)) = cursor.next().await {
+            ids.extend(m.id);
+        }
+    }
+    ids
+}
+
 pub fn revision_material_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/", get(list_materials_handler).post(create_material_handler))
+        .route("/library", get(get_library_handler))
+        .route("/earnings", get(get_earnings_handler))
         .route("/:id", get(get_material_handler))
+        .route("/:id/purchase", post(purchase_material_handler))
 }
@@ -11,9 +11,40 @@ use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
 use crate::models::{StudyRoom, RoomMessage, RoomFile};
 use crate::features::auth::auth_service::AuthUser;
+use crate::features::social::ws::{publish_to_topic, Topic, WsPayload};
+use crate::features::social::gamification::{self, Activity};
+use crate::features::monetization::entitlements::{self, Entitlement};
 use mongodb::bson::{doc, oid::ObjectId, DateTime};
 use futures::stream::StreamExt;
 
+// A stay of at least this long between join and leave counts as a study session
+const STUDY_SESSION_MIN_MINUTES: i64 = 20;
+
+fn session_key(room_id: ObjectId, user_id: ObjectId) -> String {
+    format!("study_rooms:joined:{}:{}", room_id.to_hex(), user_id.to_hex())
+}
+
+async fn start_session(state: &Arc<AppState>, room_id: ObjectId, user_id: ObjectId) {
+    let mut conn = state.redis.clone();
+    let _: Result<Option<String>, _> = redis::cmd("SET")
+        .arg(session_key(room_id, user_id))
+        .arg(chrono::Utc::now().timestamp_millis())
+        .arg("NX")
+        .arg("EX")
+        .arg(12 * 3600)
+        .query_async(&mut conn)
+        .await;
+}
+
+async fn finish_session(state: &Arc<AppState>, room_id: ObjectId, user_id: ObjectId) {
+    let mut conn = state.redis.clone();
+    let joined: Option<i64> = redis::cmd("GETDEL").arg(session_key(room_id, user_id)).query_async(&mut conn).await.ok().flatten();
+    let Some(joined_ms) = joined else { return };
+    if chrono::Utc::now().timestamp_millis() - joined_ms >= STUDY_SESSION_MIN_MINUTES * 60_000 {
+        gamification::record(state, user_id, Activity::StudySession, format!("{}:{}", room_id.to_hex(), joined_ms)).await;
+    }
+}
+
 // --- DTOs ---
 
 #[derive(Deserialize)]
@@ -70,11 +101,10 @@ pub struct ParticipantInfo {
 
 // --- Middleware: Check Premium ---
 async fn check_premium(
-    _user_id: ObjectId,
-    _state: &Arc<AppState>,
+    user_id: ObjectId,
+    state: &Arc<AppState>,
 ) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
-    // Temporarily allow everyone to access Study Rooms to resolve 402 errors during testing
-    Ok(())
+    entitlements::require(state, user_id, Entitlement::StudyRooms).await.map(|_| ())
 }
 
 // --- Handlers ---
@@ -202,6 +232,7 @@ pub async fn join_room_handler(
 
     // Check if already joined
     if room.participants.contains(&user.user_id) {
+        start_session(&state, oid, user.user_id).await;
         return Ok((StatusCode::OK, Json(json!({"message": "Already in room"}))));
     }
 
@@ -211,6 +242,7 @@ pub async fn join_room_handler(
         doc! { "$push": { "participants": user.user_id } },
         None
     ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    start_session(&state, oid, user.user_id).await;
 
     Ok((StatusCode::OK, Json(json!({"message": "Joined room"}))))
 }
@@ -231,6 +263,7 @@ pub async fn leave_room_handler(
         doc! { "$pull": { "participants": user.user_id } },
         None
     ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    finish_session(&state, oid, user.user_id).await;
 
     // If creator left or room empty, mark inactive
     let room = collection.find_one(doc! { "_id": oid }, None).await
@@ -374,7 +407,19 @@ pub async fn save_message_handler(
         timestamp: DateTime::now(),
     };
 
-    collection.insert_one(new_msg, None).await.map_err(|e: mongodb::error::Error| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    collection.insert_one(&new_msg, None).await.map_err(|e: mongodb::error::Error| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+
+    publish_to_topic(&state, &Topic::StudyRoom(room_id), &WsPayload {
+        r#type: "room_message".to_string(),
+        data: json!({
+            "room_id": room_id.to_hex(),
+            "user_id": user.user_id.to_hex(),
+            "username": new_msg.username,
+            "content": new_msg.content,
+            "timestamp": new_msg.timestamp.to_chrono().to_rfc3339(),
+        }),
+    }).await;
+
     Ok(StatusCode::CREATED)
 }
 
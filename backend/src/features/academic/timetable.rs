@@ -10,6 +10,7 @@ use serde_json::json;
 use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
 use crate::features::auth::auth_service::AuthUser;
+use crate::features::social::gamification::{self, Activity};
 use crate::models::{Timetable, TimetableClass, AttendanceLog, CrowdReport};
 use mongodb::bson::{doc, oid::ObjectId, DateTime};
 use futures::stream::StreamExt;
@@ -277,9 +278,16 @@ pub async fn log_attendance_handler(
         created_at: DateTime::now(),
     };
 
+    let source_id = format!("{}:{}:{}", t_oid.to_hex(), log.class_id, log.date);
+    let attended = log.status == "attended";
     collection.insert_one(log, None).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
+    // One award per class per date, however many times it's logged
+    if attended {
+        gamification::record(&state, user.user_id, Activity::Attendance, source_id).await;
+    }
+
     Ok(StatusCode::CREATED)
 }
 
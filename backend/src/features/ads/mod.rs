@@ -1143,6 +1143,7 @@ fn creative_to_post_response(creative: AdCreative) -> PostResponse {
         cta_text: creative.call_to_action.clone(),
         cta_url: Some(creative.final_url.clone()),
         is_ad: Some(true),
+        link_preview: None,
     }
 }
 
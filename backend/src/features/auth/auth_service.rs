@@ -1,6 +1,6 @@
 use axum::{
     extract::{State, FromRequestParts, FromRef},
-    http::{StatusCode, request::Parts},
+    http::{HeaderMap, StatusCode, request::Parts},
     response::{IntoResponse, Json},
     routing::post,
     Router,
@@ -11,8 +11,15 @@ use serde_json::json;
 use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
 use crate::models::{User, Profile};
-use crate::features::infrastructure::dto::{AuthResponse, UserResponse, RegisterRequest, LoginRequest, ForgotPasswordRequest, ResetPasswordRequest, ChangePasswordRequest};
+use crate::features::infrastructure::dto::{AuthResponse, TwoFactorChallengeResponse, UserResponse, RegisterRequest, LoginRequest, ForgotPasswordRequest, ResetPasswordRequest, ChangePasswordRequest};
 use crate::features::infrastructure::error::{AppResult, AppError};
+use crate::features::infrastructure::mail::enqueue_email;
+use crate::features::infrastructure::mail_templates::EmailTemplate;
+use crate::features::auth::email_verification::{send_verification_email, email_verification_routes};
+use crate::features::auth::two_factor::{create_login_challenge, two_factor_routes};
+use crate::features::monetization::entitlements::Entitlements;
+use crate::features::monetization::subscriptions::grace_period;
+use crate::features::auth::sessions::{issue_session, is_session_active, revoke_all_sessions, revoke_session, session_routes};
 use redis::AsyncCommands;
 use argon2::{
     password_hash::{
@@ -21,8 +28,8 @@ use argon2::{
     },
     Argon2
 };
-use jsonwebtoken::{encode, decode, EncodingKey, DecodingKey, Header, Validation};
-use chrono::{Utc, Duration};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use chrono::Utc;
 use mongodb::bson::doc;
 use bson::oid::ObjectId;
 use rand::Rng;
@@ -32,13 +39,63 @@ pub struct Claims {
     pub sub: String, // User ID (ObjectId hex)
     pub exp: usize,
     pub role: String,
+    #[serde(default)]
+    pub sid: Option<String>, // Device session the token was issued for
 }
 
 pub struct AuthUser {
     pub user_id: ObjectId,
     #[allow(dead_code)]
     pub role: String,
+    #[allow(dead_code)]
     pub token: String,
+    pub session_id: ObjectId,
+}
+
+/// Decodes an access token and checks that its device session is still live.
+/// Tokens minted before sessions existed carry no `sid` and are rejected.
+pub async fn validate_access_token(state: &Arc<AppState>, token: &str) -> AppResult<(ObjectId, ObjectId, Claims)> {
+    let token_data = decode_token(token, &state.jwt_secret)
+        .map_err(|e| AppError::Unauthorized(format!("Invalid token: {}", e)))?;
+
+    let user_id = ObjectId::parse_str(&token_data.claims.sub)
+        .map_err(|_| AppError::Unauthorized("Invalid user ID in token".to_string()))?;
+    let session_id = token_data.claims.sid.as_deref()
+        .and_then(|sid| ObjectId::parse_str(sid).ok())
+        .ok_or(AppError::Unauthorized("Session expired, please log in again".to_string()))?;
+
+    if !is_session_active(state, &session_id).await {
+        return Err(AppError::Unauthorized("Session has been revoked".to_string()));
+    }
+
+    Ok((user_id, session_id, token_data.claims))
+}
+
+/// Role and premium flag as they should appear in a freshly issued token,
+/// downgrading premium accounts whose grace period has run out on the way.
+pub async fn effective_role(state: &Arc<AppState>, user: &User) -> (String, bool) {
+    if user.is_premium && !Entitlements::for_user(user, Utc::now(), grace_period()).premium {
+        if let Some(user_id) = user.id {
+            // Only if they haven't renewed in the meantime
+            let expires_at = user.premium_expires_at;
+            let users_collection = state.mongo.collection::<User>("users");
+            tokio::spawn(async move {
+                let _ = users_collection.update_one(
+                    doc! { "_id": user_id, "role": "premium", "premium_expires_at": expires_at },
+                    doc! { "$set": { "role": "student" } },
+                    None
+                ).await;
+                let _ = users_collection.update_one(
+                    doc! { "_id": user_id, "premium_expires_at": expires_at },
+                    doc! { "$set": { "is_premium": false } },
+                    None
+                ).await;
+            });
+        }
+        let role = if user.role == "premium" { "student".to_string() } else { user.role.clone() };
+        return (role, false);
+    }
+    (user.role.clone(), user.is_premium)
 }
 
 #[async_trait]
@@ -59,25 +116,7 @@ where
             .and_then(|s| s.strip_prefix("Bearer "))
             .ok_or(AppError::Unauthorized("Missing token".to_string()))?;
 
-        let token_data = decode::<Claims>(
-            auth_header,
-            &DecodingKey::from_secret(app_state.jwt_secret.as_bytes()),
-            &Validation::default(),
-        ).map_err(|e| AppError::Unauthorized(format!("Invalid token: {}", e)))?;
-
-        let user_id = ObjectId::parse_str(&token_data.claims.sub)
-            .map_err(|_| AppError::Unauthorized("Invalid user ID in token".to_string()))?;
-
-        // --- Check if token is blacklisted (fail gracefully on Redis error) ---
-        let blacklist_key = format!("blacklist:{}", auth_header);
-        let mut redis_conn = app_state.redis.clone();
-        match redis_conn.get::<_, Option<String>>(&blacklist_key).await {
-            Ok(Some(_)) => return Err(AppError::Unauthorized("Token is blacklisted".to_string())),
-            Ok(None) => {},
-            Err(_) => {
-                tracing::warn!("Redis unavailable for blacklist check, allowing request through");
-            }
-        }
+        let (user_id, session_id, claims) = validate_access_token(&app_state, auth_header).await?;
 
         // --- Presence Tracking & last_seen (non-fatal) ---
         let user_id_clone = user_id;
@@ -101,12 +140,24 @@ where
                 let mut redis_conn2 = state_clone.redis.clone();
                 let _ = redis_conn2.set_ex::<_, _, ()>(&mongo_update_key, "1", state_clone.redis_mongo_update_ttl).await;
             }
+
+            let session_update_key = format!("session:last_seen_update:{}", session_id.to_hex());
+            if let Ok(None) = redis_conn.get::<_, Option<String>>(&session_update_key).await {
+                let sessions = state_clone.mongo.collection::<crate::models::Session>("sessions");
+                let _ = sessions.update_one(
+                    doc! { "_id": session_id },
+                    doc! { "$set": { "last_seen_at": mongodb::bson::DateTime::now() } },
+                    None
+                ).await;
+                let _ = redis_conn.set_ex::<_, _, ()>(&session_update_key, "1", state_clone.redis_mongo_update_ttl).await;
+            }
         });
 
         Ok(AuthUser {
             user_id,
-            role: token_data.claims.role,
+            role: claims.role,
             token: auth_header.to_string(),
+            session_id,
         })
     }
 }
@@ -167,6 +218,9 @@ pub async fn register_handler(
         banned_at: None,
         banned_by: None,
         created_at: mongodb::bson::DateTime::now(),
+        two_factor_enabled: false,
+        email_verified: false,
+        email_verified_at: None,
     };
 
     let insert_result = users_collection
@@ -201,6 +255,7 @@ pub async fn register_handler(
           muted_chats: None,
           interests: None,
           notification_settings: None,
+          privacy_settings: None,
           onboarded: false,
           follower_count: 0,
           following_count: 0,
@@ -211,23 +266,31 @@ pub async fn register_handler(
           level: 0,
           next_level_points: 0,
           badges: None,
+          utc_offset_minutes: None,
+          last_active_day: None,
           created_at: Some(mongodb::bson::DateTime::now()),
       };
 
     profiles_collection
         .insert_one(new_profile, None)
         .await?;
+    crate::features::infrastructure::search::reindex_profile(&state, user_id).await;
+
+    // Signup succeeds even if the verification email can't be queued; it can be resent later
+    if let Err(e) = send_verification_email(&state, user_id, &payload.email, &payload.username).await {
+        tracing::error!("Failed to queue verification email for {}: {:?}", payload.email, e);
+    }
 
     Ok((StatusCode::CREATED, Json(json!({"message": "User registered successfully"}))))
 }
 
 pub async fn login_handler(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
 ) -> AppResult<impl IntoResponse> {
     tracing::info!("Attempting login for email: {}", payload.email);
     let users_collection = state.mongo.collection::<User>("users");
-    let profiles_collection = state.mongo.collection::<Profile>("profiles");
 
     // 1. Find user
     let user = users_collection
@@ -245,36 +308,38 @@ pub async fn login_handler(
 
     let user_id = user.id.ok_or(AppError::NotFound("User not found".to_string()))?;
 
-    // Check Premium Expiry
-    let mut current_role = user.role.clone();
-    let mut current_is_premium = user.is_premium;
-    if user.is_premium {
-        if let Some(expiry) = user.premium_expires_at {
-            if expiry.to_chrono() < Utc::now() {
-                current_role = "student".to_string();
-                current_is_premium = false;
-                let users_clone = users_collection.clone();
-                let uid_clone = user_id;
-                tokio::spawn(async move {
-                    let _ = users_clone.update_one(
-                        doc! { "_id": uid_clone },
-                        doc! { "$set": { "is_premium": false, "role": "student" } },
-                        None
-                    ).await;
-                });
-            }
-        }
+    if user.is_banned {
+        return Err(AppError::Forbidden("Account is banned".to_string()));
     }
 
-    let expiration = Utc::now().checked_add_signed(Duration::days(180)).unwrap().timestamp() as usize;
+    // Second step required: hand back a short-lived challenge instead of tokens
+    if user.two_factor_enabled {
+        let challenge_token = create_login_challenge(&state, user_id, payload.device_id.clone(), payload.device_name.clone()).await?;
+        return Ok((StatusCode::OK, Json(json!(TwoFactorChallengeResponse {
+            two_factor_required: true,
+            challenge_token,
+            user_id: user_id.to_hex(),
+        }))));
+    }
 
-    let claims = Claims {
-        sub: user_id.to_hex(),
-        exp: expiration,
-        role: current_role.clone(),
-    };
+    let auth_response = complete_login(&state, user, &headers, payload.device_id, payload.device_name).await?;
+    Ok((StatusCode::OK, Json(json!(auth_response))))
+}
+
+/// Opens a device session for a fully authenticated user and builds the login response.
+pub async fn complete_login(
+    state: &Arc<AppState>,
+    user: User,
+    headers: &HeaderMap,
+    device_id: Option<String>,
+    device_name: Option<String>,
+) -> AppResult<AuthResponse> {
+    let user_id = user.id.ok_or(AppError::NotFound("User not found".to_string()))?;
+    let profiles_collection = state.mongo.collection::<Profile>("profiles");
+
+    let (current_role, current_is_premium) = effective_role(state, &user).await;
 
-    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(state.jwt_secret.as_bytes()))?;
+    let tokens = issue_session(state, user_id, &current_role, headers, device_id, device_name).await?;
 
     // Try to get profile from cache first
     let mut profile = None;
@@ -291,7 +356,9 @@ pub async fn login_handler(
     }
 
     let auth_response = AuthResponse {
-        token,
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+        expires_in: tokens.expires_in,
         user: UserResponse {
             id: user_id.to_hex(),
             email: user.email,
@@ -301,10 +368,12 @@ pub async fn login_handler(
             is_premium: current_is_premium,
             premium_expires_at: user.premium_expires_at.map(|dt| dt.to_chrono().to_rfc3339()),
             onboarded: profile.as_ref().map(|p| p.onboarded).unwrap_or(false),
+            is_2fa_enabled: user.two_factor_enabled,
+            email_verified: user.email_verified,
         }
     };
-    
-    Ok((StatusCode::OK, Json(auth_response)))
+
+    Ok(auth_response)
 }
 
 pub async fn forgot_password_handler(
@@ -333,43 +402,9 @@ pub async fn forgot_password_handler(
     let mut conn = state.redis.clone();
     conn.set_ex::<_, _, ()>(&redis_key, &otp_str, 900).await?;
 
-    tracing::info!("🔑 Reset code sent to {}", payload.email);
-
-    // 4. Send Email via Brevo
-    if state.brevo_api_key.is_empty() {
-        tracing::warn!("⚠️ BREVO_API_KEY not set! Reset code for {}: {}", payload.email, otp_str);
-    } else {
-        let email_payload = json!({
-            "sender": { "name": "Karu teens", "email": "no-reply@karuteens.site" },
-            "to": [{ "email": payload.email }],
-            "subject": "Reset Your KaruTeens Password",
-            "htmlContent": format!(
-                "<html><body><h1>Password Reset</h1><p>Your password reset code is: <strong>{}</strong></p><p>This code expires in 15 minutes.</p></body></html>",
-                otp_str
-            )
-        });
-
-        let response = state.http_client.post("https://api.brevo.com/v3/smtp/email")
-            .header("api-key", &state.brevo_api_key)
-            .json(&email_payload)
-            .send()
-            .await;
-
-        match response {
-            Ok(resp) => {
-                if !resp.status().is_success() {
-                    let err_body = resp.text().await.unwrap_or_default();
-                    tracing::error!("❌ Brevo Error ({}): {}", payload.email, err_body);
-                    return Err(AppError::InternalServerError("Failed to send reset email. Please try again later.".to_string()));
-                } else {
-                    tracing::info!("✅ Reset email sent to {}", payload.email);
-                }
-            }
-            Err(e) => {
-                tracing::error!("❌ Failed to reach Brevo: {}", e);
-            }
-        }
-    }
+    // 4. Queue the email; the outbox worker handles delivery and retries
+    enqueue_email(&state, &payload.email, None, EmailTemplate::PasswordReset { code: otp_str, expires_minutes: 15 }).await?;
+    tracing::info!("🔑 Reset code queued for {}", payload.email);
 
     Ok(Json(json!({"message": "Reset code sent successfully"})))
 }
@@ -401,6 +436,11 @@ pub async fn reset_password_handler(
                 None
             ).await?;
 
+            // Whoever knew the old password is signed out everywhere
+            if let Some(user_id) = users_collection.find_one(doc! { "email": &payload.email }, None).await?.and_then(|u| u.id) {
+                revoke_all_sessions(&state, user_id, None, "password_reset").await?;
+            }
+
              // 4. Clear OTP
              let mut conn = state.redis.clone();
              conn.del::<_, ()>(&redis_key).await?;
@@ -443,6 +483,8 @@ pub async fn change_password_handler(
         None
     ).await?;
 
+    revoke_all_sessions(&state, user.user_id, Some(user.session_id), "password_changed").await?;
+
     Ok(Json(json!({"message": "Password updated successfully"})))
 }
 
@@ -450,10 +492,7 @@ pub async fn logout_handler(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
 ) -> AppResult<impl IntoResponse> {
-    let mut conn = state.redis.clone();
-    let token_key = format!("blacklist:{}", user.token);
-    let ttl = 180 * 24 * 3600; // 180 days
-    let _ = conn.set_ex::<_, _, ()>(&token_key, "1", ttl).await;
+    revoke_session(&state, &user.session_id, "logout").await?;
     Ok(Json(json!({"message": "Logged out successfully"})))
 }
 
@@ -494,6 +533,9 @@ pub fn auth_routes() -> Router<Arc<AppState>> {
         .route("/change-password", post(change_password_handler))
         .route("/verify-free", post(verify_free_handler))
         .route("/logout", post(logout_handler))
+        .merge(session_routes())
+        .merge(two_factor_routes())
+        .merge(email_verification_routes())
 }
 
 pub fn decode_token(token: &str, secret: &str) -> Result<jsonwebtoken::TokenData<Claims>, jsonwebtoken::errors::Error> {
@@ -0,0 +1,129 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Json},
+    routing::post,
+    Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+use mongodb::bson::{doc, oid::ObjectId};
+use redis::AsyncCommands;
+use sha2::{Digest, Sha256};
+use rand::RngCore;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::error::{AppError, AppResult};
+use crate::features::infrastructure::dto::VerifyEmailRequest;
+use crate::features::infrastructure::mail::enqueue_email;
+use crate::features::infrastructure::mail_templates::EmailTemplate;
+use crate::features::auth::auth_service::AuthUser;
+use crate::models::{Profile, User};
+
+const RESEND_COOLDOWN_SECS: u64 = 60;
+
+fn token_ttl_hours() -> i64 {
+    std::env::var("EMAIL_VERIFICATION_TTL_HOURS").ok().and_then(|v| v.parse().ok()).unwrap_or(48)
+}
+
+/// Only a digest of the token is kept in Redis, so a leaked dump can't verify anyone.
+fn token_key(token: &str) -> String {
+    format!("email_verify:{}", hex::encode(Sha256::digest(token.as_bytes())))
+}
+
+pub async fn send_verification_email(state: &Arc<AppState>, user_id: ObjectId, email: &str, username: &str) -> AppResult<()> {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let ttl_hours = token_ttl_hours();
+
+    // The stored value ties the token to the address it was sent to, so changing
+    // email later invalidates outstanding links
+    let mut conn = state.redis.clone();
+    conn.set_ex::<_, _, ()>(token_key(&token), format!("{}:{}", user_id.to_hex(), email), (ttl_hours * 3600) as u64).await?;
+
+    let base_url = std::env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:5173".to_string());
+    let link = format!("{}/verify-email?token={}", base_url.split(',').next().unwrap_or("").trim_end_matches('/'), token);
+
+    enqueue_email(
+        state,
+        email,
+        Some(username.to_string()),
+        EmailTemplate::EmailVerification { username: username.to_string(), link, expires_hours: ttl_hours },
+    )
+    .await
+}
+
+// --- Handlers ---
+
+pub async fn verify_email_handler(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyEmailRequest>,
+) -> AppResult<impl IntoResponse> {
+    let invalid = || AppError::BadRequest("Verification link is invalid or has expired".to_string());
+
+    let mut conn = state.redis.clone();
+    let stored: Option<String> = redis::cmd("GETDEL").arg(token_key(payload.token.trim())).query_async(&mut conn).await?;
+    let (user_id, email) = stored
+        .as_deref()
+        .and_then(|v| v.split_once(':'))
+        .and_then(|(id, email)| ObjectId::parse_str(id).ok().map(|id| (id, email.to_string())))
+        .ok_or_else(invalid)?;
+
+    let users = state.mongo.collection::<User>("users");
+    let result = users
+        .update_one(
+            doc! { "_id": user_id, "email": &email },
+            doc! { "$set": { "email_verified": true, "email_verified_at": mongodb::bson::DateTime::now() } },
+            None,
+        )
+        .await?;
+    if result.matched_count == 0 {
+        return Err(invalid());
+    }
+
+    Ok(Json(json!({"message": "Email confirmed", "email_verified": true})))
+}
+
+pub async fn resend_verification_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let users = state.mongo.collection::<User>("users");
+    let user_doc = users
+        .find_one(doc! { "_id": user.user_id }, None)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))?;
+    if user_doc.email_verified {
+        return Err(AppError::Conflict("Email is already confirmed".to_string()));
+    }
+
+    let mut conn = state.redis.clone();
+    let cooldown_key = format!("email_verify:cooldown:{}", user.user_id.to_hex());
+    let fresh: bool = redis::cmd("SET")
+        .arg(&cooldown_key)
+        .arg("1")
+        .arg("NX")
+        .arg("EX")
+        .arg(RESEND_COOLDOWN_SECS)
+        .query_async::<Option<String>>(&mut conn)
+        .await?
+        .is_some();
+    if !fresh {
+        return Err(AppError::TooManyRequests("Please wait a minute before requesting another email".to_string()));
+    }
+
+    let profiles = state.mongo.collection::<Profile>("profiles");
+    let username = profiles
+        .find_one(doc! { "user_id": user.user_id }, None)
+        .await?
+        .map(|p| p.username)
+        .unwrap_or_else(|| "there".to_string());
+
+    send_verification_email(&state, user.user_id, &user_doc.email, &username).await?;
+    Ok(Json(json!({"message": "Verification email sent"})))
+}
+
+pub fn email_verification_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/verify-email", post(verify_email_handler))
+        .route("/verify-email/resend", post(resend_verification_handler))
+}
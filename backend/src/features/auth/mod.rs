@@ -1,2 +1,5 @@
 pub mod auth_service;
 pub mod user;
+pub mod sessions;
+pub mod two_factor;
+pub mod email_verification;
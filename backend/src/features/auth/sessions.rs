@@ -0,0 +1,426 @@
+use axum::{
+    extract::{State, Path},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
+    routing::{delete, get, post},
+    Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use redis::AsyncCommands;
+use sha2::{Digest, Sha256};
+use rand::RngCore;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use futures::stream::StreamExt;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::error::{AppError, AppResult};
+use crate::features::infrastructure::dto::{RefreshTokenRequest, RevokeAllSessionsRequest, SessionResponse, TokenPairResponse};
+use crate::features::auth::auth_service::{effective_role, AuthUser, Claims};
+use crate::models::{Session, User};
+
+/// Old refresh tokens remembered per session for reuse detection.
+const REMEMBERED_TOKEN_HASHES: i32 = 20;
+/// Two tabs refreshing at once both present the same token; don't treat that as theft.
+const ROTATION_GRACE_SECS: i64 = 10;
+
+pub fn access_token_ttl_secs() -> i64 {
+    std::env::var("ACCESS_TOKEN_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(900)
+}
+
+fn refresh_token_ttl() -> Duration {
+    let days = std::env::var("REFRESH_TOKEN_TTL_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(30);
+    Duration::days(days)
+}
+
+fn active_key(session_id: &ObjectId) -> String {
+    format!("session:active:{}", session_id.to_hex())
+}
+
+pub fn hash_refresh_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Refresh tokens are `<session id>.<random secret>`; only the secret's hash is stored.
+pub fn split_refresh_token(token: &str) -> Option<(ObjectId, &str)> {
+    let (sid, secret) = token.split_once('.')?;
+    let sid = ObjectId::parse_str(sid).ok()?;
+    if secret.is_empty() {
+        return None;
+    }
+    Some((sid, secret))
+}
+
+/// Matches the session only while `presented_hash` is still its current refresh token.
+pub fn rotation_filter(session_id: ObjectId, presented_hash: &str) -> Document {
+    doc! { "_id": session_id, "refresh_token_hash": presented_hash, "revoked_at": null }
+}
+
+/// Swaps in the new refresh token and remembers the presented one for reuse detection.
+pub fn rotation_update(
+    presented_hash: &str,
+    new_hash: &str,
+    now: mongodb::bson::DateTime,
+    expires_at: mongodb::bson::DateTime,
+    ip_address: Option<String>,
+) -> Document {
+    doc! {
+        "$set": {
+            "refresh_token_hash": new_hash,
+            "last_seen_at": now,
+            "last_rotated_at": now,
+            "expires_at": expires_at,
+            "ip_address": ip_address,
+        },
+        "$push": {
+            "previous_token_hashes": { "$each": [presented_hash], "$slice": -REMEMBERED_TOKEN_HASHES },
+        },
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RotationFailure {
+    /// Another request rotated this token moments ago; the client should retry with the new one.
+    InProgress,
+    /// An old token came back after the grace window: the family is compromised.
+    Reuse,
+    /// Never a token of this session.
+    Invalid,
+}
+
+/// Why a presented refresh token didn't rotate, judged against the session as it is now.
+pub fn rotation_failure(session: &Session, presented_hash: &str, now: chrono::DateTime<Utc>) -> RotationFailure {
+    if !session.previous_token_hashes.iter().any(|h| h == presented_hash) {
+        return RotationFailure::Invalid;
+    }
+    let since_rotation = now.timestamp_millis() - session.last_rotated_at.timestamp_millis();
+    if since_rotation < ROTATION_GRACE_SECS * 1000 {
+        RotationFailure::InProgress
+    } else {
+        RotationFailure::Reuse
+    }
+}
+
+fn new_refresh_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Best-effort client address; the app runs behind a proxy on Render.
+pub fn client_ip(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-forwarded-for")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.split(',').next())
+        .or_else(|| headers.get("x-real-ip").and_then(|h| h.to_str().ok()))
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// How many proxies in front of the app append to `X-Forwarded-For` (`TRUSTED_PROXY_HOPS`).
+/// Zero, the default, means the header isn't trusted at all.
+pub fn trusted_proxy_hops() -> usize {
+    std::env::var("TRUSTED_PROXY_HOPS").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(0)
+}
+
+/// The client address as recorded by the outermost of `hops` trusted proxies. Each proxy
+/// appends the address it saw, so only the last `hops` entries are ours; anything to the
+/// left of them came from the client and can say anything. For decisions that grant access,
+/// unlike `client_ip`.
+pub fn forwarded_ip(headers: &HeaderMap, hops: usize) -> Option<String> {
+    if hops == 0 {
+        return None;
+    }
+    let entries: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|s| s.split(','))
+        .map(str::trim)
+        .collect();
+    entries
+        .len()
+        .checked_sub(hops)
+        .map(|i| entries[i])
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+fn user_agent(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(axum::http::header::USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.chars().take(256).collect())
+}
+
+fn sign_access_token(state: &Arc<AppState>, user_id: ObjectId, session_id: ObjectId, role: &str) -> AppResult<String> {
+    let claims = Claims {
+        sub: user_id.to_hex(),
+        exp: (Utc::now().timestamp() + access_token_ttl_secs()) as usize,
+        role: role.to_string(),
+        sid: Some(session_id.to_hex()),
+    };
+    Ok(encode(&Header::default(), &claims, &EncodingKey::from_secret(state.jwt_secret.as_bytes()))?)
+}
+
+async fn mark_active(state: &Arc<AppState>, session_id: &ObjectId, expires_at: mongodb::bson::DateTime) {
+    let ttl = (expires_at.timestamp_millis() - Utc::now().timestamp_millis()) / 1000;
+    if ttl > 0 {
+        let mut conn = state.redis.clone();
+        let _ = conn.set_ex::<_, _, ()>(active_key(session_id), "1", ttl as u64).await;
+    }
+}
+
+/// Creates a device session and returns its first access/refresh token pair.
+pub async fn issue_session(
+    state: &Arc<AppState>,
+    user_id: ObjectId,
+    role: &str,
+    headers: &HeaderMap,
+    device_id: Option<String>,
+    device_name: Option<String>,
+) -> AppResult<TokenPairResponse> {
+    let sessions = state.mongo.collection::<Session>("sessions");
+
+    // Signing in again on the same device replaces that device's session
+    if let Some(ref device) = device_id {
+        let mut cursor = sessions
+            .find(doc! { "user_id": user_id, "device_id": device, "revoked_at": null }, None)
+            .await?;
+        while let Some(Ok(old)) = cursor.next().await {
+            if let Some(old_id) = old.id {
+                revoke_session(state, &old_id, "replaced").await?;
+            }
+        }
+    }
+
+    let session_id = ObjectId::new();
+    let secret = new_refresh_secret();
+    let now = mongodb::bson::DateTime::now();
+    let expires_at = mongodb::bson::DateTime::from_chrono(Utc::now() + refresh_token_ttl());
+
+    sessions
+        .insert_one(
+            Session {
+                id: Some(session_id),
+                user_id,
+                refresh_token_hash: hash_refresh_secret(&secret),
+                previous_token_hashes: Vec::new(),
+                device_id,
+                device_name: device_name.map(|d| d.chars().take(100).collect()),
+                user_agent: user_agent(headers),
+                ip_address: client_ip(headers),
+                created_at: now,
+                last_seen_at: now,
+                last_rotated_at: now,
+                expires_at,
+                revoked_at: None,
+                revoked_reason: None,
+            },
+            None,
+        )
+        .await?;
+    mark_active(state, &session_id, expires_at).await;
+
+    Ok(TokenPairResponse {
+        token: sign_access_token(state, user_id, session_id, role)?,
+        refresh_token: format!("{}.{}", session_id.to_hex(), secret),
+        expires_in: access_token_ttl_secs(),
+    })
+}
+
+/// Checked on every authenticated request. Redis holds the hot path; Mongo is the source of truth.
+pub async fn is_session_active(state: &Arc<AppState>, session_id: &ObjectId) -> bool {
+    let mut conn = state.redis.clone();
+    match conn.exists::<_, bool>(active_key(session_id)).await {
+        Ok(true) => return true,
+        Ok(false) => {}
+        Err(_) => tracing::warn!("Redis unavailable for session check, falling back to MongoDB"),
+    }
+
+    let sessions = state.mongo.collection::<Session>("sessions");
+    match sessions
+        .find_one(
+            doc! { "_id": session_id, "revoked_at": null, "expires_at": { "$gt": mongodb::bson::DateTime::now() } },
+            None,
+        )
+        .await
+    {
+        Ok(Some(session)) => {
+            mark_active(state, session_id, session.expires_at).await;
+            true
+        }
+        _ => false,
+    }
+}
+
+pub async fn revoke_session(state: &Arc<AppState>, session_id: &ObjectId, reason: &str) -> AppResult<bool> {
+    let sessions = state.mongo.collection::<Session>("sessions");
+    let result = sessions
+        .update_one(
+            doc! { "_id": session_id, "revoked_at": null },
+            doc! { "$set": { "revoked_at": mongodb::bson::DateTime::now(), "revoked_reason": reason } },
+            None,
+        )
+        .await?;
+    let mut conn = state.redis.clone();
+    let _ = conn.del::<_, ()>(active_key(session_id)).await;
+    Ok(result.modified_count > 0)
+}
+
+/// Revokes every live session of a user, optionally sparing one (the caller's own).
+pub async fn revoke_all_sessions(
+    state: &Arc<AppState>,
+    user_id: ObjectId,
+    except: Option<ObjectId>,
+    reason: &str,
+) -> AppResult<u64> {
+    let sessions = state.mongo.collection::<Session>("sessions");
+    let mut filter = doc! { "user_id": user_id, "revoked_at": null };
+    if let Some(keep) = except {
+        filter.insert("_id", doc! { "$ne": keep });
+    }
+
+    let mut revoked = 0;
+    let mut cursor = sessions.find(filter, None).await?;
+    while let Some(Ok(session)) = cursor.next().await {
+        if let Some(id) = session.id {
+            if revoke_session(state, &id, reason).await? {
+                revoked += 1;
+            }
+        }
+    }
+    Ok(revoked)
+}
+
+// --- Handlers ---
+pub async fn refresh_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<RefreshTokenRequest>,
+) -> AppResult<impl IntoResponse> {
+    let invalid = || AppError::Unauthorized("Invalid or expired refresh token".to_string());
+    let (session_id, secret) = split_refresh_token(&payload.refresh_token).ok_or_else(invalid)?;
+    let presented_hash = hash_refresh_secret(secret);
+
+    let sessions = state.mongo.collection::<Session>("sessions");
+    let session = sessions.find_one(doc! { "_id": session_id }, None).await?.ok_or_else(invalid)?;
+    if session.revoked_at.is_some() || session.expires_at.to_chrono() < Utc::now() {
+        return Err(invalid());
+    }
+
+    let new_secret = new_refresh_secret();
+    let now = mongodb::bson::DateTime::now();
+    let expires_at = mongodb::bson::DateTime::from_chrono(Utc::now() + refresh_token_ttl());
+
+    // Compare-and-swap on the current hash so concurrent refreshes can't both win
+    let rotated = sessions
+        .find_one_and_update(
+            rotation_filter(session_id, &presented_hash),
+            rotation_update(&presented_hash, &hash_refresh_secret(&new_secret), now, expires_at, client_ip(&headers)),
+            FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build(),
+        )
+        .await?;
+
+    if rotated.is_none() {
+        // Judge against the session as it is now: a rotation may have landed since the read above
+        let current = sessions.find_one(doc! { "_id": session_id }, None).await?.ok_or_else(invalid)?;
+        match rotation_failure(&current, &presented_hash, Utc::now()) {
+            RotationFailure::InProgress => {
+                return Err(AppError::Conflict("Refresh already in progress, retry with the latest token".to_string()));
+            }
+            RotationFailure::Reuse => {
+                tracing::warn!("Refresh token reuse detected for session {}, revoking", session_id);
+                revoke_session(&state, &session_id, "refresh_token_reuse").await?;
+            }
+            RotationFailure::Invalid => {}
+        }
+        return Err(invalid());
+    }
+
+    let users = state.mongo.collection::<User>("users");
+    let user = users.find_one(doc! { "_id": session.user_id }, None).await?.ok_or_else(invalid)?;
+    if user.is_banned {
+        revoke_session(&state, &session_id, "banned").await?;
+        return Err(AppError::Forbidden("Account is banned".to_string()));
+    }
+    let (role, _) = effective_role(&state, &user).await;
+
+    mark_active(&state, &session_id, expires_at).await;
+
+    Ok(Json(TokenPairResponse {
+        token: sign_access_token(&state, session.user_id, session_id, &role)?,
+        refresh_token: format!("{}.{}", session_id.to_hex(), new_secret),
+        expires_in: access_token_ttl_secs(),
+    }))
+}
+
+pub async fn list_sessions_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let sessions = state.mongo.collection::<Session>("sessions");
+    let mut cursor = sessions
+        .find(
+            doc! { "user_id": user.user_id, "revoked_at": null, "expires_at": { "$gt": mongodb::bson::DateTime::now() } },
+            FindOptions::builder().sort(doc! { "last_seen_at": -1 }).build(),
+        )
+        .await?;
+
+    let mut responses = Vec::new();
+    while let Some(result) = cursor.next().await {
+        if let Ok(s) = result {
+            let Some(id) = s.id else { continue };
+            responses.push(SessionResponse {
+                id: id.to_hex(),
+                device_name: s.device_name,
+                user_agent: s.user_agent,
+                ip_address: s.ip_address,
+                created_at: s.created_at.to_chrono().to_rfc3339(),
+                last_seen_at: s.last_seen_at.to_chrono().to_rfc3339(),
+                is_current: id == user.session_id,
+            });
+        }
+    }
+
+    Ok(Json(responses))
+}
+
+pub async fn revoke_session_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let session_id = ObjectId::parse_str(&id).map_err(|_| AppError::BadRequest("Invalid session ID".to_string()))?;
+    let sessions = state.mongo.collection::<Session>("sessions");
+    sessions
+        .find_one(doc! { "_id": session_id, "user_id": user.user_id, "revoked_at": null }, None)
+        .await?
+        .ok_or(AppError::NotFound("Session not found".to_string()))?;
+
+    revoke_session(&state, &session_id, "revoked_by_user").await?;
+    Ok(Json(json!({"message": "Session revoked"})))
+}
+
+pub async fn revoke_all_sessions_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(payload): Json<RevokeAllSessionsRequest>,
+) -> AppResult<impl IntoResponse> {
+    let except = payload.keep_current.then_some(user.session_id);
+    let revoked = revoke_all_sessions(&state, user.user_id, except, "revoked_by_user").await?;
+    Ok((StatusCode::OK, Json(json!({"message": "Sessions revoked", "revoked": revoked}))))
+}
+
+pub fn session_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/refresh", post(refresh_handler))
+        .route("/sessions", get(list_sessions_handler))
+        .route("/sessions/revoke-all", post(revoke_all_sessions_handler))
+        .route("/sessions/:id", delete(revoke_session_handler))
+}
@@ -0,0 +1,451 @@
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::options::UpdateOptions;
+use redis::AsyncCommands;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use rand::{Rng, RngCore};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use chrono::Utc;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::error::{AppError, AppResult};
+use crate::features::infrastructure::dto::{
+    DisableTwoFactorRequest, RecoveryCodesResponse, TwoFactorCodeRequest, TwoFactorSetupResponse, VerifyTwoFactorRequest,
+};
+use crate::features::auth::auth_service::{complete_login, AuthUser};
+use crate::features::auth::sessions::revoke_all_sessions;
+use crate::models::{TwoFactorAuth, User};
+
+pub const TOTP_STEP_SECS: i64 = 30;
+pub const TOTP_DIGITS: u32 = 6;
+/// Steps either side of "now" that are still accepted, to absorb phone clock drift.
+pub const TOTP_SKEW_STEPS: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+const CHALLENGE_TTL_SECS: u64 = 300;
+const MAX_CHALLENGE_ATTEMPTS: i64 = 5;
+const ISSUER: &str = "KaruTeens";
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+// No 0/o, 1/l/i: recovery codes get typed from paper
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+// --- TOTP (RFC 6238) ---
+
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            out.push(BASE32_ALPHABET[((buffer >> (bits - 5)) & 31) as usize] as char);
+            bits -= 5;
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+pub fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[19] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([digest[offset] & 0x7f, digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+pub fn totp_step(unix_time: i64) -> i64 {
+    unix_time.div_euclid(TOTP_STEP_SECS)
+}
+
+pub fn totp_code(secret: &[u8], unix_time: i64) -> String {
+    format!("{:0width$}", hotp(secret, totp_step(unix_time) as u64), width = TOTP_DIGITS as usize)
+}
+
+/// Returns the time step the code belongs to so callers can refuse to accept it twice.
+pub fn verify_totp(secret: &[u8], code: &str, unix_time: i64) -> Option<i64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let now = totp_step(unix_time);
+    (now - TOTP_SKEW_STEPS..=now + TOTP_SKEW_STEPS)
+        .filter(|step| *step >= 0)
+        .find(|step| {
+            let expected = totp_code(secret, step * TOTP_STEP_SECS);
+            // Compare without an early exit
+            expected.bytes().zip(code.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+        })
+}
+
+pub fn otpauth_uri(account: &str, secret_base32: &str) -> String {
+    let enc = |s: &str| -> String {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    };
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        enc(ISSUER),
+        enc(account),
+        secret_base32,
+        enc(ISSUER),
+        TOTP_DIGITS,
+        TOTP_STEP_SECS
+    )
+}
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 20];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+// --- Recovery codes ---
+
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::rngs::OsRng;
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut code: String = (0..10).map(|_| RECOVERY_ALPHABET[rng.gen_range(0..RECOVERY_ALPHABET.len())] as char).collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+/// Recovery codes carry ~50 bits of randomness, so a plain digest is enough at rest.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+// --- Secret storage ---
+
+fn sealing_key(state: &AppState) -> [u8; 32] {
+    let material = std::env::var("TWO_FACTOR_ENCRYPTION_KEY").unwrap_or_else(|_| state.jwt_secret.clone());
+    Sha256::digest(format!("karuteens-2fa:{}", material).as_bytes()).into()
+}
+
+pub fn seal_secret(key: &[u8; 32], secret: &[u8]) -> String {
+    let cipher = Aes256Gcm::new(key.into());
+    let mut nonce = [0u8; 12];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(cipher.encrypt(Nonce::from_slice(&nonce), secret).expect("AES-GCM encryption cannot fail"));
+    BASE64.encode(sealed)
+}
+
+pub fn open_secret(key: &[u8; 32], sealed: &str) -> Option<Vec<u8>> {
+    let raw = BASE64.decode(sealed).ok()?;
+    if raw.len() < 12 {
+        return None;
+    }
+    let (nonce, ciphertext) = raw.split_at(12);
+    Aes256Gcm::new(key.into()).decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+}
+
+/// Whether admin roles must have 2FA before `admin::require_admin` lets them through.
+pub fn admin_two_factor_required() -> bool {
+    std::env::var("REQUIRE_ADMIN_2FA").map(|v| v == "true" || v == "1").unwrap_or(false)
+}
+
+// --- Verification ---
+
+/// Accepts either a TOTP code or an unused recovery code. Both paths update Mongo
+/// conditionally so the same code can't be redeemed twice, even concurrently.
+pub async fn verify_second_factor(state: &Arc<AppState>, user_id: ObjectId, code: &str) -> AppResult<bool> {
+    let collection = state.mongo.collection::<TwoFactorAuth>("two_factor");
+    let Some(record) = collection.find_one(doc! { "user_id": user_id, "enabled": true }, None).await? else {
+        return Ok(false);
+    };
+
+    let secret = record
+        .secret
+        .as_deref()
+        .and_then(|s| open_secret(&sealing_key(state), s))
+        .ok_or(AppError::InternalServerError("Stored 2FA secret is unreadable".to_string()))?;
+
+    if let Some(step) = verify_totp(&secret, code, Utc::now().timestamp()) {
+        let result = collection
+            .update_one(
+                doc! { "_id": record.id, "last_used_step": { "$lt": step } },
+                doc! { "$set": { "last_used_step": step, "updated_at": mongodb::bson::DateTime::now() } },
+                None,
+            )
+            .await?;
+        return Ok(result.modified_count == 1);
+    }
+
+    let hash = hash_recovery_code(code);
+    let result = collection
+        .update_one(
+            doc! { "_id": record.id, "recovery_code_hashes": &hash },
+            doc! { "$pull": { "recovery_code_hashes": &hash }, "$set": { "updated_at": mongodb::bson::DateTime::now() } },
+            None,
+        )
+        .await?;
+    if result.modified_count == 1 {
+        tracing::info!("User {} signed in with a recovery code", user_id);
+    }
+    Ok(result.modified_count == 1)
+}
+
+#[derive(Serialize, Deserialize)]
+struct LoginChallenge {
+    user_id: ObjectId,
+    device_id: Option<String>,
+    device_name: Option<String>,
+}
+
+pub async fn create_login_challenge(
+    state: &Arc<AppState>,
+    user_id: ObjectId,
+    device_id: Option<String>,
+    device_name: Option<String>,
+) -> AppResult<String> {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+
+    let challenge = serde_json::to_string(&LoginChallenge { user_id, device_id, device_name })
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    let mut conn = state.redis.clone();
+    conn.set_ex::<_, _, ()>(format!("2fa:challenge:{}", token), challenge, CHALLENGE_TTL_SECS).await?;
+    Ok(token)
+}
+
+fn verify_password(user: &User, password: &str) -> AppResult<()> {
+    let parsed_hash = PasswordHash::new(&user.password_hash)
+        .map_err(|_| AppError::InternalServerError("Invalid hash format".to_string()))?;
+    Argon2::default()
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .map_err(|_| AppError::Unauthorized("Invalid password".to_string()))
+}
+
+// --- Handlers ---
+
+/// Second login step: exchanges a challenge token plus code for a session.
+pub async fn verify_login_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<VerifyTwoFactorRequest>,
+) -> AppResult<impl IntoResponse> {
+    let key = format!("2fa:challenge:{}", payload.challenge_token);
+    let attempts_key = format!("2fa:attempts:{}", payload.challenge_token);
+    let mut conn = state.redis.clone();
+
+    let challenge: LoginChallenge = conn
+        .get::<_, Option<String>>(&key)
+        .await?
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .ok_or(AppError::Unauthorized("Login challenge expired, please sign in again".to_string()))?;
+
+    let attempts: i64 = conn.incr(&attempts_key, 1).await?;
+    let _ = conn.expire::<_, ()>(&attempts_key, CHALLENGE_TTL_SECS as i64).await;
+    if attempts > MAX_CHALLENGE_ATTEMPTS {
+        let _ = conn.del::<_, ()>(&key).await;
+        return Err(AppError::TooManyRequests("Too many attempts, please sign in again".to_string()));
+    }
+
+    if !verify_second_factor(&state, challenge.user_id, &payload.code).await? {
+        return Err(AppError::Unauthorized("Invalid verification code".to_string()));
+    }
+    let _ = conn.del::<_, ()>(&[&key, &attempts_key]).await;
+
+    let users = state.mongo.collection::<User>("users");
+    let user = users
+        .find_one(doc! { "_id": challenge.user_id }, None)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))?;
+    if user.is_banned {
+        return Err(AppError::Forbidden("Account is banned".to_string()));
+    }
+
+    let auth_response = complete_login(&state, user, &headers, challenge.device_id, challenge.device_name).await?;
+    Ok(Json(auth_response))
+}
+
+pub async fn status_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let collection = state.mongo.collection::<TwoFactorAuth>("two_factor");
+    let record = collection.find_one(doc! { "user_id": user.user_id }, None).await?;
+    let enabled = record.as_ref().map(|r| r.enabled).unwrap_or(false);
+
+    Ok(Json(json!({
+        "enabled": enabled,
+        "recovery_codes_remaining": record.as_ref().filter(|r| r.enabled).map(|r| r.recovery_code_hashes.len()).unwrap_or(0),
+        "required": admin_two_factor_required() && (user.role == "admin" || user.role == "superadmin"),
+    })))
+}
+
+/// Starts enrollment. The secret stays pending until a code from it is confirmed.
+pub async fn setup_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let users = state.mongo.collection::<User>("users");
+    let user_doc = users
+        .find_one(doc! { "_id": user.user_id }, None)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))?;
+    if user_doc.two_factor_enabled {
+        return Err(AppError::Conflict("Two-factor authentication is already enabled".to_string()));
+    }
+
+    let secret = generate_secret();
+    let collection = state.mongo.collection::<TwoFactorAuth>("two_factor");
+    collection
+        .update_one(
+            doc! { "user_id": user.user_id },
+            doc! {
+                "$set": { "pending_secret": seal_secret(&sealing_key(&state), &secret), "updated_at": mongodb::bson::DateTime::now() },
+                "$setOnInsert": { "user_id": user.user_id, "secret": null, "enabled": false, "recovery_code_hashes": [], "last_used_step": 0_i64, "enabled_at": null },
+            },
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+    let secret_base32 = base32_encode(&secret);
+    Ok(Json(TwoFactorSetupResponse {
+        otpauth_uri: otpauth_uri(&user_doc.email, &secret_base32),
+        secret: secret_base32,
+    }))
+}
+
+pub async fn confirm_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(payload): Json<TwoFactorCodeRequest>,
+) -> AppResult<impl IntoResponse> {
+    let collection = state.mongo.collection::<TwoFactorAuth>("two_factor");
+    let record = collection
+        .find_one(doc! { "user_id": user.user_id }, None)
+        .await?
+        .ok_or(AppError::BadRequest("Start two-factor setup first".to_string()))?;
+    let pending = record
+        .pending_secret
+        .as_deref()
+        .ok_or(AppError::BadRequest("Start two-factor setup first".to_string()))?;
+    let secret = open_secret(&sealing_key(&state), pending)
+        .ok_or(AppError::InternalServerError("Stored 2FA secret is unreadable".to_string()))?;
+
+    let step = verify_totp(&secret, &payload.code, Utc::now().timestamp())
+        .ok_or(AppError::BadRequest("Invalid verification code".to_string()))?;
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<String> = recovery_codes.iter().map(|c| hash_recovery_code(c)).collect();
+    let now = mongodb::bson::DateTime::now();
+    collection
+        .update_one(
+            doc! { "_id": record.id, "pending_secret": pending },
+            doc! {
+                "$set": {
+                    "secret": pending,
+                    "pending_secret": null,
+                    "enabled": true,
+                    "recovery_code_hashes": hashes,
+                    "last_used_step": step,
+                    "enabled_at": now,
+                    "updated_at": now,
+                },
+            },
+            None,
+        )
+        .await?;
+
+    let users = state.mongo.collection::<User>("users");
+    users.update_one(doc! { "_id": user.user_id }, doc! { "$set": { "two_factor_enabled": true } }, None).await?;
+
+    // Sessions opened with just a password shouldn't outlive the upgrade
+    revoke_all_sessions(&state, user.user_id, Some(user.session_id), "two_factor_enabled").await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+pub async fn disable_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(payload): Json<DisableTwoFactorRequest>,
+) -> AppResult<impl IntoResponse> {
+    let users = state.mongo.collection::<User>("users");
+    let user_doc = users
+        .find_one(doc! { "_id": user.user_id }, None)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))?;
+
+    if admin_two_factor_required() && (user_doc.role == "admin" || user_doc.role == "superadmin") {
+        return Err(AppError::Forbidden("Two-factor authentication is mandatory for admin accounts".to_string()));
+    }
+
+    verify_password(&user_doc, &payload.password)?;
+    if !verify_second_factor(&state, user.user_id, &payload.code).await? {
+        return Err(AppError::Unauthorized("Invalid verification code".to_string()));
+    }
+
+    let collection = state.mongo.collection::<TwoFactorAuth>("two_factor");
+    collection.delete_one(doc! { "user_id": user.user_id }, None).await?;
+    users.update_one(doc! { "_id": user.user_id }, doc! { "$set": { "two_factor_enabled": false } }, None).await?;
+
+    Ok(Json(json!({"message": "Two-factor authentication disabled", "is_2fa_enabled": false})))
+}
+
+/// Replaces all recovery codes; the old set stops working immediately.
+pub async fn regenerate_recovery_codes_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(payload): Json<TwoFactorCodeRequest>,
+) -> AppResult<impl IntoResponse> {
+    if !verify_second_factor(&state, user.user_id, &payload.code).await? {
+        return Err(AppError::Unauthorized("Invalid verification code".to_string()));
+    }
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<String> = recovery_codes.iter().map(|c| hash_recovery_code(c)).collect();
+    let collection = state.mongo.collection::<TwoFactorAuth>("two_factor");
+    collection
+        .update_one(
+            doc! { "user_id": user.user_id, "enabled": true },
+            doc! { "$set": { "recovery_code_hashes": hashes, "updated_at": mongodb::bson::DateTime::now() } },
+            None,
+        )
+        .await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+pub fn two_factor_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/verify-2fa", post(verify_login_handler))
+        .route("/2fa", get(status_handler))
+        .route("/2fa/setup", post(setup_handler))
+        .route("/2fa/confirm", post(confirm_handler))
+        .route("/2fa/disable", post(disable_handler))
+        .route("/2fa/recovery-codes", post(regenerate_recovery_codes_handler))
+}
@@ -136,8 +136,18 @@ pub async fn update_profile_handler(
     if let Some(q) = payload.quote { update_doc.insert("quote", q); }
     if let Some(loc) = payload.location { update_doc.insert("location", loc); }
     if let Some(l) = payload.is_locked { update_doc.insert("is_locked", l); }
-    if let Some(pk) = payload.public_key { update_doc.insert("public_key", pk); }
+    if let Some(pk) = payload.public_key {
+        // Legacy single key; per-device keys live in the key directory
+        crate::features::social::e2e::parse_public_key(&pk).map_err(AppError::BadRequest)?;
+        update_doc.insert("public_key", pk);
+    }
     if let Some(ob) = payload.onboarded { update_doc.insert("onboarded", ob); }
+    if let Some(offset) = payload.utc_offset_minutes {
+        if !crate::features::social::gamification::valid_utc_offset(offset) {
+            return Err(AppError::BadRequest("utc_offset_minutes must be between -720 and 840".to_string()));
+        }
+        update_doc.insert("utc_offset_minutes", offset);
+    }
     if let Some(it) = payload.interests {
         if let Ok(it_bson) = mongodb::bson::to_bson(&it) {
             update_doc.insert("interests", it_bson);
@@ -161,6 +171,7 @@ pub async fn update_profile_handler(
     collection
         .update_one(doc! { "user_id": user.user_id }, doc! { "$set": update_doc }, None)
         .await?;
+    crate::features::infrastructure::search::reindex_profile(&state, user.user_id).await;
 
     Ok((StatusCode::OK, Json(json!({"message": "Profile updated successfully"}))))
 }
@@ -621,10 +632,45 @@ pub async fn update_notification_settings_handler(
     Ok((StatusCode::OK, Json(json!({"message": "Notification settings updated"}))))
 }
 
+pub async fn get_privacy_settings_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let collection = state.mongo.collection::<Profile>("profiles");
+
+    let profile = collection
+        .find_one(doc! { "user_id": user.user_id }, None)
+        .await?
+        .ok_or(AppError::NotFound("Profile not found".to_string()))?;
+
+    Ok((StatusCode::OK, Json(profile.privacy_settings.unwrap_or_default())))
+}
+
+pub async fn update_privacy_settings_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(payload): Json<crate::models::base::PrivacySettings>,
+) -> AppResult<impl IntoResponse> {
+    let collection = state.mongo.collection::<Profile>("profiles");
+
+    let settings_bson = mongodb::bson::to_bson(&payload).map_err(|_| AppError::InternalServerError("Failed to serialize settings".to_string()))?;
+
+    collection
+        .update_one(
+            doc! { "user_id": user.user_id },
+            doc! { "$set": { "privacy_settings": settings_bson } },
+            None,
+        )
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({"message": "Privacy settings updated"}))))
+}
+
 pub fn user_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/:username", get(get_profile_handler))
         .route("/:username/gamification", get(get_user_gamification_handler))
+        .route("/gamification/history", get(crate::features::social::gamification::get_points_history_handler))
         .route("/profile/cover", put(update_cover_photo_handler))
         .route("/:userId/posts", get(get_user_posts_handler))
         .route("/:userId/comments", get(get_user_comments_handler))
@@ -632,6 +678,7 @@ pub fn user_routes() -> Router<Arc<AppState>> {
         .route("/:username/unfollow", post(unfollow_user_by_username_handler))
         .route("/update", put(update_profile_handler))
         .route("/notifications/settings", get(get_notification_settings_handler).put(update_notification_settings_handler))
+        .route("/privacy/settings", get(get_privacy_settings_handler).put(update_privacy_settings_handler))
         .route("/chat/:id/mute", post(mute_chat_handler).delete(unmute_chat_handler))
         .route("/block/:id", post(block_user_handler).delete(unblock_user_handler))
 }
@@ -2,7 +2,7 @@ use axum::{
     extract::{State, Path, Query},
     http::StatusCode,
     response::{IntoResponse, Json},
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
 use serde_json::json;
@@ -12,11 +12,12 @@ use crate::features::infrastructure::db::AppState;
 use crate::models::{Comment, CommentReport, CommentModeration, UserCommentStats, SpamDetectionRule, Profile};
 use crate::features::infrastructure::dto::{
     CommentResponse, CommentModerationQueueResponse, UserCommentStatsResponse, ReportCommentRequest, ModerateCommentRequest, 
-    CommentFilter, SpamRuleRequest, PaginatedResponse, PaginationInfo
+    CommentFilter, SpamRuleRequest, UpdateSpamRuleRequest, PaginatedResponse, PaginationInfo
 };
 use crate::features::infrastructure::error::{AppResult, AppError};
 use crate::features::auth::auth_service::AuthUser;
 use crate::utils::check_admin;
+use crate::features::infrastructure::spam;
 use futures::stream::StreamExt;
 
 // --- Handlers ---
@@ -386,6 +387,7 @@ pub async fn create_spam_rule_handler(
     check_admin(user.user_id, &state).await?;
 
     let rules_coll = state.mongo.collection::<SpamDetectionRule>("spam_detection_rules");
+    spam::compile_pattern(&payload.pattern).map_err(|e| AppError::BadRequest(format!("Invalid pattern: {}", e)))?;
     
     let new_rule = SpamDetectionRule {
         id: None,
@@ -400,10 +402,59 @@ pub async fn create_spam_rule_handler(
     };
 
     rules_coll.insert_one(new_rule, None).await?;
+    spam::rules_changed(&state).await;
 
     Ok((StatusCode::CREATED, Json(json!({"message": "Spam detection rule created"}))))
 }
 
+pub async fn update_spam_rule_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateSpamRuleRequest>,
+) -> AppResult<impl IntoResponse> {
+    check_admin(user.user_id, &state).await?;
+    let oid = ObjectId::parse_str(&id).map_err(|_| AppError::BadRequest("Invalid rule ID".to_string()))?;
+
+    let mut update_doc = doc! { "updated_at": DateTime::now() };
+    if let Some(pattern) = payload.pattern {
+        spam::compile_pattern(&pattern).map_err(|e| AppError::BadRequest(format!("Invalid pattern: {}", e)))?;
+        update_doc.insert("pattern", pattern);
+    }
+    if let Some(name) = payload.name { update_doc.insert("name", name); }
+    if let Some(description) = payload.description { update_doc.insert("description", description); }
+    if let Some(score) = payload.score { update_doc.insert("score", score); }
+    if let Some(category) = payload.category { update_doc.insert("category", category); }
+    if let Some(is_active) = payload.is_active { update_doc.insert("is_active", is_active); }
+
+    let rules_coll = state.mongo.collection::<SpamDetectionRule>("spam_detection_rules");
+    let result = rules_coll.update_one(doc! { "_id": oid }, doc! { "$set": update_doc }, None).await?;
+    if result.matched_count == 0 {
+        return Err(AppError::NotFound("Spam rule not found".to_string()));
+    }
+    spam::rules_changed(&state).await;
+
+    Ok((StatusCode::OK, Json(json!({"message": "Spam detection rule updated"}))))
+}
+
+pub async fn delete_spam_rule_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    check_admin(user.user_id, &state).await?;
+    let oid = ObjectId::parse_str(&id).map_err(|_| AppError::BadRequest("Invalid rule ID".to_string()))?;
+
+    let rules_coll = state.mongo.collection::<SpamDetectionRule>("spam_detection_rules");
+    let result = rules_coll.delete_one(doc! { "_id": oid }, None).await?;
+    if result.deleted_count == 0 {
+        return Err(AppError::NotFound("Spam rule not found".to_string()));
+    }
+    spam::rules_changed(&state).await;
+
+    Ok((StatusCode::OK, Json(json!({"message": "Spam detection rule deleted"}))))
+}
+
 // --- Helper Functions ---
 
 async fn build_comment_response(
@@ -569,6 +620,7 @@ pub fn comment_routes() -> Router<Arc<AppState>> {
         .route("/queue", get(get_moderation_queue_handler))
         .route("/stats/:user_id", get(get_user_comment_stats_handler))
         .route("/spam-rules", get(list_spam_rules_handler).post(create_spam_rule_handler))
+        .route("/spam-rules/:id", put(update_spam_rule_handler).delete(delete_spam_rule_handler))
 }
 
 pub async fn report_comment_handler(
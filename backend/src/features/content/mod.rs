@@ -3,6 +3,9 @@ pub mod comments;
 pub mod stories;
 pub mod reels;
 pub mod playlist;
+pub mod ranking;
+pub mod timeline;
+pub mod scheduled_posts;
 
 use axum::{
     extract::{State, Path},
@@ -24,9 +27,13 @@ use mongodb::{bson::{doc, Bson}, options::FindOptions};
 use chrono::Utc;
 use bson::oid::ObjectId;
 use crate::features::social::notifications::create_notification;
+use crate::features::social::gamification::{self, Activity};
+use crate::features::infrastructure::spam::{self, ContentKind};
 use crate::features::content::posts::{delete_post_handler, update_post_handler};
 use crate::features::infrastructure::push;
+use crate::features::social::ws::{publish_to_topic, Topic, WsPayload};
 use crate::features::ads::get_ads_for_feed;
+use crate::features::monetization::entitlements::{self, Entitlement};
 
 // --- DTOs ---
 #[derive(Deserialize, Serialize)]
@@ -220,6 +227,7 @@ pub async fn posts_to_responses(
             cta_text: None,
             cta_url: None,
             is_ad: Some(false),
+            link_preview: p.link_preview,
         }
     }).collect()
 }
@@ -266,6 +274,10 @@ pub async fn create_post_handler(
         None
     };
 
+    let verdict = spam::classify(&state, user_id, ContentKind::Post, &payload.content).await;
+    let flagged = verdict.is_spam(spam::flag_threshold());
+    let spam_text = payload.content.clone();
+
     let new_post = Post {
         id: None,
         group_id,
@@ -274,7 +286,7 @@ pub async fn create_post_handler(
         content: payload.content,
         excerpt: None,
         slug: "".to_string(),
-        status: "published".to_string(),
+        status: if flagged { "pending".to_string() } else { "published".to_string() },
         post_type: payload.post_type,
         category: "general".to_string(),
         tags: None,
@@ -284,7 +296,7 @@ pub async fn create_post_handler(
         media_urls: if payload.media_urls.is_empty() { None } else { Some(payload.media_urls) },
         location,
         scheduled_publish_date: None,
-        published_at: Some(mongodb::bson::DateTime::now()),
+        published_at: if flagged { None } else { Some(mongodb::bson::DateTime::now()) },
         approved_at: None,
         approved_by: None,
         rejected_at: None,
@@ -308,11 +320,13 @@ pub async fn create_post_handler(
         source_author: None,
         plagiarism_score: None,
         content_rating: None,
+        spam_score: Some(verdict.score),
         is_nsfw: payload.is_nsfw,
         is_anonymous: payload.is_anonymous,
         poll: payload.poll,
         created_at: mongodb::bson::DateTime::now(),
         updated_at: mongodb::bson::DateTime::now(),
+        link_preview: None,
     };
 
     let result = collection
@@ -320,20 +334,31 @@ pub async fn create_post_handler(
         .await
         .map_err(AppError::from)?;
 
-    Ok((StatusCode::CREATED, Json(json!({"message": "Post created", "id": result.inserted_id.as_object_id().unwrap().to_hex()}))))
+    let post_id = result.inserted_id.as_object_id().unwrap();
+    crate::features::infrastructure::search::reindex_post(&state, post_id).await;
+    crate::features::infrastructure::unfurl::attach_later(&state, "posts", post_id, &spam_text).await;
+    if flagged {
+        spam::queue_for_review(&state, ContentKind::Post, post_id, &spam_text, &verdict).await;
+        return Ok((StatusCode::CREATED, Json(json!({"message": "Post submitted for review", "id": post_id.to_hex(), "status": "pending"}))));
+    }
+    timeline::on_post_published(&state, post_id).await;
+    gamification::record(&state, user.user_id, Activity::PostPublished, post_id.to_hex()).await;
+
+    Ok((StatusCode::CREATED, Json(json!({"message": "Post created", "id": post_id.to_hex()}))))
 }
 
-pub async fn get_feed_handler(
-    State(state): State<Arc<AppState>>,
-    user: Option<AuthUser>,
-    axum::extract::Query(query): axum::extract::Query<FeedQuery>,
-) -> AppResult<impl IntoResponse> {
-    let limit = query.limit.unwrap_or(20).min(50) as i64;
-    
+/// Campus-wide feed straight from Mongo; also used for group/page/search views and for
+/// users whose home timeline would be empty.
+async fn query_public_feed(
+    state: &Arc<AppState>,
+    user: Option<&AuthUser>,
+    query: &FeedQuery,
+    limit: i64,
+) -> AppResult<(Vec<Post>, bool)> {
     // Get relevant groups: public groups for everyone, and member groups for the user
     let groups_collection = state.mongo.collection::<crate::models::Group>("groups");
     let mut groups_query = doc! { "is_private": { "$ne": true } };
-    if let Some(ref u) = user {
+    if let Some(u) = user {
         groups_query = doc! { 
             "$or": [
                 { "members": u.user_id },
@@ -371,7 +396,7 @@ pub async fn get_feed_handler(
     };
 
     // Fetch profile for hidden posts exclusion
-    if let Some(ref auth) = user {
+    if let Some(auth) = user {
         let profiles = state.mongo.collection::<Profile>("profiles");
         if let Ok(Some(profile)) = profiles.find_one(doc! { "user_id": auth.user_id }, None).await {
             if let Some(hidden) = profile.hidden_posts {
@@ -460,6 +485,36 @@ pub async fn get_feed_handler(
     // Check if has more
     let has_more = all_posts.len() > limit as usize;
     let posts: Vec<Post> = all_posts.into_iter().take(limit as usize).collect();
+    Ok((posts, has_more))
+}
+
+pub async fn get_feed_handler(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    axum::extract::Query(query): axum::extract::Query<FeedQuery>,
+) -> AppResult<impl IntoResponse> {
+    let limit = query.limit.unwrap_or(20).min(50) as i64;
+
+    // Signed-in users get their precomputed home timeline unless they're filtering
+    let home = match user {
+        Some(ref auth) if query.page_id.is_none() && query.group_id.is_none() && query.search.as_deref().is_none_or(|s| s.trim().is_empty()) => {
+            timeline::home_page(&state, auth.user_id, query.last_id.as_deref(), limit as usize).await?
+        }
+        _ => None,
+    };
+    let from_timeline = home.is_some();
+    let (mut posts, has_more) = match home {
+        Some(page) => (page.posts, page.has_more),
+        None => query_public_feed(&state, user.as_ref(), &query, limit).await?,
+    };
+    // The public query filters hidden posts itself; timeline pages are filtered here
+    if let (true, Some(ref auth)) = (from_timeline, &user) {
+        let profiles = state.mongo.collection::<Profile>("profiles");
+        if let Ok(Some(profile)) = profiles.find_one(doc! { "user_id": auth.user_id }, None).await {
+            let hidden = profile.hidden_posts.unwrap_or_default();
+            posts.retain(|p| p.id.is_none_or(|id| !hidden.contains(&id)));
+        }
+    }
 
     if posts.is_empty() {
         return Ok((StatusCode::OK, Json(FeedResponse { 
@@ -472,15 +527,12 @@ pub async fn get_feed_handler(
     let mut post_responses = posts_to_responses(&state, user.as_ref(), posts).await;
 
     // --- Ad Injection ---
-    let mut is_premium = false;
-    if let Some(ref auth) = user {
-        let users_coll = state.mongo.collection::<crate::models::User>("users");
-        if let Ok(Some(u)) = users_coll.find_one(doc! { "_id": auth.user_id }, None).await {
-            is_premium = u.is_premium;
-        }
-    }
+    let ad_free = match user {
+        Some(ref auth) => entitlements::entitlements(&state, auth.user_id).await.has(Entitlement::AdFree),
+        None => false,
+    };
 
-    if !is_premium && !post_responses.is_empty() {
+    if !ad_free && !post_responses.is_empty() {
         // Fetch ads
         let ad_count = (post_responses.len() as f64 / 8.0).ceil() as i64;
         if let Ok(ads) = get_ads_for_feed(&state, ad_count).await {
@@ -685,6 +737,9 @@ pub async fn add_comment_handler(
         None
     };
 
+    let verdict = spam::classify(&state, user.user_id, ContentKind::Comment, &payload.content).await;
+    let flagged = verdict.is_spam(spam::flag_threshold());
+
     let new_comment = crate::models::Comment {
         id: None,
         content_id: post_oid,
@@ -696,8 +751,9 @@ pub async fn add_comment_handler(
         content: payload.content.clone(),
         media_url: payload.media_url,
         media_type: payload.media_type,
-        status: "approved".to_string(),
-        spam_score: None,
+        // "spam" lands in the comment moderation queue and stays hidden until approved
+        status: if flagged { "spam".to_string() } else { "approved".to_string() },
+        spam_score: Some(verdict.score),
         sentiment_score: None,
         reported_count: 0,
         likes: 0,
@@ -714,7 +770,23 @@ pub async fn add_comment_handler(
         updated_at: mongodb::bson::DateTime::now(),
     };
 
-    comments_collection.insert_one(new_comment, None).await?;
+    let inserted = comments_collection.insert_one(&new_comment, None).await?;
+    if flagged {
+        return Ok((StatusCode::CREATED, Json(json!({"message": "Comment held for review"}))));
+    }
+
+    publish_to_topic(&state, &Topic::PostComments(post_oid), &WsPayload {
+        r#type: "comment_added".to_string(),
+        data: json!({
+            "id": inserted.inserted_id.as_object_id().map(|id| id.to_hex()),
+            "post_id": post_oid.to_hex(),
+            "parent_id": parent_oid.map(|id| id.to_hex()),
+            "author_id": user.user_id.to_hex(),
+            "author_name": profile.username,
+            "content": new_comment.content,
+            "created_at": new_comment.created_at.to_chrono().to_rfc3339(),
+        }),
+    }).await;
 
     // Increment comment count on post
     posts_collection.update_one(doc! { "_id": post_oid }, doc! { "$inc": { "comment_count": 1 } }, None).await?;
@@ -730,6 +802,13 @@ pub async fn add_comment_handler(
 
     // Trigger Notification for the post author
     if let Ok(Some(post)) = posts_collection.find_one(doc! { "_id": post_oid }, None).await {
+        // Commenting on your own post earns nothing
+        if post.author_id != user.user_id {
+            if let Some(comment_id) = inserted.inserted_id.as_object_id() {
+                gamification::record(&state, user.user_id, Activity::Comment, comment_id.to_hex()).await;
+            }
+        }
+
         let _ = create_notification(
             &state,
             post.author_id,
@@ -764,7 +843,7 @@ pub async fn get_comments_handler(
     let comments_collection = state.mongo.collection::<crate::models::Comment>("comments");
 
     let find_options = FindOptions::builder().sort(doc! { "created_at": 1 }).build();
-    let mut cursor = comments_collection.find(doc! { "content_id": post_oid }, find_options).await?;
+    let mut cursor = comments_collection.find(doc! { "content_id": post_oid, "status": { "$ne": "spam" } }, find_options).await?;
 
     let mut comments = Vec::new();
     while let Some(result) = cursor.next().await {
@@ -892,98 +971,36 @@ pub async fn get_for_you_feed_handler(
     user: Option<AuthUser>,
     axum::extract::Query(query): axum::extract::Query<FeedQuery>,
 ) -> AppResult<impl IntoResponse> {
-    let limit = query.limit.unwrap_or(20) as i64;
-    
-    if let Some(ref auth_user) = user {
-        let follows_collection = state.mongo.collection::<crate::models::Follow>("follows");
-        let mut followed_ids = Vec::new();
-        
-        if let Ok(mut cursor) = follows_collection.find(doc! { "follower_id": auth_user.user_id }, None).await {
-            while let Some(result) = cursor.next().await {
-                if let Ok(follow) = result {
-                    followed_ids.push(follow.followed_id);
-                }
-            }
-        }
-        
-        let groups_collection = state.mongo.collection::<crate::models::Group>("groups");
-        let mut users_group_ids = Vec::new();
-        if let Ok(mut c) = groups_collection.find(
-            doc! { 
-                "$or": [
-                    { "members": auth_user.user_id },
-                    { "is_private": { "$ne": true } }
-                ]
-            },
-            None
-        ).await {
-            while let Some(Ok(group)) = c.next().await {
-                if let Some(id) = group.id {
-                    users_group_ids.push(id);
-                }
-            }
-        }
+    let limit = query.limit.unwrap_or(20).clamp(1, 50) as usize;
 
-        let page_follows = state.mongo.collection::<crate::models::PageFollow>("page_follows");
-        let mut followed_page_ids = Vec::new();
-        if let Ok(mut c) = page_follows.find(doc! { "user_id": auth_user.user_id }, None).await {
-            while let Some(Ok(f)) = c.next().await {
-                followed_page_ids.push(f.page_id);
-            }
-        }
+    if let Some(ref auth_user) = user {
+        let after = query.last_id.as_deref().and_then(|id| ObjectId::parse_str(id).ok());
+        let (page_ids, has_more) = ranking::for_you_page(&state, auth_user.user_id, after, limit).await;
 
-        if !followed_ids.is_empty() || !users_group_ids.is_empty() || !followed_page_ids.is_empty() {
+        if !page_ids.is_empty() {
             let posts_collection = state.mongo.collection::<Post>("posts");
-            let mut or_parts = vec![
-                doc! { "author_id": { "$in": &followed_ids } },
-                // Include all page posts (they are public)
-                doc! { "page_id": { "$ne": null, "$exists": true } }
-            ];
-            
-            if !users_group_ids.is_empty() {
-                or_parts.push(doc! { "group_id": { "$in": &users_group_ids } });
-            }
-
-            let mut filter = doc! {
-                "status": "published",
-                "$or": or_parts
-            };
-            
-            if let Some(ref last_id_str) = query.last_id {
-                if let Ok(last_oid) = ObjectId::parse_str(last_id_str) {
-                    filter.insert("_id", doc! { "$lt": last_oid });
+            let mut by_id = std::collections::HashMap::new();
+            let mut cursor = posts_collection.find(doc! { "_id": { "$in": &page_ids }, "status": "published" }, None).await?;
+            while let Some(Ok(post)) = cursor.next().await {
+                if let Some(id) = post.id {
+                    by_id.insert(id, post);
                 }
             }
-            
-            let posts_cursor = posts_collection.find(
-                filter,
-                FindOptions::builder()
-                    .sort(doc! { "_id": -1 })
-                    .limit(limit + 1)
-                    .build()
-            ).await?;
-            
-            let mut all_posts = Vec::new();
-            let mut cursor = posts_cursor;
-            while let Some(p) = cursor.next().await {
-                if let Ok(post) = p {
-                    all_posts.push(post);
-                }
-            }
-            
-            let posts: Vec<Post> = all_posts.iter().take(limit as usize).cloned().collect();
-            
-            if !posts.is_empty() {
-                let post_responses = posts_to_responses(&state, user.as_ref(), posts).await;
-                
-                return Ok((StatusCode::OK, Json(FeedResponse {
-                    posts: post_responses.clone(),
-                    next_cursor: if all_posts.len() > limit as usize { post_responses.last().map(|p| p.id.clone()) } else { None }
-                })).into_response());
-            }
+            // Keep ranked order; anything deleted since ranking just drops out
+            let posts: Vec<Post> = page_ids.iter().filter_map(|id| by_id.remove(id)).collect();
+            let post_responses = posts_to_responses(&state, user.as_ref(), posts).await;
+
+            return Ok((StatusCode::OK, Json(FeedResponse {
+                posts: post_responses,
+                next_cursor: if has_more { page_ids.last().map(|id| id.to_hex()) } else { None },
+            })).into_response());
+        }
+
+        if after.is_some() {
+            return Ok((StatusCode::OK, Json(FeedResponse { posts: vec![], next_cursor: None })).into_response());
         }
     }
-    
+
     get_trending_posts_handler(State(state), user, axum::extract::Query(query)).await.map(|r| r.into_response())
 }
 
@@ -18,6 +18,8 @@ use crate::features::infrastructure::dto::{
 use crate::features::infrastructure::error::{AppResult, AppError};
 use crate::features::auth::auth_service::AuthUser;
 use crate::utils::check_admin;
+use crate::features::social::gamification::{self, Activity};
+use crate::features::infrastructure::spam::{self, ContentKind};
 use futures::stream::StreamExt;
 use regex::Regex;
 
@@ -130,6 +132,50 @@ pub async fn get_post_handler(
     Ok((StatusCode::OK, Json(post_info)))
 }
 
+/// What cancelling or rescheduling a post changes: the extra `filter` makes it conditional
+/// on status, since both race the publishing sweep.
+#[derive(Debug, PartialEq)]
+pub struct ScheduleEdit {
+    pub filter: mongodb::bson::Document,
+    pub set: mongodb::bson::Document,
+    pub unset: mongodb::bson::Document,
+}
+
+/// Cancelling sends a scheduled post back to draft; a new publish date (re)schedules
+/// anything not yet published. `None` when the edit doesn't touch the schedule.
+pub fn schedule_edit(current_status: &str, cancel: bool, publish_at: Option<DateTime>) -> AppResult<Option<ScheduleEdit>> {
+    if cancel {
+        if current_status != "scheduled" {
+            return Err(AppError::BadRequest("Post is not scheduled".to_string()));
+        }
+        return Ok(Some(ScheduleEdit {
+            filter: doc! { "status": "scheduled" },
+            set: doc! { "status": "draft" },
+            unset: doc! { "scheduled_publish_date": "" },
+        }));
+    }
+    let Some(publish_at) = publish_at else { return Ok(None) };
+    if current_status == "published" {
+        return Err(AppError::Conflict("Post has already been published".to_string()));
+    }
+    Ok(Some(ScheduleEdit {
+        filter: doc! { "status": { "$ne": "published" } },
+        set: doc! { "scheduled_publish_date": publish_at, "status": "scheduled" },
+        unset: doc! {},
+    }))
+}
+
+/// The text to score again after an edit: the post's title and content with the edit
+/// applied, or `None` when neither changed.
+pub fn edited_spam_text(existing: &Post, title: Option<&str>, content: Option<&str>) -> Option<String> {
+    let title = title.unwrap_or(&existing.title);
+    let content = content.unwrap_or(&existing.content);
+    if title == existing.title && content == existing.content {
+        return None;
+    }
+    Some(format!("{}\n{}", title, content))
+}
+
 pub async fn update_post_handler(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
@@ -141,7 +187,7 @@ pub async fn update_post_handler(
 
     let posts = state.mongo.collection::<Post>("posts");
     
-    let _existing_post = posts.find_one(doc! { "_id": oid }, None).await?
+    let existing_post = posts.find_one(doc! { "_id": oid }, None).await?
         .ok_or(AppError::NotFound("Post not found".to_string()))?;
 
     // Authorization: Admin ONLY for updates
@@ -150,17 +196,35 @@ pub async fn update_post_handler(
     }
 
     let mut update_doc = doc! {};
+
+    // An edit can turn a clean post into spam, so the new text is scored like a new post
+    let spam_check = match edited_spam_text(&existing_post, payload.title.as_deref(), payload.content.as_deref()) {
+        Some(text) => {
+            let verdict = spam::classify(&state, existing_post.author_id, ContentKind::Post, &text).await;
+            verdict.is_spam(spam::flag_threshold()).then_some((text, verdict))
+        }
+        None => None,
+    };
     
     if let Some(title) = payload.title { update_doc.insert("title", title); }
+    let new_content = payload.content.clone();
     if let Some(content) = payload.content { update_doc.insert("content", content); }
     if let Some(excerpt) = payload.excerpt { update_doc.insert("excerpt", excerpt); }
     if let Some(category) = payload.category { update_doc.insert("category", category); }
     if let Some(tags) = payload.tags { update_doc.insert("tags", tags); }
     if let Some(post_type) = payload.post_type { update_doc.insert("post_type", post_type); }
-    if let Some(ref scheduled_publish_date) = payload.scheduled_publish_date {
-        let dt = chrono::DateTime::parse_from_rfc3339(&scheduled_publish_date)
-            .map_err(|_| AppError::BadRequest("Invalid scheduled publish date format".to_string()))?;
-        update_doc.insert("scheduled_publish_date", dt);
+
+    let mut filter = doc! { "_id": oid };
+    let mut unset_doc = doc! {};
+    let cancel = payload.cancel_schedule == Some(true);
+    let publish_at = match payload.scheduled_publish_date.as_deref() {
+        Some(date) if !cancel => Some(parse_schedule_date(date, chrono::Utc::now())?),
+        _ => None,
+    };
+    if let Some(edit) = schedule_edit(&existing_post.status, cancel, publish_at)? {
+        filter.extend(edit.filter);
+        update_doc.extend(edit.set);
+        unset_doc.extend(edit.unset);
     }
     if let Some(language) = payload.language { update_doc.insert("language", language); }
     if let Some(is_featured) = payload.is_featured { update_doc.insert("is_featured", is_featured); }
@@ -180,13 +244,82 @@ pub async fn update_post_handler(
         return Ok((StatusCode::OK, Json(json!({"message": "No changes made"}))));
     }
 
+    // Drafts stay private either way; anything public or on its way out is held for review
+    let target_status = update_doc.get_str("status").unwrap_or(&existing_post.status).to_string();
+    let held = spam_check.filter(|_| target_status != "draft");
+    if held.is_some() {
+        update_doc.insert("status", "pending");
+    }
+
     update_doc.insert("updated_at", DateTime::now());
 
-    posts.update_one(doc! { "_id": oid }, doc! { "$set": update_doc }, None).await?;
+    let mut update = doc! { "$set": update_doc };
+    if !unset_doc.is_empty() {
+        update.insert("$unset", unset_doc);
+    }
+    let result = posts.update_one(filter, update, None).await?;
+    if result.matched_count == 0 {
+        return Err(AppError::Conflict("Post was published before the change could be applied".to_string()));
+    }
+    crate::features::infrastructure::search::reindex_post(&state, oid).await;
+    if let Some(content) = new_content.filter(|c| *c != existing_post.content) {
+        crate::features::infrastructure::unfurl::attach_later(&state, "posts", oid, &content).await;
+    }
+    if let Some((text, verdict)) = held {
+        spam::queue_for_review(&state, ContentKind::Post, oid, &text, &verdict).await;
+        let _ = state.cache.invalidate_pattern("posts:list:*").await;
+        let _ = state.cache.invalidate_pattern("feed:*").await;
+        return Ok((StatusCode::OK, Json(json!({"message": "Post updated and held for review"}))));
+    }
 
     Ok((StatusCode::OK, Json(json!({"message": "Post updated successfully"}))))
 }
 
+/// Lets a post held for review go out, the way it would have without the hold: into search,
+/// timelines and points, or back onto the schedule if its publish date is still ahead.
+/// Returns false if the post wasn't held.
+pub async fn release_held_post(state: &Arc<AppState>, post_id: ObjectId, approver: ObjectId) -> Result<bool, mongodb::error::Error> {
+    let posts = state.mongo.collection::<Post>("posts");
+    let held = doc! { "_id": post_id, "status": { "$in": ["pending", "rejected"] } };
+    let Some(post) = posts.find_one(held.clone(), None).await? else { return Ok(false) };
+
+    let now = DateTime::now();
+    let scheduled = post.scheduled_publish_date.is_some_and(|d| d > now);
+    let mut set = doc! {
+        "approved_at": now,
+        "approved_by": approver,
+        "rejected_at": null,
+        "rejected_by": null,
+        "rejection_reason": null,
+        "updated_at": now,
+    };
+    if scheduled {
+        set.insert("status", "scheduled");
+    } else {
+        set.insert("status", "published");
+        set.insert("published_at", now);
+    }
+    if posts.update_one(held, doc! { "$set": set }, None).await?.modified_count == 0 {
+        return Ok(false);
+    }
+
+    // Approved from the post screen, it no longer needs to sit in the moderation queue
+    let _ = state.mongo.collection::<ContentModeration>("content_moderation").update_many(
+        doc! { "content_id": post_id, "content_type": "post", "status": "pending" },
+        doc! { "$set": { "status": "approved", "reviewed_by": approver, "reviewed_at": now, "updated_at": now } },
+        None,
+    ).await;
+
+    crate::features::infrastructure::search::reindex_post(state, post_id).await;
+    if !scheduled {
+        crate::features::content::timeline::on_post_published(state, post_id).await;
+        gamification::record(state, post.author_id, Activity::PostPublished, post_id.to_hex()).await;
+    }
+    let _ = state.cache.invalidate_pattern("posts:list:*").await;
+    let _ = state.cache.invalidate_pattern("feed:*").await;
+    Ok(true)
+}
+
 pub async fn approve_post_handler(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
@@ -205,8 +338,10 @@ pub async fn approve_post_handler(
     let mut update_doc = doc! {};
     let status = payload.status.clone();
     
-    if status == "approved" {
-        update_doc.insert("status", "approved");
+    if status == "approved" && release_held_post(&state, oid, user.user_id).await? {
+        // Published (or rescheduled) along with its hooks; nothing left to set
+    } else if status == "approved" {
+        // Not held for review, so it keeps whatever status it has
         update_doc.insert("approved_at", DateTime::now());
         update_doc.insert("approved_by", user.user_id);
         update_doc.insert("rejected_at", mongodb::bson::Bson::Null);
@@ -223,9 +358,11 @@ pub async fn approve_post_handler(
         return Err(AppError::BadRequest("Invalid approval status".to_string()));
     }
 
-    update_doc.insert("updated_at", DateTime::now());
-
-    posts.update_one(doc! { "_id": oid }, doc! { "$set": update_doc }, None).await?;
+    if !update_doc.is_empty() {
+        update_doc.insert("updated_at", DateTime::now());
+        posts.update_one(doc! { "_id": oid }, doc! { "$set": update_doc }, None).await?;
+        crate::features::infrastructure::search::reindex_post(&state, oid).await;
+    }
 
     let approvals = state.mongo.collection::<PostApproval>("post_approvals");
     let approval = PostApproval {
@@ -267,6 +404,11 @@ pub async fn publish_post_handler(
         },
         None
     ).await?;
+    crate::features::infrastructure::search::reindex_post(&state, oid).await;
+    crate::features::content::timeline::on_post_published(&state, oid).await;
+    if let Some(post) = posts.find_one(doc! { "_id": oid }, None).await? {
+        gamification::record(&state, post.author_id, Activity::PostPublished, oid.to_hex()).await;
+    }
 
     Ok((StatusCode::OK, Json(json!({"message": "Post published successfully"}))))
 }
@@ -290,6 +432,7 @@ pub async fn delete_post_handler(
     }
 
     posts.delete_one(doc! { "_id": oid }, None).await?;
+    state.search_index.remove(crate::features::infrastructure::search_index::DocKind::Post, &post_id);
 
     // Cleanup associated data
     let revisions = state.mongo.collection::<PostRevision>("post_revisions");
@@ -475,6 +618,16 @@ pub async fn hide_post_handler(
 
 // --- Helper Functions ---
 
+pub fn parse_schedule_date(value: &str, now: chrono::DateTime<chrono::Utc>) -> AppResult<DateTime> {
+    let dt = chrono::DateTime::parse_from_rfc3339(value)
+        .map_err(|_| AppError::BadRequest("Invalid scheduled_publish_date format".to_string()))?
+        .with_timezone(&chrono::Utc);
+    if dt <= now {
+        return Err(AppError::BadRequest("scheduled_publish_date must be in the future".to_string()));
+    }
+    Ok(dt.into())
+}
+
 async fn build_post_response(
     post: &Post,
     state: &Arc<AppState>,
@@ -565,14 +718,25 @@ pub async fn create_post_handler(
 
     let posts_collection = state.mongo.collection::<Post>("posts");
     
-    let scheduled_publish_date = if let Some(ref s) = payload.scheduled_publish_date {
-        Some(chrono::DateTime::parse_from_rfc3339(&s)
-            .map_err(|_| AppError::BadRequest("Invalid scheduled_publish_date format".to_string()))?
-            .with_timezone(&chrono::Utc).into())
+    let scheduled_publish_date = match payload.scheduled_publish_date {
+        Some(ref s) => Some(parse_schedule_date(s, chrono::Utc::now())?),
+        None => None,
+    };
+    // A future publish date always means "scheduled", whatever status was sent
+    let mut status = if scheduled_publish_date.is_some() {
+        "scheduled".to_string()
     } else {
-        None
+        payload.status.clone().unwrap_or_else(|| "draft".to_string())
     };
 
+    let spam_text = format!("{}\n{}", payload.title.as_deref().unwrap_or_default(), payload.content);
+    let verdict = spam::classify(&state, user.user_id, ContentKind::Post, &spam_text).await;
+    // Drafts stay private either way; anything headed for the public goes to review instead
+    let flagged = verdict.is_spam(spam::flag_threshold()) && status != "draft";
+    if flagged {
+        status = "pending".to_string();
+    }
+
     let content = payload.content.clone();
     
     // Extract Tags
@@ -605,7 +769,7 @@ pub async fn create_post_handler(
         content: payload.content,
         excerpt: payload.excerpt,
         slug: "".to_string(),
-        status: payload.status.clone().unwrap_or_else(|| "draft".to_string()),
+        status: status.clone(),
         post_type: payload.post_type.unwrap_or_else(|| "text".to_string()),
         category: payload.category,
         tags: if tags.is_empty() { None } else { Some(tags) },
@@ -622,11 +786,12 @@ pub async fn create_post_handler(
         seo_keywords: payload.seo_keywords,
         meta_data: payload.meta_data,
         content_rating: payload.content_rating,
+        spam_score: Some(verdict.score),
         is_nsfw: payload.is_nsfw,
         is_anonymous: payload.is_anonymous.unwrap_or(false),
         location: None,
         poll: None,
-        published_at: if payload.status.as_deref().unwrap_or("published") == "published" { Some(bson::DateTime::now()) } else { None },
+        published_at: if status == "published" { Some(bson::DateTime::now()) } else { None },
         view_count: 0,
         like_count: 0,
         comment_count: 0,
@@ -642,9 +807,21 @@ pub async fn create_post_handler(
         rejection_reason: None,
         created_at: bson::DateTime::now(),
         updated_at: bson::DateTime::now(),
+        link_preview: None,
     };
 
     let result = posts_collection.insert_one(new_post, None).await?;
+    if let Some(post_id) = result.inserted_id.as_object_id() {
+        crate::features::infrastructure::search::reindex_post(&state, post_id).await;
+        crate::features::infrastructure::unfurl::attach_later(&state, "posts", post_id, &spam_text).await;
+        if flagged {
+            spam::queue_for_review(&state, ContentKind::Post, post_id, &spam_text, &verdict).await;
+        }
+        if status == "published" {
+            crate::features::content::timeline::on_post_published(&state, post_id).await;
+            gamification::record(&state, user.user_id, Activity::PostPublished, post_id.to_hex()).await;
+        }
+    }
 
     let _ = state.cache.invalidate_pattern("posts:list:*").await;
     let _ = state.cache.invalidate_pattern("feed:*").await;
@@ -0,0 +1,411 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use chrono::Utc;
+use futures::stream::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::options::FindOptions;
+use serde::{Deserialize, Serialize};
+use crate::features::infrastructure::db::AppState;
+use crate::models::{Follow, Post, PostView, Profile};
+
+// For-You pipeline: candidate generation -> feature extraction -> scoring -> diversity
+// re-ranking. Scorers are chosen and tuned from admin settings (`settings.feed_ranking`).
+
+const CANDIDATE_WINDOW_DAYS: i64 = 7;
+const SOURCE_LIMIT: i64 = 150;
+const SEEN_LOOKBACK_DAYS: i64 = 14;
+const RANKED_FEED_SIZE: usize = 300;
+const RANKED_FEED_TTL_SECS: u64 = 600;
+const SETTINGS_CACHE_KEY: &str = "settings:feed_ranking";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FeedWeights {
+    pub followed_author: f64,
+    /// Multiplied by the share of the post's tags/category the viewer is interested in (0..1)
+    pub interest: f64,
+    pub same_school: f64,
+    pub same_year: f64,
+    pub has_media: f64,
+    pub featured: f64,
+    /// Multiplied by ln(1 + engagement velocity)
+    pub engagement: f64,
+    /// Score halves every this many hours
+    pub freshness_half_life_hours: f64,
+    /// Multiplier for posts the viewer has already opened
+    pub seen_penalty: f64,
+    /// Multiplier applied per earlier pick from the same author during re-ranking
+    pub author_repeat_penalty: f64,
+}
+
+impl Default for FeedWeights {
+    fn default() -> Self {
+        FeedWeights {
+            followed_author: 50.0,
+            interest: 40.0,
+            same_school: 15.0,
+            same_year: 20.0,
+            has_media: 15.0,
+            featured: 30.0,
+            engagement: 25.0,
+            freshness_half_life_hours: 24.0,
+            seen_penalty: 0.2,
+            author_repeat_penalty: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FeedRankingSettings {
+    /// "weighted" or "chronological"
+    pub scorer: String,
+    pub weights: FeedWeights,
+}
+
+impl Default for FeedRankingSettings {
+    fn default() -> Self {
+        FeedRankingSettings { scorer: "weighted".to_string(), weights: FeedWeights::default() }
+    }
+}
+
+impl FeedRankingSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if scorer_for(self).is_none() {
+            return Err(format!("Unknown feed scorer '{}'", self.scorer));
+        }
+        let w = &self.weights;
+        let non_negative = [w.followed_author, w.interest, w.same_school, w.same_year, w.has_media, w.featured, w.engagement];
+        if non_negative.iter().any(|v| !v.is_finite() || *v < 0.0) {
+            return Err("Feed weights must be non-negative numbers".to_string());
+        }
+        if !w.freshness_half_life_hours.is_finite() || w.freshness_half_life_hours <= 0.0 {
+            return Err("freshness_half_life_hours must be positive".to_string());
+        }
+        if !(0.0..=1.0).contains(&w.seen_penalty) || !(0.0..=1.0).contains(&w.author_repeat_penalty) {
+            return Err("seen_penalty and author_repeat_penalty must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// What the viewer brings to ranking.
+#[derive(Debug, Clone, Default)]
+pub struct ViewerContext {
+    pub followed: HashSet<ObjectId>,
+    pub interests: HashSet<String>,
+    pub school: Option<String>,
+    pub year_of_study: Option<i32>,
+    pub seen: HashSet<ObjectId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostFeatures {
+    pub age_hours: f64,
+    /// Weighted interactions per sqrt(hour) since publishing
+    pub engagement_velocity: f64,
+    pub followed_author: bool,
+    pub interest_overlap: f64,
+    pub same_school: bool,
+    pub same_year: bool,
+    pub has_media: bool,
+    pub is_featured: bool,
+    pub seen: bool,
+}
+
+pub fn extract_features(viewer: &ViewerContext, post: &Post, author: Option<&Profile>, now_ms: i64) -> PostFeatures {
+    let published_ms = post.published_at.unwrap_or(post.created_at).timestamp_millis();
+    let age_hours = ((now_ms - published_ms) as f64 / 3_600_000.0).max(0.0);
+    let engagement = post.like_count as f64 + post.comment_count as f64 * 2.0 + post.share_count as f64 * 3.0;
+
+    let mut topics: HashSet<String> = post.tags.iter().flatten().map(|t| t.to_lowercase()).collect();
+    if !post.category.is_empty() && post.category != "general" {
+        topics.insert(post.category.to_lowercase());
+    }
+    let interest_overlap = if topics.is_empty() || viewer.interests.is_empty() {
+        0.0
+    } else {
+        topics.iter().filter(|t| viewer.interests.contains(*t)).count() as f64 / topics.len() as f64
+    };
+
+    let same_school = match (viewer.school.as_deref(), author.and_then(|a| a.school.as_deref())) {
+        (Some(a), Some(b)) => !a.trim().is_empty() && a.trim().eq_ignore_ascii_case(b.trim()),
+        _ => false,
+    };
+    let same_year = viewer.year_of_study.is_some() && viewer.year_of_study == author.and_then(|a| a.year_of_study);
+
+    PostFeatures {
+        age_hours,
+        engagement_velocity: engagement.max(0.0) / age_hours.max(0.5).sqrt(),
+        followed_author: viewer.followed.contains(&post.author_id),
+        interest_overlap,
+        same_school,
+        same_year,
+        has_media: post.media_urls.as_ref().is_some_and(|m| !m.is_empty()),
+        is_featured: post.is_featured,
+        seen: post.id.is_some_and(|id| viewer.seen.contains(&id)),
+    }
+}
+
+pub trait FeedScorer: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn score(&self, features: &PostFeatures) -> f64;
+}
+
+/// Blends affinity signals and engagement, then decays by age and suppresses seen posts.
+pub struct WeightedScorer {
+    pub weights: FeedWeights,
+}
+
+impl FeedScorer for WeightedScorer {
+    fn name(&self) -> &'static str {
+        "weighted"
+    }
+
+    fn score(&self, f: &PostFeatures) -> f64 {
+        let w = &self.weights;
+        let mut relevance = w.engagement * f.engagement_velocity.ln_1p() + w.interest * f.interest_overlap;
+        if f.followed_author { relevance += w.followed_author; }
+        if f.same_school { relevance += w.same_school; }
+        if f.same_year { relevance += w.same_year; }
+        if f.has_media { relevance += w.has_media; }
+        if f.is_featured { relevance += w.featured; }
+
+        // Keep a small floor so brand-new posts with no signals still sort by freshness
+        let freshness = 0.5f64.powf(f.age_hours / w.freshness_half_life_hours);
+        let mut score = (relevance + 1.0) * freshness;
+        if f.seen {
+            score *= w.seen_penalty;
+        }
+        score
+    }
+}
+
+/// Newest first; seen posts still sink.
+pub struct ChronologicalScorer {
+    pub seen_penalty: f64,
+}
+
+impl FeedScorer for ChronologicalScorer {
+    fn name(&self) -> &'static str {
+        "chronological"
+    }
+
+    fn score(&self, f: &PostFeatures) -> f64 {
+        let score = 1.0 / (1.0 + f.age_hours);
+        if f.seen { score * self.seen_penalty } else { score }
+    }
+}
+
+pub fn scorer_for(settings: &FeedRankingSettings) -> Option<Box<dyn FeedScorer>> {
+    match settings.scorer.as_str() {
+        "weighted" => Some(Box::new(WeightedScorer { weights: settings.weights.clone() })),
+        "chronological" => Some(Box::new(ChronologicalScorer { seen_penalty: settings.weights.seen_penalty })),
+        _ => None,
+    }
+}
+
+/// Greedy re-rank: each pick takes the best remaining post after discounting authors who
+/// already appear, so one prolific account can't take over the top of the feed.
+pub fn diversify(mut scored: Vec<(f64, ObjectId, ObjectId)>, author_repeat_penalty: f64) -> Vec<ObjectId> {
+    let mut picked_per_author: HashMap<ObjectId, i32> = HashMap::new();
+    let mut ranked = Vec::with_capacity(scored.len());
+    let mut last_author = None;
+
+    while !scored.is_empty() {
+        let mut best = 0;
+        let mut best_score = f64::MIN;
+        for (i, (score, _, author)) in scored.iter().enumerate() {
+            let mut adjusted = score * author_repeat_penalty.powi(*picked_per_author.get(author).unwrap_or(&0));
+            // Never two in a row from the same author unless nothing else is left
+            if last_author == Some(*author) {
+                adjusted *= 0.5;
+            }
+            if adjusted > best_score {
+                best_score = adjusted;
+                best = i;
+            }
+        }
+        let (_, post_id, author) = scored.swap_remove(best);
+        *picked_per_author.entry(author).or_insert(0) += 1;
+        last_author = Some(author);
+        ranked.push(post_id);
+    }
+    ranked
+}
+
+// --- Data loading ---
+
+pub async fn load_ranking_settings(state: &Arc<AppState>) -> FeedRankingSettings {
+    if let Some(cached) = state.cache.get::<FeedRankingSettings>(SETTINGS_CACHE_KEY).await {
+        return cached;
+    }
+    let settings = state
+        .mongo
+        .collection::<Document>("settings")
+        .find_one(doc! {}, None)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|d| d.get_document("feed_ranking").ok().cloned())
+        .and_then(|d| mongodb::bson::from_document::<FeedRankingSettings>(d).ok())
+        .filter(|s| s.validate().is_ok())
+        .unwrap_or_default();
+    state.cache.set(SETTINGS_CACHE_KEY, &settings, 60).await;
+    settings
+}
+
+pub async fn invalidate_ranking_settings(state: &Arc<AppState>) {
+    let _ = state.cache.invalidate_pattern(SETTINGS_CACHE_KEY).await;
+}
+
+async fn collect_posts(state: &Arc<AppState>, filter: Document, sort: Document, out: &mut HashMap<ObjectId, Post>) {
+    let posts = state.mongo.collection::<Post>("posts");
+    let options = FindOptions::builder().sort(sort).limit(SOURCE_LIMIT).build();
+    if let Ok(mut cursor) = posts.find(filter, options).await {
+        while let Some(Ok(post)) = cursor.next().await {
+            if let Some(id) = post.id {
+                out.entry(id).or_insert(post);
+            }
+        }
+    }
+}
+
+async fn load_viewer(state: &Arc<AppState>, user_id: ObjectId) -> (ViewerContext, Option<Profile>) {
+    let mut viewer = ViewerContext::default();
+
+    let follows = state.mongo.collection::<Follow>("follows");
+    if let Ok(mut cursor) = follows.find(doc! { "follower_id": user_id }, None).await {
+        while let Some(Ok(f)) = cursor.next().await {
+            viewer.followed.insert(f.followed_id);
+        }
+    }
+
+    let profile = state.mongo.collection::<Profile>("profiles").find_one(doc! { "user_id": user_id }, None).await.ok().flatten();
+    if let Some(ref p) = profile {
+        viewer.interests = p.interests.iter().flatten().map(|i| i.trim().to_lowercase()).filter(|i| !i.is_empty()).collect();
+        viewer.school = p.school.clone();
+        viewer.year_of_study = p.year_of_study;
+    }
+    (viewer, profile)
+}
+
+/// Runs the whole pipeline for one viewer and returns post ids in feed order.
+pub async fn rank_for_you(state: &Arc<AppState>, user_id: ObjectId) -> Vec<ObjectId> {
+    let settings = load_ranking_settings(state).await;
+    let Some(scorer) = scorer_for(&settings) else { return vec![] };
+    let (mut viewer, profile) = load_viewer(state, user_id).await;
+
+    let since = mongodb::bson::DateTime::from_chrono(Utc::now() - chrono::Duration::days(CANDIDATE_WINDOW_DAYS));
+    let mut base = doc! { "status": "published", "created_at": { "$gte": since }, "author_id": { "$ne": user_id } };
+    if let Some(blocked) = profile.as_ref().and_then(|p| p.blocked_users.clone()).filter(|b| !b.is_empty()) {
+        base.insert("author_id", doc! { "$ne": user_id, "$nin": blocked });
+    }
+    if let Some(hidden) = profile.as_ref().and_then(|p| p.hidden_posts.clone()).filter(|h| !h.is_empty()) {
+        base.insert("_id", doc! { "$nin": hidden });
+    }
+    let with = |extra: Document| {
+        let mut filter = base.clone();
+        filter.extend(extra);
+        filter
+    };
+
+    // 1. Candidate generation from several sources
+    let mut candidates: HashMap<ObjectId, Post> = HashMap::new();
+    // Followed authors, joined groups and followed pages all come from the home timeline
+    if let Ok(Some(home)) = super::timeline::home_page(state, user_id, None, SOURCE_LIMIT as usize).await {
+        let blocked = profile.as_ref().and_then(|p| p.blocked_users.clone()).unwrap_or_default();
+        let hidden = profile.as_ref().and_then(|p| p.hidden_posts.clone()).unwrap_or_default();
+        for post in home.posts {
+            let Some(id) = post.id else { continue };
+            if post.author_id == user_id || blocked.contains(&post.author_id) || hidden.contains(&id) || post.created_at < since {
+                continue;
+            }
+            candidates.entry(id).or_insert(post);
+        }
+    }
+
+    if !viewer.interests.is_empty() {
+        let interests: Vec<&String> = viewer.interests.iter().collect();
+        collect_posts(
+            state,
+            with(doc! { "$or": [{ "tags": { "$in": &interests } }, { "category": { "$in": &interests } }] }),
+            doc! { "_id": -1 },
+            &mut candidates,
+        )
+        .await;
+    }
+
+    // Popular posts from outside the viewer's graph (private groups stay private)
+    collect_posts(state, with(doc! { "group_id": null }), doc! { "like_count": -1 }, &mut candidates).await;
+
+    if candidates.is_empty() {
+        return vec![];
+    }
+
+    // 2. Feature extraction
+    let candidate_ids: Vec<ObjectId> = candidates.keys().copied().collect();
+    let seen_since = mongodb::bson::DateTime::from_chrono(Utc::now() - chrono::Duration::days(SEEN_LOOKBACK_DAYS));
+    if let Ok(mut cursor) = state
+        .mongo
+        .collection::<PostView>("post_views")
+        .find(doc! { "user_id": user_id, "post_id": { "$in": &candidate_ids }, "viewed_at": { "$gte": seen_since } }, None)
+        .await
+    {
+        while let Some(Ok(view)) = cursor.next().await {
+            viewer.seen.insert(view.post_id);
+        }
+    }
+
+    let author_ids: Vec<ObjectId> = candidates.values().map(|p| p.author_id).collect::<HashSet<_>>().into_iter().collect();
+    let mut authors: HashMap<ObjectId, Profile> = HashMap::new();
+    if let Ok(mut cursor) = state.mongo.collection::<Profile>("profiles").find(doc! { "user_id": { "$in": &author_ids } }, None).await {
+        while let Some(Ok(p)) = cursor.next().await {
+            authors.insert(p.user_id, p);
+        }
+    }
+
+    // 3. Scoring
+    let now_ms = Utc::now().timestamp_millis();
+    let scored: Vec<(f64, ObjectId, ObjectId)> = candidates
+        .iter()
+        .map(|(id, post)| {
+            let features = extract_features(&viewer, post, authors.get(&post.author_id), now_ms);
+            (scorer.score(&features), *id, post.author_id)
+        })
+        .collect();
+
+    // 4. Diversity re-ranking
+    let mut ranked = diversify(scored, settings.weights.author_repeat_penalty);
+    ranked.truncate(RANKED_FEED_SIZE);
+    tracing::debug!("Ranked {} for-you posts for {} with {} scorer", ranked.len(), user_id, scorer.name());
+    ranked
+}
+
+fn ranked_feed_key(user_id: ObjectId) -> String {
+    format!("foryou:ranked:{}", user_id.to_hex())
+}
+
+/// Returns one page of the viewer's ranked feed plus whether more follow. The first page
+/// re-ranks; later pages read the ranking cached by it so pagination stays stable.
+pub async fn for_you_page(state: &Arc<AppState>, user_id: ObjectId, after: Option<ObjectId>, limit: usize) -> (Vec<ObjectId>, bool) {
+    let key = ranked_feed_key(user_id);
+    let cached = match after {
+        Some(_) => state.cache.get::<Vec<String>>(&key).await,
+        None => None,
+    };
+    let ranking: Vec<ObjectId> = match cached {
+        Some(ids) => ids.iter().filter_map(|id| ObjectId::parse_str(id).ok()).collect(),
+        None => {
+            let ranked = rank_for_you(state, user_id).await;
+            let ids: Vec<String> = ranked.iter().map(|id| id.to_hex()).collect();
+            state.cache.set(&key, &ids, RANKED_FEED_TTL_SECS).await;
+            ranked
+        }
+    };
+
+    let start = after.and_then(|a| ranking.iter().position(|id| *id == a).map(|i| i + 1)).unwrap_or(0);
+    let page: Vec<ObjectId> = ranking.iter().skip(start).take(limit).copied().collect();
+    let has_more = start + page.len() < ranking.len();
+    (page, has_more)
+}
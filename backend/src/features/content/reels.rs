@@ -16,6 +16,7 @@ use crate::models::{
     Location, Subtitle, Caption, User, Profile
 };
 use crate::features::auth::auth_service::AuthUser;
+use crate::features::infrastructure::spam::{self, ContentKind};
 use futures::stream::StreamExt;
 
 // --- DTOs ---
@@ -369,6 +370,10 @@ pub async fn create_reel_handler(
     let (username, user_avatar) = profile.map(|p| (p.username, p.avatar_url))
         .unwrap_or_else(|| ("Unknown".to_string(), None));
 
+    let caption = format!("{}\n{}", payload.title.as_deref().unwrap_or_default(), payload.description);
+    let verdict = spam::classify(&state, user.user_id, ContentKind::ReelCaption, &caption).await;
+    let flagged = verdict.is_spam(spam::flag_threshold());
+
     let now = DateTime::now();
     let new_reel = Reel {
         id: None,
@@ -406,9 +411,10 @@ pub async fn create_reel_handler(
         deleted_at: None,
         deleted_by: None,
         deleted_reason: None,
-        moderation_status: "pending".to_string(),
-        moderation_notes: None,
-        spam_score: None,
+        // "flagged" keeps the reel out of the feed until a moderator approves it
+        moderation_status: if flagged { "flagged".to_string() } else { "pending".to_string() },
+        moderation_notes: if flagged { Some(verdict.summary()) } else { None },
+        spam_score: Some(verdict.score),
         sentiment_score: None,
         reported_count: 0,
         view_count: 0,
@@ -808,6 +814,7 @@ pub async fn get_moderation_queue_handler(
     let mut query = doc! {
         "$or": [
             doc! { "moderation_status": "pending" },
+            doc! { "moderation_status": "flagged" },
             doc! { "moderation_status": "rejected" },
             doc! { "reported_count": { "$gt": 0 } }
         ]
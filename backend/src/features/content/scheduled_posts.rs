@@ -0,0 +1,179 @@
+use std::sync::Arc;
+use futures::stream::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use serde::{Deserialize, Serialize};
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::jobs::{self, Job, JobError};
+use crate::features::social::notifications::create_notification;
+use crate::features::social::gamification::{self, Activity};
+use crate::models::{Follow, Notification, Post};
+
+const LEADER_LOCK_KEY: &str = "posts:scheduler:leader";
+const LEADER_LOCK_SECS: u64 = 120;
+const FANOUT_PAGE_SIZE: i64 = 500;
+
+/// Publishes every `scheduled` post whose `scheduled_publish_date` has passed. Runs each minute.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishScheduledPostsJob;
+
+impl Job for PublishScheduledPostsJob {
+    const KIND: &'static str = "posts.publish_scheduled";
+    const MAX_ATTEMPTS: u32 = 3;
+}
+
+/// Tells an author's followers about a post that just went live, one page of followers per
+/// run so a retry only repeats the page that failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostFanoutJob {
+    pub post_id: String,
+    pub after_follower: Option<String>,
+}
+
+impl Job for PostFanoutJob {
+    const KIND: &'static str = "posts.fanout";
+}
+
+/// Only one instance sweeps at a time, even if a sweep outlives its cron minute.
+async fn acquire_leader(state: &Arc<AppState>, token: &str) -> redis::RedisResult<bool> {
+    let mut conn = state.redis.clone();
+    let claimed: Option<String> = redis::cmd("SET")
+        .arg(LEADER_LOCK_KEY)
+        .arg(token)
+        .arg("NX")
+        .arg("EX")
+        .arg(LEADER_LOCK_SECS)
+        .query_async(&mut conn)
+        .await?;
+    Ok(claimed.is_some())
+}
+
+async fn release_leader(state: &Arc<AppState>, token: &str) {
+    let script = redis::Script::new(
+        r#"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('DEL', KEYS[1])
+        end
+        return 0
+        "#,
+    );
+    let mut conn = state.redis.clone();
+    let _ = script.key(LEADER_LOCK_KEY).arg(token).invoke_async::<i64>(&mut conn).await;
+}
+
+/// Scheduled posts whose publish date has passed.
+pub fn due_filter(now: DateTime) -> mongodb::bson::Document {
+    doc! { "status": "scheduled", "scheduled_publish_date": { "$lte": now } }
+}
+
+pub fn publish_update(now: DateTime) -> mongodb::bson::Document {
+    doc! { "$set": { "status": "published", "published_at": now, "updated_at": now } }
+}
+
+/// Followers on a fan-out page who already have this post's notification, from a run that
+/// failed partway through the page.
+pub fn already_notified_filter(post_id: ObjectId, author_id: ObjectId, followers: &[ObjectId]) -> mongodb::bson::Document {
+    doc! {
+        "user_id": { "$in": followers },
+        "actor_id": author_id,
+        "notification_type": "new_post",
+        "target_id": post_id,
+    }
+}
+
+/// Flips one due post to `published`. The status filter makes this a compare-and-set, so a
+/// post cancelled or published by hand in the meantime is left alone.
+async fn publish_next_due(state: &Arc<AppState>) -> Result<Option<Post>, mongodb::error::Error> {
+    let posts = state.mongo.collection::<Post>("posts");
+    let now = DateTime::now();
+    posts
+        .find_one_and_update(
+            due_filter(now),
+            publish_update(now),
+            FindOneAndUpdateOptions::builder()
+                .sort(doc! { "scheduled_publish_date": 1 })
+                .return_document(ReturnDocument::After)
+                .build(),
+        )
+        .await
+}
+
+pub async fn run_publish_scheduled(state: Arc<AppState>) -> Result<(), JobError> {
+    let token = uuid::Uuid::new_v4().to_string();
+    if !acquire_leader(&state, &token).await.map_err(|e| JobError::Retry(e.to_string()))? {
+        return Ok(());
+    }
+
+    let mut published = 0;
+    let result = async {
+        while let Some(post) = publish_next_due(&state).await? {
+            let Some(post_id) = post.id else { continue };
+            published += 1;
+            crate::features::infrastructure::search::reindex_post(&state, post_id).await;
+            crate::features::content::timeline::on_post_published(&state, post_id).await;
+            gamification::record(&state, post.author_id, Activity::PostPublished, post_id.to_hex()).await;
+            if !post.is_anonymous {
+                let fanout = PostFanoutJob { post_id: post_id.to_hex(), after_follower: None };
+                if let Err(e) = jobs::enqueue(&state, fanout).await {
+                    tracing::error!("Failed to queue follower fan-out for post {}: {:?}", post_id, e);
+                }
+            }
+        }
+        Ok::<(), mongodb::error::Error>(())
+    }
+    .await;
+    release_leader(&state, &token).await;
+
+    if published > 0 {
+        tracing::info!("🗓️ Published {} scheduled posts", published);
+        let _ = state.cache.invalidate_pattern("posts:list:*").await;
+        let _ = state.cache.invalidate_pattern("feed:*").await;
+    }
+    result.map_err(JobError::from)
+}
+
+pub async fn run_post_fanout(state: Arc<AppState>, job: PostFanoutJob) -> Result<(), JobError> {
+    let post_id = ObjectId::parse_str(&job.post_id).map_err(|_| JobError::Fatal("Invalid post id".to_string()))?;
+    let posts = state.mongo.collection::<Post>("posts");
+    let Some(post) = posts.find_one(doc! { "_id": post_id, "status": "published" }, None).await? else {
+        return Ok(()); // Deleted or unpublished since
+    };
+
+    let mut filter = doc! { "followed_id": post.author_id };
+    if let Some(after) = job.after_follower.as_deref().and_then(|id| ObjectId::parse_str(id).ok()) {
+        filter.insert("follower_id", doc! { "$gt": after });
+    }
+    let follows = state.mongo.collection::<Follow>("follows");
+    let mut cursor = follows
+        .find(filter, FindOptions::builder().sort(doc! { "follower_id": 1 }).limit(FANOUT_PAGE_SIZE).build())
+        .await?;
+
+    let preview: String = if post.title.trim().is_empty() { post.content.chars().take(80).collect() } else { post.title.clone() };
+    let message = format!("{} published a new post: {}", post.author_name, preview);
+    let mut page = Vec::new();
+    while let Some(follow) = cursor.next().await {
+        page.push(follow?.follower_id);
+    }
+
+    // A retry of this page skips whoever the failed run already notified
+    let notified: Vec<ObjectId> = state.mongo.collection::<Notification>("notifications")
+        .distinct("user_id", already_notified_filter(post_id, post.author_id, &page), None)
+        .await?
+        .iter()
+        .filter_map(|id| id.as_object_id())
+        .collect();
+    let mut count = 0;
+    for follower in page.iter().filter(|f| !notified.contains(f)) {
+        create_notification(&state, *follower, post.author_id, "new_post", Some(post_id), &message, true).await?;
+        count += 1;
+    }
+
+    if page.len() as i64 == FANOUT_PAGE_SIZE {
+        if let Some(last) = page.last().copied() {
+            let next = PostFanoutJob { post_id: job.post_id.clone(), after_follower: Some(last.to_hex()) };
+            jobs::enqueue(&state, next).await.map_err(|e| JobError::Retry(format!("{:?}", e)))?;
+        }
+    }
+    tracing::debug!("Notified {} followers about post {}", count, post_id);
+    Ok(())
+}
@@ -0,0 +1,394 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use chrono::Utc;
+use futures::stream::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::options::FindOptions;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::error::AppResult;
+use crate::features::infrastructure::jobs::{self, Job, JobError};
+use crate::models::{Follow, Post, Profile};
+
+// Home timelines: followed authors (plus your own posts) are fanned out on write into
+// `timeline:home:{user}` sorted sets scored by publish time. Followed pages, joined groups
+// and authors with very large followings are fanned out on read and merged in.
+
+const HOME_TIMELINE_MAX: isize = 800;
+const BACKFILL_DAYS: i64 = 14;
+const TIMELINE_TTL_SECS: i64 = 7 * 24 * 3600;
+const SOURCES_TTL_SECS: u64 = 300;
+const FANOUT_BATCH: i64 = 500;
+// Marks a timeline as built even when it holds no posts yet, so it isn't rebuilt every read
+const SENTINEL: &str = "-";
+
+fn fanout_max_followers() -> u64 {
+    std::env::var("TIMELINE_FANOUT_MAX_FOLLOWERS").ok().and_then(|v| v.parse().ok()).unwrap_or(5000)
+}
+
+fn home_key(user_id: ObjectId) -> String {
+    format!("timeline:home:{}", user_id.to_hex())
+}
+
+fn sources_key(user_id: ObjectId) -> String {
+    format!("timeline:sources:{}", user_id.to_hex())
+}
+
+/// Authors above the threshold are read at request time instead of written to every follower.
+pub fn fans_out_on_write(follower_count: u64, max_followers: u64) -> bool {
+    follower_count <= max_followers
+}
+
+/// Only plain profile posts are pushed; group and page posts are pulled, and anonymous posts
+/// never reach home timelines because that would tell followers who wrote them.
+pub fn is_pushable(post: &Post) -> bool {
+    post.status == "published" && post.group_id.is_none() && post.page_id.is_none() && !post.is_anonymous
+}
+
+pub fn post_score(post: &Post) -> f64 {
+    post.published_at.unwrap_or(post.created_at).timestamp_millis() as f64
+}
+
+/// Merges timeline entries (newest first, by score then id) strictly older than `cursor`,
+/// dropping duplicates. Returns one page and whether more entries remain.
+pub fn merge_entries(
+    sources: Vec<Vec<(f64, ObjectId)>>,
+    cursor: Option<(f64, ObjectId)>,
+    limit: usize,
+) -> (Vec<(f64, ObjectId)>, bool) {
+    let mut seen = HashSet::new();
+    let mut merged: Vec<(f64, ObjectId)> = sources
+        .into_iter()
+        .flatten()
+        .filter(|(score, id)| match cursor {
+            Some((cs, cid)) => *score < cs || (*score == cs && id.bytes() < cid.bytes()),
+            None => true,
+        })
+        .filter(|(_, id)| seen.insert(*id))
+        .collect();
+    merged.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| b.1.bytes().cmp(&a.1.bytes())));
+    let has_more = merged.len() > limit;
+    merged.truncate(limit);
+    (merged, has_more)
+}
+
+/// Where a user's home timeline comes from. Cached briefly; follow/join handlers invalidate it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimelineSources {
+    pub push_authors: Vec<ObjectId>,
+    pub pull_authors: Vec<ObjectId>,
+    pub pages: Vec<ObjectId>,
+    pub groups: Vec<ObjectId>,
+}
+
+impl TimelineSources {
+    pub fn is_empty(&self) -> bool {
+        self.push_authors.is_empty() && self.pull_authors.is_empty() && self.pages.is_empty() && self.groups.is_empty()
+    }
+}
+
+async fn load_sources(state: &Arc<AppState>, user_id: ObjectId) -> Result<TimelineSources, mongodb::error::Error> {
+    if let Some(cached) = state.cache.get::<TimelineSources>(&sources_key(user_id)).await {
+        return Ok(cached);
+    }
+
+    let mut followed = Vec::new();
+    let mut cursor = state.mongo.collection::<Follow>("follows").find(doc! { "follower_id": user_id }, None).await?;
+    while let Some(Ok(f)) = cursor.next().await {
+        followed.push(f.followed_id);
+    }
+
+    let max_followers = fanout_max_followers();
+    let mut sources = TimelineSources::default();
+    if !followed.is_empty() {
+        let options = FindOptions::builder().projection(doc! { "user_id": 1, "follower_count": 1 }).build();
+        let mut counts = HashMap::new();
+        let mut cursor = state
+            .mongo
+            .collection::<Document>("profiles")
+            .find(doc! { "user_id": { "$in": &followed } }, options)
+            .await?;
+        while let Some(Ok(p)) = cursor.next().await {
+            if let Ok(id) = p.get_object_id("user_id") {
+                let count = p.get_i64("follower_count").or_else(|_| p.get_i32("follower_count").map(i64::from)).unwrap_or(0);
+                counts.insert(id, count.max(0) as u64);
+            }
+        }
+        for id in followed {
+            if fans_out_on_write(*counts.get(&id).unwrap_or(&0), max_followers) {
+                sources.push_authors.push(id);
+            } else {
+                sources.pull_authors.push(id);
+            }
+        }
+    }
+
+    let mut cursor = state.mongo.collection::<crate::models::PageFollow>("page_follows").find(doc! { "user_id": user_id }, None).await?;
+    while let Some(Ok(f)) = cursor.next().await {
+        sources.pages.push(f.page_id);
+    }
+    let mut cursor = state
+        .mongo
+        .collection::<Document>("groups")
+        .find(doc! { "members": user_id }, FindOptions::builder().projection(doc! { "_id": 1 }).build())
+        .await?;
+    while let Some(Ok(g)) = cursor.next().await {
+        if let Ok(id) = g.get_object_id("_id") {
+            sources.groups.push(id);
+        }
+    }
+
+    state.cache.set(&sources_key(user_id), &sources, SOURCES_TTL_SECS).await;
+    Ok(sources)
+}
+
+pub async fn invalidate_sources(state: &Arc<AppState>, user_id: ObjectId) {
+    let _ = state.cache.invalidate_pattern(&sources_key(user_id)).await;
+}
+
+async fn find_entries(state: &Arc<AppState>, filter: Document, limit: i64) -> Result<Vec<(f64, ObjectId)>, mongodb::error::Error> {
+    let options = FindOptions::builder()
+        .sort(doc! { "published_at": -1, "_id": -1 })
+        .limit(limit)
+        .build();
+    let mut cursor = state.mongo.collection::<Post>("posts").find(filter, options).await?;
+    let mut entries = Vec::new();
+    while let Some(Ok(post)) = cursor.next().await {
+        if let Some(id) = post.id {
+            entries.push((post_score(&post), id));
+        }
+    }
+    Ok(entries)
+}
+
+fn pushable_filter(authors: &[ObjectId]) -> Document {
+    doc! {
+        "status": "published",
+        "author_id": { "$in": authors },
+        "group_id": null,
+        "page_id": null,
+        "is_anonymous": { "$ne": true },
+    }
+}
+
+/// Rebuilds a home timeline from Mongo (first visit, expired, or after a Redis flush).
+async fn backfill(state: &Arc<AppState>, user_id: ObjectId, sources: &TimelineSources) -> AppResult<()> {
+    let mut authors = sources.push_authors.clone();
+    authors.push(user_id);
+    let since = mongodb::bson::DateTime::from_chrono(Utc::now() - chrono::Duration::days(BACKFILL_DAYS));
+    let mut filter = pushable_filter(&authors);
+    filter.insert("published_at", doc! { "$gte": since });
+    let entries = find_entries(state, filter, HOME_TIMELINE_MAX as i64).await?;
+
+    let key = home_key(user_id);
+    let mut pipe = redis::pipe();
+    pipe.atomic().del(&key).ignore().zadd(&key, SENTINEL, 0).ignore();
+    for (score, id) in entries {
+        pipe.zadd(&key, id.to_hex(), score).ignore();
+    }
+    pipe.expire(&key, TIMELINE_TTL_SECS).ignore();
+    let mut conn = state.redis.clone();
+    pipe.query_async::<()>(&mut conn).await?;
+    Ok(())
+}
+
+/// Adds a post to timelines that already exist; inactive users pick it up on backfill.
+async fn push_to_timelines(state: &Arc<AppState>, user_ids: &[ObjectId], post_id: ObjectId, score: f64) -> redis::RedisResult<()> {
+    let script = redis::Script::new(
+        r#"
+        for _, key in ipairs(KEYS) do
+            if redis.call('EXISTS', key) == 1 then
+                redis.call('ZADD', key, ARGV[1], ARGV[2])
+                redis.call('ZREMRANGEBYRANK', key, 0, -tonumber(ARGV[3]) - 1)
+            end
+        end
+        return #KEYS
+        "#,
+    );
+    let mut invocation = script.prepare_invoke();
+    for id in user_ids {
+        invocation.key(home_key(*id));
+    }
+    invocation.arg(score).arg(post_id.to_hex()).arg(HOME_TIMELINE_MAX);
+    let mut conn = state.redis.clone();
+    invocation.invoke_async::<i64>(&mut conn).await.map(|_| ())
+}
+
+/// Pushes a freshly published post to its author's followers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineFanoutJob {
+    pub post_id: String,
+}
+
+impl Job for TimelineFanoutJob {
+    const KIND: &'static str = "timeline.fanout";
+}
+
+pub async fn run_timeline_fanout(state: Arc<AppState>, job: TimelineFanoutJob) -> Result<(), JobError> {
+    let post_id = ObjectId::parse_str(&job.post_id).map_err(|_| JobError::Fatal("Invalid post id".to_string()))?;
+    let Some(post) = state.mongo.collection::<Post>("posts").find_one(doc! { "_id": post_id }, None).await? else {
+        return Ok(());
+    };
+    if !is_pushable(&post) {
+        return Ok(());
+    }
+    let score = post_score(&post);
+    push_to_timelines(&state, &[post.author_id], post_id, score).await.map_err(|e| JobError::Retry(e.to_string()))?;
+
+    let author = state.mongo.collection::<Profile>("profiles").find_one(doc! { "user_id": post.author_id }, None).await?;
+    if !fans_out_on_write(author.map(|a| a.follower_count).unwrap_or(0), fanout_max_followers()) {
+        return Ok(()); // Followers pull this author's posts at read time
+    }
+
+    let follows = state.mongo.collection::<Follow>("follows");
+    let mut after: Option<ObjectId> = None;
+    loop {
+        let mut filter = doc! { "followed_id": post.author_id };
+        if let Some(after) = after {
+            filter.insert("follower_id", doc! { "$gt": after });
+        }
+        let options = FindOptions::builder().sort(doc! { "follower_id": 1 }).limit(FANOUT_BATCH).build();
+        let mut cursor = follows.find(filter, options).await?;
+        let mut batch = Vec::new();
+        while let Some(Ok(f)) = cursor.next().await {
+            batch.push(f.follower_id);
+        }
+        if batch.is_empty() {
+            break;
+        }
+        // ZADD is idempotent, so a retry after a partial run just repeats some writes
+        push_to_timelines(&state, &batch, post_id, score).await.map_err(|e| JobError::Retry(e.to_string()))?;
+        if (batch.len() as i64) < FANOUT_BATCH {
+            break;
+        }
+        after = batch.last().copied();
+    }
+    Ok(())
+}
+
+/// Call whenever a post becomes published.
+pub async fn on_post_published(state: &Arc<AppState>, post_id: ObjectId) {
+    if let Err(e) = jobs::enqueue(state, TimelineFanoutJob { post_id: post_id.to_hex() }).await {
+        tracing::error!("Failed to queue timeline fan-out for post {}: {:?}", post_id, e);
+    }
+}
+
+/// Follow side effects: new sources, and the author's recent posts appear right away.
+pub async fn on_follow(state: &Arc<AppState>, follower_id: ObjectId, followed_id: ObjectId) {
+    invalidate_sources(state, follower_id).await;
+    let since = mongodb::bson::DateTime::from_chrono(Utc::now() - chrono::Duration::days(BACKFILL_DAYS));
+    let mut filter = pushable_filter(&[followed_id]);
+    filter.insert("published_at", doc! { "$gte": since });
+    let Ok(entries) = find_entries(state, filter, 50).await else { return };
+    for (score, id) in entries {
+        let _ = push_to_timelines(state, &[follower_id], id, score).await;
+    }
+}
+
+pub async fn on_unfollow(state: &Arc<AppState>, follower_id: ObjectId, followed_id: ObjectId) {
+    invalidate_sources(state, follower_id).await;
+    let since = mongodb::bson::DateTime::from_chrono(Utc::now() - chrono::Duration::days(BACKFILL_DAYS));
+    let mut filter = pushable_filter(&[followed_id]);
+    filter.insert("published_at", doc! { "$gte": since });
+    let Ok(entries) = find_entries(state, filter, HOME_TIMELINE_MAX as i64).await else { return };
+    if entries.is_empty() {
+        return;
+    }
+    let members: Vec<String> = entries.iter().map(|(_, id)| id.to_hex()).collect();
+    let mut conn = state.redis.clone();
+    let _ = conn.zrem::<_, _, ()>(home_key(follower_id), members).await;
+}
+
+async fn cursor_position(state: &Arc<AppState>, user_id: ObjectId, last_id: Option<&str>) -> Option<(f64, ObjectId)> {
+    let id = ObjectId::parse_str(last_id?).ok()?;
+    let mut conn = state.redis.clone();
+    if let Ok(Some(score)) = conn.zscore::<_, _, Option<f64>>(home_key(user_id), id.to_hex()).await {
+        return Some((score, id));
+    }
+    // Trimmed out of Redis or came from a pulled source
+    let post = state.mongo.collection::<Post>("posts").find_one(doc! { "_id": id }, None).await.ok()??;
+    Some((post_score(&post), id))
+}
+
+pub struct HomePage {
+    pub posts: Vec<Post>,
+    pub has_more: bool,
+}
+
+/// Reads one page of a user's home timeline. Returns `None` when the user follows nothing
+/// and belongs to no groups, so the caller can show the campus-wide feed instead.
+pub async fn home_page(state: &Arc<AppState>, user_id: ObjectId, last_id: Option<&str>, limit: usize) -> AppResult<Option<HomePage>> {
+    let sources = load_sources(state, user_id).await?;
+    if sources.is_empty() {
+        return Ok(None);
+    }
+
+    let key = home_key(user_id);
+    let mut conn = state.redis.clone();
+    let exists: bool = conn.exists(&key).await?;
+    if !exists {
+        backfill(state, user_id, &sources).await?;
+    }
+    let _ = conn.expire::<_, ()>(&key, TIMELINE_TTL_SECS).await;
+
+    let cursor = cursor_position(state, user_id, last_id).await;
+    // Over-fetch a little so ties on the cursor's score can be skipped client-side
+    let fetch = (limit + 1 + 20) as isize;
+    let max = cursor.map(|(score, _)| score.to_string()).unwrap_or_else(|| "+inf".to_string());
+    let raw: Vec<(String, f64)> = redis::cmd("ZREVRANGEBYSCORE")
+        .arg(&key)
+        .arg(&max)
+        .arg("(0")
+        .arg("WITHSCORES")
+        .arg("LIMIT")
+        .arg(0)
+        .arg(fetch)
+        .query_async(&mut conn)
+        .await?;
+    let pushed: Vec<(f64, ObjectId)> = raw
+        .into_iter()
+        .filter_map(|(member, score)| ObjectId::parse_str(&member).ok().map(|id| (score, id)))
+        .collect();
+
+    // Fan-out-on-read sources
+    let mut pull_parts = Vec::new();
+    if !sources.pull_authors.is_empty() {
+        pull_parts.push(pushable_filter(&sources.pull_authors));
+    }
+    if !sources.pages.is_empty() {
+        pull_parts.push(doc! { "page_id": { "$in": &sources.pages } });
+    }
+    if !sources.groups.is_empty() {
+        pull_parts.push(doc! { "group_id": { "$in": &sources.groups } });
+    }
+    let mut pulled = Vec::new();
+    if !pull_parts.is_empty() {
+        let mut filter = doc! { "status": "published", "$or": pull_parts };
+        if let Some((score, _)) = cursor {
+            filter.insert("published_at", doc! { "$lte": mongodb::bson::DateTime::from_millis(score as i64) });
+        }
+        pulled = find_entries(state, filter, fetch as i64).await?;
+    }
+
+    let (entries, has_more) = merge_entries(vec![pushed, pulled], cursor, limit);
+    let ids: Vec<ObjectId> = entries.iter().map(|(_, id)| *id).collect();
+    let mut by_id = HashMap::new();
+    if !ids.is_empty() {
+        let mut cursor = state.mongo.collection::<Post>("posts").find(doc! { "_id": { "$in": &ids }, "status": "published" }, None).await?;
+        while let Some(Ok(post)) = cursor.next().await {
+            if let Some(id) = post.id {
+                by_id.insert(id, post);
+            }
+        }
+    }
+
+    // Deleted or unpublished posts are dropped lazily from this timeline
+    let stale: Vec<String> = ids.iter().filter(|id| !by_id.contains_key(id)).map(|id| id.to_hex()).collect();
+    if !stale.is_empty() {
+        let _ = conn.zrem::<_, _, ()>(&key, stale).await;
+    }
+
+    let posts = ids.iter().filter_map(|id| by_id.remove(id)).collect();
+    Ok(Some(HomePage { posts, has_more }))
+}
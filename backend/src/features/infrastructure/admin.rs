@@ -11,13 +11,17 @@ use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
 use crate::models::{User, Profile, Post, Transaction, ContentModeration};
 use crate::features::auth::auth_service::AuthUser;
+use crate::features::auth::two_factor::admin_two_factor_required;
 use mongodb::bson::{doc, oid::ObjectId, DateTime};
 use futures::stream::StreamExt;
 use chrono::{Utc, Duration};
 use mongodb::options::UpdateOptions;
+use crate::features::social::hookup;
 use crate::features::social::ws::{send_to_user, WsPayload};
 use crate::features::social::messages::MessageResponse;
 use crate::features::content::posts::post_routes;
+use crate::features::content::ranking::{invalidate_ranking_settings, FeedRankingSettings};
+use crate::features::social::gamification::{invalidate_gamification_settings, GamificationSettings};
 use crate::features::content::stories::story_routes;
 use crate::features::social::events::event_routes;
 use crate::features::content::comments::comment_routes;
@@ -34,6 +38,10 @@ pub struct SystemSettings {
     pub maintenance_mode: bool,
     pub allow_new_registrations: bool,
     pub free_verification_limit: i64,
+    #[serde(default)]
+    pub feed_ranking: FeedRankingSettings,
+    #[serde(default)]
+    pub gamification: GamificationSettings,
 }
 
 #[derive(Deserialize)]
@@ -41,6 +49,8 @@ pub struct UpdateSettingsRequest {
     pub is_payment_enabled: Option<bool>,
     pub maintenance_mode: Option<bool>,
     pub allow_new_registrations: Option<bool>,
+    pub feed_ranking: Option<FeedRankingSettings>,
+    pub gamification: Option<GamificationSettings>,
 }
 
 // --- Admin Middleware ---
@@ -54,7 +64,15 @@ pub async fn require_admin(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
     
     match user_doc {
-        Some(u) if u.role == "admin" || u.role == "superadmin" => Ok(u.role),
+        Some(u) if u.role == "admin" || u.role == "superadmin" => {
+            if admin_two_factor_required() && !u.two_factor_enabled {
+                return Err((StatusCode::FORBIDDEN, Json(json!({
+                    "error": "Two-factor authentication must be enabled for admin access",
+                    "code": "TWO_FACTOR_REQUIRED"
+                }))));
+            }
+            Ok(u.role)
+        }
         _ => Err((StatusCode::FORBIDDEN, Json(json!({"error": "Admin access required"}))))
     }
 }
@@ -605,6 +623,29 @@ pub async fn update_moderation_handler(
         None
     ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
+    // Approving an auto-flagged post, confession or confession comment is what publishes it
+    if status == "approved" {
+        if let Ok(Some(item)) = moderation_collection.find_one(doc! { "_id": oid }, None).await {
+            let released = match item.content_type.as_str() {
+                "post" => crate::features::content::posts::release_held_post(&state, item.content_id, user.user_id).await,
+                "confession" => crate::features::social::confessions::release_held_confession(&state, item.content_id).await,
+                "confession_comment" => crate::features::social::confessions::release_held_confession_comment(&state, item.content_id).await,
+                _ => Ok(false),
+            };
+            released.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+        }
+    }
+
+    // Rejecting a reported hookup alias takes it down
+    if status == "rejected" {
+        if let Ok(Some(item)) = moderation_collection.find_one(doc! { "_id": oid }, None).await {
+            if item.content_type == "hookup_alias" {
+                hookup::suspend_alias(&state, item.content_id).await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+            }
+        }
+    }
+
     Ok((StatusCode::OK, Json(json!({"message": format!("Status set to {}", status)}))))
 }
 
@@ -623,6 +664,8 @@ pub async fn get_settings_handler(
             maintenance_mode: false,
             allow_new_registrations: true,
             free_verification_limit: 1000,
+            feed_ranking: FeedRankingSettings::default(),
+            gamification: GamificationSettings::default(),
         });
 
     Ok((StatusCode::OK, Json(settings)))
@@ -645,6 +688,18 @@ pub async fn update_settings_update(
     if let Some(val) = payload.is_payment_enabled { update_doc.insert("is_payment_enabled", val); }
     if let Some(val) = payload.maintenance_mode { update_doc.insert("maintenance_mode", val); }
     if let Some(val) = payload.allow_new_registrations { update_doc.insert("allow_new_registrations", val); }
+    if let Some(ref ranking) = payload.feed_ranking {
+        ranking.validate().map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+        let ranking_doc = mongodb::bson::to_bson(ranking)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+        update_doc.insert("feed_ranking", ranking_doc);
+    }
+    if let Some(ref gamification) = payload.gamification {
+        gamification.validate().map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+        let gamification_doc = mongodb::bson::to_bson(gamification)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+        update_doc.insert("gamification", gamification_doc);
+    }
 
     settings_collection.update_one(
         doc! {},
@@ -652,6 +707,13 @@ pub async fn update_settings_update(
         UpdateOptions::builder().upsert(true).build()
     ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
+    if payload.feed_ranking.is_some() {
+        invalidate_ranking_settings(&state).await;
+    }
+    if payload.gamification.is_some() {
+        invalidate_gamification_settings(&state).await;
+    }
+
     Ok((StatusCode::OK, Json(json!({"message": "System settings updated"}))))
 }
 
@@ -670,6 +732,8 @@ pub async fn get_public_settings_handler(
             maintenance_mode: false,
             allow_new_registrations: true,
             free_verification_limit: 1000,
+            feed_ranking: FeedRankingSettings::default(),
+            gamification: GamificationSettings::default(),
         });
 
     Ok((StatusCode::OK, Json(json!({
@@ -711,7 +775,8 @@ pub async fn broadcast_system_message_handler(
             // Find or create a 1-to-1 chat between admin and user marked as system
             let chat_query = doc! {
                 "participants": { "$all": [system_user_id, target_user_id] },
-                "is_group": false
+                "is_group": false,
+                "aliases": { "$exists": false }
             };
 
             let chat = state_clone.mongo.collection::<crate::models::Chat>("chats")
@@ -741,6 +806,9 @@ pub async fn broadcast_system_message_handler(
                     last_message_time: DateTime::now(),
                     disappearing_duration: None,
                     created_at: DateTime::now(),
+                    aliases: None,
+                    is_encrypted: false,
+                    cursors: Default::default(),
                 };
                 if let Ok(res) = state_clone.mongo.collection::<crate::models::Chat>("chats").insert_one(new_chat, None).await {
                     res.inserted_id.as_object_id().unwrap()
@@ -771,8 +839,12 @@ pub async fn broadcast_system_message_handler(
                 is_system: true,
                 is_announcement: true,
                 viewed_at: None,
+                viewed_by: Vec::new(),
                 expires_at: None,
                 created_at: DateTime::now(),
+                link_preview: None,
+                edited_at: None,
+                edit_history: Vec::new(),
             };
 
             let msg_res = state_clone.mongo.collection::<crate::models::Message>("messages").insert_one(new_msg, None).await;
@@ -781,6 +853,7 @@ pub async fn broadcast_system_message_handler(
             } else {
                 continue;
             };
+            crate::features::social::receipts::record_sent(&state_clone, chat_id, &[system_user_id, target_user_id], system_user_id, msg_id).await;
 
             // Notification
             let notif = crate::models::Notification {
@@ -809,6 +882,7 @@ pub async fn broadcast_system_message_handler(
                 reply_to: None,
                 reactions: vec![],
                 is_deleted: false,
+                status: None,
                 read_at: None,
                 encrypted_content: None,
                 encryption_iv: None,
@@ -818,6 +892,8 @@ pub async fn broadcast_system_message_handler(
                 is_view_once: false,
                 viewed_at: None,
                 expires_at: None,
+                link_preview: None,
+                edited_at: None,
             };
 
             let ws_payload = WsPayload {
@@ -926,4 +1002,7 @@ pub fn admin_routes() -> Router<Arc<AppState>> {
         .nest("/groups", group_routes())
         .nest("/pages", page_routes())
         .nest("/ads", ad_routes())
+        .nest("/jobs", crate::features::infrastructure::jobs::job_admin_routes())
+        .nest("/withdrawals", crate::features::monetization::payouts::payout_admin_routes())
+        .nest("/payments", crate::features::monetization::payments::payment_admin_routes())
 }
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+
+/// A five-field cron expression (minute hour day-of-month month day-of-week), evaluated in UTC.
+/// Supports `*`, lists (`1,15`), ranges (`1-5`) and steps (`*/15`, `0-30/10`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days_of_month: Vec<bool>,
+    months: Vec<bool>,
+    days_of_week: Vec<bool>,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>, String> {
+    let mut allowed = vec![false; (max + 1) as usize];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (r, s.parse::<u32>().map_err(|_| format!("Invalid step in '{}'", part))?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format!("Step can't be zero in '{}'", part));
+        }
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            let a = a.parse::<u32>().map_err(|_| format!("Invalid range in '{}'", part))?;
+            let b = b.parse::<u32>().map_err(|_| format!("Invalid range in '{}'", part))?;
+            (a, b)
+        } else {
+            let v = range.parse::<u32>().map_err(|_| format!("Invalid value '{}'", part))?;
+            // "5/10" means "from 5, every 10"
+            if part.contains('/') { (v, max) } else { (v, v) }
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("'{}' is outside {}-{}", part, min, max));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            allowed[v as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 5 fields, got {}", fields.len()));
+        }
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        // 7 is an alias for Sunday
+        if days_of_week[7] {
+            days_of_week[0] = true;
+        }
+        Ok(CronSchedule {
+            expression: expression.to_string(),
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            dom_restricted: fields[2] != "*",
+            dow_restricted: fields[4] != "*",
+        })
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Whether the schedule fires during the minute containing `at`.
+    pub fn matches(&self, at: DateTime<Utc>) -> bool {
+        let dom = self.days_of_month[at.day() as usize];
+        let dow = self.days_of_week[at.weekday().num_days_from_sunday() as usize];
+        // Classic cron: when both day fields are restricted, either may match
+        let day = match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        };
+        day && self.minutes[at.minute() as usize] && self.hours[at.hour() as usize] && self.months[at.month() as usize]
+    }
+
+    /// First firing strictly after `after`, searching up to a year ahead.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut candidate = after.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        let limit = after + Duration::days(366);
+        while candidate <= limit {
+            if !self.months[candidate.month() as usize] {
+                // Skip to the first minute of the next day; month changes soon enough
+                candidate = (candidate + Duration::days(1)).duration_trunc(Duration::days(1)).ok()?;
+                continue;
+            }
+            if self.matches(candidate) {
+                return Some(candidate);
+            }
+            if !self.hours[candidate.hour() as usize] {
+                candidate = (candidate + Duration::hours(1)).duration_trunc(Duration::hours(1)).ok()?;
+            } else {
+                candidate += Duration::minutes(1);
+            }
+        }
+        None
+    }
+}
@@ -8,6 +8,10 @@ use redis::aio::ConnectionManager;
 use std::env;
 use std::sync::Arc;
 use crate::features::infrastructure::cache::CacheService;
+use crate::features::infrastructure::search_index::SearchIndex;
+use crate::features::infrastructure::spam::SpamRules;
+use crate::features::infrastructure::mail::Mailer;
+use crate::features::monetization::provider::PaymentProvider;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -17,13 +21,16 @@ pub struct AppState {
     pub redis_url: String,
     pub jwt_secret: String,
     pub ws_connections: Arc<DashMap<ObjectId, Vec<mpsc::UnboundedSender<Message>>>>,
-    pub mpesa_token: Arc<DashMap<String, (String, i64)>>,
+    pub ws_topics: Arc<DashMap<String, Vec<mpsc::UnboundedSender<Message>>>>,
     pub ai_models: Arc<DashMap<String, Vec<serde_json::Value>>>,
     pub model_health: Arc<DashMap<String, serde_json::Value>>,
     pub http_client: reqwest::Client,
-    pub brevo_api_key: String,
+    pub mailer: Arc<dyn Mailer>,
+    pub payments: Arc<dyn PaymentProvider>,
     pub redis_presence_ttl: u64,
     pub redis_mongo_update_ttl: u64,
+    pub search_index: Arc<SearchIndex>,
+    pub spam_rules: Arc<SpamRules>,
 }
 
 pub async fn init_mongo() -> Database {
@@ -74,6 +81,22 @@ pub async fn init_mongo() -> Database {
         .build();
     let _ = posts_coll.create_index(post_index, None).await;
 
+    // Timeline fan-out-on-read and backfill: newest published posts per author/page/group
+    for field in ["author_id", "page_id", "group_id"] {
+        let timeline_index = IndexModel::builder()
+            .keys(bson::doc! { field: 1, "published_at": -1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build();
+        let _ = posts_coll.create_index(timeline_index, None).await;
+    }
+
+    // Scheduled publishing sweep: { status: 1, scheduled_publish_date: 1 }
+    let scheduled_index = IndexModel::builder()
+        .keys(bson::doc! { "status": 1, "scheduled_publish_date": 1 })
+        .options(IndexOptions::builder().background(Some(true)).build())
+        .build();
+    let _ = posts_coll.create_index(scheduled_index, None).await;
+
     // Messages Index: { conversation_id: 1, created_at: -1 }
     let messages_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("messages");
     let msg_index = IndexModel::builder()
@@ -81,6 +104,255 @@ pub async fn init_mongo() -> Database {
         .options(IndexOptions::builder().background(Some(true)).build())
         .build();
     let _ = messages_coll.create_index(msg_index, None).await;
+    // Swept every minute by the disappearing-message job
+    let msg_expiry_index = IndexModel::builder()
+        .keys(bson::doc! { "expires_at": 1 })
+        .options(IndexOptions::builder().background(Some(true)).build())
+        .build();
+    let _ = messages_coll.create_index(msg_expiry_index, None).await;
+
+    // Receipt log: the first cursor move past a message is when it was delivered or read
+    let receipts_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("message_receipts");
+    let receipts_index = IndexModel::builder()
+        .keys(bson::doc! { "chat_id": 1, "up_to": 1 })
+        .options(IndexOptions::builder().background(Some(true)).build())
+        .build();
+    let _ = receipts_coll.create_index(receipts_index, None).await;
+
+    // Scheduled messages: swept by send time, listed per sender
+    let scheduled_msgs_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("scheduled_messages");
+    let scheduled_msgs_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "status": 1, "send_at": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "sender_id": 1, "chat_id": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = scheduled_msgs_coll.create_indexes(scheduled_msgs_indexes, None).await;
+
+    // Push Subscriptions: unique endpoint, looked up by user
+    let push_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("push_subscriptions");
+    let push_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "endpoint": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "user_id": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = push_coll.create_indexes(push_indexes, None).await;
+
+    // Sessions: listed per user, purged by Mongo once the refresh window lapses
+    let sessions_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("sessions");
+    let session_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "user_id": 1, "last_seen_at": -1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Some(std::time::Duration::from_secs(7 * 24 * 3600))).background(Some(true)).build())
+            .build(),
+    ];
+    let _ = sessions_coll.create_indexes(session_indexes, None).await;
+
+    // Gamification ledger: each (user, activity, source) pays out once; listed newest first
+    let awards_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("point_awards");
+    let award_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "user_id": 1, "activity": 1, "source_id": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "user_id": 1, "_id": -1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = awards_coll.create_indexes(award_indexes, None).await;
+
+    // Payments: callbacks and status polls look transactions up by checkout id
+    let tx_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("transactions");
+    let _ = tx_coll.create_index(
+        IndexModel::builder()
+            .keys(bson::doc! { "checkout_request_id": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        None,
+    ).await;
+
+    // Material purchases: one per (material, buyer) so a replayed callback can't grant twice
+    let purchases_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("material_purchases");
+    let purchase_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "material_id": 1, "user_id": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "user_id": 1, "purchased_at": -1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = purchases_coll.create_indexes(purchase_indexes, None).await;
+
+    // Payments ledger: journals post once per key; one totals row per account
+    let journals_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("ledger_journals");
+    let journal_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "key": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        // The sweeper's queue of journals left unapplied
+        IndexModel::builder()
+            .keys(bson::doc! { "applied": 1, "created_at": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = journals_coll.create_indexes(journal_indexes, None).await;
+    let accounts_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("ledger_accounts");
+    let account_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "account": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "owner_id": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = accounts_coll.create_indexes(account_indexes, None).await;
+
+    // Creator withdrawals: per-user history, the admin review queue, and B2C result lookups
+    let withdrawals_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("withdrawals");
+    let withdrawal_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "user_id": 1, "created_at": -1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "status": 1, "created_at": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "conversation_id": 1 })
+            .options(IndexOptions::builder().sparse(Some(true)).background(Some(true)).build())
+            .build(),
+    ];
+    let _ = withdrawals_coll.create_indexes(withdrawal_indexes, None).await;
+
+    // Payment callbacks: the first one per checkout wins, retries are dropped
+    let callbacks_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("payment_callbacks");
+    let _ = callbacks_coll.create_index(
+        IndexModel::builder()
+            .keys(bson::doc! { "checkout_request_id": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        None,
+    ).await;
+
+    // Subscriptions: one per user; the expiry and reminder sweeps scan by status and end date
+    let subscriptions_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("subscriptions");
+    let subscription_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "user_id": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "status": 1, "current_period_end": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = subscriptions_coll.create_indexes(subscription_indexes, None).await;
+
+    // Hookup: one interaction per alias pair (re-swiping overwrites it), one match per pair
+    let hookup_matches_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("hookup_matches");
+    let hookup_match_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "from_alias_id": 1, "to_alias_id": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "to_alias_id": 1, "interaction": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = hookup_matches_coll.create_indexes(hookup_match_indexes, None).await;
+
+    let hookup_pairs_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("hookup_pairs");
+    let hookup_pair_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "pair_key": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "alias_ids": 1, "status": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = hookup_pairs_coll.create_indexes(hookup_pair_indexes, None).await;
+
+    // Live streams: ingest webhooks look streams up by key; the live list filters on is_active
+    let live_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("live_streams");
+    let live_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "stream_key": 1 })
+            .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "is_active": 1, "started_at": -1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+    ];
+    let _ = live_coll.create_indexes(live_indexes, None).await;
+
+    // Live chat: backlog and replay windows read a stream's visible lines by offset
+    let live_chat_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("live_chat_messages");
+    let live_chat_index = IndexModel::builder()
+        .keys(bson::doc! { "stream_id": 1, "status": 1, "offset_ms": 1 })
+        .options(IndexOptions::builder().background(Some(true)).build())
+        .build();
+    let _ = live_chat_coll.create_index(live_chat_index, None).await;
+
+    // E2E key directory: one key set per (user, device); relayed sender keys by recipient device
+    let device_keys_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("device_keys");
+    let device_keys_index = IndexModel::builder()
+        .keys(bson::doc! { "user_id": 1, "device_id": 1 })
+        .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+        .build();
+    let _ = device_keys_coll.create_index(device_keys_index, None).await;
+
+    let sender_keys_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("sender_key_messages");
+    let sender_keys_index = IndexModel::builder()
+        .keys(bson::doc! { "recipient_id": 1, "recipient_device_id": 1 })
+        .options(IndexOptions::builder().background(Some(true)).build())
+        .build();
+    let _ = sender_keys_coll.create_index(sender_keys_index, None).await;
+
+    // Two-factor enrollment: one record per user
+    let two_factor_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("two_factor");
+    let two_factor_index = IndexModel::builder()
+        .keys(bson::doc! { "user_id": 1 })
+        .options(IndexOptions::builder().unique(Some(true)).background(Some(true)).build())
+        .build();
+    let _ = two_factor_coll.create_index(two_factor_index, None).await;
+
+    // Email outbox: workers poll by due time; delivered mail is dropped after 30 days
+    let outbox_coll: mongodb::Collection<mongodb::bson::Document> = db.collection("email_outbox");
+    let outbox_indexes = vec![
+        IndexModel::builder()
+            .keys(bson::doc! { "status": 1, "next_attempt_at": 1 })
+            .options(IndexOptions::builder().background(Some(true)).build())
+            .build(),
+        IndexModel::builder()
+            .keys(bson::doc! { "sent_at": 1 })
+            .options(IndexOptions::builder().expire_after(Some(std::time::Duration::from_secs(30 * 24 * 3600))).background(Some(true)).build())
+            .build(),
+    ];
+    let _ = outbox_coll.create_indexes(outbox_indexes, None).await;
 
     tracing::info!("Database indexes verified.");
     
@@ -12,11 +12,15 @@ pub struct UserResponse {
     pub is_premium: bool,
     pub premium_expires_at: Option<String>,
     pub onboarded: bool,
+    pub is_2fa_enabled: bool,
+    pub email_verified: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AuthResponse {
     pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
     pub user: UserResponse,
 }
 
@@ -24,6 +28,77 @@ pub struct AuthResponse {
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct TokenPairResponse {
+    pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    pub id: String,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: String,
+    pub last_seen_at: String,
+    pub is_current: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RevokeAllSessionsRequest {
+    #[serde(default = "crate::models::base::default_true")]
+    pub keep_current: bool,
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorChallengeResponse {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+    pub user_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyTwoFactorRequest {
+    pub challenge_token: String,
+    pub code: String, // TOTP code or recovery code
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableTwoFactorRequest {
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
 }
 
 #[derive(Deserialize)]
@@ -162,6 +237,8 @@ pub struct PostResponse {
     pub cta_text: Option<String>,
     pub cta_url: Option<String>,
     pub is_ad: Option<bool>,
+    #[serde(default)]
+    pub link_preview: Option<crate::models::LinkPreview>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -255,6 +332,8 @@ pub struct UpdatePostRequest {
     pub seo_keywords: Option<Vec<String>>,
     pub meta_data: Option<serde_json::Value>,
     pub content_rating: Option<String>,
+    /// Moves a scheduled post back to draft
+    pub cancel_schedule: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -728,6 +807,7 @@ pub struct UpdateProfileRequest {
     pub onboarded: Option<bool>,
     pub interests: Option<Vec<String>>,
     pub notification_settings: Option<NotificationSettings>,
+    pub utc_offset_minutes: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -826,6 +906,16 @@ pub struct SpamRuleRequest {
     pub is_active: Option<bool>,
 }
 
+#[derive(Deserialize)]
+pub struct UpdateSpamRuleRequest {
+    pub name: Option<String>,
+    pub pattern: Option<String>,
+    pub description: Option<String>,
+    pub score: Option<f64>,
+    pub category: Option<String>,
+    pub is_active: Option<bool>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ConfessionResponse {
     pub id: String,
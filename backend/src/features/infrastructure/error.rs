@@ -12,6 +12,7 @@ pub enum AppError {
     Forbidden(String),
     NotFound(String),
     Conflict(String),
+    TooManyRequests(String),
     
     // Server errors (5xx)
     InternalServerError(String),
@@ -38,6 +39,7 @@ impl IntoResponse for AppError {
             AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, "forbidden", msg, Some("FORBIDDEN")),
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, "not_found", msg, Some("NOT_FOUND")),
             AppError::Conflict(msg) => (StatusCode::CONFLICT, "conflict", msg, Some("CONFLICT")),
+            AppError::TooManyRequests(msg) => (StatusCode::TOO_MANY_REQUESTS, "too_many_requests", msg, Some("TOO_MANY_REQUESTS")),
             AppError::InternalServerError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_server_error", msg, Some("INTERNAL_ERROR")),
             AppError::DatabaseError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, "database_error", msg, Some("DATABASE_ERROR")),
             AppError::CacheError(msg) => (StatusCode::SERVICE_UNAVAILABLE, "cache_error", msg, Some("CACHE_ERROR")),
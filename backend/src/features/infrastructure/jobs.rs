@@ -0,0 +1,697 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Router,
+};
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, DurationRound, Utc};
+use rand::Rng;
+use redis::AsyncCommands;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::admin::require_admin;
+use crate::features::infrastructure::cron::CronSchedule;
+use crate::features::infrastructure::error::{AppError, AppResult};
+use crate::features::auth::auth_service::AuthUser;
+
+// Redis layout:
+//   jobs:queue:{queue}          LIST  ready jobs (LPUSH in, BLMOVE out from the right)
+//   jobs:processing:{process}   LIST  jobs a live process is running right now
+//   jobs:heartbeat:{process}    KEY   present while that process is alive
+//   jobs:processes              SET   every process that has run workers
+//   jobs:scheduled              ZSET  delayed jobs and retries, scored by run time (ms)
+//   jobs:dead                   LIST  jobs that ran out of attempts or failed fatally
+const SCHEDULED_KEY: &str = "jobs:scheduled";
+const DEAD_KEY: &str = "jobs:dead";
+const PROCESSES_KEY: &str = "jobs:processes";
+const DEAD_LETTER_CAP: isize = 5000;
+const HEARTBEAT_TTL_SECS: u64 = 30;
+
+fn queue_key(queue: &str) -> String {
+    format!("jobs:queue:{}", queue)
+}
+
+fn processing_key(process_id: &str) -> String {
+    format!("jobs:processing:{}", process_id)
+}
+
+fn heartbeat_key(process_id: &str) -> String {
+    format!("jobs:heartbeat:{}", process_id)
+}
+
+/// A unit of background work. The payload is the job struct itself, serialized to JSON.
+pub trait Job: Serialize + DeserializeOwned + Send + 'static {
+    const KIND: &'static str;
+    const QUEUE: &'static str = "default";
+    const MAX_ATTEMPTS: u32 = 5;
+    const TIMEOUT_SECS: u64 = 300;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobError {
+    /// Try again later with backoff (until attempts run out).
+    Retry(String),
+    /// Don't bother retrying; goes straight to the dead-letter queue.
+    Fatal(String),
+}
+
+impl std::fmt::Display for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobError::Retry(e) => write!(f, "{}", e),
+            JobError::Fatal(e) => write!(f, "fatal: {}", e),
+        }
+    }
+}
+
+impl From<anyhow::Error> for JobError {
+    fn from(e: anyhow::Error) -> Self {
+        JobError::Retry(e.to_string())
+    }
+}
+
+impl From<mongodb::error::Error> for JobError {
+    fn from(e: mongodb::error::Error) -> Self {
+        JobError::Retry(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JobEnvelope {
+    pub id: String,
+    pub kind: String,
+    pub queue: String,
+    pub payload: serde_json::Value,
+    pub attempts: u32,
+    pub max_attempts: u32,
+    pub enqueued_at: i64, // unix ms
+    pub run_at: i64,      // unix ms
+    pub last_error: Option<String>,
+    pub failed_at: Option<i64>,
+}
+
+impl JobEnvelope {
+    pub fn new<J: Job>(job: &J, run_at: DateTime<Utc>) -> Result<Self, serde_json::Error> {
+        Ok(JobEnvelope {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: J::KIND.to_string(),
+            queue: J::QUEUE.to_string(),
+            payload: serde_json::to_value(job)?,
+            attempts: 0,
+            max_attempts: J::MAX_ATTEMPTS,
+            enqueued_at: Utc::now().timestamp_millis(),
+            run_at: run_at.timestamp_millis(),
+            last_error: None,
+            failed_at: None,
+        })
+    }
+}
+
+/// Delay before retry number `attempt` (1-based): 10s, 20s, 40s, ... capped at an hour.
+pub fn retry_backoff_secs(attempt: u32) -> u64 {
+    let exp = attempt.max(1).saturating_sub(1).min(16);
+    (10u64 << exp).min(3600)
+}
+
+/// What happens to a job after a failed run.
+#[derive(Debug, PartialEq)]
+pub enum FailureOutcome {
+    RetryAt(i64),
+    Dead,
+}
+
+pub fn failure_outcome(envelope: &JobEnvelope, error: &JobError, now_ms: i64) -> FailureOutcome {
+    match error {
+        JobError::Retry(_) if envelope.attempts < envelope.max_attempts => {
+            let base = retry_backoff_secs(envelope.attempts) as i64 * 1000;
+            // Up to 10% jitter so a burst of failures doesn't retry in lockstep
+            let jitter = rand::thread_rng().gen_range(0..=base / 10);
+            FailureOutcome::RetryAt(now_ms + base + jitter)
+        }
+        _ => FailureOutcome::Dead,
+    }
+}
+
+// --- Enqueueing ---
+
+async fn push_envelope(state: &Arc<AppState>, envelope: &JobEnvelope) -> AppResult<()> {
+    let raw = serde_json::to_string(envelope).map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    let mut conn = state.redis.clone();
+    if envelope.run_at <= Utc::now().timestamp_millis() {
+        conn.lpush::<_, _, ()>(queue_key(&envelope.queue), raw).await?;
+    } else {
+        conn.zadd::<_, _, _, ()>(SCHEDULED_KEY, raw, envelope.run_at).await?;
+    }
+    Ok(())
+}
+
+/// Queues a job to run as soon as a worker is free. Returns the job id.
+pub async fn enqueue<J: Job>(state: &Arc<AppState>, job: J) -> AppResult<String> {
+    enqueue_at(state, job, Utc::now()).await
+}
+
+pub async fn enqueue_at<J: Job>(state: &Arc<AppState>, job: J, run_at: DateTime<Utc>) -> AppResult<String> {
+    let envelope = JobEnvelope::new(&job, run_at).map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    push_envelope(state, &envelope).await?;
+    Ok(envelope.id)
+}
+
+// --- Registry ---
+
+type HandlerFn = Arc<dyn Fn(Arc<AppState>, serde_json::Value) -> BoxFuture<'static, Result<(), JobError>> + Send + Sync>;
+
+struct RegisteredJob {
+    handler: HandlerFn,
+    timeout: Duration,
+}
+
+struct CronEntry {
+    name: &'static str,
+    schedule: CronSchedule,
+    envelope: Arc<dyn Fn() -> Result<JobEnvelope, serde_json::Error> + Send + Sync>,
+}
+
+#[derive(Default)]
+pub struct JobRegistry {
+    handlers: HashMap<&'static str, RegisteredJob>,
+    concurrency: HashMap<&'static str, usize>,
+    crons: Vec<CronEntry>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<J, F, Fut>(mut self, handler: F) -> Self
+    where
+        J: Job,
+        F: Fn(Arc<AppState>, J) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), JobError>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let wrapped: HandlerFn = Arc::new(move |state, payload| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                let job: J = serde_json::from_value(payload)
+                    .map_err(|e| JobError::Fatal(format!("Malformed {} payload: {}", J::KIND, e)))?;
+                handler(state, job).await
+            })
+        });
+        self.concurrency.entry(J::QUEUE).or_insert(4);
+        self.handlers.insert(J::KIND, RegisteredJob { handler: wrapped, timeout: Duration::from_secs(J::TIMEOUT_SECS) });
+        self
+    }
+
+    /// Number of jobs from `queue` this process runs at once.
+    pub fn concurrency(mut self, queue: &'static str, workers: usize) -> Self {
+        self.concurrency.insert(queue, workers.max(1));
+        self
+    }
+
+    /// Enqueues `job` whenever `expression` fires. Panics on a bad expression since
+    /// schedules are fixed at startup.
+    pub fn cron<J: Job + Clone + Sync>(mut self, name: &'static str, expression: &str, job: J) -> Self {
+        let schedule = CronSchedule::parse(expression).unwrap_or_else(|e| panic!("Invalid cron '{}' for {}: {}", expression, name, e));
+        self.crons.push(CronEntry { name, schedule, envelope: Arc::new(move || JobEnvelope::new(&job, Utc::now())) });
+        self
+    }
+
+    pub fn kinds(&self) -> Vec<&'static str> {
+        let mut kinds: Vec<_> = self.handlers.keys().copied().collect();
+        kinds.sort();
+        kinds
+    }
+
+    /// Queues some registered job runs on.
+    pub fn queues(&self) -> Vec<&'static str> {
+        let mut queues: Vec<_> = self.concurrency.keys().copied().collect();
+        queues.sort();
+        queues
+    }
+}
+
+// --- Workers ---
+
+struct Runtime {
+    state: Arc<AppState>,
+    registry: JobRegistry,
+    process_id: String,
+}
+
+async fn record_failure(rt: &Runtime, raw: &str, mut envelope: JobEnvelope, error: JobError) {
+    envelope.attempts += 1;
+    envelope.last_error = Some(error.to_string());
+    let now = Utc::now().timestamp_millis();
+    let outcome = failure_outcome(&envelope, &error, now);
+
+    let mut pipe = redis::pipe();
+    pipe.atomic().lrem(processing_key(&rt.process_id), 1, raw).ignore();
+    match outcome {
+        FailureOutcome::RetryAt(at) => {
+            tracing::warn!("Job {} ({}) failed attempt {}/{}: {}", envelope.id, envelope.kind, envelope.attempts, envelope.max_attempts, error);
+            envelope.run_at = at;
+            let updated = serde_json::to_string(&envelope).unwrap_or_default();
+            pipe.zadd(SCHEDULED_KEY, updated, at).ignore();
+        }
+        FailureOutcome::Dead => {
+            tracing::error!("Job {} ({}) moved to dead-letter after {} attempts: {}", envelope.id, envelope.kind, envelope.attempts, error);
+            envelope.failed_at = Some(now);
+            let updated = serde_json::to_string(&envelope).unwrap_or_default();
+            pipe.lpush(DEAD_KEY, updated).ignore().ltrim(DEAD_KEY, 0, DEAD_LETTER_CAP - 1).ignore();
+        }
+    }
+    let mut conn = rt.state.redis.clone();
+    if let Err(e) = pipe.query_async::<()>(&mut conn).await {
+        tracing::error!("Failed to record failure for job {}: {}", envelope.id, e);
+    }
+}
+
+async fn run_one(rt: &Runtime, raw: String) {
+    let envelope: JobEnvelope = match serde_json::from_str(&raw) {
+        Ok(e) => e,
+        Err(e) => {
+            tracing::error!("Dropping unreadable job: {}", e);
+            let mut conn = rt.state.redis.clone();
+            let _ = redis::pipe()
+                .atomic()
+                .lrem(processing_key(&rt.process_id), 1, &raw).ignore()
+                .lpush(DEAD_KEY, &raw).ignore()
+                .query_async::<()>(&mut conn)
+                .await;
+            return;
+        }
+    };
+
+    let Some(registered) = rt.registry.handlers.get(envelope.kind.as_str()) else {
+        record_failure(rt, &raw, envelope.clone(), JobError::Fatal(format!("No handler registered for '{}'", envelope.kind))).await;
+        return;
+    };
+
+    let started = std::time::Instant::now();
+    let result = match tokio::time::timeout(registered.timeout, (registered.handler)(rt.state.clone(), envelope.payload.clone())).await {
+        Ok(result) => result,
+        Err(_) => Err(JobError::Retry(format!("Timed out after {:?}", registered.timeout))),
+    };
+
+    match result {
+        Ok(()) => {
+            tracing::debug!("Job {} ({}) done in {:?}", envelope.id, envelope.kind, started.elapsed());
+            let mut conn = rt.state.redis.clone();
+            let _ = conn.lrem::<_, _, ()>(processing_key(&rt.process_id), 1, &raw).await;
+        }
+        Err(e) => record_failure(rt, &raw, envelope, e).await,
+    }
+}
+
+async fn worker_loop(rt: Arc<Runtime>, queue: &'static str) {
+    loop {
+        // BLMOVE blocks its connection, so each worker gets its own
+        let mut conn = match redis::Client::open(rt.state.redis_url.clone()) {
+            Ok(client) => match client.get_multiplexed_async_connection().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::error!("Job worker ({}) can't connect to Redis: {}", queue, e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+            },
+            Err(e) => {
+                tracing::error!("Job worker ({}) has a bad Redis URL: {}", queue, e);
+                return;
+            }
+        };
+
+        loop {
+            let moved: Result<Option<String>, _> = redis::cmd("BLMOVE")
+                .arg(queue_key(queue))
+                .arg(processing_key(&rt.process_id))
+                .arg("RIGHT")
+                .arg("LEFT")
+                .arg(5)
+                .query_async(&mut conn)
+                .await;
+            match moved {
+                Ok(Some(raw)) => run_one(&rt, raw).await,
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::error!("Job worker ({}) lost Redis: {}", queue, e);
+                    break;
+                }
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+/// Moves due scheduled jobs onto their queues in one atomic step.
+async fn promote_due(state: &Arc<AppState>) -> redis::RedisResult<i64> {
+    let script = redis::Script::new(
+        r#"
+        local due = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, 200)
+        for _, raw in ipairs(due) do
+            redis.call('ZREM', KEYS[1], raw)
+            local ok, job = pcall(cjson.decode, raw)
+            if ok and job['queue'] then
+                redis.call('LPUSH', ARGV[2] .. job['queue'], raw)
+            else
+                redis.call('LPUSH', KEYS[2], raw)
+            end
+        end
+        return #due
+        "#,
+    );
+    let mut conn = state.redis.clone();
+    script
+        .key(SCHEDULED_KEY)
+        .key(DEAD_KEY)
+        .arg(Utc::now().timestamp_millis())
+        .arg("jobs:queue:")
+        .invoke_async(&mut conn)
+        .await
+}
+
+/// Returns jobs held by processes whose heartbeat lapsed. Each recovered job counts as a
+/// failed attempt, so one that keeps crashing its worker ends up dead-lettered.
+async fn recover_orphans(rt: &Runtime) -> redis::RedisResult<usize> {
+    let mut conn = rt.state.redis.clone();
+    let processes: Vec<String> = conn.smembers(PROCESSES_KEY).await?;
+    let mut recovered = 0;
+    for process in processes.into_iter().filter(|p| *p != rt.process_id) {
+        if conn.exists::<_, bool>(heartbeat_key(&process)).await? {
+            continue;
+        }
+        // Move items one at a time into our own processing list so a crash here loses nothing
+        loop {
+            let raw: Option<String> = conn.rpoplpush(processing_key(&process), processing_key(&rt.process_id)).await?;
+            let Some(raw) = raw else { break };
+            match serde_json::from_str::<JobEnvelope>(&raw) {
+                Ok(envelope) => {
+                    record_failure(rt, &raw, envelope, JobError::Retry(format!("Worker {} stopped while running the job", process))).await;
+                }
+                Err(_) => {
+                    let _ = conn.lrem::<_, _, ()>(processing_key(&rt.process_id), 1, &raw).await;
+                }
+            }
+            recovered += 1;
+        }
+        conn.srem::<_, _, ()>(PROCESSES_KEY, &process).await?;
+    }
+    Ok(recovered)
+}
+
+async fn fire_crons(rt: &Runtime, from: DateTime<Utc>, to: DateTime<Utc>) {
+    let mut minute = from;
+    while minute <= to {
+        for entry in &rt.registry.crons {
+            if !entry.schedule.matches(minute) {
+                continue;
+            }
+            // Every process ticks; only the first to claim the minute enqueues
+            let mut conn = rt.state.redis.clone();
+            let claim: Option<String> = redis::cmd("SET")
+                .arg(format!("jobs:cron:{}:{}", entry.name, minute.timestamp() / 60))
+                .arg(&rt.process_id)
+                .arg("NX")
+                .arg("EX")
+                .arg(3600)
+                .query_async(&mut conn)
+                .await
+                .unwrap_or(None);
+            if claim.is_none() {
+                continue;
+            }
+            match (entry.envelope)() {
+                Ok(envelope) => match push_envelope(&rt.state, &envelope).await {
+                    Ok(()) => tracing::info!("⏰ Cron {} enqueued {}", entry.name, envelope.kind),
+                    Err(e) => tracing::error!("Cron {} failed to enqueue: {:?}", entry.name, e),
+                },
+                Err(e) => tracing::error!("Cron {} has an unserializable job: {}", entry.name, e),
+            }
+        }
+        minute += chrono::Duration::minutes(1);
+    }
+}
+
+/// Starts workers for every registered queue plus the scheduler (delayed jobs, cron,
+/// heartbeat and orphan recovery).
+pub fn spawn_job_system(state: Arc<AppState>, registry: JobRegistry) {
+    let rt = Arc::new(Runtime { state, registry, process_id: uuid::Uuid::new_v4().simple().to_string() });
+
+    let mut queues: Vec<(&'static str, usize)> = rt.registry.concurrency.iter().map(|(q, n)| (*q, *n)).collect();
+    queues.sort();
+    tracing::info!("🧰 Job system started (process {}, queues {:?}, kinds {:?})", rt.process_id, queues, rt.registry.kinds());
+
+    for (queue, workers) in queues {
+        for _ in 0..workers {
+            tokio::spawn(worker_loop(rt.clone(), queue));
+        }
+    }
+
+    tokio::spawn(async move {
+        let mut last_minute = Utc::now().duration_trunc(chrono::Duration::minutes(1)).unwrap_or_else(|_| Utc::now());
+        let mut ticks: u64 = 0;
+        loop {
+            let mut conn = rt.state.redis.clone();
+            let _ = conn.set_ex::<_, _, ()>(heartbeat_key(&rt.process_id), "1", HEARTBEAT_TTL_SECS).await;
+            if ticks == 0 {
+                let _ = conn.sadd::<_, _, ()>(PROCESSES_KEY, &rt.process_id).await;
+            }
+
+            match promote_due(&rt.state).await {
+                Ok(0) => {}
+                Ok(n) => tracing::debug!("Promoted {} scheduled jobs", n),
+                Err(e) => tracing::error!("Failed to promote scheduled jobs: {}", e),
+            }
+
+            let now_minute = Utc::now().duration_trunc(chrono::Duration::minutes(1)).unwrap_or_else(|_| Utc::now());
+            if now_minute > last_minute {
+                // Catch up at most an hour if the process was stalled
+                let from = (last_minute + chrono::Duration::minutes(1)).max(now_minute - chrono::Duration::minutes(60));
+                fire_crons(&rt, from, now_minute).await;
+                last_minute = now_minute;
+            }
+
+            if ticks.is_multiple_of(6) {
+                match recover_orphans(&rt).await {
+                    Ok(0) => {}
+                    Ok(n) => tracing::warn!("Recovered {} jobs from stopped workers", n),
+                    Err(e) => tracing::error!("Orphan recovery failed: {}", e),
+                }
+            }
+
+            ticks += 1;
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    });
+}
+
+/// Every job kind the app knows about, with its cron schedules.
+pub fn job_registry() -> JobRegistry {
+    use crate::features::content::scheduled_posts::{PostFanoutJob, PublishScheduledPostsJob};
+    use crate::features::content::timeline::TimelineFanoutJob;
+    use crate::features::infrastructure::mail_schedules::{EventRemindersJob, WeeklyDigestJob};
+    use crate::features::infrastructure::media_processor::MediaJob;
+    use crate::features::infrastructure::unfurl::UnfurlJob;
+    use crate::features::monetization::ledger::{RebuildAccountJob, SweepJournalsJob};
+    use crate::features::monetization::payments::ReconcilePaymentsJob;
+    use crate::features::monetization::subscriptions::{ExpireSubscriptionsJob, SubscriptionRemindersJob};
+    use crate::features::social::gamification::AwardPointsJob;
+    use crate::features::social::live::EndDroppedStreamJob;
+    use crate::features::social::message_expiry::ExpireMessagesJob;
+    use crate::features::social::scheduled_messages::SendScheduledMessagesJob;
+
+    JobRegistry::new()
+        // At most 8 FFmpeg processes per instance
+        .register(|state, job: MediaJob| crate::features::infrastructure::media_processor::run_media_job(state, job))
+        .concurrency("media", 8)
+        .register(|state, _: EventRemindersJob| crate::features::infrastructure::mail_schedules::run_event_reminders(state))
+        .register(|state, _: WeeklyDigestJob| crate::features::infrastructure::mail_schedules::run_weekly_digest(state))
+        .register(|state, _: PublishScheduledPostsJob| crate::features::content::scheduled_posts::run_publish_scheduled(state))
+        .register(|state, job: PostFanoutJob| crate::features::content::scheduled_posts::run_post_fanout(state, job))
+        .register(|state, job: TimelineFanoutJob| crate::features::content::timeline::run_timeline_fanout(state, job))
+        .register(|state, job: AwardPointsJob| crate::features::social::gamification::run_award(state, job))
+        .register(|state, _: ReconcilePaymentsJob| crate::features::monetization::payments::run_reconcile_payments(state))
+        .register(|state, job: RebuildAccountJob| crate::features::monetization::ledger::run_rebuild_account(state, job))
+        .register(|state, _: SweepJournalsJob| crate::features::monetization::ledger::run_sweep_journals(state))
+        .register(|state, _: ExpireSubscriptionsJob| crate::features::monetization::subscriptions::run_expire_subscriptions(state))
+        .register(|state, _: SubscriptionRemindersJob| crate::features::monetization::subscriptions::run_subscription_reminders(state))
+        .register(|state, job: UnfurlJob| crate::features::infrastructure::unfurl::run_unfurl(state, job))
+        .register(|state, _: ExpireMessagesJob| crate::features::social::message_expiry::run_expire_messages(state))
+        .register(|state, _: SendScheduledMessagesJob| crate::features::social::scheduled_messages::run_send_scheduled(state))
+        .register(|state, job: EndDroppedStreamJob| crate::features::social::live::run_end_dropped(state, job))
+        .cron("publish-scheduled-posts", "* * * * *", PublishScheduledPostsJob)
+        .cron("expire-messages", "* * * * *", ExpireMessagesJob)
+        .cron("send-scheduled-messages", "* * * * *", SendScheduledMessagesJob)
+        .cron("sweep-ledger-journals", "* * * * *", SweepJournalsJob)
+        .cron("event-reminders", "*/15 * * * *", EventRemindersJob)
+        .cron("weekly-digest", "0 6 * * 1", WeeklyDigestJob)
+        // 02:30 in Nairobi
+        .cron("reconcile-payments", "30 23 * * *", ReconcilePaymentsJob)
+        .cron("expire-subscriptions", "5 * * * *", ExpireSubscriptionsJob)
+        // 09:00 in Nairobi
+        .cron("subscription-reminders", "0 6 * * *", SubscriptionRemindersJob)
+}
+
+// --- Admin ---
+
+type AdminResult<T> = Result<T, (StatusCode, Json<serde_json::Value>)>;
+
+fn internal(e: impl std::fmt::Display) -> (StatusCode, Json<serde_json::Value>) {
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()})))
+}
+
+fn cron_summary(registry: &JobRegistry, now: DateTime<Utc>) -> Vec<serde_json::Value> {
+    registry
+        .crons
+        .iter()
+        .map(|c| json!({ "name": c.name, "schedule": c.schedule.expression(), "next_run": c.schedule.next_after(now) }))
+        .collect()
+}
+
+#[derive(Deserialize)]
+pub struct JobListQuery {
+    pub limit: Option<isize>,
+}
+
+fn parse_envelopes(raws: Vec<String>) -> Vec<JobEnvelope> {
+    raws.iter().filter_map(|r| serde_json::from_str(r).ok()).collect()
+}
+
+pub async fn job_stats_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> AdminResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    let mut conn = state.redis.clone();
+
+    let registry = job_registry();
+    let mut queues = serde_json::Map::new();
+    for queue in registry.queues() {
+        let len: i64 = conn.llen(queue_key(queue)).await.map_err(internal)?;
+        queues.insert(queue.to_string(), json!(len));
+    }
+
+    let processes: Vec<String> = conn.smembers(PROCESSES_KEY).await.map_err(internal)?;
+    let mut running = 0i64;
+    let mut live = 0;
+    for process in &processes {
+        running += conn.llen::<_, i64>(processing_key(process)).await.map_err(internal)?;
+        if conn.exists::<_, bool>(heartbeat_key(process)).await.map_err(internal)? {
+            live += 1;
+        }
+    }
+
+    Ok(Json(json!({
+        "queues": queues,
+        "running": running,
+        "scheduled": conn.zcard::<_, i64>(SCHEDULED_KEY).await.map_err(internal)?,
+        "dead": conn.llen::<_, i64>(DEAD_KEY).await.map_err(internal)?,
+        "workers": { "live": live, "known": processes.len() },
+        "crons": cron_summary(&registry, Utc::now()),
+    })))
+}
+
+pub async fn list_dead_jobs_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Query(query): Query<JobListQuery>,
+) -> AdminResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let mut conn = state.redis.clone();
+    let raws: Vec<String> = conn.lrange(DEAD_KEY, 0, limit - 1).await.map_err(internal)?;
+    Ok(Json(json!({ "jobs": parse_envelopes(raws) })))
+}
+
+pub async fn list_scheduled_jobs_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Query(query): Query<JobListQuery>,
+) -> AdminResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let mut conn = state.redis.clone();
+    let raws: Vec<String> = conn.zrange(SCHEDULED_KEY, 0, limit - 1).await.map_err(internal)?;
+    Ok(Json(json!({ "jobs": parse_envelopes(raws) })))
+}
+
+/// Pulls dead jobs out of the dead-letter list (all of them, or just `only_id`) and
+/// queues them again with a fresh attempt budget.
+async fn redrive(state: &Arc<AppState>, only_id: Option<&str>) -> AdminResult<usize> {
+    let mut conn = state.redis.clone();
+    let raws: Vec<String> = conn.lrange(DEAD_KEY, 0, -1).await.map_err(internal)?;
+    let mut count = 0;
+    for raw in raws {
+        let Ok(mut envelope) = serde_json::from_str::<JobEnvelope>(&raw) else { continue };
+        if only_id.is_some_and(|id| id != envelope.id) {
+            continue;
+        }
+        let removed: i64 = conn.lrem(DEAD_KEY, 1, &raw).await.map_err(internal)?;
+        if removed == 0 {
+            continue; // Someone else re-drove it
+        }
+        envelope.attempts = 0;
+        envelope.failed_at = None;
+        envelope.run_at = Utc::now().timestamp_millis();
+        let updated = serde_json::to_string(&envelope).map_err(internal)?;
+        conn.lpush::<_, _, ()>(queue_key(&envelope.queue), updated).await.map_err(internal)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub async fn retry_dead_job_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> AdminResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    match redrive(&state, Some(&id)).await? {
+        0 => Err((StatusCode::NOT_FOUND, Json(json!({"error": "Dead job not found"})))),
+        _ => Ok(Json(json!({"message": "Job re-queued"}))),
+    }
+}
+
+pub async fn retry_all_dead_jobs_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> AdminResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    let count = redrive(&state, None).await?;
+    Ok(Json(json!({"message": "Dead jobs re-queued", "count": count})))
+}
+
+pub async fn delete_dead_job_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> AdminResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    let mut conn = state.redis.clone();
+    let raws: Vec<String> = conn.lrange(DEAD_KEY, 0, -1).await.map_err(internal)?;
+    for raw in raws {
+        if serde_json::from_str::<JobEnvelope>(&raw).is_ok_and(|e| e.id == id) {
+            conn.lrem::<_, _, ()>(DEAD_KEY, 1, &raw).await.map_err(internal)?;
+            return Ok(Json(json!({"message": "Dead job deleted"})));
+        }
+    }
+    Err((StatusCode::NOT_FOUND, Json(json!({"error": "Dead job not found"}))))
+}
+
+pub fn job_admin_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(job_stats_handler))
+        .route("/scheduled", get(list_scheduled_jobs_handler))
+        .route("/dead", get(list_dead_jobs_handler))
+        .route("/dead/retry-all", post(retry_all_dead_jobs_handler))
+        .route("/dead/:id/retry", post(retry_dead_job_handler))
+        .route("/dead/:id", axum::routing::delete(delete_dead_job_handler))
+}
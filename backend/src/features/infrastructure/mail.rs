@@ -0,0 +1,593 @@
+use axum::async_trait;
+use serde_json::json;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use mongodb::bson::doc;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::Utc;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::error::AppResult;
+use crate::features::infrastructure::mail_templates::EmailTemplate;
+use crate::models::OutboxEmail;
+
+/// Give up on an email after this many delivery attempts.
+pub const MAX_ATTEMPTS: i32 = 8;
+/// How long a worker owns a claimed email before another may pick it up.
+const CLAIM_SECS: i64 = 120;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailMessage {
+    pub to: String,
+    pub to_name: Option<String>,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MailError {
+    /// Worth retrying: network trouble, rate limits, 4xx SMTP replies.
+    Transient(String),
+    /// Retrying won't help: rejected recipient, bad credentials, malformed request.
+    Permanent(String),
+}
+
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailError::Transient(e) => write!(f, "transient: {}", e),
+            MailError::Permanent(e) => write!(f, "permanent: {}", e),
+        }
+    }
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn send(&self, message: &EmailMessage) -> Result<(), MailError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Sender {
+    pub email: String,
+    pub name: String,
+}
+
+impl Sender {
+    pub fn from_env() -> Self {
+        Sender {
+            email: std::env::var("MAIL_FROM_EMAIL").unwrap_or_else(|_| "no-reply@karuteens.site".to_string()),
+            name: std::env::var("MAIL_FROM_NAME").unwrap_or_else(|_| "Karu teens".to_string()),
+        }
+    }
+}
+
+// --- Brevo ---
+
+pub struct BrevoMailer {
+    client: reqwest::Client,
+    api_key: String,
+    sender: Sender,
+    endpoint: String,
+}
+
+impl BrevoMailer {
+    pub fn new(client: reqwest::Client, api_key: String, sender: Sender) -> Self {
+        BrevoMailer { client, api_key, sender, endpoint: "https://api.brevo.com/v3/smtp/email".to_string() }
+    }
+}
+
+#[async_trait]
+impl Mailer for BrevoMailer {
+    fn name(&self) -> &'static str {
+        "brevo"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<(), MailError> {
+        let mut to = json!({ "email": message.to });
+        if let Some(name) = &message.to_name {
+            to["name"] = json!(name);
+        }
+        let payload = json!({
+            "sender": { "name": self.sender.name, "email": self.sender.email },
+            "to": [to],
+            "subject": message.subject,
+            "htmlContent": message.html,
+            "textContent": message.text,
+        });
+
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .header("api-key", &self.api_key)
+            .timeout(Duration::from_secs(15))
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| MailError::Transient(format!("Failed to reach Brevo: {}", e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = response.text().await.unwrap_or_default();
+        let error = format!("Brevo returned {}: {}", status, body);
+        if status.as_u16() == 429 || status.is_server_error() {
+            Err(MailError::Transient(error))
+        } else {
+            Err(MailError::Permanent(error))
+        }
+    }
+}
+
+// --- SMTP ---
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpSecurity {
+    /// TLS from the first byte (usually port 465)
+    Tls,
+    /// Plain connection upgraded with STARTTLS (usually port 587)
+    StartTls,
+    /// No encryption; only for local relays and tests
+    None,
+}
+
+pub struct SmtpMailer {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub sender: Sender,
+}
+
+trait SmtpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> SmtpStream for T {}
+
+struct SmtpConnection {
+    stream: BufReader<Box<dyn SmtpStream>>,
+}
+
+fn io_error(e: std::io::Error) -> MailError {
+    MailError::Transient(format!("SMTP connection error: {}", e))
+}
+
+impl SmtpConnection {
+    async fn read_reply(&mut self) -> Result<(u16, String), MailError> {
+        let mut text = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await.map_err(io_error)? == 0 {
+                return Err(MailError::Transient("SMTP server closed the connection".to_string()));
+            }
+            let code = line.get(..3).and_then(|c| c.parse::<u16>().ok())
+                .ok_or_else(|| MailError::Transient(format!("Malformed SMTP reply: {}", line.trim_end())))?;
+            text.push_str(line.get(4..).unwrap_or("").trim_end());
+            text.push('\n');
+            // "250-" continues a multiline reply, "250 " ends it
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, text));
+            }
+        }
+    }
+
+    async fn expect(&mut self, expected: u16) -> Result<String, MailError> {
+        let (code, text) = self.read_reply().await?;
+        if code == expected {
+            return Ok(text);
+        }
+        let error = format!("SMTP server replied {} (expected {}): {}", code, expected, text.trim_end());
+        if (400..500).contains(&code) {
+            Err(MailError::Transient(error))
+        } else {
+            Err(MailError::Permanent(error))
+        }
+    }
+
+    async fn command(&mut self, line: &str, expected: u16) -> Result<String, MailError> {
+        self.stream.get_mut().write_all(format!("{}\r\n", line).as_bytes()).await.map_err(io_error)?;
+        self.stream.get_mut().flush().await.map_err(io_error)?;
+        self.expect(expected).await
+    }
+}
+
+async fn wrap_tls(host: &str, stream: Box<dyn SmtpStream>) -> Result<Box<dyn SmtpStream>, MailError> {
+    let connector = native_tls::TlsConnector::new()
+        .map_err(|e| MailError::Permanent(format!("TLS setup failed: {}", e)))?;
+    let tls = tokio_native_tls::TlsConnector::from(connector)
+        .connect(host, stream)
+        .await
+        .map_err(|e| MailError::Transient(format!("TLS handshake failed: {}", e)))?;
+    Ok(Box::new(tls))
+}
+
+impl SmtpMailer {
+    pub fn from_env(sender: Sender) -> Option<Self> {
+        let host = std::env::var("SMTP_HOST").ok()?;
+        let port = std::env::var("SMTP_PORT").ok().and_then(|p| p.parse().ok()).unwrap_or(587);
+        let security = match std::env::var("SMTP_SECURITY").unwrap_or_default().as_str() {
+            "tls" => SmtpSecurity::Tls,
+            "starttls" => SmtpSecurity::StartTls,
+            "none" => SmtpSecurity::None,
+            _ if port == 465 => SmtpSecurity::Tls,
+            _ => SmtpSecurity::StartTls,
+        };
+        Some(SmtpMailer {
+            host,
+            port,
+            security,
+            username: std::env::var("SMTP_USERNAME").ok(),
+            password: std::env::var("SMTP_PASSWORD").ok(),
+            sender,
+        })
+    }
+
+    async fn deliver(&self, message: &EmailMessage) -> Result<(), MailError> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port)).await.map_err(io_error)?;
+        let mut stream: Box<dyn SmtpStream> = Box::new(tcp);
+        if self.security == SmtpSecurity::Tls {
+            stream = wrap_tls(&self.host, stream).await?;
+        }
+        let mut conn = SmtpConnection { stream: BufReader::new(stream) };
+        conn.expect(220).await?;
+
+        let hello = format!("EHLO {}", self.sender.email.rsplit('@').next().unwrap_or("localhost"));
+        let mut capabilities = conn.command(&hello, 250).await?;
+
+        if self.security == SmtpSecurity::StartTls {
+            conn.command("STARTTLS", 220).await?;
+            let plain = conn.stream.into_inner();
+            conn = SmtpConnection { stream: BufReader::new(wrap_tls(&self.host, plain).await?) };
+            capabilities = conn.command(&hello, 250).await?;
+        }
+
+        if let (Some(user), Some(pass)) = (&self.username, &self.password) {
+            if !capabilities.to_uppercase().contains("AUTH") {
+                return Err(MailError::Permanent("SMTP server does not offer AUTH".to_string()));
+            }
+            let token = BASE64.encode(format!("\0{}\0{}", user, pass));
+            conn.command(&format!("AUTH PLAIN {}", token), 235).await?;
+        }
+
+        check_recipient(&message.to)?;
+        conn.command(&format!("MAIL FROM:<{}>", self.sender.email), 250).await?;
+        conn.command(&format!("RCPT TO:<{}>", message.to), 250).await?;
+        conn.command("DATA", 354).await?;
+
+        let body = build_mime_message(&self.sender, message, Utc::now());
+        conn.stream.get_mut().write_all(dot_stuff(&body).as_bytes()).await.map_err(io_error)?;
+        conn.command(".", 250).await?;
+        let _ = conn.command("QUIT", 221).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<(), MailError> {
+        match tokio::time::timeout(Duration::from_secs(30), self.deliver(message)).await {
+            Ok(result) => result,
+            Err(_) => Err(MailError::Transient("SMTP delivery timed out".to_string())),
+        }
+    }
+}
+
+/// Header values never carry control characters: a CR/LF in a user-supplied title would
+/// otherwise start new headers (or the body) of its own.
+fn encode_header(value: &str) -> String {
+    let value: String = value.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?UTF-8?B?{}?=", BASE64.encode(value))
+    }
+}
+
+/// A bare `local@domain` safe to put in `RCPT TO:<…>` and the To header.
+pub fn valid_recipient(address: &str) -> bool {
+    let Some((local, domain)) = address.split_once('@') else { return false };
+    !local.is_empty()
+        && !domain.is_empty()
+        && !domain.contains('@')
+        && address.chars().all(|c| c.is_ascii_graphic() && !matches!(c, '<' | '>' | ',' | ';'))
+}
+
+fn check_recipient(address: &str) -> Result<(), MailError> {
+    if valid_recipient(address) {
+        Ok(())
+    } else {
+        Err(MailError::Permanent(format!("Invalid recipient address {:?}", address)))
+    }
+}
+
+fn base64_lines(body: &str) -> String {
+    let encoded = BASE64.encode(body);
+    encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|chunk| format!("{}\r\n", String::from_utf8_lossy(chunk)))
+        .collect()
+}
+
+/// Renders a multipart/alternative message with CRLF line endings, ready for DATA.
+pub fn build_mime_message(sender: &Sender, message: &EmailMessage, date: chrono::DateTime<Utc>) -> String {
+    let boundary = format!("=_karuteens_{}", uuid::Uuid::new_v4().simple());
+    let domain = sender.email.rsplit('@').next().unwrap_or("karuteens.site");
+    let to = match &message.to_name {
+        Some(name) => format!("{} <{}>", encode_header(name), message.to),
+        None => format!("<{}>", message.to),
+    };
+
+    format!(
+        "From: {} <{}>\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\nMIME-Version: 1.0\r\n\
+Content-Type: multipart/alternative; boundary=\"{}\"\r\n\r\n\
+--{}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\
+--{}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\
+--{}--\r\n",
+        encode_header(&sender.name),
+        sender.email,
+        to,
+        encode_header(&message.subject),
+        date.to_rfc2822(),
+        uuid::Uuid::new_v4().simple(),
+        domain,
+        boundary,
+        boundary,
+        base64_lines(&message.text),
+        boundary,
+        base64_lines(&message.html),
+        boundary,
+    )
+}
+
+/// A lone "." ends DATA, so any line starting with one gets doubled.
+pub fn dot_stuff(body: &str) -> String {
+    body.split("\r\n")
+        .map(|line| if line.starts_with('.') { format!(".{}", line) } else { line.to_string() })
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+// --- Development and test backends ---
+
+/// Keeps sent mail in memory; handy in tests.
+#[derive(Default, Clone)]
+pub struct MemoryMailer {
+    sent: Arc<Mutex<Vec<EmailMessage>>>,
+}
+
+impl MemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[allow(dead_code)]
+    pub fn sent(&self) -> Vec<EmailMessage> {
+        self.sent.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<(), MailError> {
+        self.sent
+            .lock()
+            .map_err(|_| MailError::Transient("Memory mailer lock poisoned".to_string()))?
+            .push(message.clone());
+        Ok(())
+    }
+}
+
+/// Writes each email as an .eml file so it can be opened in a mail client.
+pub struct FileMailer {
+    pub dir: PathBuf,
+    pub sender: Sender,
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<(), MailError> {
+        check_recipient(&message.to)?;
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| MailError::Permanent(format!("Cannot create mail dir: {}", e)))?;
+        let path = self.dir.join(format!("{}-{}.eml", Utc::now().format("%Y%m%dT%H%M%S"), uuid::Uuid::new_v4().simple()));
+        tokio::fs::write(&path, build_mime_message(&self.sender, message, Utc::now()))
+            .await
+            .map_err(|e| MailError::Transient(format!("Cannot write {}: {}", path.display(), e)))
+    }
+}
+
+/// Fallback when no provider is configured: logs the plain-text body.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<(), MailError> {
+        tracing::warn!("⚠️ No mail provider configured. Email to {} ({}):\n{}", message.to, message.subject, message.text);
+        Ok(())
+    }
+}
+
+/// Picks the backend from `MAIL_PROVIDER` (brevo, smtp, file, memory, log).
+/// Without it, Brevo is used when `BREVO_API_KEY` is set and emails are logged otherwise.
+pub fn mailer_from_env(http_client: reqwest::Client) -> Arc<dyn Mailer> {
+    let sender = Sender::from_env();
+    let brevo_key = std::env::var("BREVO_API_KEY").unwrap_or_default();
+    let provider = std::env::var("MAIL_PROVIDER").unwrap_or_else(|_| {
+        if brevo_key.is_empty() { "log".to_string() } else { "brevo".to_string() }
+    });
+
+    match provider.as_str() {
+        "brevo" if !brevo_key.is_empty() => Arc::new(BrevoMailer::new(http_client, brevo_key, sender)),
+        "smtp" => match SmtpMailer::from_env(sender) {
+            Some(mailer) => Arc::new(mailer),
+            None => {
+                tracing::error!("MAIL_PROVIDER=smtp but SMTP_HOST is not set; emails will only be logged");
+                Arc::new(LogMailer)
+            }
+        },
+        "file" => Arc::new(FileMailer {
+            dir: PathBuf::from(std::env::var("MAIL_FILE_DIR").unwrap_or_else(|_| "mail-outbox".to_string())),
+            sender,
+        }),
+        "memory" => Arc::new(MemoryMailer::new()),
+        "log" => Arc::new(LogMailer),
+        other => {
+            tracing::error!("Mail provider '{}' is unknown or missing credentials; emails will only be logged", other);
+            Arc::new(LogMailer)
+        }
+    }
+}
+
+// --- Outbox ---
+
+/// Backoff before retry number `attempt` (1-based): 30s, 1m, 2m, ... capped at 6h.
+pub fn retry_delay_secs(attempt: i32) -> i64 {
+    let exp = (attempt.max(1) - 1).min(20) as u32;
+    (30_i64 * 2_i64.pow(exp)).min(6 * 3600)
+}
+
+/// Renders a template and queues it. Never talks to the mail provider, so it's safe
+/// to call from request handlers.
+pub async fn enqueue_email(
+    state: &Arc<AppState>,
+    to: &str,
+    to_name: Option<String>,
+    template: EmailTemplate,
+) -> AppResult<()> {
+    let rendered = template.render();
+    let now = mongodb::bson::DateTime::now();
+    let outbox = state.mongo.collection::<OutboxEmail>("email_outbox");
+    outbox
+        .insert_one(
+            OutboxEmail {
+                id: None,
+                to: to.to_string(),
+                to_name,
+                subject: rendered.subject,
+                html: rendered.html,
+                text: rendered.text,
+                template: template.name().to_string(),
+                status: "pending".to_string(),
+                attempts: 0,
+                next_attempt_at: now,
+                locked_until: None,
+                last_error: None,
+                created_at: now,
+                sent_at: None,
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Claims the next due email. Emails stuck in "sending" past their claim (a worker
+/// died mid-send) become claimable again.
+async fn claim_next(state: &Arc<AppState>) -> Result<Option<OutboxEmail>, mongodb::error::Error> {
+    let now = mongodb::bson::DateTime::now();
+    let outbox = state.mongo.collection::<OutboxEmail>("email_outbox");
+    outbox
+        .find_one_and_update(
+            doc! {
+                "$or": [
+                    { "status": "pending", "next_attempt_at": { "$lte": now } },
+                    { "status": "sending", "locked_until": { "$lte": now } },
+                ]
+            },
+            doc! {
+                "$set": {
+                    "status": "sending",
+                    "locked_until": mongodb::bson::DateTime::from_chrono(Utc::now() + chrono::Duration::seconds(CLAIM_SECS)),
+                },
+                "$inc": { "attempts": 1 },
+            },
+            FindOneAndUpdateOptions::builder()
+                .sort(doc! { "next_attempt_at": 1 })
+                .return_document(ReturnDocument::After)
+                .build(),
+        )
+        .await
+}
+
+async fn process(state: &Arc<AppState>, email: OutboxEmail) {
+    let message = EmailMessage {
+        to: email.to.clone(),
+        to_name: email.to_name.clone(),
+        subject: email.subject.clone(),
+        html: email.html.clone(),
+        text: email.text.clone(),
+    };
+    let result = state.mailer.send(&message).await;
+
+    let outbox = state.mongo.collection::<OutboxEmail>("email_outbox");
+    let update = match result {
+        Ok(()) => {
+            tracing::info!("✅ Sent {} email to {} via {}", email.template, email.to, state.mailer.name());
+            doc! { "$set": { "status": "sent", "sent_at": mongodb::bson::DateTime::now(), "locked_until": null, "last_error": null } }
+        }
+        Err(MailError::Transient(e)) if email.attempts < MAX_ATTEMPTS => {
+            let delay = retry_delay_secs(email.attempts);
+            tracing::warn!("Email {} to {} failed (attempt {}), retrying in {}s: {}", email.template, email.to, email.attempts, delay, e);
+            doc! {
+                "$set": {
+                    "status": "pending",
+                    "next_attempt_at": mongodb::bson::DateTime::from_chrono(Utc::now() + chrono::Duration::seconds(delay)),
+                    "locked_until": null,
+                    "last_error": e,
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!("❌ Giving up on {} email to {} after {} attempts: {}", email.template, email.to, email.attempts, e);
+            doc! { "$set": { "status": "failed", "locked_until": null, "last_error": e.to_string() } }
+        }
+    };
+    if let Err(e) = outbox.update_one(doc! { "_id": email.id }, update, None).await {
+        tracing::error!("Failed to record outcome for outbox email {:?}: {}", email.id, e);
+    }
+}
+
+/// Drains the outbox with `MAIL_WORKER_CONCURRENCY` loops (default 2).
+pub fn spawn_mail_worker(state: Arc<AppState>) {
+    let concurrency = std::env::var("MAIL_WORKER_CONCURRENCY").ok().and_then(|v| v.parse().ok()).unwrap_or(2usize);
+    tracing::info!("📧 Mail worker started ({} loops, provider: {})", concurrency, state.mailer.name());
+
+    for _ in 0..concurrency.max(1) {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                match claim_next(&state).await {
+                    Ok(Some(email)) => process(&state, email).await,
+                    Ok(None) => tokio::time::sleep(Duration::from_secs(2)).await,
+                    Err(e) => {
+                        tracing::error!("Mail outbox poll failed: {}", e);
+                        tokio::time::sleep(Duration::from_secs(10)).await;
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use futures::stream::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::options::FindOptions;
+use redis::AsyncCommands;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::jobs::{Job, JobError};
+use crate::features::infrastructure::mail::enqueue_email;
+use crate::features::infrastructure::mail_templates::{DigestItem, EmailTemplate};
+use crate::models::{Event, EventRSVP, Post, Profile, User};
+
+fn frontend_url() -> String {
+    std::env::var("FRONTEND_URL")
+        .ok()
+        .and_then(|v| v.split(',').next().map(|s| s.trim().trim_end_matches('/').to_string()))
+        .filter(|v| !v.is_empty() && v != "*")
+        .unwrap_or_else(|| "https://karuteens.site".to_string())
+}
+
+async fn emails_for(state: &Arc<AppState>, user_ids: &[ObjectId]) -> HashMap<ObjectId, String> {
+    let users = state.mongo.collection::<User>("users");
+    let mut emails = HashMap::new();
+    if let Ok(mut cursor) = users.find(doc! { "_id": { "$in": user_ids }, "is_banned": false }, None).await {
+        while let Some(Ok(user)) = cursor.next().await {
+            if let Some(id) = user.id {
+                emails.insert(id, user.email);
+            }
+        }
+    }
+    emails
+}
+
+/// Emails everyone going to (or interested in) an event that starts within a day. An
+/// instance leases the event with `reminder_claimed_until`, records each attendee it has
+/// queued in `reminded_user_ids`, and only sets `reminder_sent_at` once everyone's email is
+/// in the outbox. A failed run lets the lease go so the next one picks up where it stopped.
+pub async fn queue_event_reminders(state: &Arc<AppState>) -> Result<usize, JobError> {
+    let events = state.mongo.collection::<Event>("events");
+    let now = Utc::now();
+    let mut queued = 0;
+
+    loop {
+        let now_bson = mongodb::bson::DateTime::from_chrono(now);
+        let event = events
+            .find_one_and_update(
+                doc! {
+                    "status": "published",
+                    "start_datetime": {
+                        "$gt": now_bson,
+                        "$lte": mongodb::bson::DateTime::from_chrono(now + chrono::Duration::hours(24)),
+                    },
+                    "reminder_sent_at": { "$exists": false },
+                    "$or": [
+                        { "reminder_claimed_until": { "$exists": false } },
+                        { "reminder_claimed_until": { "$lt": now_bson } },
+                    ],
+                },
+                doc! { "$set": { "reminder_claimed_until": mongodb::bson::DateTime::from_chrono(now + chrono::Duration::minutes(10)) } },
+                None,
+            )
+            .await?;
+        let Some(event) = event else { break };
+        let Some(event_id) = event.id else { continue };
+
+        match remind_attendees(state, &event, event_id).await {
+            Ok(n) => {
+                queued += n;
+                events.update_one(
+                    doc! { "_id": event_id },
+                    doc! { "$set": { "reminder_sent_at": mongodb::bson::DateTime::now() }, "$unset": { "reminder_claimed_until": "" } },
+                    None,
+                ).await?;
+            }
+            Err(e) => {
+                let _ = events.update_one(doc! { "_id": event_id }, doc! { "$unset": { "reminder_claimed_until": "" } }, None).await;
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(queued)
+}
+
+/// Queues the reminder for each attendee not already in `reminded_user_ids`.
+async fn remind_attendees(state: &Arc<AppState>, event: &Event, event_id: ObjectId) -> Result<usize, JobError> {
+    let rsvps = state.mongo.collection::<EventRSVP>("event_rsvps");
+    let profiles = state.mongo.collection::<Profile>("profiles");
+    let events = state.mongo.collection::<mongodb::bson::Document>("events");
+
+    let reminded: Vec<ObjectId> = events
+        .find_one(doc! { "_id": event_id }, None)
+        .await?
+        .and_then(|e| e.get_array("reminded_user_ids").ok().map(|ids| ids.iter().filter_map(|id| id.as_object_id()).collect()))
+        .unwrap_or_default();
+    let mut attendee_ids = Vec::new();
+    let mut cursor = rsvps.find(doc! { "event_id": event_id, "status": { "$in": ["going", "interested"] } }, None).await?;
+    while let Some(rsvp) = cursor.next().await {
+        let rsvp = rsvp?;
+        if !reminded.contains(&rsvp.user_id) {
+            attendee_ids.push(rsvp.user_id);
+        }
+    }
+    if attendee_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let mut queued = 0;
+    let emails = emails_for(state, &attendee_ids).await;
+    let mut cursor = profiles.find(doc! { "user_id": { "$in": &attendee_ids } }, None).await?;
+    while let Some(profile) = cursor.next().await {
+        let profile = profile?;
+        let Some(email) = emails.get(&profile.user_id) else { continue };
+        let template = EmailTemplate::EventReminder {
+            username: profile.username.clone(),
+            event_title: event.title.clone(),
+            // Campus time (EAT, UTC+3)
+            starts_at: (event.start_datetime.to_chrono() + chrono::Duration::hours(3)).format("%a %d %b, %H:%M EAT").to_string(),
+            location: event.venue_name.clone().unwrap_or_else(|| event.location.clone()),
+            link: format!("{}/events/{}", frontend_url(), event_id.to_hex()),
+        };
+        enqueue_email(state, email, Some(profile.username.clone()), template)
+            .await
+            .map_err(|e| JobError::Retry(format!("Couldn't queue reminder for event {}: {:?}", event_id, e)))?;
+        events.update_one(doc! { "_id": event_id }, doc! { "$addToSet": { "reminded_user_ids": profile.user_id } }, None).await?;
+        queued += 1;
+    }
+    Ok(queued)
+}
+
+/// ISO week a digest belongs to, e.g. "2026-W42".
+pub fn digest_week(now: chrono::DateTime<Utc>) -> String {
+    let week = now.iso_week();
+    format!("{}-W{:02}", week.year(), week.week())
+}
+
+fn digest_claim_key(week: &str, user_id: ObjectId) -> String {
+    format!("mail:digest:{}:{}", week, user_id.to_hex())
+}
+
+/// Claims one user's digest for the week. The claim outlives the week, so a retried or
+/// repeated run skips everyone who already got theirs.
+async fn claim_digest(state: &Arc<AppState>, week: &str, user_id: ObjectId) -> Result<bool, redis::RedisError> {
+    let mut conn = state.redis.clone();
+    let claimed: Option<String> = redis::cmd("SET")
+        .arg(digest_claim_key(week, user_id))
+        .arg("1")
+        .arg("NX")
+        .arg("EX")
+        .arg(8 * 24 * 3600)
+        .query_async(&mut conn)
+        .await?;
+    Ok(claimed.is_some())
+}
+
+/// Sends the weekly digest to users who opted in via `notification_settings.email_digest`,
+/// at most once per user per ISO week.
+pub async fn queue_weekly_digests(state: &Arc<AppState>) -> Result<usize, JobError> {
+    let week = digest_week(Utc::now());
+    let week_ago = mongodb::bson::DateTime::from_chrono(Utc::now() - chrono::Duration::days(7));
+    let base_url = frontend_url();
+
+    let posts = state.mongo.collection::<Post>("posts");
+    let mut items = Vec::new();
+    let mut cursor = posts
+        .find(
+            doc! { "status": "published", "created_at": { "$gte": week_ago } },
+            FindOptions::builder().sort(doc! { "like_count": -1 }).limit(5).build(),
+        )
+        .await?;
+    while let Some(Ok(post)) = cursor.next().await {
+        let Some(id) = post.id else { continue };
+        items.push(DigestItem {
+            title: post.title.clone(),
+            summary: post.content.chars().take(140).collect(),
+            url: format!("{}/post/{}", base_url, id.to_hex()),
+        });
+    }
+    if items.is_empty() {
+        return Ok(0);
+    }
+
+    let profiles = state.mongo.collection::<Profile>("profiles");
+    let notifications = state.mongo.collection::<mongodb::bson::Document>("notifications");
+    let mut queued = 0;
+    let mut subscribers = Vec::new();
+    let mut cursor = profiles.find(doc! { "notification_settings.email_digest": true }, None).await?;
+    while let Some(Ok(profile)) = cursor.next().await {
+        subscribers.push(profile);
+    }
+
+    for batch in subscribers.chunks(200) {
+        let ids: Vec<ObjectId> = batch.iter().map(|p| p.user_id).collect();
+        let emails = emails_for(state, &ids).await;
+        for profile in batch {
+            let Some(email) = emails.get(&profile.user_id) else { continue };
+            if !claim_digest(state, &week, profile.user_id).await.map_err(|e| JobError::Retry(e.to_string()))? {
+                continue;
+            }
+            let unread = notifications
+                .count_documents(doc! { "user_id": profile.user_id, "is_read": false }, None)
+                .await
+                .unwrap_or(0) as i64;
+            let template = EmailTemplate::WeeklyDigest {
+                username: profile.username.clone(),
+                items: items.clone(),
+                unread_notifications: unread,
+                link: base_url.clone(),
+            };
+            if enqueue_email(state, email, Some(profile.username.clone()), template).await.is_ok() {
+                queued += 1;
+            } else {
+                // Let the retry send it
+                let mut conn = state.redis.clone();
+                let _: Result<(), _> = conn.del(digest_claim_key(&week, profile.user_id)).await;
+            }
+        }
+    }
+
+    Ok(queued)
+}
+
+/// Cron-driven; see `jobs::job_registry` for the schedule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRemindersJob;
+
+impl Job for EventRemindersJob {
+    const KIND: &'static str = "mail.event_reminders";
+    const MAX_ATTEMPTS: u32 = 3;
+}
+
+/// Cron-driven: Monday 06:00 UTC (09:00 EAT).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeeklyDigestJob;
+
+impl Job for WeeklyDigestJob {
+    const KIND: &'static str = "mail.weekly_digest";
+    const MAX_ATTEMPTS: u32 = 3;
+    const TIMEOUT_SECS: u64 = 1800;
+}
+
+pub async fn run_event_reminders(state: Arc<AppState>) -> Result<(), JobError> {
+    let n = queue_event_reminders(&state).await?;
+    if n > 0 {
+        tracing::info!("📧 Queued {} event reminder emails", n);
+    }
+    Ok(())
+}
+
+pub async fn run_weekly_digest(state: Arc<AppState>) -> Result<(), JobError> {
+    let n = queue_weekly_digests(&state).await?;
+    tracing::info!("📧 Queued {} weekly digest emails", n);
+    Ok(())
+}
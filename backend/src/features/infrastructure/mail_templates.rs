@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestItem {
+    pub title: String,
+    pub summary: String,
+    pub url: String,
+}
+
+/// Every transactional email the platform sends. Rendering is pure so templates
+/// can be checked without a mail provider.
+#[derive(Debug, Clone)]
+pub enum EmailTemplate {
+    PasswordReset { code: String, expires_minutes: i64 },
+    EmailVerification { username: String, link: String, expires_hours: i64 },
+    WeeklyDigest { username: String, items: Vec<DigestItem>, unread_notifications: i64, link: String },
+    EventReminder { username: String, event_title: String, starts_at: String, location: String, link: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+pub fn escape_html(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn layout(heading: &str, body: &str) -> String {
+    format!(
+        "<html><body style=\"font-family:Arial,sans-serif;background:#f5f6fa;padding:24px\">\
+<div style=\"max-width:560px;margin:0 auto;background:#ffffff;border-radius:12px;padding:32px\">\
+<h1 style=\"color:#3742fa;font-size:22px;margin-top:0\">{}</h1>{}\
+<p style=\"color:#888;font-size:12px;margin-top:32px\">KaruTeens · You received this because you have an account with us.</p>\
+</div></body></html>",
+        escape_html(heading),
+        body
+    )
+}
+
+fn button(link: &str, label: &str) -> String {
+    format!(
+        "<p><a href=\"{}\" style=\"display:inline-block;background:#3742fa;color:#fff;padding:12px 20px;border-radius:8px;text-decoration:none\">{}</a></p>",
+        escape_html(link),
+        escape_html(label)
+    )
+}
+
+impl EmailTemplate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmailTemplate::PasswordReset { .. } => "password_reset",
+            EmailTemplate::EmailVerification { .. } => "email_verification",
+            EmailTemplate::WeeklyDigest { .. } => "weekly_digest",
+            EmailTemplate::EventReminder { .. } => "event_reminder",
+        }
+    }
+
+    pub fn render(&self) -> RenderedEmail {
+        match self {
+            EmailTemplate::PasswordReset { code, expires_minutes } => RenderedEmail {
+                subject: "Reset Your KaruTeens Password".to_string(),
+                html: layout(
+                    "Password Reset",
+                    &format!(
+                        "<p>Your password reset code is: <strong style=\"font-size:20px;letter-spacing:2px\">{}</strong></p>\
+<p>This code expires in {} minutes. If you didn't ask for it, you can ignore this email.</p>",
+                        escape_html(code),
+                        expires_minutes
+                    ),
+                ),
+                text: format!(
+                    "Your KaruTeens password reset code is: {}\nThis code expires in {} minutes. If you didn't ask for it, you can ignore this email.\n",
+                    code, expires_minutes
+                ),
+            },
+            EmailTemplate::EmailVerification { username, link, expires_hours } => RenderedEmail {
+                subject: "Confirm your KaruTeens email".to_string(),
+                html: layout(
+                    "Confirm your email",
+                    &format!(
+                        "<p>Hi {},</p><p>Tap the button below to confirm this is your email address.</p>{}\
+<p>The link expires in {} hours.</p>",
+                        escape_html(username),
+                        button(link, "Confirm email"),
+                        expires_hours
+                    ),
+                ),
+                text: format!(
+                    "Hi {},\n\nConfirm your KaruTeens email address by opening this link:\n{}\n\nThe link expires in {} hours.\n",
+                    username, link, expires_hours
+                ),
+            },
+            EmailTemplate::WeeklyDigest { username, items, unread_notifications, link } => {
+                let list_html: String = items
+                    .iter()
+                    .map(|item| {
+                        format!(
+                            "<li style=\"margin-bottom:12px\"><a href=\"{}\" style=\"color:#3742fa\">{}</a><br><span style=\"color:#555\">{}</span></li>",
+                            escape_html(&item.url),
+                            escape_html(&item.title),
+                            escape_html(&item.summary)
+                        )
+                    })
+                    .collect();
+                let list_text: String = items
+                    .iter()
+                    .map(|item| format!("- {}\n  {}\n  {}\n", item.title, item.summary, item.url))
+                    .collect();
+                RenderedEmail {
+                    subject: "Your week on KaruTeens".to_string(),
+                    html: layout(
+                        "Your week on KaruTeens",
+                        &format!(
+                            "<p>Hi {},</p><p>You have {} unread notifications. Here's what happened this week:</p><ul style=\"padding-left:18px\">{}</ul>{}",
+                            escape_html(username),
+                            unread_notifications,
+                            list_html,
+                            button(link, "Open KaruTeens")
+                        ),
+                    ),
+                    text: format!(
+                        "Hi {},\n\nYou have {} unread notifications. Here's what happened this week:\n\n{}\nOpen KaruTeens: {}\n",
+                        username, unread_notifications, list_text, link
+                    ),
+                }
+            }
+            EmailTemplate::EventReminder { username, event_title, starts_at, location, link } => RenderedEmail {
+                subject: format!("Reminder: {} starts soon", event_title),
+                html: layout(
+                    "Event reminder",
+                    &format!(
+                        "<p>Hi {},</p><p><strong>{}</strong> starts at {}.</p><p>Location: {}</p>{}",
+                        escape_html(username),
+                        escape_html(event_title),
+                        escape_html(starts_at),
+                        escape_html(location),
+                        button(link, "View event")
+                    ),
+                ),
+                text: format!(
+                    "Hi {},\n\n{} starts at {}.\nLocation: {}\n\nView event: {}\n",
+                    username, event_title, starts_at, location, link
+                ),
+            },
+        }
+    }
+}
@@ -7,6 +7,8 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::jobs;
+use crate::features::infrastructure::media_processor::MediaJob;
 use crate::features::auth::auth_service::AuthUser;
 use sha1::{Sha1, Digest};
 use std::collections::BTreeMap;
@@ -114,19 +116,36 @@ async fn trigger_media_processing(
     state: axum::extract::State<Arc<AppState>>,
     Json(payload): Json<MediaProcessRequest>,
 ) -> AppResult<impl IntoResponse> {
+    let job_id = queue_media_job(&state.0, &_user.user_id.to_string(), payload.temp_url, payload.media_type, payload.original_name).await?;
+
+    Ok(Json(MediaProcessResponse {
+        job_id,
+        status: "queued".to_string(),
+    }))
+}
+
+/// Records a media job and queues it for the FFmpeg workers. Returns the job id,
+/// which `/media/status/:id` reports on.
+pub async fn queue_media_job(
+    state: &Arc<AppState>,
+    user_id: &str,
+    temp_url: String,
+    media_type: String,
+    original_name: String,
+) -> AppResult<String> {
     let job_id = uuid::Uuid::new_v4().to_string();
     
     // 1. Create Job Record in MongoDB
     let coll = state.mongo.collection::<MediaJobRecord>("media_jobs");
     
-    let final_url = payload.temp_url.clone();
+    let final_url = temp_url.clone();
 
     let record = MediaJobRecord {
         id: job_id.clone(),
-        user_id: _user.user_id.to_string(),
+        user_id: user_id.to_string(),
         status: "pending".to_string(),
-        media_type: payload.media_type.clone(),
-        original_name: payload.original_name.clone(),
+        media_type: media_type.clone(),
+        original_name: original_name.clone(),
         final_url,
         error: None,
         created_at: Utc::now().timestamp(),
@@ -138,29 +157,20 @@ async fn trigger_media_processing(
         AppError::InternalServerError("Database error".to_string())
     })?;
 
-    // 2. Push job to Redis queue
-    let mut conn = state.redis.clone();
-    let job_data = serde_json::json!({
-        "job_id": &job_id,
-        "temp_url": payload.temp_url,
-        "media_type": payload.media_type,
-        "original_name": payload.original_name,
-        "user_id": _user.user_id.to_string(),
-        "created_at": Utc::now().timestamp(),
-    });
-
-    let _: () = redis::Cmd::lpush("media_queue", job_data.to_string())
-        .query_async(&mut conn)
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to queue media job: {}", e);
-            AppError::InternalServerError("Queue system unavailable".to_string())
-        })?;
+    // 2. Queue the processing job
+    let job = MediaJob {
+        job_id: job_id.clone(),
+        temp_url,
+        media_type,
+        original_name,
+        user_id: user_id.to_string(),
+    };
+    jobs::enqueue(state, job).await.map_err(|e| {
+        tracing::error!("Failed to queue media job: {:?}", e);
+        AppError::InternalServerError("Queue system unavailable".to_string())
+    })?;
 
-    Ok(Json(MediaProcessResponse {
-        job_id,
-        status: "queued".to_string(),
-    }))
+    Ok(job_id)
 }
 
 use crate::features::infrastructure::error::{AppError, AppResult};
@@ -369,3 +379,56 @@ pub async fn generate_image_variants(
 
     Ok(variants)
 } // end of generate_image_variants
+
+// ── Deletion ──────────────────────────────────────────────────────────────
+
+/// The bucket key behind one of our public R2 URLs, or `None` for anything we don't host.
+pub fn r2_key_for_url(public_base_url: &str, url: &str) -> Option<String> {
+    let base = public_base_url.trim_end_matches('/');
+    let key = url.strip_prefix(base)?.strip_prefix('/')?;
+    let key = key.split(['?', '#']).next().unwrap_or_default();
+    if key.is_empty() || key.split('/').any(|part| part.is_empty() || part == "..") {
+        return None;
+    }
+    Some(key.to_string())
+}
+
+/// Deletes an uploaded object and the image variants generated from it. URLs outside the
+/// bucket are left alone and report `false`.
+pub async fn delete_r2_object(url: &str) -> anyhow::Result<bool> {
+    let public_base_url = std::env::var("R2_PUBLIC_BASE_URL")?;
+    let Some(key) = r2_key_for_url(&public_base_url, url) else {
+        return Ok(false);
+    };
+
+    let account_id = std::env::var("R2_ACCOUNT_ID")?;
+    let access_key_id = std::env::var("R2_ACCESS_KEY_ID")?;
+    let secret_access_key = std::env::var("R2_SECRET_ACCESS_KEY")?;
+    let bucket = std::env::var("R2_BUCKET")?;
+
+    let endpoint = format!("https://{}.r2.cloudflarestorage.com", account_id);
+    let credentials = aws_sdk_s3::config::Credentials::new(
+        access_key_id,
+        secret_access_key,
+        None,
+        None,
+        "Static",
+    );
+
+    let config = aws_sdk_s3::config::Builder::new()
+        .behavior_version(aws_sdk_s3::config::BehaviorVersion::latest())
+        .endpoint_url(endpoint)
+        .region(aws_sdk_s3::config::Region::new("auto"))
+        .credentials_provider(credentials)
+        .build();
+
+    let client = S3Client::from_conf(config);
+
+    let stem = key.rsplit_once('.').map(|(p, _)| p.to_string()).unwrap_or_else(|| key.clone());
+    // Deleting a missing key succeeds, so the variants can be removed blindly
+    for object in [key.clone(), format!("{}_thumb.webp", stem), format!("{}_medium.webp", stem)] {
+        client.delete_object().bucket(&bucket).key(&object).send().await?;
+    }
+
+    Ok(true)
+}
@@ -1,7 +1,6 @@
 use std::sync::Arc;
-use tokio::time::{sleep, Duration};
-use tokio::sync::Semaphore;
 use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::jobs::{Job, JobError};
 use crate::models::MediaJobRecord;
 use serde::{Deserialize, Serialize};
 use std::process::Command;
@@ -10,80 +9,28 @@ use tokio::fs;
 use mongodb::bson::doc;
 use chrono::Utc;
 
-#[derive(Debug, Deserialize, Serialize)]
-struct MediaJobQueueItem {
-    job_id: String,
-    temp_url: String,
-    media_type: String,
-    original_name: String,
-    user_id: String,
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MediaJob {
+    pub job_id: String,
+    pub temp_url: String,
+    pub media_type: String,
+    pub original_name: String,
+    pub user_id: String,
 }
 
-pub fn spawn_media_worker(state: Arc<AppState>) {
-    // Limit to 8 concurrent FFmpeg processes to prevent CPU/RAM exhaustion
-    let semaphore = Arc::new(Semaphore::new(8));
-
-    tokio::spawn(async move {
-        tracing::info!("Media Worker started with concurrency limit of 8");
-        let redis_url = state.redis_url.clone();
-        
-        loop {
-            // Use a dedicated connection for blocking BLPOP instead of the shared manager
-            let client = match redis::Client::open(redis_url.clone()) {
-                Ok(c) => c,
-                Err(e) => {
-                    tracing::error!("Failed to open Redis client for worker: {}", e);
-                    sleep(Duration::from_secs(5)).await;
-                    continue;
-                }
-            };
-
-            let mut conn = match redis::aio::ConnectionManager::new(client).await {
-                Ok(c) => c,
-                Err(e) => {
-                    tracing::error!("Failed to get Redis connection for worker: {}", e);
-                    sleep(Duration::from_secs(5)).await;
-                    continue;
-                }
-            };
-
-            loop {
-                // BLPOP returns nil on timeout, which maps to None in Option<T>
-                let result: Result<Option<(String, String)>, _> = redis::Cmd::blpop("media_queue", 10.0)
-                    .query_async(&mut conn)
-                    .await;
-
-                match result {
-                    Ok(Some((_, job_json))) => {
-                        if let Ok(job) = serde_json::from_str::<MediaJobQueueItem>(&job_json) {
-                            let state_clone = state.clone();
-                            let permit = semaphore.clone().acquire_owned().await;
-                            
-                            tokio::spawn(async move {
-                                // The permit is held until this task finishes
-                                let _permit = permit; 
-                                if let Err(e) = process_media_job(state_clone, job).await {
-                                    tracing::error!("Media job processing failed: {}", e);
-                                }
-                            });
-                        }
-                    }
-                    Ok(None) => {
-                        // Timeout with no job — continue polling
-                    }
-                    Err(e) => {
-                        tracing::error!("Redis queue error: {}", e);
-                        // Break the inner loop to reconnect
-                        break;
-                    }
-                }
-            }
-            sleep(Duration::from_secs(5)).await;
-        }
-    });
+impl Job for MediaJob {
+    const KIND: &'static str = "media.process";
+    const QUEUE: &'static str = "media";
+    const MAX_ATTEMPTS: u32 = 3;
+    const TIMEOUT_SECS: u64 = 900;
+}
+
+/// Job handler; concurrency is capped per process by the "media" queue's worker count.
+pub async fn run_media_job(state: Arc<AppState>, job: MediaJob) -> Result<(), JobError> {
+    process_media_job(state, job).await.map_err(JobError::from)
 }
 
-async fn process_media_job(state: Arc<AppState>, job: MediaJobQueueItem) -> anyhow::Result<()> {
+async fn process_media_job(state: Arc<AppState>, job: MediaJob) -> anyhow::Result<()> {
     tracing::info!("Processing media job: {} ({})", job.job_id, job.media_type);
 
     let coll = state.mongo.collection::<MediaJobRecord>("media_jobs");
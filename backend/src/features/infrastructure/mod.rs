@@ -4,10 +4,18 @@ pub mod dto;
 pub mod cache;
 pub mod cache_utils;
 pub mod cdn;
+pub mod cron;
+pub mod jobs;
+pub mod mail;
+pub mod mail_schedules;
+pub mod mail_templates;
 pub mod media;
 pub mod media_processor;
 pub mod push;
 pub mod rate_limit;
 pub mod search;
+pub mod search_index;
+pub mod spam;
+pub mod unfurl;
 pub mod stats;
 pub mod admin;
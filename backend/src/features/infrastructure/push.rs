@@ -1,7 +1,35 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Router,
+};
 use mongodb::bson::{doc, oid::ObjectId};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::sync::Arc;
+use futures::stream::StreamExt;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use aes_gcm::{aead::{Aead, KeyInit}, Aes128Gcm, Nonce};
+use rand::RngCore;
 use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::error::{AppError, AppResult};
+use crate::features::auth::auth_service::AuthUser;
+use crate::models::{Profile, PushSubscription};
+
+/// Record size advertised in the aes128gcm header. Payloads are sent as a single record.
+const RECORD_SIZE: u32 = 4096;
+/// How long a push service should hold an undelivered message (seconds).
+const DEFAULT_TTL: u32 = 24 * 3600;
+/// VAPID tokens may be valid for at most 24h; keep well inside that.
+const VAPID_TOKEN_TTL: i64 = 12 * 3600;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PushNotificationPayload {
@@ -20,13 +48,252 @@ pub struct PushAction {
     pub title: String,
 }
 
+/// Application server identity used to sign requests to push services (RFC 8292).
+pub struct VapidConfig {
+    signing_key: SigningKey,
+    public_key: Vec<u8>,
+    pub subject: String,
+}
+
+impl VapidConfig {
+    /// Reads `VAPID_PRIVATE_KEY` (base64url raw P-256 scalar) and `VAPID_SUBJECT`.
+    /// Returns `None` when push is not configured for this deployment.
+    pub fn from_env() -> Option<Self> {
+        let private_key = std::env::var("VAPID_PRIVATE_KEY").unwrap_or_default();
+        if private_key.trim().is_empty() {
+            return None;
+        }
+        let subject = std::env::var("VAPID_SUBJECT").unwrap_or_else(|_| "mailto:admin@karuteens.site".to_string());
+        match Self::from_private_key(private_key.trim(), &subject) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                tracing::error!("Invalid VAPID_PRIVATE_KEY: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn from_private_key(private_key_b64: &str, subject: &str) -> Result<Self, String> {
+        let raw = decode_base64url(private_key_b64)?;
+        let secret = SecretKey::from_slice(&raw).map_err(|e| e.to_string())?;
+        let public_key = secret.public_key().to_encoded_point(false).as_bytes().to_vec();
+        Ok(Self {
+            signing_key: SigningKey::from(secret),
+            public_key,
+            subject: subject.to_string(),
+        })
+    }
+
+    /// Uncompressed public key, base64url encoded, as expected by `PushManager.subscribe`.
+    pub fn public_key_base64(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.public_key)
+    }
+
+    /// Builds the `Authorization: vapid t=..., k=...` header value for a subscription endpoint.
+    pub fn authorization_header(&self, endpoint: &str) -> Result<String, String> {
+        let url = reqwest::Url::parse(endpoint).map_err(|e| e.to_string())?;
+        let audience = url.origin().ascii_serialization();
+        let exp = chrono::Utc::now().timestamp() + VAPID_TOKEN_TTL;
+
+        let header = URL_SAFE_NO_PAD.encode(br#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(&json!({ "aud": audience, "exp": exp, "sub": self.subject }))
+                .map_err(|e| e.to_string())?,
+        );
+        let signing_input = format!("{}.{}", header, claims);
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+        let token = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.to_bytes()));
+
+        Ok(format!("vapid t={}, k={}", token, self.public_key_base64()))
+    }
+}
+
+pub fn decode_base64url(value: &str) -> Result<Vec<u8>, String> {
+    let trimmed = value.trim().trim_end_matches('=').replace('+', "-").replace('/', "_");
+    URL_SAFE_NO_PAD.decode(trimmed).map_err(|e| e.to_string())
+}
+
+/// Encrypts a push message body with the `aes128gcm` content coding (RFC 8188 / RFC 8291).
+pub fn encrypt_payload(p256dh: &str, auth: &str, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let ua_public_bytes = decode_base64url(p256dh)?;
+    let auth_secret = decode_base64url(auth)?;
+    if auth_secret.len() != 16 {
+        return Err("auth secret must be 16 bytes".to_string());
+    }
+    let ua_public = PublicKey::from_sec1_bytes(&ua_public_bytes).map_err(|e| e.to_string())?;
+
+    // Single record: plaintext followed by the 0x02 last-record delimiter
+    if plaintext.len() + 1 + 16 > RECORD_SIZE as usize {
+        return Err("payload too large".to_string());
+    }
+
+    let as_secret = SecretKey::random(&mut rand::rngs::OsRng);
+    let as_public_bytes = as_secret.public_key().to_encoded_point(false).as_bytes().to_vec();
+    let shared = p256::ecdh::diffie_hellman(as_secret.to_nonzero_scalar(), ua_public.as_affine());
+
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(&ua_public_bytes);
+    key_info.extend_from_slice(&as_public_bytes);
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&auth_secret), shared.raw_secret_bytes())
+        .expand(&key_info, &mut ikm)
+        .map_err(|e| e.to_string())?;
+
+    let mut salt = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    let prk = Hkdf::<Sha256>::new(Some(&salt), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek).map_err(|e| e.to_string())?;
+    prk.expand(b"Content-Encoding: nonce\0", &mut nonce).map_err(|e| e.to_string())?;
+
+    let mut record = plaintext.to_vec();
+    record.push(0x02);
+    let cipher = Aes128Gcm::new_from_slice(&cek).map_err(|e| e.to_string())?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), record.as_slice())
+        .map_err(|e| e.to_string())?;
+
+    let mut body = Vec::with_capacity(16 + 4 + 1 + as_public_bytes.len() + ciphertext.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public_bytes.len() as u8);
+    body.extend_from_slice(&as_public_bytes);
+    body.extend_from_slice(&ciphertext);
+    Ok(body)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeliveryOutcome {
+    Delivered,
+    /// The push service reported the subscription as expired or unknown (404/410).
+    Gone,
+    Failed(String),
+}
+
+/// Sends one encrypted message to a single subscription endpoint.
+pub async fn deliver(
+    client: &reqwest::Client,
+    vapid: &VapidConfig,
+    subscription: &PushSubscription,
+    payload: &PushNotificationPayload,
+    ttl: u32,
+) -> DeliveryOutcome {
+    let body = match serde_json::to_vec(payload)
+        .map_err(|e| e.to_string())
+        .and_then(|json| encrypt_payload(&subscription.p256dh, &subscription.auth, &json))
+    {
+        Ok(b) => b,
+        Err(e) => return DeliveryOutcome::Failed(format!("encryption failed: {}", e)),
+    };
+    let authorization = match vapid.authorization_header(&subscription.endpoint) {
+        Ok(a) => a,
+        Err(e) => return DeliveryOutcome::Failed(format!("invalid endpoint: {}", e)),
+    };
+
+    let mut request = client
+        .post(&subscription.endpoint)
+        .header("Authorization", authorization)
+        .header("Content-Encoding", "aes128gcm")
+        .header("Content-Type", "application/octet-stream")
+        .header("TTL", ttl.to_string())
+        .header("Urgency", "normal");
+    if let Some(tag) = payload.tag.as_deref() {
+        // Topic must be at most 32 base64url characters
+        let topic: String = tag.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_').take(32).collect();
+        if !topic.is_empty() {
+            request = request.header("Topic", topic);
+        }
+    }
+
+    match request.body(body).timeout(std::time::Duration::from_secs(10)).send().await {
+        Ok(resp) if resp.status().is_success() => DeliveryOutcome::Delivered,
+        Ok(resp) if resp.status() == reqwest::StatusCode::NOT_FOUND || resp.status() == reqwest::StatusCode::GONE => {
+            DeliveryOutcome::Gone
+        }
+        Ok(resp) => {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            DeliveryOutcome::Failed(format!("push service returned {}: {}", status, text))
+        }
+        Err(e) => DeliveryOutcome::Failed(e.to_string()),
+    }
+}
+
+/// Delivers a notification to every registered device of `user_id`, honouring their
+/// notification settings and pruning subscriptions the push service no longer knows.
 pub async fn send_push_notification(
-    _state: &Arc<AppState>,
-    _user_id: ObjectId,
-    _payload: PushNotificationPayload,
+    state: &Arc<AppState>,
+    user_id: ObjectId,
+    payload: PushNotificationPayload,
 ) -> Result<(), String> {
-    // Push subscription feature not implemented yet
-    // This is a placeholder that does nothing but returns success
+    let vapid = match VapidConfig::from_env() {
+        Some(v) => v,
+        None => {
+            tracing::debug!("VAPID keys not configured, skipping web push");
+            return Ok(());
+        }
+    };
+
+    let kind = payload
+        .data
+        .as_ref()
+        .and_then(|d| d.get("type"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("");
+    let profiles = state.mongo.collection::<Profile>("profiles");
+    if let Ok(Some(profile)) = profiles.find_one(doc! { "user_id": user_id }, None).await {
+        if let Some(settings) = profile.notification_settings {
+            if !settings.allows(kind) {
+                return Ok(());
+            }
+        }
+    }
+
+    let collection = state.mongo.collection::<PushSubscription>("push_subscriptions");
+    let subscriptions: Vec<PushSubscription> = collection
+        .find(doc! { "user_id": user_id }, None)
+        .await
+        .map_err(|e| e.to_string())?
+        .filter_map(|r| async move { r.ok() })
+        .collect()
+        .await;
+
+    if subscriptions.is_empty() {
+        return Ok(());
+    }
+
+    let outcomes = futures::future::join_all(
+        subscriptions
+            .iter()
+            .map(|sub| deliver(&state.http_client, &vapid, sub, &payload, DEFAULT_TTL)),
+    )
+    .await;
+
+    let mut errors = Vec::new();
+    for (sub, outcome) in subscriptions.iter().zip(outcomes) {
+        let Some(sub_id) = sub.id else { continue };
+        match outcome {
+            DeliveryOutcome::Delivered => {
+                let _ = collection
+                    .update_one(
+                        doc! { "_id": sub_id },
+                        doc! { "$set": { "last_success_at": mongodb::bson::DateTime::now() } },
+                        None,
+                    )
+                    .await;
+            }
+            DeliveryOutcome::Gone => {
+                tracing::info!("Pruning expired push subscription {} for user {}", sub_id, user_id);
+                let _ = collection.delete_one(doc! { "_id": sub_id }, None).await;
+            }
+            DeliveryOutcome::Failed(e) => errors.push(e),
+        }
+    }
+
+    if errors.len() == subscriptions.len() {
+        return Err(errors.join("; "));
+    }
     Ok(())
 }
 
@@ -124,3 +391,145 @@ pub async fn notify_new_follower(
         tracing::warn!("Failed to send follow notification: {}", e);
     }
 }
+
+// --- DTOs ---
+#[derive(Deserialize)]
+pub struct SubscriptionKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+/// Mirrors `PushSubscription.toJSON()` from the browser, plus an optional stable device id.
+#[derive(Deserialize)]
+pub struct SubscribeRequest {
+    pub endpoint: String,
+    pub keys: SubscriptionKeys,
+    pub device_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UnsubscribeRequest {
+    pub endpoint: String,
+}
+
+#[derive(Serialize)]
+pub struct PushSubscriptionResponse {
+    pub id: String,
+    pub device_id: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+    pub last_success_at: Option<String>,
+}
+
+// --- Handlers ---
+pub async fn get_vapid_public_key_handler() -> AppResult<impl IntoResponse> {
+    let vapid = VapidConfig::from_env().ok_or(AppError::NotFound("Web push is not configured".to_string()))?;
+    Ok(Json(json!({ "public_key": vapid.public_key_base64() })))
+}
+
+pub async fn subscribe_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    headers: HeaderMap,
+    Json(payload): Json<SubscribeRequest>,
+) -> AppResult<impl IntoResponse> {
+    let endpoint = reqwest::Url::parse(&payload.endpoint)
+        .map_err(|_| AppError::BadRequest("Invalid subscription endpoint".to_string()))?;
+    if endpoint.scheme() != "https" {
+        return Err(AppError::BadRequest("Subscription endpoint must use https".to_string()));
+    }
+
+    let p256dh = decode_base64url(&payload.keys.p256dh)
+        .map_err(|_| AppError::BadRequest("Invalid p256dh key".to_string()))?;
+    if PublicKey::from_sec1_bytes(&p256dh).is_err() {
+        return Err(AppError::BadRequest("Invalid p256dh key".to_string()));
+    }
+    let auth = decode_base64url(&payload.keys.auth)
+        .map_err(|_| AppError::BadRequest("Invalid auth secret".to_string()))?;
+    if auth.len() != 16 {
+        return Err(AppError::BadRequest("Invalid auth secret".to_string()));
+    }
+
+    let user_agent = headers
+        .get(axum::http::header::USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.chars().take(256).collect::<String>());
+
+    let collection = state.mongo.collection::<PushSubscription>("push_subscriptions");
+
+    // A device re-subscribing with a new endpoint replaces its old registration
+    if let Some(device_id) = &payload.device_id {
+        collection
+            .delete_many(
+                doc! { "user_id": user.user_id, "device_id": device_id, "endpoint": { "$ne": &payload.endpoint } },
+                None,
+            )
+            .await?;
+    }
+
+    collection
+        .update_one(
+            doc! { "endpoint": &payload.endpoint },
+            doc! {
+                "$set": {
+                    "user_id": user.user_id,
+                    "p256dh": URL_SAFE_NO_PAD.encode(&p256dh),
+                    "auth": URL_SAFE_NO_PAD.encode(&auth),
+                    "device_id": &payload.device_id,
+                    "user_agent": user_agent,
+                },
+                "$setOnInsert": {
+                    "endpoint": &payload.endpoint,
+                    "created_at": mongodb::bson::DateTime::now(),
+                    "last_success_at": mongodb::bson::Bson::Null,
+                },
+            },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(json!({"message": "Push subscription saved"}))))
+}
+
+pub async fn unsubscribe_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(payload): Json<UnsubscribeRequest>,
+) -> AppResult<impl IntoResponse> {
+    let collection = state.mongo.collection::<PushSubscription>("push_subscriptions");
+    collection
+        .delete_one(doc! { "endpoint": &payload.endpoint, "user_id": user.user_id }, None)
+        .await?;
+    Ok(Json(json!({"message": "Push subscription removed"})))
+}
+
+pub async fn list_subscriptions_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let collection = state.mongo.collection::<PushSubscription>("push_subscriptions");
+    let mut cursor = collection.find(doc! { "user_id": user.user_id }, None).await?;
+
+    let mut responses = Vec::new();
+    while let Some(result) = cursor.next().await {
+        if let Ok(sub) = result {
+            responses.push(PushSubscriptionResponse {
+                id: sub.id.map(|id| id.to_hex()).unwrap_or_default(),
+                device_id: sub.device_id,
+                user_agent: sub.user_agent,
+                created_at: sub.created_at.to_chrono().to_rfc3339(),
+                last_success_at: sub.last_success_at.map(|dt| dt.to_chrono().to_rfc3339()),
+            });
+        }
+    }
+
+    Ok(Json(responses))
+}
+
+pub fn push_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/vapid-public-key", get(get_vapid_public_key_handler))
+        .route("/subscribe", post(subscribe_handler))
+        .route("/unsubscribe", post(unsubscribe_handler))
+        .route("/subscriptions", get(list_subscriptions_handler))
+}
@@ -1,6 +1,5 @@
 use axum::{
     extract::{State, Query},
-    http::StatusCode,
     response::{IntoResponse, Json},
     routing::get,
     Router,
@@ -10,6 +9,8 @@ use serde_json::json;
 use std::sync::Arc;
 use mongodb::bson::doc;
 use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::error::{AppError, AppResult};
+use crate::features::infrastructure::search_index::{DocKind, FacetCounts, SearchDocument, SearchFilters, SearchRequest, SortBy};
 use crate::models::{Profile, Post, Group, Event};
 use futures::stream::StreamExt;
 
@@ -18,6 +19,7 @@ pub struct SearchQuery {
     pub q: String,
     pub search_type: Option<String>, // all, users, posts, groups, events
     pub limit: Option<i64>,
+    pub cursor: Option<String>,
     // Advanced filters
     pub category: Option<String>,
     pub school: Option<String>,
@@ -39,196 +41,298 @@ pub struct SearchResults {
     pub posts: Option<Vec<serde_json::Value>>,
     pub groups: Option<Vec<serde_json::Value>>,
     pub events: Option<Vec<serde_json::Value>>,
+    pub results: Vec<serde_json::Value>,
+    pub facets: FacetCounts,
+    pub next_cursor: Option<String>,
     pub total_results: i64,
 }
 
 pub async fn search_handler(
     State(state): State<Arc<AppState>>,
     Query(params): Query<SearchQuery>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> AppResult<impl IntoResponse> {
+    let search_type = params.search_type.clone().unwrap_or_else(|| "all".to_string());
+    let kinds = DocKind::from_search_type(&search_type)
+        .ok_or(AppError::BadRequest("Invalid search_type".to_string()))?;
+
     if params.q.trim().is_empty() {
-        return Ok(Json(json!({ "users": [], "posts": [], "groups": [], "events": [], "total_results": 0 })));
+        return Ok(Json(json!({ "users": [], "posts": [], "groups": [], "events": [], "results": [], "total_results": 0 })));
     }
 
-    let q = params.q;
-    let limit = params.limit.unwrap_or(10);
-    let search_type = params.search_type.unwrap_or_else(|| "all".to_string());
+    let parse_date = |value: &Option<String>| -> AppResult<Option<i64>> {
+        match value {
+            Some(v) => chrono::DateTime::parse_from_rfc3339(v)
+                .map(|d| Some(d.timestamp_millis()))
+                .map_err(|_| AppError::BadRequest("Invalid date filter, expected RFC3339".to_string())),
+            None => Ok(None),
+        }
+    };
 
-    let mut results = SearchResults {
-        users: None,
-        posts: None,
-        groups: None,
-        events: None,
-        total_results: 0,
+    let request = SearchRequest {
+        query: params.q.clone(),
+        kinds: kinds.clone(),
+        filters: SearchFilters {
+            school: params.school.clone(),
+            year_of_study: params.year_of_study,
+            category: params.category.clone(),
+            tags: params.tags.clone().unwrap_or_default(),
+            has_media: params.has_media,
+            post_type: params.post_type.clone(),
+            language: params.language.clone(),
+            min_likes: params.min_likes.map(i64::from),
+            date_from: parse_date(&params.date_from)?,
+            date_to: parse_date(&params.date_to)?,
+        },
+        sort: SortBy::parse(params.sort_by.as_deref()),
+        limit: params.limit.unwrap_or(10).clamp(1, 50) as usize,
+        cursor: params.cursor.clone(),
     };
 
-    // --- Search Users ---
-    if search_type == "all" || search_type == "users" {
-        let profiles = state.mongo.collection::<Profile>("profiles");
-        
-        let mut query = doc! {
-            "$or": [
-                { "username": { "$regex": &q, "$options": "i" } },
-                { "full_name": { "$regex": &q, "$options": "i" } }
-            ]
-        };
-        
-        // Add advanced filters
-        if let Some(ref school) = params.school {
-            query.insert("school", doc! { "$regex": school, "$options": "i" });
-        }
-        if let Some(year) = params.year_of_study {
-            query.insert("year_of_study", year);
-        }
-        
-        let mut cursor = profiles.find(
-            query,
-            Some(mongodb::options::FindOptions::builder().limit(limit).build())
-        ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
-
-        let mut users_list = Vec::new();
-        while let Some(profile) = cursor.next().await {
-            if let Ok(p) = profile {
-                users_list.push(json!({
-                    "username": p.username,
-                    "full_name": p.full_name,
-                    "avatar_url": p.avatar_url,
-                    "school": p.school,
-                    "year_of_study": p.year_of_study,
-                    "user_id": p.user_id.to_hex()
-                }));
-            }
-        }
-        results.users = Some(users_list);
+    if state.search_index.is_empty() {
+        tracing::warn!("Search index is empty; the initial build may still be running");
     }
+    let page = state.search_index.search(&request);
 
-    // --- Search Posts ---
-    if search_type == "all" || search_type == "posts" {
-        let posts = state.mongo.collection::<Post>("posts");
-        let mut post_query = doc! {
-            "status": "published",
-            "$or": [
-                { "title": { "$regex": &q, "$options": "i" } },
-                { "content": { "$regex": &q, "$options": "i" } },
-                { "tags": { "$regex": &q, "$options": "i" } }
-            ]
-        };
-
-        if let Some(ref cat) = params.category {
-            post_query.insert("category", cat);
+    let group_of = |kind: DocKind| -> Option<Vec<serde_json::Value>> {
+        if !kinds.contains(&kind) {
+            return None;
         }
+        Some(page.hits.iter().filter(|h| h.kind == kind).map(|h| h.payload.clone()).collect())
+    };
 
-        if let Some(has_media) = params.has_media {
-            if has_media {
-                post_query.insert("media_urls", doc! { "$exists": true, "$ne": [] });
-            } else {
-                post_query.insert("media_urls", doc! { "$in": [null, []] });
-            }
-        }
+    let results = SearchResults {
+        users: group_of(DocKind::User),
+        posts: group_of(DocKind::Post),
+        groups: group_of(DocKind::Group),
+        events: group_of(DocKind::Event),
+        results: page.hits.iter().map(|h| json!(h)).collect(),
+        facets: page.facets.clone(),
+        next_cursor: page.next_cursor.clone(),
+        total_results: page.total as i64,
+    };
 
-        if let (Some(from), Some(to)) = (&params.date_from, &params.date_to) {
-            if let (Ok(f), Ok(t)) = (mongodb::bson::DateTime::parse_rfc3339_str(from), mongodb::bson::DateTime::parse_rfc3339_str(to)) {
-                post_query.insert("created_at", doc! { "$gte": f, "$lte": t });
-            }
-        }
+    Ok(Json(json!(results)))
+}
 
-        if let Some(ref post_type) = params.post_type {
-            post_query.insert("post_type", post_type);
-        }
+// --- Indexing ---
 
-        if let Some(min_likes) = params.min_likes {
-            post_query.insert("like_count", doc! { "$gte": min_likes });
-        }
+pub fn profile_document(p: &Profile) -> SearchDocument {
+    let mut fields = vec![(p.username.clone(), 3.0)];
+    if let Some(name) = &p.full_name {
+        fields.push((name.clone(), 2.0));
+    }
+    if let Some(bio) = &p.bio {
+        fields.push((bio.clone(), 0.5));
+    }
+    SearchDocument {
+        kind: DocKind::User,
+        id: p.user_id.to_hex(),
+        fields,
+        school: p.school.clone(),
+        year_of_study: p.year_of_study,
+        category: None,
+        tags: p.interests.clone().unwrap_or_default(),
+        has_media: p.avatar_url.is_some(),
+        post_type: None,
+        language: None,
+        created_at: p.created_at.map(|d| d.timestamp_millis()).unwrap_or(0),
+        popularity: p.follower_count as i64,
+        payload: json!({
+            "username": p.username,
+            "full_name": p.full_name,
+            "avatar_url": p.avatar_url,
+            "school": p.school,
+            "year_of_study": p.year_of_study,
+            "user_id": p.user_id.to_hex()
+        }),
+    }
+}
 
-        if let Some(ref lang) = params.language {
-            post_query.insert("language", lang);
-        }
+/// Only published posts are searchable; anonymous posts never expose their author.
+pub fn post_document(p: &Post) -> Option<SearchDocument> {
+    let id = p.id?;
+    if p.status != "published" {
+        return None;
+    }
+    let author_name = if p.is_anonymous { "Anonymous".to_string() } else { p.author_name.clone() };
+    let mut fields = vec![(p.title.clone(), 2.0), (p.content.clone(), 1.0)];
+    if !p.is_anonymous {
+        fields.push((p.author_name.clone(), 0.5));
+    }
+    Some(SearchDocument {
+        kind: DocKind::Post,
+        id: id.to_hex(),
+        fields,
+        school: None,
+        year_of_study: None,
+        category: Some(p.category.clone()),
+        tags: p.tags.clone().unwrap_or_default(),
+        has_media: p.media_urls.as_ref().map(|m| !m.is_empty()).unwrap_or(false),
+        post_type: Some(p.post_type.clone()),
+        language: Some(p.language.clone()),
+        created_at: p.created_at.timestamp_millis(),
+        popularity: p.like_count as i64,
+        payload: json!({
+            "id": id.to_hex(),
+            "title": p.title,
+            "excerpt": p.content.chars().take(160).collect::<String>(),
+            "author_name": author_name,
+            "created_at": p.created_at.to_chrono().to_rfc3339()
+        }),
+    })
+}
 
-        if let Some(ref tags) = params.tags {
-            if !tags.is_empty() {
-                post_query.insert("tags", doc! { "$in": tags });
-            }
-        }
+pub fn group_document(g: &Group) -> Option<SearchDocument> {
+    let id = g.id?;
+    if g.is_private {
+        return None;
+    }
+    Some(SearchDocument {
+        kind: DocKind::Group,
+        id: id.to_hex(),
+        fields: vec![(g.name.clone(), 2.0), (g.description.clone(), 1.0)],
+        school: None,
+        year_of_study: None,
+        category: Some(g.category.clone()),
+        tags: Vec::new(),
+        has_media: g.avatar_url.is_some(),
+        post_type: None,
+        language: None,
+        created_at: g.created_at.timestamp_millis(),
+        popularity: g.members.len() as i64,
+        payload: json!({
+            "id": id.to_hex(),
+            "name": g.name,
+            "avatar_url": g.avatar_url,
+            "member_count": g.members.len()
+        }),
+    })
+}
+
+pub fn event_document(e: &Event) -> Option<SearchDocument> {
+    let id = e.id?;
+    if e.status != "published" {
+        return None;
+    }
+    Some(SearchDocument {
+        kind: DocKind::Event,
+        id: id.to_hex(),
+        fields: vec![(e.title.clone(), 2.0), (e.description.clone(), 1.0), (e.location.clone(), 0.5)],
+        school: None,
+        year_of_study: None,
+        category: Some(e.category.clone()),
+        tags: e.tags.clone().unwrap_or_default(),
+        has_media: e.image_url.is_some(),
+        post_type: None,
+        language: None,
+        created_at: e.start_datetime.timestamp_millis(),
+        popularity: e.current_attendees as i64,
+        payload: json!({
+            "id": id.to_hex(),
+            "title": e.title,
+            "location": e.location,
+            "start_datetime": e.start_datetime.to_chrono().to_rfc3339()
+        }),
+    })
+}
+
+/// Re-reads one document after a write and refreshes (or drops) its index entry.
+async fn reindex_one<T>(
+    state: &Arc<AppState>,
+    collection: &str,
+    filter: mongodb::bson::Document,
+    kind: DocKind,
+    id: &str,
+    document: impl Fn(&T) -> Option<SearchDocument>,
+) where
+    T: serde::de::DeserializeOwned + Unpin + Send + Sync,
+{
+    match state.mongo.collection::<T>(collection).find_one(filter, None).await {
+        Ok(Some(found)) => match document(&found) {
+            Some(document) => state.search_index.upsert(document),
+            None => state.search_index.remove(kind, id),
+        },
+        Ok(None) => state.search_index.remove(kind, id),
+        Err(e) => tracing::warn!("Failed to reindex {} {}: {}", kind.as_str(), id, e),
+    }
+}
+
+/// Re-reads a post and refreshes (or drops) its index entry after a write.
+pub async fn reindex_post(state: &Arc<AppState>, post_id: mongodb::bson::oid::ObjectId) {
+    reindex_one(state, "posts", doc! { "_id": post_id }, DocKind::Post, &post_id.to_hex(), post_document).await;
+}
+
+/// Profiles are indexed under their user id.
+pub async fn reindex_profile(state: &Arc<AppState>, user_id: mongodb::bson::oid::ObjectId) {
+    reindex_one(state, "profiles", doc! { "user_id": user_id }, DocKind::User, &user_id.to_hex(), |p: &Profile| {
+        Some(profile_document(p))
+    })
+    .await;
+}
+
+pub async fn reindex_group(state: &Arc<AppState>, group_id: mongodb::bson::oid::ObjectId) {
+    reindex_one(state, "groups", doc! { "_id": group_id }, DocKind::Group, &group_id.to_hex(), group_document).await;
+}
+
+pub async fn reindex_event(state: &Arc<AppState>, event_id: mongodb::bson::oid::ObjectId) {
+    reindex_one(state, "events", doc! { "_id": event_id }, DocKind::Event, &event_id.to_hex(), event_document).await;
+}
 
-        let sort = match params.sort_by.as_deref() {
-            Some("recent") => doc! { "created_at": -1 },
-            Some("popular") => doc! { "like_count": -1 },
-            _ => doc! { "score": { "$meta": "textScore" } } // Default if text index exists, otherwise fallback
-        };
+async fn load_documents(state: &Arc<AppState>) -> Result<Vec<SearchDocument>, mongodb::error::Error> {
+    let mut documents = Vec::new();
 
-        let mut find_options = mongodb::options::FindOptions::builder().limit(limit).build();
-        if params.sort_by.is_some() {
-            find_options.sort = Some(sort);
+    let mut cursor = state.mongo.collection::<Profile>("profiles").find(doc! {}, None).await?;
+    while let Some(result) = cursor.next().await {
+        if let Ok(p) = result {
+            documents.push(profile_document(&p));
         }
+    }
 
-        let mut cursor = posts.find(post_query, find_options)
-            .await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
-
-        let mut posts_list = Vec::new();
-        while let Some(post) = cursor.next().await {
-            if let Ok(p) = post {
-                posts_list.push(json!({
-                    "id": p.id.unwrap().to_hex(),
-                    "title": p.title,
-                    "author_name": p.author_name,
-                    "created_at": p.created_at.to_chrono().to_rfc3339()
-                }));
-            }
+    let mut cursor = state.mongo.collection::<Post>("posts").find(doc! { "status": "published" }, None).await?;
+    while let Some(result) = cursor.next().await {
+        if let Ok(p) = result {
+            documents.extend(post_document(&p));
         }
-        results.posts = Some(posts_list);
     }
 
-    // --- Search Groups ---
-    if search_type == "all" || search_type == "groups" {
-        let groups = state.mongo.collection::<Group>("groups");
-        let mut cursor = groups.find(
-            doc! {
-                "is_private": false,
-                "name": { "$regex": &q, "$options": "i" }
-            },
-            Some(mongodb::options::FindOptions::builder().limit(limit).build())
-        ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
-
-        let mut groups_list = Vec::new();
-        while let Some(group) = cursor.next().await {
-            if let Ok(g) = group {
-                groups_list.push(json!({
-                    "id": g.id.unwrap().to_hex(),
-                    "name": g.name,
-                    "avatar_url": g.avatar_url,
-                    "member_count": g.members.len()
-                }));
-            }
+    let mut cursor = state.mongo.collection::<Group>("groups").find(doc! { "is_private": false }, None).await?;
+    while let Some(result) = cursor.next().await {
+        if let Ok(g) = result {
+            documents.extend(group_document(&g));
         }
-        results.groups = Some(groups_list);
     }
 
-    // --- Search Events ---
-    if search_type == "all" || search_type == "events" {
-        let events = state.mongo.collection::<Event>("events");
-        let mut cursor = events.find(
-            doc! {
-                "status": "published",
-                "title": { "$regex": &q, "$options": "i" }
-            },
-            Some(mongodb::options::FindOptions::builder().limit(limit).build())
-        ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
-
-        let mut events_list = Vec::new();
-        while let Some(event) = cursor.next().await {
-            if let Ok(e) = event {
-                events_list.push(json!({
-                    "id": e.id.unwrap().to_hex(),
-                    "title": e.title,
-                    "location": e.location,
-                    "start_datetime": e.start_datetime.to_chrono().to_rfc3339()
-                }));
-            }
+    let mut cursor = state.mongo.collection::<Event>("events").find(doc! { "status": "published" }, None).await?;
+    while let Some(result) = cursor.next().await {
+        if let Ok(e) = result {
+            documents.extend(event_document(&e));
         }
-        results.events = Some(events_list);
     }
 
-    Ok(Json(json!(results)))
+    Ok(documents)
+}
+
+/// Builds the search index at startup and rebuilds it every `SEARCH_REINDEX_SECS` so
+/// writes made through other instances (or outside the API) become searchable.
+pub fn spawn_search_indexer(state: Arc<AppState>) {
+    let interval = std::env::var("SEARCH_REINDEX_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(300);
+
+    tokio::spawn(async move {
+        loop {
+            let started = std::time::Instant::now();
+            match load_documents(&state).await {
+                Ok(documents) => {
+                    state.search_index.replace_all(documents);
+                    tracing::info!("Search index rebuilt with {} documents in {:?}", state.search_index.len(), started.elapsed());
+                }
+                Err(e) => tracing::error!("Search index rebuild failed: {}", e),
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+        }
+    });
 }
 
 pub fn search_routes() -> Router<Arc<AppState>> {
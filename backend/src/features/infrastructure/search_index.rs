@@ -0,0 +1,676 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+// BM25 tuning
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+// Relative weight of non-exact term expansions
+const PREFIX_WEIGHT: f64 = 0.7;
+const FUZZY_WEIGHT: f64 = 0.4;
+const MAX_EXPANSIONS: usize = 50;
+
+const STOPWORDS: &[&str] = &[
+    // English
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "if", "in", "into", "is", "it",
+    "of", "on", "or", "so", "that", "the", "their", "then", "there", "these", "they", "this", "to", "was",
+    "were", "will", "with",
+    // Swahili / Sheng
+    "na", "ya", "wa", "za", "la", "cha", "vya", "kwa", "ni", "si", "ku", "katika", "hii", "hiyo", "huo",
+    "ile", "kama", "pia", "au", "lakini", "tu",
+];
+
+/// Common Sheng and Swahili spellings folded onto one canonical term.
+const SHENG_VARIANTS: &[(&str, &str)] = &[
+    ("mathree", "matatu"),
+    ("mat", "matatu"),
+    ("doo", "pesa"),
+    ("dooh", "pesa"),
+    ("ganji", "pesa"),
+    ("mbesha", "pesa"),
+    ("chapaa", "pesa"),
+    ("keja", "nyumba"),
+    ("digs", "nyumba"),
+    ("chuo", "campus"),
+    ("campo", "campus"),
+    ("shule", "school"),
+    ("skuul", "school"),
+    ("mse", "msee"),
+    ("masee", "msee"),
+    ("sherehe", "party"),
+    ("bash", "party"),
+];
+
+const SWAHILI_SUFFIXES: &[&str] = &["isha", "esha", "ika", "eka", "iwa", "ewa", "ana", "ia", "ea"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum DocKind {
+    User,
+    Post,
+    Group,
+    Event,
+}
+
+impl DocKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocKind::User => "user",
+            DocKind::Post => "post",
+            DocKind::Group => "group",
+            DocKind::Event => "event",
+        }
+    }
+
+    /// Maps the `search_type` query values onto document kinds.
+    pub fn from_search_type(search_type: &str) -> Option<Vec<DocKind>> {
+        match search_type {
+            "all" => Some(vec![DocKind::User, DocKind::Post, DocKind::Group, DocKind::Event]),
+            "users" => Some(vec![DocKind::User]),
+            "posts" => Some(vec![DocKind::Post]),
+            "groups" => Some(vec![DocKind::Group]),
+            "events" => Some(vec![DocKind::Event]),
+            _ => None,
+        }
+    }
+}
+
+/// A searchable entity as seen by the index. `payload` is what gets returned to clients.
+#[derive(Debug, Clone)]
+pub struct SearchDocument {
+    pub kind: DocKind,
+    pub id: String,
+    /// Text fields with their boost (e.g. title 2.0, body 1.0)
+    pub fields: Vec<(String, f64)>,
+    pub school: Option<String>,
+    pub year_of_study: Option<i32>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub has_media: bool,
+    pub post_type: Option<String>,
+    pub language: Option<String>,
+    pub created_at: i64, // millis
+    pub popularity: i64,
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortBy {
+    Relevance,
+    Recent,
+    Popular,
+}
+
+impl SortBy {
+    pub fn parse(value: Option<&str>) -> Self {
+        match value {
+            Some("recent") => SortBy::Recent,
+            Some("popular") => SortBy::Popular,
+            _ => SortBy::Relevance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilters {
+    pub school: Option<String>,
+    pub year_of_study: Option<i32>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub has_media: Option<bool>,
+    pub post_type: Option<String>,
+    pub language: Option<String>,
+    pub min_likes: Option<i64>,
+    pub date_from: Option<i64>,
+    pub date_to: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchRequest {
+    pub query: String,
+    pub kinds: Vec<DocKind>,
+    pub filters: SearchFilters,
+    pub sort: SortBy,
+    pub limit: usize,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub kind: DocKind,
+    pub id: String,
+    pub score: f64,
+    #[serde(flatten)]
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FacetCounts {
+    pub school: BTreeMap<String, usize>,
+    pub year_of_study: BTreeMap<i32, usize>,
+    pub category: BTreeMap<String, usize>,
+    pub tags: BTreeMap<String, usize>,
+    pub has_media: BTreeMap<bool, usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchPage {
+    pub hits: Vec<SearchHit>,
+    pub total: usize,
+    pub facets: FacetCounts,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    k: f64,
+    id: String,
+}
+
+struct IndexedDoc {
+    doc: SearchDocument,
+    length: f64,
+}
+
+#[derive(Default)]
+struct Inner {
+    docs: HashMap<u32, IndexedDoc>,
+    keys: HashMap<(DocKind, String), u32>,
+    postings: BTreeMap<String, HashMap<u32, f64>>,
+    total_length: f64,
+    next_id: u32,
+}
+
+/// In-process inverted index over users, posts, groups and events.
+#[derive(Default)]
+pub struct SearchIndex {
+    inner: RwLock<Inner>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().map(|i| i.docs.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds or replaces a document.
+    pub fn upsert(&self, doc: SearchDocument) {
+        if let Ok(mut inner) = self.inner.write() {
+            inner.remove(doc.kind, &doc.id);
+            inner.insert(doc);
+        }
+    }
+
+    pub fn remove(&self, kind: DocKind, id: &str) {
+        if let Ok(mut inner) = self.inner.write() {
+            inner.remove(kind, id);
+        }
+    }
+
+    /// Swaps in a freshly built index, used by the periodic full reindex.
+    pub fn replace_all(&self, docs: Vec<SearchDocument>) {
+        let mut fresh = Inner::default();
+        for doc in docs {
+            fresh.remove(doc.kind, &doc.id);
+            fresh.insert(doc);
+        }
+        if let Ok(mut inner) = self.inner.write() {
+            *inner = fresh;
+        }
+    }
+
+    pub fn search(&self, request: &SearchRequest) -> SearchPage {
+        let empty = SearchPage { hits: Vec::new(), total: 0, facets: FacetCounts::default(), next_cursor: None };
+        let inner = match self.inner.read() {
+            Ok(i) => i,
+            Err(_) => return empty,
+        };
+
+        let raw_tokens = split_words(&request.query);
+        if raw_tokens.is_empty() {
+            return empty;
+        }
+
+        // Each query token must match (AND); score is the best expansion per token
+        let doc_count = inner.docs.len() as f64;
+        let avg_length = if doc_count > 0.0 { inner.total_length / doc_count } else { 1.0 };
+        let last = raw_tokens.len() - 1;
+        let mut scores: Option<HashMap<u32, f64>> = None;
+
+        for (i, raw) in raw_tokens.iter().enumerate() {
+            let term = normalize_term(raw);
+            if term.is_empty() {
+                continue;
+            }
+            let expansions = inner.expand(&term, raw, i == last);
+            let mut token_scores: HashMap<u32, f64> = HashMap::new();
+            for (expanded, weight) in expansions {
+                let Some(postings) = inner.postings.get(&expanded) else { continue };
+                let df = postings.len() as f64;
+                let idf = (1.0 + (doc_count - df + 0.5) / (df + 0.5)).ln();
+                for (&doc_id, &tf) in postings {
+                    let length = inner.docs.get(&doc_id).map(|d| d.length).unwrap_or(avg_length);
+                    let bm25 = idf * (tf * (K1 + 1.0)) / (tf + K1 * (1.0 - B + B * length / avg_length));
+                    let entry = token_scores.entry(doc_id).or_insert(0.0);
+                    *entry = entry.max(weight * bm25);
+                }
+            }
+
+            scores = Some(match scores {
+                None => token_scores,
+                Some(prev) => prev
+                    .into_iter()
+                    .filter_map(|(id, s)| token_scores.get(&id).map(|t| (id, s + t)))
+                    .collect(),
+            });
+        }
+
+        let scores = scores.unwrap_or_default();
+        let mut matched: Vec<(&IndexedDoc, f64)> = scores
+            .iter()
+            .filter_map(|(id, score)| inner.docs.get(id).map(|d| (d, *score)))
+            .filter(|(d, _)| request.kinds.contains(&d.doc.kind) && matches_filters(&d.doc, &request.filters))
+            .collect();
+
+        let facets = count_facets(matched.iter().map(|(d, _)| &d.doc));
+        let total = matched.len();
+
+        let sort_key = |d: &IndexedDoc, score: f64| -> f64 {
+            match request.sort {
+                SortBy::Relevance => score,
+                SortBy::Recent => d.doc.created_at as f64,
+                SortBy::Popular => d.doc.popularity as f64,
+            }
+        };
+        matched.sort_by(|(a, sa), (b, sb)| {
+            sort_key(b, *sb)
+                .partial_cmp(&sort_key(a, *sa))
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| doc_key(&a.doc).cmp(&doc_key(&b.doc)))
+        });
+
+        let cursor = request.cursor.as_deref().and_then(decode_cursor);
+        let start = match cursor {
+            Some(c) => matched
+                .iter()
+                .position(|(d, s)| {
+                    let k = sort_key(d, *s);
+                    k < c.k || (k == c.k && doc_key(&d.doc) > c.id)
+                })
+                .unwrap_or(matched.len()),
+            None => 0,
+        };
+
+        let limit = request.limit.max(1);
+        let page: Vec<&(&IndexedDoc, f64)> = matched.iter().skip(start).take(limit).collect();
+        let next_cursor = if start + page.len() < matched.len() {
+            page.last().map(|(d, s)| encode_cursor(&Cursor { k: sort_key(d, *s), id: doc_key(&d.doc) }))
+        } else {
+            None
+        };
+
+        let hits = page
+            .into_iter()
+            .map(|(d, s)| SearchHit { kind: d.doc.kind, id: d.doc.id.clone(), score: *s, payload: d.doc.payload.clone() })
+            .collect();
+
+        SearchPage { hits, total, facets, next_cursor }
+    }
+}
+
+impl Inner {
+    fn insert(&mut self, doc: SearchDocument) {
+        let doc_id = self.next_id;
+        self.next_id += 1;
+
+        let mut term_freqs: HashMap<String, f64> = HashMap::new();
+        let mut length = 0.0;
+        for (text, boost) in &doc.fields {
+            for term in tokenize(text) {
+                *term_freqs.entry(term).or_insert(0.0) += boost;
+                length += boost;
+            }
+        }
+        for tag in &doc.tags {
+            for term in tokenize(tag) {
+                *term_freqs.entry(term).or_insert(0.0) += 1.5;
+                length += 1.5;
+            }
+        }
+
+        for (term, tf) in term_freqs {
+            self.postings.entry(term).or_default().insert(doc_id, tf);
+        }
+        self.total_length += length;
+        self.keys.insert((doc.kind, doc.id.clone()), doc_id);
+        self.docs.insert(doc_id, IndexedDoc { doc, length });
+    }
+
+    fn remove(&mut self, kind: DocKind, id: &str) {
+        let Some(doc_id) = self.keys.remove(&(kind, id.to_string())) else { return };
+        if let Some(indexed) = self.docs.remove(&doc_id) {
+            self.total_length -= indexed.length;
+            let mut terms: HashSet<String> = HashSet::new();
+            for (text, _) in &indexed.doc.fields {
+                terms.extend(tokenize(text));
+            }
+            for tag in &indexed.doc.tags {
+                terms.extend(tokenize(tag));
+            }
+            for term in terms {
+                if let Some(postings) = self.postings.get_mut(&term) {
+                    postings.remove(&doc_id);
+                    if postings.is_empty() {
+                        self.postings.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Expands a query term into indexed terms: exact, prefix (last token only) and typo matches.
+    fn expand(&self, term: &str, raw: &str, allow_prefix: bool) -> Vec<(String, f64)> {
+        let mut expansions: HashMap<String, f64> = HashMap::new();
+        if self.postings.contains_key(term) {
+            expansions.insert(term.to_string(), 1.0);
+        }
+
+        if allow_prefix {
+            for prefix in [term, raw] {
+                if prefix.chars().count() < 2 {
+                    continue;
+                }
+                for (candidate, _) in self
+                    .postings
+                    .range(prefix.to_string()..)
+                    .take_while(|(k, _)| k.starts_with(prefix))
+                    .take(MAX_EXPANSIONS)
+                {
+                    expansions.entry(candidate.clone()).or_insert(PREFIX_WEIGHT);
+                }
+            }
+        }
+
+        let max_edits = match term.chars().count() {
+            0..=3 => 0,
+            4..=7 => 1,
+            _ => 2,
+        };
+        if max_edits > 0 {
+            let first = term.chars().next().map(|c| c.to_string()).unwrap_or_default();
+            let mut found = 0;
+            for (candidate, _) in self.postings.range(first.clone()..).take_while(|(k, _)| k.starts_with(&first)) {
+                if found >= MAX_EXPANSIONS {
+                    break;
+                }
+                if candidate.chars().count().abs_diff(term.chars().count()) > max_edits {
+                    continue;
+                }
+                if edit_distance(term, candidate) <= max_edits {
+                    expansions.entry(candidate.clone()).or_insert(FUZZY_WEIGHT);
+                    found += 1;
+                }
+            }
+        }
+
+        expansions.into_iter().collect()
+    }
+}
+
+fn doc_key(doc: &SearchDocument) -> String {
+    format!("{}:{}", doc.kind.as_str(), doc.id)
+}
+
+fn encode_cursor(cursor: &Cursor) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(cursor).unwrap_or_default())
+}
+
+fn decode_cursor(value: &str) -> Option<Cursor> {
+    let bytes = URL_SAFE_NO_PAD.decode(value).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Filters only constrain kinds that carry the field, so `search_type=all` with a
+/// `school` filter narrows users without hiding posts.
+fn matches_filters(doc: &SearchDocument, filters: &SearchFilters) -> bool {
+    let kind = doc.kind;
+    let eq_ci = |a: &Option<String>, b: &str| a.as_deref().map(|v| v.eq_ignore_ascii_case(b)).unwrap_or(false);
+
+    if kind == DocKind::User {
+        if let Some(school) = &filters.school {
+            if !eq_ci(&doc.school, school) {
+                return false;
+            }
+        }
+        if let Some(year) = filters.year_of_study {
+            if doc.year_of_study != Some(year) {
+                return false;
+            }
+        }
+    }
+
+    if kind != DocKind::User {
+        if let Some(category) = &filters.category {
+            if !eq_ci(&doc.category, category) {
+                return false;
+            }
+        }
+    }
+
+    if matches!(kind, DocKind::Post | DocKind::Event) && !filters.tags.is_empty() {
+        let has_tag = doc.tags.iter().any(|t| filters.tags.iter().any(|f| f.eq_ignore_ascii_case(t)));
+        if !has_tag {
+            return false;
+        }
+    }
+
+    if kind == DocKind::Post {
+        if let Some(has_media) = filters.has_media {
+            if doc.has_media != has_media {
+                return false;
+            }
+        }
+        if let Some(post_type) = &filters.post_type {
+            if !eq_ci(&doc.post_type, post_type) {
+                return false;
+            }
+        }
+        if let Some(language) = &filters.language {
+            if !eq_ci(&doc.language, language) {
+                return false;
+            }
+        }
+        if let Some(min_likes) = filters.min_likes {
+            if doc.popularity < min_likes {
+                return false;
+            }
+        }
+        if let Some(from) = filters.date_from {
+            if doc.created_at < from {
+                return false;
+            }
+        }
+        if let Some(to) = filters.date_to {
+            if doc.created_at > to {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn count_facets<'a>(docs: impl Iterator<Item = &'a SearchDocument>) -> FacetCounts {
+    let mut facets = FacetCounts::default();
+    for doc in docs {
+        if let Some(school) = &doc.school {
+            *facets.school.entry(school.clone()).or_insert(0) += 1;
+        }
+        if let Some(year) = doc.year_of_study {
+            *facets.year_of_study.entry(year).or_insert(0) += 1;
+        }
+        if let Some(category) = &doc.category {
+            *facets.category.entry(category.clone()).or_insert(0) += 1;
+        }
+        for tag in &doc.tags {
+            *facets.tags.entry(tag.to_lowercase()).or_insert(0) += 1;
+        }
+        if doc.kind == DocKind::Post {
+            *facets.has_media.entry(doc.has_media).or_insert(0) += 1;
+        }
+    }
+    facets
+}
+
+/// Lowercases, folds accents and splits on anything that is not a letter or digit.
+fn split_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.chars().flat_map(char::to_lowercase).map(fold_accent).collect())
+        .collect()
+}
+
+fn fold_accent(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ä' | 'ã' | 'å' => 'a',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'ò' | 'ó' | 'ô' | 'ö' | 'õ' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'ñ' => 'n',
+        'ç' => 'c',
+        _ => c,
+    }
+}
+
+/// Full analysis chain used for both documents and queries.
+pub fn tokenize(text: &str) -> Vec<String> {
+    split_words(text)
+        .iter()
+        .map(|w| normalize_term(w))
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+fn normalize_term(word: &str) -> String {
+    // Elongated chat spellings: "poaaa" -> "poa", "sawaaa" -> "sawa"
+    let chars: Vec<char> = word.chars().collect();
+    let mut collapsed = String::with_capacity(word.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let run = chars[i..].iter().take_while(|&&c| c == chars[i]).count();
+        let keep = if run >= 3 { 1 } else { run };
+        collapsed.extend(std::iter::repeat_n(chars[i], keep));
+        i += run;
+    }
+
+    if STOPWORDS.contains(&collapsed.as_str()) {
+        return String::new();
+    }
+    if let Some((_, canonical)) = SHENG_VARIANTS.iter().find(|(variant, _)| *variant == collapsed) {
+        return canonical.to_string();
+    }
+    stem(&collapsed)
+}
+
+/// Light stemmer: Swahili verb extensions first, then common English suffixes.
+pub fn stem(word: &str) -> String {
+    if word.chars().any(|c| c.is_ascii_digit()) || word.len() <= 3 {
+        return word.to_string();
+    }
+
+    for suffix in SWAHILI_SUFFIXES {
+        if let Some(root) = word.strip_suffix(suffix) {
+            if root.len() >= 3 && !root.ends_with(|c: char| "aeiou".contains(c)) {
+                return format!("{}a", root);
+            }
+        }
+    }
+    if let Some(root) = word.strip_suffix("wa") {
+        if word.len() >= 6 && !root.ends_with(|c: char| "aeiou".contains(c)) {
+            return format!("{}a", root);
+        }
+    }
+
+    stem_english(word)
+}
+
+fn stem_english(word: &str) -> String {
+    let has_vowel = |s: &str| s.chars().any(|c| "aeiouy".contains(c));
+
+    if let Some(root) = word.strip_suffix("ational") {
+        return format!("{}ate", root);
+    }
+    if let Some(root) = word.strip_suffix("ization") {
+        return format!("{}ize", root);
+    }
+    for suffix in ["ness", "ment", "fully", "ly"] {
+        if let Some(root) = word.strip_suffix(suffix) {
+            if root.len() >= 3 && has_vowel(root) {
+                return stem_english(root);
+            }
+        }
+    }
+    if let Some(root) = word.strip_suffix("ies") {
+        if root.len() >= 2 {
+            return format!("{}y", root);
+        }
+    }
+    if word.ends_with("sses") {
+        return word[..word.len() - 2].to_string();
+    }
+    for suffix in ["ing", "ed"] {
+        if let Some(root) = word.strip_suffix(suffix) {
+            if root.len() >= 3 && has_vowel(root) {
+                return undouble(root);
+            }
+        }
+    }
+    if word.ends_with('s') && !word.ends_with("ss") && !word.ends_with("us") && !word.ends_with("is") {
+        return word[..word.len() - 1].to_string();
+    }
+    word.to_string()
+}
+
+/// "running" -> "runn" -> "run"
+fn undouble(root: &str) -> String {
+    let bytes = root.as_bytes();
+    let n = bytes.len();
+    if n >= 2 && bytes[n - 1] == bytes[n - 2] && !b"lsz".contains(&bytes[n - 1]) && bytes[n - 1].is_ascii_alphabetic() {
+        return root[..n - 1].to_string();
+    }
+    root.to_string()
+}
+
+/// Damerau-Levenshtein (optimal string alignment) distance.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
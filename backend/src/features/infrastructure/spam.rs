@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use futures::stream::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use redis::AsyncCommands;
+use regex::{Regex, RegexBuilder};
+use crate::features::infrastructure::db::AppState;
+use crate::models::{ContentModeration, SpamDetectionRule};
+
+// Write-time content classification. Every comment, post, reel caption, confession, chat
+// message and live chat line is scored against the admin-managed `spam_detection_rules`
+// plus a few heuristics; anything at or above the threshold is routed to that content's
+// moderation queue.
+//
+// Compiled rules are cached per process. Rule edits bump `spam_rules:version` in Redis and
+// every instance reloads on its next check, so changes apply without a restart.
+
+const RULES_VERSION_KEY: &str = "spam_rules:version";
+const RULES_RECHECK: Duration = Duration::from_secs(10);
+const VELOCITY_WINDOW_SECS: i64 = 600;
+const NEW_ACCOUNT_HOURS: i64 = 24;
+
+pub fn flag_threshold() -> f64 {
+    env::var("SPAM_FLAG_THRESHOLD").ok().and_then(|v| v.parse().ok()).unwrap_or(0.7)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Comment,
+    Post,
+    ReelCaption,
+    Confession,
+    ConfessionComment,
+    Message,
+    LiveChat,
+}
+
+impl ContentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentKind::Comment => "comment",
+            ContentKind::Post => "post",
+            ContentKind::ReelCaption => "reel",
+            ContentKind::Confession => "confession",
+            ContentKind::ConfessionComment => "confession_comment",
+            ContentKind::Message => "message",
+            ContentKind::LiveChat => "live_chat",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub name: String,
+    pub category: String,
+    pub score: f64,
+    pub regex: Regex,
+}
+
+/// Compiles a rule pattern the way the classifier will run it (case-insensitive, size-capped).
+pub fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).size_limit(1 << 20).build()
+}
+
+/// Active rules with valid patterns; broken patterns are logged and skipped.
+pub fn compile_rules(rules: &[SpamDetectionRule]) -> Vec<CompiledRule> {
+    rules
+        .iter()
+        .filter(|r| r.is_active)
+        .filter_map(|r| match compile_pattern(&r.pattern) {
+            Ok(regex) => Some(CompiledRule { name: r.name.clone(), category: r.category.clone(), score: r.score, regex }),
+            Err(e) => {
+                tracing::warn!("Skipping spam rule '{}': {}", r.name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Loaded {
+    version: Option<i64>,
+    checked_at: Option<Instant>,
+    rules: Arc<Vec<CompiledRule>>,
+}
+
+/// Per-process cache of compiled rules, kept in `AppState`.
+#[derive(Default)]
+pub struct SpamRules {
+    inner: RwLock<Loaded>,
+}
+
+impl SpamRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn fresh(&self) -> Option<Arc<Vec<CompiledRule>>> {
+        let inner = self.inner.read().ok()?;
+        inner.checked_at.filter(|at| at.elapsed() < RULES_RECHECK)?;
+        Some(inner.rules.clone())
+    }
+
+    fn version(&self) -> Option<i64> {
+        self.inner.read().ok().and_then(|i| i.version)
+    }
+
+    fn touch(&self) -> Arc<Vec<CompiledRule>> {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        inner.checked_at = Some(Instant::now());
+        inner.rules.clone()
+    }
+
+    fn replace(&self, version: i64, rules: Vec<CompiledRule>) -> Arc<Vec<CompiledRule>> {
+        let rules = Arc::new(rules);
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        *inner = Loaded { version: Some(version), checked_at: Some(Instant::now()), rules: rules.clone() };
+        rules
+    }
+
+    fn expire(&self) {
+        if let Ok(mut inner) = self.inner.write() {
+            inner.checked_at = None;
+            inner.version = None;
+        }
+    }
+}
+
+async fn active_rules(state: &Arc<AppState>) -> Arc<Vec<CompiledRule>> {
+    if let Some(rules) = state.spam_rules.fresh() {
+        return rules;
+    }
+    let mut conn = state.redis.clone();
+    let version: i64 = conn.get::<_, Option<i64>>(RULES_VERSION_KEY).await.ok().flatten().unwrap_or(0);
+    if state.spam_rules.version() == Some(version) {
+        return state.spam_rules.touch();
+    }
+
+    let coll = state.mongo.collection::<SpamDetectionRule>("spam_detection_rules");
+    let mut rules = Vec::new();
+    match coll.find(doc! { "is_active": true }, None).await {
+        Ok(mut cursor) => {
+            while let Some(Ok(rule)) = cursor.next().await {
+                rules.push(rule);
+            }
+        }
+        Err(e) => {
+            // Keep the old rules and try again on the next check
+            tracing::error!("Failed to load spam rules: {:?}", e);
+            return state.spam_rules.touch();
+        }
+    }
+    state.spam_rules.replace(version, compile_rules(&rules))
+}
+
+/// Call after creating, editing or deleting a rule.
+pub async fn rules_changed(state: &Arc<AppState>) {
+    let mut conn = state.redis.clone();
+    let _: Result<i64, _> = conn.incr(RULES_VERSION_KEY, 1).await;
+    state.spam_rules.expire();
+}
+
+// --- Scoring ---
+
+#[derive(Debug, Clone, Default)]
+pub struct AuthorSignals {
+    pub account_age_hours: i64,
+    /// Writes (of any kind) by this author in the last ten minutes, including this one
+    pub recent_writes: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpamVerdict {
+    /// 0..=1
+    pub score: f64,
+    pub reasons: Vec<String>,
+}
+
+impl SpamVerdict {
+    pub fn is_spam(&self, threshold: f64) -> bool {
+        self.score >= threshold
+    }
+
+    pub fn summary(&self) -> String {
+        format!("Auto-flagged as spam ({:.2}): {}", self.score, self.reasons.join(", "))
+    }
+}
+
+fn count_links(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|w| {
+            let w = w.to_lowercase();
+            w.starts_with("http://") || w.starts_with("https://") || w.starts_with("www.") || w.contains("wa.me/") || w.contains("t.me/")
+        })
+        .count()
+}
+
+fn longest_char_run(text: &str) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut prev = None;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        run = if prev == Some(c) { run + 1 } else { 1 };
+        prev = Some(c);
+        longest = longest.max(run);
+    }
+    longest
+}
+
+/// Rule matches plus heuristics for link density, repetition and new-account velocity.
+pub fn score_text(text: &str, rules: &[CompiledRule], author: &AuthorSignals) -> SpamVerdict {
+    let mut verdict = SpamVerdict::default();
+    let mut add = |score: f64, reason: String| {
+        verdict.score += score;
+        verdict.reasons.push(reason);
+    };
+
+    for rule in rules.iter().filter(|r| r.regex.is_match(text)) {
+        add(rule.score, format!("rule:{} ({})", rule.name, rule.category));
+    }
+
+    let words: Vec<String> = text.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let links = count_links(text);
+    if links > 0 {
+        let density = links as f64 / words.len().max(1) as f64;
+        if density >= 0.25 {
+            add(0.4, format!("link density {:.0}%", density * 100.0));
+        } else if links >= 3 {
+            add(0.25, format!("{} links", links));
+        }
+    }
+
+    if words.len() >= 6 {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for w in &words {
+            *counts.entry(w.as_str()).or_default() += 1;
+        }
+        let top = counts.values().copied().max().unwrap_or(0);
+        if top as f64 / words.len() as f64 >= 0.5 {
+            add(0.3, "repeated words".to_string());
+        }
+    }
+    if longest_char_run(text) >= 12 {
+        add(0.2, "repeated characters".to_string());
+    }
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() >= 20 && letters.iter().filter(|c| c.is_uppercase()).count() * 10 >= letters.len() * 9 {
+        add(0.1, "all caps".to_string());
+    }
+
+    if author.account_age_hours < NEW_ACCOUNT_HOURS && author.recent_writes > 5 {
+        add(0.35, format!("new account posting fast ({} in 10m)", author.recent_writes));
+    } else if author.recent_writes > 20 {
+        add(0.3, format!("posting fast ({} in 10m)", author.recent_writes));
+    }
+
+    verdict.score = verdict.score.clamp(0.0, 1.0);
+    verdict
+}
+
+async fn author_signals(state: &Arc<AppState>, author_id: ObjectId) -> AuthorSignals {
+    let key = format!("spam:velocity:{}", author_id.to_hex());
+    let mut conn = state.redis.clone();
+    let recent_writes: i64 = redis::pipe()
+        .incr(&key, 1)
+        .expire(&key, VELOCITY_WINDOW_SECS)
+        .ignore()
+        .query_async::<(i64,)>(&mut conn)
+        .await
+        .map(|(n,)| n)
+        .unwrap_or(0);
+    // ObjectIds carry their creation time, so the user id tells us the account's age
+    let created_ms = author_id.timestamp().timestamp_millis();
+    let account_age_hours = (DateTime::now().timestamp_millis() - created_ms) / 3_600_000;
+    AuthorSignals { account_age_hours, recent_writes }
+}
+
+/// Scores a piece of user content before it's stored.
+pub async fn classify(state: &Arc<AppState>, author_id: ObjectId, kind: ContentKind, text: &str) -> SpamVerdict {
+    let rules = active_rules(state).await;
+    let signals = author_signals(state, author_id).await;
+    let verdict = score_text(text, &rules, &signals);
+    if verdict.score > 0.0 {
+        tracing::debug!("{} by {} scored {:.2}: {:?}", kind.as_str(), author_id, verdict.score, verdict.reasons);
+    }
+    verdict
+}
+
+/// Adds an auto-flagged item to the shared `content_moderation` queue.
+pub async fn queue_for_review(state: &Arc<AppState>, kind: ContentKind, content_id: ObjectId, text: &str, verdict: &SpamVerdict) {
+    let now = DateTime::now();
+    let item = ContentModeration {
+        id: None,
+        content_id,
+        content_type: kind.as_str().to_string(),
+        content_text: text.to_string(),
+        reported_by: None,
+        reported_reason: Some(verdict.summary()),
+        reported_at: Some(now),
+        status: "pending".to_string(),
+        reviewed_by: None,
+        reviewed_at: None,
+        review_notes: None,
+        action_taken: None,
+        created_at: now,
+        updated_at: now,
+    };
+    if let Err(e) = state.mongo.collection::<ContentModeration>("content_moderation").insert_one(item, None).await {
+        tracing::error!("Failed to queue {} {} for review: {:?}", kind.as_str(), content_id, e);
+    }
+}
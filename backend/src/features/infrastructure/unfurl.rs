@@ -0,0 +1,561 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use mongodb::bson::{doc, oid::ObjectId};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::jobs::{self, Job, JobError};
+use crate::features::social::ably::publish_to_ably;
+use crate::features::social::ws::{publish_to_topic, Topic, WsPayload};
+use crate::models::LinkPreview;
+
+// Link unfurling. User-supplied URLs are fetched from inside our network, so every hop is
+// checked: http(s) only, usual ports only, and the host must resolve to public addresses
+// only. The connection is pinned to the address we checked, so a second DNS answer can't
+// point it somewhere else. Redirects and body size are capped, and results (misses included)
+// are cached in Redis. YouTube and TikTok are asked through oEmbed, everything else is read
+// from OpenGraph / Twitter card tags.
+
+const MAX_REDIRECTS: usize = 3;
+/// Enough for any <head>; the rest of the page is never read.
+const MAX_BODY_BYTES: usize = 512 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+const CACHE_TTL_SECS: u64 = 24 * 3600;
+const MISS_CACHE_TTL_SECS: u64 = 3600;
+const ALLOWED_PORTS: [u16; 4] = [80, 443, 8080, 8443];
+const USER_AGENT: &str = "Mozilla/5.0 (compatible; KaruTeensBot/1.0; +https://karuteens.site)";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnfurlError {
+    InvalidUrl(String),
+    Blocked(String),
+    Fetch(String),
+    NotHtml,
+}
+
+impl std::fmt::Display for UnfurlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnfurlError::InvalidUrl(e) => write!(f, "invalid URL: {}", e),
+            UnfurlError::Blocked(e) => write!(f, "blocked: {}", e),
+            UnfurlError::Fetch(e) => write!(f, "fetch failed: {}", e),
+            UnfurlError::NotHtml => write!(f, "not an HTML page"),
+        }
+    }
+}
+
+// --- Address checks ---
+
+/// Whether an address is on the public internet. Private, loopback, link-local, shared
+/// (CGNAT), documentation, multicast and reserved ranges are all refused, including IPv4
+/// addresses smuggled inside IPv6 (mapped, compatible and NAT64).
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_public_v4(v4),
+        IpAddr::V6(v6) => is_public_v6(v6),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)) // shared address space
+        || (a == 192 && b == 0 && c == 0) // IETF protocol assignments
+        || (a == 198 && (b == 18 || b == 19)) // benchmarking
+        || a >= 240) // reserved
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_v4(v4);
+    }
+    // 64:ff9b::/96 (NAT64) and the deprecated ::a.b.c.d form both reach IPv4 hosts
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] || (segments[..6] == [0; 6] && !ip.is_loopback() && !ip.is_unspecified()) {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || (segments[0] & 0xfe00) == 0xfc00 // unique local
+        || (segments[0] & 0xffc0) == 0xfe80 // link-local
+        || (segments[0] & 0xffc0) == 0xfec0 // site-local
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)) // documentation
+}
+
+/// Everything about a URL that can be checked before resolving it.
+pub fn check_url(url: &Url) -> Result<(), UnfurlError> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(UnfurlError::InvalidUrl("only http and https links are unfurled".to_string()));
+    }
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err(UnfurlError::Blocked("credentials in URL".to_string()));
+    }
+    let port = url.port_or_known_default().unwrap_or(0);
+    if !ALLOWED_PORTS.contains(&port) {
+        return Err(UnfurlError::Blocked(format!("port {}", port)));
+    }
+    let host = url.host_str().ok_or_else(|| UnfurlError::InvalidUrl("no host".to_string()))?;
+    match host_ip(host) {
+        Some(ip) if !is_public_ip(ip) => Err(UnfurlError::Blocked(ip.to_string())),
+        Some(_) => Ok(()),
+        None => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            let internal = host == "localhost"
+                || [".localhost", ".local", ".internal", ".lan", ".home.arpa"].iter().any(|s| host.ends_with(s))
+                || !host.contains('.');
+            if internal { Err(UnfurlError::Blocked(host)) } else { Ok(()) }
+        }
+    }
+}
+
+/// The address of an IP-literal host (IPv6 comes bracketed), or None for a domain name.
+fn host_ip(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// Resolves the host and returns an address to connect to, refusing hosts where any answer
+/// is private (a mixed answer is how rebinding tricks usually look).
+async fn resolve_public(url: &Url) -> Result<Option<SocketAddr>, UnfurlError> {
+    let Some(host) = url.host_str().filter(|h| host_ip(h).is_none()) else {
+        // IP literals were checked by check_url and need no resolving
+        return Ok(None);
+    };
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await
+        .map_err(|e| UnfurlError::Fetch(format!("DNS: {}", e)))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(UnfurlError::Fetch("DNS: no addresses".to_string()));
+    }
+    if let Some(private) = addrs.iter().find(|a| !is_public_ip(a.ip())) {
+        return Err(UnfurlError::Blocked(format!("{} resolves to {}", host, private.ip())));
+    }
+    Ok(Some(addrs[0]))
+}
+
+// --- Fetching ---
+
+struct Fetched {
+    url: Url,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+/// GETs a URL, re-checking every redirect hop, and reads at most MAX_BODY_BYTES.
+async fn fetch(url: Url, accept: &str) -> Result<Fetched, UnfurlError> {
+    let mut url = url;
+    for _ in 0..=MAX_REDIRECTS {
+        check_url(&url)?;
+        let pinned = resolve_public(&url).await?;
+
+        let mut builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .no_proxy()
+            .timeout(FETCH_TIMEOUT)
+            .connect_timeout(Duration::from_secs(3))
+            .user_agent(USER_AGENT);
+        if let (Some(addr), Some(host)) = (pinned, url.host_str()) {
+            builder = builder.resolve(host, addr);
+        }
+        let client = builder.build().map_err(|e| UnfurlError::Fetch(e.to_string()))?;
+
+        let mut res = client.get(url.clone()).header("Accept", accept).send().await
+            .map_err(|e| UnfurlError::Fetch(e.to_string()))?;
+
+        if res.status().is_redirection() {
+            let location = res.headers().get(reqwest::header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .ok_or_else(|| UnfurlError::Fetch("redirect without Location".to_string()))?;
+            url = url.join(location).map_err(|e| UnfurlError::InvalidUrl(e.to_string()))?;
+            continue;
+        }
+        if !res.status().is_success() {
+            return Err(UnfurlError::Fetch(format!("HTTP {}", res.status())));
+        }
+
+        let content_type = res.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|c| c.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let mut body = Vec::new();
+        while let Some(chunk) = res.chunk().await.map_err(|e| UnfurlError::Fetch(e.to_string()))? {
+            let room = MAX_BODY_BYTES - body.len();
+            body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            if body.len() >= MAX_BODY_BYTES {
+                break;
+            }
+        }
+        return Ok(Fetched { url, content_type, body });
+    }
+    Err(UnfurlError::Fetch(format!("more than {} redirects", MAX_REDIRECTS)))
+}
+
+// --- HTML ---
+
+/// The head metadata we care about, in document order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PageMeta {
+    pub title: Option<String>,
+    pub meta: Vec<(String, String)>, // (property or name, lowercased; content)
+}
+
+impl PageMeta {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.meta.iter().find(|(k, v)| k == key && !v.trim().is_empty()).map(|(_, v)| v.trim())
+    }
+
+    fn first(&self, keys: &[&str]) -> Option<String> {
+        keys.iter().find_map(|k| self.get(k)).map(str::to_string)
+    }
+}
+
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.bytes().take(12).position(|b| b == b';') else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Parses a tag's attributes: any order, double, single or no quotes, valueless attributes.
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let bytes = tag.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        let start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'=' && bytes[i] != b'/' {
+            i += 1;
+        }
+        if start == i {
+            break;
+        }
+        let name = tag[start..i].to_ascii_lowercase();
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] != b'=' {
+            attrs.push((name, String::new()));
+            continue;
+        }
+        i += 1;
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let value = if i < bytes.len() && (bytes[i] == b'"' || bytes[i] == b'\'') {
+            let quote = bytes[i];
+            let value_start = i + 1;
+            let end = tag[value_start..].bytes().position(|b| b == quote).map(|p| value_start + p).unwrap_or(bytes.len());
+            i = (end + 1).min(bytes.len());
+            &tag[value_start..end]
+        } else {
+            let value_start = i;
+            while i < bytes.len() && !bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            &tag[value_start..i]
+        };
+        attrs.push((name, decode_entities(value)));
+    }
+    attrs
+}
+
+/// Walks the tags of a page, skipping comments, scripts and styles, and collects <title> and
+/// <meta property|name=... content=...>.
+pub fn parse_html(html: &str) -> PageMeta {
+    let mut page = PageMeta::default();
+    let lower = html.to_ascii_lowercase();
+    let mut pos = 0;
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        if lower[start..].starts_with("<!--") {
+            pos = lower[start..].find("-->").map(|e| start + e + 3).unwrap_or(html.len());
+            continue;
+        }
+        let Some(end) = html[start..].find('>').map(|e| start + e) else { break };
+        let inner = &html[start + 1..end];
+        let name_len = inner.find(|c: char| c.is_ascii_whitespace() || c == '/').unwrap_or(inner.len());
+        let name = inner[..name_len].to_ascii_lowercase();
+        pos = end + 1;
+
+        match name.as_str() {
+            "script" | "style" | "title" => {
+                let close = format!("</{}", name);
+                let Some(close_at) = lower[pos..].find(&close).map(|c| pos + c) else { break };
+                if name == "title" && page.title.is_none() {
+                    let text = decode_entities(&html[pos..close_at]);
+                    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if !text.is_empty() {
+                        page.title = Some(text);
+                    }
+                }
+                pos = close_at;
+            }
+            "meta" => {
+                let attrs = parse_attributes(&inner[name_len..]);
+                let attr = |n: &str| attrs.iter().find(|(k, _)| k == n).map(|(_, v)| v.clone());
+                if let (Some(key), Some(content)) = (attr("property").or_else(|| attr("name")), attr("content")) {
+                    page.meta.push((key.to_ascii_lowercase(), content));
+                }
+            }
+            // Nothing we read lives past the head
+            "body" => break,
+            _ => {}
+        }
+    }
+    page
+}
+
+fn absolute(base: &Url, value: Option<String>) -> Option<String> {
+    let joined = base.join(&value?).ok()?;
+    matches!(joined.scheme(), "http" | "https").then(|| joined.to_string())
+}
+
+fn clip(value: Option<String>, max_chars: usize) -> Option<String> {
+    value.map(|v| v.chars().take(max_chars).collect())
+}
+
+/// Builds a preview from a page's OpenGraph and Twitter card tags, falling back to <title>
+/// and the plain description.
+pub fn preview_from_html(url: &Url, html: &str) -> LinkPreview {
+    let page = parse_html(html);
+    LinkPreview {
+        url: url.to_string(),
+        title: clip(page.first(&["og:title", "twitter:title"]).or(page.title.clone()), 300),
+        description: clip(page.first(&["og:description", "twitter:description", "description"]), 1000),
+        image: absolute(url, page.first(&["og:image:secure_url", "og:image", "og:image:url", "twitter:image", "twitter:image:src"])),
+        site_name: clip(page.first(&["og:site_name", "application-name"]), 100),
+        author_name: clip(page.first(&["author", "article:author", "twitter:creator"]), 100),
+        media_type: page.first(&["og:type"]),
+    }
+}
+
+// --- oEmbed ---
+
+/// The oEmbed endpoint for providers we embed, or None to read the page itself.
+pub fn oembed_endpoint(url: &Url) -> Option<String> {
+    let host = url.host_str()?.trim_start_matches("www.").trim_start_matches("m.");
+    let endpoint = match host {
+        "youtube.com" | "youtu.be" | "music.youtube.com" => "https://www.youtube.com/oembed",
+        "tiktok.com" | "vm.tiktok.com" => "https://www.tiktok.com/oembed",
+        _ => return None,
+    };
+    let mut endpoint = Url::parse(endpoint).ok()?;
+    endpoint.query_pairs_mut().append_pair("url", url.as_str()).append_pair("format", "json");
+    Some(endpoint.to_string())
+}
+
+#[derive(Debug, Deserialize)]
+struct OEmbed {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    title: Option<String>,
+    author_name: Option<String>,
+    provider_name: Option<String>,
+    thumbnail_url: Option<String>,
+}
+
+async fn unfurl_oembed(url: &Url, endpoint: &str) -> Result<LinkPreview, UnfurlError> {
+    let endpoint = Url::parse(endpoint).map_err(|e| UnfurlError::InvalidUrl(e.to_string()))?;
+    let fetched = fetch(endpoint, "application/json").await?;
+    let oembed: OEmbed = serde_json::from_slice(&fetched.body).map_err(|e| UnfurlError::Fetch(e.to_string()))?;
+    Ok(LinkPreview {
+        url: url.to_string(),
+        title: clip(oembed.title, 300),
+        description: None,
+        image: oembed.thumbnail_url.and_then(|t| absolute(url, Some(t))),
+        site_name: oembed.provider_name,
+        author_name: clip(oembed.author_name, 100),
+        media_type: oembed.kind,
+    })
+}
+
+// --- Public API ---
+
+async fn unfurl_uncached(url: Url) -> Result<LinkPreview, UnfurlError> {
+    if let Some(endpoint) = oembed_endpoint(&url) {
+        match unfurl_oembed(&url, &endpoint).await {
+            Ok(preview) => return Ok(preview),
+            Err(e) => tracing::debug!("oEmbed failed for {}, reading the page: {}", url, e),
+        }
+    }
+    let fetched = fetch(url, "text/html,application/xhtml+xml").await?;
+    if !fetched.content_type.is_empty() && !fetched.content_type.contains("html") {
+        return Err(UnfurlError::NotHtml);
+    }
+    Ok(preview_from_html(&fetched.url, &String::from_utf8_lossy(&fetched.body)))
+}
+
+fn cache_key(url: &str) -> String {
+    format!("unfurl:v1:{}", hex::encode(Sha256::digest(url.as_bytes())))
+}
+
+/// Unfurls a URL through the cache. Failures are cached briefly too, so a dead or blocked
+/// link isn't fetched again for every message that repeats it.
+pub async fn unfurl(state: &Arc<AppState>, raw_url: &str) -> Result<Option<LinkPreview>, UnfurlError> {
+    let url = Url::parse(raw_url.trim()).map_err(|e| UnfurlError::InvalidUrl(e.to_string()))?;
+    check_url(&url)?;
+
+    let key = cache_key(url.as_str());
+    if let Some(cached) = state.cache.get::<Option<LinkPreview>>(&key).await {
+        return Ok(cached);
+    }
+
+    match unfurl_uncached(url.clone()).await {
+        Ok(preview) => {
+            let preview = (preview.title.is_some() || preview.description.is_some() || preview.image.is_some()).then_some(preview);
+            state.cache.set(&key, &preview, CACHE_TTL_SECS).await;
+            Ok(preview)
+        }
+        Err(e) => {
+            tracing::debug!("Unfurl of {} failed: {}", url, e);
+            state.cache.set(&key, &None::<LinkPreview>, MISS_CACHE_TTL_SECS).await;
+            Err(e)
+        }
+    }
+}
+
+/// The first http(s) link in a piece of text, without trailing punctuation.
+pub fn first_url(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .filter_map(|word| {
+            let start = word.find("https://").or_else(|| word.find("http://"))?;
+            let mut url = &word[start..];
+            loop {
+                let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"', ']', '}', '>']);
+                // Keep a closing paren that belongs to the URL, as in Wikipedia links
+                let trimmed = if trimmed.ends_with(')') && trimmed.matches('(').count() < trimmed.matches(')').count() {
+                    &trimmed[..trimmed.len() - 1]
+                } else {
+                    trimmed
+                };
+                if trimmed == url {
+                    break;
+                }
+                url = trimmed;
+            }
+            Url::parse(url).ok().filter(|u| u.host_str().is_some()).map(|_| url.to_string())
+        })
+        .next()
+}
+
+// --- Attaching to posts and messages ---
+
+/// Unfurls the link in a post or message in the background and stores it on the document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnfurlJob {
+    pub collection: String, // "posts" or "messages"
+    pub id: String,
+    pub url: String,
+}
+
+impl Job for UnfurlJob {
+    const KIND: &'static str = "unfurl.attach";
+    const MAX_ATTEMPTS: u32 = 2;
+    const TIMEOUT_SECS: u64 = 60;
+}
+
+/// Queues an unfurl for the first link in `text`, if there is one.
+pub async fn attach_later(state: &Arc<AppState>, collection: &str, id: ObjectId, text: &str) {
+    let Some(url) = first_url(text) else { return };
+    let job = UnfurlJob { collection: collection.to_string(), id: id.to_hex(), url };
+    if let Err(e) = jobs::enqueue(state, job).await {
+        tracing::warn!("Failed to queue unfurl for {} {}: {:?}", collection, id.to_hex(), e);
+    }
+}
+
+/// Matches the document only while it still holds the text the preview was made from and
+/// hasn't been deleted, expired or opened (view-once messages are wiped once seen).
+pub fn preview_filter(id: ObjectId, content: &str) -> bson::Document {
+    doc! { "_id": id, "content": content, "is_deleted": { "$ne": true }, "viewed_at": null }
+}
+
+/// Whether the job's link is still the one in the document; an edit may have changed it.
+pub fn still_links_to(document: &bson::Document, url: &str) -> bool {
+    let content = document.get_str("content").unwrap_or_default();
+    let title = document.get_str("title").unwrap_or_default();
+    first_url(content).as_deref() == Some(url) || first_url(&format!("{}\n{}", title, content)).as_deref() == Some(url)
+}
+
+pub async fn run_unfurl(state: Arc<AppState>, job: UnfurlJob) -> Result<(), JobError> {
+    if job.collection != "posts" && job.collection != "messages" {
+        return Err(JobError::Fatal(format!("can't unfurl into {}", job.collection)));
+    }
+    let id = ObjectId::parse_str(&job.id).map_err(|e| JobError::Fatal(e.to_string()))?;
+
+    let preview = match unfurl(&state, &job.url).await {
+        Ok(Some(preview)) => preview,
+        // Nothing to show, or a link we won't fetch; retrying doesn't change that
+        Ok(None) | Err(_) => return Ok(()),
+    };
+    let stored = bson::to_bson(&preview).map_err(|e| JobError::Fatal(e.to_string()))?;
+    let collection = state.mongo.collection::<bson::Document>(&job.collection);
+    let Some(current) = collection.find_one(doc! { "_id": id }, None).await? else { return Ok(()) };
+    if !still_links_to(&current, &job.url) {
+        return Ok(());
+    }
+    let content = current.get_str("content").unwrap_or_default();
+    let updated = collection
+        .update_one(preview_filter(id, content), doc! { "$set": { "link_preview": stored } }, None)
+        .await?;
+    // Edited, deleted or wiped while we were fetching
+    if updated.matched_count == 0 {
+        return Ok(());
+    }
+
+    if job.collection == "messages" {
+        if let Ok(Some(msg)) = state.mongo.collection::<crate::models::Message>("messages").find_one(doc! { "_id": id }, None).await {
+            let data = json!({ "id": job.id, "link_preview": preview });
+            publish_to_ably(&format!("chat:{}", msg.chat_id.to_hex()), "link_preview", data.clone()).await;
+            publish_to_topic(&state, &Topic::Chat(msg.chat_id), &WsPayload { r#type: "link_preview".to_string(), data }).await;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,441 @@
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use base64::{Engine as _, engine::general_purpose};
+use chrono::Utc;
+use dashmap::DashMap;
+use crate::features::monetization::provider::{
+    CallbackEvent, PaymentError, PaymentProvider, PaymentRequest, PaymentResult, PaymentStatus, PayoutRequest, RefundRequest, RefundStatus,
+};
+
+// Safaricom Daraja: STK push for payments, STK query for status, transaction reversal for
+// refunds and B2C for payouts. Credentials and shortcodes come from the MPESA_* variables, read per request.
+
+// --- DTOs ---
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MpesaCallback {
+    #[serde(rename = "Body")]
+    pub body: StkCallbackBody,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StkCallbackBody {
+    #[serde(rename = "stkCallback")]
+    pub stk_callback: StkCallback,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StkCallback {
+    #[serde(rename = "MerchantRequestID")]
+    pub merchant_request_id: String,
+    #[serde(rename = "CheckoutRequestID")]
+    pub checkout_request_id: String,
+    #[serde(rename = "ResultCode")]
+    pub result_code: i32,
+    #[serde(rename = "ResultDesc")]
+    pub result_desc: String,
+    #[serde(rename = "CallbackMetadata")]
+    pub callback_metadata: Option<CallbackMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CallbackMetadata {
+    #[serde(rename = "Item")]
+    pub item: Vec<MetadataItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MetadataItem {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Value")]
+    pub value: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StkQueryResponse {
+    #[serde(rename = "ResponseCode")]
+    pub response_code: String,
+    #[serde(rename = "ResponseDescription")]
+    pub response_description: String,
+    #[serde(rename = "MerchantRequestID")]
+    pub merchant_request_id: String,
+    #[serde(rename = "CheckoutRequestID")]
+    pub checkout_request_id: String,
+    #[serde(rename = "ResultCode")]
+    pub result_code: Option<String>,
+    #[serde(rename = "ResultDesc")]
+    pub result_desc: Option<String>,
+}
+
+/// Async results for requests that go through the initiator APIs (reversals, B2C).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InitiatorResultCallback {
+    #[serde(rename = "Result")]
+    pub result: InitiatorResult,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InitiatorResult {
+    #[serde(rename = "ResultCode")]
+    pub result_code: i64,
+    #[serde(rename = "ResultDesc", default)]
+    pub result_desc: String,
+    #[serde(rename = "ConversationID", default)]
+    pub conversation_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct B2cRequest {
+    #[serde(rename = "OriginatorConversationID")]
+    pub originator_conversation_id: String,
+    #[serde(rename = "InitiatorName")]
+    pub initiator_name: String,
+    #[serde(rename = "SecurityCredential")]
+    pub security_credential: String,
+    #[serde(rename = "CommandID")]
+    pub command_id: String,
+    #[serde(rename = "Amount")]
+    pub amount: i64, // Whole KES
+    #[serde(rename = "PartyA")]
+    pub party_a: String,
+    #[serde(rename = "PartyB")]
+    pub party_b: String,
+    #[serde(rename = "Remarks")]
+    pub remarks: String,
+    #[serde(rename = "QueueTimeOutURL")]
+    pub queue_timeout_url: String,
+    #[serde(rename = "ResultURL")]
+    pub result_url: String,
+    #[serde(rename = "Occassion")]
+    pub occasion: String,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct B2cAccepted {
+    #[serde(rename = "ConversationID")]
+    pub conversation_id: String,
+    #[serde(rename = "OriginatorConversationID")]
+    pub originator_conversation_id: String,
+    #[serde(rename = "ResponseCode")]
+    pub response_code: String,
+    #[serde(rename = "ResponseDescription")]
+    pub response_description: String,
+}
+
+// --- OAuth ---
+
+#[derive(Deserialize)]
+struct MpesaAuthResponse {
+    access_token: String,
+}
+
+/// Daraja host for the current `MPESA_ENV`. `MPESA_API_BASE` overrides it (e.g. a local mock).
+pub fn daraja_base_url() -> String {
+    if let Ok(base) = std::env::var("MPESA_API_BASE") {
+        if !base.trim().is_empty() {
+            return base.trim().trim_end_matches('/').to_string();
+        }
+    }
+    let mpesa_env = std::env::var("MPESA_ENV").unwrap_or_else(|_| "sandbox".to_string()).trim().to_lowercase();
+    if mpesa_env == "live" {
+        "https://api.safaricom.co.ke".to_string()
+    } else {
+        "https://sandbox.safaricom.co.ke".to_string()
+    }
+}
+
+/// Fetches a fresh OAuth token from `base` (no caching).
+pub async fn request_mpesa_token(client: &reqwest::Client, base: &str, consumer_key: &str, consumer_secret: &str) -> Result<String, String> {
+    let auth = general_purpose::STANDARD.encode(format!("{}:{}", consumer_key, consumer_secret));
+    let url = format!("{}/oauth/v1/generate?grant_type=client_credentials", base);
+
+    tracing::trace!("Requesting new M-Pesa token from {}", url);
+
+    let res = client.get(&url)
+        .header("Authorization", format!("Basic {}", auth))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !res.status().is_success() {
+        let err_text = res.text().await.unwrap_or_default();
+        return Err(format!("M-Pesa OAuth failed: {}", err_text));
+    }
+
+    let data: MpesaAuthResponse = res.json().await.map_err(|e| e.to_string())?;
+    Ok(data.access_token)
+}
+
+/// OAuth token for the current Daraja host, cached per host.
+pub async fn cached_token(client: &reqwest::Client, tokens: &DashMap<String, (String, i64)>) -> Result<String, String> {
+    // Check cache first
+    let base = daraja_base_url();
+    if let Some(entry) = tokens.get(&base) {
+        let (token, expires_at) = entry.value();
+        if Utc::now().timestamp_millis() < *expires_at {
+            return Ok(token.clone());
+        }
+    }
+
+    let consumer_key = std::env::var("MPESA_CONSUMER_KEY").map_err(|_| "Missing MPESA_CONSUMER_KEY")?.trim().to_string();
+    let consumer_secret = std::env::var("MPESA_CONSUMER_SECRET").map_err(|_| "Missing MPESA_CONSUMER_SECRET")?.trim().to_string();
+
+    let token = request_mpesa_token(client, &base, &consumer_key, &consumer_secret).await?;
+
+    // Cache the token (standard expiry is 3600s, we use 3000s for safety)
+    let expires_at = Utc::now().timestamp_millis() + 3000 * 1000;
+    tokens.insert(base, (token.clone(), expires_at));
+
+    Ok(token)
+}
+
+/// Initiator name and encrypted credential for the B2C and reversal APIs.
+pub fn initiator() -> (String, String) {
+    (
+        std::env::var("MPESA_B2C_INITIATOR_NAME").unwrap_or_else(|_| "testapi".to_string()),
+        std::env::var("MPESA_B2C_SECURITY_CREDENTIAL").unwrap_or_default(),
+    )
+}
+
+/// Reads an STK push callback.
+pub fn parse_stk_callback(callback: StkCallback) -> PaymentResult {
+    let mut receipt = None;
+    let mut amount = None;
+    if let Some(meta) = callback.callback_metadata {
+        for item in meta.item {
+            match item.name.as_str() {
+                "MpesaReceiptNumber" => receipt = item.value.and_then(|v| v.as_str().map(|s| s.to_string())),
+                "Amount" => amount = item.value.and_then(|v| v.as_f64()).map(|kes| (kes * 100.0).round() as i64),
+                _ => {}
+            }
+        }
+    }
+    let status = if callback.result_code == 0 {
+        PaymentStatus::Completed { receipt }
+    } else {
+        PaymentStatus::Failed { reason: callback.result_desc.clone() }
+    };
+    PaymentResult {
+        reference: callback.checkout_request_id,
+        status,
+        result_code: callback.result_code,
+        result_desc: callback.result_desc,
+        amount,
+    }
+}
+
+/// Maps an STK query response: a result code means the payer finished on their phone.
+pub fn stk_query_status(data: StkQueryResponse) -> PaymentStatus {
+    match data.result_code.as_deref() {
+        Some("0") => PaymentStatus::Completed { receipt: None },
+        // Still processing
+        Some("4999") => PaymentStatus::Pending,
+        Some(_) => PaymentStatus::Failed { reason: data.result_desc.unwrap_or_else(|| "Unknown error".to_string()) },
+        // Accepted but the payer hasn't answered yet
+        None if data.response_code == "0" => PaymentStatus::Pending,
+        None => PaymentStatus::Failed { reason: data.response_description },
+    }
+}
+
+/// Daraja's form of a payout; the shortcode, initiator and command come from MPESA_B2C_*.
+pub fn b2c_request(request: &PayoutRequest) -> B2cRequest {
+    let shortcode = std::env::var("MPESA_B2C_SHORTCODE")
+        .or_else(|_| std::env::var("MPESA_BUSINESS_SHORTCODE"))
+        .unwrap_or_else(|_| "600000".to_string());
+    let (initiator_name, security_credential) = initiator();
+    B2cRequest {
+        originator_conversation_id: request.reference.clone(),
+        initiator_name,
+        security_credential,
+        command_id: std::env::var("MPESA_B2C_COMMAND").unwrap_or_else(|_| "BusinessPayment".to_string()),
+        amount: request.amount_kes,
+        party_a: shortcode,
+        party_b: request.phone.clone(),
+        remarks: request.remarks.clone(),
+        queue_timeout_url: request.timeout_url.clone(),
+        result_url: request.result_url.clone(),
+        occasion: "Payout".to_string(),
+    }
+}
+
+/// Submits a B2C payment. Daraja only queues it here; the outcome arrives on the result URL.
+/// Anything that may have reached Daraja without a clear answer is `Indeterminate`.
+pub async fn send_b2c(client: &reqwest::Client, base: &str, token: &str, request: &B2cRequest) -> Result<B2cAccepted, PaymentError> {
+    let res = match client
+        .post(format!("{}/mpesa/b2c/v1/paymentrequest", base))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(request)
+        .send()
+        .await
+    {
+        Ok(res) => res,
+        Err(e) if e.is_connect() || e.is_builder() => return Err(PaymentError::Unavailable(e.to_string())),
+        Err(e) => return Err(PaymentError::Indeterminate(e.to_string())),
+    };
+    let status = res.status();
+    let body: serde_json::Value = match res.json().await {
+        Ok(body) => body,
+        Err(e) if status.is_client_error() => return Err(PaymentError::Rejected(e.to_string())),
+        Err(e) => return Err(PaymentError::Indeterminate(format!("{} ({})", e, status))),
+    };
+    match serde_json::from_value::<B2cAccepted>(body.clone()) {
+        Ok(accepted) if status.is_success() && accepted.response_code == "0" => Ok(accepted),
+        Ok(rejected) => Err(PaymentError::Rejected(rejected.response_description)),
+        Err(_) if status.is_server_error() => Err(PaymentError::Indeterminate(format!("Daraja answered {}", status))),
+        Err(_) => Err(PaymentError::Rejected(body["errorMessage"].as_str().unwrap_or("B2C request rejected").to_string())),
+    }
+}
+
+
+pub struct DarajaProvider {
+    client: reqwest::Client,
+    tokens: Arc<DashMap<String, (String, i64)>>,
+}
+
+impl DarajaProvider {
+    pub fn new(client: reqwest::Client, tokens: Arc<DashMap<String, (String, i64)>>) -> Self {
+        DarajaProvider { client, tokens }
+    }
+
+    async fn token(&self) -> Result<String, PaymentError> {
+        cached_token(&self.client, &self.tokens).await.map_err(PaymentError::Unavailable)
+    }
+
+    fn shortcode() -> String {
+        std::env::var("MPESA_BUSINESS_SHORTCODE").unwrap_or_else(|_| "174379".to_string())
+    }
+
+    fn password(shortcode: &str) -> (String, String) {
+        let passkey = std::env::var("MPESA_PASSKEY").unwrap_or_default();
+        let timestamp = Utc::now().format("%Y%m%d%H%M%S").to_string();
+        let password = general_purpose::STANDARD.encode(format!("{}{}{}", shortcode, passkey, timestamp));
+        (password, timestamp)
+    }
+
+    async fn post(&self, path: &str, body: &serde_json::Value) -> Result<serde_json::Value, PaymentError> {
+        let token = self.token().await?;
+        let res = self.client.post(format!("{}{}", daraja_base_url(), path))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| PaymentError::Unavailable(e.to_string()))?;
+        res.json().await.map_err(|e| PaymentError::Unavailable(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for DarajaProvider {
+    fn name(&self) -> &'static str {
+        "mpesa"
+    }
+
+    async fn initiate(&self, request: &PaymentRequest) -> Result<String, PaymentError> {
+        let shortcode = Self::shortcode();
+        let (password, timestamp) = Self::password(&shortcode);
+        let transaction_type = std::env::var("MPESA_TRANSACTION_TYPE").unwrap_or_else(|_| "CustomerPayBillOnline".to_string());
+        let party_b = std::env::var("MPESA_PARTYB").unwrap_or_else(|_| shortcode.clone());
+
+        tracing::info!("Initiating STK Push for {} (Amount: {})", request.phone, request.amount_kes);
+
+        let mpesa_data = self.post("/mpesa/stkpush/v1/processrequest", &json!({
+            "BusinessShortCode": shortcode,
+            "Password": password,
+            "Timestamp": timestamp,
+            "TransactionType": transaction_type,
+            "Amount": request.amount_kes,
+            "PartyA": request.phone,
+            "PartyB": party_b,
+            "PhoneNumber": request.phone,
+            "CallBackURL": request.callback_url,
+            "AccountReference": "KaruTeens",
+            "TransactionDesc": request.description
+        })).await?;
+
+        if mpesa_data["ResponseCode"] != "0" {
+            tracing::error!("M-Pesa STK Push error: {:?}", mpesa_data);
+            let reason = mpesa_data["ResponseDescription"].as_str()
+                .or_else(|| mpesa_data["errorMessage"].as_str())
+                .unwrap_or("M-Pesa rejected the request");
+            return Err(PaymentError::Rejected(reason.to_string()));
+        }
+
+        mpesa_data["CheckoutRequestID"].as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| PaymentError::Unavailable("M-Pesa did not return a checkout ID".to_string()))
+    }
+
+    async fn query_status(&self, reference: &str) -> Result<PaymentStatus, PaymentError> {
+        let shortcode = std::env::var("MPESA_BUSINESS_SHORTCODE")
+            .map_err(|_| PaymentError::Unavailable("Missing MPESA_BUSINESS_SHORTCODE".to_string()))?;
+        let (password, timestamp) = Self::password(&shortcode);
+
+        tracing::trace!("Pinging Safaricom for {}", reference);
+
+        let body = self.post("/mpesa/stkpushquery/v1/query", &json!({
+            "BusinessShortCode": shortcode,
+            "Password": password,
+            "Timestamp": timestamp,
+            "CheckoutRequestID": reference
+        })).await?;
+        tracing::trace!("Safaricom Response for {}: {:?}", reference, body);
+
+        let data: StkQueryResponse = serde_json::from_value(body)
+            .map_err(|e| PaymentError::Unavailable(format!("Unexpected STK query response: {}", e)))?;
+        Ok(stk_query_status(data))
+    }
+
+    fn parse_callback(&self, body: &serde_json::Value) -> Result<CallbackEvent, PaymentError> {
+        if let Ok(callback) = serde_json::from_value::<MpesaCallback>(body.clone()) {
+            return Ok(CallbackEvent::Payment(parse_stk_callback(callback.body.stk_callback)));
+        }
+        if let Ok(callback) = serde_json::from_value::<InitiatorResultCallback>(body.clone()) {
+            let result = callback.result;
+            return Ok(CallbackEvent::Refund {
+                reference: result.conversation_id,
+                succeeded: result.result_code == 0,
+                reason: result.result_desc,
+            });
+        }
+        Err(PaymentError::InvalidCallback("Not an M-Pesa callback".to_string()))
+    }
+
+    async fn refund(&self, request: &RefundRequest) -> Result<RefundStatus, PaymentError> {
+        let receipt = request.receipt.as_deref()
+            .ok_or_else(|| PaymentError::Rejected("No M-Pesa receipt to reverse".to_string()))?;
+        let (initiator, credential) = initiator();
+
+        let data = self.post("/mpesa/reversal/v1/request", &json!({
+            "Initiator": initiator,
+            "SecurityCredential": credential,
+            "CommandID": "TransactionReversal",
+            "TransactionID": receipt,
+            "Amount": request.amount_kes,
+            "ReceiverParty": Self::shortcode(),
+            "RecieverIdentifierType": "11",
+            "ResultURL": request.callback_url,
+            "QueueTimeOutURL": request.callback_url,
+            "Remarks": request.reason,
+            "Occasion": "Refund"
+        })).await?;
+
+        if data["ResponseCode"] != "0" {
+            let reason = data["ResponseDescription"].as_str()
+                .or_else(|| data["errorMessage"].as_str())
+                .unwrap_or("M-Pesa rejected the reversal");
+            return Err(PaymentError::Rejected(reason.to_string()));
+        }
+        data["ConversationID"].as_str()
+            .map(|id| RefundStatus::Pending { reference: id.to_string() })
+            .ok_or_else(|| PaymentError::Unavailable("M-Pesa did not return a conversation ID".to_string()))
+    }
+
+    async fn send_b2c(&self, request: &PayoutRequest) -> Result<String, PaymentError> {
+        let token = self.token().await?;
+        let accepted = send_b2c(&self.client, &daraja_base_url(), &token, &b2c_request(request)).await?;
+        Ok(accepted.conversation_id)
+    }
+}
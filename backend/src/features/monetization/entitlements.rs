@@ -0,0 +1,107 @@
+use axum::{http::StatusCode, Json};
+use chrono::{DateTime, Duration, Utc};
+use mongodb::bson::{doc, oid::ObjectId};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use crate::features::infrastructure::db::AppState;
+use crate::features::monetization::subscriptions::grace_period;
+use crate::models::User;
+
+// What a user's premium status unlocks. Features ask here instead of reading
+// `User::is_premium` themselves, so the grace period and admin overrides apply everywhere.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entitlement {
+    /// Creating and joining study rooms.
+    StudyRooms,
+    /// Browsing hookup aliases without paying the alias activation fee.
+    HookupDiscovery,
+    /// No sponsored posts in the feed.
+    AdFree,
+    /// A discount on locked revision materials.
+    MaterialDiscount,
+}
+
+impl Entitlement {
+    pub const ALL: [Entitlement; 4] = [
+        Entitlement::StudyRooms,
+        Entitlement::HookupDiscovery,
+        Entitlement::AdFree,
+        Entitlement::MaterialDiscount,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Entitlements {
+    pub premium: bool,
+    /// Premium has lapsed but is still honoured while the user renews.
+    pub in_grace: bool,
+    pub staff: bool,
+}
+
+/// Percentage off locked materials for premium members (`PREMIUM_MATERIAL_DISCOUNT_PERCENT`, default 20).
+pub fn material_discount_percent() -> i64 {
+    std::env::var("PREMIUM_MATERIAL_DISCOUNT_PERCENT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(20)
+        .clamp(0, 100)
+}
+
+impl Entitlements {
+    pub fn none() -> Self {
+        Entitlements { premium: false, in_grace: false, staff: false }
+    }
+
+    pub fn for_user(user: &User, now: DateTime<Utc>, grace: Duration) -> Self {
+        let staff = matches!(user.role.as_str(), "admin" | "superadmin");
+        let expires_at = user.premium_expires_at.map(|d| d.to_chrono());
+        let (premium, in_grace) = match (user.is_premium, expires_at) {
+            (false, _) => (false, false),
+            (true, None) => (true, false),
+            (true, Some(end)) if now < end => (true, false),
+            (true, Some(end)) => (now < end + grace, now < end + grace),
+        };
+        Entitlements { premium, in_grace, staff }
+    }
+
+    pub fn has(&self, entitlement: Entitlement) -> bool {
+        match entitlement {
+            Entitlement::StudyRooms | Entitlement::HookupDiscovery | Entitlement::AdFree => self.premium || self.staff,
+            Entitlement::MaterialDiscount => self.premium,
+        }
+    }
+
+    /// What this user pays for something listed at `cents`.
+    pub fn material_price(&self, cents: i64, discount_percent: i64) -> i64 {
+        if !self.has(Entitlement::MaterialDiscount) {
+            return cents;
+        }
+        cents - cents * discount_percent.clamp(0, 100) / 100
+    }
+
+    pub fn granted(&self) -> Vec<Entitlement> {
+        Entitlement::ALL.into_iter().filter(|e| self.has(*e)).collect()
+    }
+}
+
+pub async fn entitlements(state: &Arc<AppState>, user_id: ObjectId) -> Entitlements {
+    match state.mongo.collection::<User>("users").find_one(doc! { "_id": user_id }, None).await {
+        Ok(Some(user)) => Entitlements::for_user(&user, Utc::now(), grace_period()),
+        _ => Entitlements::none(),
+    }
+}
+
+/// 402s unless the user has `entitlement`.
+pub async fn require(state: &Arc<AppState>, user_id: ObjectId, entitlement: Entitlement) -> Result<Entitlements, (StatusCode, Json<serde_json::Value>)> {
+    let granted = entitlements(state, user_id).await;
+    if !granted.has(entitlement) {
+        return Err((StatusCode::PAYMENT_REQUIRED, Json(json!({
+            "error": "Premium subscription required",
+            "entitlement": entitlement,
+        }))));
+    }
+    Ok(granted)
+}
@@ -0,0 +1,354 @@
+use std::sync::Arc;
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::error::{AppError, AppResult};
+use crate::features::infrastructure::jobs::{Job, JobError};
+use crate::models::{LedgerAccount, LedgerJournal, LedgerLine, Transaction};
+
+// Double-entry ledger for money moving through the platform. Every journal's lines sum to
+// zero: cash arriving from M-Pesa debits `platform:mpesa` and credits whoever it belongs
+// to (platform revenue, donations, a creator's earnings). Journals are keyed so posting the
+// same event twice is a no-op; account totals are kept alongside for cheap balance reads.
+// A journal is written unapplied, its lines are added to the totals (each account remembers
+// which journals it has counted, so a line is never added twice), then it's marked applied.
+// A poster that dies halfway leaves an unapplied journal for the sweeper to finish.
+
+pub const MPESA_ACCOUNT: &str = "platform:mpesa";
+pub const REVENUE_ACCOUNT: &str = "platform:revenue";
+pub const DONATIONS_ACCOUNT: &str = "platform:donations";
+
+/// A user's share of sales, withdrawable later.
+pub fn earnings_account(user_id: ObjectId) -> String {
+    format!("user:{}:earnings", user_id.to_hex())
+}
+
+/// Earnings set aside for a withdrawal until M-Pesa confirms or fails it.
+pub fn payout_hold_account(user_id: ObjectId) -> String {
+    format!("user:{}:payout_hold", user_id.to_hex())
+}
+
+fn account_owner(account: &str) -> Option<ObjectId> {
+    account.strip_prefix("user:")
+        .and_then(|rest| rest.split(':').next())
+        .and_then(|hex| ObjectId::parse_str(hex).ok())
+}
+
+/// Lines must be non-empty, non-zero and balance out.
+pub fn validate_lines(lines: &[LedgerLine]) -> Result<(), String> {
+    if lines.is_empty() {
+        return Err("journal has no lines".to_string());
+    }
+    if lines.iter().any(|l| l.amount == 0 || l.account.is_empty()) {
+        return Err("journal has an empty line".to_string());
+    }
+    let total: i64 = lines.iter().map(|l| l.amount).sum();
+    if total != 0 {
+        return Err(format!("journal is unbalanced by {}", total));
+    }
+    Ok(())
+}
+
+/// Lines for `amount` cents paid in through M-Pesa: `splits` are credited first and the
+/// remainder goes to `remainder_account`. Splits larger than the payment are scaled back.
+pub fn payment_lines(amount: i64, remainder_account: &str, splits: &[LedgerLine]) -> Vec<LedgerLine> {
+    let mut lines = vec![LedgerLine { account: MPESA_ACCOUNT.to_string(), amount: -amount }];
+    let mut left = amount;
+    for split in splits {
+        let take = split.amount.clamp(0, left);
+        if take > 0 {
+            lines.push(LedgerLine { account: split.account.clone(), amount: take });
+            left -= take;
+        }
+    }
+    if left > 0 {
+        lines.push(LedgerLine { account: remainder_account.to_string(), amount: left });
+    }
+    lines
+}
+
+/// Journal keys remembered per account; far more than can be pending at once.
+const APPLIED_JOURNALS_KEPT: i32 = 500;
+/// Unapplied journals younger than this are probably still being applied by their poster.
+const SWEEP_AFTER_SECS: i64 = 60;
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        &*e.kind,
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(we)) if we.code == 11000
+    )
+}
+
+/// Posts a journal once per `key`. Returns `false` if it was already posted. Once the journal
+/// is written the posting stands: lines that can't be applied now are left to the sweeper.
+pub async fn post_journal(
+    state: &Arc<AppState>,
+    key: &str,
+    purpose: &str,
+    lines: Vec<LedgerLine>,
+    transaction_id: Option<ObjectId>,
+) -> AppResult<bool> {
+    validate_lines(&lines).map_err(AppError::BadRequest)?;
+
+    let journal = LedgerJournal {
+        id: None,
+        key: key.to_string(),
+        purpose: purpose.to_string(),
+        lines,
+        transaction_id,
+        applied: false,
+        funded_account: None,
+        created_at: DateTime::now(),
+    };
+    let journals = state.mongo.collection::<LedgerJournal>("ledger_journals");
+    let (journal, posted) = match journals.insert_one(&journal, None).await {
+        Ok(_) => (journal, true),
+        Err(e) if is_duplicate_key(&e) => {
+            // Posted before; finish it if that attempt died before applying every line
+            match journals.find_one(doc! { "key": key }, None).await? {
+                Some(existing) if !existing.applied => (existing, false),
+                _ => return Ok(false),
+            }
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if let Err(e) = apply_journal(state, &journal, true).await {
+        tracing::error!("Ledger journal {} posted but not applied, leaving it to the sweeper: {:?}", key, e);
+    }
+    Ok(posted)
+}
+
+/// Matches the account only if the journal isn't already in its totals.
+pub fn line_filter(account: &str, key: &str) -> mongodb::bson::Document {
+    doc! { "account": account, "applied_journals": { "$ne": key } }
+}
+
+/// Adds one journal line to its account's totals and remembers the journal as counted.
+pub fn line_update(key: &str, amount: i64) -> mongodb::bson::Document {
+    doc! {
+        "$inc": {
+            "balance": amount,
+            "credited": amount.max(0),
+            "debited": (-amount).max(0),
+            "version": 1,
+        },
+        "$push": { "applied_journals": { "$each": [key], "$slice": -APPLIED_JOURNALS_KEPT } },
+        "$set": { "updated_at": DateTime::now() },
+    }
+}
+
+async fn ensure_account(state: &Arc<AppState>, account: &str) -> mongodb::error::Result<()> {
+    let upsert = mongodb::options::UpdateOptions::builder().upsert(true).build();
+    state.mongo.collection::<LedgerAccount>("ledger_accounts").update_one(
+        doc! { "account": account },
+        doc! { "$setOnInsert": { "owner_id": account_owner(account), "updated_at": DateTime::now() } },
+        upsert,
+    ).await?;
+    Ok(())
+}
+
+/// Applies the journal's lines that aren't on their accounts yet, then marks it applied.
+/// A transfer's debit only goes through while the funded account covers it; if it doesn't,
+/// or `new_debits` is false (the poster gave up on it), the transfer is dropped unapplied.
+/// Returns `false` when that happens.
+async fn apply_journal(state: &Arc<AppState>, journal: &LedgerJournal, new_debits: bool) -> AppResult<bool> {
+    let accounts = state.mongo.collection::<LedgerAccount>("ledger_accounts");
+    let journals = state.mongo.collection::<LedgerJournal>("ledger_journals");
+    let key = &journal.key;
+
+    for line in &journal.lines {
+        ensure_account(state, &line.account).await?;
+        let funded = journal.funded_account.as_deref() == Some(line.account.as_str()) && line.amount < 0;
+        if !funded {
+            accounts.update_one(line_filter(&line.account, key), line_update(key, line.amount), None).await?;
+            continue;
+        }
+
+        let mut filter = line_filter(&line.account, key);
+        filter.insert("balance", doc! { "$gte": -line.amount });
+        let debited = new_debits && accounts.update_one(filter, line_update(key, line.amount), None).await?.matched_count == 1;
+        if !debited {
+            let already = accounts.count_documents(doc! { "account": &line.account, "applied_journals": key }, None).await?;
+            if already == 0 {
+                journals.delete_one(doc! { "key": key, "applied": false }, None).await?;
+                return Ok(false);
+            }
+        }
+    }
+
+    journals.update_one(doc! { "key": key }, doc! { "$set": { "applied": true } }, None).await?;
+    Ok(true)
+}
+
+/// Cron-driven: finishes journals whose poster died (or failed) before applying them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepJournalsJob;
+
+impl Job for SweepJournalsJob {
+    const KIND: &'static str = "ledger.sweep_journals";
+    const MAX_ATTEMPTS: u32 = 3;
+}
+
+pub async fn run_sweep_journals(state: Arc<AppState>) -> Result<(), JobError> {
+    let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis() - SWEEP_AFTER_SECS * 1000);
+    let journals = state.mongo.collection::<LedgerJournal>("ledger_journals");
+    let mut cursor = journals.find(doc! { "applied": false, "created_at": { "$lt": cutoff } }, None).await?;
+    let (mut applied, mut dropped) = (0, 0);
+    while let Some(journal) = cursor.next().await {
+        let journal = journal?;
+        match apply_journal(&state, &journal, false).await {
+            Ok(true) => applied += 1,
+            Ok(false) => dropped += 1,
+            Err(e) => return Err(JobError::Retry(format!("{}: {:?}", journal.key, e))),
+        }
+    }
+    if applied + dropped > 0 {
+        tracing::warn!("Ledger sweep applied {} journals and dropped {} unfunded transfers", applied, dropped);
+    }
+    Ok(())
+}
+
+/// Recomputes an account's totals from the journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebuildAccountJob {
+    pub account: String,
+}
+
+impl Job for RebuildAccountJob {
+    const KIND: &'static str = "ledger.rebuild_account";
+    const MAX_ATTEMPTS: u32 = 10;
+}
+
+/// Balance, credited and debited for `account` across `journals`.
+pub fn account_totals<'a>(account: &str, journals: impl IntoIterator<Item = &'a LedgerJournal>) -> (i64, i64, i64) {
+    journals
+        .into_iter()
+        .flat_map(|j| j.lines.iter())
+        .filter(|l| l.account == account)
+        .fold((0, 0, 0), |(balance, credited, debited), l| {
+            (balance + l.amount, credited + l.amount.max(0), debited + (-l.amount).max(0))
+        })
+}
+
+/// Whether a journal is part of an account's totals: it was fully applied, or at least its
+/// line for this account was (`applied_here` is the account's `applied_journals`).
+pub fn counts_toward(journal: &LedgerJournal, applied_here: &[String]) -> bool {
+    journal.applied || applied_here.contains(&journal.key)
+}
+
+pub async fn run_rebuild_account(state: Arc<AppState>, job: RebuildAccountJob) -> Result<(), JobError> {
+    ensure_account(&state, &job.account).await?;
+    let accounts = state.mongo.collection::<LedgerAccount>("ledger_accounts");
+    let journals_collection = state.mongo.collection::<LedgerJournal>("ledger_journals");
+
+    // Lines applied while we read the journal bump the version; start over if one did
+    for _ in 0..5 {
+        let Some(current) = accounts.find_one(doc! { "account": &job.account }, None).await? else { continue };
+        let mut cursor = journals_collection.find(doc! { "lines.account": &job.account }, None).await?;
+        let mut journals = Vec::new();
+        while let Some(journal) = cursor.next().await {
+            let journal = journal?;
+            if counts_toward(&journal, &current.applied_journals) {
+                journals.push(journal);
+            }
+        }
+        let (balance, credited, debited) = account_totals(&job.account, &journals);
+
+        let result = accounts.update_one(
+            doc! { "account": &job.account, "version": current.version },
+            doc! {
+                "$set": { "balance": balance, "credited": credited, "debited": debited, "updated_at": DateTime::now() },
+                "$inc": { "version": 1 },
+            },
+            None,
+        ).await?;
+        if result.matched_count == 1 {
+            tracing::info!("Rebuilt ledger account {} from {} journals: balance {}", job.account, journals.len(), balance);
+            return Ok(());
+        }
+    }
+    Err(JobError::Retry(format!("{} kept changing during the rebuild", job.account)))
+}
+
+/// Moves `amount` cents from `from` to `to`, but only if `from` has that much. Returns
+/// `false` when it doesn't. The journal is written first and the debit only applied while
+/// the balance covers it, so two concurrent transfers can't both spend the same money and a
+/// crash in between leaves a journal the sweeper can finish (or drop, if nothing moved yet).
+pub async fn transfer_if_funded(
+    state: &Arc<AppState>,
+    key: &str,
+    purpose: &str,
+    from: &str,
+    to: &str,
+    amount: i64,
+) -> AppResult<bool> {
+    if amount <= 0 {
+        return Err(AppError::BadRequest("Transfer amount must be positive".to_string()));
+    }
+    let journal = LedgerJournal {
+        id: None,
+        key: key.to_string(),
+        purpose: purpose.to_string(),
+        lines: vec![
+            LedgerLine { account: from.to_string(), amount: -amount },
+            LedgerLine { account: to.to_string(), amount },
+        ],
+        transaction_id: None,
+        applied: false,
+        funded_account: Some(from.to_string()),
+        created_at: DateTime::now(),
+    };
+    let journals = state.mongo.collection::<LedgerJournal>("ledger_journals");
+    match journals.insert_one(&journal, None).await {
+        Ok(_) => apply_journal(state, &journal, true).await,
+        Err(e) if is_duplicate_key(&e) => match journals.find_one(doc! { "key": key }, None).await? {
+            Some(existing) if !existing.applied => apply_journal(state, &existing, true).await,
+            Some(_) => Ok(true),
+            // Dropped as unfunded between our insert and this read
+            None => Ok(false),
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Records a completed M-Pesa payment. Safe to call again for the same transaction.
+pub async fn record_payment(state: &Arc<AppState>, tx: &Transaction, purpose: &str, remainder_account: &str, splits: &[LedgerLine]) {
+    let lines = payment_lines(tx.amount, remainder_account, splits);
+    let key = format!("payment:{}", tx.checkout_request_id);
+    if let Err(e) = post_journal(state, &key, purpose, lines, tx.id).await {
+        tracing::error!("Failed to post {}: {:?}", key, e);
+    }
+}
+
+/// Lines that undo `lines`.
+pub fn reversal_lines(lines: &[LedgerLine]) -> Vec<LedgerLine> {
+    lines.iter().map(|l| LedgerLine { account: l.account.clone(), amount: -l.amount }).collect()
+}
+
+/// Reverses a refunded payment's journal, taking back any creator's share along with the
+/// platform's. Safe to call again for the same transaction.
+pub async fn record_refund(state: &Arc<AppState>, tx: &Transaction) {
+    let payment_key = format!("payment:{}", tx.checkout_request_id);
+    let journals = state.mongo.collection::<LedgerJournal>("ledger_journals");
+    let original = match journals.find_one(doc! { "key": &payment_key }, None).await {
+        Ok(Some(journal)) => journal,
+        Ok(None) => {
+            tracing::error!("Refunded {} but it was never posted", payment_key);
+            return;
+        }
+        Err(e) => {
+            tracing::error!("Failed to load {}: {:?}", payment_key, e);
+            return;
+        }
+    };
+    let key = format!("refund:{}", tx.checkout_request_id);
+    let purpose = format!("{}_refund", original.purpose);
+    if let Err(e) = post_journal(state, &key, &purpose, reversal_lines(&original.lines), tx.id).await {
+        tracing::error!("Failed to post {}: {:?}", key, e);
+    }
+}
+
+pub async fn account(state: &Arc<AppState>, account: &str) -> mongodb::error::Result<Option<LedgerAccount>> {
+    state.mongo.collection::<LedgerAccount>("ledger_accounts").find_one(doc! { "account": account }, None).await
+}
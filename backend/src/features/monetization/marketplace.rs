@@ -8,10 +8,11 @@ use axum::{
 use serde_json::json;
 use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
-use crate::models::{MarketplaceItem, Profile, User};
+use crate::models::{MarketplaceItem, Profile, Transaction, User};
 use crate::features::infrastructure::dto::{MarketplaceItemResponse, CreateItemRequest, ItemFilter, IdResponse, MessageResponse};
 use crate::features::infrastructure::error::{AppResult, AppError};
 use crate::features::auth::auth_service::AuthUser;
+use crate::features::monetization::payments::{self, PaymentPurpose};
 use mongodb::{bson::{doc, oid::ObjectId}, options::FindOptions};
 use futures::stream::StreamExt;
 use std::collections::HashMap;
@@ -180,10 +181,53 @@ pub async fn get_item_details_handler(
     Ok((StatusCode::OK, Json(response)))
 }
 
+/// Price of a 24-hour boost in KES. Zero (the default) keeps boosts free.
+pub fn boost_price_kes() -> i64 {
+    std::env::var("MARKETPLACE_BOOST_PRICE").ok().and_then(|v| v.parse().ok()).filter(|p: &i64| *p >= 0).unwrap_or(0)
+}
+
+#[derive(serde::Deserialize)]
+pub struct BoostItemRequest {
+    pub phone: Option<String>,
+}
+
+/// Pushes an item's boost 24 hours further out. Also the fulfilment hook for paid boosts.
+pub async fn apply_boost(state: &Arc<AppState>, item_id: ObjectId) -> Option<chrono::DateTime<chrono::Utc>> {
+    let items_collection = state.mongo.collection::<MarketplaceItem>("marketplace_items");
+    let item = items_collection.find_one(doc! { "_id": item_id }, None).await.ok()??;
+
+    let now = chrono::Utc::now();
+    let current_boost = item.boosted_until.map(|dt| dt.to_chrono()).filter(|dt| *dt > now).unwrap_or(now);
+    let new_boost = current_boost + chrono::Duration::hours(24);
+
+    items_collection.update_one(
+        doc! { "_id": item_id },
+        doc! { "$set": { "boosted_until": mongodb::bson::DateTime::from_chrono(new_boost) } },
+        None
+    ).await.ok()?;
+    Some(new_boost)
+}
+
+/// Takes back a refunded paid boost: 24 hours off whatever boost the item has left.
+pub async fn revoke_boost(state: &Arc<AppState>, item_id: ObjectId) -> Result<(), mongodb::error::Error> {
+    let items_collection = state.mongo.collection::<MarketplaceItem>("marketplace_items");
+    let Some(item) = items_collection.find_one(doc! { "_id": item_id }, None).await? else { return Ok(()) };
+    let Some(boosted_until) = item.boosted_until else { return Ok(()) };
+
+    let new_boost = boosted_until.to_chrono() - chrono::Duration::hours(24);
+    items_collection.update_one(
+        doc! { "_id": item_id, "boosted_until": boosted_until },
+        doc! { "$set": { "boosted_until": mongodb::bson::DateTime::from_chrono(new_boost) } },
+        None
+    ).await?;
+    Ok(())
+}
+
 pub async fn boost_item_handler(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
     Path(id): Path<String>,
+    payload: Option<Json<BoostItemRequest>>,
 ) -> AppResult<impl IntoResponse> {
     let oid = ObjectId::parse_str(&id).map_err(|_| AppError::BadRequest("Invalid ID".to_string()))?;
     let items_collection = state.mongo.collection::<MarketplaceItem>("marketplace_items");
@@ -196,22 +240,46 @@ pub async fn boost_item_handler(
         return Err(AppError::Forbidden("Not authorized to boost this item".to_string()));
     }
 
-    // In a real scenario, check user balance / charge M-Pesa here
-    // For now, we simulate success with a fixed price of 50 units
-    
-    let current_boost = item.boosted_until.map(|dt| dt.to_chrono()).unwrap_or_else(|| chrono::Utc::now());
-    let new_boost = current_boost + chrono::Duration::hours(24);
-    let new_boost_bson = mongodb::bson::DateTime::from_chrono(new_boost);
+    let price = boost_price_kes();
+    if price == 0 {
+        let new_boost = apply_boost(&state, oid).await
+            .ok_or(AppError::InternalServerError("Failed to boost item".to_string()))?;
+        return Ok((StatusCode::OK, Json(json!({
+            "message": "Item boosted for 24 hours!",
+            "boosted_until": new_boost.to_rfc3339()
+        }))));
+    }
 
-    items_collection.update_one(
-        doc! { "_id": oid },
-        doc! { "$set": { "boosted_until": new_boost_bson } },
-        None
-    ).await?;
+    // Paid boosts are applied by the payment callback
+    let phone = payload.and_then(|Json(p)| p.phone)
+        .ok_or(AppError::BadRequest("A phone number is required to pay for the boost".to_string()))?;
+    let checkout_request_id = payments::start_payment(&state, &phone, price, "Marketplace Boost").await
+        .map_err(|(_, Json(body))| AppError::BadRequest(body["error"].as_str().unwrap_or("Payment failed").to_string()))?;
+
+    let now = mongodb::bson::DateTime::now();
+    let tx = Transaction {
+        id: None,
+        user_id: user.user_id,
+        phone_number: phone,
+        amount: price * 100,
+        mpesa_receipt_number: None,
+        checkout_request_id: checkout_request_id.clone(),
+        status: "pending".to_string(),
+        provider: state.payments.name().to_string(),
+        refund_reference: None,
+        refund_due: None,
+        transaction_type: PaymentPurpose::MarketplaceBoost.as_str().to_string(),
+        premium_duration: None,
+        target_id: Some(oid),
+        created_at: now,
+        updated_at: now,
+    };
+    state.mongo.collection::<Transaction>("transactions").insert_one(tx, None).await?;
 
-    Ok((StatusCode::OK, Json(json!({
-        "message": "Item boosted for 24 hours!",
-        "boosted_until": new_boost.to_rfc3339()
+    Ok((StatusCode::ACCEPTED, Json(json!({
+        "message": "STK Push initiated",
+        "checkout_request_id": checkout_request_id,
+        "amount": price
     }))))
 }
 
@@ -0,0 +1,216 @@
+use axum::async_trait;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::features::monetization::provider::{
+    CallbackEvent, PaymentError, PaymentProvider, PaymentRequest, PaymentResult, PaymentStatus, PayoutRequest, RefundRequest, RefundStatus,
+};
+
+// In-process stand-in for a real provider. The payer's phone number picks the outcome so
+// tests and local runs are deterministic:
+//   ...0000  the payer cancels (failed callback)
+//   ...1111  nothing ever comes back (stays pending, as if the prompt timed out)
+//   ...2222  succeeds, and the callback is delivered twice
+//   anything else succeeds
+// Payouts use the payee's phone the same way: 0000 fails, 1111 never answers, anything
+// else is paid. Callbacks and payout results are queued for `take_callbacks`; with HTTP
+// delivery on, they're also POSTed to the request's callback or result URL like a real
+// provider would.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockScenario {
+    Success,
+    Failure,
+    Timeout,
+    DuplicateCallback,
+}
+
+impl MockScenario {
+    pub fn for_phone(phone: &str) -> Self {
+        if phone.ends_with("0000") {
+            MockScenario::Failure
+        } else if phone.ends_with("1111") {
+            MockScenario::Timeout
+        } else if phone.ends_with("2222") {
+            MockScenario::DuplicateCallback
+        } else {
+            MockScenario::Success
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MockProvider {
+    next_id: AtomicU64,
+    payments: Mutex<HashMap<String, (PaymentRequest, MockScenario)>>,
+    callbacks: Mutex<Vec<serde_json::Value>>,
+    refunds: Mutex<Vec<RefundRequest>>,
+    payouts: Mutex<Vec<PayoutRequest>>,
+    http: Option<reqwest::Client>,
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also POST callbacks to the request's callback URL, a couple of seconds after initiating.
+    pub fn with_http_delivery(mut self, client: reqwest::Client) -> Self {
+        self.http = Some(client);
+        self
+    }
+
+    /// Callbacks produced so far, oldest first; duplicates included.
+    #[allow(dead_code)]
+    pub fn take_callbacks(&self) -> Vec<serde_json::Value> {
+        self.callbacks.lock().map(|mut c| std::mem::take(&mut *c)).unwrap_or_default()
+    }
+
+    #[allow(dead_code)]
+    pub fn refunds(&self) -> Vec<RefundRequest> {
+        self.refunds.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+
+    #[allow(dead_code)]
+    pub fn payouts(&self) -> Vec<PayoutRequest> {
+        self.payouts.lock().map(|p| p.clone()).unwrap_or_default()
+    }
+
+    /// Queues `callback` and, with HTTP delivery on, POSTs it to `url` `deliveries` times.
+    fn deliver(&self, url: &str, callback: serde_json::Value, deliveries: usize) {
+        if let Ok(mut queued) = self.callbacks.lock() {
+            queued.extend(std::iter::repeat_n(callback.clone(), deliveries));
+        }
+        if let Some(client) = self.http.clone().filter(|_| deliveries > 0) {
+            let url = url.to_string();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                for _ in 0..deliveries {
+                    if let Err(e) = client.post(&url).json(&callback).send().await {
+                        tracing::warn!("Mock provider couldn't deliver callback to {}: {}", url, e);
+                    }
+                }
+            });
+        }
+    }
+
+    fn receipt(reference: &str) -> String {
+        format!("MOCK{}", reference.trim_start_matches("mock_ws_CO_"))
+    }
+
+    fn callback(reference: &str, request: &PaymentRequest, scenario: MockScenario) -> serde_json::Value {
+        match scenario {
+            MockScenario::Failure => json!({
+                "provider": "mock",
+                "reference": reference,
+                "result_code": 1032,
+                "result_desc": "Request cancelled by user",
+            }),
+            _ => json!({
+                "provider": "mock",
+                "reference": reference,
+                "result_code": 0,
+                "result_desc": "The service request is processed successfully.",
+                "receipt": Self::receipt(reference),
+                "amount": request.amount_kes * 100,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn initiate(&self, request: &PaymentRequest) -> Result<String, PaymentError> {
+        if request.amount_kes < 1 {
+            return Err(PaymentError::Rejected("Invalid Amount".to_string()));
+        }
+        let reference = format!("mock_ws_CO_{:06}", self.next_id.fetch_add(1, Ordering::SeqCst) + 1);
+        let scenario = MockScenario::for_phone(&request.phone);
+        self.payments
+            .lock()
+            .map_err(|_| PaymentError::Unavailable("mock provider lock poisoned".to_string()))?
+            .insert(reference.clone(), (request.clone(), scenario));
+
+        let deliveries = match scenario {
+            MockScenario::Timeout => 0,
+            MockScenario::DuplicateCallback => 2,
+            _ => 1,
+        };
+        self.deliver(&request.callback_url, Self::callback(&reference, request, scenario), deliveries);
+        Ok(reference)
+    }
+
+    async fn query_status(&self, reference: &str) -> Result<PaymentStatus, PaymentError> {
+        let payments = self.payments.lock().map_err(|_| PaymentError::Unavailable("mock provider lock poisoned".to_string()))?;
+        let (_, scenario) = payments.get(reference).ok_or_else(|| PaymentError::Rejected("Unknown reference".to_string()))?;
+        Ok(match scenario {
+            MockScenario::Failure => PaymentStatus::Failed { reason: "Request cancelled by user".to_string() },
+            MockScenario::Timeout => PaymentStatus::Pending,
+            _ => PaymentStatus::Completed { receipt: Some(Self::receipt(reference)) },
+        })
+    }
+
+    fn parse_callback(&self, body: &serde_json::Value) -> Result<CallbackEvent, PaymentError> {
+        if body["provider"] != "mock" {
+            return Err(PaymentError::InvalidCallback("Not a mock callback".to_string()));
+        }
+        let reference = body["reference"].as_str()
+            .ok_or_else(|| PaymentError::InvalidCallback("Missing reference".to_string()))?
+            .to_string();
+        let result_code = body["result_code"].as_i64().unwrap_or(-1) as i32;
+        let result_desc = body["result_desc"].as_str().unwrap_or_default().to_string();
+        let status = if result_code == 0 {
+            PaymentStatus::Completed { receipt: body["receipt"].as_str().map(|s| s.to_string()) }
+        } else {
+            PaymentStatus::Failed { reason: result_desc.clone() }
+        };
+        Ok(CallbackEvent::Payment(PaymentResult { reference, status, result_code, result_desc, amount: body["amount"].as_i64() }))
+    }
+
+    async fn refund(&self, request: &RefundRequest) -> Result<RefundStatus, PaymentError> {
+        let known = self.payments.lock().map(|p| p.contains_key(&request.reference)).unwrap_or(false);
+        if !known {
+            return Err(PaymentError::Rejected("Unknown reference".to_string()));
+        }
+        if let Ok(mut refunds) = self.refunds.lock() {
+            refunds.push(request.clone());
+        }
+        Ok(RefundStatus::Completed { reference: format!("mock_refund_{}", request.reference) })
+    }
+
+    async fn send_b2c(&self, request: &PayoutRequest) -> Result<String, PaymentError> {
+        if request.amount_kes < 1 {
+            return Err(PaymentError::Rejected("Invalid Amount".to_string()));
+        }
+        let conversation_id = format!("mock_AG_{:06}", self.next_id.fetch_add(1, Ordering::SeqCst) + 1);
+        self.payouts
+            .lock()
+            .map_err(|_| PaymentError::Unavailable("mock provider lock poisoned".to_string()))?
+            .push(request.clone());
+
+        // Shaped like Daraja's B2C result, which is what the result URL parses
+        let (result_code, result_desc, transaction_id) = match MockScenario::for_phone(&request.phone) {
+            MockScenario::Timeout => return Ok(conversation_id),
+            MockScenario::Failure => (2001, "The initiator information is invalid.", None),
+            _ => (0, "The service request is processed successfully.", Some(format!("MOCKB2C{}", conversation_id.trim_start_matches("mock_AG_")))),
+        };
+        let result = json!({
+            "Result": {
+                "ResultType": 0,
+                "ResultCode": result_code,
+                "ResultDesc": result_desc,
+                "OriginatorConversationID": request.reference,
+                "ConversationID": conversation_id,
+                "TransactionID": transaction_id,
+            }
+        });
+        self.deliver(&request.result_url, result, 1);
+        Ok(conversation_id)
+    }
+}
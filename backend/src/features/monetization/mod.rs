@@ -1,2 +1,9 @@
 pub mod payments;
 pub mod marketplace;
+pub mod ledger;
+pub mod payouts;
+pub mod provider;
+pub mod daraja;
+pub mod mock_provider;
+pub mod subscriptions;
+pub mod entitlements;
@@ -1,6 +1,6 @@
 use axum::{
-    extract::{State, Path, Json},
-    http::StatusCode,
+    extract::{State, Path, Json, Query},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
     routing::{post, get},
     Router,
@@ -10,10 +10,15 @@ use serde_json::json;
 use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
 use crate::features::auth::auth_service::AuthUser;
-use crate::models::{Transaction, HookupAlias};
+use crate::features::auth::sessions::{forwarded_ip, trusted_proxy_hops};
+use crate::features::academic::revision_materials;
+use crate::features::infrastructure::admin::require_admin;
+use crate::features::infrastructure::jobs::{Job, JobError};
+use crate::features::monetization::{ledger, marketplace, subscriptions::{self, Plan}};
+use crate::models::{Transaction, HookupAlias, LedgerLine, PaymentCallback};
 use mongodb::bson::{doc, DateTime};
 use chrono::Utc;
-use base64::{Engine as _, engine::general_purpose};
+use crate::features::monetization::provider::{CallbackEvent, PaymentError, PaymentRequest, PaymentResult, PaymentStatus, RefundRequest, RefundStatus};
 
 // --- DTOs ---
 
@@ -21,171 +26,85 @@ use base64::{Engine as _, engine::general_purpose};
 pub struct StkPushRequest {
     pub phone: String,
     pub amount: f64,
-    pub tx_type: String, // "verification" or "premium"
+    pub tx_type: String, // "verification", "premium", "hookup" or "donation"
     pub premium_duration: Option<String>, 
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct MpesaCallback {
-    #[serde(rename = "Body")]
-    pub body: StkCallbackBody,
+/// What a transaction pays for; stored as `Transaction::transaction_type` and used to pick
+/// the fulfilment hook once the payment completes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentPurpose {
+    Verification,
+    Premium,
+    Hookup,
+    Donation,
+    Material,
+    MarketplaceBoost,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct StkCallbackBody {
-    #[serde(rename = "stkCallback")]
-    pub stk_callback: StkCallback,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct StkCallback {
-    #[serde(rename = "MerchantRequestID")]
-    pub merchant_request_id: String,
-    #[serde(rename = "CheckoutRequestID")]
-    pub checkout_request_id: String,
-    #[serde(rename = "ResultCode")]
-    pub result_code: i32,
-    #[serde(rename = "ResultDesc")]
-    pub result_desc: String,
-    #[serde(rename = "CallbackMetadata")]
-    pub callback_metadata: Option<CallbackMetadata>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CallbackMetadata {
-    #[serde(rename = "Item")]
-    pub item: Vec<MetadataItem>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct MetadataItem {
-    #[serde(rename = "Name")]
-    pub name: String,
-    #[serde(rename = "Value")]
-    pub value: Option<serde_json::Value>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct StkQueryResponse {
-    #[serde(rename = "ResponseCode")]
-    pub response_code: String,
-    #[serde(rename = "ResponseDescription")]
-    pub response_description: String,
-    #[serde(rename = "MerchantRequestID")]
-    pub merchant_request_id: String,
-    #[serde(rename = "CheckoutRequestID")]
-    pub checkout_request_id: String,
-    #[serde(rename = "ResultCode")]
-    pub result_code: Option<String>,
-    #[serde(rename = "ResultDesc")]
-    pub result_desc: Option<String>,
-}
-
-// --- OAuth Response ---
-#[derive(Deserialize)]
-struct MpesaAuthResponse {
-    access_token: String,
-}
-
-// --- Handler Functions ---
-
-async fn get_mpesa_token(state: &AppState) -> Result<String, String> {
-    // Check cache first
-    let mpesa_env = std::env::var("MPESA_ENV").unwrap_or_else(|_| "sandbox".to_string()).trim().to_lowercase();
-    if let Some(entry) = state.mpesa_token.get(&mpesa_env) {
-        let (token, expires_at) = entry.value();
-        if Utc::now().timestamp_millis() < *expires_at {
-            return Ok(token.clone());
+impl PaymentPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentPurpose::Verification => "verification",
+            PaymentPurpose::Premium => "premium",
+            PaymentPurpose::Hookup => "hookup",
+            PaymentPurpose::Donation => "donation",
+            PaymentPurpose::Material => "material",
+            PaymentPurpose::MarketplaceBoost => "marketplace_boost",
         }
     }
 
-    let consumer_key = std::env::var("MPESA_CONSUMER_KEY").map_err(|_| "Missing MPESA_CONSUMER_KEY")?.trim().to_string();
-    let consumer_secret = std::env::var("MPESA_CONSUMER_SECRET").map_err(|_| "Missing MPESA_CONSUMER_SECRET")?.trim().to_string();
-
-    let client = reqwest::Client::new();
-    let auth = general_purpose::STANDARD.encode(format!("{}:{}", consumer_key, consumer_secret));
-
-    let url = if mpesa_env == "live" {
-        "https://api.safaricom.co.ke/oauth/v1/generate?grant_type=client_credentials"
-    } else {
-        "https://sandbox.safaricom.co.ke/oauth/v1/generate?grant_type=client_credentials"
-    };
-
-    tracing::trace!("Requesting new M-Pesa token from {}", url);
-
-    let res = client.get(url)
-        .header("Authorization", format!("Basic {}", auth))
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if !res.status().is_success() {
-        let err_text = res.text().await.unwrap_or_default();
-        return Err(format!("M-Pesa OAuth failed: {}", err_text));
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "verification" => PaymentPurpose::Verification,
+            "premium" => PaymentPurpose::Premium,
+            "hookup" => PaymentPurpose::Hookup,
+            "donation" => PaymentPurpose::Donation,
+            "material" => PaymentPurpose::Material,
+            "marketplace_boost" => PaymentPurpose::MarketplaceBoost,
+            _ => return None,
+        })
     }
 
-    let data: MpesaAuthResponse = res.json().await.map_err(|e| e.to_string())?;
-    
-    // Cache the token (standard expiry is 3600s, we use 3000s for safety)
-    let expires_at = Utc::now().timestamp_millis() + 3000 * 1000;
-    state.mpesa_token.insert(mpesa_env, (data.access_token.clone(), expires_at));
-    
-    Ok(data.access_token)
+    /// Where the platform's part of the payment is booked.
+    pub fn remainder_account(&self) -> &'static str {
+        match self {
+            PaymentPurpose::Donation => ledger::DONATIONS_ACCOUNT,
+            _ => ledger::REVENUE_ACCOUNT,
+        }
+    }
 }
 
-async fn query_stk_status_mpesa(state: &AppState, checkout_id: &str) -> Option<(String, String)> {
-    let token = get_mpesa_token(state).await.ok()?;
-    let shortcode = std::env::var("MPESA_BUSINESS_SHORTCODE").ok()?;
-    let passkey = std::env::var("MPESA_PASSKEY").ok()?;
-    let timestamp = Utc::now().format("%Y%m%d%H%M%S").to_string();
-    let password = general_purpose::STANDARD.encode(format!("{}{}{}", shortcode, passkey, timestamp));
-
-    let mpesa_env = std::env::var("MPESA_ENV").unwrap_or_else(|_| "sandbox".to_string()).trim().to_lowercase();
-    let url = if mpesa_env == "live" {
-        "https://api.safaricom.co.ke/mpesa/stkpushquery/v1/query"
-    } else {
-        "https://sandbox.safaricom.co.ke/mpesa/stkpushquery/v1/query"
+/// Starts a payment of `amount_kes` whole shillings with the configured provider and
+/// returns its reference, which is stored as the transaction's `checkout_request_id`.
+pub(crate) async fn start_payment(state: &AppState, phone: &str, amount_kes: i64, description: &str) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+    let request = PaymentRequest {
+        phone: phone.to_string(),
+        amount_kes,
+        description: description.to_string(),
+        callback_url: callback_url("/api/payments/callback"),
     };
+    state.payments.initiate(&request).await.map_err(|e| {
+        tracing::error!("{} payment for {} failed to start: {}", state.payments.name(), phone, e);
+        let status = match e {
+            PaymentError::Rejected(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        (status, Json(json!({"error": e.to_string()})))
+    })
+}
 
-    tracing::trace!("Pinging Safaricom for {}", checkout_id);
-
-    let client = reqwest::Client::new();
-    let res = client.post(url)
-        .header("Authorization", format!("Bearer {}", token))
-        .json(&json!({
-            "BusinessShortCode": shortcode,
-            "Password": password,
-            "Timestamp": timestamp,
-            "CheckoutRequestID": checkout_id
-        }))
-        .send()
-        .await
-        .ok()?;
-
-    let data: StkQueryResponse = res.json().await.ok()?;
-    tracing::trace!("Safaricom Response for {}: {:?}", checkout_id, data);
-    
-    // Check for explicit result code (meaning the user finished on their phone)
-    if let Some(code) = data.result_code {
-        if code == "0" {
-            return Some(("completed".to_string(), "Success".to_string()));
-        } else if code == "4999" {
-            // Processing - return None to keep it pending
-            return None;
-        } else {
-            let desc = data.result_desc.unwrap_or_else(|| "Unknown error".to_string());
-            return Some(("failed".to_string(), desc));
-        }
-    }
-    
-    // Specific check for pending vs other errors
-    if data.response_code == "0" && data.result_code.is_none() {
-        // Still pending on phone
-        return None;
-    }
+/// M-Pesa only takes whole shillings, so cent prices are rounded up.
+pub fn kes_for_cents(cents: i64) -> i64 {
+    (cents.max(0) + 99) / 100
+}
 
-    // Handled explicit error from Safaricom response
-    Some(("failed".to_string(), data.response_description))
+/// Whole shillings charged for a donation of `amount` KES, rounded up like other prices.
+pub fn donation_kes(amount: f64) -> i64 {
+    if !amount.is_finite() {
+        return 0;
+    }
+    kes_for_cents((amount * 100.0).round() as i64)
 }
 
 pub async fn initiate_verification_payment(
@@ -193,100 +112,51 @@ pub async fn initiate_verification_payment(
     user: AuthUser,
     Json(payload): Json<StkPushRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let token = get_mpesa_token(&state).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e}))))?;
-
-    let tx_amount = match payload.tx_type.as_str() {
-        "premium" => match payload.premium_duration.as_deref() {
-            Some("weekly") => 10.0,
-            Some("monthly") => 40.0,
-            Some("semi-annual") => 200.0,
-            _ => 40.0,
-        },
-        "hookup" => 20.0,
-        "donation" => payload.amount,
-        _ => 20.0, // Default verification
+    // Purchases of a specific thing have their own endpoints
+    let purpose = match PaymentPurpose::parse(&payload.tx_type) {
+        Some(PaymentPurpose::Material | PaymentPurpose::MarketplaceBoost) => {
+            return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Unsupported payment type"}))));
+        }
+        Some(purpose) => purpose,
+        None => PaymentPurpose::Verification,
     };
-
-    let shortcode = std::env::var("MPESA_BUSINESS_SHORTCODE").unwrap_or_else(|_| "174379".to_string());
-    let passkey = std::env::var("MPESA_PASSKEY").unwrap_or_default();
-    let transaction_type = std::env::var("MPESA_TRANSACTION_TYPE").unwrap_or_else(|_| "CustomerPayBillOnline".to_string());
-    let party_b = std::env::var("MPESA_PARTYB").unwrap_or_else(|_| shortcode.clone());
-    
-    let timestamp = Utc::now().format("%Y%m%d%H%M%S").to_string();
-    let password = general_purpose::STANDARD.encode(format!("{}{}{}", shortcode, passkey, timestamp));
-
-    let base_url = std::env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
-    let callback_url = format!("{}/api/payments/callback", base_url.trim_end_matches('/'));
-    
-    tracing::info!("Using callback URL: {}", callback_url);
-
-    let client = reqwest::Client::new();
-    let mpesa_env = std::env::var("MPESA_ENV").unwrap_or_else(|_| "sandbox".to_string()).trim().to_lowercase();
-    let url = if mpesa_env == "live" {
-        "https://api.safaricom.co.ke/mpesa/stkpush/v1/processrequest"
-    } else {
-        "https://sandbox.safaricom.co.ke/mpesa/stkpush/v1/processrequest"
+    let amount_kes = match purpose {
+        PaymentPurpose::Premium => payload.premium_duration.as_deref()
+            .and_then(Plan::parse)
+            .unwrap_or(Plan::Monthly)
+            .price_kes(),
+        PaymentPurpose::Hookup => 20,
+        PaymentPurpose::Donation => donation_kes(payload.amount),
+        _ => 20, // Default verification
     };
-
-    tracing::info!("Initiating STK Push for {} (Amount: {})", payload.phone, tx_amount);
-
-    let mpesa_res = client.post(url)
-        .header("Authorization", format!("Bearer {}", token))
-        .json(&json!({
-            "BusinessShortCode": shortcode,
-            "Password": password,
-            "Timestamp": timestamp,
-            "TransactionType": transaction_type,
-            "Amount": tx_amount as i32,
-            "PartyA": payload.phone,
-            "PartyB": party_b,
-            "PhoneNumber": payload.phone,
-            "CallBackURL": callback_url,
-            "AccountReference": "KaruTeens",
-            "TransactionDesc": match payload.tx_type.as_str() {
-                "premium" => "Premium Upgrade",
-                "hookup" => "Hookup Alias Activation",
-                "donation" => "Donation to KaruTeens",
-                _ => "Account Verification"
-            }
-        }))
-        .send()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
-
-    let mpesa_data: serde_json::Value = mpesa_res.json().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
-
-    if mpesa_data["ResponseCode"] != "0" {
-        tracing::error!("M-Pesa STK Push error: {:?}", mpesa_data);
-        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": mpesa_data["ResponseDescription"]}))));
+    if amount_kes < 1 {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Amount must be at least KES 1"}))));
     }
+    let description = match purpose {
+        PaymentPurpose::Premium => "Premium Upgrade",
+        PaymentPurpose::Hookup => "Hookup Alias Activation",
+        PaymentPurpose::Donation => "Donation to KaruTeens",
+        _ => "Account Verification"
+    };
 
-    let checkout_request_id = mpesa_data["CheckoutRequestID"].as_str().unwrap().to_string();
+    let checkout_request_id = start_payment(&state, &payload.phone, amount_kes, description).await?;
 
     // Save pending transaction to MongoDB
     let collection = state.mongo.collection::<Transaction>("transactions");
-    let tx_amount = match payload.tx_type.as_str() {
-        "premium" => match payload.premium_duration.as_deref() {
-            Some("weekly") => 10.0,
-            Some("monthly") => 40.0,
-            Some("semi-annual") => 200.0,
-            _ => 40.0,
-        },
-        "hookup" => 20.0,
-        "donation" => payload.amount,
-        _ => 5.0, // Default verification
-    };
-
     let new_tx = Transaction {
         id: None,
         user_id: user.user_id,
         phone_number: payload.phone,
-        amount: (tx_amount * 100.0) as i64,
+        amount: amount_kes * 100,
         mpesa_receipt_number: None,
         checkout_request_id: checkout_request_id.clone(),
         status: "pending".to_string(),
-        transaction_type: payload.tx_type,
+        provider: state.payments.name().to_string(),
+        refund_reference: None,
+        refund_due: None,
+        transaction_type: purpose.as_str().to_string(),
         premium_duration: payload.premium_duration,
+        target_id: None,
         created_at: DateTime::now(),
         updated_at: DateTime::now(),
     };
@@ -300,90 +170,349 @@ pub async fn initiate_verification_payment(
     }))))
 }
 
-pub async fn mpesa_callback_handler(
+/// Moves a transaction to `completed` and returns it only on the first transition, so the
+/// callback, status polling and reconciliation can race without fulfilling twice.
+async fn complete_transaction(state: &Arc<AppState>, checkout_id: &str, receipt: Option<String>) -> Option<Transaction> {
+    let tx_collection = state.mongo.collection::<Transaction>("transactions");
+    let mut set = doc! { "status": "completed", "updated_at": DateTime::now() };
+    if let Some(receipt) = receipt {
+        set.insert("mpesa_receipt_number", receipt);
+    }
+    let filter = doc! { "checkout_request_id": checkout_id, "status": { "$in": ["pending", "failed"] } };
+    match tx_collection.find_one_and_update(filter, doc! { "$set": set }, None).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            tracing::error!("Failed to complete transaction {}: {:?}", checkout_id, e);
+            None
+        }
+    }
+}
+
+/// Runs the purpose's fulfilment hook, then books the money in the ledger.
+async fn fulfil_transaction(state: &Arc<AppState>, tx: &Transaction) {
+    let users = state.mongo.collection::<crate::models::User>("users");
+    
+    // ANY successful payment verifies the account for posting/commenting
+    let _ = users.update_one(
+        doc! { "_id": tx.user_id },
+        doc! { "$set": { "is_verified": true } },
+        None
+    ).await;
+
+    let Some(purpose) = PaymentPurpose::parse(&tx.transaction_type) else {
+        tracing::error!("Completed transaction {} has unknown type '{}'", tx.checkout_request_id, tx.transaction_type);
+        return;
+    };
+
+    // Hooks return any part of the payment owed to someone other than the platform
+    let splits: Vec<LedgerLine> = match purpose {
+        PaymentPurpose::Premium => {
+            subscriptions::activate(state, tx).await;
+            Vec::new()
+        }
+        PaymentPurpose::Hookup => {
+            let hookup_collection = state.mongo.collection::<HookupAlias>("hookup_aliases");
+            let _ = hookup_collection.update_one(
+                doc! { "user_id": tx.user_id },
+                doc! { "$set": { "is_verified": true } },
+                None
+            ).await;
+            tracing::info!("User {} activated Hookup Alias & verified account", tx.user_id);
+            Vec::new()
+        }
+        PaymentPurpose::Material => revision_materials::grant_purchase(state, tx).await,
+        PaymentPurpose::MarketplaceBoost => {
+            match tx.target_id {
+                Some(item_id) => {
+                    if marketplace::apply_boost(state, item_id).await.is_none() {
+                        tracing::error!("Failed to apply paid boost to item {}", item_id);
+                    }
+                }
+                None => tracing::error!("Boost transaction {} has no item", tx.checkout_request_id),
+            }
+            Vec::new()
+        }
+        PaymentPurpose::Verification | PaymentPurpose::Donation => {
+            tracing::info!("User {} verified via M-Pesa", tx.user_id);
+            Vec::new()
+        }
+    };
+
+    ledger::record_payment(state, tx, purpose.as_str(), purpose.remainder_account(), &splits).await;
+}
+
+// --- Callback authenticity ---
+//
+// Daraja doesn't sign callbacks, so we check two things we control: a secret token we
+// append to the CallBackURL (MPESA_CALLBACK_SECRET), and optionally the source address
+// against MPESA_CALLBACK_IPS (comma-separated). The secret is mandatory: without one every
+// callback is refused. The source address is only known when TRUSTED_PROXY_HOPS says how
+// many proxies in front of us append to X-Forwarded-For.
+
+#[derive(Deserialize)]
+pub struct CallbackAuthQuery {
+    pub token: Option<String>,
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Whether a callback from `source_ip` carrying `token` should be trusted. Fails closed:
+/// with no secret configured nothing is trusted.
+pub fn callback_authorized(source_ip: Option<&str>, token: Option<&str>, allowed_ips: &[String], secret: Option<&str>) -> bool {
+    if !allowed_ips.is_empty() && !source_ip.is_some_and(|ip| allowed_ips.iter().any(|a| a == ip)) {
+        return false;
+    }
+    match (secret, token) {
+        (Some(secret), Some(token)) => constant_time_eq(token.as_bytes(), secret.as_bytes()),
+        _ => false,
+    }
+}
+
+fn callback_allowed_ips() -> Vec<String> {
+    std::env::var("MPESA_CALLBACK_IPS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn callback_secret() -> Option<String> {
+    std::env::var("MPESA_CALLBACK_SECRET").ok().filter(|s| !s.is_empty())
+}
+
+/// Whether payment callbacks can be accepted at all; checked at startup.
+pub fn callback_secret_configured() -> bool {
+    callback_secret().is_some()
+}
+
+/// Public URL Daraja should call back on, carrying the callback secret if one is set.
+pub(crate) fn callback_url(path: &str) -> String {
+    let base_url = std::env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+    let url = format!("{}{}", base_url.trim_end_matches('/'), path);
+    tracing::info!("Using callback URL: {}", url);
+    match (callback_secret(), reqwest::Url::parse(&url)) {
+        (Some(secret), Ok(mut parsed)) => {
+            parsed.query_pairs_mut().append_pair("token", &secret);
+            parsed.to_string()
+        }
+        _ => url,
+    }
+}
+
+/// Rejects callbacks that fail the secret or IP allow-list check.
+pub(crate) fn check_callback(headers: &HeaderMap, token: Option<&str>) -> Result<Option<String>, StatusCode> {
+    let source_ip = forwarded_ip(headers, trusted_proxy_hops());
+    if !callback_authorized(source_ip.as_deref(), token, &callback_allowed_ips(), callback_secret().as_deref()) {
+        tracing::warn!("Rejected M-Pesa callback from {:?}", source_ip);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(source_ip)
+}
+
+/// Receives payment and refund results from the configured provider.
+pub async fn payment_callback_handler(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<MpesaCallback>,
+    headers: HeaderMap,
+    Query(auth): Query<CallbackAuthQuery>,
+    Json(payload): Json<serde_json::Value>,
 ) -> impl IntoResponse {
-    let callback = payload.body.stk_callback;
-    let checkout_id = &callback.checkout_request_id;
-    let result_code = callback.result_code;
+    let source_ip = match check_callback(&headers, auth.token.as_deref()) {
+        Ok(ip) => ip,
+        Err(status) => return status,
+    };
 
-    let tx_collection = state.mongo.collection::<Transaction>("transactions");
+    match state.payments.parse_callback(&payload) {
+        Ok(CallbackEvent::Payment(result)) => {
+            handle_payment_result(&state, result, source_ip).await;
+            StatusCode::OK
+        }
+        Ok(CallbackEvent::Refund { reference, succeeded, reason }) => {
+            finish_refund(&state, doc! { "refund_reference": &reference }, succeeded, &reason).await;
+            StatusCode::OK
+        }
+        Err(e) => {
+            tracing::warn!("Unreadable {} callback: {}", state.payments.name(), e);
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+async fn handle_payment_result(state: &Arc<AppState>, result: PaymentResult, source_ip: Option<String>) {
+    let checkout_id = result.reference.clone();
+    let receipt = match &result.status {
+        PaymentStatus::Completed { receipt } => receipt.clone(),
+        _ => None,
+    };
 
-    if result_code == 0 {
-        // Success
-        let mut receipt = String::new();
-        if let Some(meta) = callback.callback_metadata {
-            for item in meta.item {
-                if item.name == "MpesaReceiptNumber" {
-                    receipt = item.value.and_then(|v| v.as_str().map(|s| s.to_string())).unwrap_or_default();
+    // One callback per checkout; the provider's retries are acknowledged and dropped
+    let record = PaymentCallback {
+        id: None,
+        checkout_request_id: checkout_id.clone(),
+        result_code: result.result_code,
+        result_desc: result.result_desc,
+        receipt: receipt.clone(),
+        amount: result.amount,
+        source_ip,
+        received_at: DateTime::now(),
+    };
+    if let Err(e) = state.mongo.collection::<PaymentCallback>("payment_callbacks").insert_one(record, None).await {
+        if matches!(&*e.kind, mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(we)) if we.code == 11000) {
+            tracing::debug!("Duplicate payment callback for {}", checkout_id);
+            return;
+        }
+        tracing::error!("Failed to record payment callback for {}: {:?}", checkout_id, e);
+    }
+
+    match result.status {
+        PaymentStatus::Completed { .. } => {
+            // Polling may have completed it already
+            if let Some(tx) = complete_transaction(state, &checkout_id, receipt).await {
+                if result.amount.is_some_and(|paid| paid != tx.amount) {
+                    tracing::warn!("Checkout {} paid {:?} cents, expected {}", checkout_id, result.amount, tx.amount);
                 }
+                fulfil_transaction(state, &tx).await;
             }
         }
+        PaymentStatus::Failed { .. } => fail_transaction(state, &checkout_id).await,
+        PaymentStatus::Pending => {}
+    }
+}
 
-        // Update Transaction
-        let filter = doc! { "checkout_request_id": checkout_id };
-        let update = doc! { 
-            "$set": { 
-                "status": "completed", 
-                "mpesa_receipt_number": receipt,
-                "updated_at": DateTime::now()
-            } 
-        };
-        
-        if let Ok(Some(tx)) = tx_collection.find_one_and_update(filter, update, None).await {
-            // Update User Status
-            let users = state.mongo.collection::<crate::models::User>("users");
-            
-            // ANY successful payment verifies the account for posting/commenting
-            let _ = users.update_one(
-                doc! { "_id": tx.user_id },
-                doc! { "$set": { "is_verified": true } },
+async fn fail_transaction(state: &AppState, checkout_id: &str) {
+    let filter = doc! { "checkout_request_id": checkout_id, "status": "pending" };
+    let update = doc! { "$set": { "status": "failed", "updated_at": DateTime::now() } };
+    let _ = state.mongo.collection::<Transaction>("transactions").update_one(filter, update, None).await;
+}
+
+// --- Refunds ---
+
+#[derive(Deserialize)]
+pub struct RefundPaymentRequest {
+    pub reason: Option<String>,
+}
+
+/// Settles a `refund_pending` transaction once. A refund that went through is reversed in
+/// the ledger and whatever was bought is taken back; one that failed leaves the payment
+/// standing.
+async fn finish_refund(state: &Arc<AppState>, mut filter: mongodb::bson::Document, succeeded: bool, reason: &str) {
+    filter.insert("status", "refund_pending");
+    let status = if succeeded { "refunded" } else { "completed" };
+    let update = doc! { "$set": { "status": status, "updated_at": DateTime::now() } };
+    let tx = match state.mongo.collection::<Transaction>("transactions").find_one_and_update(filter.clone(), update, None).await {
+        Ok(Some(tx)) => tx,
+        Ok(None) => {
+            tracing::warn!("Refund result for {:?} matched no pending refund", filter);
+            return;
+        }
+        Err(e) => {
+            tracing::error!("Failed to settle refund {:?}: {:?}", filter, e);
+            return;
+        }
+    };
+    if !succeeded {
+        tracing::warn!("Refund of {} failed: {}", tx.checkout_request_id, reason);
+        return;
+    }
+
+    ledger::record_refund(state, &tx).await;
+    revoke_entitlement(state, &tx).await;
+    tracing::info!("Refunded {} ({} cents) to {}", tx.checkout_request_id, tx.amount, tx.user_id);
+}
+
+/// Undoes what `fulfil_transaction` granted for a payment that has been refunded.
+async fn revoke_entitlement(state: &Arc<AppState>, tx: &Transaction) {
+    let Some(purpose) = PaymentPurpose::parse(&tx.transaction_type) else { return };
+    match purpose {
+        PaymentPurpose::Premium => subscriptions::revoke(state, tx).await,
+        PaymentPurpose::Hookup => {
+            let hookup_collection = state.mongo.collection::<HookupAlias>("hookup_aliases");
+            if let Err(e) = hookup_collection.update_one(
+                doc! { "user_id": tx.user_id },
+                doc! { "$set": { "is_verified": false } },
                 None
+            ).await {
+                tracing::error!("Failed to deactivate hookup alias of {} after refund: {:?}", tx.user_id, e);
+            }
+        }
+        PaymentPurpose::Material => revision_materials::revoke_purchase(state, tx).await,
+        PaymentPurpose::MarketplaceBoost => {
+            if let Some(item_id) = tx.target_id {
+                if let Err(e) = marketplace::revoke_boost(state, item_id).await {
+                    tracing::error!("Failed to take back refunded boost on item {}: {:?}", item_id, e);
+                }
+            }
+        }
+        PaymentPurpose::Verification => {
+            // Any other payment still verifies the account
+            let transactions = state.mongo.collection::<Transaction>("transactions");
+            let others = transactions.count_documents(
+                doc! { "user_id": tx.user_id, "status": "completed", "_id": { "$ne": tx.id } },
+                None,
             ).await;
-
-            if tx.transaction_type == "premium" {
-                let now = Utc::now();
-                let duration = match tx.premium_duration.as_deref() {
-                    Some("weekly") => chrono::Duration::days(7),
-                    Some("monthly") => chrono::Duration::days(30),
-                    Some("semi-annual") => chrono::Duration::days(157), // 5 months + 1 week
-                    _ => chrono::Duration::days(30),
-                };
-                let expires_at = now + duration;
-
-                let _ = users.update_one(
-                    doc! { "_id": tx.user_id },
-                    doc! { 
-                        "$set": { 
-                            "is_premium": true, 
-                            "role": "premium",
-                            "premium_expires_at": DateTime::from_chrono(expires_at)
-                        } 
-                    },
-                    None
-                ).await;
-                tracing::info!("User {} upgraded to PREMIUM & verified. Expires: {}", tx.user_id, expires_at);
-            } else if tx.transaction_type == "hookup" {
-                let hookup_collection = state.mongo.collection::<HookupAlias>("hookup_aliases");
-                let _ = hookup_collection.update_one(
-                    doc! { "user_id": tx.user_id },
-                    doc! { "$set": { "is_verified": true } },
-                    None
-                ).await;
-                tracing::info!("User {} activated Hookup Alias & verified account", tx.user_id);
-            } else {
-                tracing::info!("User {} verified via M-Pesa", tx.user_id);
+            if others.is_ok_and(|n| n == 0) {
+                let users = state.mongo.collection::<crate::models::User>("users");
+                let _ = users.update_one(doc! { "_id": tx.user_id }, doc! { "$set": { "is_verified": false } }, None).await;
             }
         }
-    } else {
-        // Failed
-        let filter = doc! { "checkout_request_id": checkout_id };
-        let update = doc! { "$set": { "status": "failed", "updated_at": DateTime::now() } };
-        let _ = tx_collection.update_one(filter, update, None).await;
+        PaymentPurpose::Donation => {}
     }
+}
 
-    StatusCode::OK
+/// Admin: refunds a completed payment through the provider that took it.
+pub async fn refund_payment_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(checkout_id): Path<String>,
+    Json(payload): Json<RefundPaymentRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    require_admin(user.user_id, &state).await?;
+    let collection = state.mongo.collection::<Transaction>("transactions");
+    let reason = payload.reason.unwrap_or_else(|| "Refunded by an admin".to_string());
+
+    let tx = collection.find_one(doc! { "checkout_request_id": &checkout_id }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Transaction not found"}))))?;
+    if tx.provider != state.payments.name() {
+        return Err((StatusCode::CONFLICT, Json(json!({"error": format!("Paid through {}, which isn't the active provider", tx.provider)}))));
+    }
+
+    let filter = doc! { "checkout_request_id": &checkout_id, "status": "completed" };
+    let claim = doc! { "$set": { "status": "refund_pending", "updated_at": DateTime::now() } };
+    let tx = collection.find_one_and_update(filter, claim, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::CONFLICT, Json(json!({"error": "Only completed payments can be refunded"}))))?;
+
+    let request = RefundRequest {
+        reference: tx.checkout_request_id.clone(),
+        receipt: tx.mpesa_receipt_number.clone(),
+        phone: tx.phone_number.clone(),
+        amount_kes: tx.amount / 100,
+        reason: reason.clone(),
+        callback_url: callback_url("/api/payments/callback"),
+    };
+    let (refund_reference, done) = match state.payments.refund(&request).await {
+        Ok(RefundStatus::Completed { reference }) => (reference, true),
+        Ok(RefundStatus::Pending { reference }) => (reference, false),
+        Err(e) => {
+            let restore = doc! { "$set": { "status": "completed", "updated_at": DateTime::now() } };
+            let _ = collection.update_one(doc! { "checkout_request_id": &checkout_id, "status": "refund_pending" }, restore, None).await;
+            return Err((StatusCode::BAD_GATEWAY, Json(json!({"error": format!("Refund failed: {}", e)}))));
+        }
+    };
+    let _ = collection.update_one(
+        doc! { "checkout_request_id": &checkout_id },
+        doc! { "$set": { "refund_reference": &refund_reference } },
+        None,
+    ).await;
+
+    if done {
+        finish_refund(&state, doc! { "checkout_request_id": &checkout_id }, true, &reason).await;
+        return Ok((StatusCode::OK, Json(json!({ "message": "Payment refunded", "refund_reference": refund_reference }))));
+    }
+    Ok((StatusCode::ACCEPTED, Json(json!({ "message": "Refund requested", "refund_reference": refund_reference }))))
 }
 
 pub async fn get_transaction_status(
@@ -400,7 +529,26 @@ pub async fn get_transaction_status(
         Some(mut row) => {
             let mut error_msg = None;
 
-            // 1. Check for manual timeout (90s + 10s = 100s)
+            // 1. If it's still pending, ask the provider directly
+            if row.status == "pending" {
+                match state.payments.query_status(&id).await {
+                    Ok(PaymentStatus::Completed { receipt }) => {
+                        row.status = "completed".to_string();
+                        if let Some(tx) = complete_transaction(&state, &id, receipt).await {
+                            fulfil_transaction(&state, &tx).await;
+                        }
+                    }
+                    Ok(PaymentStatus::Failed { reason }) => {
+                        fail_transaction(&state, &id).await;
+                        row.status = "failed".to_string();
+                        error_msg = Some(reason);
+                    }
+                    Ok(PaymentStatus::Pending) => {}
+                    Err(e) => tracing::debug!("Status query for {} failed: {}", id, e),
+                }
+            }
+
+            // 2. Give up on it after the STK prompt's lifetime (90s + 10s = 100s)
             let created_at_ms = row.created_at.timestamp_millis();
             let now_ms = Utc::now().timestamp_millis();
             let elapsed_sec = (now_ms - created_at_ms) / 1000;
@@ -409,65 +557,7 @@ pub async fn get_transaction_status(
                 tracing::warn!("Checkout {} timed out after {}s", id, elapsed_sec);
                 row.status = "failed".to_string();
                 error_msg = Some("Transaction timed out. Please try again.".to_string());
-                
-                let filter = doc! { "checkout_request_id": &id };
-                let update = doc! { "$set": { "status": "failed", "updated_at": DateTime::now() } };
-                let _ = collection.update_one(filter, update, None).await;
-            }
-
-            // 2. If it's still pending, try querying M-Pesa directly
-            if row.status == "pending" {
-                if let Some((new_status, desc)) = query_stk_status_mpesa(&state, &id).await {
-                    row.status = new_status.clone();
-                    error_msg = if row.status == "failed" { Some(desc) } else { None };
-                    
-                    // Update DB with new status from query
-                    let filter = doc! { "checkout_request_id": &id };
-                    let update = doc! { "$set": { "status": &row.status, "updated_at": DateTime::now() } };
-                    let _ = collection.update_one(filter, update, None).await;
-
-                    // If it just completed, update user
-                    if row.status == "completed" {
-                        let users = state.mongo.collection::<crate::models::User>("users");
-                        
-                        // ANY successful payment verifies the account
-                        let _ = users.update_one(
-                            doc! { "_id": row.user_id },
-                            doc! { "$set": { "is_verified": true } },
-                            None
-                        ).await;
-
-                        if row.transaction_type == "premium" {
-                            let now = Utc::now();
-                            let duration = match row.premium_duration.as_deref() {
-                                Some("weekly") => chrono::Duration::days(7),
-                                Some("monthly") => chrono::Duration::days(30),
-                                Some("semi-annual") => chrono::Duration::days(157),
-                                _ => chrono::Duration::days(30),
-                            };
-                            let expires_at = now + duration;
-
-                            let _ = users.update_one(
-                                doc! { "_id": row.user_id },
-                                doc! { 
-                                    "$set": { 
-                                        "is_premium": true, 
-                                        "role": "premium",
-                                        "premium_expires_at": DateTime::from_chrono(expires_at)
-                                    } 
-                                },
-                                None
-                            ).await;
-                        } else if row.transaction_type == "hookup" {
-                            let hookup_collection = state.mongo.collection::<HookupAlias>("hookup_aliases");
-                            let _ = hookup_collection.update_one(
-                                doc! { "user_id": row.user_id },
-                                doc! { "$set": { "is_verified": true } },
-                                None
-                            ).await;
-                        }
-                    }
-                }
+                fail_transaction(&state, &id).await;
             }
 
             Ok(Json(json!({
@@ -537,11 +627,118 @@ pub async fn get_recent_donors(
     Ok(Json(donors))
 }
 
+// --- Reconciliation ---
+
+/// Nightly sweep that asks the provider about payments whose callback never arrived.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconcilePaymentsJob;
+
+impl Job for ReconcilePaymentsJob {
+    const KIND: &'static str = "payments.reconcile";
+    const MAX_ATTEMPTS: u32 = 3;
+    const TIMEOUT_SECS: u64 = 1800;
+}
+
+const RECONCILE_MIN_AGE_MINS: i64 = 10;
+const RECONCILE_BATCH: i64 = 500;
+const RECONCILE_GIVE_UP_HOURS: i64 = 48;
+
+/// Re-queries stuck `pending` transactions, plus recent `failed` ones that only failed
+/// because the client stopped waiting (no callback was ever received for them).
+pub async fn run_reconcile_payments(state: Arc<AppState>) -> Result<(), JobError> {
+    use futures::StreamExt;
+
+    let collection = state.mongo.collection::<Transaction>("transactions");
+    let callbacks = state.mongo.collection::<PaymentCallback>("payment_callbacks");
+    let now = Utc::now();
+    let settled_before = DateTime::from_chrono(now - chrono::Duration::minutes(RECONCILE_MIN_AGE_MINS));
+    let recent = DateTime::from_chrono(now - chrono::Duration::hours(24));
+    let give_up_before = DateTime::from_chrono(now - chrono::Duration::hours(RECONCILE_GIVE_UP_HOURS));
+
+    let filter = doc! {
+        "created_at": { "$lt": settled_before },
+        "$or": [
+            { "status": "pending" },
+            { "status": "failed", "created_at": { "$gte": recent } },
+        ],
+    };
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "created_at": 1 })
+        .limit(RECONCILE_BATCH)
+        .build();
+    let mut cursor = collection.find(filter, options).await?;
+
+    let (mut completed, mut failed) = (0, 0);
+    while let Some(Ok(tx)) = cursor.next().await {
+        let id = tx.checkout_request_id.clone();
+        if tx.status == "failed" && callbacks.count_documents(doc! { "checkout_request_id": &id }, None).await? > 0 {
+            continue; // The provider told us it failed
+        }
+
+        match state.payments.query_status(&id).await {
+            Ok(PaymentStatus::Completed { receipt }) => {
+                if let Some(tx) = complete_transaction(&state, &id, receipt).await {
+                    fulfil_transaction(&state, &tx).await;
+                    completed += 1;
+                }
+            }
+            Ok(PaymentStatus::Failed { .. }) if tx.status == "pending" => {
+                fail_transaction(&state, &id).await;
+                failed += 1;
+            }
+            Ok(PaymentStatus::Pending) | Err(_) if tx.status == "pending" && tx.created_at < give_up_before => {
+                tracing::warn!("Giving up on checkout {} after {}h", id, RECONCILE_GIVE_UP_HOURS);
+                fail_transaction(&state, &id).await;
+                failed += 1;
+            }
+            _ => {}
+        }
+        // Providers rate-limit their query APIs
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    }
+
+    if completed + failed > 0 {
+        tracing::info!("💳 Reconciled payments: {} completed, {} failed", completed, failed);
+    }
+    Ok(())
+}
+
+/// The current user's ledger accounts (e.g. earnings), in KES.
+pub async fn get_balances_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    use futures::StreamExt;
+
+    let accounts = state.mongo.collection::<crate::models::LedgerAccount>("ledger_accounts");
+    let mut cursor = accounts.find(doc! { "owner_id": user.user_id }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+
+    let mut balances = Vec::new();
+    while let Some(Ok(a)) = cursor.next().await {
+        balances.push(json!({
+            "account": a.account,
+            "balance": a.balance as f64 / 100.0,
+            "credited": a.credited as f64 / 100.0,
+            "debited": a.debited as f64 / 100.0,
+        }));
+    }
+    Ok(Json(json!(balances)))
+}
+
 pub fn payment_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/verify", post(initiate_verification_payment))
-        .route("/callback", post(mpesa_callback_handler))
+        .route("/callback", post(payment_callback_handler))
         .route("/status/:id", get(get_transaction_status))
+        .route("/balances", get(get_balances_handler))
         .route("/donations/stats", get(get_donation_stats))
         .route("/donations/recent", get(get_recent_donors))
+        .merge(super::payouts::payout_routes())
+}
+
+/// Mounted under `/api/admin/payments`.
+pub fn payment_admin_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/:id/refund", post(refund_payment_handler))
 }
@@ -0,0 +1,496 @@
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use crate::features::auth::auth_service::AuthUser;
+use crate::features::infrastructure::admin::require_admin;
+use crate::features::infrastructure::db::AppState;
+use crate::features::monetization::ledger;
+use crate::features::monetization::payments::{self, CallbackAuthQuery};
+use crate::features::monetization::provider::{PaymentError, PayoutRequest};
+use crate::features::social::notifications::create_notification;
+use crate::models::{LedgerLine, User, Withdrawal};
+
+// Creator payouts. A withdrawal moves earnings into a per-user hold account, waits for an
+// admin to approve it, then goes out as an M-Pesa B2C payment. Daraja answers on the
+// result URL (paid or failed) or the queue-timeout URL; failures and rejections move the
+// hold back into earnings, successes move it out of `platform:mpesa`. A queue timeout or a
+// request that may or may not have reached Daraja leaves the withdrawal `unknown` with the
+// hold in place: only a later result or an admin who has checked the M-Pesa statement
+// settles it, since releasing the hold on a payout that went through pays the user twice.
+
+type ApiResult<T> = Result<T, (StatusCode, Json<serde_json::Value>)>;
+
+fn error(status: StatusCode, msg: impl std::fmt::Display) -> (StatusCode, Json<serde_json::Value>) {
+    (status, Json(json!({"error": msg.to_string()})))
+}
+
+fn internal(e: impl std::fmt::Display) -> (StatusCode, Json<serde_json::Value>) {
+    error(StatusCode::INTERNAL_SERVER_ERROR, e)
+}
+
+/// Smallest withdrawal in cents (`PAYOUT_MIN_KES`, default KES 100).
+pub fn min_withdrawal() -> i64 {
+    std::env::var("PAYOUT_MIN_KES").ok().and_then(|v| v.parse::<i64>().ok()).filter(|v| *v > 0).unwrap_or(100) * 100
+}
+
+/// Checks a withdrawal of `amount` cents against what the user has and is allowed.
+pub fn validate_withdrawal(amount: i64, available: i64, minimum: i64, verified: bool) -> Result<(), String> {
+    if !verified {
+        return Err("Verify your account before withdrawing".to_string());
+    }
+    if amount % 100 != 0 {
+        return Err("Withdrawals must be in whole shillings".to_string());
+    }
+    if amount < minimum {
+        return Err(format!("The minimum withdrawal is KES {}", minimum / 100));
+    }
+    if amount > available {
+        return Err(format!("You only have KES {:.2} available", available as f64 / 100.0));
+    }
+    Ok(())
+}
+
+/// Accepts 07XX/01XX, +254 and 254 forms and returns the 2547XXXXXXXX form Daraja expects.
+pub fn normalize_phone(phone: &str) -> Option<String> {
+    let digits: String = phone.chars().filter(|c| !c.is_whitespace() && *c != '-').collect();
+    let digits = digits.strip_prefix('+').unwrap_or(&digits);
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let local = digits.strip_prefix("254").or_else(|| digits.strip_prefix('0'))?;
+    (local.len() == 9 && (local.starts_with('7') || local.starts_with('1'))).then(|| format!("254{}", local))
+}
+
+// --- B2C results ---
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct B2cResultCallback {
+    #[serde(rename = "Result")]
+    pub result: B2cResult,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct B2cResult {
+    #[serde(rename = "ResultCode")]
+    pub result_code: i64,
+    #[serde(rename = "ResultDesc", default)]
+    pub result_desc: String,
+    #[serde(rename = "OriginatorConversationID", default)]
+    pub originator_conversation_id: String,
+    #[serde(rename = "ConversationID", default)]
+    pub conversation_id: String,
+    #[serde(rename = "TransactionID")]
+    pub transaction_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PayoutOutcome {
+    Paid { receipt: Option<String> },
+    Failed { reason: String },
+}
+
+impl B2cResult {
+    pub fn outcome(&self) -> PayoutOutcome {
+        if self.result_code == 0 {
+            PayoutOutcome::Paid { receipt: self.transaction_id.clone().filter(|t| !t.is_empty()) }
+        } else {
+            PayoutOutcome::Failed { reason: self.result_desc.clone() }
+        }
+    }
+}
+
+// --- Settlement ---
+
+fn withdrawals(state: &AppState) -> mongodb::Collection<Withdrawal> {
+    state.mongo.collection::<Withdrawal>("withdrawals")
+}
+
+fn withdrawal_json(w: &Withdrawal) -> serde_json::Value {
+    json!({
+        "id": w.id.map(|id| id.to_hex()),
+        "user_id": w.user_id.to_hex(),
+        "amount": w.amount as f64 / 100.0,
+        "phone_number": w.phone_number,
+        "status": w.status,
+        "mpesa_receipt_number": w.mpesa_receipt_number,
+        "failure_reason": w.failure_reason,
+        "reviewed_at": w.reviewed_at.map(|d| d.to_chrono().to_rfc3339()),
+        "created_at": w.created_at.to_chrono().to_rfc3339(),
+        "updated_at": w.updated_at.to_chrono().to_rfc3339(),
+    })
+}
+
+/// Returns held funds to earnings after a withdrawal fails or is rejected.
+async fn release_hold(state: &Arc<AppState>, w: &Withdrawal) {
+    let Some(id) = w.id else { return };
+    let lines = vec![
+        LedgerLine { account: ledger::payout_hold_account(w.user_id), amount: -w.amount },
+        LedgerLine { account: ledger::earnings_account(w.user_id), amount: w.amount },
+    ];
+    if let Err(e) = ledger::post_journal(state, &format!("withdrawal:{}:release", id.to_hex()), "withdrawal", lines, None).await {
+        tracing::error!("Failed to release hold for withdrawal {}: {:?}", id, e);
+    }
+}
+
+async fn notify(state: &Arc<AppState>, w: &Withdrawal, kind: &str, content: &str) {
+    // Notifications need an actor other than the recipient; payouts always have a reviewer
+    if let Some(reviewer) = w.reviewed_by {
+        let _ = create_notification(state, w.user_id, reviewer, kind, w.id, content, true).await;
+    }
+}
+
+/// Settles a `processing` or `unknown` withdrawal exactly once, whichever callback arrives first.
+pub async fn settle_withdrawal(state: &Arc<AppState>, filter: mongodb::bson::Document, outcome: PayoutOutcome) {
+    let mut filter = filter;
+    filter.insert("status", doc! { "$in": ["processing", "unknown"] });
+    settle(state, filter, outcome).await;
+}
+
+/// Parks a `processing` withdrawal whose outcome we can't know yet. The hold stays where it
+/// is; a late result from Daraja still settles it, otherwise an admin does.
+async fn mark_unknown(state: &Arc<AppState>, filter: mongodb::bson::Document, reason: &str) {
+    let mut filter = filter;
+    filter.insert("status", "processing");
+    let update = doc! { "$set": { "status": "unknown", "failure_reason": reason, "updated_at": DateTime::now() } };
+    match withdrawals(state).find_one_and_update(filter.clone(), update, None).await {
+        Ok(Some(w)) => tracing::warn!("Withdrawal {:?} outcome unknown ({}); holding funds until it is resolved", w.id, reason),
+        Ok(None) => tracing::debug!("No processing withdrawal for {:?}; already settled", filter),
+        Err(e) => tracing::error!("Failed to park withdrawal {:?}: {:?}", filter, e),
+    }
+}
+
+async fn settle(state: &Arc<AppState>, filter: mongodb::bson::Document, outcome: PayoutOutcome) {
+    let now = DateTime::now();
+    let update = match &outcome {
+        PayoutOutcome::Paid { receipt } => doc! { "$set": { "status": "completed", "mpesa_receipt_number": receipt, "updated_at": now } },
+        PayoutOutcome::Failed { reason } => doc! { "$set": { "status": "failed", "failure_reason": reason, "updated_at": now } },
+    };
+    let w = match withdrawals(state).find_one_and_update(filter.clone(), update, None).await {
+        Ok(Some(w)) => w,
+        Ok(None) => {
+            tracing::debug!("No processing withdrawal for {:?}; already settled", filter);
+            return;
+        }
+        Err(e) => {
+            tracing::error!("Failed to settle withdrawal {:?}: {:?}", filter, e);
+            return;
+        }
+    };
+    let Some(id) = w.id else { return };
+    let amount = format!("KES {:.2}", w.amount as f64 / 100.0);
+
+    match outcome {
+        PayoutOutcome::Paid { .. } => {
+            let lines = vec![
+                LedgerLine { account: ledger::payout_hold_account(w.user_id), amount: -w.amount },
+                LedgerLine { account: ledger::MPESA_ACCOUNT.to_string(), amount: w.amount },
+            ];
+            if let Err(e) = ledger::post_journal(state, &format!("withdrawal:{}:paid", id.to_hex()), "withdrawal", lines, None).await {
+                tracing::error!("Failed to book paid withdrawal {}: {:?}", id, e);
+            }
+            tracing::info!("💸 Paid out withdrawal {} ({}) to {}", id, amount, w.user_id);
+            notify(state, &w, "withdrawal_completed", &format!("Your withdrawal of {} has been sent to M-Pesa", amount)).await;
+        }
+        PayoutOutcome::Failed { reason } => {
+            release_hold(state, &w).await;
+            tracing::warn!("Withdrawal {} failed: {}", id, reason);
+            notify(state, &w, "withdrawal_failed", &format!("Your withdrawal of {} failed and is back in your balance", amount)).await;
+        }
+    }
+}
+
+// --- User handlers ---
+
+#[derive(Deserialize)]
+pub struct WithdrawalRequest {
+    pub amount: f64, // KES
+    pub phone: String,
+}
+
+pub async fn request_withdrawal_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(payload): Json<WithdrawalRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let phone = normalize_phone(&payload.phone)
+        .ok_or_else(|| error(StatusCode::BAD_REQUEST, "Enter a valid Safaricom number"))?;
+    let amount = (payload.amount * 100.0).round() as i64;
+
+    let account = state.mongo.collection::<User>("users")
+        .find_one(doc! { "_id": user.user_id }, None).await.map_err(internal)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+    let earnings = ledger::earnings_account(user.user_id);
+    let available = ledger::account(&state, &earnings).await.map_err(internal)?.map(|a| a.balance).unwrap_or(0);
+    validate_withdrawal(amount, available, min_withdrawal(), account.is_verified)
+        .map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+
+    let open = withdrawals(&state)
+        .count_documents(doc! { "user_id": user.user_id, "status": { "$in": ["pending_review", "processing", "unknown"] } }, None)
+        .await.map_err(internal)?;
+    if open > 0 {
+        return Err(error(StatusCode::CONFLICT, "You already have a withdrawal in progress"));
+    }
+
+    let now = DateTime::now();
+    let withdrawal = Withdrawal {
+        id: Some(ObjectId::new()),
+        user_id: user.user_id,
+        amount,
+        phone_number: phone,
+        status: "pending_review".to_string(),
+        conversation_id: None,
+        mpesa_receipt_number: None,
+        failure_reason: None,
+        reviewed_by: None,
+        reviewed_at: None,
+        created_at: now,
+        updated_at: now,
+    };
+    let id = withdrawal.id.unwrap_or_default();
+
+    // Set the money aside first so it can't be spent twice
+    let key = format!("withdrawal:{}:hold", id.to_hex());
+    let held = ledger::transfer_if_funded(&state, &key, "withdrawal", &earnings, &ledger::payout_hold_account(user.user_id), amount)
+        .await
+        .map_err(|e| internal(format!("{:?}", e)))?;
+    if !held {
+        return Err(error(StatusCode::BAD_REQUEST, "Insufficient balance"));
+    }
+    if let Err(e) = withdrawals(&state).insert_one(&withdrawal, None).await {
+        release_hold(&state, &withdrawal).await;
+        return Err(internal(e));
+    }
+
+    Ok((StatusCode::CREATED, Json(withdrawal_json(&withdrawal))))
+}
+
+pub async fn list_my_withdrawals_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> ApiResult<impl IntoResponse> {
+    let options = mongodb::options::FindOptions::builder().sort(doc! { "created_at": -1 }).limit(50).build();
+    let mut cursor = withdrawals(&state).find(doc! { "user_id": user.user_id }, options).await.map_err(internal)?;
+    let mut items = Vec::new();
+    while let Some(Ok(w)) = cursor.next().await {
+        items.push(withdrawal_json(&w));
+    }
+    Ok(Json(json!(items)))
+}
+
+// --- Daraja callbacks ---
+
+pub async fn b2c_result_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(auth): Query<CallbackAuthQuery>,
+    Json(payload): Json<B2cResultCallback>,
+) -> StatusCode {
+    if let Err(status) = payments::check_callback(&headers, auth.token.as_deref()) {
+        return status;
+    }
+    let result = payload.result;
+    // We send the withdrawal id as the originator id; fall back to Daraja's own id
+    let filter = match ObjectId::parse_str(&result.originator_conversation_id) {
+        Ok(id) => doc! { "_id": id },
+        Err(_) if !result.conversation_id.is_empty() => doc! { "conversation_id": &result.conversation_id },
+        Err(_) => {
+            tracing::warn!("B2C result for unknown originator {}", result.originator_conversation_id);
+            return StatusCode::OK;
+        }
+    };
+    settle_withdrawal(&state, filter, result.outcome()).await;
+    StatusCode::OK
+}
+
+/// Daraja's queue timed out. That says nothing about whether the payment went through, so
+/// the withdrawal waits, still holding the funds, for a result or an admin.
+pub async fn b2c_timeout_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(auth): Query<CallbackAuthQuery>,
+    Json(payload): Json<serde_json::Value>,
+) -> StatusCode {
+    if let Err(status) = payments::check_callback(&headers, auth.token.as_deref()) {
+        return status;
+    }
+    let body = payload.get("Result").unwrap_or(&payload);
+    let filter = match (body["OriginatorConversationID"].as_str().and_then(|s| ObjectId::parse_str(s).ok()), body["ConversationID"].as_str()) {
+        (Some(id), _) => doc! { "_id": id },
+        (None, Some(conversation)) => doc! { "conversation_id": conversation },
+        _ => {
+            tracing::warn!("Unmatched B2C timeout: {}", payload);
+            return StatusCode::OK;
+        }
+    };
+    mark_unknown(&state, filter, "Timed out in the M-Pesa queue").await;
+    StatusCode::OK
+}
+
+// --- Admin ---
+
+#[derive(Deserialize)]
+pub struct WithdrawalListQuery {
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub async fn admin_list_withdrawals_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Query(query): Query<WithdrawalListQuery>,
+) -> ApiResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    let filter = match query.status {
+        Some(status) => doc! { "status": status },
+        None => doc! {},
+    };
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "created_at": 1 })
+        .limit(query.limit.unwrap_or(100).clamp(1, 500))
+        .build();
+    let mut cursor = withdrawals(&state).find(filter, options).await.map_err(internal)?;
+    let mut items = Vec::new();
+    while let Some(Ok(w)) = cursor.next().await {
+        items.push(withdrawal_json(&w));
+    }
+    Ok(Json(json!(items)))
+}
+
+async fn claim_for_review(state: &Arc<AppState>, id: &str, admin: ObjectId, status: &str) -> ApiResult<Withdrawal> {
+    let oid = ObjectId::parse_str(id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid withdrawal ID"))?;
+    let now = DateTime::now();
+    let options = mongodb::options::FindOneAndUpdateOptions::builder()
+        .return_document(mongodb::options::ReturnDocument::After)
+        .build();
+    withdrawals(state)
+        .find_one_and_update(
+            doc! { "_id": oid, "status": "pending_review" },
+            doc! { "$set": { "status": status, "reviewed_by": admin, "reviewed_at": now, "updated_at": now } },
+            options,
+        )
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| error(StatusCode::CONFLICT, "Withdrawal is not awaiting review"))
+}
+
+pub async fn approve_withdrawal_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    let w = claim_for_review(&state, &id, user.user_id, "processing").await?;
+    let Some(wid) = w.id else { return Err(internal("Withdrawal has no id")) };
+
+    let request = PayoutRequest {
+        reference: wid.to_hex(),
+        phone: w.phone_number.clone(),
+        amount_kes: w.amount / 100,
+        remarks: "KaruTeens creator payout".to_string(),
+        result_url: payments::callback_url("/api/payments/b2c/result"),
+        timeout_url: payments::callback_url("/api/payments/b2c/timeout"),
+    };
+    match state.payments.send_b2c(&request).await {
+        Ok(conversation_id) => {
+            let _ = withdrawals(&state).update_one(
+                doc! { "_id": wid },
+                doc! { "$set": { "conversation_id": &conversation_id, "updated_at": DateTime::now() } },
+                None,
+            ).await;
+            Ok(Json(json!({ "message": "Payout sent to M-Pesa", "conversation_id": conversation_id })))
+        }
+        Err(PaymentError::Indeterminate(reason)) => {
+            mark_unknown(&state, doc! { "_id": wid }, &reason).await;
+            Ok(Json(json!({
+                "message": "M-Pesa didn't confirm the payout; it stays on hold until the result arrives or it is resolved",
+                "status": "unknown",
+            })))
+        }
+        // Never sent or refused outright: no money moved
+        Err(e) => {
+            let reason = e.to_string();
+            settle_withdrawal(&state, doc! { "_id": wid }, PayoutOutcome::Failed { reason: reason.clone() }).await;
+            Err(error(StatusCode::BAD_GATEWAY, format!("M-Pesa didn't take the payout: {}", reason)))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ResolveWithdrawalRequest {
+    pub paid: bool,
+    pub receipt: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// Settles an `unknown` withdrawal by hand, after checking the M-Pesa statement.
+pub async fn resolve_withdrawal_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(payload): Json<ResolveWithdrawalRequest>,
+) -> ApiResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    let oid = ObjectId::parse_str(&id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid withdrawal ID"))?;
+    let pending = withdrawals(&state)
+        .count_documents(doc! { "_id": oid, "status": "unknown" }, None)
+        .await
+        .map_err(internal)?;
+    if pending == 0 {
+        return Err(error(StatusCode::CONFLICT, "Withdrawal is not awaiting resolution"));
+    }
+    let outcome = if payload.paid {
+        PayoutOutcome::Paid { receipt: payload.receipt.filter(|r| !r.trim().is_empty()) }
+    } else {
+        PayoutOutcome::Failed { reason: payload.reason.unwrap_or_else(|| "Not paid by M-Pesa".to_string()) }
+    };
+    tracing::info!("Admin {} resolved withdrawal {} as {:?}", user.user_id, oid, outcome);
+    settle(&state, doc! { "_id": oid, "status": "unknown" }, outcome).await;
+    Ok(Json(json!({ "message": "Withdrawal resolved" })))
+}
+
+#[derive(Deserialize)]
+pub struct RejectWithdrawalRequest {
+    pub reason: Option<String>,
+}
+
+pub async fn reject_withdrawal_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(payload): Json<RejectWithdrawalRequest>,
+) -> ApiResult<impl IntoResponse> {
+    require_admin(user.user_id, &state).await?;
+    let w = claim_for_review(&state, &id, user.user_id, "rejected").await?;
+    let reason = payload.reason.unwrap_or_else(|| "Rejected by an admin".to_string());
+    let _ = withdrawals(&state).update_one(doc! { "_id": w.id }, doc! { "$set": { "failure_reason": &reason } }, None).await;
+
+    release_hold(&state, &w).await;
+    notify(&state, &w, "withdrawal_rejected", &format!("Your withdrawal was declined: {}", reason)).await;
+    Ok(Json(json!({ "message": "Withdrawal rejected" })))
+}
+
+/// Mounted under `/api/payments`.
+pub fn payout_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/withdrawals", get(list_my_withdrawals_handler).post(request_withdrawal_handler))
+        .route("/b2c/result", post(b2c_result_handler))
+        .route("/b2c/timeout", post(b2c_timeout_handler))
+}
+
+/// Mounted under `/api/admin/withdrawals`.
+pub fn payout_admin_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(admin_list_withdrawals_handler))
+        .route("/:id/approve", post(approve_withdrawal_handler))
+        .route("/:id/reject", post(reject_withdrawal_handler))
+        .route("/:id/resolve", post(resolve_withdrawal_handler))
+}
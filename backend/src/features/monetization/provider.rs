@@ -0,0 +1,131 @@
+use axum::async_trait;
+use std::sync::Arc;
+use dashmap::DashMap;
+use crate::features::monetization::daraja::DarajaProvider;
+use crate::features::monetization::mock_provider::MockProvider;
+
+// Payment providers, for money in and creator payouts out. Handlers only talk to
+// `AppState.payments`; which provider that is comes from PAYMENT_PROVIDER, so adding
+// Airtel Money or a card processor means another implementation here rather than changes
+// to the payment flows.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymentRequest {
+    /// Payer's phone in 2547XXXXXXXX form.
+    pub phone: String,
+    /// Whole shillings; mobile money doesn't take cents.
+    pub amount_kes: i64,
+    pub description: String,
+    /// Where the provider should send the result.
+    pub callback_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaymentStatus {
+    Pending,
+    Completed { receipt: Option<String> },
+    Failed { reason: String },
+}
+
+/// The result of a payment, as reported asynchronously by the provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymentResult {
+    /// The id returned from `initiate` (our `checkout_request_id`).
+    pub reference: String,
+    pub status: PaymentStatus,
+    /// The provider's own result code, kept for the audit trail.
+    pub result_code: i32,
+    pub result_desc: String,
+    /// What was actually paid, in cents, when the provider says.
+    pub amount: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallbackEvent {
+    Payment(PaymentResult),
+    /// `reference` is the id returned from `refund`.
+    Refund { reference: String, succeeded: bool, reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefundRequest {
+    /// The original payment's `initiate` reference.
+    pub reference: String,
+    /// The provider's receipt for the original payment, if it gave one.
+    pub receipt: Option<String>,
+    pub phone: String,
+    pub amount_kes: i64,
+    pub reason: String,
+    pub callback_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RefundStatus {
+    /// Accepted; the outcome arrives later as a `CallbackEvent::Refund` for this reference.
+    Pending { reference: String },
+    Completed { reference: String },
+}
+
+/// A payout to a phone (M-Pesa B2C).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayoutRequest {
+    /// Our id for the payout, echoed back in its result (the withdrawal id).
+    pub reference: String,
+    /// Payee's phone in 2547XXXXXXXX form.
+    pub phone: String,
+    pub amount_kes: i64,
+    pub remarks: String,
+    pub result_url: String,
+    pub timeout_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaymentError {
+    /// The provider refused the request (bad number, insufficient float, ...).
+    Rejected(String),
+    /// Couldn't reach the provider or it misbehaved; worth trying again.
+    Unavailable(String),
+    /// The request may have reached the provider, so it may still go through.
+    Indeterminate(String),
+    /// Not something this provider understands (e.g. another provider's callback).
+    InvalidCallback(String),
+}
+
+impl std::fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaymentError::Rejected(e) => write!(f, "{}", e),
+            PaymentError::Unavailable(e) => write!(f, "payment provider unavailable: {}", e),
+            PaymentError::Indeterminate(e) => write!(f, "outcome unknown: {}", e),
+            PaymentError::InvalidCallback(e) => write!(f, "invalid callback: {}", e),
+        }
+    }
+}
+
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Asks the payer to pay (e.g. an STK prompt) and returns the reference to track it by.
+    async fn initiate(&self, request: &PaymentRequest) -> Result<String, PaymentError>;
+    /// Asks the provider directly, for when a callback is late or lost.
+    async fn query_status(&self, reference: &str) -> Result<PaymentStatus, PaymentError>;
+    /// Turns a callback body into an event; doesn't check where it came from.
+    fn parse_callback(&self, body: &serde_json::Value) -> Result<CallbackEvent, PaymentError>;
+    async fn refund(&self, request: &RefundRequest) -> Result<RefundStatus, PaymentError>;
+    /// Pays out to a phone and returns the provider's id for it; the result arrives on the
+    /// request's result URL. Only `Indeterminate` means the money may have moved.
+    async fn send_b2c(&self, request: &PayoutRequest) -> Result<String, PaymentError>;
+}
+
+/// Picks the provider from `PAYMENT_PROVIDER` (mpesa, mock). Defaults to M-Pesa when unset;
+/// any other value is an error, since guessing could move real money.
+pub fn payment_provider_from_env(http_client: reqwest::Client, mpesa_tokens: Arc<DashMap<String, (String, i64)>>) -> Result<Arc<dyn PaymentProvider>, String> {
+    match std::env::var("PAYMENT_PROVIDER").unwrap_or_else(|_| "mpesa".to_string()).trim() {
+        "mpesa" => Ok(Arc::new(DarajaProvider::new(http_client, mpesa_tokens))),
+        "mock" => {
+            tracing::warn!("⚠️ PAYMENT_PROVIDER=mock: payments and payouts are simulated and no money moves");
+            Ok(Arc::new(MockProvider::new().with_http_delivery(http_client)))
+        }
+        other => Err(format!("Unknown PAYMENT_PROVIDER '{}'; expected mpesa or mock", other)),
+    }
+}
@@ -0,0 +1,452 @@
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post, put},
+    Router,
+};
+use chrono::{Duration, Utc};
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use crate::features::auth::auth_service::AuthUser;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::jobs::{Job, JobError};
+use crate::features::monetization::entitlements;
+use crate::features::monetization::payments::{self, PaymentPurpose};
+use crate::features::social::notifications::create_system_notification;
+use crate::models::{Subscription, Transaction, User};
+
+// Premium subscriptions. A paid "premium" transaction starts or extends a period; when it
+// ends the subscription sits in a grace period (still premium) for PREMIUM_GRACE_DAYS, and
+// after that the expiry job takes premium away. Renewing inside the grace period carries on
+// from the old end date. M-Pesa can't charge without the payer, so "auto-renew" means we
+// send the STK prompt ourselves when the period ends.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Plan {
+    Weekly,
+    Monthly,
+    Semester,
+}
+
+impl Plan {
+    pub const ALL: [Plan; 3] = [Plan::Weekly, Plan::Monthly, Plan::Semester];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Plan::Weekly => "weekly",
+            Plan::Monthly => "monthly",
+            Plan::Semester => "semester",
+        }
+    }
+
+    /// Also accepts "semi-annual", which older clients send for the semester plan.
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "weekly" => Plan::Weekly,
+            "monthly" => Plan::Monthly,
+            "semester" | "semi-annual" => Plan::Semester,
+            _ => return None,
+        })
+    }
+
+    pub fn price_kes(&self) -> i64 {
+        match self {
+            Plan::Weekly => 10,
+            Plan::Monthly => 40,
+            Plan::Semester => 200,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            Plan::Weekly => Duration::days(7),
+            Plan::Monthly => Duration::days(30),
+            Plan::Semester => Duration::days(157), // 5 months + 1 week
+        }
+    }
+}
+
+/// How long lapsed premium is still honoured (`PREMIUM_GRACE_DAYS`, default 3).
+pub fn grace_period() -> Duration {
+    Duration::days(std::env::var("PREMIUM_GRACE_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(3).max(0))
+}
+
+/// How far ahead of the end date the renewal reminder goes out (`PREMIUM_REMINDER_DAYS`, default 2).
+pub fn reminder_window() -> Duration {
+    Duration::days(std::env::var("PREMIUM_REMINDER_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(2).max(0))
+}
+
+/// The period a payment made `now` buys. A subscription that's still running or in its
+/// grace period is extended from its end date; a lapsed one starts over.
+pub fn next_period(
+    now: chrono::DateTime<Utc>,
+    current_end: Option<chrono::DateTime<Utc>>,
+    grace: Duration,
+    plan: Plan,
+) -> (chrono::DateTime<Utc>, chrono::DateTime<Utc>) {
+    let start = match current_end {
+        Some(end) if now < end + grace => end,
+        _ => now,
+    };
+    (start, start + plan.duration())
+}
+
+fn subscriptions(state: &AppState) -> mongodb::Collection<Subscription> {
+    state.mongo.collection::<Subscription>("subscriptions")
+}
+
+/// Fulfilment hook for "premium" payments. Safe to run again for the same transaction.
+pub async fn activate(state: &Arc<AppState>, tx: &Transaction) {
+    let plan = tx.premium_duration.as_deref().and_then(Plan::parse).unwrap_or(Plan::Monthly);
+    let now = Utc::now();
+
+    let existing = subscriptions(state).find_one(doc! { "user_id": tx.user_id }, None).await.ok().flatten();
+    if existing.as_ref().is_some_and(|s| tx.id.is_some() && s.last_transaction_id == tx.id) {
+        return;
+    }
+    let current_end = existing.as_ref().map(|s| s.current_period_end.to_chrono());
+    let (start, end) = next_period(now, current_end, grace_period(), plan);
+
+    let update = doc! {
+        "$set": {
+            "plan": plan.as_str(),
+            "status": "active",
+            "current_period_start": DateTime::from_chrono(start),
+            "current_period_end": DateTime::from_chrono(end),
+            "last_transaction_id": tx.id,
+            "updated_at": DateTime::now(),
+        },
+        "$setOnInsert": { "created_at": DateTime::now(), "auto_renew": false },
+    };
+    let upsert = mongodb::options::UpdateOptions::builder().upsert(true).build();
+    if let Err(e) = subscriptions(state).update_one(doc! { "user_id": tx.user_id }, update, upsert).await {
+        tracing::error!("Failed to record {} subscription for {}: {:?}", plan.as_str(), tx.user_id, e);
+    }
+
+    let users = state.mongo.collection::<User>("users");
+    let _ = users.update_one(
+        doc! { "_id": tx.user_id },
+        doc! { "$set": { "is_premium": true, "premium_expires_at": DateTime::from_chrono(end) } },
+        None,
+    ).await;
+    // Staff keep their own role
+    let _ = users.update_one(
+        doc! { "_id": tx.user_id, "role": { "$nin": ["admin", "superadmin"] } },
+        doc! { "$set": { "role": "premium" } },
+        None,
+    ).await;
+    tracing::info!("User {} is PREMIUM ({}) until {}", tx.user_id, plan.as_str(), end);
+}
+
+/// What's left of a period after refunding a `plan` payment: the refunded time comes off the
+/// end, and `None` means nothing paid for is left so premium ends now, without a grace period.
+pub fn period_end_after_refund(
+    now: chrono::DateTime<Utc>,
+    current_end: chrono::DateTime<Utc>,
+    plan: Plan,
+) -> Option<chrono::DateTime<Utc>> {
+    Some(current_end - plan.duration()).filter(|end| *end > now)
+}
+
+/// Refund hook for "premium" payments: takes back the time the payment bought.
+pub async fn revoke(state: &Arc<AppState>, tx: &Transaction) {
+    let plan = tx.premium_duration.as_deref().and_then(Plan::parse).unwrap_or(Plan::Monthly);
+    let sub = match subscriptions(state).find_one(doc! { "user_id": tx.user_id }, None).await {
+        Ok(Some(sub)) => sub,
+        Ok(None) => {
+            tracing::warn!("Refunded premium payment {} has no subscription", tx.checkout_request_id);
+            return;
+        }
+        Err(e) => {
+            tracing::error!("Failed to load subscription for refund {}: {:?}", tx.checkout_request_id, e);
+            return;
+        }
+    };
+
+    let users = state.mongo.collection::<User>("users");
+    let current_end = sub.current_period_end.to_chrono();
+    let result = match period_end_after_refund(Utc::now(), current_end, plan) {
+        Some(end) => {
+            let _ = subscriptions(state).update_one(
+                doc! { "_id": sub.id, "current_period_end": sub.current_period_end },
+                doc! { "$set": { "current_period_end": DateTime::from_chrono(end), "updated_at": DateTime::now() } },
+                None,
+            ).await;
+            users.update_one(
+                doc! { "_id": tx.user_id },
+                doc! { "$set": { "premium_expires_at": DateTime::from_chrono(end) } },
+                None,
+            ).await.map(|_| ())
+        }
+        None => {
+            let now = DateTime::now();
+            let _ = subscriptions(state).update_one(
+                doc! { "_id": sub.id, "current_period_end": sub.current_period_end },
+                doc! { "$set": { "status": "expired", "current_period_end": now, "updated_at": now } },
+                None,
+            ).await;
+            // Staff keep their own role
+            let _ = users.update_one(
+                doc! { "_id": tx.user_id, "role": "premium" },
+                doc! { "$set": { "role": "student" } },
+                None,
+            ).await;
+            users.update_one(
+                doc! { "_id": tx.user_id },
+                doc! { "$set": { "is_premium": false, "premium_expires_at": now } },
+                None,
+            ).await.map(|_| ())
+        }
+    };
+    if let Err(e) = result {
+        tracing::error!("Failed to revoke premium from {} after refund: {:?}", tx.user_id, e);
+    }
+}
+
+/// Sends the STK prompt for `plan` and records the pending transaction.
+async fn start_checkout(state: &Arc<AppState>, user_id: ObjectId, phone: &str, plan: Plan) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+    let checkout_request_id = payments::start_payment(state, phone, plan.price_kes(), "Premium Upgrade").await?;
+
+    let now = DateTime::now();
+    let tx = Transaction {
+        id: None,
+        user_id,
+        phone_number: phone.to_string(),
+        amount: plan.price_kes() * 100,
+        mpesa_receipt_number: None,
+        checkout_request_id: checkout_request_id.clone(),
+        status: "pending".to_string(),
+        provider: state.payments.name().to_string(),
+        refund_reference: None,
+        refund_due: None,
+        transaction_type: PaymentPurpose::Premium.as_str().to_string(),
+        premium_duration: Some(plan.as_str().to_string()),
+        target_id: None,
+        created_at: now,
+        updated_at: now,
+    };
+    state.mongo.collection::<Transaction>("transactions").insert_one(tx, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    Ok(checkout_request_id)
+}
+
+fn subscription_json(s: &Subscription) -> serde_json::Value {
+    json!({
+        "plan": s.plan,
+        "status": s.status,
+        "current_period_start": s.current_period_start.to_chrono().to_rfc3339(),
+        "current_period_end": s.current_period_end.to_chrono().to_rfc3339(),
+        "grace_ends_at": (s.current_period_end.to_chrono() + grace_period()).to_rfc3339(),
+        "auto_renew": s.auto_renew,
+        "renewal_phone": s.renewal_phone,
+    })
+}
+
+// --- Handlers ---
+
+pub async fn list_plans_handler() -> impl IntoResponse {
+    let plans: Vec<_> = Plan::ALL.iter().map(|p| json!({
+        "plan": p.as_str(),
+        "price": p.price_kes(),
+        "days": p.duration().num_days(),
+    })).collect();
+    Json(json!({ "plans": plans, "grace_days": grace_period().num_days() }))
+}
+
+pub async fn get_my_subscription_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let subscription = subscriptions(&state).find_one(doc! { "user_id": user.user_id }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    let granted = entitlements::entitlements(&state, user.user_id).await;
+    Ok(Json(json!({
+        "subscription": subscription.as_ref().map(subscription_json),
+        "premium": granted.premium,
+        "in_grace": granted.in_grace,
+        "entitlements": granted.granted(),
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct CheckoutRequest {
+    pub plan: String,
+    pub phone: String,
+}
+
+pub async fn checkout_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(payload): Json<CheckoutRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let plan = Plan::parse(&payload.plan)
+        .ok_or((StatusCode::BAD_REQUEST, Json(json!({"error": "Unknown plan"}))))?;
+    let checkout_request_id = start_checkout(&state, user.user_id, &payload.phone, plan).await?;
+    Ok((StatusCode::OK, Json(json!({
+        "message": "STK Push initiated",
+        "checkout_request_id": checkout_request_id,
+        "amount": plan.price_kes()
+    }))))
+}
+
+#[derive(Deserialize)]
+pub struct AutoRenewRequest {
+    pub auto_renew: bool,
+    pub phone: Option<String>,
+}
+
+pub async fn set_auto_renew_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(payload): Json<AutoRenewRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let existing = subscriptions(&state).find_one(doc! { "user_id": user.user_id }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "You don't have a subscription"}))))?;
+
+    let phone = payload.phone.or(existing.renewal_phone);
+    if payload.auto_renew && phone.is_none() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "A phone number is required to auto-renew"}))));
+    }
+    subscriptions(&state).update_one(
+        doc! { "user_id": user.user_id },
+        doc! { "$set": { "auto_renew": payload.auto_renew, "renewal_phone": &phone, "updated_at": DateTime::now() } },
+        None,
+    ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    Ok(Json(json!({ "auto_renew": payload.auto_renew, "renewal_phone": phone })))
+}
+
+pub fn subscription_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/plans", get(list_plans_handler))
+        .route("/me", get(get_my_subscription_handler))
+        .route("/me/auto-renew", put(set_auto_renew_handler))
+        .route("/checkout", post(checkout_handler))
+}
+
+// --- Jobs ---
+
+/// Cron-driven: moves ended periods into grace, and lapsed ones out of premium.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpireSubscriptionsJob;
+
+impl Job for ExpireSubscriptionsJob {
+    const KIND: &'static str = "subscriptions.expire";
+    const MAX_ATTEMPTS: u32 = 3;
+}
+
+/// Cron-driven: tells users their premium is about to end.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionRemindersJob;
+
+impl Job for SubscriptionRemindersJob {
+    const KIND: &'static str = "subscriptions.remind";
+    const MAX_ATTEMPTS: u32 = 3;
+}
+
+pub async fn run_expire_subscriptions(state: Arc<AppState>) -> Result<(), JobError> {
+    let now = Utc::now();
+    let grace = grace_period();
+    let collection = subscriptions(&state);
+
+    // Ended: keep premium for the grace period and prompt for renewal
+    let mut entered_grace = 0;
+    let mut cursor = collection.find(doc! { "status": "active", "current_period_end": { "$lte": DateTime::from_chrono(now) } }, None).await?;
+    while let Some(Ok(sub)) = cursor.next().await {
+        let claimed = collection.update_one(
+            doc! { "_id": sub.id, "status": "active", "current_period_end": sub.current_period_end },
+            doc! { "$set": { "status": "grace", "updated_at": DateTime::now() } },
+            None,
+        ).await?;
+        if claimed.modified_count == 0 {
+            continue;
+        }
+        entered_grace += 1;
+
+        let renewal = match (sub.auto_renew, sub.renewal_phone.as_deref(), Plan::parse(&sub.plan)) {
+            (true, Some(phone), Some(plan)) => Some(start_checkout(&state, sub.user_id, phone, plan).await),
+            _ => None,
+        };
+        let message = match renewal {
+            Some(Ok(_)) => "Your Premium has ended. Check your phone to approve the renewal payment.".to_string(),
+            Some(Err((_, Json(e)))) => {
+                tracing::warn!("Auto-renewal for {} failed: {}", sub.user_id, e["error"]);
+                format!("Your Premium has ended and we couldn't start the renewal. Renew within {} days to keep it.", grace.num_days())
+            }
+            None => format!("Your Premium has ended. Renew within {} days to keep it.", grace.num_days()),
+        };
+        let _ = create_system_notification(&state, sub.user_id, "premium_grace", sub.id, &message, true).await;
+    }
+
+    // Lapsed: the grace period is over
+    let cutoff = DateTime::from_chrono(now - grace);
+    let mut expired = 0;
+    let mut cursor = collection.find(doc! { "status": { "$in": ["active", "grace"] }, "current_period_end": { "$lte": cutoff } }, None).await?;
+    while let Some(Ok(sub)) = cursor.next().await {
+        let claimed = collection.update_one(
+            doc! { "_id": sub.id, "status": { "$in": ["active", "grace"] }, "current_period_end": sub.current_period_end },
+            doc! { "$set": { "status": "expired", "updated_at": DateTime::now() } },
+            None,
+        ).await?;
+        if claimed.modified_count == 0 {
+            continue;
+        }
+        expired += 1;
+        let _ = create_system_notification(&state, sub.user_id, "premium_expired", sub.id, "Your Premium has expired.", true).await;
+    }
+
+    // Flip the user flags, including premium granted without a subscription (e.g. by an admin)
+    let users = state.mongo.collection::<User>("users");
+    let lapsed = doc! { "is_premium": true, "premium_expires_at": { "$lte": cutoff } };
+    let mut demote = lapsed.clone();
+    demote.insert("role", "premium");
+    users.update_many(demote, doc! { "$set": { "role": "student" } }, None).await?;
+    users.update_many(lapsed, doc! { "$set": { "is_premium": false } }, None).await?;
+
+    if entered_grace + expired > 0 {
+        tracing::info!("⭐ Subscriptions: {} entered grace, {} expired", entered_grace, expired);
+    }
+    Ok(())
+}
+
+pub async fn run_subscription_reminders(state: Arc<AppState>) -> Result<(), JobError> {
+    let now = Utc::now();
+    let collection = subscriptions(&state);
+    let filter = doc! {
+        "status": "active",
+        "current_period_end": { "$gt": DateTime::from_chrono(now), "$lte": DateTime::from_chrono(now + reminder_window()) },
+    };
+    let mut cursor = collection.find(filter, None).await?;
+    let mut sent = 0;
+    while let Some(Ok(sub)) = cursor.next().await {
+        if sub.reminded_for == Some(sub.current_period_end) {
+            continue;
+        }
+        // Claim it so overlapping runs don't remind twice
+        let claimed = collection.update_one(
+            doc! { "_id": sub.id, "reminded_for": { "$ne": sub.current_period_end } },
+            doc! { "$set": { "reminded_for": sub.current_period_end } },
+            None,
+        ).await?;
+        if claimed.modified_count == 0 {
+            continue;
+        }
+        let ends = sub.current_period_end.to_chrono().format("%a %d %b");
+        let message = if sub.auto_renew {
+            format!("Your Premium renews on {}. We'll send an M-Pesa prompt then.", ends)
+        } else {
+            format!("Your Premium ends on {}. Renew to keep study rooms and an ad-free feed.", ends)
+        };
+        let _ = create_system_notification(&state, sub.user_id, "premium_renewal_reminder", sub.id, &message, true).await;
+        sent += 1;
+    }
+    if sent > 0 {
+        tracing::info!("⭐ Sent {} premium renewal reminders", sent);
+    }
+    Ok(())
+}
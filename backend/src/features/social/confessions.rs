@@ -18,6 +18,7 @@ use crate::features::infrastructure::error::{AppResult, AppError};
 use mongodb::bson::{doc, oid::ObjectId, DateTime};
 use futures::stream::StreamExt;
 use crate::models::ContentModeration;
+use crate::features::infrastructure::spam::{self, ContentKind};
 
 // --- Models (keeping internal models here for simplicity, or move to models.rs if preferred) ---
 
@@ -69,6 +70,9 @@ pub struct ConfessionComment {
     pub author_id: ObjectId,
     pub author_username: String,
     pub content: String,
+    /// "spam" while held for review; visible otherwise.
+    #[serde(default)]
+    pub status: Option<String>,
     pub created_at: DateTime,
 }
 
@@ -140,6 +144,11 @@ pub async fn create_confession_handler(
     let has_media = payload.media_url.is_some();
     let auto_publish_at = DateTime::from_millis(now.timestamp_millis() + 12 * 3600 * 1000);
     
+    // Score against the real poster even for anonymous confessions
+    let verdict = spam::classify(&state, user.user_id, ContentKind::Confession, &payload.content).await;
+    let flagged = verdict.is_spam(spam::flag_threshold());
+    let spam_text = payload.content.clone();
+
     let author_id = if !payload.is_anonymous.unwrap_or(false) {
         payload.author_id.and_then(|id| ObjectId::parse_str(id).ok()).unwrap_or(user.user_id)
     } else {
@@ -158,13 +167,19 @@ pub async fn create_confession_handler(
         updated_at: now,
         media_url: payload.media_url,
         media_type: payload.media_type,
-        status: if has_media { Some("pending".to_string()) } else { None },
-        auto_publish_at: if has_media { Some(auto_publish_at) } else { None },
+        status: if has_media || flagged { Some("pending".to_string()) } else { None },
+        // Flagged confessions wait for a moderator rather than publishing themselves
+        auto_publish_at: if has_media && !flagged { Some(auto_publish_at) } else { None },
     };
     
     let result = state.mongo.collection::<Confession>("confessions")
         .insert_one(confession, None)
         .await?;
+    if flagged {
+        if let Some(id) = result.inserted_id.as_object_id() {
+            spam::queue_for_review(&state, ContentKind::Confession, id, &spam_text, &verdict).await;
+        }
+    }
     
     Ok((StatusCode::CREATED, Json(IdResponse { 
         id: result.inserted_id.as_object_id().unwrap().to_hex(),
@@ -263,7 +278,7 @@ pub async fn list_confession_comments_handler(
     let oid = ObjectId::parse_str(&id).map_err(|_| AppError::BadRequest("Invalid confession ID".to_string()))?;
     
     let comments_coll = state.mongo.collection::<ConfessionComment>("confession_comments");
-    let mut cursor = comments_coll.find(doc! { "confession_id": oid }, None).await?;
+    let mut cursor = comments_coll.find(doc! { "confession_id": oid, "status": { "$ne": "spam" } }, None).await?;
     
     let mut comments = Vec::new();
     while let Some(comment_res) = cursor.next().await {
@@ -294,24 +309,35 @@ pub async fn add_confession_comment_handler(
     let profile = profiles.find_one(doc! { "user_id": user.user_id }, None).await?
         .ok_or(AppError::NotFound("Profile not found".to_string()))?;
 
+    let verdict = spam::classify(&state, user.user_id, ContentKind::ConfessionComment, &payload.content).await;
+    let flagged = verdict.is_spam(spam::flag_threshold());
+
     let comment = ConfessionComment {
         id: None,
         confession_id: oid,
         author_id: user.user_id,
         author_username: profile.username,
         content: payload.content,
+        // Held comments stay hidden until a moderator approves them
+        status: if flagged { Some("spam".to_string()) } else { None },
         created_at: DateTime::now(),
     };
     
-    state.mongo.collection::<ConfessionComment>("confession_comments")
-        .insert_one(comment, None)
+    let result = state.mongo.collection::<ConfessionComment>("confession_comments")
+        .insert_one(&comment, None)
         .await?;
+    if flagged {
+        if let Some(id) = result.inserted_id.as_object_id() {
+            spam::queue_for_review(&state, ContentKind::ConfessionComment, id, &comment.content, &verdict).await;
+        }
+        return Ok((StatusCode::CREATED, Json(MessageResponse { message: "Comment held for review".to_string() })));
+    }
 
     state.mongo.collection::<Confession>("confessions")
         .update_one(doc! { "_id": oid }, doc! { "$inc": { "comments": 1 } }, None)
         .await?;
     
-    Ok(StatusCode::CREATED)
+    Ok((StatusCode::CREATED, Json(MessageResponse { message: "Comment added".to_string() })))
 }
 
 pub async fn report_confession_handler(
@@ -389,6 +415,30 @@ pub async fn admin_list_confessions_handler(
     Ok((StatusCode::OK, Json(confessions_list)))
 }
 
+/// Publishes a confession held for review. Returns false if it wasn't pending.
+pub async fn release_held_confession(state: &Arc<AppState>, id: ObjectId) -> Result<bool, mongodb::error::Error> {
+    let result = state.mongo.collection::<Confession>("confessions")
+        .update_one(
+            doc! { "_id": id, "status": "pending" },
+            doc! { "$set": { "status": "approved", "auto_publish_at": null, "updated_at": DateTime::now() } },
+            None,
+        )
+        .await?;
+    Ok(result.modified_count > 0)
+}
+
+/// Shows a confession comment that was held as spam and counts it on its confession.
+pub async fn release_held_confession_comment(state: &Arc<AppState>, id: ObjectId) -> Result<bool, mongodb::error::Error> {
+    let comment = state.mongo.collection::<ConfessionComment>("confession_comments")
+        .find_one_and_update(doc! { "_id": id, "status": "spam" }, doc! { "$set": { "status": null } }, None)
+        .await?;
+    let Some(comment) = comment else { return Ok(false) };
+    state.mongo.collection::<Confession>("confessions")
+        .update_one(doc! { "_id": comment.confession_id }, doc! { "$inc": { "comments": 1 } }, None)
+        .await?;
+    Ok(true)
+}
+
 pub async fn admin_update_confession_handler(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
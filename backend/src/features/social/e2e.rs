@@ -0,0 +1,546 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post, put},
+    Router,
+    Json,
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument, UpdateOptions};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use p256::PublicKey;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha512};
+use crate::features::auth::auth_service::AuthUser;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::push::decode_base64url;
+use crate::features::social::ws::{send_to_user, WsPayload};
+use crate::models::{Chat, DeviceKeys, OneTimePrekey, Profile, SenderKeyMessage, SignedPrekey};
+
+// Key directory for end-to-end encrypted chats. Each device publishes an identity key, a
+// signed prekey and a batch of one-time prekeys; a sender fetches a bundle per device of the
+// recipient (using up one one-time prekey each) and runs the key agreement itself. Groups use
+// sender keys, which members encrypt pairwise and we relay without being able to read them.
+// The server never sees private keys or plaintext: it checks that keys are well-formed
+// P-256 points and that the signed prekey really is signed by the identity key.
+
+pub const MAX_DEVICES: usize = 5;
+pub const MAX_ONE_TIME_PREKEYS: usize = 100;
+/// Below this many one-time prekeys the device is asked to upload more.
+pub const LOW_PREKEY_THRESHOLD: usize = 10;
+const MAX_CIPHERTEXT_LEN: usize = 16 * 1024;
+/// Same stretching as Signal's numeric fingerprints.
+const FINGERPRINT_ITERATIONS: usize = 5200;
+
+pub fn e2e_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/devices", get(list_devices))
+        .route("/devices/:device_id", put(register_device).delete(remove_device))
+        .route("/devices/:device_id/signed_prekey", put(rotate_signed_prekey))
+        .route("/devices/:device_id/prekeys", post(upload_prekeys))
+        .route("/devices/:device_id/sender_keys", get(take_sender_keys))
+        .route("/users/:user_id/bundles", get(get_bundles))
+        .route("/users/:user_id/safety_number", get(get_safety_number))
+        .route("/chats/:chat_id/sender_keys", post(send_sender_keys))
+}
+
+// --- Key checks ---
+
+/// Decodes a base64 (standard or url-safe) SEC1 P-256 public key.
+pub fn parse_public_key(encoded: &str) -> Result<Vec<u8>, String> {
+    let raw = decode_base64url(encoded).map_err(|_| "Keys must be base64".to_string())?;
+    PublicKey::from_sec1_bytes(&raw).map_err(|_| "Not a P-256 public key".to_string())?;
+    Ok(raw)
+}
+
+/// Checks the signed prekey's signature (ECDSA P-256 / SHA-256 over the raw prekey bytes).
+/// Accepts Web Crypto's raw r||s and DER.
+pub fn verify_signed_prekey(identity_key: &str, prekey: &SignedPrekey) -> Result<(), String> {
+    let identity = parse_public_key(identity_key)?;
+    let public = parse_public_key(&prekey.public_key)?;
+    let verifying = VerifyingKey::from_sec1_bytes(&identity).map_err(|e| e.to_string())?;
+    let raw = decode_base64url(&prekey.signature).map_err(|_| "Signatures must be base64".to_string())?;
+    let signature = Signature::from_slice(&raw)
+        .or_else(|_| Signature::from_der(&raw))
+        .map_err(|_| "Malformed signature".to_string())?;
+    verifying.verify(&public, &signature).map_err(|_| "The signed prekey isn't signed by this identity key".to_string())
+}
+
+pub fn validate_one_time_prekeys(keys: &[OneTimePrekey]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for key in keys {
+        parse_public_key(&key.public_key)?;
+        if !ids.insert(key.key_id) {
+            return Err(format!("Prekey id {} is repeated", key.key_id));
+        }
+    }
+    Ok(())
+}
+
+fn valid_device_id(device_id: &str) -> bool {
+    (1..=64).contains(&device_id.len())
+        && device_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// --- Safety numbers ---
+
+/// A user's 30-digit half of a safety number, over all their devices' identity keys. Device
+/// order doesn't matter; adding, removing or changing a device changes it.
+pub fn fingerprint(user_id: ObjectId, identity_keys: &[String]) -> String {
+    let mut keys: Vec<Vec<u8>> = identity_keys.iter().filter_map(|k| decode_base64url(k).ok()).collect();
+    keys.sort();
+    let joined = keys.concat();
+
+    let mut hash = Sha512::new()
+        .chain_update([0u8, 0u8]) // version
+        .chain_update(&joined)
+        .chain_update(user_id.bytes())
+        .finalize()
+        .to_vec();
+    for _ in 0..FINGERPRINT_ITERATIONS {
+        hash = Sha512::new().chain_update(&hash).chain_update(&joined).finalize().to_vec();
+    }
+
+    hash[..30]
+        .chunks(5)
+        .map(|chunk| {
+            let n = chunk.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+            format!("{:05}", n % 100_000)
+        })
+        .collect()
+}
+
+/// The 60-digit number both people see: the two halves in a fixed order.
+pub fn safety_number(a: &str, b: &str) -> String {
+    if a <= b { format!("{}{}", a, b) } else { format!("{}{}", b, a) }
+}
+
+// --- DTOs ---
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterDeviceRequest {
+    pub identity_key: String,
+    pub signed_prekey: SignedPrekey,
+    #[serde(default)]
+    pub one_time_prekeys: Vec<OneTimePrekey>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadPrekeysRequest {
+    pub one_time_prekeys: Vec<OneTimePrekey>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SenderKeyEnvelope {
+    pub recipient_id: String,
+    pub recipient_device_id: String,
+    pub ciphertext: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendSenderKeysRequest {
+    pub device_id: String,
+    pub messages: Vec<SenderKeyEnvelope>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceSummary {
+    pub device_id: String,
+    pub identity_key: String,
+    pub signed_prekey_id: i64,
+    pub one_time_prekeys_remaining: usize,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrekeyBundle {
+    pub device_id: String,
+    pub identity_key: String,
+    pub signed_prekey: SignedPrekey,
+    pub one_time_prekey: Option<OneTimePrekey>,
+}
+
+type ApiResult<T> = Result<T, (StatusCode, Json<serde_json::Value>)>;
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<serde_json::Value>) {
+    (status, Json(json!({"error": message})))
+}
+
+fn internal(e: impl std::fmt::Display) -> (StatusCode, Json<serde_json::Value>) {
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()})))
+}
+
+fn device_keys(state: &Arc<AppState>) -> mongodb::Collection<DeviceKeys> {
+    state.mongo.collection::<DeviceKeys>("device_keys")
+}
+
+async fn devices_of(state: &Arc<AppState>, user_id: ObjectId) -> ApiResult<Vec<DeviceKeys>> {
+    let mut cursor = device_keys(state).find(doc! { "user_id": user_id }, None).await.map_err(internal)?;
+    let mut devices = Vec::new();
+    while let Some(Ok(d)) = cursor.next().await {
+        devices.push(d);
+    }
+    Ok(devices)
+}
+
+// --- Notifications ---
+
+/// Tells everyone who shares a chat with `user_id` (and their own other devices) that the
+/// user's keys changed, so clients re-fetch bundles and warn about the new safety number.
+/// Anonymous chats are skipped: the event would name the real account behind the alias.
+pub async fn notify_key_change(state: &Arc<AppState>, user_id: ObjectId, device_id: &str, change: &str) {
+    let mut recipients = HashSet::from([user_id]);
+    if let Ok(mut cursor) = state.mongo.collection::<Chat>("chats")
+        .find(doc! { "participants": user_id, "aliases": { "$exists": false } }, None).await
+    {
+        while let Some(Ok(chat)) = cursor.next().await {
+            recipients.extend(chat.participants);
+        }
+    }
+
+    let payload = WsPayload {
+        r#type: "key_changed".to_string(),
+        data: json!({ "user_id": user_id.to_hex(), "device_id": device_id, "change": change }),
+    };
+    for pid in recipients {
+        send_to_user(state, &pid, &payload).await;
+    }
+}
+
+/// Asks the members of an encrypted group to hand out fresh sender keys, e.g. after someone
+/// left (who still holds the old ones) or joined (who has none).
+pub async fn request_sender_key_rotation(state: &Arc<AppState>, chat: &Chat, reason: &str) {
+    let Some(chat_id) = chat.id else { return };
+    if !chat.is_encrypted || !chat.is_group {
+        return;
+    }
+    let payload = WsPayload {
+        r#type: "sender_key_reset".to_string(),
+        data: json!({ "chat_id": chat_id.to_hex(), "reason": reason }),
+    };
+    for pid in &chat.participants {
+        send_to_user(state, pid, &payload).await;
+    }
+}
+
+// --- Handlers ---
+
+async fn list_devices(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> ApiResult<impl IntoResponse> {
+    let devices: Vec<DeviceSummary> = devices_of(&state, user.user_id).await?
+        .into_iter()
+        .map(|d| DeviceSummary {
+            device_id: d.device_id,
+            identity_key: d.identity_key,
+            signed_prekey_id: d.signed_prekey.key_id,
+            one_time_prekeys_remaining: d.one_time_prekeys.len(),
+            updated_at: d.updated_at.to_chrono().to_rfc3339(),
+        })
+        .collect();
+    Ok(Json(devices))
+}
+
+/// Registers a device or replaces its keys. A new identity key on an existing device is a
+/// key change, and everyone the user talks to is told.
+async fn register_device(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(device_id): Path<String>,
+    Json(payload): Json<RegisterDeviceRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if !valid_device_id(&device_id) {
+        return Err(error(StatusCode::BAD_REQUEST, "Device IDs are 1-64 letters, digits, '-' or '_'"));
+    }
+    verify_signed_prekey(&payload.identity_key, &payload.signed_prekey)
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &e))?;
+    if payload.one_time_prekeys.len() > MAX_ONE_TIME_PREKEYS {
+        return Err(error(StatusCode::BAD_REQUEST, &format!("At most {} one-time prekeys", MAX_ONE_TIME_PREKEYS)));
+    }
+    validate_one_time_prekeys(&payload.one_time_prekeys).map_err(|e| error(StatusCode::BAD_REQUEST, &e))?;
+
+    let devices = devices_of(&state, user.user_id).await?;
+    let existing = devices.iter().find(|d| d.device_id == device_id);
+    if existing.is_none() && devices.len() >= MAX_DEVICES {
+        return Err(error(StatusCode::CONFLICT, &format!("At most {} devices can hold encryption keys; remove one first", MAX_DEVICES)));
+    }
+    let change = match existing {
+        None => Some("added"),
+        Some(d) if d.identity_key != payload.identity_key => Some("changed"),
+        Some(_) => None,
+    };
+
+    let signed_prekey = bson::to_bson(&payload.signed_prekey).map_err(internal)?;
+    let one_time_prekeys = bson::to_bson(&payload.one_time_prekeys).map_err(internal)?;
+    let now = DateTime::now();
+    device_keys(&state).update_one(
+        doc! { "user_id": user.user_id, "device_id": &device_id },
+        doc! {
+            "$set": {
+                "identity_key": &payload.identity_key,
+                "signed_prekey": signed_prekey,
+                "one_time_prekeys": one_time_prekeys,
+                "updated_at": now,
+            },
+            "$setOnInsert": { "created_at": now },
+        },
+        UpdateOptions::builder().upsert(true).build(),
+    ).await.map_err(internal)?;
+
+    if let Some(change) = change {
+        notify_key_change(&state, user.user_id, &device_id, change).await;
+    }
+
+    let status = if change == Some("added") { StatusCode::CREATED } else { StatusCode::OK };
+    Ok((status, Json(json!({ "device_id": device_id, "one_time_prekeys": payload.one_time_prekeys.len() }))))
+}
+
+async fn remove_device(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(device_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let result = device_keys(&state)
+        .delete_one(doc! { "user_id": user.user_id, "device_id": &device_id }, None).await
+        .map_err(internal)?;
+    if result.deleted_count == 0 {
+        return Err(error(StatusCode::NOT_FOUND, "Device not found"));
+    }
+    let _ = state.mongo.collection::<SenderKeyMessage>("sender_key_messages")
+        .delete_many(doc! { "recipient_id": user.user_id, "recipient_device_id": &device_id }, None).await;
+
+    notify_key_change(&state, user.user_id, &device_id, "removed").await;
+    Ok(Json(json!({"message": "Device removed"})))
+}
+
+async fn rotate_signed_prekey(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(device_id): Path<String>,
+    Json(payload): Json<SignedPrekey>,
+) -> ApiResult<impl IntoResponse> {
+    let device = device_keys(&state)
+        .find_one(doc! { "user_id": user.user_id, "device_id": &device_id }, None).await
+        .map_err(internal)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Device not found"))?;
+    verify_signed_prekey(&device.identity_key, &payload).map_err(|e| error(StatusCode::BAD_REQUEST, &e))?;
+
+    let signed_prekey = bson::to_bson(&payload).map_err(internal)?;
+    device_keys(&state).update_one(
+        doc! { "_id": device.id },
+        doc! { "$set": { "signed_prekey": signed_prekey, "updated_at": DateTime::now() } },
+        None,
+    ).await.map_err(internal)?;
+
+    Ok(Json(json!({"message": "Signed prekey rotated"})))
+}
+
+async fn upload_prekeys(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(device_id): Path<String>,
+    Json(payload): Json<UploadPrekeysRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let count = payload.one_time_prekeys.len();
+    if count == 0 || count > MAX_ONE_TIME_PREKEYS {
+        return Err(error(StatusCode::BAD_REQUEST, &format!("Upload between 1 and {} prekeys", MAX_ONE_TIME_PREKEYS)));
+    }
+    validate_one_time_prekeys(&payload.one_time_prekeys).map_err(|e| error(StatusCode::BAD_REQUEST, &e))?;
+
+    let filter = doc! { "user_id": user.user_id, "device_id": &device_id };
+    if device_keys(&state).count_documents(filter.clone(), None).await.map_err(internal)? == 0 {
+        return Err(error(StatusCode::NOT_FOUND, "Device not found"));
+    }
+
+    // The size check and the push are one update, so parallel uploads can't overshoot the cap
+    let mut capped = filter;
+    capped.insert("$expr", doc! { "$lte": [{ "$size": "$one_time_prekeys" }, (MAX_ONE_TIME_PREKEYS - count) as i64] });
+    let keys = bson::to_bson(&payload.one_time_prekeys).map_err(internal)?;
+    let updated = device_keys(&state).find_one_and_update(
+        capped,
+        doc! { "$push": { "one_time_prekeys": { "$each": keys } }, "$set": { "updated_at": DateTime::now() } },
+        FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build(),
+    ).await.map_err(internal)?
+        .ok_or_else(|| error(StatusCode::CONFLICT, &format!("A device holds at most {} one-time prekeys", MAX_ONE_TIME_PREKEYS)))?;
+
+    Ok(Json(json!({ "one_time_prekeys_remaining": updated.one_time_prekeys.len() })))
+}
+
+/// One bundle per device of the user, each with a one-time prekey that nobody else will get.
+/// When a device runs out, its bundle comes without one and the signed prekey alone is used.
+async fn get_bundles(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(user_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let target = ObjectId::parse_str(&user_id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid user ID"))?;
+
+    if target != user.user_id {
+        let profile = state.mongo.collection::<Profile>("profiles")
+            .find_one(doc! { "user_id": target }, None).await
+            .map_err(internal)?
+            .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+        if profile.blocked_users.is_some_and(|b| b.contains(&user.user_id)) {
+            return Err(error(StatusCode::FORBIDDEN, "You are blocked by this user"));
+        }
+    }
+
+    let devices = devices_of(&state, target).await?;
+    if devices.is_empty() {
+        return Err(error(StatusCode::NOT_FOUND, "This user hasn't set up encryption yet"));
+    }
+
+    let mut bundles = Vec::new();
+    for device in devices {
+        // $pop returns nothing, so read the document as it was before to see what we took
+        let before = device_keys(&state).find_one_and_update(
+            doc! { "_id": device.id },
+            doc! { "$pop": { "one_time_prekeys": -1 } },
+            FindOneAndUpdateOptions::builder().return_document(ReturnDocument::Before).build(),
+        ).await.map_err(internal)?;
+        let Some(before) = before else { continue };
+
+        let remaining = before.one_time_prekeys.len().saturating_sub(1);
+        if remaining < LOW_PREKEY_THRESHOLD {
+            send_to_user(&state, &target, &WsPayload {
+                r#type: "prekeys_low".to_string(),
+                data: json!({ "device_id": before.device_id, "remaining": remaining }),
+            }).await;
+        }
+
+        bundles.push(PrekeyBundle {
+            one_time_prekey: before.one_time_prekeys.first().cloned(),
+            device_id: before.device_id,
+            identity_key: before.identity_key,
+            signed_prekey: before.signed_prekey,
+        });
+    }
+
+    Ok(Json(bundles))
+}
+
+async fn get_safety_number(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(user_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let target = ObjectId::parse_str(&user_id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid user ID"))?;
+    if target == user.user_id {
+        return Err(error(StatusCode::BAD_REQUEST, "Safety numbers are between two people"));
+    }
+
+    let mine: Vec<String> = devices_of(&state, user.user_id).await?.into_iter().map(|d| d.identity_key).collect();
+    let theirs: Vec<String> = devices_of(&state, target).await?.into_iter().map(|d| d.identity_key).collect();
+    if mine.is_empty() || theirs.is_empty() {
+        return Err(error(StatusCode::NOT_FOUND, "Both people need to set up encryption first"));
+    }
+
+    let number = safety_number(&fingerprint(user.user_id, &mine), &fingerprint(target, &theirs));
+    Ok(Json(json!({ "user_id": target.to_hex(), "safety_number": number })))
+}
+
+/// Relays sender key distribution messages for an encrypted group. Each ciphertext is for one
+/// member's device and is opaque to us; recipients are pinged to come and collect them.
+async fn send_sender_keys(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(chat_id): Path<String>,
+    Json(payload): Json<SendSenderKeysRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let oid = ObjectId::parse_str(&chat_id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid ID"))?;
+    let chat = state.mongo.collection::<Chat>("chats")
+        .find_one(doc! { "_id": oid, "participants": user.user_id }, None).await
+        .map_err(internal)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Chat not found or access denied"))?;
+    if !chat.is_group || !chat.is_encrypted {
+        return Err(error(StatusCode::BAD_REQUEST, "Sender keys are only used in encrypted groups"));
+    }
+
+    let sender_device = device_keys(&state)
+        .count_documents(doc! { "user_id": user.user_id, "device_id": &payload.device_id }, None).await
+        .map_err(internal)?;
+    if sender_device == 0 {
+        return Err(error(StatusCode::BAD_REQUEST, "Register this device's keys first"));
+    }
+    if payload.messages.is_empty() || payload.messages.len() > chat.participants.len() * MAX_DEVICES {
+        return Err(error(StatusCode::BAD_REQUEST, "One message per member device"));
+    }
+
+    let now = DateTime::now();
+    let mut messages = Vec::new();
+    let mut recipients = HashSet::new();
+    for envelope in payload.messages {
+        let recipient_id = ObjectId::parse_str(&envelope.recipient_id)
+            .ok()
+            .filter(|id| chat.participants.contains(id))
+            .ok_or_else(|| error(StatusCode::BAD_REQUEST, "Every recipient must be in the chat"))?;
+        if envelope.ciphertext.is_empty() || envelope.ciphertext.len() > MAX_CIPHERTEXT_LEN {
+            return Err(error(StatusCode::BAD_REQUEST, "Invalid ciphertext"));
+        }
+        recipients.insert(recipient_id);
+        messages.push(SenderKeyMessage {
+            id: None,
+            chat_id: oid,
+            sender_id: user.user_id,
+            sender_device_id: payload.device_id.clone(),
+            recipient_id,
+            recipient_device_id: envelope.recipient_device_id,
+            ciphertext: envelope.ciphertext,
+            created_at: now,
+        });
+    }
+
+    let count = messages.len();
+    state.mongo.collection::<SenderKeyMessage>("sender_key_messages")
+        .insert_many(messages, None).await
+        .map_err(internal)?;
+
+    let ping = WsPayload {
+        r#type: "sender_key".to_string(),
+        data: json!({ "chat_id": oid.to_hex(), "sender_id": user.user_id.to_hex(), "sender_device_id": payload.device_id }),
+    };
+    for pid in recipients {
+        send_to_user(&state, &pid, &ping).await;
+    }
+
+    Ok((StatusCode::CREATED, Json(json!({ "relayed": count }))))
+}
+
+/// Hands a device its pending sender keys. Each is delivered once and then dropped.
+async fn take_sender_keys(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(device_id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let collection = state.mongo.collection::<SenderKeyMessage>("sender_key_messages");
+    let mut cursor = collection
+        .find(doc! { "recipient_id": user.user_id, "recipient_device_id": &device_id }, None).await
+        .map_err(internal)?;
+
+    let mut ids = Vec::new();
+    let mut messages = Vec::new();
+    while let Some(Ok(m)) = cursor.next().await {
+        if let Some(id) = m.id {
+            ids.push(id);
+        }
+        messages.push(json!({
+            "id": m.id.map(|id| id.to_hex()),
+            "chat_id": m.chat_id.to_hex(),
+            "sender_id": m.sender_id.to_hex(),
+            "sender_device_id": m.sender_device_id,
+            "ciphertext": m.ciphertext,
+            "created_at": m.created_at.to_chrono().to_rfc3339(),
+        }));
+    }
+
+    if !ids.is_empty() {
+        collection.delete_many(doc! { "_id": { "$in": ids } }, None).await.map_err(internal)?;
+    }
+    Ok(Json(messages))
+}
@@ -17,6 +17,7 @@ use crate::features::infrastructure::dto::{
     PaginatedResponse, PaginationInfo, IdResponse, MessageResponse
 };
 use crate::features::infrastructure::error::{AppResult, AppError};
+use crate::features::infrastructure::search_index::DocKind;
 use crate::features::auth::auth_service::AuthUser;
 use crate::utils::check_admin;
 use futures::stream::StreamExt;
@@ -58,11 +59,26 @@ pub struct CalendarSummary {
 
 // --- Handlers ---
 
+/// Titles end up in reminder email subjects, so they must be a single printable line.
+pub fn validate_event_title(title: &str) -> AppResult<()> {
+    if title.trim().is_empty() {
+        return Err(AppError::BadRequest("Event title is required".to_string()));
+    }
+    if title.chars().count() > 200 {
+        return Err(AppError::BadRequest("Event title must be at most 200 characters".to_string()));
+    }
+    if title.chars().any(char::is_control) {
+        return Err(AppError::BadRequest("Event title cannot contain line breaks or control characters".to_string()));
+    }
+    Ok(())
+}
+
 pub async fn create_event_handler(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
     Json(payload): Json<CreateEventRequest>,
 ) -> AppResult<impl IntoResponse> {
+    validate_event_title(&payload.title)?;
     let events = state.mongo.collection::<Event>("events");
     let profiles = state.mongo.collection::<crate::models::Profile>("profiles");
     
@@ -132,9 +148,11 @@ pub async fn create_event_handler(
     };
 
     let result = events.insert_one(new_event, None).await?;
+    let event_id = result.inserted_id.as_object_id().unwrap();
+    crate::features::infrastructure::search::reindex_event(&state, event_id).await;
 
     Ok((StatusCode::CREATED, Json(IdResponse { 
-        id: result.inserted_id.as_object_id().unwrap().to_hex(),
+        id: event_id.to_hex(),
         message: Some("Event created successfully".to_string())
     })))
 }
@@ -283,6 +301,7 @@ pub async fn update_event_handler(
     let mut update_doc = doc! {};
     
     if let Some(title) = payload.title {
+        validate_event_title(&title)?;
         update_doc.insert("title", title);
     }
     if let Some(description) = payload.description {
@@ -331,6 +350,7 @@ pub async fn update_event_handler(
         doc! { "$set": update_doc },
         None
     ).await?;
+    crate::features::infrastructure::search::reindex_event(&state, oid).await;
 
     Ok((StatusCode::OK, Json(MessageResponse { message: "Event updated successfully".to_string() })))
 }
@@ -348,6 +368,7 @@ pub async fn delete_event_handler(
     let events = state.mongo.collection::<Event>("events");
     
     events.delete_one(doc! { "_id": oid }, None).await?;
+    state.search_index.remove(DocKind::Event, &oid.to_hex());
 
     // Also delete associated data
     let event_rsvps = state.mongo.collection::<EventRSVP>("event_rsvps");
@@ -73,6 +73,8 @@ pub async fn follow_user_handler(
         None
     ).await;
     
+    crate::features::content::timeline::on_follow(&state, user.user_id, target_oid).await;
+
     // Notification logic
     if let Ok(Some(follower_profile)) = profiles_collection.find_one(doc! { "user_id": user.user_id }, None).await {
         let follower_name = if follower_profile.username.is_empty() {
@@ -124,6 +126,8 @@ pub async fn unfollow_user_handler(
         doc! { "$inc": { "following_count": -1 } },
         None
     ).await;
+
+    crate::features::content::timeline::on_unfollow(&state, user.user_id, target_oid).await;
     
     Ok((StatusCode::OK, Json(json!({"message": "User unfollowed successfully"}))))
 }
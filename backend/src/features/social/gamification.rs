@@ -0,0 +1,475 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::sync::Arc;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use futures::stream::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::features::auth::auth_service::AuthUser;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::error::AppResult;
+use crate::features::infrastructure::jobs::{self, Job, JobError};
+use crate::features::social::ws::{send_to_user, WsPayload};
+use crate::models::{Badge, PointAward, Profile};
+
+// Points, daily streaks, levels and badges. Handlers call `record` when a user does something
+// worth rewarding; the award itself runs as a job. Point rules and badge criteria come from
+// `settings.gamification` and fall back to the defaults below.
+
+const SETTINGS_CACHE_KEY: &str = "settings:gamification";
+const EAT_OFFSET_MINUTES: i32 = 180; // Most of our users are in Kenya
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Activity {
+    PostPublished,
+    Comment,
+    StudySession,
+    Attendance,
+    MaterialUpload,
+}
+
+impl Activity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Activity::PostPublished => "post_published",
+            Activity::Comment => "comment",
+            Activity::StudySession => "study_session",
+            Activity::Attendance => "attendance",
+            Activity::MaterialUpload => "material_upload",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PointRule {
+    pub activity: Activity,
+    pub points: u64,
+    /// Awards per user per local day; anything past this earns nothing
+    pub daily_cap: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BadgeCriterion {
+    Points { min: u64 },
+    Streak { min: u64 },
+    Level { min: u64 },
+    Activity { activity: Activity, count: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BadgeDefinition {
+    pub id: String,
+    pub name: String,
+    pub icon: String,
+    pub description: String,
+    pub criterion: BadgeCriterion,
+}
+
+impl BadgeDefinition {
+    pub fn badge(&self) -> Badge {
+        Badge {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            icon: self.icon.clone(),
+            description: self.description.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct GamificationSettings {
+    pub rules: Vec<PointRule>,
+    pub badges: Vec<BadgeDefinition>,
+    /// Level n starts at level_step * (n - 1)^2 points
+    pub level_step: u64,
+    /// Used for streaks when the user hasn't set a timezone
+    pub default_utc_offset_minutes: i32,
+}
+
+fn badge(id: &str, name: &str, icon: &str, description: &str, criterion: BadgeCriterion) -> BadgeDefinition {
+    BadgeDefinition {
+        id: id.to_string(),
+        name: name.to_string(),
+        icon: icon.to_string(),
+        description: description.to_string(),
+        criterion,
+    }
+}
+
+impl Default for GamificationSettings {
+    fn default() -> Self {
+        let rule = |activity, points, daily_cap| PointRule { activity, points, daily_cap };
+        GamificationSettings {
+            rules: vec![
+                rule(Activity::PostPublished, 10, 5),
+                rule(Activity::Comment, 2, 20),
+                rule(Activity::StudySession, 15, 4),
+                rule(Activity::Attendance, 5, 8),
+                rule(Activity::MaterialUpload, 20, 5),
+            ],
+            badges: vec![
+                badge("first-post", "First Post", "✍️", "Published your first post", BadgeCriterion::Activity { activity: Activity::PostPublished, count: 1 }),
+                badge("conversationalist", "Conversationalist", "💬", "Left 100 comments", BadgeCriterion::Activity { activity: Activity::Comment, count: 100 }),
+                badge("bookworm", "Bookworm", "📚", "Completed 10 study sessions", BadgeCriterion::Activity { activity: Activity::StudySession, count: 10 }),
+                badge("never-miss", "Never Miss", "🎯", "Logged attendance for 30 classes", BadgeCriterion::Activity { activity: Activity::Attendance, count: 30 }),
+                badge("contributor", "Contributor", "🎁", "Shared 5 revision materials", BadgeCriterion::Activity { activity: Activity::MaterialUpload, count: 5 }),
+                badge("week-streak", "On Fire", "🔥", "Active 7 days in a row", BadgeCriterion::Streak { min: 7 }),
+                badge("month-streak", "Unstoppable", "⚡", "Active 30 days in a row", BadgeCriterion::Streak { min: 30 }),
+                badge("level-5", "Rising Star", "⭐", "Reached level 5", BadgeCriterion::Level { min: 5 }),
+                badge("points-1000", "Comrade", "🏆", "Earned 1,000 points", BadgeCriterion::Points { min: 1000 }),
+            ],
+            level_step: 100,
+            default_utc_offset_minutes: EAT_OFFSET_MINUTES,
+        }
+    }
+}
+
+pub fn valid_utc_offset(minutes: i32) -> bool {
+    (-12 * 60..=14 * 60).contains(&minutes)
+}
+
+impl GamificationSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        let mut activities = HashSet::new();
+        for rule in &self.rules {
+            if !activities.insert(rule.activity) {
+                return Err(format!("Duplicate rule for {}", rule.activity.as_str()));
+            }
+            if rule.points > 1000 {
+                return Err(format!("{} is worth more than 1000 points", rule.activity.as_str()));
+            }
+        }
+        let mut ids = HashSet::new();
+        for b in &self.badges {
+            if b.id.trim().is_empty() || b.name.trim().is_empty() {
+                return Err("Badges need an id and a name".to_string());
+            }
+            if !ids.insert(b.id.as_str()) {
+                return Err(format!("Duplicate badge {}", b.id));
+            }
+        }
+        if self.level_step == 0 {
+            return Err("level_step must be positive".to_string());
+        }
+        if !valid_utc_offset(self.default_utc_offset_minutes) {
+            return Err("default_utc_offset_minutes is out of range".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn rule_for(&self, activity: Activity) -> Option<&PointRule> {
+        self.rules.iter().find(|r| r.activity == activity)
+    }
+}
+
+/// Level for a points total (starting at 1) and the total needed for the next one.
+pub fn level_for(points: u64, level_step: u64) -> (u64, u64) {
+    let level = (points / level_step.max(1)).isqrt() + 1;
+    (level, level_step.saturating_mul(level * level))
+}
+
+pub fn local_day(now: DateTime<Utc>, utc_offset_minutes: i32) -> NaiveDate {
+    (now + Duration::minutes(utc_offset_minutes as i64)).date_naive()
+}
+
+/// New streak after activity on `today`, or None when the streak doesn't change
+/// (already active today, or the last active day is ahead after a timezone change).
+pub fn advance_streak(last_active: Option<NaiveDate>, today: NaiveDate, current: u64) -> Option<u64> {
+    match last_active {
+        Some(last) if last >= today => None,
+        Some(last) if today.pred_opt() == Some(last) => Some(current + 1),
+        _ => Some(1),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub points: u64,
+    pub streak: u64,
+    pub level: u64,
+    pub activity_counts: HashMap<Activity, u64>,
+}
+
+pub fn criterion_met(criterion: &BadgeCriterion, progress: &Progress) -> bool {
+    match criterion {
+        BadgeCriterion::Points { min } => progress.points >= *min,
+        BadgeCriterion::Streak { min } => progress.streak >= *min,
+        BadgeCriterion::Level { min } => progress.level >= *min,
+        BadgeCriterion::Activity { activity, count } => progress.activity_counts.get(activity).copied().unwrap_or(0) >= *count,
+    }
+}
+
+pub async fn load_gamification_settings(state: &Arc<AppState>) -> GamificationSettings {
+    if let Some(cached) = state.cache.get::<GamificationSettings>(SETTINGS_CACHE_KEY).await {
+        return cached;
+    }
+    let settings = state
+        .mongo
+        .collection::<Document>("settings")
+        .find_one(doc! {}, None)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|d| d.get_document("gamification").ok().cloned())
+        .and_then(|d| mongodb::bson::from_document::<GamificationSettings>(d).ok())
+        .filter(|s| s.validate().is_ok())
+        .unwrap_or_default();
+    state.cache.set(SETTINGS_CACHE_KEY, &settings, 60).await;
+    settings
+}
+
+pub async fn invalidate_gamification_settings(state: &Arc<AppState>) {
+    let _ = state.cache.invalidate_pattern(SETTINGS_CACHE_KEY).await;
+}
+
+// --- Awarding ---
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AwardPointsJob {
+    pub user_id: String,
+    pub activity: Activity,
+    /// What was rewarded (post id, comment id, ...); each source pays out once
+    pub source_id: String,
+}
+
+impl Job for AwardPointsJob {
+    const KIND: &'static str = "gamification.award";
+}
+
+/// Queues an award. Fire-and-forget: a failure here must never fail the user's request.
+pub async fn record(state: &Arc<AppState>, user_id: ObjectId, activity: Activity, source_id: impl Into<String>) {
+    let job = AwardPointsJob { user_id: user_id.to_hex(), activity, source_id: source_id.into() };
+    if let Err(e) = jobs::enqueue(state, job).await {
+        tracing::error!("Failed to queue {} award for {}: {:?}", activity.as_str(), user_id, e);
+    }
+}
+
+/// Claims one of today's slots for this activity. Re-claiming the same source (a job retry)
+/// succeeds without using another slot.
+async fn claim_daily_slot(state: &Arc<AppState>, key: &str, source_id: &str, cap: u32) -> Result<bool, redis::RedisError> {
+    let script = redis::Script::new(
+        r#"
+        if redis.call('SISMEMBER', KEYS[1], ARGV[1]) == 1 then return 1 end
+        if redis.call('SCARD', KEYS[1]) >= tonumber(ARGV[2]) then return 0 end
+        redis.call('SADD', KEYS[1], ARGV[1])
+        redis.call('EXPIRE', KEYS[1], 172800)
+        return 1
+        "#,
+    );
+    let mut conn = state.redis.clone();
+    let claimed: i64 = script.key(key).arg(source_id).arg(cap).invoke_async(&mut conn).await?;
+    Ok(claimed == 1)
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        e.kind.as_ref(),
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(we)) if we.code == 11000
+    )
+}
+
+async fn notify_user(state: &Arc<AppState>, user_id: ObjectId, kind: &str, data: serde_json::Value) {
+    let payload = WsPayload { r#type: kind.to_string(), data };
+    send_to_user(state, &user_id, &payload).await;
+}
+
+pub async fn run_award(state: Arc<AppState>, job: AwardPointsJob) -> Result<(), JobError> {
+    let user_id = ObjectId::parse_str(&job.user_id).map_err(|_| JobError::Fatal("Invalid user id".to_string()))?;
+    let settings = load_gamification_settings(&state).await;
+    let Some(rule) = settings.rule_for(job.activity).cloned() else { return Ok(()) };
+
+    let profiles = state.mongo.collection::<Profile>("profiles");
+    let Some(profile) = profiles.find_one(doc! { "user_id": user_id }, None).await? else { return Ok(()) };
+    let offset = profile.utc_offset_minutes.filter(|o| valid_utc_offset(*o)).unwrap_or(settings.default_utc_offset_minutes);
+    let today = local_day(Utc::now(), offset);
+    let day = today.format("%Y-%m-%d").to_string();
+
+    let cap_key = format!("gamification:cap:{}:{}:{}", user_id.to_hex(), job.activity.as_str(), day);
+    if !claim_daily_slot(&state, &cap_key, &job.source_id, rule.daily_cap).await.map_err(|e| JobError::Retry(e.to_string()))? {
+        return Ok(());
+    }
+
+    // The ledger row goes in first, unapplied, so a retry finds it and finishes the job. The
+    // profile remembers the awards it has counted (the last 50), which makes the $inc itself
+    // idempotent if we crash before flagging the row.
+    let ledger = state.mongo.collection::<PointAward>("point_awards");
+    let award = PointAward {
+        id: None,
+        user_id,
+        activity: job.activity.as_str().to_string(),
+        source_id: job.source_id.clone(),
+        points: rule.points as i64,
+        badge_id: None,
+        local_day: day.clone(),
+        applied: false,
+        created_at: mongodb::bson::DateTime::now(),
+    };
+    let award_id = match ledger.insert_one(&award, None).await {
+        Ok(inserted) => inserted.inserted_id.as_object_id(),
+        Err(e) if is_duplicate_key(&e) => {
+            let existing = ledger
+                .find_one(doc! { "user_id": user_id, "activity": job.activity.as_str(), "source_id": &job.source_id }, None)
+                .await?;
+            match existing {
+                Some(existing) if !existing.applied => existing.id,
+                _ => return Ok(()),
+            }
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let Some(award_id) = award_id else { return Err(JobError::Fatal("Award has no id".to_string())) };
+
+    let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+    let counted = profiles
+        .find_one_and_update(
+            doc! { "user_id": user_id, "applied_awards": { "$ne": award_id } },
+            doc! {
+                "$inc": { "points": rule.points as i64 },
+                "$push": { "applied_awards": { "$each": [award_id], "$slice": -50 } },
+            },
+            options,
+        )
+        .await?;
+    let profile = match counted {
+        Some(profile) => profile,
+        // Already counted by an earlier attempt
+        None => match profiles.find_one(doc! { "user_id": user_id }, None).await? {
+            Some(profile) => profile,
+            None => return Ok(()),
+        },
+    };
+    ledger.update_one(doc! { "_id": award_id }, doc! { "$set": { "applied": true } }, None).await?;
+
+    // Streak: compare-and-set on the previous day so concurrent awards bump it once
+    let mut streak = profile.streak;
+    let last_active = profile.last_active_day.as_deref().and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+    if let Some(next) = advance_streak(last_active, today, profile.streak) {
+        let result = profiles
+            .update_one(
+                doc! { "user_id": user_id, "last_active_day": profile.last_active_day.clone() },
+                doc! { "$set": { "streak": next as i64, "last_active_day": &day }, "$max": { "longest_streak": next as i64 } },
+                None,
+            )
+            .await?;
+        if result.modified_count == 1 {
+            streak = next;
+        }
+    }
+
+    // Levels can only move up; the filter makes the level-up message fire once
+    let (level, next_level_points) = level_for(profile.points, settings.level_step);
+    if level > profile.level || next_level_points != profile.next_level_points {
+        let result = profiles
+            .update_one(
+                doc! { "user_id": user_id, "level": { "$lte": level as i64 } },
+                doc! { "$set": { "level": level as i64, "next_level_points": next_level_points as i64 } },
+                None,
+            )
+            .await?;
+        if result.modified_count == 1 && profile.level > 0 && level > profile.level {
+            notify_user(&state, user_id, "level_up", json!({ "level": level, "next_level_points": next_level_points })).await;
+        }
+    }
+
+    let progress = Progress { points: profile.points, streak, level: level.max(profile.level), activity_counts: HashMap::new() };
+    unlock_badges(&state, &settings, user_id, &profile, progress, &day).await?;
+    Ok(())
+}
+
+async fn unlock_badges(
+    state: &Arc<AppState>,
+    settings: &GamificationSettings,
+    user_id: ObjectId,
+    profile: &Profile,
+    mut progress: Progress,
+    day: &str,
+) -> Result<(), JobError> {
+    let owned: HashSet<&str> = profile.badges.iter().flatten().map(|b| b.id.as_str()).collect();
+    let pending: Vec<&BadgeDefinition> = settings.badges.iter().filter(|b| !owned.contains(b.id.as_str())).collect();
+
+    // Only count ledger rows for activities some unearned badge actually cares about
+    let ledger = state.mongo.collection::<PointAward>("point_awards");
+    for def in &pending {
+        if let BadgeCriterion::Activity { activity, .. } = def.criterion {
+            if let Entry::Vacant(slot) = progress.activity_counts.entry(activity) {
+                slot.insert(ledger.count_documents(doc! { "user_id": user_id, "activity": activity.as_str() }, None).await?);
+            }
+        }
+    }
+
+    let profiles = state.mongo.collection::<Profile>("profiles");
+    for def in pending.into_iter().filter(|b| criterion_met(&b.criterion, &progress)) {
+        let badge_bson = mongodb::bson::to_bson(&def.badge()).map_err(|e| JobError::Fatal(e.to_string()))?;
+        let result = profiles
+            .update_one(doc! { "user_id": user_id, "badges.id": { "$ne": &def.id } }, doc! { "$push": { "badges": badge_bson } }, None)
+            .await?;
+        if result.modified_count == 0 {
+            continue;
+        }
+        let award = PointAward {
+            id: None,
+            user_id,
+            activity: "badge".to_string(),
+            source_id: def.id.clone(),
+            points: 0,
+            badge_id: Some(def.id.clone()),
+            local_day: day.to_string(),
+            applied: true,
+            created_at: mongodb::bson::DateTime::now(),
+        };
+        if let Err(e) = ledger.insert_one(&award, None).await {
+            if !is_duplicate_key(&e) {
+                tracing::error!("Failed to record badge {} for {}: {:?}", def.id, user_id, e);
+            }
+        }
+        notify_user(state, user_id, "badge_unlocked", json!(def.badge())).await;
+    }
+    Ok(())
+}
+
+// --- Handlers ---
+
+#[derive(Deserialize)]
+pub struct LedgerQuery {
+    pub before: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// The signed-in user's award history, newest first.
+pub async fn get_points_history_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Query(query): Query<LedgerQuery>,
+) -> AppResult<impl IntoResponse> {
+    let limit = query.limit.unwrap_or(30).clamp(1, 100);
+    let mut filter = doc! { "user_id": user.user_id };
+    if let Some(before) = query.before.as_deref().and_then(|b| ObjectId::parse_str(b).ok()) {
+        filter.insert("_id", doc! { "$lt": before });
+    }
+    let options = FindOptions::builder().sort(doc! { "_id": -1 }).limit(limit).build();
+    let mut cursor = state.mongo.collection::<PointAward>("point_awards").find(filter, options).await?;
+
+    let mut awards = Vec::new();
+    while let Some(Ok(a)) = cursor.next().await {
+        awards.push(json!({
+            "id": a.id.map(|id| id.to_hex()),
+            "activity": a.activity,
+            "points": a.points,
+            "badge_id": a.badge_id,
+            "day": a.local_day,
+            "created_at": a.created_at.to_chrono().to_rfc3339(),
+        }));
+    }
+    let next_cursor = if awards.len() as i64 == limit { awards.last().and_then(|a| a["id"].as_str().map(String::from)) } else { None };
+
+    Ok((StatusCode::OK, Json(json!({ "awards": awards, "next_cursor": next_cursor }))))
+}
@@ -16,8 +16,10 @@ use crate::features::infrastructure::dto::{
     MakeAdminRequest, GroupFilter, IdResponse, MessageResponse
 };
 use crate::features::infrastructure::error::{AppResult, AppError};
+use crate::features::infrastructure::search_index::DocKind;
 use crate::features::auth::auth_service::AuthUser;
 use crate::utils::check_admin;
+use crate::features::infrastructure::spam::{self, ContentKind};
 use futures::stream::StreamExt;
 
 // --- Helpers: Check Group Admin Status ---
@@ -72,9 +74,11 @@ pub async fn create_group_handler(
     };
     
     let result = groups.insert_one(group, None).await?;
+    let group_id = result.inserted_id.as_object_id().unwrap();
+    crate::features::infrastructure::search::reindex_group(&state, group_id).await;
     
     Ok((StatusCode::CREATED, Json(IdResponse { 
-        id: result.inserted_id.as_object_id().unwrap().to_hex(),
+        id: group_id.to_hex(),
         message: Some("Group created successfully".to_string())
     })))
 }
@@ -164,6 +168,7 @@ pub async fn update_group_handler(
     update_doc.insert("updated_at", mongodb::bson::DateTime::now());
 
     groups.update_one(doc! { "_id": oid }, doc! { "$set": update_doc }, None).await?;
+    crate::features::infrastructure::search::reindex_group(&state, oid).await;
 
     Ok((StatusCode::OK, Json(MessageResponse { message: "Group updated successfully".to_string() })))
 }
@@ -185,10 +190,15 @@ pub async fn delete_group_handler(
 
     let groups = state.mongo.collection::<Group>("groups");
     groups.delete_one(doc! { "_id": oid }, None).await?;
+    state.search_index.remove(DocKind::Group, &oid.to_hex());
 
     // Delete associated posts
     let posts = state.mongo.collection::<crate::models::Post>("posts");
+    let post_ids = posts.distinct("_id", doc! { "group_id": oid }, None).await?;
     posts.delete_many(doc! { "group_id": oid }, None).await?;
+    for post_id in post_ids.iter().filter_map(|id| id.as_object_id()) {
+        state.search_index.remove(DocKind::Post, &post_id.to_hex());
+    }
 
     Ok((StatusCode::OK, Json(MessageResponse { message: "Group deleted successfully".to_string() })))
 }
@@ -221,6 +231,7 @@ pub async fn add_member_handler(
         doc! { "$addToSet": { "members": user_oid } },
         None
     ).await?;
+    crate::features::content::timeline::invalidate_sources(&state, user_oid).await;
 
     Ok((StatusCode::OK, Json(MessageResponse { message: "Member added successfully".to_string() })))
 }
@@ -246,6 +257,7 @@ pub async fn remove_member_handler(
         doc! { "$pull": { "members": user_oid, "admins": user_oid } },
         None
     ).await?;
+    crate::features::content::timeline::invalidate_sources(&state, user_oid).await;
 
     Ok((StatusCode::OK, Json(MessageResponse { message: "Member removed successfully".to_string() })))
 }
@@ -318,6 +330,10 @@ pub async fn create_group_post_handler(
     
     let author_name = profile.map(|p| p.username).unwrap_or_else(|| "Anonymous".to_string());
 
+    let verdict = spam::classify(&state, user.user_id, ContentKind::Post, &payload.content).await;
+    let flagged = verdict.is_spam(spam::flag_threshold());
+    let spam_text = payload.content.clone();
+
     let now = mongodb::bson::DateTime::now();
     let new_post = crate::models::Post {
         id: None,
@@ -329,7 +345,7 @@ pub async fn create_group_post_handler(
         content: payload.content,
         excerpt: None,
         slug: "".to_string(),
-        status: "published".to_string(),
+        status: if flagged { "pending".to_string() } else { "published".to_string() },
         post_type: payload.post_type,
         category: "general".to_string(),
         tags: None,
@@ -343,7 +359,7 @@ pub async fn create_group_post_handler(
             expires_at: l.duration_minutes.map(|m| mongodb::bson::DateTime::from_millis(chrono::Utc::now().timestamp_millis() + (m as i64 * 60000))),
         }),
         scheduled_publish_date: None,
-        published_at: Some(now),
+        published_at: if flagged { None } else { Some(now) },
         approved_at: None,
         approved_by: None,
         rejected_at: None,
@@ -367,16 +383,24 @@ pub async fn create_group_post_handler(
         source_author: None,
         plagiarism_score: None,
         content_rating: None,
+        spam_score: Some(verdict.score),
         is_nsfw: payload.is_nsfw,
         poll: payload.poll,
         is_anonymous: payload.is_anonymous,
         created_at: now,
         updated_at: now,
+        link_preview: None,
     };
     
     let result = posts.insert_one(new_post, None).await?;
+    let post_id = result.inserted_id.as_object_id().unwrap();
+    crate::features::infrastructure::unfurl::attach_later(&state, "posts", post_id, &spam_text).await;
+    if flagged {
+        spam::queue_for_review(&state, ContentKind::Post, post_id, &spam_text, &verdict).await;
+        return Ok((StatusCode::CREATED, Json(json!({ "id": post_id.to_hex(), "status": "pending" }))));
+    }
     
-    Ok((StatusCode::CREATED, Json(json!({ "id": result.inserted_id.as_object_id().unwrap().to_hex() }))))
+    Ok((StatusCode::CREATED, Json(json!({ "id": post_id.to_hex() }))))
 }
 
 // --- Helper Functions ---
@@ -435,6 +459,7 @@ pub async fn join_group_handler(
         doc! { "$addToSet": { "members": user.user_id } },
         None
     ).await?;
+    crate::features::content::timeline::invalidate_sources(&state, user.user_id).await;
 
     Ok((StatusCode::OK, Json(MessageResponse { message: "Joined group successfully".to_string() })))
 }
@@ -454,6 +479,7 @@ pub async fn leave_group_handler(
         doc! { "$pull": { "members": user.user_id, "admins": user.user_id } },
         None
     ).await?;
+    crate::features::content::timeline::invalidate_sources(&state, user.user_id).await;
 
     Ok((StatusCode::OK, Json(MessageResponse { message: "Left group successfully".to_string() })))
 }
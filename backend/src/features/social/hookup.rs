@@ -2,18 +2,31 @@ use axum::{
     extract::{State, Path},
     http::StatusCode,
     response::{IntoResponse, Json},
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashSet;
 use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
-use crate::models::{HookupAlias, HookupMatch};
+use crate::models::{Chat, ChatAlias, ContentModeration, HookupAlias, HookupMatch, HookupPair, HookupPreferences, Message, MessageReceipt};
 use crate::features::auth::auth_service::AuthUser;
+use crate::features::monetization::entitlements::{self, Entitlement};
+use crate::features::social::notifications::create_system_notification;
 use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::options::{FindOptions, UpdateOptions};
 use futures::stream::StreamExt;
 
+const MIN_AGE: i32 = 18;
+const MAX_AGE: i32 = 99;
+/// How many candidates are scored per discovery request, most recently active first.
+const CANDIDATE_POOL: i64 = 200;
+const DISCOVERY_PAGE: usize = 20;
+/// Recency weight halves every three days of inactivity.
+const RECENCY_HALF_LIFE_HOURS: f64 = 72.0;
+
 // --- DTOs ---
 
 #[derive(Deserialize)]
@@ -22,6 +35,13 @@ pub struct CreateAliasRequest {
     pub gender: String,
     pub age: i32,
     pub bio: String,
+    pub school: Option<String>,
+    pub preferences: Option<HookupPreferences>,
+}
+
+#[derive(Deserialize)]
+pub struct ReportAliasRequest {
+    pub reason: String,
 }
 
 #[derive(Serialize)]
@@ -31,6 +51,7 @@ pub struct AliasDiscovery {
     pub gender: String,
     pub age: i32,
     pub bio: String,
+    pub school: Option<String>,
     pub created_at: String,
 }
 
@@ -42,10 +63,281 @@ pub struct AliasResponse {
     pub gender: String,
     pub age: i32,
     pub bio: String,
+    pub school: Option<String>,
+    pub preferences: HookupPreferences,
     pub is_verified: bool,
+    pub is_suspended: bool,
     pub created_at: String,
 }
 
+#[derive(Serialize)]
+pub struct MatchResponse {
+    pub id: String,
+    pub chat_id: Option<String>,
+    pub alias_id: String,
+    pub alias_username: String,
+    pub gender: String,
+    pub age: i32,
+    pub bio: String,
+    pub matched_at: String,
+}
+
+// --- Matching ---
+
+/// The interactions a user can record against another alias.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interaction {
+    Like,
+    Pass,
+    Block,
+}
+
+impl Interaction {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "like" => Some(Interaction::Like),
+            "pass" => Some(Interaction::Pass),
+            "block" => Some(Interaction::Block),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interaction::Like => "like",
+            Interaction::Pass => "pass",
+            Interaction::Block => "block",
+        }
+    }
+}
+
+fn same_text(a: &str, b: &str) -> bool {
+    a.trim().eq_ignore_ascii_case(b.trim())
+}
+
+/// Whether `other` fits the stated preferences. Empty gender and school lists accept anyone.
+pub fn accepts(prefs: &HookupPreferences, other: &HookupAlias) -> bool {
+    let gender_ok = prefs.genders.is_empty() || prefs.genders.iter().any(|g| same_text(g, &other.gender));
+    let age_ok = other.age >= prefs.min_age && other.age <= prefs.max_age;
+    let school_ok = prefs.schools.is_empty()
+        || other.school.as_deref().is_some_and(|s| prefs.schools.iter().any(|p| same_text(p, s)));
+    gender_ok && age_ok && school_ok
+}
+
+/// How good a candidate is for `me`, or `None` when either side's preferences rule the
+/// other out. Higher is better: a shared school, a close age, recent activity and an
+/// existing like from the candidate all add to the score.
+pub fn compatibility_score(me: &HookupAlias, candidate: &HookupAlias, liked_me: bool, now: chrono::DateTime<Utc>) -> Option<f64> {
+    if !accepts(&me.preferences, candidate) || !accepts(&candidate.preferences, me) {
+        return None;
+    }
+
+    let mut score = 0.0;
+    if let (Some(a), Some(b)) = (me.school.as_deref(), candidate.school.as_deref()) {
+        if same_text(a, b) {
+            score += 1.0;
+        }
+    }
+
+    let age_gap = (me.age - candidate.age).abs() as f64;
+    score += (1.0 - age_gap / 10.0).max(0.0);
+
+    let last_seen = candidate.last_active_at.unwrap_or(candidate.created_at).to_chrono();
+    let idle_hours = ((now - last_seen).num_minutes().max(0) as f64) / 60.0;
+    score += 2.0 * 0.5f64.powf(idle_hours / RECENCY_HALF_LIFE_HOURS);
+
+    if liked_me {
+        score += 1.5;
+    }
+    Some(score)
+}
+
+/// Sorted alias ids, so both sides of a match resolve to the same pair.
+pub fn pair_key(a: ObjectId, b: ObjectId) -> String {
+    let (a, b) = if a.bytes() <= b.bytes() { (a, b) } else { (b, a) };
+    format!("{}:{}", a.to_hex(), b.to_hex())
+}
+
+fn validate_alias(payload: &CreateAliasRequest) -> Result<(), String> {
+    let name_len = payload.alias_username.trim().chars().count();
+    if name_len == 0 || name_len > 30 {
+        return Err("Alias must be between 1 and 30 characters".to_string());
+    }
+    if payload.gender.trim().is_empty() {
+        return Err("Gender is required".to_string());
+    }
+    if payload.age < MIN_AGE || payload.age > MAX_AGE {
+        return Err(format!("You must be at least {} to use hookup", MIN_AGE));
+    }
+    if let Some(prefs) = &payload.preferences {
+        if prefs.min_age < MIN_AGE || prefs.max_age > MAX_AGE || prefs.min_age > prefs.max_age {
+            return Err(format!("Preferred ages must be between {} and {}", MIN_AGE, MAX_AGE));
+        }
+    }
+    Ok(())
+}
+
+fn normalize_school(school: Option<String>) -> Option<String> {
+    school.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+// --- Helpers ---
+
+fn internal(e: impl ToString) -> (StatusCode, Json<serde_json::Value>) {
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()})))
+}
+
+async fn load_my_alias(state: &Arc<AppState>, user_id: ObjectId) -> Result<HookupAlias, (StatusCode, Json<serde_json::Value>)> {
+    let alias = state.mongo.collection::<HookupAlias>("hookup_aliases")
+        .find_one(doc! { "user_id": user_id }, None).await
+        .map_err(internal)?
+        .ok_or((StatusCode::FORBIDDEN, Json(json!({"error": "Create an alias first"}))))?;
+    if alias.is_suspended {
+        return Err((StatusCode::FORBIDDEN, Json(json!({"error": "Your alias has been suspended"}))));
+    }
+    Ok(alias)
+}
+
+async fn load_target(state: &Arc<AppState>, me: &HookupAlias, target_id: &str) -> Result<HookupAlias, (StatusCode, Json<serde_json::Value>)> {
+    let target_oid = ObjectId::parse_str(target_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
+    if Some(target_oid) == me.id {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "You cannot interact with yourself"}))));
+    }
+    state.mongo.collection::<HookupAlias>("hookup_aliases")
+        .find_one(doc! { "_id": target_oid }, None).await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Alias not found"}))))
+}
+
+async fn touch(state: &Arc<AppState>, alias_id: ObjectId) {
+    let _ = state.mongo.collection::<HookupAlias>("hookup_aliases").update_one(
+        doc! { "_id": alias_id },
+        doc! { "$set": { "last_active_at": DateTime::now() } },
+        None,
+    ).await;
+}
+
+/// Records `from`'s latest interaction with `to`, replacing any earlier one.
+async fn record_interaction(state: &Arc<AppState>, from: ObjectId, to: ObjectId, interaction: Interaction) -> mongodb::error::Result<()> {
+    state.mongo.collection::<HookupMatch>("hookup_matches").update_one(
+        doc! { "from_alias_id": from, "to_alias_id": to },
+        doc! { "$set": { "interaction": interaction.as_str(), "created_at": DateTime::now() } },
+        UpdateOptions::builder().upsert(true).build(),
+    ).await?;
+    Ok(())
+}
+
+/// Opens the anonymous chat for a mutual like. Idempotent: an active match is returned as is,
+/// and a pair that unmatched and liked each other again gets a fresh chat.
+async fn open_match(state: &Arc<AppState>, a: &HookupAlias, b: &HookupAlias) -> mongodb::error::Result<Option<HookupPair>> {
+    let pairs = state.mongo.collection::<HookupPair>("hookup_pairs");
+    let (a_id, b_id) = (a.id.unwrap(), b.id.unwrap());
+    let key = pair_key(a_id, b_id);
+
+    let existing = pairs.find_one(doc! { "pair_key": &key }, None).await?;
+    match existing.as_ref().map(|p| p.status.as_str()) {
+        Some("active") => return Ok(existing),
+        Some("unmatched") | None => {}
+        // Blocked or removed by moderation: never rematch
+        Some(_) => return Ok(None),
+    }
+
+    let now = DateTime::now();
+    let chat = Chat {
+        id: None,
+        participants: vec![a.user_id, b.user_id],
+        is_group: false,
+        name: None,
+        avatar_url: None,
+        admins: Vec::new(),
+        last_message: None,
+        last_message_time: now,
+        disappearing_duration: None,
+        created_at: now,
+        aliases: Some(vec![
+            ChatAlias { user_id: a.user_id, alias_id: a_id, alias_username: a.alias_username.clone() },
+            ChatAlias { user_id: b.user_id, alias_id: b_id, alias_username: b.alias_username.clone() },
+        ]),
+        is_encrypted: false,
+        cursors: Default::default(),
+    };
+    let chat_id = state.mongo.collection::<Chat>("chats").insert_one(chat, None).await?
+        .inserted_id.as_object_id();
+
+    let pair = HookupPair {
+        id: existing.as_ref().and_then(|p| p.id),
+        pair_key: key.clone(),
+        alias_ids: vec![a_id, b_id],
+        user_ids: vec![a.user_id, b.user_id],
+        chat_id,
+        status: "active".to_string(),
+        ended_by: None,
+        created_at: now,
+        ended_at: None,
+    };
+    if existing.is_some() {
+        pairs.replace_one(doc! { "pair_key": &key }, &pair, None).await?;
+    } else if let Err(e) = pairs.insert_one(&pair, None).await {
+        // Both liked at the same moment and the other request won the race
+        if let Some(chat_id) = chat_id {
+            let _ = state.mongo.collection::<Chat>("chats").delete_one(doc! { "_id": chat_id }, None).await;
+        }
+        tracing::debug!("Hookup pair {} already opened: {:?}", key, e);
+        return pairs.find_one(doc! { "pair_key": &key }, None).await;
+    }
+
+    for (to, other) in [(a, b), (b, a)] {
+        let _ = create_system_notification(
+            state,
+            to.user_id,
+            "hookup_match",
+            chat_id,
+            &format!("You matched with {}. Say hi!", other.alias_username),
+            true,
+        ).await;
+    }
+    Ok(Some(pair))
+}
+
+/// Closes an active match and deletes its chat, so nothing said there outlives it.
+async fn end_pair(state: &Arc<AppState>, pair: &HookupPair, status: &str, by: Option<ObjectId>) -> mongodb::error::Result<()> {
+    let closed = state.mongo.collection::<HookupPair>("hookup_pairs").update_one(
+        doc! { "pair_key": &pair.pair_key, "status": "active" },
+        doc! { "$set": { "status": status, "ended_by": by, "ended_at": DateTime::now() } },
+        None,
+    ).await?;
+    if closed.modified_count == 0 {
+        return Ok(());
+    }
+    if let Some(chat_id) = pair.chat_id {
+        state.mongo.collection::<Message>("messages").delete_many(doc! { "chat_id": chat_id }, None).await?;
+        state.mongo.collection::<MessageReceipt>("message_receipts").delete_many(doc! { "chat_id": chat_id }, None).await?;
+        state.mongo.collection::<Chat>("chats").delete_one(doc! { "_id": chat_id }, None).await?;
+    }
+    Ok(())
+}
+
+async fn active_pair_between(state: &Arc<AppState>, a: ObjectId, b: ObjectId) -> mongodb::error::Result<Option<HookupPair>> {
+    state.mongo.collection::<HookupPair>("hookup_pairs")
+        .find_one(doc! { "pair_key": pair_key(a, b), "status": "active" }, None).await
+}
+
+/// Takes an alias down after moderation and closes all of its matches.
+pub async fn suspend_alias(state: &Arc<AppState>, alias_id: ObjectId) -> mongodb::error::Result<()> {
+    state.mongo.collection::<HookupAlias>("hookup_aliases").update_one(
+        doc! { "_id": alias_id },
+        doc! { "$set": { "is_suspended": true } },
+        None,
+    ).await?;
+
+    let mut cursor = state.mongo.collection::<HookupPair>("hookup_pairs")
+        .find(doc! { "alias_ids": alias_id, "status": "active" }, None).await?;
+    while let Some(Ok(pair)) = cursor.next().await {
+        end_pair(state, &pair, "removed", None).await?;
+    }
+    Ok(())
+}
+
 // --- Handlers ---
 
 pub async fn get_my_alias_handler(
@@ -64,7 +356,10 @@ pub async fn get_my_alias_handler(
             gender: a.gender,
             age: a.age,
             bio: a.bio,
+            school: a.school,
+            preferences: a.preferences,
             is_verified: a.is_verified,
+            is_suspended: a.is_suspended,
             created_at: a.created_at.to_chrono().to_rfc3339(),
         }))),
         None => Err((StatusCode::NOT_FOUND, Json(json!({"error": "No alias found"})))),
@@ -76,17 +371,23 @@ pub async fn create_or_update_alias_handler(
     user: AuthUser,
     Json(payload): Json<CreateAliasRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    validate_alias(&payload).map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
     let collection = state.mongo.collection::<HookupAlias>("hookup_aliases");
 
     let existing = collection.find_one(doc! { "user_id": user.user_id }, None).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
     if let Some(mut alias) = existing {
-        alias.alias_username = payload.alias_username;
-        alias.gender = payload.gender;
+        alias.alias_username = payload.alias_username.trim().to_string();
+        alias.gender = payload.gender.trim().to_lowercase();
         alias.age = payload.age;
         alias.bio = payload.bio;
-        // is_verified remains whatever it was
+        alias.school = normalize_school(payload.school);
+        if let Some(prefs) = payload.preferences {
+            alias.preferences = prefs;
+        }
+        alias.last_active_at = Some(DateTime::now());
+        // is_verified and is_suspended remain whatever they were
 
         collection.replace_one(doc! { "_id": alias.id.unwrap() }, alias, None).await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
@@ -96,12 +397,16 @@ pub async fn create_or_update_alias_handler(
         let new_alias = HookupAlias {
             id: None,
             user_id: user.user_id,
-            alias_username: payload.alias_username,
-            gender: payload.gender,
+            alias_username: payload.alias_username.trim().to_string(),
+            gender: payload.gender.trim().to_lowercase(),
             age: payload.age,
             bio: payload.bio,
             is_verified: false, // Must pay Ksh 20 to activate
             created_at: DateTime::now(),
+            school: normalize_school(payload.school),
+            preferences: payload.preferences.unwrap_or_default(),
+            last_active_at: Some(DateTime::now()),
+            is_suspended: false,
         };
 
         collection.insert_one(new_alias, None).await
@@ -118,50 +423,68 @@ pub async fn discover_aliases_handler(
     let alias_collection = state.mongo.collection::<HookupAlias>("hookup_aliases");
     let match_collection = state.mongo.collection::<HookupMatch>("hookup_matches");
 
-    // Get user's alias
-    let my_alias = alias_collection.find_one(doc! { "user_id": user.user_id }, None).await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
-        .ok_or((StatusCode::FORBIDDEN, Json(json!({"error": "Create an alias first"}))))?;
+    let my_alias = load_my_alias(&state, user.user_id).await?;
+    let my_id = my_alias.id.unwrap();
 
-    if !my_alias.is_verified {
+    // Premium members can browse without paying the activation fee
+    if !my_alias.is_verified && !entitlements::entitlements(&state, user.user_id).await.has(Entitlement::HookupDiscovery) {
         return Err((StatusCode::PAYMENT_REQUIRED, Json(json!({"error": "Payment required to discover"}))));
     }
 
-    // Get aliases I've already interacted with
-    let mut cursor = match_collection.find(doc! { "from_alias_id": my_alias.id.unwrap() }, None).await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
-
-    let mut interacted_ids = vec![my_alias.id.unwrap()];
-    while let Some(m) = cursor.next().await {
-        if let Ok(match_obj) = m {
-            interacted_ids.push(match_obj.to_alias_id);
+    // Skip aliases I've already swiped on, and anyone who blocked me
+    let mut excluded = vec![my_id];
+    let mut cursor = match_collection.find(doc! { "from_alias_id": my_id }, None).await.map_err(internal)?;
+    while let Some(Ok(m)) = cursor.next().await {
+        excluded.push(m.to_alias_id);
+    }
+    let mut liked_me = HashSet::new();
+    let mut cursor = match_collection.find(doc! { "to_alias_id": my_id, "interaction": { "$in": ["like", "block"] } }, None).await.map_err(internal)?;
+    while let Some(Ok(m)) = cursor.next().await {
+        if m.interaction == "block" {
+            excluded.push(m.from_alias_id);
+        } else {
+            liked_me.insert(m.from_alias_id);
         }
     }
 
-    // Find new aliases to discover
+    let prefs = &my_alias.preferences;
     let mut discover_cursor = alias_collection.find(
-        doc! { 
-            "_id": { "$nin": interacted_ids },
+        doc! {
+            "_id": { "$nin": excluded },
             "is_verified": true,
-            "gender": { "$ne": my_alias.gender } // Simple straight matching for now
-        }, 
-        mongodb::options::FindOptions::builder().limit(20).build()
-    ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
-
-    let mut discovery = Vec::new();
-    while let Some(a) = discover_cursor.next().await {
-        if let Ok(alias) = a {
-            discovery.push(AliasDiscovery {
-                id: alias.id.unwrap().to_hex(),
-                alias_username: alias.alias_username,
-                gender: alias.gender,
-                age: alias.age,
-                bio: alias.bio,
-                created_at: alias.created_at.to_chrono().to_rfc3339(),
-            });
+            "is_suspended": { "$ne": true },
+            "age": { "$gte": prefs.min_age, "$lte": prefs.max_age },
+        },
+        FindOptions::builder()
+            .sort(doc! { "last_active_at": -1, "created_at": -1 })
+            .limit(CANDIDATE_POOL)
+            .build(),
+    ).await.map_err(internal)?;
+
+    let now = Utc::now();
+    let mut scored = Vec::new();
+    while let Some(Ok(alias)) = discover_cursor.next().await {
+        let liked = alias.id.is_some_and(|id| liked_me.contains(&id));
+        if let Some(score) = compatibility_score(&my_alias, &alias, liked, now) {
+            scored.push((score, alias));
         }
     }
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let discovery: Vec<AliasDiscovery> = scored.into_iter()
+        .take(DISCOVERY_PAGE)
+        .map(|(_, alias)| AliasDiscovery {
+            id: alias.id.unwrap().to_hex(),
+            alias_username: alias.alias_username,
+            gender: alias.gender,
+            age: alias.age,
+            bio: alias.bio,
+            school: alias.school,
+            created_at: alias.created_at.to_chrono().to_rfc3339(),
+        })
+        .collect();
 
+    touch(&state, my_id).await;
     Ok((StatusCode::OK, Json(discovery)))
 }
 
@@ -170,45 +493,220 @@ pub async fn interact_handler(
     user: AuthUser,
     Path((target_id, interaction)): Path<(String, String)>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let alias_collection = state.mongo.collection::<HookupAlias>("hookup_aliases");
     let match_collection = state.mongo.collection::<HookupMatch>("hookup_matches");
 
-    let my_alias = alias_collection.find_one(doc! { "user_id": user.user_id }, None).await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
-        .ok_or((StatusCode::FORBIDDEN, Json(json!({"error": "Create an alias first"}))))?;
+    let interaction = match Interaction::parse(&interaction) {
+        Some(Interaction::Block) => {
+            block_alias(&state, user.user_id, &target_id).await?;
+            return Ok((StatusCode::OK, Json(json!({"match": false, "message": "Alias blocked"}))));
+        }
+        Some(i) => i,
+        None => return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Interaction must be like, pass or block"})))),
+    };
 
-    let target_oid = ObjectId::parse_str(&target_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
+    let my_alias = load_my_alias(&state, user.user_id).await?;
+    let my_id = my_alias.id.unwrap();
+    let target = load_target(&state, &my_alias, &target_id).await?;
+    let target_oid = target.id.unwrap();
+    if target.is_suspended {
+        return Err((StatusCode::NOT_FOUND, Json(json!({"error": "Alias not found"}))));
+    }
 
-    let new_interaction = HookupMatch {
-        id: None,
-        from_alias_id: my_alias.id.unwrap(),
-        to_alias_id: target_oid,
-        interaction: interaction.clone(),
-        created_at: DateTime::now(),
-    };
+    let previous = match_collection.find_one(doc! { "from_alias_id": my_id, "to_alias_id": target_oid }, None).await
+        .map_err(internal)?;
+    if previous.as_ref().is_some_and(|p| p.interaction == "block") {
+        return Err((StatusCode::CONFLICT, Json(json!({"error": "You have blocked this alias"}))));
+    }
+    if interaction == Interaction::Pass && active_pair_between(&state, my_id, target_oid).await.map_err(internal)?.is_some() {
+        return Err((StatusCode::CONFLICT, Json(json!({"error": "You are matched with this alias. Unmatch instead."}))));
+    }
 
-    match_collection.insert_one(new_interaction, None).await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    record_interaction(&state, my_id, target_oid, interaction).await.map_err(internal)?;
+    touch(&state, my_id).await;
 
     // Check if it's a mutual match
-    if interaction == "like" {
+    if interaction == Interaction::Like {
         let mutual = match_collection.find_one(doc! {
             "from_alias_id": target_oid,
-            "to_alias_id": my_alias.id.unwrap(),
+            "to_alias_id": my_id,
             "interaction": "like"
-        }, None).await.unwrap_or(None);
+        }, None).await.map_err(internal)?;
 
         if mutual.is_some() {
-            return Ok((StatusCode::OK, Json(json!({"match": true, "message": "It's a mutual match!"}))));
+            if let Some(pair) = open_match(&state, &my_alias, &target).await.map_err(internal)? {
+                return Ok((StatusCode::OK, Json(json!({
+                    "match": true,
+                    "message": "It's a mutual match!",
+                    "match_id": pair.id.map(|id| id.to_hex()),
+                    "chat_id": pair.chat_id.map(|id| id.to_hex()),
+                }))));
+            }
         }
     }
 
     Ok((StatusCode::OK, Json(json!({"match": false, "message": "Interaction saved"}))))
 }
 
+/// Takes back a like or pass so the alias shows up in discovery again.
+pub async fn undo_interaction_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(target_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let my_alias = load_my_alias(&state, user.user_id).await?;
+    let my_id = my_alias.id.unwrap();
+    let target_oid = ObjectId::parse_str(&target_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
+
+    if active_pair_between(&state, my_id, target_oid).await.map_err(internal)?.is_some() {
+        return Err((StatusCode::CONFLICT, Json(json!({"error": "You are matched with this alias. Unmatch instead."}))));
+    }
+
+    let result = state.mongo.collection::<HookupMatch>("hookup_matches").delete_one(
+        doc! { "from_alias_id": my_id, "to_alias_id": target_oid, "interaction": { "$in": ["like", "pass"] } },
+        None,
+    ).await.map_err(internal)?;
+    if result.deleted_count == 0 {
+        return Err((StatusCode::NOT_FOUND, Json(json!({"error": "Nothing to undo"}))));
+    }
+
+    Ok((StatusCode::OK, Json(json!({"message": "Interaction undone"}))))
+}
+
+pub async fn get_matches_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let my_alias = load_my_alias(&state, user.user_id).await?;
+    let my_id = my_alias.id.unwrap();
+
+    let mut cursor = state.mongo.collection::<HookupPair>("hookup_pairs").find(
+        doc! { "alias_ids": my_id, "status": "active" },
+        FindOptions::builder().sort(doc! { "created_at": -1 }).build(),
+    ).await.map_err(internal)?;
+    let mut pairs = Vec::new();
+    while let Some(Ok(pair)) = cursor.next().await {
+        pairs.push(pair);
+    }
+
+    let other_ids: Vec<ObjectId> = pairs.iter()
+        .filter_map(|p| p.alias_ids.iter().find(|&&id| id != my_id).copied())
+        .collect();
+    let mut aliases = std::collections::HashMap::new();
+    let mut cursor = state.mongo.collection::<HookupAlias>("hookup_aliases")
+        .find(doc! { "_id": { "$in": &other_ids } }, None).await.map_err(internal)?;
+    while let Some(Ok(alias)) = cursor.next().await {
+        aliases.insert(alias.id.unwrap(), alias);
+    }
+
+    let matches: Vec<MatchResponse> = pairs.into_iter().filter_map(|pair| {
+        let other_id = pair.alias_ids.iter().find(|&&id| id != my_id)?;
+        let other = aliases.get(other_id)?;
+        Some(MatchResponse {
+            id: pair.id?.to_hex(),
+            chat_id: pair.chat_id.map(|id| id.to_hex()),
+            alias_id: other_id.to_hex(),
+            alias_username: other.alias_username.clone(),
+            gender: other.gender.clone(),
+            age: other.age,
+            bio: other.bio.clone(),
+            matched_at: pair.created_at.to_chrono().to_rfc3339(),
+        })
+    }).collect();
+
+    Ok((StatusCode::OK, Json(matches)))
+}
+
+pub async fn unmatch_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(match_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let my_alias = load_my_alias(&state, user.user_id).await?;
+    let my_id = my_alias.id.unwrap();
+    let match_oid = ObjectId::parse_str(&match_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
+
+    let pair = state.mongo.collection::<HookupPair>("hookup_pairs")
+        .find_one(doc! { "_id": match_oid, "alias_ids": my_id, "status": "active" }, None).await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Match not found"}))))?;
+
+    end_pair(&state, &pair, "unmatched", Some(my_id)).await.map_err(internal)?;
+    // My like becomes a pass so we don't rematch straight away; undoing it re-enables matching
+    if let Some(other_id) = pair.alias_ids.iter().find(|&&id| id != my_id) {
+        record_interaction(&state, my_id, *other_id, Interaction::Pass).await.map_err(internal)?;
+    }
+
+    Ok((StatusCode::OK, Json(json!({"message": "Unmatched"}))))
+}
+
+async fn block_alias(state: &Arc<AppState>, user_id: ObjectId, target_id: &str) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let my_alias = load_my_alias(state, user_id).await?;
+    let my_id = my_alias.id.unwrap();
+    let target = load_target(state, &my_alias, target_id).await?;
+    let target_oid = target.id.unwrap();
+
+    record_interaction(state, my_id, target_oid, Interaction::Block).await.map_err(internal)?;
+    if let Some(pair) = active_pair_between(state, my_id, target_oid).await.map_err(internal)? {
+        end_pair(state, &pair, "blocked", Some(my_id)).await.map_err(internal)?;
+    }
+    Ok(())
+}
+
+pub async fn block_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(target_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    block_alias(&state, user.user_id, &target_id).await?;
+    Ok((StatusCode::OK, Json(json!({"message": "Alias blocked"}))))
+}
+
+/// Reports an alias to the moderation queue and blocks it for the reporter.
+pub async fn report_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(target_id): Path<String>,
+    Json(payload): Json<ReportAliasRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "A reason is required"}))));
+    }
+    let my_alias = load_my_alias(&state, user.user_id).await?;
+    let target = load_target(&state, &my_alias, &target_id).await?;
+
+    let now = DateTime::now();
+    let item = ContentModeration {
+        id: None,
+        content_id: target.id.unwrap(),
+        content_type: "hookup_alias".to_string(),
+        content_text: format!("{}: {}", target.alias_username, target.bio),
+        reported_by: Some(user.user_id),
+        reported_reason: Some(reason.to_string()),
+        reported_at: Some(now),
+        status: "pending".to_string(),
+        reviewed_by: None,
+        reviewed_at: None,
+        review_notes: None,
+        action_taken: None,
+        created_at: now,
+        updated_at: now,
+    };
+    state.mongo.collection::<ContentModeration>("content_moderation").insert_one(item, None).await
+        .map_err(internal)?;
+
+    block_alias(&state, user.user_id, &target_id).await?;
+    Ok((StatusCode::CREATED, Json(json!({"message": "Report submitted. You won't see this alias again."}))))
+}
+
 pub fn hookup_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/alias", get(get_my_alias_handler).post(create_or_update_alias_handler))
         .route("/discover", get(discover_aliases_handler))
+        .route("/interact/:id", delete(undo_interaction_handler))
         .route("/interact/:id/:type", post(interact_handler))
+        .route("/matches", get(get_matches_handler))
+        .route("/matches/:id/unmatch", post(unmatch_handler))
+        .route("/block/:id", post(block_handler))
+        .route("/report/:id", post(report_handler))
 }
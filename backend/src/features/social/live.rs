@@ -1,53 +1,451 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
     routing::{get, post},
-    Router,
+    Form, Router,
     Json,
 };
 use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::jobs::{enqueue_at, Job, JobError};
+use crate::features::infrastructure::media::queue_media_job;
 use crate::features::auth::auth_service::AuthUser;
+use crate::features::monetization::payments::callback_authorized;
+use crate::features::social::ws::{publish_to_topic, Topic, WsPayload};
 use crate::models::user::Profile;
 use crate::models::social::LiveStream;
+use crate::models::MediaJobRecord;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use bson::{doc, oid::ObjectId, DateTime};
 use futures::StreamExt;
+use redis::AsyncCommands;
+
+// Streams are pushed to a local RTMP/SRT server (nginx-rtmp or MediaMTX), which calls the
+// /ingest webhooks below to check the stream key and report when publishing starts and
+// stops. Viewers watch the HLS output; recordings go through the media pipeline as VODs.
+//
+// A stream is pending until the ingest server accepts its key, live while `is_active`,
+// and ended once `ended_at` is set. Ended streams never accept their key again. When the
+// encoder drops, the stream is only interrupted: it ends if it hasn't reconnected within
+// the grace period.
+
+/// A viewer drops out of the count if their socket hasn't refreshed them for this long
+/// (three WebSocket heartbeats).
+pub const VIEWER_TTL_SECS: i64 = 75;
 
 pub fn live_routes() -> Router<Arc<AppState>> {
+    if ingest_secret().is_none() {
+        tracing::warn!("LIVE_INGEST_SECRET not set: live ingest webhooks will refuse every request");
+    }
     Router::new()
         .route("/start", post(start_stream))
         .route("/active", get(get_active_streams))
+        .route("/:id", get(get_stream))
         .route("/:id/end", post(end_stream))
         .route("/:id/heart", post(send_heart))
-        .route("/:id/viewers", post(update_viewers))
+        .route("/:id/viewers", get(get_viewers))
+        // nginx-rtmp: on_publish, on_done and on_record_done (form-encoded)
+        .route("/ingest/rtmp/publish", post(rtmp_publish))
+        .route("/ingest/rtmp/done", post(rtmp_done))
+        .route("/ingest/rtmp/record_done", post(rtmp_record_done))
+        // MediaMTX: authHTTPAddress, and runOnReady / runOnNotReady / runOnRecordSegmentComplete posting JSON
+        .route("/ingest/mediamtx/auth", post(mediamtx_auth))
+        .route("/ingest/mediamtx/ready", post(mediamtx_ready))
+        .route("/ingest/mediamtx/not_ready", post(mediamtx_not_ready))
+        .route("/ingest/mediamtx/segment", post(mediamtx_segment))
+        .merge(crate::features::social::live_chat::live_chat_routes())
+}
+
+// --- Configuration ---
+
+/// Where streamers point OBS (`LIVE_INGEST_URL`); the stream key goes in the key field.
+fn ingest_url() -> String {
+    std::env::var("LIVE_INGEST_URL").unwrap_or_else(|_| "rtmp://localhost:1935/live".to_string())
+}
+
+/// HLS playlist URL for a live stream, with `{id}` standing for the stream id (`LIVE_HLS_URL_TEMPLATE`).
+fn hls_url_template() -> String {
+    std::env::var("LIVE_HLS_URL_TEMPLATE").unwrap_or_else(|_| "http://localhost:8888/live/{id}/index.m3u8".to_string())
+}
+
+fn ingest_secret() -> Option<String> {
+    std::env::var("LIVE_INGEST_SECRET").ok().filter(|s| !s.is_empty())
+}
+
+/// How long an interrupted stream waits for its encoder to come back (`LIVE_RECONNECT_GRACE_SECS`).
+pub fn reconnect_grace_secs() -> i64 {
+    std::env::var("LIVE_RECONNECT_GRACE_SECS").ok().and_then(|v| v.parse().ok()).filter(|v| *v >= 0).unwrap_or(60)
+}
+
+/// Whether a stream interrupted at `disconnected_at` has run out of time to reconnect.
+pub fn reconnect_expired(disconnected_at: chrono::DateTime<chrono::Utc>, now: chrono::DateTime<chrono::Utc>, grace_secs: i64) -> bool {
+    now - disconnected_at >= chrono::Duration::seconds(grace_secs)
+}
+
+// --- Ingest decisions ---
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublishDecision {
+    Allow,
+    /// Accept, but publish under this name instead (nginx-rtmp follows 3xx redirects),
+    /// so the stream key never shows up in playback URLs.
+    Redirect(String),
+    Deny,
+}
+
+/// Checks a publish of `name` for the stream that owns the presented key. Streamers either
+/// publish to the stream id with `?key=<stream_key>`, or to the bare stream key, which is
+/// then renamed to the stream id.
+pub fn publish_decision(stream: Option<&LiveStream>, name: &str) -> PublishDecision {
+    let Some(stream) = stream else { return PublishDecision::Deny };
+    let Some(id) = stream.id.map(|id| id.to_hex()) else { return PublishDecision::Deny };
+    if stream.ended_at.is_some() {
+        return PublishDecision::Deny;
+    }
+    if name == id {
+        PublishDecision::Allow
+    } else if name == stream.stream_key {
+        PublishDecision::Redirect(id)
+    } else {
+        PublishDecision::Deny
+    }
+}
+
+/// Whether a MediaMTX path that became ready can go live. The key was already checked by the
+/// auth hook (from the query or the password, which runOnReady never sees), and that only
+/// admits publishes to the stream id, so here the path alone names the stream.
+pub fn ready_allowed(stream: Option<&LiveStream>, name: &str) -> bool {
+    stream.is_some_and(|s| s.ended_at.is_none() && s.id.is_some_and(|id| id.to_hex() == name))
+}
+
+/// The stream name in an ingest path such as `live/<name>`.
+pub fn stream_name(path: &str) -> &str {
+    path.trim_matches('/').rsplit('/').next().unwrap_or("")
+}
+
+/// A single value from a raw query string like `key=abc&foo=bar`.
+pub fn query_param(query: &str, name: &str) -> Option<String> {
+    query.trim_start_matches('?')
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.to_string())
+        .filter(|v| !v.is_empty())
+}
+
+pub fn hls_url(template: &str, stream_id: &str) -> String {
+    template.replace("{id}", stream_id)
+}
+
+/// Public URL for a recording file the ingest server wrote under `root`, served from `base`.
+/// Paths outside `root` fall back to the bare file name; anything with `..` is refused.
+pub fn recording_url(base: &str, root: Option<&str>, file_path: &str) -> Option<String> {
+    let relative = match root.map(|r| r.trim_end_matches('/')) {
+        Some(root) if !root.is_empty() && file_path.starts_with(&format!("{}/", root)) => &file_path[root.len() + 1..],
+        _ => file_path.rsplit('/').next().unwrap_or(""),
+    };
+    let relative = relative.trim_start_matches('/');
+    if relative.is_empty() || relative.split('/').any(|part| part == ".." || part.is_empty()) {
+        return None;
+    }
+    Some(format!("{}/{}", base.trim_end_matches('/'), relative))
+}
+
+// --- Lifecycle ---
+
+async fn find_by_key(state: &Arc<AppState>, key: &str) -> Option<LiveStream> {
+    state.mongo.collection::<LiveStream>("live_streams")
+        .find_one(doc! { "stream_key": key }, None).await.ok().flatten()
+}
+
+/// Ingest callbacks name a stream by its id, or by its key when nginx-rtmp reports the
+/// name it was originally published under.
+async fn find_by_name(state: &Arc<AppState>, name: &str) -> Option<LiveStream> {
+    let filter = match ObjectId::parse_str(name) {
+        Ok(id) => doc! { "_id": id },
+        Err(_) => doc! { "stream_key": name },
+    };
+    state.mongo.collection::<LiveStream>("live_streams").find_one(filter, None).await.ok().flatten()
+}
+
+async fn go_live(state: &Arc<AppState>, stream: &LiveStream) {
+    let Some(id) = stream.id else { return };
+    let stream_url = hls_url(&hls_url_template(), &id.to_hex());
+    let now = DateTime::now();
+    // A reconnect keeps the original start so chat offsets still line up with the VOD
+    let started = stream.ingest_started_at.unwrap_or(now);
+    let result = state.mongo.collection::<LiveStream>("live_streams").update_one(
+        doc! { "_id": id, "ended_at": null },
+        doc! {
+            "$set": { "is_active": true, "stream_url": &stream_url, "ingest_started_at": started, "updated_at": now },
+            "$unset": { "disconnected_at": "" },
+        },
+        None
+    ).await;
+    if let Err(e) = result {
+        tracing::error!("Failed to mark live stream {} active: {:?}", id, e);
+        return;
+    }
+    publish_to_topic(state, &Topic::Live(id), &WsPayload {
+        r#type: "stream_started".to_string(),
+        data: json!({ "stream_id": id.to_hex(), "stream_url": stream_url }),
+    }).await;
+}
+
+async fn finish_stream(state: &Arc<AppState>, id: ObjectId) -> bool {
+    finish_stream_where(state, id, doc! {}).await
+}
+
+/// Ends the stream if it is still running and also matches `extra`.
+async fn finish_stream_where(state: &Arc<AppState>, id: ObjectId, extra: bson::Document) -> bool {
+    let now = DateTime::now();
+    let mut filter = doc! { "_id": id, "ended_at": null };
+    filter.extend(extra);
+    let ended = state.mongo.collection::<LiveStream>("live_streams").update_one(
+        filter,
+        doc! { "$set": { "is_active": false, "ended_at": now, "updated_at": now }, "$unset": { "disconnected_at": "" } },
+        None
+    ).await.map(|r| r.modified_count > 0).unwrap_or(false);
+    if ended {
+        let mut conn = state.redis.clone();
+        let _: redis::RedisResult<()> = conn.del(viewers_key(id)).await;
+        publish_to_topic(state, &Topic::Live(id), &WsPayload {
+            r#type: "stream_ended".to_string(),
+            data: json!({ "stream_id": id.to_hex() }),
+        }).await;
+    }
+    ended
+}
+
+/// The encoder stopped publishing. Viewers are told the stream is interrupted, and it only
+/// ends if the encoder hasn't reconnected once the grace period is up.
+async fn encoder_dropped(state: &Arc<AppState>, id: ObjectId) {
+    let now = DateTime::now();
+    let interrupted = state.mongo.collection::<LiveStream>("live_streams").update_one(
+        doc! { "_id": id, "ended_at": null, "is_active": true },
+        doc! { "$set": { "is_active": false, "disconnected_at": now, "updated_at": now } },
+        None
+    ).await.map(|r| r.modified_count > 0).unwrap_or(false);
+    if !interrupted {
+        return;
+    }
+    publish_to_topic(state, &Topic::Live(id), &WsPayload {
+        r#type: "stream_interrupted".to_string(),
+        data: json!({ "stream_id": id.to_hex() }),
+    }).await;
+
+    let grace = reconnect_grace_secs();
+    let job = EndDroppedStreamJob { stream_id: id.to_hex() };
+    if let Err(e) = enqueue_at(state, job, chrono::Utc::now() + chrono::Duration::seconds(grace)).await {
+        tracing::error!("Failed to schedule the end of interrupted stream {}: {:?}", id, e);
+        finish_stream(state, id).await;
+    }
+}
+
+/// Ends an interrupted stream whose encoder didn't come back in time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndDroppedStreamJob {
+    pub stream_id: String,
+}
+
+impl Job for EndDroppedStreamJob {
+    const KIND: &'static str = "live.end_dropped";
+    const MAX_ATTEMPTS: u32 = 3;
+}
+
+pub async fn run_end_dropped(state: Arc<AppState>, job: EndDroppedStreamJob) -> Result<(), JobError> {
+    let id = ObjectId::parse_str(&job.stream_id).map_err(|e| JobError::Fatal(e.to_string()))?;
+    let stream = state.mongo.collection::<LiveStream>("live_streams")
+        .find_one(doc! { "_id": id, "ended_at": null, "is_active": false }, None).await?;
+    let Some(disconnected_at) = stream.and_then(|s| s.disconnected_at) else {
+        return Ok(()); // Reconnected or already ended
+    };
+    if !reconnect_expired(disconnected_at.to_chrono(), chrono::Utc::now(), reconnect_grace_secs()) {
+        // Dropped again after a reconnect; that drop scheduled its own check
+        return Ok(());
+    }
+    // Same `disconnected_at`, so a reconnect in the meantime leaves the stream alone
+    if finish_stream_where(&state, id, doc! { "is_active": false, "disconnected_at": disconnected_at }).await {
+        tracing::info!("Live stream {} ended after its encoder didn't reconnect", id);
+    }
+    Ok(())
+}
+
+/// Hands a finished recording to the media pipeline, which transcodes it into the VOD.
+async fn queue_recording(state: &Arc<AppState>, stream: &LiveStream, file_path: &str) {
+    let Some(id) = stream.id else { return };
+    let Ok(base) = std::env::var("LIVE_RECORDINGS_BASE_URL") else {
+        tracing::warn!("LIVE_RECORDINGS_BASE_URL is not set; dropping recording {} of stream {}", file_path, id);
+        return;
+    };
+    let root = std::env::var("LIVE_RECORDINGS_ROOT").ok();
+    let Some(url) = recording_url(&base, root.as_deref(), file_path) else {
+        tracing::warn!("Refusing recording path {} for stream {}", file_path, id);
+        return;
+    };
+
+    let original_name = format!("live_{}_{}", id.to_hex(), stream_name(file_path));
+    match queue_media_job(state, &stream.user_id.to_string(), url, "video".to_string(), original_name).await {
+        Ok(job_id) => {
+            let _ = state.mongo.collection::<LiveStream>("live_streams").update_one(
+                doc! { "_id": id },
+                doc! { "$push": { "recording_job_ids": job_id } },
+                None
+            ).await;
+        }
+        Err(e) => tracing::error!("Failed to queue recording of stream {}: {:?}", id, e),
+    }
+}
+
+// --- Viewer presence ---
+//
+// Viewers are counted from WebSocket subscriptions to `live:<id>`, not from anything the
+// client reports. Each viewer is a member of a Redis sorted set scored by when their socket
+// last refreshed them, so counts are shared across instances and crashed sockets age out.
+
+fn viewers_key(stream_id: ObjectId) -> String {
+    format!("live:viewers:{}", stream_id.to_hex())
+}
+
+/// Current number of viewers, dropping any that have gone quiet.
+pub async fn viewer_count(state: &Arc<AppState>, stream_id: ObjectId) -> i64 {
+    let key = viewers_key(stream_id);
+    let cutoff = chrono::Utc::now().timestamp() - VIEWER_TTL_SECS;
+    let mut conn = state.redis.clone();
+    let _: redis::RedisResult<()> = conn.zrembyscore(&key, "-inf", cutoff).await;
+    conn.zcard(&key).await.unwrap_or(0)
+}
+
+/// Called on every heartbeat of a socket subscribed to the stream.
+pub async fn refresh_viewer(state: &Arc<AppState>, stream_id: ObjectId, viewer: ObjectId) {
+    let key = viewers_key(stream_id);
+    let mut conn = state.redis.clone();
+    let _: redis::RedisResult<()> = conn.zadd(&key, viewer.to_hex(), chrono::Utc::now().timestamp()).await;
+    let _: redis::RedisResult<()> = conn.expire(&key, VIEWER_TTL_SECS * 2).await;
+}
+
+pub async fn viewer_joined(state: &Arc<AppState>, stream_id: ObjectId, viewer: ObjectId) {
+    refresh_viewer(state, stream_id, viewer).await;
+    broadcast_viewer_count(state, stream_id).await;
+}
+
+pub async fn viewer_left(state: &Arc<AppState>, stream_id: ObjectId, viewer: ObjectId) {
+    let mut conn = state.redis.clone();
+    let _: redis::RedisResult<()> = conn.zrem(viewers_key(stream_id), viewer.to_hex()).await;
+    broadcast_viewer_count(state, stream_id).await;
+}
+
+async fn broadcast_viewer_count(state: &Arc<AppState>, stream_id: ObjectId) {
+    let count = viewer_count(state, stream_id).await;
+    let _ = state.mongo.collection::<LiveStream>("live_streams").update_one(
+        doc! { "_id": stream_id, "is_active": true },
+        doc! {
+            "$set": { "viewer_count": count as i32 },
+            "$max": { "peak_viewer_count": count as i32 },
+        },
+        None
+    ).await;
+    publish_to_topic(state, &Topic::Live(stream_id), &WsPayload {
+        r#type: "viewer_count".to_string(),
+        data: json!({ "stream_id": stream_id.to_hex(), "count": count }),
+    }).await;
+}
+
+// --- Responses ---
+
+#[derive(Debug, Serialize)]
+pub struct LiveStreamResponse {
+    pub id: String,
+    pub user_id: String,
+    pub username: String,
+    pub user_avatar: Option<String>,
+    pub title: String,
+    pub status: String, // pending, live, ended
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub viewer_count: i64,
+    pub peak_viewer_count: i32,
+    pub stream_url: Option<String>,
+    pub thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recordings: Vec<RecordingResponse>,
+    // Streamer only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingest_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecordingResponse {
+    pub job_id: String,
+    pub status: String,
+    pub url: Option<String>,
+}
+
+fn stream_status(stream: &LiveStream) -> &'static str {
+    if stream.ended_at.is_some() {
+        "ended"
+    } else if stream.is_active {
+        "live"
+    } else {
+        "pending"
+    }
+}
+
+fn to_response(stream: LiveStream, viewer_count: i64, include_secrets: bool) -> LiveStreamResponse {
+    LiveStreamResponse {
+        id: stream.id.map(|id| id.to_hex()).unwrap_or_default(),
+        user_id: stream.user_id.to_hex(),
+        status: stream_status(&stream).to_string(),
+        username: stream.username,
+        user_avatar: stream.user_avatar,
+        title: stream.title,
+        started_at: stream.started_at.to_chrono().to_rfc3339(),
+        ended_at: stream.ended_at.map(|d| d.to_chrono().to_rfc3339()),
+        viewer_count,
+        peak_viewer_count: stream.peak_viewer_count,
+        stream_url: stream.stream_url,
+        thumbnail_url: stream.thumbnail_url,
+        recordings: Vec::new(),
+        stream_key: include_secrets.then_some(stream.stream_key),
+        ingest_url: include_secrets.then(ingest_url),
+    }
 }
 
+// --- Handlers ---
+
 async fn start_stream(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
     Json(payload): Json<serde_json::Value>,
 ) -> impl IntoResponse {
     let collection = state.mongo.collection::<LiveStream>("live_streams");
-    
-    // End any existing active streams for this user
-    let _ = collection.update_many(
-        doc! { "user_id": user.user_id, "is_active": true },
-        doc! { "$set": { "is_active": false, "ended_at": DateTime::now() } },
-        None
-    ).await;
+
+    // End any earlier stream of this user, live or still waiting for the encoder
+    let mut previous = match collection.find(doc! { "user_id": user.user_id, "ended_at": null }, None).await {
+        Ok(c) => c,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to start stream"}))).into_response(),
+    };
+    while let Some(Ok(stream)) = previous.next().await {
+        if let Some(id) = stream.id {
+            finish_stream(&state, id).await;
+        }
+    }
 
     // Fetch profile to get username and avatar
     let profiles_collection = state.mongo.collection::<Profile>("profiles");
     let profile = profiles_collection.find_one(doc! { "user_id": user.user_id }, None).await.unwrap_or(None);
-    
+
     let username = profile.as_ref().map(|p| p.username.clone()).unwrap_or_else(|| "User".to_string());
     let user_avatar = profile.as_ref().and_then(|p| p.avatar_url.clone());
 
     let stream_key = uuid::Uuid::new_v4().to_string();
     let now = DateTime::now();
-    
-    let new_stream = LiveStream {
+
+    let mut new_stream = LiveStream {
         id: None,
         user_id: user.user_id,
         username,
@@ -55,23 +453,31 @@ async fn start_stream(
         title: payload.get("title").and_then(|t| t.as_str()).unwrap_or("Live Stream").to_string(),
         started_at: now,
         ended_at: None,
-        is_active: true,
+        is_active: false, // Goes live when the ingest server accepts the key
         viewer_count: 0,
         stream_key,
         stream_url: None,
         thumbnail_url: payload.get("thumbnail_url").and_then(|t| t.as_str()).map(|s| s.to_string()),
         created_at: now,
         updated_at: now,
+        ingest_started_at: None,
+        peak_viewer_count: 0,
+        recording_job_ids: Vec::new(),
+        moderators: Vec::new(),
+        banned_user_ids: Vec::new(),
+        slow_mode_secs: 0,
+        disconnected_at: None,
     };
 
-    match collection.insert_one(new_stream, None).await {
+    match collection.insert_one(&new_stream, None).await {
         Ok(result) => {
-            (axum::http::StatusCode::CREATED, Json(json!({
-                "id": result.inserted_id,
-                "message": "Stream started successfully"
+            new_stream.id = result.inserted_id.as_object_id();
+            (StatusCode::CREATED, Json(json!({
+                "stream": to_response(new_stream, 0, true),
+                "message": "Stream created. Start streaming to the ingest URL with your stream key."
             }))).into_response()
         }
-        Err(_) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to start stream"}))).into_response()
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to start stream"}))).into_response()
     }
 }
 
@@ -79,19 +485,60 @@ async fn get_active_streams(
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
     let collection = state.mongo.collection::<LiveStream>("live_streams");
-    let mut cursor = match collection.find(doc! { "is_active": true }, None).await {
+    let options = mongodb::options::FindOptions::builder().sort(doc! { "started_at": -1 }).build();
+    let mut cursor = match collection.find(doc! { "is_active": true }, options).await {
         Ok(c) => c,
-        Err(_) => return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to fetch streams"}))).into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to fetch streams"}))).into_response(),
     };
 
     let mut streams = Vec::new();
     while let Some(Ok(stream)) = cursor.next().await {
-        streams.push(stream);
+        let viewers = match stream.id {
+            Some(id) => viewer_count(&state, id).await,
+            None => 0,
+        };
+        streams.push(to_response(stream, viewers, false));
     }
 
     Json(streams).into_response()
 }
 
+async fn get_stream(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let obj_id = match ObjectId::parse_str(&id) {
+        Ok(oid) => oid,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid stream ID"}))).into_response(),
+    };
+
+    let stream = match state.mongo.collection::<LiveStream>("live_streams").find_one(doc! { "_id": obj_id }, None).await {
+        Ok(Some(s)) => s,
+        _ => return (StatusCode::NOT_FOUND, Json(json!({"error": "Stream not found"}))).into_response(),
+    };
+
+    let is_owner = user.is_some_and(|u| u.user_id == stream.user_id);
+    let viewers = if stream.is_active { viewer_count(&state, obj_id).await } else { 0 };
+    let job_ids = stream.recording_job_ids.clone();
+    let mut response = to_response(stream, viewers, is_owner);
+
+    if !job_ids.is_empty() {
+        if let Ok(mut jobs) = state.mongo.collection::<MediaJobRecord>("media_jobs").find(doc! { "_id": { "$in": &job_ids } }, None).await {
+            while let Some(Ok(job)) = jobs.next().await {
+                let done = job.status == "completed";
+                response.recordings.push(RecordingResponse {
+                    job_id: job.id,
+                    status: job.status,
+                    url: done.then_some(job.final_url),
+                });
+            }
+        }
+    }
+
+    Json(response).into_response()
+}
+
 async fn end_stream(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
@@ -99,42 +546,28 @@ async fn end_stream(
 ) -> impl IntoResponse {
     let obj_id = match ObjectId::parse_str(&id) {
         Ok(oid) => oid,
-        Err(_) => return (axum::http::StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid stream ID"}))).into_response(),
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid stream ID"}))).into_response(),
     };
 
     let collection = state.mongo.collection::<LiveStream>("live_streams");
-    let result = collection.update_one(
-        doc! { "_id": obj_id, "user_id": user.user_id },
-        doc! { "$set": { "is_active": false, "ended_at": DateTime::now() } },
-        None
-    ).await;
+    let owned = collection.find_one(doc! { "_id": obj_id, "user_id": user.user_id }, None).await;
 
-    match result {
-        Ok(res) if res.modified_count > 0 => Json(json!({"message": "Stream ended"})).into_response(),
-        _ => (axum::http::StatusCode::NOT_FOUND, Json(json!({"error": "Stream not found or unauthorized"}))).into_response(),
+    match owned {
+        Ok(Some(_)) if finish_stream(&state, obj_id).await => Json(json!({"message": "Stream ended"})).into_response(),
+        _ => (StatusCode::NOT_FOUND, Json(json!({"error": "Stream not found or unauthorized"}))).into_response(),
     }
 }
 
-async fn update_viewers(
+async fn get_viewers(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-    Json(payload): Json<serde_json::Value>,
 ) -> impl IntoResponse {
     let obj_id = match ObjectId::parse_str(&id) {
         Ok(oid) => oid,
-        Err(_) => return (axum::http::StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid stream ID"}))).into_response(),
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid stream ID"}))).into_response(),
     };
 
-    let count = payload.get("count").and_then(|c| c.as_i64()).unwrap_or(0);
-    let collection = state.mongo.collection::<LiveStream>("live_streams");
-    
-    let _ = collection.update_one(
-        doc! { "_id": obj_id },
-        doc! { "$set": { "viewer_count": count as i32, "updated_at": DateTime::now() } },
-        None
-    ).await;
-
-    Json(json!({"status": "updated"})).into_response()
+    Json(json!({"count": viewer_count(&state, obj_id).await})).into_response()
 }
 
 async fn send_heart(
@@ -145,3 +578,186 @@ async fn send_heart(
     // For now, we'll just return success to confirm receipt.
     Json(json!({"message": "Heart sent"})).into_response()
 }
+
+// --- Ingest webhooks ---
+//
+// Authenticated by LIVE_INGEST_SECRET, passed as `?secret=` on the webhook URL or in an
+// `X-Ingest-Secret` header. Without it set, every webhook is refused.
+
+#[derive(Debug, Deserialize)]
+pub struct IngestAuthQuery {
+    pub secret: Option<String>,
+}
+
+fn ingest_authorized(headers: &HeaderMap, query: &IngestAuthQuery) -> bool {
+    let token = query.secret.as_deref()
+        .or_else(|| headers.get("x-ingest-secret").and_then(|v| v.to_str().ok()));
+    callback_authorized(None, token, &[], ingest_secret().as_deref())
+}
+
+fn forbidden() -> axum::response::Response {
+    (StatusCode::FORBIDDEN, Json(json!({"error": "Forbidden"}))).into_response()
+}
+
+/// nginx-rtmp notification. Query arguments of the publish URL arrive as extra fields,
+/// so `rtmp://host/live/<stream id>?key=<stream key>` sets `key`.
+#[derive(Debug, Deserialize)]
+pub struct RtmpCallback {
+    pub name: String,
+    pub key: Option<String>,
+    pub path: Option<String>, // on_record_done only
+}
+
+async fn rtmp_publish(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(auth): Query<IngestAuthQuery>,
+    Form(callback): Form<RtmpCallback>,
+) -> impl IntoResponse {
+    if !ingest_authorized(&headers, &auth) {
+        return forbidden();
+    }
+    let key = callback.key.clone().unwrap_or_else(|| callback.name.clone());
+    let stream = find_by_key(&state, &key).await;
+
+    match publish_decision(stream.as_ref(), &callback.name) {
+        PublishDecision::Allow => {
+            go_live(&state, stream.as_ref().unwrap()).await;
+            StatusCode::OK.into_response()
+        }
+        PublishDecision::Redirect(name) => {
+            go_live(&state, stream.as_ref().unwrap()).await;
+            (StatusCode::FOUND, [(header::LOCATION, name)]).into_response()
+        }
+        PublishDecision::Deny => forbidden(),
+    }
+}
+
+async fn rtmp_done(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(auth): Query<IngestAuthQuery>,
+    Form(callback): Form<RtmpCallback>,
+) -> impl IntoResponse {
+    if !ingest_authorized(&headers, &auth) {
+        return forbidden();
+    }
+    if let Some(id) = find_by_name(&state, &callback.name).await.and_then(|s| s.id) {
+        encoder_dropped(&state, id).await;
+    }
+    StatusCode::OK.into_response()
+}
+
+async fn rtmp_record_done(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(auth): Query<IngestAuthQuery>,
+    Form(callback): Form<RtmpCallback>,
+) -> impl IntoResponse {
+    if !ingest_authorized(&headers, &auth) {
+        return forbidden();
+    }
+    if let (Some(stream), Some(path)) = (find_by_name(&state, &callback.name).await, callback.path.as_deref()) {
+        queue_recording(&state, &stream, path).await;
+    }
+    StatusCode::OK.into_response()
+}
+
+/// MediaMTX authHTTPAddress request.
+#[derive(Debug, Deserialize)]
+pub struct MediaMtxAuth {
+    pub action: String,
+    pub path: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub query: String,
+}
+
+/// Body posted by the runOnReady / runOnNotReady / runOnRecordSegmentComplete hooks,
+/// built from MTX_PATH and MTX_SEGMENT_PATH.
+#[derive(Debug, Deserialize)]
+pub struct MediaMtxHook {
+    pub path: String,
+    pub segment_path: Option<String>,
+}
+
+async fn mediamtx_auth(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(auth): Query<IngestAuthQuery>,
+    Json(request): Json<MediaMtxAuth>,
+) -> impl IntoResponse {
+    if !ingest_authorized(&headers, &auth) {
+        return forbidden();
+    }
+    match request.action.as_str() {
+        // HLS playback is public
+        "read" | "playback" => StatusCode::OK.into_response(),
+        "publish" => {
+            let key = query_param(&request.query, "key").or(request.password.filter(|p| !p.is_empty()));
+            let Some(key) = key else { return forbidden() };
+            let stream = find_by_key(&state, &key).await;
+            // MediaMTX can't rename a path, so publishing to the bare key would leak it into the HLS URL
+            match publish_decision(stream.as_ref(), stream_name(&request.path)) {
+                PublishDecision::Allow => StatusCode::OK.into_response(),
+                _ => forbidden(),
+            }
+        }
+        _ => forbidden(),
+    }
+}
+
+async fn mediamtx_ready(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(auth): Query<IngestAuthQuery>,
+    Json(hook): Json<MediaMtxHook>,
+) -> impl IntoResponse {
+    if !ingest_authorized(&headers, &auth) {
+        return forbidden();
+    }
+    let name = stream_name(&hook.path);
+    let Ok(id) = ObjectId::parse_str(name) else { return forbidden() };
+    match find_by_name(&state, &id.to_hex()).await {
+        Some(stream) if ready_allowed(Some(&stream), name) => {
+            go_live(&state, &stream).await;
+            StatusCode::OK.into_response()
+        }
+        _ => forbidden(),
+    }
+}
+
+async fn mediamtx_not_ready(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(auth): Query<IngestAuthQuery>,
+    Json(hook): Json<MediaMtxHook>,
+) -> impl IntoResponse {
+    if !ingest_authorized(&headers, &auth) {
+        return forbidden();
+    }
+    if let Ok(id) = ObjectId::parse_str(stream_name(&hook.path)) {
+        encoder_dropped(&state, id).await;
+    }
+    StatusCode::OK.into_response()
+}
+
+async fn mediamtx_segment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(auth): Query<IngestAuthQuery>,
+    Json(hook): Json<MediaMtxHook>,
+) -> impl IntoResponse {
+    if !ingest_authorized(&headers, &auth) {
+        return forbidden();
+    }
+    let stream = match ObjectId::parse_str(stream_name(&hook.path)) {
+        Ok(id) => find_by_name(&state, &id.to_hex()).await,
+        Err(_) => None,
+    };
+    if let (Some(stream), Some(path)) = (stream, hook.segment_path.as_deref()) {
+        queue_recording(&state, &stream, path).await;
+    }
+    StatusCode::OK.into_response()
+}
@@ -0,0 +1,446 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Router,
+    Json,
+};
+use std::sync::Arc;
+use chrono::Utc;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::spam::{self, ContentKind};
+use crate::features::auth::auth_service::AuthUser;
+use crate::features::social::ably::publish_to_ably;
+use crate::features::social::ws::{publish_to_topic, Topic, WsPayload};
+use crate::models::user::Profile;
+use crate::models::social::{LiveChatMessage, LiveStream};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::StreamExt;
+
+// Chat for live streams. Lines are sent through the API, checked (bans, slow mode, spam),
+// stored, and fanned out on the `live:<id>` WebSocket topic and the streamer's Ably chat
+// channel. Each line keeps its offset from when the stream went live, so the replay of a
+// recording can show chat in sync with the video.
+
+pub const MAX_MESSAGE_CHARS: usize = 200;
+pub const MAX_SLOW_MODE_SECS: i64 = 300;
+const MAX_PAGE: i64 = 500;
+
+pub fn live_chat_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/:id/chat", get(get_chat).post(send_chat))
+        .route("/:id/chat/:message_id", delete(delete_chat))
+        .route("/:id/chat/slow_mode", put(set_slow_mode))
+        .route("/:id/moderators", post(add_moderator))
+        .route("/:id/moderators/:user_id", delete(remove_moderator))
+        .route("/:id/bans", post(ban_user))
+        .route("/:id/bans/:user_id", delete(unban_user))
+}
+
+// --- Roles ---
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRole {
+    Streamer,
+    Moderator,
+    Viewer,
+    Banned,
+}
+
+impl ChatRole {
+    pub fn can_moderate(&self) -> bool {
+        matches!(self, ChatRole::Streamer | ChatRole::Moderator)
+    }
+}
+
+/// The streamer can't be banned from their own chat; a banned moderator is just banned.
+pub fn chat_role(stream: &LiveStream, user_id: ObjectId) -> ChatRole {
+    if stream.user_id == user_id {
+        ChatRole::Streamer
+    } else if stream.banned_user_ids.contains(&user_id) {
+        ChatRole::Banned
+    } else if stream.moderators.contains(&user_id) {
+        ChatRole::Moderator
+    } else {
+        ChatRole::Viewer
+    }
+}
+
+/// Milliseconds into the stream, counted from when the ingest server accepted it (which is
+/// where the recording starts).
+pub fn chat_offset_ms(stream: &LiveStream, now: chrono::DateTime<Utc>) -> i64 {
+    let start = stream.ingest_started_at.unwrap_or(stream.started_at).to_chrono();
+    (now - start).num_milliseconds().max(0)
+}
+
+// --- DTOs ---
+
+#[derive(Debug, Deserialize)]
+pub struct SendChatRequest {
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SlowModeRequest {
+    pub seconds: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TargetUserRequest {
+    pub user_id: String,
+}
+
+/// `from_ms`/`to_ms` select a window of the stream for replay; without them the latest
+/// lines are returned.
+#[derive(Debug, Deserialize)]
+pub struct ChatQuery {
+    pub from_ms: Option<i64>,
+    pub to_ms: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LiveChatResponse {
+    pub id: String,
+    pub stream_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub user_avatar: Option<String>,
+    pub content: String,
+    pub offset_ms: i64,
+    pub created_at: String,
+    // Shape the live page's Ably listener already renders
+    pub user: String,
+    pub text: String,
+    pub timestamp: i64,
+}
+
+fn to_response(msg: LiveChatMessage) -> LiveChatResponse {
+    LiveChatResponse {
+        id: msg.id.map(|id| id.to_hex()).unwrap_or_default(),
+        stream_id: msg.stream_id.to_hex(),
+        user_id: msg.user_id.to_hex(),
+        user: msg.username.clone(),
+        username: msg.username,
+        user_avatar: msg.user_avatar,
+        text: msg.content.clone(),
+        content: msg.content,
+        offset_ms: msg.offset_ms,
+        timestamp: msg.created_at.timestamp_millis(),
+        created_at: msg.created_at.to_chrono().to_rfc3339(),
+    }
+}
+
+// --- Helpers ---
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+async fn load_stream(state: &Arc<AppState>, id: &str) -> Result<LiveStream, ApiError> {
+    let oid = ObjectId::parse_str(id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid stream ID"))?;
+    state.mongo.collection::<LiveStream>("live_streams")
+        .find_one(doc! { "_id": oid }, None).await
+        .ok()
+        .flatten()
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Stream not found"))
+}
+
+fn parse_user(id: &str) -> Result<ObjectId, ApiError> {
+    ObjectId::parse_str(id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid user ID"))
+}
+
+/// Sends an event to everyone watching, over both the WebSocket topic and Ably.
+async fn broadcast(state: &Arc<AppState>, stream: &LiveStream, event: &str, data: serde_json::Value) {
+    let Some(id) = stream.id else { return };
+    publish_to_topic(state, &Topic::Live(id), &WsPayload { r#type: event.to_string(), data: data.clone() }).await;
+    let ably_event = if event == "live_chat" { "message" } else { event };
+    publish_to_ably(&format!("live:{}:chat", stream.user_id.to_hex()), ably_event, data).await;
+}
+
+/// Claims the sender's slow-mode slot. Returns the seconds left if they posted too recently.
+async fn slow_mode_wait(state: &Arc<AppState>, stream_id: ObjectId, user_id: ObjectId, seconds: i64) -> Option<i64> {
+    let key = format!("live:slow:{}:{}", stream_id.to_hex(), user_id.to_hex());
+    let mut conn = state.redis.clone();
+    let claimed: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(&key).arg(1).arg("NX").arg("EX").arg(seconds)
+        .query_async(&mut conn).await;
+    match claimed {
+        Ok(Some(_)) => None,
+        Ok(None) => {
+            let ttl: i64 = redis::cmd("TTL").arg(&key).query_async(&mut conn).await.unwrap_or(seconds);
+            Some(ttl.max(1))
+        }
+        // Don't take chat down with Redis
+        Err(_) => None,
+    }
+}
+
+// --- Handlers ---
+
+async fn send_chat(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(payload): Json<SendChatRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let stream = load_stream(&state, &id).await?;
+    let stream_id = stream.id.unwrap();
+    if !stream.is_active {
+        return Err(error(StatusCode::CONFLICT, "This stream is not live"));
+    }
+
+    let role = chat_role(&stream, user.user_id);
+    if role == ChatRole::Banned {
+        return Err(error(StatusCode::FORBIDDEN, "You are banned from this chat"));
+    }
+
+    let content = payload.content.trim().to_string();
+    if content.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "Message is empty"));
+    }
+    if content.chars().count() > MAX_MESSAGE_CHARS {
+        return Err(error(StatusCode::BAD_REQUEST, &format!("Messages are limited to {} characters", MAX_MESSAGE_CHARS)));
+    }
+
+    if stream.slow_mode_secs > 0 && !role.can_moderate() {
+        if let Some(wait) = slow_mode_wait(&state, stream_id, user.user_id, stream.slow_mode_secs).await {
+            return Err((StatusCode::TOO_MANY_REQUESTS, Json(json!({
+                "error": "Slow mode is on",
+                "retry_after": wait,
+            }))));
+        }
+    }
+
+    let verdict = spam::classify(&state, user.user_id, ContentKind::LiveChat, &content).await;
+    let held = verdict.is_spam(spam::flag_threshold());
+
+    let profile = state.mongo.collection::<Profile>("profiles")
+        .find_one(doc! { "user_id": user.user_id }, None).await.unwrap_or(None);
+
+    let mut msg = LiveChatMessage {
+        id: None,
+        stream_id,
+        user_id: user.user_id,
+        username: profile.as_ref().map(|p| p.username.clone()).unwrap_or_else(|| "User".to_string()),
+        user_avatar: profile.and_then(|p| p.avatar_url),
+        content: content.clone(),
+        offset_ms: chat_offset_ms(&stream, Utc::now()),
+        // Held lines are stored for review but never shown
+        status: if held { "spam".to_string() } else { "visible".to_string() },
+        deleted_by: None,
+        created_at: DateTime::now(),
+    };
+
+    match state.mongo.collection::<LiveChatMessage>("live_chat_messages").insert_one(&msg, None).await {
+        Ok(result) => msg.id = result.inserted_id.as_object_id(),
+        Err(_) => return Err(error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to send message")),
+    }
+
+    if held {
+        if let Some(msg_id) = msg.id {
+            spam::queue_for_review(&state, ContentKind::LiveChat, msg_id, &content, &verdict).await;
+        }
+        return Ok((StatusCode::ACCEPTED, Json(json!({"held": true, "message": "Your message is being reviewed"}))));
+    }
+
+    let response = to_response(msg);
+    broadcast(&state, &stream, "live_chat", json!(response)).await;
+    Ok((StatusCode::CREATED, Json(json!(response))))
+}
+
+async fn get_chat(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<ChatQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let stream = load_stream(&state, &id).await?;
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_PAGE);
+
+    let mut filter = doc! { "stream_id": stream.id.unwrap(), "status": "visible" };
+    let replay = query.from_ms.is_some() || query.to_ms.is_some();
+    if replay {
+        let mut range = doc! {};
+        if let Some(from) = query.from_ms {
+            range.insert("$gte", from);
+        }
+        if let Some(to) = query.to_ms {
+            range.insert("$lt", to);
+        }
+        filter.insert("offset_ms", range);
+    }
+
+    // A replay window reads forwards; the live backlog is the newest lines, returned oldest first
+    let sort = if replay { doc! { "offset_ms": 1 } } else { doc! { "offset_ms": -1 } };
+    let options = mongodb::options::FindOptions::builder().sort(sort).limit(limit).build();
+    let mut cursor = match state.mongo.collection::<LiveChatMessage>("live_chat_messages").find(filter, options).await {
+        Ok(c) => c,
+        Err(_) => return Err(error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load chat")),
+    };
+
+    let mut messages = Vec::new();
+    while let Some(Ok(msg)) = cursor.next().await {
+        messages.push(to_response(msg));
+    }
+    if !replay {
+        messages.reverse();
+    }
+
+    Ok(Json(messages))
+}
+
+/// Authors can delete their own lines; the streamer and moderators can delete anyone's.
+async fn delete_chat(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path((id, message_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let stream = load_stream(&state, &id).await?;
+    let Ok(msg_oid) = ObjectId::parse_str(&message_id) else {
+        return Err(error(StatusCode::BAD_REQUEST, "Invalid message ID"));
+    };
+
+    let mut filter = doc! { "_id": msg_oid, "stream_id": stream.id.unwrap(), "status": "visible" };
+    if !chat_role(&stream, user.user_id).can_moderate() {
+        filter.insert("user_id", user.user_id);
+    }
+    let result = state.mongo.collection::<LiveChatMessage>("live_chat_messages").update_one(
+        filter,
+        doc! { "$set": { "status": "deleted", "deleted_by": user.user_id } },
+        None
+    ).await;
+
+    match result {
+        Ok(r) if r.modified_count > 0 => {
+            broadcast(&state, &stream, "live_chat_deleted", json!({ "id": message_id })).await;
+            Ok(Json(json!({"message": "Message deleted"})))
+        }
+        _ => Err(error(StatusCode::NOT_FOUND, "Message not found or not yours to delete")),
+    }
+}
+
+async fn set_slow_mode(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(payload): Json<SlowModeRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let stream = load_stream(&state, &id).await?;
+    if !chat_role(&stream, user.user_id).can_moderate() {
+        return Err(error(StatusCode::FORBIDDEN, "Only the streamer and moderators can change slow mode"));
+    }
+    if payload.seconds < 0 || payload.seconds > MAX_SLOW_MODE_SECS {
+        return Err(error(StatusCode::BAD_REQUEST, &format!("Slow mode must be between 0 and {} seconds", MAX_SLOW_MODE_SECS)));
+    }
+
+    let _ = state.mongo.collection::<LiveStream>("live_streams").update_one(
+        doc! { "_id": stream.id.unwrap() },
+        doc! { "$set": { "slow_mode_secs": payload.seconds, "updated_at": DateTime::now() } },
+        None
+    ).await;
+    broadcast(&state, &stream, "live_chat_slow_mode", json!({ "seconds": payload.seconds })).await;
+
+    Ok(Json(json!({"slow_mode_secs": payload.seconds})))
+}
+
+async fn add_moderator(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(payload): Json<TargetUserRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let stream = load_stream(&state, &id).await?;
+    if chat_role(&stream, user.user_id) != ChatRole::Streamer {
+        return Err(error(StatusCode::FORBIDDEN, "Only the streamer can appoint moderators"));
+    }
+    let target = parse_user(&payload.user_id)?;
+    if target == stream.user_id {
+        return Err(error(StatusCode::BAD_REQUEST, "You already moderate your own stream"));
+    }
+
+    let _ = state.mongo.collection::<LiveStream>("live_streams").update_one(
+        doc! { "_id": stream.id.unwrap() },
+        doc! { "$addToSet": { "moderators": target }, "$pull": { "banned_user_ids": target } },
+        None
+    ).await;
+    Ok(Json(json!({"message": "Moderator added"})))
+}
+
+async fn remove_moderator(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path((id, user_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let stream = load_stream(&state, &id).await?;
+    if chat_role(&stream, user.user_id) != ChatRole::Streamer {
+        return Err(error(StatusCode::FORBIDDEN, "Only the streamer can remove moderators"));
+    }
+    let target = parse_user(&user_id)?;
+
+    let _ = state.mongo.collection::<LiveStream>("live_streams").update_one(
+        doc! { "_id": stream.id.unwrap() },
+        doc! { "$pull": { "moderators": target } },
+        None
+    ).await;
+    Ok(Json(json!({"message": "Moderator removed"})))
+}
+
+/// Bans a viewer from this stream's chat and hides what they already said. Moderators
+/// can't ban each other; only the streamer can.
+async fn ban_user(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(payload): Json<TargetUserRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let stream = load_stream(&state, &id).await?;
+    let role = chat_role(&stream, user.user_id);
+    if !role.can_moderate() {
+        return Err(error(StatusCode::FORBIDDEN, "Only the streamer and moderators can ban"));
+    }
+    let target = parse_user(&payload.user_id)?;
+    match chat_role(&stream, target) {
+        ChatRole::Streamer => return Err(error(StatusCode::BAD_REQUEST, "The streamer can't be banned")),
+        ChatRole::Moderator if role != ChatRole::Streamer => return Err(error(StatusCode::FORBIDDEN, "Only the streamer can ban a moderator")),
+        _ => {}
+    }
+    let stream_id = stream.id.unwrap();
+
+    let _ = state.mongo.collection::<LiveStream>("live_streams").update_one(
+        doc! { "_id": stream_id },
+        doc! { "$addToSet": { "banned_user_ids": target }, "$pull": { "moderators": target } },
+        None
+    ).await;
+    let _ = state.mongo.collection::<LiveChatMessage>("live_chat_messages").update_many(
+        doc! { "stream_id": stream_id, "user_id": target, "status": "visible" },
+        doc! { "$set": { "status": "deleted", "deleted_by": user.user_id } },
+        None
+    ).await;
+    broadcast(&state, &stream, "live_chat_ban", json!({ "user_id": target.to_hex() })).await;
+
+    Ok(Json(json!({"message": "User banned from chat"})))
+}
+
+async fn unban_user(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path((id, user_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let stream = load_stream(&state, &id).await?;
+    if !chat_role(&stream, user.user_id).can_moderate() {
+        return Err(error(StatusCode::FORBIDDEN, "Only the streamer and moderators can unban"));
+    }
+    let target = parse_user(&user_id)?;
+
+    let _ = state.mongo.collection::<LiveStream>("live_streams").update_one(
+        doc! { "_id": stream.id.unwrap() },
+        doc! { "$pull": { "banned_user_ids": target } },
+        None
+    ).await;
+    Ok(Json(json!({"message": "User unbanned"})))
+}
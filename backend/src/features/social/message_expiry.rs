@@ -0,0 +1,194 @@
+use chrono::Utc;
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::jobs::{Job, JobError};
+use crate::features::infrastructure::media;
+use crate::features::social::ably::publish_to_ably;
+use crate::features::social::ws::{send_to_user, WsPayload};
+use crate::models::{Chat, Message};
+
+// Disappearing and view-once messages. Sending stamps `expires_at` from the chat's
+// disappearing setting; the sweeper wipes the body and attachment (R2 objects included)
+// once that passes, and a view-once message is wiped once every recipient has opened it. The message
+// document itself stays behind as a tombstone so replies and read state still line up, and
+// participants get a `message_expired` event to drop it from screen.
+
+const SWEEP_BATCH: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryReason {
+    Expired,
+    Viewed,
+}
+
+impl ExpiryReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpiryReason::Expired => "expired",
+            ExpiryReason::Viewed => "viewed",
+        }
+    }
+}
+
+/// When a message sent now should disappear. Zero or negative durations mean "off".
+pub fn expires_at_for(duration_secs: Option<i64>, now: chrono::DateTime<Utc>) -> Option<DateTime> {
+    duration_secs
+        .filter(|d| *d > 0)
+        .map(|d| DateTime::from_chrono(now + chrono::Duration::seconds(d)))
+}
+
+/// Whether `viewer` may open this view-once message now: they are in the chat, didn't send
+/// it, and haven't opened it before.
+pub fn may_open_view_once(msg: &Message, participants: &[ObjectId], viewer: ObjectId) -> bool {
+    msg.is_view_once
+        && msg.viewed_at.is_none()
+        && msg.sender_id != viewer
+        && participants.contains(&viewer)
+        && !msg.viewed_by.contains(&viewer)
+}
+
+/// Whether everyone a view-once message went to, the sender aside, has opened it.
+pub fn all_recipients_viewed(sender: ObjectId, participants: &[ObjectId], viewed_by: &[ObjectId]) -> bool {
+    participants.iter().filter(|p| **p != sender).all(|p| viewed_by.contains(p))
+}
+
+/// The claim filter for a message that still has something to wipe.
+pub fn purge_filter(id: ObjectId, reason: ExpiryReason) -> Document {
+    match reason {
+        ExpiryReason::Expired => doc! { "_id": id, "expires_at": { "$lte": DateTime::now() }, "is_deleted": false },
+        ExpiryReason::Viewed => doc! { "_id": id, "is_view_once": true, "viewed_at": { "$ne": null }, "$or": unwiped() },
+    }
+}
+
+/// Live messages other than `id` that still point at `url`; forwarding copies the attachment
+/// URL, so the object can only go once none are left.
+pub fn attachment_users_filter(url: &str, id: ObjectId) -> Document {
+    doc! { "_id": { "$ne": id }, "attachment_url": url, "is_deleted": { "$ne": true } }
+}
+
+fn unwiped() -> Vec<Document> {
+    vec![
+        doc! { "content": { "$ne": "" } },
+        doc! { "attachment_url": { "$ne": null } },
+        doc! { "encrypted_content": { "$ne": null } },
+    ]
+}
+
+/// Wipes one message and tells its chat. Returns false if someone else got there first.
+pub async fn purge_message(state: &Arc<AppState>, id: ObjectId, reason: ExpiryReason) -> bool {
+    let mut wipe = doc! {
+        "content": "",
+        "encrypted_content": null,
+        "encryption_iv": null,
+        "attachment_url": null,
+        "poll": null,
+        "location": null,
+        "contact": null,
+        "link_preview": null,
+        "edit_history": [],
+    };
+    // A viewed view-once message still shows as "Media viewed"; an expired one is gone
+    if reason == ExpiryReason::Expired {
+        wipe.insert("is_deleted", true);
+        wipe.insert("deleted_at", DateTime::now());
+    }
+
+    let messages = state.mongo.collection::<Message>("messages");
+    // Returns the document as it was before the wipe, so we still know the attachment
+    let claimed = messages.find_one_and_update(
+        purge_filter(id, reason),
+        doc! { "$set": wipe },
+        FindOneAndUpdateOptions::default(),
+    ).await;
+    let msg = match claimed {
+        Ok(Some(msg)) => msg,
+        Ok(None) => return false,
+        Err(e) => {
+            tracing::error!("Failed to expire message {}: {}", id.to_hex(), e);
+            return false;
+        }
+    };
+
+    if let Some(url) = &msg.attachment_url {
+        match messages.count_documents(attachment_users_filter(url, id), None).await {
+            Ok(0) => {
+                if let Err(e) = media::delete_r2_object(url).await {
+                    tracing::warn!("Failed to delete attachment of expired message {}: {}", id.to_hex(), e);
+                }
+            }
+            Ok(_) => tracing::debug!("Keeping attachment of expired message {}; forwarded copies still use it", id.to_hex()),
+            // Better to leave an orphan in the bucket than break someone else's message
+            Err(e) => tracing::warn!("Not deleting attachment of expired message {}: {}", id.to_hex(), e),
+        }
+    }
+
+    let chats = state.mongo.collection::<Chat>("chats");
+    // The chat list preview would otherwise keep the text around
+    if !msg.content.is_empty() {
+        let _ = chats.update_one(
+            doc! { "_id": msg.chat_id, "last_message": &msg.content },
+            doc! { "$set": { "last_message": "Message expired" } },
+            None,
+        ).await;
+    }
+
+    let tombstone = json!({ "id": id.to_hex(), "chat_id": msg.chat_id.to_hex(), "reason": reason.as_str() });
+    if let Ok(Some(chat)) = chats.find_one(doc! { "_id": msg.chat_id }, None).await {
+        let payload = WsPayload { r#type: "message_expired".to_string(), data: tombstone.clone() };
+        for pid in &chat.participants {
+            send_to_user(state, pid, &payload).await;
+        }
+    }
+    publish_to_ably(&format!("chat:{}", msg.chat_id.to_hex()), "message_expired", tombstone).await;
+
+    true
+}
+
+// --- Jobs ---
+
+/// Cron-driven: wipes messages whose disappearing timer ran out, and any opened view-once
+/// message the view handler didn't manage to wipe.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpireMessagesJob;
+
+impl Job for ExpireMessagesJob {
+    const KIND: &'static str = "messages.expire";
+    const MAX_ATTEMPTS: u32 = 3;
+}
+
+pub async fn run_expire_messages(state: Arc<AppState>) -> Result<(), JobError> {
+    let now = DateTime::now();
+
+    let sweeps = [
+        (ExpiryReason::Expired, doc! { "expires_at": { "$lte": now }, "is_deleted": false }),
+        (ExpiryReason::Viewed, doc! { "is_view_once": true, "viewed_at": { "$ne": null }, "$or": unwiped() }),
+    ];
+
+    let mut purged = 0;
+    for (reason, filter) in sweeps {
+        // Anything past the batch is picked up next minute
+        let options = FindOptions::builder().limit(SWEEP_BATCH).projection(doc! { "_id": 1 }).build();
+        let mut cursor = state.mongo.collection::<Document>("messages").find(filter, options).await?;
+        let mut ids = Vec::new();
+        while let Some(Ok(d)) = cursor.next().await {
+            if let Ok(id) = d.get_object_id("_id") {
+                ids.push(id);
+            }
+        }
+        for id in ids {
+            if purge_message(&state, id, reason).await {
+                purged += 1;
+            }
+        }
+    }
+
+    if purged > 0 {
+        tracing::info!("Expired {} messages", purged);
+    }
+    Ok(())
+}
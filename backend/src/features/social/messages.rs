@@ -2,7 +2,7 @@ use axum::{
     extract::{Path, State, Query},
     http::StatusCode,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
     Json, Router,
 };
 use mongodb::{
@@ -14,11 +14,17 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::collections::HashMap;
 use crate::features::infrastructure::db::AppState;
-use crate::models::{Chat, Message, Profile, MessageReaction};
+use crate::models::{Chat, ChatAlias, LinkPreview, Message, Profile, MessageReaction};
+use crate::features::infrastructure::unfurl;
 use super::ably::publish_to_ably;
+use super::message_expiry;
+use super::e2e;
+use super::receipts;
+use super::scheduled_messages;
 use crate::features::auth::auth_service::AuthUser;
-use crate::features::social::notifications::create_notification;
-use crate::features::social::ws::{send_to_user, WsPayload};
+use crate::features::social::notifications::{create_notification, create_system_notification};
+use crate::features::social::ws::{publish_to_topic, send_to_user, Topic, WsPayload};
+use crate::features::infrastructure::spam::{self, ContentKind};
 use redis::AsyncCommands;
 use serde_json::json;
 
@@ -97,6 +103,7 @@ pub struct ChatResponse {
     pub admins: Option<Vec<String>>,
     pub is_muted: bool,
     pub disappearing_duration: Option<i64>,
+    pub is_encrypted: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -121,14 +128,6 @@ pub struct UpdateGroupRequest {
     pub avatar_url: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LinkPreviewResponse {
-    pub title: Option<String>,
-    pub description: Option<String>,
-    pub image: Option<String>,
-    pub url: String,
-}
-
 #[derive(Debug, Serialize)]
 pub struct ParentMessageSummary {
     pub id: String,
@@ -150,6 +149,7 @@ pub struct MessageResponse {
     pub reply_to: Option<ParentMessageSummary>,
     pub reactions: Vec<ReactionSummary>,
     pub is_deleted: bool,
+    pub status: Option<String>, // sent, delivered or read; only on your own messages
     pub read_at: Option<String>,
     pub encrypted_content: Option<String>,
     pub encryption_iv: Option<String>,
@@ -159,6 +159,8 @@ pub struct MessageResponse {
     pub is_view_once: bool,
     pub viewed_at: Option<String>,
     pub expires_at: Option<String>,
+    pub link_preview: Option<LinkPreview>,
+    pub edited_at: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -215,6 +217,26 @@ pub struct DeleteRequest {
     pub mode: String, // "me" or "everyone"
 }
 
+#[derive(Debug, Deserialize)]
+pub struct EditMessageRequest {
+    pub content: String,
+    pub encrypted_content: Option<String>,
+    pub encryption_iv: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionResponse {
+    pub content: String,
+    pub encrypted_content: Option<String>,
+    pub encryption_iv: Option<String>,
+    pub replaced_at: String,
+}
+
+/// How long after sending a message can still be edited.
+pub const EDIT_WINDOW_MINUTES: i64 = 15;
+/// Edits kept per message; past this a message can't be edited again.
+pub const MAX_EDITS: usize = 20;
+
 // --- Routes ---
 pub fn message_routes() -> Router<Arc<AppState>> {
     Router::new()
@@ -228,12 +250,19 @@ pub fn message_routes() -> Router<Arc<AppState>> {
         .route("/:id/update", post(update_group_handler))
         .route("/:id/toggle-admin", post(toggle_admin_handler))
         .route("/:id/disappearing", post(set_disappearing_handler))
+        .route("/:id/encryption", post(enable_encryption_handler))
+        .route("/:id/read", post(receipts::mark_chat_read_handler))
+        .route("/:id/delivered", post(receipts::mark_chat_delivered_handler))
+        .route("/:id/scheduled", get(scheduled_messages::list_scheduled_handler).post(scheduled_messages::schedule_message_handler))
+        .route("/scheduled/:scheduled_id", delete(scheduled_messages::cancel_scheduled_handler))
         .route("/preview", get(get_link_preview_handler))
         .route("/messages/:msg_id/react", post(react_message_handler))
         .route("/messages/:msg_id/vote", post(vote_poll_handler))
         .route("/messages/:msg_id/view", post(mark_viewed_handler))
-        .route("/messages/:msg_id/read", post(mark_read_handler))
-        .route("/messages/:msg_id", post(delete_message_handler))
+        .route("/messages/:msg_id/read", post(receipts::mark_message_read_handler))
+        .route("/messages/:msg_id/receipts", get(receipts::get_message_receipts_handler))
+        .route("/messages/:msg_id", post(delete_message_handler).put(edit_message_handler))
+        .route("/messages/:msg_id/history", get(get_edit_history_handler))
         .route("/update-live-location", post(update_live_location_handler))
 }
 
@@ -247,6 +276,11 @@ pub struct SearchMessagesQuery {
 // --- Handlers ---
 
 // Create or Get Chat
+/// How `user_id` appears in an anonymous chat, if `chat` is one.
+fn alias_for(chat: &Chat, user_id: ObjectId) -> Option<&ChatAlias> {
+    chat.aliases.as_ref()?.iter().find(|a| a.user_id == user_id)
+}
+
 pub async fn create_chat_handler(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
@@ -266,13 +300,14 @@ pub async fn create_chat_handler(
         return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Cannot chat with yourself"}))));
     }
 
-    // 2. Check if chat already exists
+    // 2. Check if chat already exists (anonymous hookup chats between the same people don't count)
     let existing_chat = chats_collection.find_one(
         doc! { 
             "is_group": false,
             "participants": { 
                 "$all": [user.user_id, recipient_profile.user_id] 
-            } 
+            },
+            "aliases": { "$exists": false }
         }, 
         None
     ).await
@@ -294,6 +329,9 @@ pub async fn create_chat_handler(
         last_message_time: mongodb::bson::DateTime::now(),
         disappearing_duration: None,
         created_at: mongodb::bson::DateTime::now(),
+        aliases: None,
+        is_encrypted: false,
+        cursors: Default::default(),
     };
 
     let result = chats_collection.insert_one(new_chat, None).await
@@ -336,6 +374,9 @@ pub async fn create_group_handler(
         last_message_time: mongodb::bson::DateTime::now(),
         disappearing_duration: None,
         created_at: mongodb::bson::DateTime::now(),
+        aliases: None,
+        is_encrypted: false,
+        cursors: Default::default(),
     };
 
     let result = chats_collection.insert_one(new_group, None).await
@@ -393,22 +434,30 @@ pub async fn get_chats_handler(
     let user_profile = profile_map.get(&user.user_id);
     let muted_chat_ids = user_profile.and_then(|p| p.muted_chats.as_ref()).cloned().unwrap_or_default();
 
-    // 4. Batch fetch unread counts using aggregation
-    let chat_ids: Vec<ObjectId> = chats.iter().filter_map(|c| c.id).collect();
-    let unread_pipeline = vec![
-        doc! { "$match": { 
-            "chat_id": { "$in": &chat_ids }, 
-            "sender_id": { "$ne": user.user_id }, 
-            "read_at": null 
-        }},
-        doc! { "$group": { "_id": "$chat_id", "count": { "$sum": 1 } } }
-    ];
-    
-    let mut unread_map = HashMap::new();
-    if let Ok(mut cursor) = messages_collection.aggregate(unread_pipeline, None).await {
-        while let Some(Ok(result)) = cursor.next().await {
-            if let (Ok(cid), Ok(count)) = (result.get_object_id("_id"), result.get_i64("count")) {
-                unread_map.insert(cid, count as i32);
+    // 4. Unread counts are kept on each chat's cursors. Chats nobody has read or written in
+    // since cursors were introduced still need counting the old way.
+    let user_key = user.user_id.to_hex();
+    let mut unread_map: HashMap<ObjectId, i32> = chats.iter()
+        .filter_map(|c| Some((c.id?, c.cursors.get(&user_key)?.unread_count as i32)))
+        .collect();
+    let legacy_ids: Vec<ObjectId> = chats.iter()
+        .filter(|c| !c.cursors.contains_key(&user_key))
+        .filter_map(|c| c.id)
+        .collect();
+    if !legacy_ids.is_empty() {
+        let unread_pipeline = vec![
+            doc! { "$match": { 
+                "chat_id": { "$in": &legacy_ids }, 
+                "sender_id": { "$ne": user.user_id }, 
+                "read_at": null 
+            }},
+            doc! { "$group": { "_id": "$chat_id", "count": { "$sum": 1 } } }
+        ];
+        if let Ok(mut cursor) = messages_collection.aggregate(unread_pipeline, None).await {
+            while let Some(Ok(result)) = cursor.next().await {
+                if let (Ok(cid), Ok(count)) = (result.get_object_id("_id"), result.get_i32("count")) {
+                    unread_map.insert(cid, count);
+                }
             }
         }
     }
@@ -457,10 +506,22 @@ pub async fn get_chats_handler(
                 admins: Some(chat.admins.iter().map(|id| id.to_hex()).collect()),
                 is_muted,
                 disappearing_duration: chat.disappearing_duration,
+                is_encrypted: chat.is_encrypted,
             });
         } else {
             let other_id = chat.participants.iter().find(|&&id| id != user.user_id).unwrap_or(&user.user_id);
-            let profile_summary = if let Some(p) = profile_map.get(other_id) {
+            let profile_summary = if let Some(alias) = alias_for(&chat, *other_id) {
+                // Anonymous hookup chat: the other side is only ever their alias
+                Some(ProfileSummary {
+                    user_id: alias.alias_id.to_hex(),
+                    username: alias.alias_username.clone(),
+                    avatar_url: None,
+                    public_key: None,
+                    is_online: false,
+                    last_seen: None,
+                    last_location: None,
+                })
+            } else if let Some(p) = profile_map.get(other_id) {
                 let is_online = presence_map.get(other_id).cloned().unwrap_or(false);
 
                 Some(ProfileSummary {
@@ -505,6 +566,7 @@ pub async fn get_chats_handler(
                     admins: None,
                     is_muted,
                     disappearing_duration: chat.disappearing_duration,
+                    is_encrypted: chat.is_encrypted,
                 });
             }
         }
@@ -525,7 +587,7 @@ pub async fn get_messages_handler(
     let _profiles_collection = state.mongo.collection::<Profile>("profiles");
 
     // Verify participation
-    let _chat = chats_collection.find_one(doc! { "_id": oid, "participants": user.user_id }, None).await
+    let chat = chats_collection.find_one(doc! { "_id": oid, "participants": user.user_id }, None).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
         .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Chat not found or access denied"}))))?;
 
@@ -543,6 +605,13 @@ pub async fn get_messages_handler(
         return Ok((StatusCode::OK, Json(json!([]))));
     }
 
+    // Fetching a chat's messages is what delivers them
+    if let Some(newest) = raw_messages.last().and_then(|m| m.id) {
+        if let Err(e) = receipts::mark_delivered(&state, &chat, user.user_id, newest).await {
+            tracing::warn!("Failed to mark chat {} delivered: {}", chat_id, e);
+        }
+    }
+
     let responses = messages_to_responses(&state, user.user_id, raw_messages).await?;
     Ok((StatusCode::OK, Json(json!(responses))))
 }
@@ -555,6 +624,26 @@ pub async fn messages_to_responses(
     let messages_collection = state.mongo.collection::<Message>("messages");
     let profiles_collection = state.mongo.collection::<Profile>("profiles");
 
+    // Senders in anonymous chats are shown by alias; receipts come from each chat's cursors
+    let chat_ids: std::collections::HashSet<ObjectId> = raw_messages.iter().map(|m| m.chat_id).collect();
+    let mut alias_map: HashMap<(ObjectId, ObjectId), ChatAlias> = HashMap::new();
+    let mut chat_map: HashMap<ObjectId, Chat> = HashMap::new();
+    let mut chat_cursor = state.mongo.collection::<Chat>("chats").find(
+        doc! { "_id": { "$in": chat_ids.into_iter().collect::<Vec<_>>() } },
+        None
+    ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    while let Some(Ok(chat)) = chat_cursor.next().await {
+        for alias in chat.aliases.clone().unwrap_or_default() {
+            alias_map.insert((chat.id.unwrap(), alias.user_id), alias);
+        }
+        chat_map.insert(chat.id.unwrap(), chat);
+    }
+    let readers: Vec<ObjectId> = chat_map.values()
+        .flat_map(|c| c.participants.iter().copied())
+        .filter(|p| *p != current_user_id)
+        .collect();
+    let hidden_readers = receipts::hidden_readers(state, &readers).await;
+
     // 1. Collect all unique sender IDs and parent message IDs
     let mut sender_ids = std::collections::HashSet::new();
     let mut parent_ids = std::collections::HashSet::new();
@@ -597,8 +686,11 @@ pub async fn messages_to_responses(
             }
         }
 
+        let sender_alias = alias_map.get(&(msg.chat_id, msg.sender_id));
         let sender_username = if msg.is_system {
             "System Admin".to_string()
+        } else if let Some(alias) = sender_alias {
+            alias.alias_username.clone()
         } else {
             profile_map.get(&msg.sender_id)
                 .map(|p| p.username.clone())
@@ -608,8 +700,9 @@ pub async fn messages_to_responses(
         let mut reply_to = None;
         if let Some(parent_id) = msg.reply_to_id {
             if let Some(pm) = parent_message_map.get(&parent_id) {
-                let parent_username = profile_map.get(&pm.sender_id)
-                    .map(|p| p.username.clone())
+                let parent_username = alias_map.get(&(pm.chat_id, pm.sender_id))
+                    .map(|a| a.alias_username.clone())
+                    .or_else(|| profile_map.get(&pm.sender_id).map(|p| p.username.clone()))
                     .unwrap_or_else(|| "Unknown".to_string());
                 
                 reply_to = Some(ParentMessageSummary {
@@ -645,10 +738,27 @@ pub async fn messages_to_responses(
             msg.content.clone()
         };
 
+        // Your own messages carry how far they got; everyone else's whether you've read them.
+        // Messages from before read cursors fall back to the old per-message read_at.
+        let chat = chat_map.get(&msg.chat_id);
+        let (status, read_at) = match chat {
+            Some(chat) if msg.sender_id == current_user_id => {
+                let (status, read_at) = receipts::message_status(msg.id.unwrap(), msg.sender_id, &chat.participants, &chat.cursors, &hidden_readers);
+                (Some(status.as_str().to_string()), read_at.or(msg.read_at))
+            }
+            Some(chat) => {
+                let read_at = chat.cursors.get(&current_user_id.to_hex())
+                    .filter(|c| c.last_read_id.is_some_and(|id| id >= msg.id.unwrap()))
+                    .and_then(|c| c.read_at);
+                (None, read_at.or(msg.read_at))
+            }
+            None => (None, msg.read_at),
+        };
+
         let mut res = MessageResponse {
             id: msg.id.unwrap().to_hex(),
             chat_id: msg.chat_id.to_hex(),
-            sender_id: msg.sender_id.to_hex(),
+            sender_id: sender_alias.map(|a| a.alias_id).unwrap_or(msg.sender_id).to_hex(),
             sender_username,
             content,
             attachment_url: if msg.is_deleted { None } else { msg.attachment_url.clone() },
@@ -658,7 +768,8 @@ pub async fn messages_to_responses(
             reply_to,
             reactions: reaction_summaries,
             is_deleted: msg.is_deleted,
-            read_at: msg.read_at.map(|dt| dt.to_chrono().to_rfc3339()),
+            status,
+            read_at: read_at.map(|dt| dt.to_chrono().to_rfc3339()),
             encrypted_content: msg.encrypted_content.clone(),
             encryption_iv: msg.encryption_iv.clone(),
             poll: msg.poll.clone().map(|p| {
@@ -695,11 +806,14 @@ pub async fn messages_to_responses(
             is_view_once: msg.is_view_once,
             viewed_at: msg.viewed_at.map(|dt| dt.to_chrono().to_rfc3339()),
             expires_at: msg.expires_at.map(|dt| dt.to_chrono().to_rfc3339()),
+            link_preview: if msg.is_deleted { None } else { msg.link_preview.clone() },
+            edited_at: if msg.is_deleted { None } else { msg.edited_at.map(|dt| dt.to_chrono().to_rfc3339()) },
         };
 
-        if res.is_view_once && res.viewed_at.is_some() {
+        if res.is_view_once && (res.viewed_at.is_some() || msg.viewed_by.contains(&current_user_id)) {
             res.attachment_url = None;
             res.content = "Media viewed".to_string();
+            res.link_preview = None;
         }
 
         messages.push(res);
@@ -719,17 +833,20 @@ pub async fn search_messages_handler(
     let messages_collection = state.mongo.collection::<Message>("messages");
 
     // Verify participation
-    let _chat = chats_collection.find_one(doc! { "_id": oid, "participants": user.user_id }, None).await
+    let chat = chats_collection.find_one(doc! { "_id": oid, "participants": user.user_id }, None).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
         .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Chat not found or access denied"}))))?;
 
+    // Ciphertext can't be searched here. In encrypted chats this only finds messages from
+    // before encryption was turned on, and the header tells the client to search the rest
+    // on the device.
+    let scope = [("x-search-scope", if chat.is_encrypted { "plaintext-only" } else { "all" })];
+
     let limit = params.limit.unwrap_or(20);
     let mut filter = doc! { 
         "chat_id": oid,
-        "$or": [
-            { "content": { "$regex": &params.q, "$options": "i" } },
-            { "encrypted_content": { "$regex": &params.q, "$options": "i" } }
-        ]
+        "encrypted_content": null,
+        "content": { "$regex": regex::escape(&params.q), "$options": "i" },
     };
 
     if let Some(before_id) = params.before {
@@ -748,11 +865,11 @@ pub async fn search_messages_handler(
     }
 
     if raw_messages.is_empty() {
-        return Ok((StatusCode::OK, Json(json!([]))));
+        return Ok((StatusCode::OK, scope, Json(json!([]))));
     }
 
     let responses = messages_to_responses(&state, user.user_id, raw_messages).await?;
-    Ok((StatusCode::OK, Json(json!(responses))))
+    Ok((StatusCode::OK, scope, Json(json!(responses))))
 }
 
 // Send Message
@@ -763,13 +880,30 @@ pub async fn send_message_handler(
     Json(payload): Json<SendMessageRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     let oid = ObjectId::parse_str(&chat_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
+    let msg_id = deliver_message(&state, user.user_id, oid, payload).await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ 
+        "id": msg_id.to_hex(),
+        "created_at": mongodb::bson::DateTime::now().to_chrono().to_rfc3339()
+    }))))
+}
+
+/// Sends a message as `sender_id`, with every check a send goes through. Scheduled messages
+/// are delivered through here too, so the checks run against the sender and chat as they are
+/// at delivery time, not when the message was queued.
+pub async fn deliver_message(
+    state: &Arc<AppState>,
+    sender_id: ObjectId,
+    oid: ObjectId,
+    payload: SendMessageRequest,
+) -> Result<ObjectId, (StatusCode, Json<serde_json::Value>)> {
     let chats_collection = state.mongo.collection::<Chat>("chats");
     let messages_collection = state.mongo.collection::<Message>("messages");
     let users_collection = state.mongo.collection::<crate::models::User>("users");
     let profiles_collection = state.mongo.collection::<Profile>("profiles");
 
     // Check verification (Admins bypass this check)
-    let db_user = users_collection.find_one(doc! { "_id": user.user_id }, None).await.unwrap_or(None);
+    let db_user = users_collection.find_one(doc! { "_id": sender_id }, None).await.unwrap_or(None);
     if let Some(u) = db_user {
         if !u.is_verified && u.role != "admin" && u.role != "superadmin" {
             return Err((StatusCode::FORBIDDEN, Json(json!({"error": "Account verification required to send messages. Please verify your account for Ksh 20."}))));
@@ -779,13 +913,14 @@ pub async fn send_message_handler(
     }
 
     // Verify participation and get chat info in one query
-    let chat = chats_collection.find_one(doc! { "_id": oid, "participants": user.user_id }, None).await
+    let chat = chats_collection.find_one(doc! { "_id": oid, "participants": sender_id }, None).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
         .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Chat not found or access denied"}))))?;
 
-    // Block Check for DMs with cached profile lookup
-    if !chat.is_group {
-        let other_id = chat.participants.iter().find(|&&id| id != user.user_id).unwrap_or(&user.user_id);
+    // Block Check for DMs with cached profile lookup. Anonymous chats are closed by
+    // hookup blocks instead, so a profile block can't reveal who is behind an alias.
+    if !chat.is_group && chat.aliases.is_none() {
+        let other_id = chat.participants.iter().find(|&&id| id != sender_id).unwrap_or(&sender_id);
         
         // Try to get profile from cache first
         let mut other_profile = None;
@@ -802,20 +937,42 @@ pub async fn send_message_handler(
 
         if let Some(profile) = other_profile {
             if let Some(blocked) = profile.blocked_users {
-                if blocked.contains(&user.user_id) {
+                if blocked.contains(&sender_id) {
                     return Err((StatusCode::FORBIDDEN, Json(json!({"error": "You are blocked by this user"}))));
                 }
             }
         }
     }
 
+    // Encrypted chats carry everything inside the ciphertext; plaintext fields are refused
+    // rather than stored, and the body we keep is empty.
+    let content = if chat.is_encrypted {
+        let has_ciphertext = payload.encrypted_content.as_deref().is_some_and(|c| !c.is_empty())
+            && payload.encryption_iv.as_deref().is_some_and(|iv| !iv.is_empty());
+        if !has_ciphertext {
+            return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "This chat is end-to-end encrypted; send encrypted_content and encryption_iv"}))));
+        }
+        if payload.poll.is_some() || payload.location.is_some() || payload.contact.is_some() {
+            return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Polls, locations and contacts go inside the encrypted payload in this chat"}))));
+        }
+        String::new()
+    } else {
+        payload.content.clone()
+    };
+
     let reply_to_id = payload.reply_to_id.and_then(|id| ObjectId::parse_str(id).ok());
 
+    // Only plaintext can be scored; end-to-end encrypted bodies are opaque to us.
+    // Chats aren't held back, flagged messages just go to the moderation queue.
+    let verdict = spam::classify(state, sender_id, ContentKind::Message, &content).await;
+    let expires_at = message_expiry::expires_at_for(chat.disappearing_duration, chrono::Utc::now());
+
+    let view_once = payload.is_view_once.unwrap_or(false);
     let new_message = Message {
         id: None,
         chat_id: oid,
-        sender_id: user.user_id,
-        content: payload.content.clone(),
+        sender_id,
+        content: content.clone(),
         attachment_url: payload.attachment_url.clone(),
         attachment_type: payload.attachment_type.clone(),
         reply_to_id,
@@ -855,51 +1012,65 @@ pub async fn send_message_handler(
             full_name: c.full_name,
             avatar_url: c.avatar_url,
         }),
-        is_view_once: payload.is_view_once.unwrap_or(false),
+        is_view_once: view_once,
         is_system: false,
         is_announcement: false,
         viewed_at: None,
-        expires_at: chat.disappearing_duration.map(|d| {
-            let expires = chrono::Utc::now() + chrono::Duration::seconds(d);
-            mongodb::bson::DateTime::from_chrono(expires)
-        }),
+        viewed_by: Vec::new(),
+        expires_at,
         created_at: DateTime::now(),
+        link_preview: None,
+        edited_at: None,
+        edit_history: Vec::new(),
     };
 
     let result = messages_collection.insert_one(new_message, None).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    if verdict.is_spam(spam::flag_threshold()) {
+        if let Some(id) = result.inserted_id.as_object_id() {
+            spam::queue_for_review(state, ContentKind::Message, id, &content, &verdict).await;
+        }
+    }
+    // Encrypted chats have no plaintext to find links in; their clients unfurl for themselves.
+    // View-once messages are gone once opened, so there's no preview worth fetching
+    if let Some(id) = result.inserted_id.as_object_id().filter(|_| !view_once) {
+        unfurl::attach_later(state, "messages", id, &content).await;
+    }
 
     // Update Chat last_message with atomic operation
     let _ = chats_collection.update_one(
         doc! { "_id": oid },
         doc! { 
             "$set": { 
-                "last_message": payload.content.clone(),
+                "last_message": if chat.is_encrypted { "Encrypted message".to_string() } else { content.clone() },
                 "last_message_time": mongodb::bson::DateTime::now() 
             }  
         },
         None
     ).await;
+    if let Some(id) = result.inserted_id.as_object_id() {
+        receipts::record_sent(state, oid, &chat.participants, sender_id, id).await;
+    }
 
     // Trigger Notifications & WS Broadcast (Async - fire and forget)
     let state_clone = state.clone();
-    let _chat_id_str = chat_id.clone();
-    let sender_id = user.user_id;
-    let content_clone = payload.content.clone();
+    let content_clone = content.clone();
     let attachment_url_clone = payload.attachment_url.clone();
     let attachment_type_clone = payload.attachment_type.clone();
     let encrypted_content_clone = payload.encrypted_content.clone();
     let encryption_iv_clone = payload.encryption_iv.clone();
     let is_view_once = payload.is_view_once.unwrap_or(false);
-    let disappearing_duration = chat.disappearing_duration;
     let is_group = chat.is_group;
     let chat_name = chat.name.clone().unwrap_or_else(|| "group".to_string());
     let msg_id = result.inserted_id.as_object_id().unwrap();
 
     let chat_for_task = chat.clone();
+    let sender_alias = alias_for(&chat, sender_id).cloned();
     tokio::spawn(async move {
         // Get sender username (with caching)
-        let sender_username = if let Some(cached_profile) = state_clone.cache.get::<Profile>(&format!("profile:{}", sender_id.to_hex())).await {
+        let sender_username = if let Some(alias) = &sender_alias {
+            alias.alias_username.clone()
+        } else if let Some(cached_profile) = state_clone.cache.get::<Profile>(&format!("profile:{}", sender_id.to_hex())).await {
             cached_profile.username
         } else if let Ok(Some(profile)) = state_clone.mongo.collection::<Profile>("profiles")
             .find_one(doc! { "user_id": sender_id }, None).await {
@@ -914,7 +1085,7 @@ pub async fn send_message_handler(
         let res = MessageResponse {
             id: msg_id.to_hex(),
             chat_id: oid.to_hex(),
-            sender_id: sender_id.to_hex(),
+            sender_id: sender_alias.as_ref().map(|a| a.alias_id).unwrap_or(sender_id).to_hex(),
             sender_username,
             content: content_clone,
             attachment_url: attachment_url_clone,
@@ -922,6 +1093,7 @@ pub async fn send_message_handler(
             reply_to: None,
             reactions: Vec::new(),
             is_deleted: false,
+            status: Some(receipts::ReceiptStatus::Sent.as_str().to_string()),
             read_at: None,
             is_me: false,
             encrypted_content: encrypted_content_clone,
@@ -931,11 +1103,10 @@ pub async fn send_message_handler(
             contact: None,
             is_view_once,
             viewed_at: None,
-            expires_at: disappearing_duration.map(|d| {
-                let expires = chrono::Utc::now() + chrono::Duration::seconds(d);
-                mongodb::bson::DateTime::from_chrono(expires).to_chrono().to_rfc3339()
-            }),
+            expires_at: expires_at.map(|dt| dt.to_chrono().to_rfc3339()),
             created_at: chrono::Utc::now().to_rfc3339(),
+            link_preview: None,
+            edited_at: None,
         };
 
         let ws_payload = WsPayload {
@@ -943,6 +1114,15 @@ pub async fn send_message_handler(
             data: json!(res),
         };
 
+        // Whoever muted the chat still gets the message, just without a notification
+        let mut muted_by = std::collections::HashSet::new();
+        if let Ok(mut cursor) = state_clone.mongo.collection::<Profile>("profiles")
+            .find(doc! { "user_id": { "$in": &chat_for_task.participants }, "muted_chats": oid }, None).await {
+            while let Some(Ok(profile)) = cursor.next().await {
+                muted_by.insert(profile.user_id);
+            }
+        }
+
         // Use the chat we already have
         for pid in chat_for_task.participants {
             if pid != sender_id {
@@ -953,15 +1133,29 @@ pub async fn send_message_handler(
                     "sent you a message".to_string()
                 };
 
-                let _ = create_notification(
-                    &state_clone,
-                    pid,
-                    sender_id,
-                    "message",
-                    Some(oid),
-                    &notification_text,
-                    false
-                ).await;
+                if !muted_by.contains(&pid) {
+                    if let Some(alias) = &sender_alias {
+                        // Attributing it to the sender would reveal who they are
+                        let _ = create_system_notification(
+                            &state_clone,
+                            pid,
+                            "message",
+                            Some(oid),
+                            &format!("{} sent you a message", alias.alias_username),
+                            false
+                        ).await;
+                    } else {
+                        let _ = create_notification(
+                            &state_clone,
+                            pid,
+                            sender_id,
+                            "message",
+                            Some(oid),
+                            &notification_text,
+                            false
+                        ).await;
+                    }
+                }
 
                 // Send WebSocket message
                 let _ = send_to_user(&state_clone, &pid, &ws_payload).await;
@@ -975,12 +1169,13 @@ pub async fn send_message_handler(
         // Publish to Ably for the specific chat channel (main area)
         let chat_channel = format!("chat:{}", oid.to_hex());
         publish_to_ably(&chat_channel, "new_message", json!(res)).await;
+        publish_to_topic(&state_clone, &Topic::Chat(oid), &WsPayload {
+            r#type: "new_message".to_string(),
+            data: json!(res),
+        }).await;
     });
 
-    Ok((StatusCode::CREATED, Json(json!({ 
-        "id": msg_id.to_hex(),
-        "created_at": mongodb::bson::DateTime::now().to_chrono().to_rfc3339()
-    }))))
+    Ok(msg_id)
 }
 
 // React to Message
@@ -1027,29 +1222,6 @@ pub async fn react_message_handler(
     Ok(StatusCode::OK)
 }
 
-// Mark Message as Read
-pub async fn mark_read_handler(
-    State(state): State<Arc<AppState>>,
-    user: AuthUser,
-    Path(msg_id): Path<String>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let oid = ObjectId::parse_str(&msg_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
-    let messages_collection = state.mongo.collection::<Message>("messages");
-
-    messages_collection.update_one(
-        doc! { "_id": oid, "sender_id": { "$ne": user.user_id }, "read_at": null },
-        doc! { "$set": { "read_at": DateTime::now() } },
-        None
-    ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
-
-    // Publish Read Update via Ably
-    if let Ok(Some(msg)) = messages_collection.find_one(doc! { "_id": oid }, None).await {
-        let _ = publish_to_ably(&format!("chat:{}", msg.chat_id.to_hex()), "message_read", json!({ "id": msg_id, "read_at": msg.read_at.map(|dt| dt.to_chrono().to_rfc3339()) })).await;
-    }
-
-    Ok(StatusCode::OK)
-}
-
 // Mark View Once Message as Viewed
 pub async fn mark_viewed_handler(
     State(state): State<Arc<AppState>>,
@@ -1059,15 +1231,52 @@ pub async fn mark_viewed_handler(
     let oid = ObjectId::parse_str(&msg_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
     let messages_collection = state.mongo.collection::<crate::models::Message>("messages");
 
-    messages_collection.update_one(
-        doc! { "_id": oid, "sender_id": { "$ne": user.user_id }, "is_view_once": true, "viewed_at": null },
-        doc! { "$set": { "viewed_at": DateTime::now() } },
-        None
+    let msg = messages_collection.find_one(doc! { "_id": oid }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Message not found"}))))?;
+    let chat = state.mongo.collection::<Chat>("chats")
+        .find_one(doc! { "_id": msg.chat_id }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Message not found"}))))?;
+    // Outsiders get the same answer as for a message that doesn't exist
+    if !chat.participants.contains(&user.user_id) {
+        return Err((StatusCode::NOT_FOUND, Json(json!({"error": "Message not found"}))));
+    }
+    if !message_expiry::may_open_view_once(&msg, &chat.participants, user.user_id) {
+        return Ok(StatusCode::OK);
+    }
+
+    // Each recipient gets to open it once; the filter repeats the checks against concurrent views
+    let viewed = messages_collection.find_one_and_update(
+        doc! { "_id": oid, "sender_id": { "$ne": user.user_id }, "is_view_once": true, "viewed_at": null, "viewed_by": { "$ne": user.user_id } },
+        doc! { "$addToSet": { "viewed_by": user.user_id } },
+        mongodb::options::FindOneAndUpdateOptions::builder().return_document(mongodb::options::ReturnDocument::After).build()
     ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    let Some(viewed) = viewed else { return Ok(StatusCode::OK) };
 
-    // Publish View Update via Ably
-    if let Ok(Some(msg)) = messages_collection.find_one(doc! { "_id": oid }, None).await {
-        let _ = publish_to_ably(&format!("chat:{}", msg.chat_id.to_hex()), "message_viewed", json!({ "id": msg_id, "viewed_at": msg.viewed_at.map(|dt| dt.to_chrono().to_rfc3339()) })).await;
+    // Only once everyone it was sent to has opened it is it gone for good
+    let everyone = message_expiry::all_recipients_viewed(viewed.sender_id, &chat.participants, &viewed.viewed_by);
+    let viewed_at = if everyone {
+        let now = DateTime::now();
+        messages_collection.update_one(
+            doc! { "_id": oid, "viewed_at": null },
+            doc! { "$set": { "viewed_at": now } },
+            None
+        ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+        Some(now)
+    } else {
+        None
+    };
+
+    let _ = publish_to_ably(&format!("chat:{}", msg.chat_id.to_hex()), "message_viewed", json!({
+        "id": msg_id,
+        "viewer_id": user.user_id.to_hex(),
+        "viewed_at": viewed_at.map(|dt| dt.to_chrono().to_rfc3339()),
+    })).await;
+
+    // Opened by everyone means gone: the media can't be fetched again
+    if everyone {
+        message_expiry::purge_message(&state, oid, message_expiry::ExpiryReason::Viewed).await;
     }
 
     Ok(StatusCode::OK)
@@ -1093,7 +1302,7 @@ pub async fn delete_message_handler(
         }
         messages_collection.update_one(
             doc! { "_id": oid },
-            doc! { "$set": { "is_deleted": true, "deleted_at": DateTime::now() } },
+            doc! { "$set": { "is_deleted": true, "deleted_at": DateTime::now(), "edit_history": [] } },
             None
         ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
@@ -1112,6 +1321,172 @@ pub async fn delete_message_handler(
     Ok(StatusCode::OK)
 }
 
+/// Whether a message sent at `created_at` is still inside the edit window.
+pub fn within_edit_window(created_at: DateTime, now: chrono::DateTime<chrono::Utc>) -> bool {
+    now - created_at.to_chrono() <= chrono::Duration::minutes(EDIT_WINDOW_MINUTES)
+}
+
+/// Only plain text and attachment captions can be edited.
+pub fn is_editable(msg: &Message) -> bool {
+    !(msg.is_deleted || msg.is_system || msg.is_view_once || msg.poll.is_some() || msg.location.is_some() || msg.contact.is_some())
+}
+
+/// Matches `msg` only as `editor` read it. Matching on the body makes concurrent edits a
+/// compare-and-set; a deletion, the window closing or a full history also match nothing.
+pub fn edit_filter(msg: &Message, editor: ObjectId, now: chrono::DateTime<chrono::Utc>) -> mongodb::bson::Document {
+    let cutoff = DateTime::from_chrono(now - chrono::Duration::minutes(EDIT_WINDOW_MINUTES));
+    doc! {
+        "_id": msg.id,
+        "sender_id": editor,
+        "is_deleted": false,
+        "created_at": { "$gte": cutoff },
+        "content": &msg.content,
+        "encrypted_content": &msg.encrypted_content,
+        format!("edit_history.{}", MAX_EDITS - 1): { "$exists": false },
+    }
+}
+
+// Edit Message
+pub async fn edit_message_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(msg_id): Path<String>,
+    Json(payload): Json<EditMessageRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let oid = ObjectId::parse_str(&msg_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
+    let messages_collection = state.mongo.collection::<Message>("messages");
+    let chats_collection = state.mongo.collection::<Chat>("chats");
+
+    let msg = messages_collection.find_one(doc! { "_id": oid }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Message not found"}))))?;
+
+    if msg.sender_id != user.user_id {
+        return Err((StatusCode::FORBIDDEN, Json(json!({"error": "You can only edit your own messages"}))));
+    }
+    if !is_editable(&msg) {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "This message can't be edited"}))));
+    }
+    let now = chrono::Utc::now();
+    if !within_edit_window(msg.created_at, now) {
+        return Err((StatusCode::FORBIDDEN, Json(json!({"error": format!("Messages can only be edited within {} minutes of sending", EDIT_WINDOW_MINUTES)}))));
+    }
+
+    let chat = chats_collection.find_one(doc! { "_id": msg.chat_id, "participants": user.user_id }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Chat not found or access denied"}))))?;
+
+    // Same rules as sending: encrypted chats only take ciphertext
+    let content = if chat.is_encrypted {
+        let has_ciphertext = payload.encrypted_content.as_deref().is_some_and(|c| !c.is_empty())
+            && payload.encryption_iv.as_deref().is_some_and(|iv| !iv.is_empty());
+        if !has_ciphertext {
+            return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "This chat is end-to-end encrypted; send encrypted_content and encryption_iv"}))));
+        }
+        String::new()
+    } else {
+        if payload.content.trim().is_empty() && payload.encrypted_content.is_none() && msg.attachment_url.is_none() {
+            return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Message can't be empty"}))));
+        }
+        payload.content.clone()
+    };
+    if content == msg.content && payload.encrypted_content == msg.encrypted_content && payload.encryption_iv == msg.encryption_iv {
+        let responses = messages_to_responses(&state, user.user_id, vec![msg]).await?;
+        return Ok((StatusCode::OK, Json(json!(responses.first()))));
+    }
+
+    let verdict = spam::classify(&state, user.user_id, ContentKind::Message, &content).await;
+
+    let revision = crate::models::MessageRevision {
+        content: msg.content.clone(),
+        encrypted_content: msg.encrypted_content.clone(),
+        encryption_iv: msg.encryption_iv.clone(),
+        replaced_at: DateTime::from_chrono(now),
+    };
+    let updated = messages_collection.find_one_and_update(
+        edit_filter(&msg, user.user_id, now),
+        doc! {
+            "$set": {
+                "content": &content,
+                "encrypted_content": &payload.encrypted_content,
+                "encryption_iv": &payload.encryption_iv,
+                "edited_at": DateTime::from_chrono(now),
+                "link_preview": null,
+            },
+            "$push": { "edit_history": mongodb::bson::to_bson(&revision).unwrap() },
+        },
+        mongodb::options::FindOneAndUpdateOptions::builder().return_document(mongodb::options::ReturnDocument::After).build()
+    ).await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+    .ok_or((StatusCode::CONFLICT, Json(json!({"error": "Message was changed or can no longer be edited"}))))?;
+
+    if verdict.is_spam(spam::flag_threshold()) {
+        spam::queue_for_review(&state, ContentKind::Message, oid, &content, &verdict).await;
+    }
+    unfurl::attach_later(&state, "messages", oid, &content).await;
+
+    // The chat list preview shows the old text if this was the last message
+    if !chat.is_encrypted {
+        let _ = chats_collection.update_one(
+            doc! { "_id": chat.id, "last_message": &msg.content },
+            doc! { "$set": { "last_message": &content } },
+            None
+        ).await;
+    }
+
+    let mut responses = messages_to_responses(&state, user.user_id, vec![updated]).await?;
+    let Some(res) = responses.pop() else {
+        return Err((StatusCode::GONE, Json(json!({"error": "Message has expired"}))));
+    };
+
+    // Everyone else sees it as someone else's message
+    let mut broadcast = json!(res);
+    broadcast["is_me"] = json!(false);
+    broadcast["status"] = json!(null);
+    let ws_payload = WsPayload { r#type: "message_edited".to_string(), data: broadcast.clone() };
+    for pid in chat.participants.iter().filter(|p| **p != user.user_id) {
+        send_to_user(&state, pid, &ws_payload).await;
+    }
+    publish_to_topic(&state, &Topic::Chat(msg.chat_id), &ws_payload).await;
+    publish_to_ably(&format!("chat:{}", msg.chat_id.to_hex()), "message_edited", broadcast).await;
+
+    Ok((StatusCode::OK, Json(json!(res))))
+}
+
+// Edit History of a Message
+pub async fn get_edit_history_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(msg_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let oid = ObjectId::parse_str(&msg_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
+
+    let msg = state.mongo.collection::<Message>("messages").find_one(doc! { "_id": oid }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Message not found"}))))?;
+    state.mongo.collection::<Chat>("chats").find_one(doc! { "_id": msg.chat_id, "participants": user.user_id }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Message not found"}))))?;
+
+    // Deleted and opened view-once messages keep nothing to show
+    let revisions: Vec<RevisionResponse> = if msg.is_deleted || msg.viewed_at.is_some() {
+        Vec::new()
+    } else {
+        msg.edit_history.into_iter().map(|r| RevisionResponse {
+            content: r.content,
+            encrypted_content: r.encrypted_content,
+            encryption_iv: r.encryption_iv,
+            replaced_at: r.replaced_at.to_chrono().to_rfc3339(),
+        }).collect()
+    };
+
+    Ok((StatusCode::OK, Json(json!({
+        "id": msg_id,
+        "edited_at": msg.edited_at.map(|dt| dt.to_chrono().to_rfc3339()),
+        "revisions": revisions,
+    }))))
+}
+
 // Vote on Poll
 pub async fn vote_poll_handler(
     State(state): State<Arc<AppState>>,
@@ -1163,62 +1538,20 @@ pub async fn vote_poll_handler(
 // Get Link Preview
 pub async fn get_link_preview_handler(
     State(state): State<Arc<AppState>>,
+    _user: AuthUser,
     Query(params): Query<std::collections::HashMap<String, String>>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     let url = params.get("url").ok_or((StatusCode::BAD_REQUEST, Json(json!({"error": "Missing URL"}))))?;
-    
-    let res = state.http_client.get(url)
-        .header("User-Agent", "Mozilla/5.0 (compatible; KaruTeensBot/1.0)")
-        .timeout(std::time::Duration::from_secs(5))
-        .send().await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
-    let body = res.text().await.unwrap_or_default();
-
-    let mut preview = LinkPreviewResponse {
-        title: None,
-        description: None,
-        image: None,
-        url: url.clone(),
-    };
-
-    // Regex for title
-    let title_re = regex::Regex::new(r#"(?i)<title[^>]*>(.*?)</title>"#).unwrap();
-    if let Some(caps) = title_re.captures(&body) {
-        preview.title = Some(caps.get(1).unwrap().as_str().trim().to_string());
+    match unfurl::unfurl(&state, url).await {
+        Ok(Some(preview)) => Ok((StatusCode::OK, Json(json!(preview)))),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(json!({"error": "Nothing to preview at this link"})))),
+        Err(unfurl::UnfurlError::InvalidUrl(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": format!("Invalid URL: {}", e)})))),
+        Err(unfurl::UnfurlError::Blocked(_)) => Err((StatusCode::FORBIDDEN, Json(json!({"error": "This link can't be previewed"})))),
+        Err(e) => Err((StatusCode::BAD_GATEWAY, Json(json!({"error": e.to_string()})))),
     }
-
-    // Look for OG tags
-    let og_title = extract_meta(&body, "og:title");
-    if og_title.is_some() { preview.title = og_title; }
-    
-    preview.description = extract_meta(&body, "og:description")
-        .or_else(|| extract_meta(&body, "description"));
-    
-    preview.image = extract_meta(&body, "og:image");
-
-    Ok((StatusCode::OK, Json(preview)))
 }
 
-fn extract_meta(body: &str, property: &str) -> Option<String> {
-    // Regex to match <meta ... property="og:title" ... content="Value" ...>
-    // or <meta ... content="Value" ... property="og:title" ...>
-    // It handles: property/name, single/double quotes, and attribute order.
-    
-    let re_str = format!(
-        r#"(?i)<meta\s+[^>]*?(?:(?:property|name)\s*=\s*['"]{}['"]|content\s*=\s*['"]([^'"]*?)['"])[^>]*?(?:(?:property|name)\s*=\s*['"]{}['"]|content\s*=\s*['"]([^'"]*?)['"])[^>]*?>"#,
-        regex::escape(property),
-        regex::escape(property)
-    );
-
-    if let Ok(re) = regex::Regex::new(&re_str) {
-        if let Some(caps) = re.captures(body) {
-            // One of the captures will be the content, depending on order
-            return caps.get(1).or_else(|| caps.get(2)).map(|m| m.as_str().to_string());
-        }
-    }
-    None
-}
 
 // Add Participants to Group
 pub async fn add_participants_handler(
@@ -1251,10 +1584,14 @@ pub async fn add_participants_handler(
 
     chats_collection.update_one(
         doc! { "_id": oid },
-        doc! { "$push": { "participants": { "$each": new_ids } } },
+        doc! { "$push": { "participants": { "$each": &new_ids } } },
         None
     ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
+    let mut chat = chat;
+    chat.participants.extend(new_ids);
+    e2e::request_sender_key_rotation(&state, &chat, "joined").await;
+
     Ok(StatusCode::OK)
 }
 
@@ -1270,7 +1607,7 @@ pub async fn remove_participant_handler(
     let profiles_collection = state.mongo.collection::<Profile>("profiles");
 
     // Check if chat is group and user is admin
-    let _chat = chats_collection.find_one(doc! { "_id": oid, "is_group": true, "admins": user.user_id }, None).await
+    let mut chat = chats_collection.find_one(doc! { "_id": oid, "is_group": true, "admins": user.user_id }, None).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
         .ok_or((StatusCode::FORBIDDEN, Json(json!({"error": "Only admins can remove participants"}))))?;
 
@@ -1288,6 +1625,12 @@ pub async fn remove_participant_handler(
         None
     ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
+    receipts::forget_participant(&state, oid, p_to_remove.user_id).await;
+
+    // They still hold the old sender keys
+    chat.participants.retain(|id| *id != p_to_remove.user_id);
+    e2e::request_sender_key_rotation(&state, &chat, "left").await;
+
     Ok(StatusCode::OK)
 }
 
@@ -1300,12 +1643,17 @@ pub async fn leave_group_handler(
     let oid = ObjectId::parse_str(&chat_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
     let chats_collection = state.mongo.collection::<Chat>("chats");
 
-    chats_collection.update_one(
+    let remaining = chats_collection.find_one_and_update(
         doc! { "_id": oid, "is_group": true },
         doc! { "$pull": { "participants": user.user_id, "admins": user.user_id } },
-        None
+        mongodb::options::FindOneAndUpdateOptions::builder().return_document(mongodb::options::ReturnDocument::After).build()
     ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
+    if let Some(chat) = remaining {
+        receipts::forget_participant(&state, oid, user.user_id).await;
+        e2e::request_sender_key_rotation(&state, &chat, "left").await;
+    }
+
     Ok(StatusCode::OK)
 }
 
@@ -1333,13 +1681,65 @@ pub async fn set_disappearing_handler(
 
     chats_collection.update_one(
         doc! { "_id": oid },
-        doc! { "$set": { "disappearing_duration": payload.duration } },
+        doc! { "$set": { "disappearing_duration": payload.duration.filter(|d| *d > 0) } },
         None
     ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
 
     Ok((StatusCode::OK, Json(json!({"message": "Disappearing messages updated"}))))
 }
 
+// Turn On End-to-End Encryption (one way: an encrypted chat can't be downgraded)
+pub async fn enable_encryption_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(chat_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let oid = ObjectId::parse_str(&chat_id).map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid ID"}))))?;
+    let chats_collection = state.mongo.collection::<Chat>("chats");
+
+    let chat = chats_collection.find_one(doc! { "_id": oid, "participants": user.user_id }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"error": "Chat not found"}))))?;
+
+    if chat.is_encrypted {
+        return Ok((StatusCode::OK, Json(json!({"message": "Chat is already end-to-end encrypted"}))));
+    }
+    // Fetching key bundles needs the real account, which would unmask an alias
+    if chat.aliases.is_some() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Anonymous chats can't be end-to-end encrypted"}))));
+    }
+    if chat.is_group && !chat.admins.contains(&user.user_id) {
+        return Err((StatusCode::FORBIDDEN, Json(json!({"error": "Only admins can change this setting"}))));
+    }
+
+    let with_keys = state.mongo.collection::<crate::models::DeviceKeys>("device_keys")
+        .distinct("user_id", doc! { "user_id": { "$in": &chat.participants } }, None).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+    let missing = chat.participants.len().saturating_sub(with_keys.len());
+    if missing > 0 {
+        return Err((StatusCode::CONFLICT, Json(json!({
+            "error": "Everyone in the chat needs to set up encryption first",
+            "missing": missing,
+        }))));
+    }
+
+    chats_collection.update_one(
+        doc! { "_id": oid },
+        doc! { "$set": { "is_encrypted": true } },
+        None
+    ).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?;
+
+    let event = json!({ "chat_id": oid.to_hex(), "enabled_by": user.user_id.to_hex() });
+    publish_to_ably(&format!("chat:{}", oid.to_hex()), "encryption_enabled", event.clone()).await;
+    publish_to_topic(&state, &Topic::Chat(oid), &WsPayload { r#type: "encryption_enabled".to_string(), data: event }).await;
+
+    let mut chat = chat;
+    chat.is_encrypted = true;
+    e2e::request_sender_key_rotation(&state, &chat, "enabled").await;
+
+    Ok((StatusCode::OK, Json(json!({"message": "End-to-end encryption turned on"}))))
+}
+
 // Update Group Metadata
 pub async fn update_group_handler(
     State(state): State<Arc<AppState>>,
@@ -1,4 +1,5 @@
 pub mod follows;
+pub mod gamification;
 pub mod notifications;
 pub mod messages;
 pub mod ws;
@@ -9,3 +10,8 @@ pub mod pages;
 pub mod confessions;
 pub mod hookup;
 pub mod live;
+pub mod live_chat;
+pub mod message_expiry;
+pub mod e2e;
+pub mod receipts;
+pub mod scheduled_messages;
@@ -46,10 +46,16 @@ pub async fn get_notifications_handler(
     let mut responses = Vec::new();
     while let Some(result) = cursor.next().await {
         if let Ok(notif) = result {
-            let actor = profile_collection.find_one(doc! { "user_id": notif.actor_id }, None).await.unwrap_or(None);
+            // System notices are stored with the recipient as their own actor
+            let actor = if notif.actor_id == notif.user_id {
+                None
+            } else {
+                profile_collection.find_one(doc! { "user_id": notif.actor_id }, None).await.unwrap_or(None)
+            };
+            let fallback = if notif.actor_id == notif.user_id { SYSTEM_ACTOR } else { "Someone" };
             responses.push(NotificationResponse {
                 id: notif.id.unwrap().to_hex(),
-                actor_username: actor.as_ref().map(|p| p.username.clone()).unwrap_or_else(|| "Someone".to_string()),
+                actor_username: actor.as_ref().map(|p| p.username.clone()).unwrap_or_else(|| fallback.to_string()),
                 actor_avatar_url: actor.and_then(|p| p.avatar_url),
                 notification_type: notif.notification_type,
                 target_id: notif.target_id.map(|oid| oid.to_hex()),
@@ -109,6 +115,50 @@ pub async fn delete_notification_handler(
     Ok(StatusCode::OK)
 }
 
+const SYSTEM_ACTOR: &str = "KaruTeens";
+
+/// A notice from the platform itself (billing, account changes), never batched.
+pub async fn create_system_notification(
+    state: &Arc<AppState>,
+    user_id: ObjectId,
+    notification_type: &str,
+    target_id: Option<ObjectId>,
+    content: &str,
+    broadcast: bool,
+) -> Result<(), mongodb::error::Error> {
+    let new_notif = Notification {
+        id: None,
+        user_id,
+        actor_id: user_id,
+        notification_type: notification_type.to_string(),
+        target_id,
+        content: content.to_string(),
+        is_read: false,
+        created_at: mongodb::bson::DateTime::now(),
+    };
+    let result = state.mongo.collection::<Notification>("notifications").insert_one(new_notif, None).await?;
+
+    if broadcast {
+        let ws_payload = WsPayload {
+            r#type: "notification".to_string(),
+            data: json!(NotificationResponse {
+                id: result.inserted_id.as_object_id().map(|id| id.to_hex()).unwrap_or_default(),
+                actor_username: SYSTEM_ACTOR.to_string(),
+                actor_avatar_url: None,
+                notification_type: notification_type.to_string(),
+                target_id: target_id.map(|id| id.to_hex()),
+                content: content.to_string(),
+                is_read: false,
+                created_at: chrono::Utc::now().to_rfc3339(),
+            }),
+        };
+        send_to_user(state, &user_id, &ws_payload).await;
+        let channel = format!("user:{}:notifications", user_id.to_hex());
+        publish_to_ably(&channel, "new_notification", ws_payload.data).await;
+    }
+    Ok(())
+}
+
 // Utility to create notification (not a handler)
 // Batching: same actor+type within 60s window gets merged into one notification
 pub async fn create_notification(
@@ -179,6 +179,7 @@ pub async fn follow_page_handler(
     follows_coll.insert_one(follow, None).await?;
     
     pages_coll.update_one(doc! { "_id": oid }, doc! { "$inc": { "follower_count": 1 } }, None).await.ok();
+    crate::features::content::timeline::invalidate_sources(&state, user.user_id).await;
 
     Ok((StatusCode::OK, Json(json!({"message": "Followed successfully"}))))
 }
@@ -197,6 +198,7 @@ pub async fn unfollow_page_handler(
     if res.deleted_count > 0 {
         pages_coll.update_one(doc! { "_id": oid }, doc! { "$inc": { "follower_count": -1 } }, None).await.ok();
     }
+    crate::features::content::timeline::invalidate_sources(&state, user.user_id).await;
 
     Ok((StatusCode::OK, Json(json!({"message": "Unfollowed successfully"}))))
 }
@@ -0,0 +1,409 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::options::{FindOneAndUpdateOptions, FindOneOptions, ReturnDocument};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use crate::features::auth::auth_service::AuthUser;
+use crate::features::infrastructure::db::AppState;
+use crate::features::social::ably::publish_to_ably;
+use crate::features::social::ws::{send_to_user, WsPayload};
+use crate::models::{Chat, ChatCursor, Message, MessageReceipt, Profile};
+
+// Delivery and read receipts. Every participant has a cursor on the chat document: the last
+// message delivered to them, the last one they read, and an unread counter that sending bumps,
+// so the chat list never has to scan messages; reading takes what it read off that counter in
+// the same write that moves the cursor. Each cursor move is also logged to
+// `message_receipts`, which is where per-recipient times in group receipts come from.
+// Turning read receipts off keeps your own cursor and counter working; nobody else is told
+// when you read.
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+fn internal(e: impl std::fmt::Display) -> ApiError {
+    error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReceiptStatus {
+    Sent,
+    Delivered,
+    Read,
+}
+
+impl ReceiptStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReceiptStatus::Sent => "sent",
+            ReceiptStatus::Delivered => "delivered",
+            ReceiptStatus::Read => "read",
+        }
+    }
+}
+
+fn cursor_path(user_id: ObjectId, field: &str) -> String {
+    format!("cursors.{}.{}", user_id.to_hex(), field)
+}
+
+/// Matches a chat whose cursor for `user_id` hasn't reached `up_to` yet.
+fn behind(user_id: ObjectId, field: &str, up_to: ObjectId) -> Vec<Document> {
+    let path = cursor_path(user_id, field);
+    vec![doc! { &path: null }, doc! { &path: { "$lt": up_to } }]
+}
+
+/// The chat update for a new message: everyone else has one more unread, and the sender has
+/// caught up with the chat by writing in it.
+pub fn sent_update(sender_id: ObjectId, participants: &[ObjectId], message_id: ObjectId, now: DateTime) -> Document {
+    let mut inc = Document::new();
+    for pid in participants.iter().filter(|p| **p != sender_id) {
+        inc.insert(cursor_path(*pid, "unread_count"), 1_i64);
+    }
+    let mut update = doc! {
+        "$max": {
+            cursor_path(sender_id, "last_delivered_id"): message_id,
+            cursor_path(sender_id, "last_read_id"): message_id,
+        },
+        "$set": {
+            cursor_path(sender_id, "delivered_at"): now,
+            cursor_path(sender_id, "read_at"): now,
+            cursor_path(sender_id, "unread_count"): 0_i64,
+        },
+    };
+    if !inc.is_empty() {
+        update.insert("$inc", inc);
+    }
+    update
+}
+
+pub async fn record_sent(state: &Arc<AppState>, chat_id: ObjectId, participants: &[ObjectId], sender_id: ObjectId, message_id: ObjectId) {
+    let update = sent_update(sender_id, participants, message_id, DateTime::now());
+    if let Err(e) = state.mongo.collection::<Chat>("chats").update_one(doc! { "_id": chat_id }, update, None).await {
+        tracing::warn!("Failed to update unread counters for chat {}: {}", chat_id.to_hex(), e);
+    }
+}
+
+/// Where a message stands for its sender: read once every other participant has read it,
+/// delivered once it reached all of them. Readers who hide receipts only ever count as delivered.
+/// The time is the last reader's cursor move, which is close enough for a list of messages;
+/// exact per-recipient times come from the receipt log.
+pub fn message_status(
+    message_id: ObjectId,
+    sender_id: ObjectId,
+    participants: &[ObjectId],
+    cursors: &HashMap<String, ChatCursor>,
+    hidden: &HashSet<ObjectId>,
+) -> (ReceiptStatus, Option<DateTime>) {
+    let mut status = ReceiptStatus::Read;
+    let mut read_at: Option<DateTime> = None;
+    let mut others = 0;
+    for pid in participants.iter().filter(|p| **p != sender_id) {
+        others += 1;
+        let cursor = cursors.get(&pid.to_hex()).cloned().unwrap_or_default();
+        let reached = |id: Option<ObjectId>| id.is_some_and(|id| id >= message_id);
+        if reached(cursor.last_read_id) && !hidden.contains(pid) {
+            read_at = read_at.max(cursor.read_at);
+        } else if reached(cursor.last_delivered_id) || reached(cursor.last_read_id) {
+            status = status.min(ReceiptStatus::Delivered);
+        } else {
+            status = ReceiptStatus::Sent;
+        }
+    }
+    if others == 0 {
+        return (ReceiptStatus::Sent, None);
+    }
+    (status, read_at.filter(|_| status == ReceiptStatus::Read))
+}
+
+/// The participants among `user_ids` who have turned read receipts off.
+pub async fn hidden_readers(state: &AppState, user_ids: &[ObjectId]) -> HashSet<ObjectId> {
+    let mut hidden = HashSet::new();
+    let filter = doc! { "user_id": { "$in": user_ids }, "privacy_settings.read_receipts": false };
+    if let Ok(mut cursor) = state.mongo.collection::<Profile>("profiles").find(filter, None).await {
+        while let Some(Ok(profile)) = cursor.next().await {
+            hidden.insert(profile.user_id);
+        }
+    }
+    hidden
+}
+
+async fn log_receipt(state: &Arc<AppState>, chat_id: ObjectId, user_id: ObjectId, status: ReceiptStatus, up_to: ObjectId, at: DateTime) {
+    let receipt = MessageReceipt { id: None, chat_id, user_id, status: status.as_str().to_string(), up_to, at };
+    let _ = state.mongo.collection::<MessageReceipt>("message_receipts").insert_one(receipt, None).await;
+}
+
+async fn broadcast(state: &Arc<AppState>, chat: &Chat, user_id: ObjectId, status: ReceiptStatus, up_to: ObjectId, at: DateTime) {
+    let chat_id = chat.id.unwrap();
+    // Anonymous chats only ever show the alias
+    let shown_as = chat.aliases.iter().flatten().find(|a| a.user_id == user_id).map(|a| a.alias_id).unwrap_or(user_id);
+    let data = json!({
+        "chat_id": chat_id.to_hex(),
+        "user_id": shown_as.to_hex(),
+        "status": status.as_str(),
+        "up_to": up_to.to_hex(),
+        "at": at.to_chrono().to_rfc3339(),
+    });
+    let payload = WsPayload { r#type: "receipt".to_string(), data: data.clone() };
+    for pid in chat.participants.iter().filter(|p| **p != user_id) {
+        send_to_user(state, pid, &payload).await;
+    }
+    publish_to_ably(&format!("chat:{}", chat_id.to_hex()), "receipt", data).await;
+}
+
+/// Moves the delivered cursor up to `up_to`. Returns false if it was already there.
+pub async fn mark_delivered(state: &Arc<AppState>, chat: &Chat, user_id: ObjectId, up_to: ObjectId) -> Result<bool, mongodb::error::Error> {
+    let chat_id = chat.id.unwrap();
+    let now = DateTime::now();
+    let result = state.mongo.collection::<Chat>("chats").update_one(
+        doc! { "_id": chat_id, "participants": user_id, "$or": behind(user_id, "last_delivered_id", up_to) },
+        doc! { "$set": { cursor_path(user_id, "last_delivered_id"): up_to, cursor_path(user_id, "delivered_at"): now } },
+        None,
+    ).await?;
+    if result.modified_count == 0 {
+        return Ok(false);
+    }
+    log_receipt(state, chat_id, user_id, ReceiptStatus::Delivered, up_to, now).await;
+    broadcast(state, chat, user_id, ReceiptStatus::Delivered, up_to, now).await;
+    Ok(true)
+}
+
+/// The chat update for `user_id` reading up to `up_to`, which takes `newly_read` messages off
+/// their unread count in the same write that moves the cursor. Sends `$inc` the counter
+/// concurrently, so it's adjusted rather than overwritten; the floor of zero absorbs drift.
+pub fn read_update(user_id: ObjectId, up_to: ObjectId, newly_read: i64, now: DateTime) -> Vec<Document> {
+    let unread = cursor_path(user_id, "unread_count");
+    vec![doc! { "$set": {
+        cursor_path(user_id, "last_read_id"): up_to,
+        cursor_path(user_id, "read_at"): now,
+        &unread: { "$max": [0_i64, { "$subtract": [{ "$ifNull": [format!("${}", unread), 0_i64] }, newly_read] }] },
+    } }]
+}
+
+/// Marks everything up to `up_to` read. Returns the new unread count, or None if the read
+/// cursor was already past `up_to`.
+pub async fn mark_read(state: &Arc<AppState>, chat: &Chat, user_id: ObjectId, up_to: ObjectId) -> Result<Option<i64>, mongodb::error::Error> {
+    let chat_id = chat.id.unwrap();
+    let chats = state.mongo.collection::<Chat>("chats");
+    let messages = state.mongo.collection::<Message>("messages");
+    let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+    let now = DateTime::now();
+
+    let mut last_read = chat.cursors.get(&user_id.to_hex()).and_then(|c| c.last_read_id);
+    // Compare-and-set on the cursor we counted from; another device moving it means recounting
+    let updated = loop {
+        if last_read.is_some_and(|id| id >= up_to) {
+            return Ok(None);
+        }
+        let mut range = doc! { "$lte": up_to };
+        if let Some(from) = last_read {
+            range.insert("$gt", from);
+        }
+        let newly_read = messages.count_documents(
+            doc! { "chat_id": chat_id, "_id": range, "sender_id": { "$ne": user_id } },
+            None,
+        ).await? as i64;
+
+        let updated = chats.find_one_and_update(
+            doc! { "_id": chat_id, "participants": user_id, cursor_path(user_id, "last_read_id"): last_read },
+            read_update(user_id, up_to, newly_read, now),
+            options.clone(),
+        ).await?;
+        if let Some(updated) = updated {
+            break updated;
+        }
+        match chats.find_one(doc! { "_id": chat_id, "participants": user_id }, None).await? {
+            Some(current) => last_read = current.cursors.get(&user_id.to_hex()).and_then(|c| c.last_read_id),
+            None => return Ok(None),
+        }
+    };
+    let unread = updated.cursors.get(&user_id.to_hex()).map(|c| c.unread_count).unwrap_or(0);
+    log_receipt(state, chat_id, user_id, ReceiptStatus::Read, up_to, now).await;
+
+    // Reading something means it arrived
+    mark_delivered(state, chat, user_id, up_to).await?;
+    if hidden_readers(state, &[user_id]).await.is_empty() {
+        broadcast(state, chat, user_id, ReceiptStatus::Read, up_to, now).await;
+    }
+
+    // Keeps the reader's other devices in step
+    send_to_user(state, &user_id, &WsPayload {
+        r#type: "chat_read".to_string(),
+        data: json!({ "chat_id": chat_id.to_hex(), "up_to": up_to.to_hex(), "unread_count": unread }),
+    }).await;
+    Ok(Some(unread))
+}
+
+/// Drops a participant's cursor when they leave or are removed.
+pub async fn forget_participant(state: &Arc<AppState>, chat_id: ObjectId, user_id: ObjectId) {
+    let _ = state.mongo.collection::<Chat>("chats").update_one(
+        doc! { "_id": chat_id },
+        doc! { "$unset": { format!("cursors.{}", user_id.to_hex()): "" } },
+        None,
+    ).await;
+}
+
+// --- Handlers ---
+
+#[derive(Debug, Deserialize, Default)]
+pub struct CursorRequest {
+    pub message_id: Option<String>, // Defaults to the newest message in the chat
+}
+
+async fn load_chat(state: &Arc<AppState>, chat_id: &str, user_id: ObjectId) -> Result<Chat, ApiError> {
+    let oid = ObjectId::parse_str(chat_id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid ID"))?;
+    state.mongo.collection::<Chat>("chats")
+        .find_one(doc! { "_id": oid, "participants": user_id }, None).await
+        .map_err(internal)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Chat not found or access denied"))
+}
+
+/// The message a cursor should move to: the one named, as long as it is in this chat, or the
+/// newest one. None for an empty chat.
+async fn resolve_up_to(state: &Arc<AppState>, chat_id: ObjectId, message_id: Option<&str>) -> Result<Option<ObjectId>, ApiError> {
+    let messages = state.mongo.collection::<Message>("messages");
+    let found = match message_id {
+        Some(id) => {
+            let oid = ObjectId::parse_str(id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid message ID"))?;
+            let msg = messages.find_one(doc! { "_id": oid, "chat_id": chat_id }, None).await.map_err(internal)?;
+            Some(msg.ok_or_else(|| error(StatusCode::NOT_FOUND, "Message not found in this chat"))?)
+        }
+        None => {
+            let options = FindOneOptions::builder().sort(doc! { "_id": -1 }).build();
+            messages.find_one(doc! { "chat_id": chat_id }, options).await.map_err(internal)?
+        }
+    };
+    Ok(found.and_then(|m| m.id))
+}
+
+fn unread_of(chat: &Chat, user_id: ObjectId) -> i64 {
+    chat.cursors.get(&user_id.to_hex()).map(|c| c.unread_count).unwrap_or(0)
+}
+
+// Mark a chat read up to a message
+pub async fn mark_chat_read_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(chat_id): Path<String>,
+    payload: Option<Json<CursorRequest>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let chat = load_chat(&state, &chat_id, user.user_id).await?;
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    let Some(up_to) = resolve_up_to(&state, chat.id.unwrap(), payload.message_id.as_deref()).await? else {
+        return Ok((StatusCode::OK, Json(json!({ "unread_count": 0, "last_read_id": null }))));
+    };
+
+    let unread = match mark_read(&state, &chat, user.user_id, up_to).await.map_err(internal)? {
+        Some(unread) => unread,
+        None => unread_of(&chat, user.user_id),
+    };
+    Ok((StatusCode::OK, Json(json!({ "unread_count": unread, "last_read_id": up_to.to_hex() }))))
+}
+
+// Acknowledge delivery up to a message
+pub async fn mark_chat_delivered_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(chat_id): Path<String>,
+    payload: Option<Json<CursorRequest>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let chat = load_chat(&state, &chat_id, user.user_id).await?;
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    if let Some(up_to) = resolve_up_to(&state, chat.id.unwrap(), payload.message_id.as_deref()).await? {
+        mark_delivered(&state, &chat, user.user_id, up_to).await.map_err(internal)?;
+    }
+    Ok(StatusCode::OK)
+}
+
+// Mark Message as Read: kept for older clients, reads the chat up to this message
+pub async fn mark_message_read_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(msg_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let oid = ObjectId::parse_str(&msg_id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid ID"))?;
+    let msg = state.mongo.collection::<Message>("messages")
+        .find_one(doc! { "_id": oid }, None).await.map_err(internal)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Message not found"))?;
+    let chat = load_chat(&state, &msg.chat_id.to_hex(), user.user_id).await?;
+    mark_read(&state, &chat, user.user_id, oid).await.map_err(internal)?;
+    Ok(StatusCode::OK)
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecipientReceipt {
+    pub user_id: String,
+    pub username: String,
+    pub avatar_url: Option<String>,
+    pub delivered_at: Option<String>,
+    pub read_at: Option<String>,
+}
+
+// Who a message reached and who read it. Only its sender may ask.
+pub async fn get_message_receipts_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(msg_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let oid = ObjectId::parse_str(&msg_id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid ID"))?;
+    let msg = state.mongo.collection::<Message>("messages")
+        .find_one(doc! { "_id": oid }, None).await.map_err(internal)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Message not found"))?;
+    if msg.sender_id != user.user_id {
+        return Err(error(StatusCode::FORBIDDEN, "Only the sender can see receipts for a message"));
+    }
+    let chat = load_chat(&state, &msg.chat_id.to_hex(), user.user_id).await?;
+    let recipients: Vec<ObjectId> = chat.participants.iter().copied().filter(|p| *p != user.user_id).collect();
+    let hidden = hidden_readers(&state, &recipients).await;
+
+    // The first cursor move to reach this message is when it was delivered or read
+    let pipeline = vec![
+        doc! { "$match": { "chat_id": msg.chat_id, "user_id": { "$in": &recipients }, "up_to": { "$gte": oid } } },
+        doc! { "$sort": { "up_to": 1, "at": 1 } },
+        doc! { "$group": { "_id": { "user_id": "$user_id", "status": "$status" }, "at": { "$first": "$at" } } },
+    ];
+    let mut times: HashMap<(ObjectId, String), DateTime> = HashMap::new();
+    let mut cursor = state.mongo.collection::<MessageReceipt>("message_receipts")
+        .aggregate(pipeline, None).await.map_err(internal)?;
+    while let Some(Ok(d)) = cursor.next().await {
+        let (Ok(key), Ok(at)) = (d.get_document("_id"), d.get_datetime("at")) else { continue };
+        if let (Ok(uid), Ok(status)) = (key.get_object_id("user_id"), key.get_str("status")) {
+            times.insert((uid, status.to_string()), *at);
+        }
+    }
+
+    let mut profiles = HashMap::new();
+    let mut profile_cursor = state.mongo.collection::<Profile>("profiles")
+        .find(doc! { "user_id": { "$in": &recipients } }, None).await.map_err(internal)?;
+    while let Some(Ok(p)) = profile_cursor.next().await {
+        profiles.insert(p.user_id, p);
+    }
+
+    let receipts: Vec<RecipientReceipt> = recipients.iter().map(|pid| {
+        let at = |status: ReceiptStatus| times.get(&(*pid, status.as_str().to_string())).map(|dt| dt.to_chrono().to_rfc3339());
+        let read_at = if hidden.contains(pid) { None } else { at(ReceiptStatus::Read) };
+        let alias = chat.aliases.iter().flatten().find(|a| a.user_id == *pid);
+        let profile = profiles.get(pid);
+        RecipientReceipt {
+            user_id: alias.map(|a| a.alias_id).unwrap_or(*pid).to_hex(),
+            username: alias.map(|a| a.alias_username.clone())
+                .or_else(|| profile.map(|p| p.username.clone()))
+                .unwrap_or_else(|| "Unknown".to_string()),
+            avatar_url: if alias.is_some() { None } else { profile.and_then(|p| p.avatar_url.clone()) },
+            delivered_at: at(ReceiptStatus::Delivered).or_else(|| read_at.clone()),
+            read_at,
+        }
+    }).collect();
+
+    let (status, _) = message_status(oid, msg.sender_id, &chat.participants, &chat.cursors, &hidden);
+    Ok((StatusCode::OK, Json(json!({ "status": status.as_str(), "recipients": receipts }))))
+}
@@ -0,0 +1,365 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use crate::features::auth::auth_service::AuthUser;
+use crate::features::infrastructure::db::AppState;
+use crate::features::infrastructure::jobs::{Job, JobError};
+use crate::features::social::messages::{deliver_message, SendMessageRequest};
+use crate::features::social::notifications::create_system_notification;
+use crate::features::social::ws::{send_to_user, WsPayload};
+use crate::models::{Chat, Message, ScheduledMessage};
+
+// "Send later". A scheduled message is only stored when it is queued; when it falls due the
+// sweeper hands it to the same delivery path a live send uses, so verification, blocks and
+// mutes are checked as they stand at that moment. If delivery is refused the message is
+// marked failed and the sender is told why. A message left `sending` by an instance that
+// died mid-delivery is picked up again by the next sweep once its claim has gone stale.
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+fn internal(e: impl std::fmt::Display) -> ApiError {
+    error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+}
+
+/// How far ahead a message can be scheduled.
+pub const MAX_SCHEDULE_DAYS: i64 = 30;
+/// Pending scheduled messages one user may have across all chats.
+const MAX_PENDING_PER_USER: u64 = 100;
+/// How long a sweep may hold a message in `sending` before it counts as abandoned.
+pub const SENDING_TIMEOUT_MINUTES: i64 = 10;
+
+fn scheduled(state: &AppState) -> mongodb::Collection<ScheduledMessage> {
+    state.mongo.collection::<ScheduledMessage>("scheduled_messages")
+}
+
+/// Parses and checks a requested send time: at least a minute out, at most
+/// `MAX_SCHEDULE_DAYS` away.
+pub fn validate_send_at(raw: &str, now: chrono::DateTime<Utc>) -> Result<chrono::DateTime<Utc>, String> {
+    let send_at = chrono::DateTime::parse_from_rfc3339(raw)
+        .map_err(|_| "send_at must be an RFC 3339 timestamp".to_string())?
+        .with_timezone(&Utc);
+    if send_at < now + chrono::Duration::minutes(1) {
+        return Err("send_at must be at least a minute from now".to_string());
+    }
+    if send_at > now + chrono::Duration::days(MAX_SCHEDULE_DAYS) {
+        return Err(format!("Messages can be scheduled at most {} days ahead", MAX_SCHEDULE_DAYS));
+    }
+    Ok(send_at)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleMessageRequest {
+    pub send_at: String,
+    pub content: String,
+    pub attachment_url: Option<String>,
+    pub attachment_type: Option<String>,
+    pub reply_to_id: Option<String>,
+    pub is_view_once: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledMessageResponse {
+    pub id: String,
+    pub chat_id: String,
+    pub content: String,
+    pub attachment_url: Option<String>,
+    pub attachment_type: Option<String>,
+    pub reply_to_id: Option<String>,
+    pub is_view_once: bool,
+    pub send_at: String,
+    pub status: String,
+    pub message_id: Option<String>,
+    pub error: Option<String>,
+    pub created_at: String,
+}
+
+impl From<ScheduledMessage> for ScheduledMessageResponse {
+    fn from(s: ScheduledMessage) -> Self {
+        ScheduledMessageResponse {
+            id: s.id.map(|id| id.to_hex()).unwrap_or_default(),
+            chat_id: s.chat_id.to_hex(),
+            content: s.content,
+            attachment_url: s.attachment_url,
+            attachment_type: s.attachment_type,
+            reply_to_id: s.reply_to_id.map(|id| id.to_hex()),
+            is_view_once: s.is_view_once,
+            send_at: s.send_at.to_chrono().to_rfc3339(),
+            status: s.status,
+            message_id: s.message_id.map(|id| id.to_hex()),
+            error: s.error,
+            created_at: s.created_at.to_chrono().to_rfc3339(),
+        }
+    }
+}
+
+async fn load_chat(state: &Arc<AppState>, chat_id: &str, user_id: ObjectId) -> Result<Chat, ApiError> {
+    let oid = ObjectId::parse_str(chat_id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid ID"))?;
+    state.mongo.collection::<Chat>("chats")
+        .find_one(doc! { "_id": oid, "participants": user_id }, None).await
+        .map_err(internal)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Chat not found or access denied"))
+}
+
+// Schedule a message
+pub async fn schedule_message_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(chat_id): Path<String>,
+    Json(payload): Json<ScheduleMessageRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let chat = load_chat(&state, &chat_id, user.user_id).await?;
+    // By the time it goes out the chat's keys may have changed under the ciphertext
+    if chat.is_encrypted {
+        return Err(error(StatusCode::BAD_REQUEST, "Messages can't be scheduled in end-to-end encrypted chats"));
+    }
+    let send_at = validate_send_at(&payload.send_at, Utc::now()).map_err(|e| error(StatusCode::BAD_REQUEST, &e))?;
+    if payload.content.trim().is_empty() && payload.attachment_url.is_none() {
+        return Err(error(StatusCode::BAD_REQUEST, "Message can't be empty"));
+    }
+    let reply_to_id = match payload.reply_to_id.as_deref() {
+        Some(id) => Some(ObjectId::parse_str(id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid reply_to_id"))?),
+        None => None,
+    };
+
+    let pending = scheduled(&state)
+        .count_documents(doc! { "sender_id": user.user_id, "status": "pending" }, None).await
+        .map_err(internal)?;
+    if pending >= MAX_PENDING_PER_USER {
+        return Err(error(StatusCode::TOO_MANY_REQUESTS, "Too many scheduled messages; cancel some first"));
+    }
+
+    let now = DateTime::now();
+    let mut message = ScheduledMessage {
+        id: None,
+        chat_id: chat.id.unwrap(),
+        sender_id: user.user_id,
+        content: payload.content,
+        attachment_url: payload.attachment_url,
+        attachment_type: payload.attachment_type,
+        reply_to_id,
+        is_view_once: payload.is_view_once.unwrap_or(false),
+        send_at: DateTime::from_chrono(send_at),
+        status: "pending".to_string(),
+        message_id: None,
+        error: None,
+        created_at: now,
+        updated_at: now,
+    };
+    let result = scheduled(&state).insert_one(&message, None).await.map_err(internal)?;
+    message.id = result.inserted_id.as_object_id();
+
+    Ok((StatusCode::CREATED, Json(ScheduledMessageResponse::from(message))))
+}
+
+// List your scheduled messages in a chat
+pub async fn list_scheduled_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(chat_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let chat = load_chat(&state, &chat_id, user.user_id).await?;
+    let options = FindOptions::builder().sort(doc! { "send_at": 1 }).limit(200).build();
+    let mut cursor = scheduled(&state)
+        .find(doc! { "chat_id": chat.id, "sender_id": user.user_id, "status": { "$in": ["pending", "failed"] } }, options).await
+        .map_err(internal)?;
+
+    let mut list = Vec::new();
+    while let Some(Ok(s)) = cursor.next().await {
+        list.push(ScheduledMessageResponse::from(s));
+    }
+    Ok((StatusCode::OK, Json(list)))
+}
+
+// Cancel a scheduled message that hasn't gone out yet
+pub async fn cancel_scheduled_handler(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(scheduled_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let oid = ObjectId::parse_str(&scheduled_id).map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid ID"))?;
+    let result = scheduled(&state).update_one(
+        cancel_filter(oid, user.user_id),
+        doc! { "$set": { "status": "cancelled", "updated_at": DateTime::now() } },
+        None,
+    ).await.map_err(internal)?;
+    if result.matched_count == 0 {
+        return Err(error(StatusCode::CONFLICT, "Scheduled message not found or already sent"));
+    }
+    Ok(StatusCode::OK)
+}
+
+// --- Jobs ---
+
+/// Cron-driven: sends every scheduled message whose time has come.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendScheduledMessagesJob;
+
+impl Job for SendScheduledMessagesJob {
+    const KIND: &'static str = "messages.send_scheduled";
+    const MAX_ATTEMPTS: u32 = 3;
+}
+
+/// Only the sender can cancel, and only before the message has gone out (or after it failed).
+pub fn cancel_filter(id: ObjectId, sender_id: ObjectId) -> mongodb::bson::Document {
+    doc! { "_id": id, "sender_id": sender_id, "status": { "$in": ["pending", "failed"] } }
+}
+
+/// Pending messages whose time has come.
+pub fn due_filter(now: DateTime) -> mongodb::bson::Document {
+    doc! { "status": "pending", "send_at": { "$lte": now } }
+}
+
+/// Messages claimed for sending more than `SENDING_TIMEOUT_MINUTES` ago.
+pub fn stale_sending_filter(now: chrono::DateTime<Utc>) -> mongodb::bson::Document {
+    let cutoff = DateTime::from_chrono(now - chrono::Duration::minutes(SENDING_TIMEOUT_MINUTES));
+    doc! { "status": "sending", "updated_at": { "$lt": cutoff } }
+}
+
+/// Where a claimed message goes after a delivery attempt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeliveryOutcome {
+    Sent(ObjectId),
+    /// Our fault, not the message's: back to pending for the next sweep.
+    Retry(String),
+    /// Refused (blocked, no longer a member, ...): failed, and the sender is told why.
+    Failed(String),
+}
+
+pub fn delivery_outcome(result: Result<ObjectId, ApiError>) -> DeliveryOutcome {
+    match result {
+        Ok(message_id) => DeliveryOutcome::Sent(message_id),
+        Err((status, Json(body))) if status.is_server_error() => DeliveryOutcome::Retry(body.to_string()),
+        Err((_, Json(body))) => DeliveryOutcome::Failed(
+            body.get("error").and_then(|e| e.as_str()).unwrap_or("Delivery was refused").to_string(),
+        ),
+    }
+}
+
+/// Puts messages abandoned in `sending` back in the queue. One that actually went out
+/// before its sweep died is marked sent instead, so it isn't delivered twice.
+async fn reap_stale_sending(state: &Arc<AppState>) -> Result<(), mongodb::error::Error> {
+    let mut stale = Vec::new();
+    let mut cursor = scheduled(state).find(stale_sending_filter(Utc::now()), None).await?;
+    while let Some(message) = cursor.next().await {
+        stale.push(message?);
+    }
+
+    let messages = state.mongo.collection::<Message>("messages");
+    for message in stale {
+        let delivered = messages.find_one(
+            doc! {
+                "chat_id": message.chat_id,
+                "sender_id": message.sender_id,
+                "content": &message.content,
+                "attachment_url": &message.attachment_url,
+                "created_at": { "$gte": message.updated_at },
+            },
+            None,
+        ).await?;
+        let claim = doc! { "_id": message.id, "status": "sending", "updated_at": message.updated_at };
+        let update = match delivered.and_then(|m| m.id) {
+            Some(message_id) => doc! { "$set": { "status": "sent", "message_id": message_id, "updated_at": DateTime::now() } },
+            None => doc! { "$set": { "status": "pending", "updated_at": DateTime::now() } },
+        };
+        if scheduled(state).update_one(claim, update, None).await?.modified_count == 1 {
+            tracing::warn!("Recovered scheduled message {:?} left in sending", message.id);
+        }
+    }
+    Ok(())
+}
+
+/// Claims one due message. The status filter makes this a compare-and-set, so a message
+/// cancelled in the meantime, or claimed by another instance, is left alone.
+async fn claim_next_due(state: &Arc<AppState>) -> Result<Option<ScheduledMessage>, mongodb::error::Error> {
+    let now = DateTime::now();
+    scheduled(state).find_one_and_update(
+        due_filter(now),
+        doc! { "$set": { "status": "sending", "updated_at": now } },
+        FindOneAndUpdateOptions::builder()
+            .sort(doc! { "send_at": 1 })
+            .return_document(ReturnDocument::After)
+            .build(),
+    ).await
+}
+
+async fn notify_failure(state: &Arc<AppState>, message: &ScheduledMessage, reason: &str) {
+    let id = message.id.unwrap();
+    send_to_user(state, &message.sender_id, &WsPayload {
+        r#type: "scheduled_message_failed".to_string(),
+        data: json!({ "id": id.to_hex(), "chat_id": message.chat_id.to_hex(), "error": reason }),
+    }).await;
+    let _ = create_system_notification(
+        state,
+        message.sender_id,
+        "message",
+        Some(message.chat_id),
+        &format!("Your scheduled message couldn't be sent: {}", reason),
+        false,
+    ).await;
+}
+
+pub async fn run_send_scheduled(state: Arc<AppState>) -> Result<(), JobError> {
+    reap_stale_sending(&state).await?;
+    let mut sent = 0;
+    while let Some(message) = claim_next_due(&state).await? {
+        let id = message.id.unwrap();
+        let request = SendMessageRequest {
+            content: message.content.clone(),
+            attachment_url: message.attachment_url.clone(),
+            attachment_type: message.attachment_type.clone(),
+            reply_to_id: message.reply_to_id.map(|id| id.to_hex()),
+            encrypted_content: None,
+            encryption_iv: None,
+            poll: None,
+            location: None,
+            contact: None,
+            is_view_once: Some(message.is_view_once),
+        };
+
+        match delivery_outcome(deliver_message(&state, message.sender_id, message.chat_id, request).await) {
+            DeliveryOutcome::Sent(message_id) => {
+                sent += 1;
+                scheduled(&state).update_one(
+                    doc! { "_id": id },
+                    doc! { "$set": { "status": "sent", "message_id": message_id, "updated_at": DateTime::now() } },
+                    None,
+                ).await?;
+            }
+            DeliveryOutcome::Retry(reason) => {
+                scheduled(&state).update_one(
+                    doc! { "_id": id, "status": "sending" },
+                    doc! { "$set": { "status": "pending", "updated_at": DateTime::now() } },
+                    None,
+                ).await?;
+                return Err(JobError::Retry(reason));
+            }
+            DeliveryOutcome::Failed(reason) => {
+                scheduled(&state).update_one(
+                    doc! { "_id": id },
+                    doc! { "$set": { "status": "failed", "error": &reason, "updated_at": DateTime::now() } },
+                    None,
+                ).await?;
+                notify_failure(&state, &message, &reason).await;
+            }
+        }
+    }
+
+    if sent > 0 {
+        tracing::info!("Sent {} scheduled messages", sent);
+    }
+    Ok(())
+}
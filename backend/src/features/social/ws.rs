@@ -4,13 +4,29 @@ use axum::{
     http::HeaderMap,
 };
 use futures::{sink::SinkExt, stream::StreamExt};
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, oid::ObjectId};
+use redis::AsyncCommands;
 use serde::{Serialize, Deserialize};
 use serde_json::json;
 use crate::features::infrastructure::db::AppState;
-use crate::features::auth::auth_service::decode_token;
+use crate::features::auth::auth_service::validate_access_token;
+use crate::features::social::live;
+
+/// How often the server pings each socket and refreshes presence.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(25);
+/// Sockets silent for longer than this (no frames, no pongs) are dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Events kept per user for reconnecting clients.
+const REPLAY_BUFFER_SIZE: isize = 100;
+const REPLAY_BUFFER_TTL: i64 = 300;
+const SEQUENCE_TTL: i64 = 7 * 24 * 3600;
+
+/// Signaling that is useless once stale, so it is never buffered for replay.
+const EPHEMERAL_TYPES: &[&str] = &["call-offer", "call-answer", "ice-candidate", "typing"];
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WsPayload {
@@ -18,6 +34,41 @@ pub struct WsPayload {
     pub data: serde_json::Value,
 }
 
+/// Named streams a socket can subscribe to, e.g. `chat:<id>` or `post:<id>:comments`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topic {
+    Chat(ObjectId),
+    StudyRoom(ObjectId),
+    Live(ObjectId),
+    PostComments(ObjectId),
+    Presence(ObjectId),
+}
+
+impl Topic {
+    pub fn parse(value: &str) -> Option<Topic> {
+        let parts: Vec<&str> = value.split(':').collect();
+        let id = |s: &str| ObjectId::parse_str(s).ok();
+        match parts.as_slice() {
+            ["chat", oid] => id(oid).map(Topic::Chat),
+            ["study_room", oid] => id(oid).map(Topic::StudyRoom),
+            ["live", oid] => id(oid).map(Topic::Live),
+            ["post", oid, "comments"] => id(oid).map(Topic::PostComments),
+            ["presence", oid] => id(oid).map(Topic::Presence),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Topic::Chat(id) => format!("chat:{}", id.to_hex()),
+            Topic::StudyRoom(id) => format!("study_room:{}", id.to_hex()),
+            Topic::Live(id) => format!("live:{}", id.to_hex()),
+            Topic::PostComments(id) => format!("post:{}:comments", id.to_hex()),
+            Topic::Presence(id) => format!("presence:{}", id.to_hex()),
+        }
+    }
+}
+
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
@@ -40,18 +91,181 @@ pub async fn ws_handler(
     ws.on_upgrade(|socket| handle_socket(socket, state))
 }
 
-// Utility to broadcast to a specific user via Redis Pub/Sub
+/// Whether events of this type skip sequencing and the replay buffer.
+pub fn is_ephemeral(kind: &str) -> bool {
+    EPHEMERAL_TYPES.contains(&kind)
+}
+
+/// Who a chat's members see sending an ephemeral event: the sender's alias in an anonymous
+/// chat, or nobody if they have no alias there.
+pub fn chat_sender(aliases: Option<&[crate::models::ChatAlias]>, user_id: ObjectId) -> Option<ObjectId> {
+    match aliases {
+        Some(aliases) => aliases.iter().find(|a| a.user_id == user_id).map(|a| a.alias_id),
+        None => Some(user_id),
+    }
+}
+
+/// The frame a user's sockets receive; durable events carry their sequence number.
+pub fn user_envelope(payload: &WsPayload, seq: Option<u64>) -> String {
+    match seq {
+        Some(seq) => json!({ "type": payload.r#type, "data": payload.data, "seq": seq }),
+        None => json!({ "type": payload.r#type, "data": payload.data }),
+    }
+    .to_string()
+}
+
+/// Whether a client that last saw `last_seq` missed events replay can't give back: the
+/// oldest buffered event is past the next one it needs, the buffer is gone while newer
+/// events were sent, or its sequence is ahead of ours (the counter was reset).
+pub fn replay_gap(last_seq: u64, oldest_buffered: Option<u64>, current: u64) -> bool {
+    if last_seq > current {
+        return true;
+    }
+    match oldest_buffered {
+        Some(first) => first > last_seq + 1,
+        None => current > last_seq,
+    }
+}
+
+/// The presence change to announce after a user's live connection count moves to `connections`.
+pub fn presence_change(connections: i64, connected: bool) -> Option<&'static str> {
+    match (connected, connections) {
+        (true, 1) => Some("online"),
+        (false, n) if n <= 0 => Some("offline"),
+        _ => None,
+    }
+}
+
+// Utility to broadcast to a specific user via Redis Pub/Sub.
+// Durable events get a per-user sequence number and are kept in the replay buffer.
 pub async fn send_to_user(state: &Arc<AppState>, user_id: &ObjectId, payload: &WsPayload) {
+    if is_ephemeral(&payload.r#type) {
+        send_ephemeral_to_user(state, user_id, payload).await;
+        return;
+    }
+
+    let uid = user_id.to_hex();
+    let seq_key = format!("ws:seq:{}", uid);
+    let replay_key = format!("ws:replay:{}", uid);
+    let mut conn = state.redis.clone();
+
+    let envelope = match conn.incr::<_, _, u64>(&seq_key, 1).await {
+        Ok(seq) => {
+            let envelope = user_envelope(payload, Some(seq));
+            let _: Result<(), _> = redis::pipe()
+                .atomic()
+                .zadd(&replay_key, &envelope, seq)
+                .ignore()
+                .zremrangebyrank(&replay_key, 0, -(REPLAY_BUFFER_SIZE + 1))
+                .ignore()
+                .expire(&replay_key, REPLAY_BUFFER_TTL)
+                .ignore()
+                .expire(&seq_key, SEQUENCE_TTL)
+                .ignore()
+                .query_async(&mut conn)
+                .await;
+            envelope
+        }
+        Err(e) => {
+            tracing::warn!("Failed to sequence ws event for {}: {}", uid, e);
+            user_envelope(payload, None)
+        }
+    };
+
+    let _: Result<i64, _> = conn.publish(format!("ws:user:{}", uid), envelope).await;
+}
+
+/// Delivers to the user's live sockets only, without sequencing or replay.
+pub async fn send_ephemeral_to_user(state: &Arc<AppState>, user_id: &ObjectId, payload: &WsPayload) {
     let channel = format!("ws:user:{}", user_id.to_hex());
     state.cache.publish(&channel, payload).await;
 }
 
+/// Broadcasts to every socket, on any instance, subscribed to `topic`.
+pub async fn publish_to_topic(state: &Arc<AppState>, topic: &Topic, payload: &WsPayload) {
+    let name = topic.name();
+    let envelope = json!({ "type": payload.r#type, "topic": name, "data": payload.data });
+    state.cache.publish(&format!("ws:topic:{}", name), &envelope).await;
+}
+
+/// Runs the single multiplexed Redis subscriber for this process and fans
+/// messages out to local sockets in `ws_connections` and `ws_topics`.
+pub fn spawn_ws_gateway(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        tracing::info!("WebSocket gateway subscriber started");
+        loop {
+            let client = match redis::Client::open(state.redis_url.clone()) {
+                Ok(c) => c,
+                Err(e) => {
+                    tracing::error!("Failed to open Redis client for ws gateway: {}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+            let mut pubsub = match client.get_async_pubsub().await {
+                Ok(p) => p,
+                Err(e) => {
+                    tracing::error!("Failed to open Redis pub/sub for ws gateway: {}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+            if let Err(e) = pubsub.psubscribe(&["ws:user:*", "ws:topic:*"]).await {
+                tracing::error!("Failed to subscribe ws gateway: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+
+            let mut stream = pubsub.on_message();
+            while let Some(msg) = stream.next().await {
+                let channel = msg.get_channel_name().to_string();
+                let payload: String = msg.get_payload().unwrap_or_default();
+                if payload.is_empty() {
+                    continue;
+                }
+
+                if let Some(uid) = channel.strip_prefix("ws:user:").and_then(|s| ObjectId::parse_str(s).ok()) {
+                    if let Some(mut senders) = state.ws_connections.get_mut(&uid) {
+                        senders.retain(|tx| tx.send(Message::Text(payload.clone())).is_ok());
+                    }
+                } else if let Some(topic) = channel.strip_prefix("ws:topic:") {
+                    if let Some(mut senders) = state.ws_topics.get_mut(topic) {
+                        senders.retain(|tx| tx.send(Message::Text(payload.clone())).is_ok());
+                    }
+                }
+            }
+
+            tracing::warn!("WebSocket gateway subscriber disconnected, reconnecting");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    });
+}
+
+struct SocketSession {
+    user_id: Option<ObjectId>,
+    topics: HashSet<String>,
+}
+
+impl SocketSession {
+    /// Live streams this socket is watching; they count it as a viewer.
+    fn live_streams(&self) -> Vec<ObjectId> {
+        self.topics.iter()
+            .filter_map(|name| match Topic::parse(name) {
+                Some(Topic::Live(id)) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn send_json(tx: &mpsc::UnboundedSender<Message>, value: serde_json::Value) {
+    let _ = tx.send(Message::Text(value.to_string()));
+}
+
 async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     let (mut sender, mut receiver) = socket.split();
     let (tx, mut rx) = mpsc::unbounded_channel();
-    
-    let mut current_user_id: Option<ObjectId> = None;
-    let mut redis_sub_task: Option<tokio::task::JoinHandle<()>> = None;
+    let mut session = SocketSession { user_id: None, topics: HashSet::new() };
 
     // Handle outgoing messages to the WebSocket
     let send_task = tokio::spawn(async move {
@@ -62,85 +276,35 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
         }
     });
 
-    // Handle incoming messages from the WebSocket (Authentication first)
-    while let Some(Ok(msg)) = receiver.next().await {
-        if let Message::Text(text) = msg {
-            if let Ok(payload) = serde_json::from_str::<serde_json::Value>(&text) {
-                // Check for auth message: { "type": "auth", "token": "..." }
-                if payload["type"] == "auth" {
-                    if let Some(token) = payload["token"].as_str() {
-                        if let Ok(claims_data) = decode_token(token, &state.jwt_secret) {
-                            if let Ok(uid) = ObjectId::parse_str(&claims_data.claims.sub) {
-                                // If already authenticated, skip
-                                if current_user_id.is_some() { continue; }
-                                
-                                current_user_id = Some(uid);
-                                
-                                // Register connection locally for cleanup/stats
-                                state.ws_connections.entry(uid).or_default().push(tx.clone());
-                                
-                                // Subscribe to Redis channel for this user
-                                let channel = format!("ws:user:{}", uid.to_hex());
-                                let redis_url = state.redis_url.clone();
-                                let tx_inner = tx.clone();
-                                
-                                redis_sub_task = Some(tokio::spawn(async move {
-                                    if let Ok(client) = redis::Client::open(redis_url) {
-                                        if let Ok(mut pubsub_conn) = client.get_async_pubsub().await {
-                                            if pubsub_conn.subscribe(&channel).await.is_ok() {
-                                                let mut pubsub_stream = pubsub_conn.on_message();
-                                                while let Some(msg) = pubsub_stream.next().await {
-                                                    let payload: String = msg.get_payload().unwrap_or_default();
-                                                    if !payload.is_empty() {
-                                                        let _ = tx_inner.send(Message::Text(payload));
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }));
-
-                                let _ = tx.send(Message::Text(serde_json::to_string(&json!({
-                                    "type": "auth_success",
-                                    "data": { "user_id": uid.to_hex() }
-                                })).unwrap()));
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    heartbeat.tick().await;
+    let mut last_activity = Instant::now();
+
+    loop {
+        tokio::select! {
+            incoming = receiver.next() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(msg)) => {
+                        last_activity = Instant::now();
+                        if let Message::Text(text) = msg {
+                            if let Ok(payload) = serde_json::from_str::<serde_json::Value>(&text) {
+                                handle_client_message(&state, &tx, &mut session, payload).await;
                             }
                         }
                     }
-                } else if current_user_id.is_none() {
-                    // Reject all non-auth messages from unauthenticated users
-                    let _ = tx.send(Message::Text(serde_json::to_string(&json!({
-                        "type": "error",
-                        "error": "Not authenticated"
-                    })).unwrap()));
-                } else {
-                    // Signaling logic for authenticated users
-                    let msg_type = payload["type"].as_str().unwrap_or("");
-                    if ["call-offer", "call-answer", "ice-candidate", "typing"].contains(&msg_type) {
-                        if let Some(sender_uid) = current_user_id {
-                             if let Some(to_uid_str) = payload["data"].get("to").and_then(|v| v.as_str()) {
-                                if let Ok(to_uid) = ObjectId::parse_str(to_uid_str) {
-                                    let mut forward_data = payload["data"].clone();
-                                    if let Some(obj) = forward_data.as_object_mut() {
-                                        obj.insert("from".to_string(), json!(sender_uid.to_hex()));
-                                        
-                                        // For call-offer, include caller's username
-                                        if msg_type == "call-offer" {
-                                            let profiles = state.mongo.collection::<crate::models::Profile>("profiles");
-                                            if let Ok(Some(profile)) = profiles.find_one(mongodb::bson::doc! { "user_id": sender_uid }, None).await {
-                                                obj.insert("callerUsername".to_string(), json!(profile.username));
-                                            }
-                                        }
-                                    }
-                                    
-                                    let forward_payload = WsPayload {
-                                        r#type: msg_type.to_string(),
-                                        data: forward_data,
-                                    };
-                                    send_to_user(&state, &to_uid, &forward_payload).await;
-                                }
-                            }
-                        }
+                }
+            }
+            _ = heartbeat.tick() => {
+                if last_activity.elapsed() > CLIENT_TIMEOUT {
+                    tracing::debug!("Closing idle WebSocket");
+                    break;
+                }
+                let _ = tx.send(Message::Ping(Vec::new()));
+                if let Some(uid) = session.user_id {
+                    refresh_presence(&state, uid).await;
+                    for stream_id in session.live_streams() {
+                        live::refresh_viewer(&state, stream_id, uid).await;
                     }
                 }
             }
@@ -148,18 +312,238 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     }
 
     // Cleanup
-    if let Some(uid) = current_user_id {
-        if let Some(mut connections) = state.ws_connections.get_mut(&uid) {
-            connections.retain(|c| !c.same_channel(&tx));
-            if connections.is_empty() {
-                drop(connections);
-                state.ws_connections.remove(&uid);
+    for topic in &session.topics {
+        remove_sender(&state.ws_topics, topic, &tx);
+    }
+    if let Some(uid) = session.user_id {
+        for stream_id in session.live_streams() {
+            live::viewer_left(&state, stream_id, uid).await;
+        }
+        remove_sender(&state.ws_connections, &uid, &tx);
+        mark_offline(&state, uid).await;
+    }
+    send_task.abort();
+}
+
+fn remove_sender<K: std::hash::Hash + Eq + Clone>(
+    map: &dashmap::DashMap<K, Vec<mpsc::UnboundedSender<Message>>>,
+    key: &K,
+    tx: &mpsc::UnboundedSender<Message>,
+) {
+    if let Some(mut senders) = map.get_mut(key) {
+        senders.retain(|c| !c.same_channel(tx));
+        if senders.is_empty() {
+            drop(senders);
+            map.remove_if(key, |_, v| v.is_empty());
+        }
+    }
+}
+
+async fn handle_client_message(
+    state: &Arc<AppState>,
+    tx: &mpsc::UnboundedSender<Message>,
+    session: &mut SocketSession,
+    payload: serde_json::Value,
+) {
+    let msg_type = payload["type"].as_str().unwrap_or("");
+
+    // Authentication first: { "type": "auth", "token": "...", "last_seq": 42 }
+    if msg_type == "auth" {
+        if session.user_id.is_some() {
+            return;
+        }
+        let uid = match payload["token"].as_str() {
+            Some(token) => validate_access_token(state, token).await.ok().map(|(uid, _, _)| uid),
+            None => None,
+        };
+        let Some(uid) = uid else {
+            send_json(tx, json!({ "type": "error", "error": "Invalid token" }));
+            return;
+        };
+
+        session.user_id = Some(uid);
+        state.ws_connections.entry(uid).or_default().push(tx.clone());
+        mark_online(state, uid).await;
+
+        let mut conn = state.redis.clone();
+        let seq: u64 = conn.get::<_, Option<u64>>(format!("ws:seq:{}", uid.to_hex())).await.ok().flatten().unwrap_or(0);
+        send_json(tx, json!({ "type": "auth_success", "data": { "user_id": uid.to_hex(), "seq": seq } }));
+
+        if let Some(last_seq) = payload["last_seq"].as_u64() {
+            replay_missed(state, tx, uid, last_seq).await;
+        }
+        return;
+    }
+
+    let Some(sender_uid) = session.user_id else {
+        // Reject all non-auth messages from unauthenticated users
+        send_json(tx, json!({ "type": "error", "error": "Not authenticated" }));
+        return;
+    };
+
+    match msg_type {
+        "ping" => send_json(tx, json!({ "type": "pong" })),
+        "subscribe" => {
+            let topic = payload["topic"].as_str().and_then(Topic::parse);
+            let Some(topic) = topic else {
+                send_json(tx, json!({ "type": "error", "error": "Unknown topic" }));
+                return;
+            };
+            if !can_subscribe(state, sender_uid, &topic).await {
+                send_json(tx, json!({ "type": "error", "error": "Not allowed to subscribe", "topic": topic.name() }));
+                return;
+            }
+            let name = topic.name();
+            if session.topics.insert(name.clone()) {
+                state.ws_topics.entry(name.clone()).or_default().push(tx.clone());
+                if let Topic::Live(stream_id) = topic {
+                    live::viewer_joined(state, stream_id, sender_uid).await;
+                }
             }
+            send_json(tx, json!({ "type": "subscribed", "data": { "topic": name } }));
         }
+        "unsubscribe" => {
+            if let Some(topic) = payload["topic"].as_str().and_then(Topic::parse) {
+                let name = topic.name();
+                if session.topics.remove(&name) {
+                    remove_sender(&state.ws_topics, &name, tx);
+                    if let Topic::Live(stream_id) = topic {
+                        live::viewer_left(state, stream_id, sender_uid).await;
+                    }
+                }
+                send_json(tx, json!({ "type": "unsubscribed", "data": { "topic": name } }));
+            }
+        }
+        _ if is_ephemeral(msg_type) => {
+            let mut forward_data = payload["data"].clone();
+            if let Some(obj) = forward_data.as_object_mut() {
+                obj.insert("from".to_string(), json!(sender_uid.to_hex()));
+            }
+
+            // Typing indicators can target a subscribed topic instead of one user
+            if let Some(topic) = payload["data"].get("topic").and_then(|v| v.as_str()).and_then(Topic::parse) {
+                if !session.topics.contains(&topic.name()) {
+                    return;
+                }
+                if let Topic::Chat(chat_id) = topic {
+                    let chat = state.mongo.collection::<crate::models::Chat>("chats")
+                        .find_one(doc! { "_id": chat_id }, None).await.ok().flatten();
+                    let Some(from) = chat.and_then(|c| chat_sender(c.aliases.as_deref(), sender_uid)) else { return };
+                    if let Some(obj) = forward_data.as_object_mut() {
+                        obj.insert("from".to_string(), json!(from.to_hex()));
+                    }
+                }
+                publish_to_topic(state, &topic, &WsPayload { r#type: msg_type.to_string(), data: forward_data }).await;
+                return;
+            }
+
+            let Some(to_uid) = payload["data"].get("to").and_then(|v| v.as_str()).and_then(|s| ObjectId::parse_str(s).ok()) else {
+                return;
+            };
+
+            // For call-offer, include caller's username
+            if msg_type == "call-offer" {
+                let profiles = state.mongo.collection::<crate::models::Profile>("profiles");
+                if let Ok(Some(profile)) = profiles.find_one(doc! { "user_id": sender_uid }, None).await {
+                    if let Some(obj) = forward_data.as_object_mut() {
+                        obj.insert("callerUsername".to_string(), json!(profile.username));
+                    }
+                }
+            }
+
+            let forward_payload = WsPayload {
+                r#type: msg_type.to_string(),
+                data: forward_data,
+            };
+            send_ephemeral_to_user(state, &to_uid, &forward_payload).await;
+        }
+        _ => {}
     }
-    
-    if let Some(task) = redis_sub_task {
-        task.abort();
+}
+
+/// Chats and study rooms are members-only; live streams, comments and presence are public.
+async fn can_subscribe(state: &Arc<AppState>, user_id: ObjectId, topic: &Topic) -> bool {
+    match topic {
+        Topic::Chat(chat_id) => state
+            .mongo
+            .collection::<crate::models::Chat>("chats")
+            .find_one(doc! { "_id": chat_id, "participants": user_id }, None)
+            .await
+            .map(|c| c.is_some())
+            .unwrap_or(false),
+        Topic::StudyRoom(room_id) => state
+            .mongo
+            .collection::<crate::models::StudyRoom>("study_rooms")
+            .find_one(doc! { "_id": room_id, "participants": user_id }, None)
+            .await
+            .map(|r| r.is_some())
+            .unwrap_or(false),
+        Topic::Live(_) | Topic::PostComments(_) | Topic::Presence(_) => true,
     }
-    send_task.abort();
+}
+
+async fn replay_missed(state: &Arc<AppState>, tx: &mpsc::UnboundedSender<Message>, uid: ObjectId, last_seq: u64) {
+    let replay_key = format!("ws:replay:{}", uid.to_hex());
+    let mut conn = state.redis.clone();
+    let events: Vec<(String, u64)> = match conn
+        .zrangebyscore_withscores(&replay_key, format!("({}", last_seq), "+inf")
+        .await
+    {
+        Ok(events) => events,
+        Err(e) => {
+            tracing::warn!("Failed to read ws replay buffer for {}: {}", uid, e);
+            return;
+        }
+    };
+
+    // The buffer is bounded; tell the client when it must refetch instead of relying on replay
+    let current: u64 = conn.get::<_, Option<u64>>(format!("ws:seq:{}", uid.to_hex())).await.ok().flatten().unwrap_or(0);
+    if replay_gap(last_seq, events.first().map(|(_, s)| *s), current) {
+        send_json(tx, json!({ "type": "replay_gap", "data": { "from": last_seq, "to": current } }));
+    }
+
+    for (event, _) in events {
+        let _ = tx.send(Message::Text(event));
+    }
+}
+
+async fn mark_online(state: &Arc<AppState>, uid: ObjectId) {
+    let mut conn = state.redis.clone();
+    let count_key = format!("ws:connections:{}", uid.to_hex());
+    let count: i64 = conn.incr(&count_key, 1).await.unwrap_or(1);
+    let _ = conn.expire::<_, ()>(&count_key, (state.redis_presence_ttl * 2) as i64).await;
+    let _ = conn.set_ex::<_, _, ()>(format!("user:presence:{}", uid.to_hex()), "online", state.redis_presence_ttl).await;
+
+    if let Some(status) = presence_change(count, true) {
+        publish_to_topic(state, &Topic::Presence(uid), &WsPayload {
+            r#type: "presence".to_string(),
+            data: json!({ "user_id": uid.to_hex(), "status": status }),
+        }).await;
+    }
+}
+
+async fn refresh_presence(state: &Arc<AppState>, uid: ObjectId) {
+    let mut conn = state.redis.clone();
+    let _ = conn.set_ex::<_, _, ()>(format!("user:presence:{}", uid.to_hex()), "online", state.redis_presence_ttl).await;
+    let _ = conn.expire::<_, ()>(format!("ws:connections:{}", uid.to_hex()), (state.redis_presence_ttl * 2) as i64).await;
+}
+
+async fn mark_offline(state: &Arc<AppState>, uid: ObjectId) {
+    let mut conn = state.redis.clone();
+    let count_key = format!("ws:connections:{}", uid.to_hex());
+    let remaining: i64 = conn.decr(&count_key, 1).await.unwrap_or(0);
+    let Some(status) = presence_change(remaining, false) else { return };
+
+    let _ = conn.del::<_, ()>(&count_key).await;
+    let _ = conn.del::<_, ()>(format!("user:presence:{}", uid.to_hex())).await;
+    let _ = state
+        .mongo
+        .collection::<crate::models::Profile>("profiles")
+        .update_one(doc! { "user_id": uid }, doc! { "$set": { "last_seen_at": mongodb::bson::DateTime::now() } }, None)
+        .await;
+
+    publish_to_topic(state, &Topic::Presence(uid), &WsPayload {
+        r#type: "presence".to_string(),
+        data: json!({ "user_id": uid.to_hex(), "status": status }),
+    }).await;
 }
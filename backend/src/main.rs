@@ -50,7 +50,23 @@ async fn main() {
     let mongo_db = db::init_mongo().await;
     let redis_client = db::init_redis().await;
     let jwt_secret = std::env::var("JWT_SECRET").unwrap();
-    let brevo_api_key = std::env::var("BREVO_API_KEY").unwrap_or_default();
+    let http_client = reqwest::Client::new();
+    let mailer = crate::features::infrastructure::mail::mailer_from_env(http_client.clone());
+    let payments = match crate::features::monetization::provider::payment_provider_from_env(http_client.clone(), Arc::new(dashmap::DashMap::new())) {
+        Ok(payments) => payments,
+        Err(e) => {
+            tracing::error!("FATAL: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if !crate::features::monetization::payments::callback_secret_configured() {
+        if payments.name() == "mock" {
+            tracing::warn!("MPESA_CALLBACK_SECRET not set: every payment callback will be rejected");
+        } else {
+            tracing::error!("FATAL: MPESA_CALLBACK_SECRET must be set; payment callbacks can't be authenticated without it");
+            std::process::exit(1);
+        }
+    }
 
     let state = Arc::new(db::AppState {
         mongo: mongo_db,
@@ -59,11 +75,12 @@ async fn main() {
         redis_url: std::env::var("REDIS_URL").expect("REDIS_URL must be set"),
         jwt_secret,
         ws_connections: Arc::new(dashmap::DashMap::new()),
-        mpesa_token: Arc::new(dashmap::DashMap::new()),
+        ws_topics: Arc::new(dashmap::DashMap::new()),
         ai_models: Arc::new(dashmap::DashMap::new()),
         model_health: Arc::new(dashmap::DashMap::new()),
-        http_client: reqwest::Client::new(),
-        brevo_api_key,
+        http_client,
+        mailer,
+        payments,
         redis_presence_ttl: std::env::var("REDIS_PRESENCE_TTL")
             .unwrap_or_else(|_| "300".to_string())
             .parse()
@@ -72,13 +89,24 @@ async fn main() {
             .unwrap_or_else(|_| "60".to_string())
             .parse()
             .unwrap_or(60),
+        search_index: Arc::new(crate::features::infrastructure::search_index::SearchIndex::new()),
+        spam_rules: Arc::new(crate::features::infrastructure::spam::SpamRules::new()),
     });
 
     // Start AI Model Updater
     ai::spawn_model_updater(state.clone());
 
-    // Start Media Processing Worker
-    crate::features::infrastructure::media_processor::spawn_media_worker(state.clone());
+    // Start Background Job Workers (media processing, scheduled mail, cron)
+    crate::features::infrastructure::jobs::spawn_job_system(state.clone(), crate::features::infrastructure::jobs::job_registry());
+
+    // Start Email Outbox Worker
+    crate::features::infrastructure::mail::spawn_mail_worker(state.clone());
+
+    // Start WebSocket Gateway Subscriber
+    crate::features::social::ws::spawn_ws_gateway(state.clone());
+
+    // Start Search Indexer
+    crate::features::infrastructure::search::spawn_search_indexer(state.clone());
 
     // Port and Address Setup
     let port = std::env::var("PORT")
@@ -121,6 +149,7 @@ async fn main() {
         ])
         .expose_headers([
             axum::http::header::CONTENT_DISPOSITION,
+            axum::http::HeaderName::from_static("x-search-scope"),
         ]);
 
     let mut cors = cors;
@@ -112,6 +112,8 @@ pub struct RevisionMaterialPurchase {
     pub material_id: ObjectId,
     pub user_id: ObjectId,
     pub amount: i64, // In cents
+    #[serde(default)]
+    pub creator_amount: i64, // Uploader's share of `amount`, in cents
     pub transaction_id: Option<ObjectId>,
     pub purchased_at: bson::DateTime,
 }
@@ -42,6 +42,18 @@ pub struct Badge {
     pub description: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrivacySettings {
+    #[serde(default = "default_true")]
+    pub read_receipts: bool,
+}
+
+impl Default for PrivacySettings {
+    fn default() -> Self {
+        Self { read_receipts: true }
+    }
+}
+
 pub fn default_true() -> bool {
     true
 }
@@ -49,3 +61,38 @@ pub fn default_true() -> bool {
 pub fn default_false() -> bool {
     false
 }
+
+impl NotificationSettings {
+    /// Whether a notification of the given kind (the `type` carried in push data) may be delivered.
+    pub fn allows(&self, kind: &str) -> bool {
+        match kind {
+            "message" | "dm" => self.messages,
+            "like" | "reaction" => self.likes,
+            "comment" | "reply" => self.comments,
+            "follow" => self.follows,
+            "mention" => self.mentions,
+            "class_reminder" => self.class_reminders,
+            "template_update" => self.template_updates,
+            "ad" | "promotion" => self.ad_promotions,
+            _ => true,
+        }
+    }
+}
+
+/// What a link unfurls to, stored on posts and messages that contain it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct LinkPreview {
+    pub url: String, // After redirects
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub site_name: Option<String>,
+    #[serde(default)]
+    pub author_name: Option<String>,
+    #[serde(default)]
+    pub media_type: Option<String>, // og:type or the oEmbed type, e.g. "video"
+}
@@ -101,6 +101,8 @@ pub struct Post {
     #[serde(default)]
     pub content_rating: Option<String>,
     #[serde(default)]
+    pub spam_score: Option<f64>,
+    #[serde(default)]
     pub is_nsfw: Option<bool>,
     pub poll: Option<Poll>,
     #[serde(default)]
@@ -109,6 +111,8 @@ pub struct Post {
     pub group_id: Option<ObjectId>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub page_id: Option<ObjectId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_preview: Option<super::base::LinkPreview>,
     pub created_at: bson::DateTime,
     pub updated_at: bson::DateTime,
 }
@@ -1,5 +1,6 @@
 use bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use super::base::Location;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -15,6 +16,44 @@ pub struct Chat {
     pub last_message_time: bson::DateTime,
     pub disappearing_duration: Option<i64>,
     pub created_at: bson::DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<ChatAlias>>, // Set on anonymous chats: participants are shown by alias only
+    #[serde(default)]
+    pub is_encrypted: bool, // End-to-end: only encrypted_content is accepted, and it can't be turned off
+    #[serde(default)]
+    pub cursors: HashMap<String, ChatCursor>, // Keyed by participant user id (hex)
+}
+
+/// How far one participant has got in a chat. Both cursors only ever move forward.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ChatCursor {
+    #[serde(default)]
+    pub unread_count: i64,
+    pub last_delivered_id: Option<ObjectId>,
+    pub delivered_at: Option<bson::DateTime>,
+    pub last_read_id: Option<ObjectId>,
+    pub read_at: Option<bson::DateTime>,
+}
+
+/// One move of a participant's cursor: everything up to `up_to` was delivered or read at `at`.
+/// Kept so per-message receipt times survive the cursor moving on.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageReceipt {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub chat_id: ObjectId,
+    pub user_id: ObjectId,
+    pub status: String, // delivered, read
+    pub up_to: ObjectId,
+    pub at: bson::DateTime,
+}
+
+/// How a participant appears in an anonymous chat.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatAlias {
+    pub user_id: ObjectId,
+    pub alias_id: ObjectId,
+    pub alias_username: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -53,9 +92,46 @@ pub struct Message {
     pub is_view_once: bool,
     pub is_system: bool,
     pub is_announcement: bool,
-    pub viewed_at: Option<bson::DateTime>,
+    pub viewed_at: Option<bson::DateTime>, // View-once: set once every recipient has opened it
+    #[serde(default)]
+    pub viewed_by: Vec<ObjectId>, // View-once: recipients who have opened it
     pub expires_at: Option<bson::DateTime>,
     pub created_at: bson::DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_preview: Option<super::base::LinkPreview>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<bson::DateTime>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edit_history: Vec<MessageRevision>, // Oldest first
+}
+
+/// A message body as it was before an edit replaced it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageRevision {
+    pub content: String,
+    pub encrypted_content: Option<String>,
+    pub encryption_iv: Option<String>,
+    pub replaced_at: bson::DateTime,
+}
+
+/// A message queued to be sent later. It goes through the normal send path when it is due.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScheduledMessage {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub chat_id: ObjectId,
+    pub sender_id: ObjectId,
+    pub content: String,
+    pub attachment_url: Option<String>,
+    pub attachment_type: Option<String>,
+    pub reply_to_id: Option<ObjectId>,
+    pub is_view_once: bool,
+    pub send_at: bson::DateTime,
+    pub status: String, // pending, sending, sent, failed, cancelled
+    pub message_id: Option<ObjectId>,
+    pub error: Option<String>,
+    pub created_at: bson::DateTime,
+    pub updated_at: bson::DateTime,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -70,3 +146,79 @@ pub struct Notification {
     pub is_read: bool,
     pub created_at: bson::DateTime,
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PushSubscription {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub endpoint: String,
+    pub p256dh: String, // base64url, uncompressed P-256 point
+    pub auth: String,   // base64url, 16 byte auth secret
+    pub device_id: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: bson::DateTime,
+    pub last_success_at: Option<bson::DateTime>,
+}
+
+/// One device's end-to-end encryption keys, one document per (user, device). Public halves
+/// only; keys are base64 SEC1 P-256 points and signatures raw r||s, as Web Crypto produces.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceKeys {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub device_id: String,
+    pub identity_key: String,
+    pub signed_prekey: SignedPrekey,
+    pub one_time_prekeys: Vec<OneTimePrekey>, // Handed out (and removed) one per bundle fetch
+    pub created_at: bson::DateTime,
+    pub updated_at: bson::DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SignedPrekey {
+    pub key_id: i64,
+    pub public_key: String,
+    pub signature: String, // By the identity key, over the raw prekey bytes
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OneTimePrekey {
+    pub key_id: i64,
+    pub public_key: String,
+}
+
+/// A group sender key, encrypted pairwise for one recipient device. The server only relays it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SenderKeyMessage {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub chat_id: ObjectId,
+    pub sender_id: ObjectId,
+    pub sender_device_id: String,
+    pub recipient_id: ObjectId,
+    pub recipient_device_id: String,
+    pub ciphertext: String,
+    pub created_at: bson::DateTime,
+}
+
+/// Queued transactional email. Handlers enqueue; `mail::spawn_mail_worker` delivers.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OutboxEmail {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub to: String,
+    pub to_name: Option<String>,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    pub template: String, // e.g. "password_reset", kept for stats and debugging
+    pub status: String, // pending, sending, sent, failed
+    pub attempts: i32,
+    pub next_attempt_at: bson::DateTime,
+    pub locked_until: Option<bson::DateTime>,
+    pub last_error: Option<String>,
+    pub created_at: bson::DateTime,
+    pub sent_at: Option<bson::DateTime>,
+}
@@ -55,7 +55,7 @@ pub use social::{
     ReelSave, ReelAnalytics, UserReelStats, ReelReport, ReelModeration, 
     ReelTranscodingJob,
     Subtitle, Caption,
-    Group, HookupAlias, HookupMatch, Follow
+    Group, HookupAlias, HookupMatch, HookupPair, HookupPreferences, Follow
 };
 
 pub use academic::{StudyRoom, Timetable, TimetableClass, TimetableTask, RevisionMaterial, RevisionMaterialPurchase, StudyPlaylist, PlaylistItem, RoomMessage, RoomFile, AttendanceLog, CrowdReport};
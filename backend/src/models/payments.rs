@@ -1,5 +1,6 @@
 use bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use super::base::default_true;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Transaction {
@@ -8,11 +9,125 @@ pub struct Transaction {
     pub user_id: ObjectId,
     pub phone_number: String,
     pub amount: i64, // Amount in cents (e.g. 100 for Ksh 1.00)
-    pub mpesa_receipt_number: Option<String>,
-    pub checkout_request_id: String,
-    pub status: String, // pending, completed, failed
-    pub transaction_type: String, // verification, premium, donation
+    pub mpesa_receipt_number: Option<String>, // The provider's receipt, whichever provider
+    pub checkout_request_id: String, // The provider's reference for the payment
+    pub status: String, // pending, completed, failed, refund_pending, refunded
+    #[serde(default = "default_provider")]
+    pub provider: String, // See `PaymentProvider::name`
+    #[serde(default)]
+    pub refund_reference: Option<String>,
+    /// Why a completed payment should be refunded by an admin, e.g. a second purchase of the same material.
+    #[serde(default)]
+    pub refund_due: Option<String>,
+    pub transaction_type: String, // See `payments::PaymentPurpose`
     pub premium_duration: Option<String>,
+    #[serde(default, alias = "material_id")]
+    pub target_id: Option<ObjectId>, // What was paid for: the material, marketplace item, ...
+    pub created_at: bson::DateTime,
+    pub updated_at: bson::DateTime,
+}
+
+fn default_provider() -> String {
+    "mpesa".to_string()
+}
+
+/// Every payment callback we accepted, one per checkout. Providers retry callbacks, so the
+/// unique `checkout_request_id` is what makes processing them idempotent.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PaymentCallback {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub checkout_request_id: String,
+    pub result_code: i32,
+    pub result_desc: String,
+    pub receipt: Option<String>,
+    pub amount: Option<i64>, // Cents, as reported by the provider
+    pub source_ip: Option<String>,
+    pub received_at: bson::DateTime,
+}
+
+/// One side of a journal entry. Positive amounts credit the account, negative ones debit it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LedgerLine {
+    pub account: String,
+    pub amount: i64, // Cents
+}
+
+/// A balanced set of ledger lines (they sum to zero), posted at most once per `key`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LedgerJournal {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub key: String, // Idempotency key, e.g. "payment:{checkout_request_id}"
+    pub purpose: String,
+    pub lines: Vec<LedgerLine>,
+    pub transaction_id: Option<ObjectId>,
+    /// False until every line is on its account; journals from before the flag were applied.
+    #[serde(default = "default_true")]
+    pub applied: bool,
+    /// Set on transfers: the account whose balance must cover its debit.
+    #[serde(default)]
+    pub funded_account: Option<String>,
+    pub created_at: bson::DateTime,
+}
+
+/// Running totals for a ledger account such as `user:{id}:earnings` or `platform:revenue`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LedgerAccount {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub account: String,
+    pub owner_id: Option<ObjectId>,
+    #[serde(default)]
+    pub balance: i64, // Credits minus debits, in cents
+    #[serde(default)]
+    pub credited: i64,
+    #[serde(default)]
+    pub debited: i64,
+    /// Bumped by every change to the totals, so a rebuild can't overwrite one it didn't see.
+    #[serde(default)]
+    pub version: i64,
+    /// Keys of the most recent journals counted in the totals; makes applying a line idempotent.
+    #[serde(default)]
+    pub applied_journals: Vec<String>,
+    pub updated_at: bson::DateTime,
+}
+
+/// A creator's request to be paid their earnings over M-Pesa B2C.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Withdrawal {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub amount: i64, // Cents
+    pub phone_number: String,
+    pub status: String, // pending_review, processing, unknown, completed, failed, rejected
+    pub conversation_id: Option<String>, // Daraja's ConversationID once the B2C request is accepted
+    pub mpesa_receipt_number: Option<String>,
+    pub failure_reason: Option<String>,
+    pub reviewed_by: Option<ObjectId>,
+    pub reviewed_at: Option<bson::DateTime>,
+    pub created_at: bson::DateTime,
+    pub updated_at: bson::DateTime,
+}
+
+/// A user's premium subscription; `User::is_premium` and `premium_expires_at` mirror it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Subscription {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub plan: String, // weekly, monthly, semester
+    pub status: String, // active, grace, expired
+    pub current_period_start: bson::DateTime,
+    pub current_period_end: bson::DateTime,
+    #[serde(default)]
+    pub auto_renew: bool, // Send an STK prompt when the period ends
+    #[serde(default)]
+    pub renewal_phone: Option<String>,
+    #[serde(default)]
+    pub reminded_for: Option<bson::DateTime>, // Period end the renewal reminder went out for
+    pub last_transaction_id: Option<ObjectId>,
     pub created_at: bson::DateTime,
     pub updated_at: bson::DateTime,
 }
@@ -413,18 +413,59 @@ pub struct HookupAlias {
     pub bio: String,
     pub is_verified: bool,
     pub created_at: bson::DateTime,
+    #[serde(default)]
+    pub school: Option<String>,
+    #[serde(default)]
+    pub preferences: HookupPreferences,
+    #[serde(default)]
+    pub last_active_at: Option<bson::DateTime>,
+    #[serde(default)]
+    pub is_suspended: bool, // Taken down by moderation
+}
+
+/// Who an alias wants to be shown. Empty lists mean "anyone".
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HookupPreferences {
+    #[serde(default)]
+    pub genders: Vec<String>,
+    pub min_age: i32,
+    pub max_age: i32,
+    #[serde(default)]
+    pub schools: Vec<String>,
+}
+
+impl Default for HookupPreferences {
+    fn default() -> Self {
+        HookupPreferences { genders: Vec::new(), min_age: 18, max_age: 99, schools: Vec::new() }
+    }
 }
 
+/// One alias's latest interaction with another; unique per (from, to).
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct HookupMatch {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub from_alias_id: ObjectId,
     pub to_alias_id: ObjectId,
-    pub interaction: String,
+    pub interaction: String, // like, pass, block
     pub created_at: bson::DateTime,
 }
 
+/// A mutual like, and the anonymous chat it opened.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HookupPair {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub pair_key: String, // Both alias ids, sorted, so a pair exists once
+    pub alias_ids: Vec<ObjectId>,
+    pub user_ids: Vec<ObjectId>,
+    pub chat_id: Option<ObjectId>,
+    pub status: String, // active, unmatched, blocked, removed
+    pub ended_by: Option<ObjectId>, // Alias that ended it
+    pub created_at: bson::DateTime,
+    pub ended_at: Option<bson::DateTime>,
+}
+
 // --- Group Models ---
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Group {
@@ -457,9 +498,38 @@ pub struct LiveStream {
     pub ended_at: Option<bson::DateTime>,
     pub is_active: bool,
     pub viewer_count: i32,
-    pub stream_key: String,
-    pub stream_url: Option<String>,
+    pub stream_key: String, // Secret: only ever shown to the streamer
+    pub stream_url: Option<String>, // HLS playlist, set once the ingest server accepts the publish
     pub thumbnail_url: Option<String>,
     pub created_at: bson::DateTime,
     pub updated_at: bson::DateTime,
+    #[serde(default)]
+    pub ingest_started_at: Option<bson::DateTime>,
+    #[serde(default)]
+    pub peak_viewer_count: i32,
+    #[serde(default)]
+    pub recording_job_ids: Vec<String>, // media_jobs transcoding the VOD recordings
+    #[serde(default)]
+    pub moderators: Vec<ObjectId>, // Appointed by the streamer for this stream's chat
+    #[serde(default)]
+    pub banned_user_ids: Vec<ObjectId>,
+    #[serde(default)]
+    pub slow_mode_secs: i64, // 0 = off
+    #[serde(default)]
+    pub disconnected_at: Option<bson::DateTime>, // Encoder dropped; ends unless it reconnects in time
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LiveChatMessage {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub stream_id: ObjectId,
+    pub user_id: ObjectId,
+    pub username: String,
+    pub user_avatar: Option<String>,
+    pub content: String,
+    pub offset_ms: i64, // Since the stream went live, to line chat up with the VOD
+    pub status: String, // visible, spam (held for review), deleted
+    pub deleted_by: Option<ObjectId>,
+    pub created_at: bson::DateTime,
 }
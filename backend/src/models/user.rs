@@ -1,6 +1,6 @@
 use bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
-use super::base::{Location, Badge, NotificationSettings};
+use super::base::{default_true, Location, Badge, NotificationSettings, PrivacySettings};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
@@ -16,6 +16,12 @@ pub struct User {
     pub banned_at: Option<bson::DateTime>,
     pub banned_by: Option<ObjectId>,
     pub created_at: bson::DateTime,
+    #[serde(default)]
+    pub two_factor_enabled: bool,
+    #[serde(default)]
+    pub email_verified: bool, // Owns the address; separate from the paid `is_verified` badge
+    #[serde(default)]
+    pub email_verified_at: Option<bson::DateTime>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -54,6 +60,8 @@ pub struct Profile {
     pub interests: Option<Vec<String>>,
     pub notification_settings: Option<NotificationSettings>,
     #[serde(default)]
+    pub privacy_settings: Option<PrivacySettings>,
+    #[serde(default)]
     pub onboarded: bool,
     #[serde(default)]
     pub is_locked: bool,
@@ -75,9 +83,31 @@ pub struct Profile {
     #[serde(default)]
     pub next_level_points: u64,
     pub badges: Option<Vec<Badge>>,
+    #[serde(default)]
+    pub utc_offset_minutes: Option<i32>, // Used to decide where the user's day starts for streaks
+    #[serde(default)]
+    pub last_active_day: Option<String>, // Local YYYY-MM-DD of the last rewarded activity
     pub created_at: Option<bson::DateTime>,
 }
 
+/// One row of the gamification audit ledger: points granted for an activity, or a badge unlock.
+/// (user_id, activity, source_id) is unique, so the same post or session is never rewarded twice.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PointAward {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub activity: String, // post_published, comment, study_session, attendance, material_upload, badge
+    pub source_id: String,
+    pub points: i64,
+    pub badge_id: Option<String>,
+    pub local_day: String,
+    /// False until the points are on the profile; rows from before the flag were applied.
+    #[serde(default = "default_true")]
+    pub applied: bool,
+    pub created_at: bson::DateTime,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserCommentStats {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -98,3 +128,42 @@ pub struct UserCommentStats {
     pub created_at: bson::DateTime,
     pub updated_at: bson::DateTime,
 }
+
+/// A signed-in device. Each session is one refresh token family: rotating the
+/// refresh token keeps the session, replaying an old one revokes it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Session {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub refresh_token_hash: String,
+    #[serde(default)]
+    pub previous_token_hashes: Vec<String>,
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: bson::DateTime,
+    pub last_seen_at: bson::DateTime,
+    pub last_rotated_at: bson::DateTime,
+    pub expires_at: bson::DateTime,
+    pub revoked_at: Option<bson::DateTime>,
+    pub revoked_reason: Option<String>,
+}
+
+/// TOTP enrollment, kept out of `users` so secrets never ride along with user reads.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TwoFactorAuth {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub secret: Option<String>, // Sealed, see two_factor::seal_secret
+    pub pending_secret: Option<String>, // Awaiting confirmation during enrollment
+    pub enabled: bool,
+    #[serde(default)]
+    pub recovery_code_hashes: Vec<String>,
+    #[serde(default)]
+    pub last_used_step: i64, // Highest TOTP time step accepted, blocks replays
+    pub enabled_at: Option<bson::DateTime>,
+    pub updated_at: bson::DateTime,
+}
@@ -6,10 +6,10 @@ use std::sync::Arc;
 use crate::features::infrastructure::db::AppState;
 use crate::features::{
     ads, ai, auth::user, content,
-    social::{ably, confessions, events, follows, groups, hookup, messages, notifications, pages, ws},
+    social::{ably, confessions, e2e, events, follows, groups, hookup, messages, notifications, pages, ws},
     academic::{revision_materials, study_rooms, timetable},
-    monetization::{marketplace, payments},
-    infrastructure::{admin, stats, media, search},
+    monetization::{marketplace, payments, subscriptions},
+    infrastructure::{admin, stats, media, push, search},
 };
 use crate::features::content::{comments, playlist, reels};
 
@@ -27,8 +27,11 @@ pub fn create_router(state: Arc<AppState>) -> Router<Arc<AppState>> {
         .nest("/api/ably", ably::ably_routes())
         .nest("/api/marketplace", marketplace::marketplace_routes())
         .nest("/api/messages", messages::message_routes())
+        .nest("/api/keys", e2e::e2e_routes())
         .nest("/api/notifications", notifications::notification_routes())
+        .nest("/api/push", push::push_routes())
         .nest("/api/payments", payments::payment_routes())
+        .nest("/api/subscriptions", subscriptions::subscription_routes())
         .nest("/api/ai", ai::ai_routes())
         .nest("/api/hookup", hookup::hookup_routes())
         .nest("/api/study-rooms", study_rooms::study_room_routes())
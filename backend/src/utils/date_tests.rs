@@ -123,6 +123,7 @@ pub fn verify_endpoint_date_serialization() {
         cta_text: None,
         cta_url: None,
         is_ad: Some(false),
+        link_preview: None,
     };
     
     let json_str = serde_json::to_string(&test_dto).expect("Should serialize");
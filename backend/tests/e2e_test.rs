@@ -0,0 +1,89 @@
+// E2E key directory: key and prekey-signature validation, and safety numbers.
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use bson::oid::ObjectId;
+use karuteens_backend::features::social::e2e::{
+    fingerprint, parse_public_key, safety_number, validate_one_time_prekeys, verify_signed_prekey,
+};
+use karuteens_backend::models::{OneTimePrekey, SignedPrekey};
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::SecretKey;
+
+fn new_key() -> (SecretKey, String) {
+    let secret = SecretKey::random(&mut rand::rngs::OsRng);
+    let public = STANDARD.encode(secret.public_key().to_encoded_point(false).as_bytes());
+    (secret, public)
+}
+
+fn signed_prekey(identity: &SecretKey) -> SignedPrekey {
+    let (prekey, public_key) = new_key();
+    let raw = prekey.public_key().to_encoded_point(false).as_bytes().to_vec();
+    let signature: Signature = SigningKey::from(identity.clone()).sign(&raw);
+    SignedPrekey { key_id: 1, public_key, signature: STANDARD.encode(signature.to_bytes()) }
+}
+
+#[test]
+fn test_public_keys_must_be_p256_points() {
+    let (_, public) = new_key();
+    assert_eq!(parse_public_key(&public).unwrap().len(), 65);
+    let url_safe = URL_SAFE_NO_PAD.encode(STANDARD.decode(&public).unwrap());
+    assert!(parse_public_key(&url_safe).is_ok(), "url-safe base64 is accepted too");
+    assert!(parse_public_key(&STANDARD.encode([4u8; 65])).is_err());
+    assert!(parse_public_key("not base64!").is_err());
+}
+
+#[test]
+fn test_signed_prekeys_must_be_signed_by_the_identity_key() {
+    let (identity, identity_public) = new_key();
+    let prekey = signed_prekey(&identity);
+    assert!(verify_signed_prekey(&identity_public, &prekey).is_ok());
+
+    let (_, someone_else) = new_key();
+    assert!(verify_signed_prekey(&someone_else, &prekey).is_err());
+
+    let mut swapped = prekey.clone();
+    swapped.public_key = new_key().1;
+    assert!(verify_signed_prekey(&identity_public, &swapped).is_err(), "the signature covers the prekey");
+
+    let der: Signature = Signature::from_slice(&STANDARD.decode(&prekey.signature).unwrap()).unwrap();
+    let mut as_der = prekey.clone();
+    as_der.signature = STANDARD.encode(der.to_der().as_bytes());
+    assert!(verify_signed_prekey(&identity_public, &as_der).is_ok(), "DER signatures are accepted");
+}
+
+#[test]
+fn test_one_time_prekey_ids_are_unique() {
+    let keys = vec![
+        OneTimePrekey { key_id: 1, public_key: new_key().1 },
+        OneTimePrekey { key_id: 2, public_key: new_key().1 },
+    ];
+    assert!(validate_one_time_prekeys(&keys).is_ok());
+
+    let mut repeated = keys.clone();
+    repeated.push(OneTimePrekey { key_id: 1, public_key: new_key().1 });
+    assert!(validate_one_time_prekeys(&repeated).is_err());
+
+    let bad = vec![OneTimePrekey { key_id: 3, public_key: "AAAA".to_string() }];
+    assert!(validate_one_time_prekeys(&bad).is_err());
+}
+
+#[test]
+fn test_safety_numbers() {
+    let (alice, bob) = (ObjectId::new(), ObjectId::new());
+    let (phone, laptop) = (new_key().1, new_key().1);
+    let bob_keys = vec![new_key().1];
+
+    let a = fingerprint(alice, &[phone.clone(), laptop.clone()]);
+    assert_eq!(a.len(), 30);
+    assert!(a.chars().all(|c| c.is_ascii_digit()));
+    assert_eq!(a, fingerprint(alice, &[laptop.clone(), phone.clone()]), "device order doesn't matter");
+    assert_ne!(a, fingerprint(alice, &[phone.clone()]), "removing a device changes it");
+    assert_ne!(a, fingerprint(bob, &[phone.clone(), laptop.clone()]), "bound to the account");
+
+    let b = fingerprint(bob, &bob_keys);
+    assert_eq!(safety_number(&a, &b), safety_number(&b, &a), "both sides see the same number");
+    assert_eq!(safety_number(&a, &b).len(), 60);
+    assert_ne!(safety_number(&a, &b), safety_number(&a, &fingerprint(bob, &[new_key().1])));
+}
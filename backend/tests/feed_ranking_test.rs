@@ -0,0 +1,157 @@
+// For-You ranking tests: feature extraction, the scorers and diversity re-ranking.
+// Everything runs on hand-built posts with a fixed clock.
+
+use bson::{doc, oid::ObjectId};
+use karuteens_backend::features::content::ranking::{
+    diversify, extract_features, scorer_for, FeedRankingSettings, FeedScorer, FeedWeights, PostFeatures,
+    ViewerContext, WeightedScorer,
+};
+use karuteens_backend::models::{Post, Profile};
+
+const NOW_MS: i64 = 1_790_000_000_000;
+const HOUR_MS: i64 = 3_600_000;
+
+fn post(author: ObjectId, age_hours: i64, likes: i32, tags: &[&str], media: bool) -> Post {
+    let published = bson::DateTime::from_millis(NOW_MS - age_hours * HOUR_MS);
+    let mut d = doc! {
+        "_id": ObjectId::new(),
+        "author_id": author,
+        "category": "general",
+        "tags": tags.iter().map(|t| t.to_string()).collect::<Vec<_>>(),
+        "like_count": likes,
+        "published_at": published,
+        "created_at": published,
+        "updated_at": published,
+    };
+    if media {
+        d.insert("media_urls", vec!["https://cdn.example/a.jpg"]);
+    }
+    bson::from_document(d).unwrap()
+}
+
+fn profile(user_id: ObjectId, school: &str, year: i32) -> Profile {
+    bson::from_document(doc! { "user_id": user_id, "username": "author", "school": school, "year_of_study": year }).unwrap()
+}
+
+fn neutral() -> PostFeatures {
+    PostFeatures {
+        age_hours: 2.0,
+        engagement_velocity: 0.0,
+        followed_author: false,
+        interest_overlap: 0.0,
+        same_school: false,
+        same_year: false,
+        has_media: false,
+        is_featured: false,
+        seen: false,
+    }
+}
+
+#[test]
+fn test_features_capture_affinity_and_engagement() {
+    let author = ObjectId::new();
+    let seen_post = post(author, 4, 18, &["Football", "campus"], true);
+    let viewer = ViewerContext {
+        followed: [author].into_iter().collect(),
+        interests: ["football".to_string()].into_iter().collect(),
+        school: Some("Karatina University".into()),
+        year_of_study: Some(2),
+        seen: [seen_post.id.unwrap()].into_iter().collect(),
+    };
+
+    let f = extract_features(&viewer, &seen_post, Some(&profile(author, "karatina university", 2)), NOW_MS);
+    assert!((f.age_hours - 4.0).abs() < 1e-9);
+    assert!((f.engagement_velocity - 9.0).abs() < 1e-9); // 18 likes / sqrt(4h)
+    assert!((f.interest_overlap - 0.5).abs() < 1e-9);
+    assert!(f.followed_author && f.same_school && f.same_year && f.has_media && f.seen);
+
+    let stranger = extract_features(&viewer, &post(ObjectId::new(), 1, 0, &[], false), None, NOW_MS);
+    assert!(!stranger.followed_author && !stranger.same_school && !stranger.same_year && !stranger.seen);
+    assert_eq!(stranger.interest_overlap, 0.0);
+}
+
+#[test]
+fn test_weighted_scorer_rewards_each_signal() {
+    let scorer = WeightedScorer { weights: FeedWeights::default() };
+    let base = scorer.score(&neutral());
+
+    let boosted = [
+        PostFeatures { followed_author: true, ..neutral() },
+        PostFeatures { interest_overlap: 1.0, ..neutral() },
+        PostFeatures { same_school: true, ..neutral() },
+        PostFeatures { same_year: true, ..neutral() },
+        PostFeatures { has_media: true, ..neutral() },
+        PostFeatures { is_featured: true, ..neutral() },
+        PostFeatures { engagement_velocity: 20.0, ..neutral() },
+    ];
+    for features in boosted {
+        assert!(scorer.score(&features) > base, "{:?} should beat a neutral post", features);
+    }
+}
+
+#[test]
+fn test_weighted_scorer_decays_and_suppresses_seen() {
+    let scorer = WeightedScorer { weights: FeedWeights::default() };
+    let fresh = PostFeatures { followed_author: true, ..neutral() };
+    let day_old = PostFeatures { age_hours: 26.0, ..fresh.clone() };
+    // One half-life later the score halves
+    assert!((scorer.score(&day_old) * 2.0 - scorer.score(&fresh)).abs() < 1e-9);
+
+    // A seen post from a followed author sinks below an unseen one with a weaker signal
+    let seen = PostFeatures { seen: true, ..fresh.clone() };
+    let unseen_media = PostFeatures { has_media: true, ..neutral() };
+    assert!(scorer.score(&seen) < scorer.score(&unseen_media));
+}
+
+#[test]
+fn test_chronological_scorer_ignores_affinity() {
+    let settings = FeedRankingSettings { scorer: "chronological".into(), ..Default::default() };
+    let scorer = scorer_for(&settings).unwrap();
+    assert_eq!(scorer.name(), "chronological");
+    let newer = PostFeatures { age_hours: 1.0, ..neutral() };
+    let older_but_followed = PostFeatures { age_hours: 5.0, followed_author: true, is_featured: true, ..neutral() };
+    assert!(scorer.score(&newer) > scorer.score(&older_but_followed));
+}
+
+#[test]
+fn test_settings_validation() {
+    assert!(FeedRankingSettings::default().validate().is_ok());
+    assert!(FeedRankingSettings { scorer: "random".into(), ..Default::default() }.validate().is_err());
+
+    let mut negative = FeedRankingSettings::default();
+    negative.weights.interest = -1.0;
+    assert!(negative.validate().is_err());
+
+    let mut no_decay = FeedRankingSettings::default();
+    no_decay.weights.freshness_half_life_hours = 0.0;
+    assert!(no_decay.validate().is_err());
+
+    // Partial documents from the admin panel fill in the defaults
+    let partial: FeedRankingSettings = serde_json::from_value(serde_json::json!({ "weights": { "same_year": 5.0 } })).unwrap();
+    assert_eq!(partial.scorer, "weighted");
+    assert_eq!(partial.weights.same_year, 5.0);
+    assert_eq!(partial.weights.followed_author, FeedWeights::default().followed_author);
+}
+
+#[test]
+fn test_diversify_spreads_out_prolific_authors() {
+    let loud = ObjectId::new();
+    let quiet = ObjectId::new();
+    let loud_posts: Vec<ObjectId> = (0..3).map(|_| ObjectId::new()).collect();
+    let quiet_post = ObjectId::new();
+
+    let scored = vec![
+        (10.0, loud_posts[0], loud),
+        (9.5, loud_posts[1], loud),
+        (9.0, loud_posts[2], loud),
+        (6.0, quiet_post, quiet),
+    ];
+    let ranked = diversify(scored, 0.6);
+    assert_eq!(ranked.len(), 4);
+    assert_eq!(ranked[0], loud_posts[0]);
+    assert_eq!(ranked[1], quiet_post);
+
+    // With no penalty, pure score order wins apart from the back-to-back rule
+    let ranked = diversify(vec![(10.0, loud_posts[0], loud), (9.0, quiet_post, quiet)], 1.0);
+    assert_eq!(ranked, vec![loud_posts[0], quiet_post]);
+}
@@ -0,0 +1,106 @@
+// Gamification rules without a database: levels, timezone-aware streaks, badge
+// criteria, settings validation and award rows.
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use karuteens_backend::models::PointAward;
+use karuteens_backend::features::social::gamification::{
+    advance_streak, criterion_met, level_for, local_day, Activity, BadgeCriterion, GamificationSettings, PointRule, Progress,
+};
+
+fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+#[test]
+fn test_levels_roll_over_on_a_quadratic_curve() {
+    assert_eq!(level_for(0, 100), (1, 100));
+    assert_eq!(level_for(99, 100), (1, 100));
+    assert_eq!(level_for(100, 100), (2, 400));
+    assert_eq!(level_for(399, 100), (2, 400));
+    assert_eq!(level_for(400, 100), (3, 900));
+    // A big award can skip levels
+    assert_eq!(level_for(2_500, 100), (6, 3_600));
+}
+
+#[test]
+fn test_local_day_follows_the_users_offset() {
+    // 22:30 UTC on the 1st is already the 2nd in Nairobi (+3) but still the 1st in Lagos (+1)
+    let now = Utc.with_ymd_and_hms(2026, 3, 1, 22, 30, 0).unwrap();
+    assert_eq!(local_day(now, 180), day(2026, 3, 2));
+    assert_eq!(local_day(now, 60), day(2026, 3, 1));
+    assert_eq!(local_day(Utc.with_ymd_and_hms(2026, 3, 1, 2, 0, 0).unwrap(), -300), day(2026, 2, 28));
+}
+
+#[test]
+fn test_streaks() {
+    let today = day(2026, 3, 2);
+    assert_eq!(advance_streak(None, today, 0), Some(1));
+    assert_eq!(advance_streak(Some(day(2026, 3, 1)), today, 4), Some(5));
+    assert_eq!(advance_streak(Some(today), today, 4), None);
+    assert_eq!(advance_streak(Some(day(2026, 2, 27)), today, 4), Some(1));
+    // Moving west can put the last active day "tomorrow"; leave the streak alone
+    assert_eq!(advance_streak(Some(day(2026, 3, 3)), today, 4), None);
+    // Month boundaries
+    assert_eq!(advance_streak(Some(day(2026, 2, 28)), day(2026, 3, 1), 9), Some(10));
+}
+
+#[test]
+fn test_badge_criteria() {
+    let mut progress = Progress { points: 1_200, streak: 6, level: 4, ..Default::default() };
+    progress.activity_counts.insert(Activity::Comment, 100);
+
+    assert!(criterion_met(&BadgeCriterion::Points { min: 1_000 }, &progress));
+    assert!(!criterion_met(&BadgeCriterion::Streak { min: 7 }, &progress));
+    assert!(!criterion_met(&BadgeCriterion::Level { min: 5 }, &progress));
+    assert!(criterion_met(&BadgeCriterion::Activity { activity: Activity::Comment, count: 100 }, &progress));
+    assert!(!criterion_met(&BadgeCriterion::Activity { activity: Activity::StudySession, count: 1 }, &progress));
+}
+
+#[test]
+fn test_settings_validation_and_defaults() {
+    let defaults = GamificationSettings::default();
+    assert!(defaults.validate().is_ok());
+    for activity in [Activity::PostPublished, Activity::Comment, Activity::StudySession, Activity::Attendance, Activity::MaterialUpload] {
+        let rule = defaults.rule_for(activity).unwrap();
+        assert!(rule.points > 0 && rule.daily_cap > 0, "{} should pay out, capped", activity.as_str());
+    }
+
+    let mut duplicate = GamificationSettings::default();
+    duplicate.rules.push(PointRule { activity: Activity::Comment, points: 1, daily_cap: 1 });
+    assert!(duplicate.validate().is_err());
+
+    let mut badges = GamificationSettings::default();
+    badges.badges.push(badges.badges[0].clone());
+    assert!(badges.validate().is_err());
+
+    assert!(GamificationSettings { level_step: 0, ..Default::default() }.validate().is_err());
+    assert!(GamificationSettings { default_utc_offset_minutes: 15 * 60, ..Default::default() }.validate().is_err());
+
+    // Badges are declared as tagged JSON from the admin panel
+    let parsed: GamificationSettings = serde_json::from_value(serde_json::json!({
+        "badges": [{ "id": "night-owl", "name": "Night Owl", "icon": "🦉", "description": "50 comments",
+                     "criterion": { "type": "activity", "activity": "comment", "count": 50 } }]
+    }))
+    .unwrap();
+    assert_eq!(parsed.badges[0].criterion, BadgeCriterion::Activity { activity: Activity::Comment, count: 50 });
+    assert_eq!(parsed.rules, GamificationSettings::default().rules);
+}
+
+#[test]
+fn test_award_rows_from_before_the_applied_flag_count_as_applied() {
+    let old = bson::doc! {
+        "user_id": bson::oid::ObjectId::new(),
+        "activity": "comment",
+        "source_id": "c1",
+        "points": 2,
+        "badge_id": null,
+        "local_day": "2026-10-18",
+        "created_at": bson::DateTime::now(),
+    };
+    let award: PointAward = bson::from_document(old.clone()).unwrap();
+    assert!(award.applied);
+
+    let mut pending = old;
+    pending.insert("applied", false);
+    assert!(!bson::from_document::<PointAward>(pending).unwrap().applied);
+}
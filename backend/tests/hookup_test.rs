@@ -0,0 +1,104 @@
+// Hookup matching: preference filters, compatibility scoring and pair keys.
+
+use chrono::{Duration, TimeZone, Utc};
+use karuteens_backend::features::social::hookup::{accepts, compatibility_score, pair_key, Interaction};
+use karuteens_backend::models::{HookupAlias, HookupPreferences};
+
+fn alias(gender: &str, age: i32, school: Option<&str>, last_active: chrono::DateTime<Utc>) -> HookupAlias {
+    let doc = bson::doc! {
+        "user_id": bson::oid::ObjectId::new(),
+        "alias_username": "night_owl",
+        "gender": gender,
+        "age": age,
+        "bio": "",
+        "is_verified": true,
+        "created_at": bson::DateTime::from_chrono(last_active - Duration::days(30)),
+        "school": school,
+        "last_active_at": bson::DateTime::from_chrono(last_active),
+    };
+    bson::from_document(doc).unwrap()
+}
+
+fn prefs(genders: &[&str], min_age: i32, max_age: i32, schools: &[&str]) -> HookupPreferences {
+    HookupPreferences {
+        genders: genders.iter().map(|s| s.to_string()).collect(),
+        min_age,
+        max_age,
+        schools: schools.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+#[test]
+fn test_aliases_without_preferences_accept_any_adult() {
+    let now = Utc::now();
+    let old: HookupAlias = bson::from_document(bson::doc! {
+        "user_id": bson::oid::ObjectId::new(),
+        "alias_username": "legacy",
+        "gender": "female",
+        "age": 21,
+        "bio": "",
+        "is_verified": true,
+        "created_at": bson::DateTime::now(),
+    }).unwrap();
+    assert_eq!(old.preferences, HookupPreferences::default());
+    assert!(!old.is_suspended);
+    assert!(accepts(&old.preferences, &alias("male", 40, None, now)));
+}
+
+#[test]
+fn test_accepts_checks_gender_age_and_school() {
+    let now = Utc::now();
+    let p = prefs(&["Female"], 19, 24, &["School of Business"]);
+    assert!(accepts(&p, &alias("female", 21, Some("school of business"), now)), "matching is case-insensitive");
+    assert!(!accepts(&p, &alias("male", 21, Some("School of Business"), now)));
+    assert!(!accepts(&p, &alias("female", 25, Some("School of Business"), now)));
+    assert!(!accepts(&p, &alias("female", 21, Some("School of Law"), now)));
+    assert!(!accepts(&p, &alias("female", 21, None, now)), "a school filter excludes aliases without a school");
+}
+
+#[test]
+fn test_score_requires_both_sides_to_fit() {
+    let now = Utc::now();
+    let mut me = alias("male", 22, None, now);
+    me.preferences = prefs(&["female"], 18, 30, &[]);
+    let mut them = alias("female", 22, None, now);
+    assert!(compatibility_score(&me, &them, false, now).is_some());
+
+    them.preferences = prefs(&["female"], 18, 30, &[]);
+    assert_eq!(compatibility_score(&me, &them, false, now), None, "they aren't looking for me");
+}
+
+#[test]
+fn test_score_ranks_school_age_recency_and_likes() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+    let me = alias("male", 22, Some("School of Engineering"), now);
+    let score = |a: &HookupAlias, liked: bool| compatibility_score(&me, a, liked, now).unwrap();
+
+    let base = alias("female", 22, None, now);
+    let same_school = alias("female", 22, Some("school of engineering"), now);
+    let older = alias("female", 28, None, now);
+    let idle = alias("female", 22, None, now - Duration::days(6));
+
+    assert!(score(&same_school, false) > score(&base, false));
+    assert!(score(&base, false) > score(&older, false));
+    assert!(score(&base, false) > score(&idle, false));
+    assert!(score(&idle, true) > score(&idle, false));
+    assert!((score(&base, false) - score(&idle, false) - 1.5).abs() < 1e-9, "recency halves every three days");
+}
+
+#[test]
+fn test_pair_key_is_symmetric() {
+    let a = bson::oid::ObjectId::new();
+    let b = bson::oid::ObjectId::new();
+    assert_eq!(pair_key(a, b), pair_key(b, a));
+    assert_ne!(pair_key(a, b), pair_key(a, bson::oid::ObjectId::new()));
+}
+
+#[test]
+fn test_interactions() {
+    for name in ["like", "pass", "block"] {
+        assert_eq!(Interaction::parse(name).unwrap().as_str(), name);
+    }
+    assert_eq!(Interaction::parse("superlike"), None);
+    assert_eq!(Interaction::parse("LIKE"), None);
+}
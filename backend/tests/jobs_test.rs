@@ -0,0 +1,125 @@
+// Job queue tests that don't need Redis: cron evaluation, retry policy and the
+// envelope wire format.
+
+use chrono::{TimeZone, Utc};
+use karuteens_backend::features::infrastructure::cron::CronSchedule;
+use karuteens_backend::features::infrastructure::jobs::{
+    failure_outcome, retry_backoff_secs, FailureOutcome, Job, JobEnvelope, JobError,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct ResizeJob {
+    upload_id: String,
+    width: u32,
+}
+
+impl Job for ResizeJob {
+    const KIND: &'static str = "test.resize";
+    const QUEUE: &'static str = "images";
+    const MAX_ATTEMPTS: u32 = 3;
+}
+
+#[test]
+fn test_cron_parses_steps_ranges_and_lists() {
+    let every_15 = CronSchedule::parse("*/15 * * * *").unwrap();
+    assert!(every_15.matches(Utc.with_ymd_and_hms(2026, 3, 2, 10, 45, 30).unwrap()));
+    assert!(!every_15.matches(Utc.with_ymd_and_hms(2026, 3, 2, 10, 46, 0).unwrap()));
+
+    let weekdays = CronSchedule::parse("0 8-10 * * 1-5").unwrap();
+    // 2026-03-02 is a Monday, 2026-03-07 a Saturday
+    assert!(weekdays.matches(Utc.with_ymd_and_hms(2026, 3, 2, 9, 0, 0).unwrap()));
+    assert!(!weekdays.matches(Utc.with_ymd_and_hms(2026, 3, 2, 11, 0, 0).unwrap()));
+    assert!(!weekdays.matches(Utc.with_ymd_and_hms(2026, 3, 7, 9, 0, 0).unwrap()));
+
+    let listed = CronSchedule::parse("5,35 0 1,15 * *").unwrap();
+    assert!(listed.matches(Utc.with_ymd_and_hms(2026, 3, 15, 0, 35, 0).unwrap()));
+    assert!(!listed.matches(Utc.with_ymd_and_hms(2026, 3, 16, 0, 35, 0).unwrap()));
+}
+
+#[test]
+fn test_cron_treats_7_as_sunday() {
+    let sunday = CronSchedule::parse("0 0 * * 7").unwrap();
+    // 2026-03-08 is a Sunday
+    assert!(sunday.matches(Utc.with_ymd_and_hms(2026, 3, 8, 0, 0, 0).unwrap()));
+}
+
+#[test]
+fn test_cron_rejects_bad_expressions() {
+    assert!(CronSchedule::parse("* * * *").is_err());
+    assert!(CronSchedule::parse("60 * * * *").is_err());
+    assert!(CronSchedule::parse("*/0 * * * *").is_err());
+    assert!(CronSchedule::parse("10-5 * * * *").is_err());
+    assert!(CronSchedule::parse("a * * * *").is_err());
+}
+
+#[test]
+fn test_cron_next_after() {
+    let weekly = CronSchedule::parse("0 6 * * 1").unwrap();
+    let from = Utc.with_ymd_and_hms(2026, 3, 2, 6, 0, 0).unwrap();
+    // Strictly after: the firing at `from` itself doesn't count
+    assert_eq!(weekly.next_after(from), Some(Utc.with_ymd_and_hms(2026, 3, 9, 6, 0, 0).unwrap()));
+
+    let leap_day = CronSchedule::parse("0 0 29 2 *").unwrap();
+    assert_eq!(
+        leap_day.next_after(Utc.with_ymd_and_hms(2027, 3, 1, 0, 0, 0).unwrap()),
+        Some(Utc.with_ymd_and_hms(2028, 2, 29, 0, 0, 0).unwrap())
+    );
+    // Never fires, so the search gives up instead of looping forever
+    assert_eq!(CronSchedule::parse("0 0 31 2 *").unwrap().next_after(from), None);
+}
+
+#[test]
+fn test_retry_backoff_doubles_and_caps() {
+    assert_eq!(retry_backoff_secs(1), 10);
+    assert_eq!(retry_backoff_secs(2), 20);
+    assert_eq!(retry_backoff_secs(3), 40);
+    assert_eq!(retry_backoff_secs(20), 3600);
+}
+
+#[test]
+fn test_failure_outcome_retries_until_attempts_run_out() {
+    let now = 1_000_000;
+    let mut envelope = JobEnvelope::new(&ResizeJob { upload_id: "u1".into(), width: 640 }, Utc::now()).unwrap();
+
+    envelope.attempts = 1;
+    match failure_outcome(&envelope, &JobError::Retry("timeout".into()), now) {
+        FailureOutcome::RetryAt(at) => assert!((now + 10_000..=now + 11_000).contains(&at)),
+        other => panic!("expected a retry, got {:?}", other),
+    }
+
+    envelope.attempts = 3;
+    assert_eq!(failure_outcome(&envelope, &JobError::Retry("timeout".into()), now), FailureOutcome::Dead);
+
+    envelope.attempts = 1;
+    assert_eq!(failure_outcome(&envelope, &JobError::Fatal("bad input".into()), now), FailureOutcome::Dead);
+}
+
+#[test]
+fn test_envelope_carries_job_metadata_and_roundtrips() {
+    let job = ResizeJob { upload_id: "u1".into(), width: 640 };
+    let envelope = JobEnvelope::new(&job, Utc::now()).unwrap();
+    assert_eq!(envelope.kind, "test.resize");
+    assert_eq!(envelope.queue, "images");
+    assert_eq!(envelope.max_attempts, 3);
+    assert_eq!(envelope.attempts, 0);
+
+    let raw = serde_json::to_string(&envelope).unwrap();
+    let decoded: JobEnvelope = serde_json::from_str(&raw).unwrap();
+    assert_eq!(decoded, envelope);
+    assert_eq!(serde_json::from_value::<ResizeJob>(decoded.payload).unwrap(), job);
+}
+
+#[test]
+fn test_registry_covers_background_work() {
+    let kinds = karuteens_backend::features::infrastructure::jobs::job_registry().kinds();
+    for kind in ["media.process", "mail.event_reminders", "mail.weekly_digest", "posts.publish_scheduled", "posts.fanout", "timeline.fanout", "gamification.award", "payments.reconcile", "ledger.rebuild_account", "ledger.sweep_journals", "subscriptions.expire", "subscriptions.remind", "messages.expire", "unfurl.attach", "messages.send_scheduled", "live.end_dropped"] {
+        assert!(kinds.contains(&kind), "{} is not registered", kind);
+    }
+}
+
+#[test]
+fn test_registry_lists_its_queues() {
+    let registry = karuteens_backend::features::infrastructure::jobs::job_registry();
+    assert_eq!(registry.queues(), vec!["default", "media"]);
+}
@@ -0,0 +1,180 @@
+// Payments ledger: journal balancing, payment splits, purposes and callback authentication.
+
+use karuteens_backend::features::monetization::ledger::{
+    account_totals, counts_toward, earnings_account, line_filter, line_update, payment_lines, validate_lines,
+    MPESA_ACCOUNT, REVENUE_ACCOUNT,
+};
+use karuteens_backend::features::monetization::payments::{callback_authorized, donation_kes, PaymentPurpose};
+use karuteens_backend::models::{LedgerJournal, LedgerLine, Transaction};
+
+fn line(account: &str, amount: i64) -> LedgerLine {
+    LedgerLine { account: account.to_string(), amount }
+}
+
+#[test]
+fn test_validate_lines_requires_balance() {
+    assert!(validate_lines(&[line("a", -500), line("b", 300), line("c", 200)]).is_ok());
+    assert!(validate_lines(&[line("a", -500), line("b", 400)]).is_err());
+    assert!(validate_lines(&[]).is_err());
+    assert!(validate_lines(&[line("a", 0), line("b", 0)]).is_err());
+}
+
+#[test]
+fn test_payment_lines_split_and_remainder() {
+    let creator = earnings_account(bson::oid::ObjectId::new());
+    let lines = payment_lines(10_000, REVENUE_ACCOUNT, &[line(&creator, 7_000)]);
+    assert_eq!(lines, vec![line(MPESA_ACCOUNT, -10_000), line(&creator, 7_000), line(REVENUE_ACCOUNT, 3_000)]);
+    assert!(validate_lines(&lines).is_ok());
+
+    // No splits: everything is platform revenue
+    let lines = payment_lines(2_000, REVENUE_ACCOUNT, &[]);
+    assert_eq!(lines, vec![line(MPESA_ACCOUNT, -2_000), line(REVENUE_ACCOUNT, 2_000)]);
+
+    // A split larger than the payment can't create money
+    let lines = payment_lines(1_000, REVENUE_ACCOUNT, &[line(&creator, 5_000)]);
+    assert_eq!(lines, vec![line(MPESA_ACCOUNT, -1_000), line(&creator, 1_000)]);
+    assert!(validate_lines(&lines).is_ok());
+}
+
+fn journal(key: &str, lines: Vec<LedgerLine>) -> LedgerJournal {
+    LedgerJournal {
+        id: None,
+        key: key.to_string(),
+        purpose: "test".to_string(),
+        lines,
+        transaction_id: None,
+        applied: true,
+        funded_account: None,
+        created_at: bson::DateTime::now(),
+    }
+}
+
+#[test]
+fn test_account_totals_rebuild_from_journals() {
+    let journals = [
+        journal("payment:1", vec![line(MPESA_ACCOUNT, -5000), line(REVENUE_ACCOUNT, 5000)]),
+        journal("payment:2", vec![line(MPESA_ACCOUNT, -2000), line("user:x:earnings", 1400), line(REVENUE_ACCOUNT, 600)]),
+        journal("refund:1", vec![line(MPESA_ACCOUNT, 5000), line(REVENUE_ACCOUNT, -5000)]),
+    ];
+    assert_eq!(account_totals(REVENUE_ACCOUNT, &journals), (600, 5600, 5000));
+    assert_eq!(account_totals(MPESA_ACCOUNT, &journals), (-2000, 5000, 7000));
+    assert_eq!(account_totals("user:x:earnings", &journals), (1400, 1400, 0));
+    assert_eq!(account_totals("user:y:earnings", &journals), (0, 0, 0));
+}
+
+#[test]
+fn test_applying_a_line_is_idempotent_and_versioned() {
+    let filter = line_filter(REVENUE_ACCOUNT, "payment:1");
+    assert_eq!(filter.get_str("account").unwrap(), REVENUE_ACCOUNT);
+    // A retry after a crash can't add the same journal twice
+    assert_eq!(filter.get_document("applied_journals").unwrap().get_str("$ne").unwrap(), "payment:1");
+
+    let update = line_update("payment:1", -700);
+    let inc = update.get_document("$inc").unwrap();
+    assert_eq!(inc.get_i64("balance").unwrap(), -700);
+    assert_eq!(inc.get_i64("credited").unwrap(), 0);
+    assert_eq!(inc.get_i64("debited").unwrap(), 700);
+    // Every change bumps the version a rebuild compares against
+    assert_eq!(inc.get_i32("version").unwrap(), 1);
+    let pushed = update.get_document("$push").unwrap().get_document("applied_journals").unwrap();
+    assert_eq!(pushed.get_array("$each").unwrap(), &vec![bson::Bson::String("payment:1".to_string())]);
+    assert!(pushed.get_i32("$slice").unwrap() < 0);
+}
+
+#[test]
+fn test_rebuild_counts_only_journals_on_the_account() {
+    let mut half_done = journal("payment:2", vec![line(MPESA_ACCOUNT, -2000), line(REVENUE_ACCOUNT, 2000)]);
+    half_done.applied = false;
+    let done = journal("payment:1", vec![line(MPESA_ACCOUNT, -5000), line(REVENUE_ACCOUNT, 5000)]);
+
+    // The poster died after crediting mpesa but before revenue: mpesa counts it, revenue doesn't yet
+    let mpesa_applied = vec!["payment:1".to_string(), "payment:2".to_string()];
+    let revenue_applied = vec!["payment:1".to_string()];
+    assert!(counts_toward(&done, &[]));
+    assert!(counts_toward(&half_done, &mpesa_applied));
+    assert!(!counts_toward(&half_done, &revenue_applied));
+
+    let counted: Vec<_> = [&done, &half_done].into_iter().filter(|j| counts_toward(j, &revenue_applied)).collect();
+    assert_eq!(account_totals(REVENUE_ACCOUNT, counted), (5000, 5000, 0));
+}
+
+#[test]
+fn test_legacy_journals_read_as_applied() {
+    let legacy: LedgerJournal = bson::from_document(bson::doc! {
+        "key": "payment:old",
+        "purpose": "premium",
+        "lines": [{ "account": MPESA_ACCOUNT, "amount": -100_i64 }, { "account": REVENUE_ACCOUNT, "amount": 100_i64 }],
+        "transaction_id": null,
+        "created_at": bson::DateTime::now(),
+    }).unwrap();
+    assert!(legacy.applied);
+    assert!(legacy.funded_account.is_none());
+}
+
+#[test]
+fn test_donations_charge_and_record_the_same_whole_shillings() {
+    assert_eq!(donation_kes(50.0), 50);
+    // M-Pesa takes whole shillings, so the stored amount must be the rounded charge
+    assert_eq!(donation_kes(50.4), 51);
+    assert_eq!(donation_kes(0.29), 1);
+    // Float noise doesn't round up a whole amount
+    assert_eq!(donation_kes(19.999999999), 20);
+    assert_eq!(donation_kes(0.0), 0);
+    assert_eq!(donation_kes(-10.0), 0);
+    assert_eq!(donation_kes(f64::NAN), 0);
+}
+
+#[test]
+fn test_purpose_round_trips_transaction_types() {
+    for purpose in [
+        PaymentPurpose::Verification,
+        PaymentPurpose::Premium,
+        PaymentPurpose::Hookup,
+        PaymentPurpose::Donation,
+        PaymentPurpose::Material,
+        PaymentPurpose::MarketplaceBoost,
+    ] {
+        assert_eq!(PaymentPurpose::parse(purpose.as_str()), Some(purpose));
+    }
+    assert_eq!(PaymentPurpose::parse("bogus"), None);
+}
+
+#[test]
+fn test_callback_authorization() {
+    let ips = vec!["196.201.214.200".to_string(), "196.201.214.206".to_string()];
+
+    // No secret configured: nothing is trusted, whatever the address or token
+    assert!(!callback_authorized(None, None, &[], None));
+    assert!(!callback_authorized(None, Some("anything"), &[], None));
+    assert!(!callback_authorized(Some("196.201.214.200"), None, &ips, None));
+    assert!(!callback_authorized(Some("196.201.214.200"), Some(""), &ips, None));
+
+    assert!(callback_authorized(None, Some("s3cret"), &[], Some("s3cret")));
+    assert!(!callback_authorized(None, Some("s3cre7"), &[], Some("s3cret")));
+    assert!(!callback_authorized(None, None, &[], Some("s3cret")));
+
+    // Both configured: both must pass
+    assert!(callback_authorized(Some("196.201.214.206"), Some("s3cret"), &ips, Some("s3cret")));
+    assert!(!callback_authorized(Some("10.0.0.1"), Some("s3cret"), &ips, Some("s3cret")));
+    assert!(!callback_authorized(None, Some("s3cret"), &ips, Some("s3cret")), "allow-list set but address unknown");
+}
+
+#[test]
+fn test_legacy_material_id_reads_as_target() {
+    let material = bson::oid::ObjectId::new();
+    let doc = bson::doc! {
+        "user_id": bson::oid::ObjectId::new(),
+        "phone_number": "254700000000",
+        "amount": 5000_i64,
+        "mpesa_receipt_number": null,
+        "checkout_request_id": "ws_CO_2",
+        "status": "pending",
+        "transaction_type": "material",
+        "premium_duration": null,
+        "material_id": material,
+        "created_at": bson::DateTime::now(),
+        "updated_at": bson::DateTime::now(),
+    };
+    let tx: Transaction = bson::from_document(doc).unwrap();
+    assert_eq!(tx.target_id, Some(material));
+}
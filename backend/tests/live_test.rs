@@ -0,0 +1,130 @@
+// Live streams: stream key validation, ingest path parsing, recording URLs and chat roles.
+
+use chrono::{Duration, TimeZone, Utc};
+use karuteens_backend::features::social::live_chat::{chat_offset_ms, chat_role, ChatRole};
+use karuteens_backend::features::social::live::{
+    hls_url, publish_decision, query_param, ready_allowed, reconnect_expired, recording_url, stream_name, PublishDecision,
+};
+use karuteens_backend::models::social::LiveStream;
+
+fn stream(ended: bool) -> LiveStream {
+    let doc = bson::doc! {
+        "_id": bson::oid::ObjectId::parse_str("652f1c2e9d1e8a0012345678").unwrap(),
+        "user_id": bson::oid::ObjectId::new(),
+        "username": "streamer",
+        "user_avatar": null,
+        "title": "Finals revision",
+        "started_at": bson::DateTime::now(),
+        "ended_at": if ended { Some(bson::DateTime::now()) } else { None },
+        "is_active": false,
+        "viewer_count": 0,
+        "stream_key": "5b0e1f6a-2c3d-4e5f-8a9b-0c1d2e3f4a5b",
+        "stream_url": null,
+        "thumbnail_url": null,
+        "created_at": bson::DateTime::now(),
+        "updated_at": bson::DateTime::now(),
+    };
+    bson::from_document(doc).unwrap()
+}
+
+#[test]
+fn test_publish_decision() {
+    let s = stream(false);
+    assert_eq!(publish_decision(Some(&s), "652f1c2e9d1e8a0012345678"), PublishDecision::Allow);
+    assert_eq!(
+        publish_decision(Some(&s), "5b0e1f6a-2c3d-4e5f-8a9b-0c1d2e3f4a5b"),
+        PublishDecision::Redirect("652f1c2e9d1e8a0012345678".to_string()),
+        "publishing to the bare key is renamed to the stream id",
+    );
+    assert_eq!(publish_decision(Some(&s), "someone-elses-stream"), PublishDecision::Deny);
+    assert_eq!(publish_decision(None, "652f1c2e9d1e8a0012345678"), PublishDecision::Deny, "unknown key");
+    assert_eq!(publish_decision(Some(&stream(true)), "652f1c2e9d1e8a0012345678"), PublishDecision::Deny, "ended streams can't be resumed");
+}
+
+#[test]
+fn test_ready_hook_goes_live_by_stream_id() {
+    // A password-authenticated publish has no key in the query, so the id in the path is enough
+    assert!(ready_allowed(Some(&stream(false)), "652f1c2e9d1e8a0012345678"));
+    assert!(!ready_allowed(Some(&stream(false)), "5b0e1f6a-2c3d-4e5f-8a9b-0c1d2e3f4a5b"));
+    assert!(!ready_allowed(Some(&stream(true)), "652f1c2e9d1e8a0012345678"), "ended streams stay ended");
+    assert!(!ready_allowed(None, "652f1c2e9d1e8a0012345678"));
+}
+
+#[test]
+fn test_streams_saved_before_ingest_deserialize() {
+    let s = stream(false);
+    assert_eq!(s.peak_viewer_count, 0);
+    assert!(s.recording_job_ids.is_empty());
+    assert!(s.ingest_started_at.is_none());
+}
+
+#[test]
+fn test_ingest_paths_and_queries() {
+    assert_eq!(stream_name("live/652f1c2e9d1e8a0012345678"), "652f1c2e9d1e8a0012345678");
+    assert_eq!(stream_name("/live/abc/"), "abc");
+    assert_eq!(stream_name("abc"), "abc");
+
+    assert_eq!(query_param("key=abc&foo=bar", "key"), Some("abc".to_string()));
+    assert_eq!(query_param("?foo=bar&key=abc", "key"), Some("abc".to_string()));
+    assert_eq!(query_param("key=", "key"), None);
+    assert_eq!(query_param("", "key"), None);
+    assert_eq!(query_param("monkey=abc", "key"), None);
+
+    assert_eq!(
+        hls_url("https://live.example.test/hls/{id}/index.m3u8", "652f"),
+        "https://live.example.test/hls/652f/index.m3u8",
+    );
+}
+
+#[test]
+fn test_recording_url() {
+    let base = "https://rec.example.test/recordings/";
+    assert_eq!(
+        recording_url(base, None, "/var/rec/652f-1697630000.flv"),
+        Some("https://rec.example.test/recordings/652f-1697630000.flv".to_string()),
+    );
+    assert_eq!(
+        recording_url(base, Some("/recordings"), "/recordings/live/652f/2026-10-18_12-00-00.mp4"),
+        Some("https://rec.example.test/recordings/live/652f/2026-10-18_12-00-00.mp4".to_string()),
+    );
+    assert_eq!(recording_url(base, Some("/recordings"), "/recordings/../etc/passwd"), None);
+    assert_eq!(recording_url(base, None, "/var/rec/"), None);
+}
+
+#[test]
+fn test_chat_roles() {
+    let mut s = stream(false);
+    let (viewer, moderator, banned) = (bson::oid::ObjectId::new(), bson::oid::ObjectId::new(), bson::oid::ObjectId::new());
+    s.moderators.push(moderator);
+    s.banned_user_ids.push(banned);
+    s.banned_user_ids.push(s.user_id);
+
+    assert_eq!(chat_role(&s, s.user_id), ChatRole::Streamer, "the streamer can't be banned from their own chat");
+    assert_eq!(chat_role(&s, moderator), ChatRole::Moderator);
+    assert_eq!(chat_role(&s, viewer), ChatRole::Viewer);
+    assert_eq!(chat_role(&s, banned), ChatRole::Banned);
+
+    assert!(ChatRole::Streamer.can_moderate() && ChatRole::Moderator.can_moderate());
+    assert!(!ChatRole::Viewer.can_moderate() && !ChatRole::Banned.can_moderate());
+}
+
+#[test]
+fn test_chat_offsets_follow_the_recording() {
+    let mut s = stream(false);
+    let created = Utc.with_ymd_and_hms(2026, 10, 18, 18, 0, 0).unwrap();
+    s.started_at = bson::DateTime::from_chrono(created);
+    assert_eq!(chat_offset_ms(&s, created + Duration::seconds(90)), 90_000, "before ingest, offsets count from creation");
+
+    s.ingest_started_at = Some(bson::DateTime::from_chrono(created + Duration::minutes(2)));
+    assert_eq!(chat_offset_ms(&s, created + Duration::minutes(3)), 60_000);
+    assert_eq!(chat_offset_ms(&s, created), 0, "never negative");
+}
+
+#[test]
+fn test_dropped_encoder_gets_a_grace_period() {
+    let dropped = Utc.with_ymd_and_hms(2026, 10, 18, 20, 0, 0).unwrap();
+    assert!(!reconnect_expired(dropped, dropped + Duration::seconds(5), 60), "a blip doesn't end the stream");
+    assert!(!reconnect_expired(dropped, dropped + Duration::seconds(59), 60));
+    assert!(reconnect_expired(dropped, dropped + Duration::seconds(60), 60));
+    assert!(reconnect_expired(dropped, dropped, 0), "no grace ends it straight away");
+}
@@ -0,0 +1,238 @@
+// Mail subsystem tests: templates, MIME encoding, retry schedule and the SMTP client
+// (against a scripted local server, no TLS)
+
+use karuteens_backend::features::infrastructure::mail::{
+    build_mime_message, dot_stuff, retry_delay_secs, valid_recipient, EmailMessage, FileMailer, MailError, Mailer, MemoryMailer, Sender,
+    SmtpMailer, SmtpSecurity,
+};
+use karuteens_backend::features::infrastructure::mail_schedules::digest_week;
+use karuteens_backend::features::infrastructure::mail_templates::{escape_html, DigestItem, EmailTemplate};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+fn sender() -> Sender {
+    Sender { email: "no-reply@karuteens.site".to_string(), name: "Karu teens".to_string() }
+}
+
+fn message() -> EmailMessage {
+    EmailMessage {
+        to: "student@karu.ac.ke".to_string(),
+        to_name: Some("Wanjiru".to_string()),
+        subject: "Habari".to_string(),
+        html: "<p>Hello</p>".to_string(),
+        text: "Hello\n.hidden line".to_string(),
+    }
+}
+
+#[test]
+fn test_templates_escape_user_content() {
+    let rendered = EmailTemplate::EmailVerification {
+        username: "<script>alert(1)</script>".to_string(),
+        link: "https://karuteens.site/verify-email?token=abc&x=1".to_string(),
+        expires_hours: 48,
+    }
+    .render();
+    assert!(!rendered.html.contains("<script>"));
+    assert!(rendered.html.contains("&lt;script&gt;"));
+    assert!(rendered.html.contains("token=abc&amp;x=1"));
+    assert!(rendered.text.contains("https://karuteens.site/verify-email?token=abc&x=1"));
+    assert_eq!(escape_html("a\"b'c"), "a&quot;b&#39;c");
+}
+
+#[test]
+fn test_each_template_has_subject_and_both_bodies() {
+    let templates = vec![
+        EmailTemplate::PasswordReset { code: "123456".to_string(), expires_minutes: 15 },
+        EmailTemplate::EmailVerification { username: "jay".to_string(), link: "https://x".to_string(), expires_hours: 48 },
+        EmailTemplate::WeeklyDigest {
+            username: "jay".to_string(),
+            items: vec![DigestItem { title: "Exam timetable".to_string(), summary: "Out now".to_string(), url: "https://x/p/1".to_string() }],
+            unread_notifications: 3,
+            link: "https://x".to_string(),
+        },
+        EmailTemplate::EventReminder {
+            username: "jay".to_string(),
+            event_title: "Freshers Night".to_string(),
+            starts_at: "Fri 7:00 PM".to_string(),
+            location: "Main Hall".to_string(),
+            link: "https://x/events/1".to_string(),
+        },
+    ];
+    let names: Vec<&str> = templates.iter().map(|t| t.name()).collect();
+    assert_eq!(names, vec!["password_reset", "email_verification", "weekly_digest", "event_reminder"]);
+    for template in templates {
+        let rendered = template.render();
+        assert!(!rendered.subject.is_empty());
+        assert!(rendered.html.starts_with("<html>"));
+        assert!(!rendered.text.is_empty());
+    }
+    assert_eq!(
+        EmailTemplate::EventReminder {
+            username: "jay".to_string(),
+            event_title: "Freshers Night".to_string(),
+            starts_at: "Fri".to_string(),
+            location: "Hall".to_string(),
+            link: "https://x".to_string(),
+        }
+        .render()
+        .subject,
+        "Reminder: Freshers Night starts soon"
+    );
+}
+
+#[test]
+fn test_mime_message_and_dot_stuffing() {
+    let mut msg = message();
+    msg.subject = "Karibu 🎉".to_string();
+    let mime = build_mime_message(&sender(), &msg, chrono::Utc::now());
+    assert!(mime.contains("From: Karu teens <no-reply@karuteens.site>\r\n"));
+    assert!(mime.contains("To: Wanjiru <student@karu.ac.ke>\r\n"));
+    assert!(mime.contains("Subject: =?UTF-8?B?"));
+    assert!(mime.contains("Content-Type: text/plain; charset=utf-8"));
+    assert!(mime.contains("Content-Type: text/html; charset=utf-8"));
+    assert!(mime.lines().all(|l| l.len() <= 998));
+
+    assert_eq!(dot_stuff("a\r\n.b\r\n..c"), "a\r\n..b\r\n...c");
+}
+
+#[test]
+fn test_header_values_cannot_inject_headers() {
+    let mut msg = message();
+    msg.subject = "Reminder: Party\r\nBcc: victim@example.com\r\n\r\nfake body starts soon".to_string();
+    msg.to_name = Some("Wanjiru\nX-Injected: 1".to_string());
+    let mime = build_mime_message(&sender(), &msg, chrono::Utc::now());
+    let headers = mime.split("\r\n\r\n").next().unwrap();
+    assert!(!headers.lines().any(|l| l.starts_with("Bcc:") || l.starts_with("X-Injected:")), "{}", headers);
+    assert!(headers.contains("Subject: Reminder: Party  Bcc: victim@example.com    fake body starts soon\r\n"));
+
+    assert!(valid_recipient("student@karu.ac.ke"));
+    assert!(!valid_recipient("student@karu.ac.ke>\r\nRCPT TO:<victim@example.com"));
+    assert!(!valid_recipient("a@b@c"));
+    assert!(!valid_recipient("no-at-sign"));
+    assert!(!valid_recipient("two words@karu.ac.ke"));
+}
+
+#[test]
+fn test_event_titles_are_single_line() {
+    use karuteens_backend::features::social::events::validate_event_title;
+    assert!(validate_event_title("Freshers' night").is_ok());
+    assert!(validate_event_title("Party\r\nBcc: victim@example.com").is_err());
+    assert!(validate_event_title("   ").is_err());
+    assert!(validate_event_title(&"x".repeat(201)).is_err());
+}
+
+#[test]
+fn test_retry_delay_backs_off_and_caps() {
+    assert_eq!(retry_delay_secs(1), 30);
+    assert_eq!(retry_delay_secs(2), 60);
+    assert_eq!(retry_delay_secs(5), 480);
+    assert_eq!(retry_delay_secs(40), 6 * 3600);
+}
+
+#[test]
+fn test_digest_week_is_the_iso_week() {
+    use chrono::TimeZone;
+    let monday = chrono::Utc.with_ymd_and_hms(2026, 10, 12, 6, 0, 0).unwrap();
+    assert_eq!(digest_week(monday), "2026-W42");
+    assert_eq!(digest_week(monday + chrono::Duration::days(6)), "2026-W42");
+    assert_eq!(digest_week(monday + chrono::Duration::days(7)), "2026-W43");
+    // The first days of January can belong to the previous ISO year
+    assert_eq!(digest_week(chrono::Utc.with_ymd_and_hms(2027, 1, 1, 6, 0, 0).unwrap()), "2026-W53");
+}
+
+#[tokio::test]
+async fn test_memory_and_file_mailers() {
+    let memory = MemoryMailer::new();
+    memory.send(&message()).await.unwrap();
+    assert_eq!(memory.sent(), vec![message()]);
+
+    let dir = std::env::temp_dir().join(format!("karuteens-mail-{}", uuid::Uuid::new_v4().simple()));
+    let file = FileMailer { dir: dir.clone(), sender: sender() };
+    file.send(&message()).await.unwrap();
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+/// Plays the server side of an SMTP exchange and records what the client sent.
+async fn scripted_smtp_server(rcpt_reply: &'static str) -> (u16, tokio::task::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let (read, mut write) = socket.into_split();
+        let mut reader = BufReader::new(read);
+        let mut received = Vec::new();
+        write.write_all(b"220 mock ESMTP\r\n").await.unwrap();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end().to_string();
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    write.write_all(b"250 queued\r\n").await.unwrap();
+                }
+                received.push(line);
+                continue;
+            }
+            received.push(line.clone());
+            let reply: &[u8] = match line.split(' ').next().unwrap_or("") {
+                "EHLO" => b"250-mock\r\n250 AUTH PLAIN\r\n",
+                "AUTH" => b"235 ok\r\n",
+                "MAIL" => b"250 ok\r\n",
+                "RCPT" => rcpt_reply.as_bytes(),
+                "DATA" => {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                }
+                "QUIT" => {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => b"500 what\r\n",
+            };
+            write.write_all(reply).await.unwrap();
+        }
+        received
+    });
+    (port, handle)
+}
+
+fn smtp(port: u16) -> SmtpMailer {
+    SmtpMailer {
+        host: "127.0.0.1".to_string(),
+        port,
+        security: SmtpSecurity::None,
+        username: Some("user".to_string()),
+        password: Some("pass".to_string()),
+        sender: sender(),
+    }
+}
+
+#[tokio::test]
+async fn test_smtp_mailer_delivers_message() {
+    let (port, server) = scripted_smtp_server("250 ok\r\n").await;
+    smtp(port).send(&message()).await.unwrap();
+
+    let received = server.await.unwrap();
+    assert_eq!(received[0], "EHLO karuteens.site");
+    assert!(received[1].starts_with("AUTH PLAIN "));
+    assert_eq!(received[2], "MAIL FROM:<no-reply@karuteens.site>");
+    assert_eq!(received[3], "RCPT TO:<student@karu.ac.ke>");
+    assert_eq!(received[4], "DATA");
+    assert!(received.contains(&"To: Wanjiru <student@karu.ac.ke>".to_string()));
+    assert_eq!(received.last().unwrap(), "QUIT");
+}
+
+#[tokio::test]
+async fn test_smtp_reply_codes_map_to_error_kinds() {
+    let (port, _server) = scripted_smtp_server("450 mailbox busy\r\n").await;
+    assert!(matches!(smtp(port).send(&message()).await, Err(MailError::Transient(_))));
+
+    let (port, _server) = scripted_smtp_server("550 no such user\r\n").await;
+    assert!(matches!(smtp(port).send(&message()).await, Err(MailError::Permanent(_))));
+}
@@ -0,0 +1,92 @@
+// Revision material checkout: amount conversion and the uploader's share of a sale.
+
+use karuteens_backend::features::academic::revision_materials::{creator_share, pending_checkout_filter, same_payment};
+use karuteens_backend::features::monetization::payments::kes_for_cents;
+use karuteens_backend::models::{RevisionMaterialPurchase, Transaction};
+
+#[test]
+fn test_kes_for_cents_rounds_up_to_whole_shillings() {
+    assert_eq!(kes_for_cents(5000), 50);
+    assert_eq!(kes_for_cents(5001), 51);
+    assert_eq!(kes_for_cents(1), 1);
+    assert_eq!(kes_for_cents(0), 0);
+    assert_eq!(kes_for_cents(-300), 0);
+}
+
+#[test]
+fn test_creator_share_rounds_down() {
+    assert_eq!(creator_share(10000, 70), 7000);
+    assert_eq!(creator_share(999, 70), 699);
+    assert_eq!(creator_share(5000, 0), 0);
+    assert_eq!(creator_share(5000, 100), 5000);
+    // Out-of-range percentages are clamped rather than over- or negatively paying
+    assert_eq!(creator_share(5000, 150), 5000);
+    assert_eq!(creator_share(5000, -10), 0);
+}
+
+#[test]
+fn test_transactions_without_target_still_deserialize() {
+    let doc = bson::doc! {
+        "user_id": bson::oid::ObjectId::new(),
+        "phone_number": "254700000000",
+        "amount": 2000_i64,
+        "mpesa_receipt_number": null,
+        "checkout_request_id": "ws_CO_1",
+        "status": "completed",
+        "transaction_type": "verification",
+        "premium_duration": null,
+        "created_at": bson::DateTime::now(),
+        "updated_at": bson::DateTime::now(),
+    };
+    let tx: Transaction = bson::from_document(doc).unwrap();
+    assert!(tx.target_id.is_none());
+}
+
+#[test]
+fn test_a_recent_pending_checkout_blocks_another() {
+    let (user, material) = (bson::oid::ObjectId::new(), bson::oid::ObjectId::new());
+    let now = chrono::Utc::now();
+    let filter = pending_checkout_filter(user, material, now);
+    assert_eq!(filter.get_object_id("user_id").unwrap(), user);
+    assert_eq!(filter.get_object_id("target_id").unwrap(), material);
+    assert_eq!(filter.get_str("transaction_type").unwrap(), "material");
+    assert_eq!(filter.get_str("status").unwrap(), "pending");
+    // An abandoned prompt stops blocking once it can no longer be paid
+    let since = filter.get_document("created_at").unwrap().get_datetime("$gt").unwrap().to_chrono();
+    assert!(since < now && since > now - chrono::Duration::minutes(30));
+}
+
+#[test]
+fn test_only_the_paying_transaction_re_runs_a_purchase() {
+    let doc = bson::doc! {
+        "_id": bson::oid::ObjectId::new(),
+        "user_id": bson::oid::ObjectId::new(),
+        "phone_number": "254700000000",
+        "amount": 5000_i64,
+        "mpesa_receipt_number": "QK1",
+        "checkout_request_id": "ws_CO_2",
+        "status": "completed",
+        "transaction_type": "material",
+        "premium_duration": null,
+        "created_at": bson::DateTime::now(),
+        "updated_at": bson::DateTime::now(),
+    };
+    let tx: Transaction = bson::from_document(doc).unwrap();
+    assert!(tx.refund_due.is_none());
+    let mut purchase = RevisionMaterialPurchase {
+        id: None,
+        material_id: bson::oid::ObjectId::new(),
+        user_id: tx.user_id,
+        amount: 5000,
+        creator_amount: 3500,
+        transaction_id: tx.id,
+        purchased_at: bson::DateTime::now(),
+    };
+    assert!(same_payment(&purchase, &tx));
+
+    // Paid twice: the uploader is credited once and the second payment is flagged
+    purchase.transaction_id = Some(bson::oid::ObjectId::new());
+    assert!(!same_payment(&purchase, &tx));
+    purchase.transaction_id = None;
+    assert!(!same_payment(&purchase, &tx));
+}
@@ -0,0 +1,105 @@
+// Message edits: the window after sending in which a message can still be changed, which
+// messages can be edited at all, and the compare-and-set an edit is applied with.
+
+use bson::{doc, oid::ObjectId, DateTime};
+use chrono::{Duration, TimeZone, Utc};
+use karuteens_backend::features::social::messages::{edit_filter, is_editable, within_edit_window, EDIT_WINDOW_MINUTES, MAX_EDITS};
+use karuteens_backend::models::{Contact, Location, Message, Poll};
+
+fn message(sender: ObjectId, sent: chrono::DateTime<Utc>) -> Message {
+    bson::from_document(doc! {
+        "_id": ObjectId::new(),
+        "chat_id": ObjectId::new(),
+        "sender_id": sender,
+        "content": "see you at 8",
+        "encrypted_content": null,
+        "encryption_iv": null,
+        "attachment_url": null,
+        "attachment_type": null,
+        "reply_to_id": null,
+        "reactions": [],
+        "is_deleted": false,
+        "deleted_at": null,
+        "read_at": null,
+        "poll": null,
+        "location": null,
+        "contact": null,
+        "is_view_once": false,
+        "is_system": false,
+        "is_announcement": false,
+        "viewed_at": null,
+        "expires_at": null,
+        "created_at": DateTime::from_chrono(sent),
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_edit_window() {
+    let sent = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+    let created_at = DateTime::from_chrono(sent);
+
+    assert!(within_edit_window(created_at, sent));
+    assert!(within_edit_window(created_at, sent + Duration::minutes(EDIT_WINDOW_MINUTES)));
+    assert!(!within_edit_window(created_at, sent + Duration::minutes(EDIT_WINDOW_MINUTES) + Duration::seconds(1)));
+    assert!(!within_edit_window(created_at, sent + Duration::days(1)));
+}
+
+#[test]
+fn test_only_plain_messages_are_editable() {
+    let sent = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+    let plain = message(ObjectId::new(), sent);
+    assert!(is_editable(&plain));
+
+    let mut deleted = plain.clone();
+    deleted.is_deleted = true;
+    let mut system = plain.clone();
+    system.is_system = true;
+    let mut view_once = plain.clone();
+    view_once.is_view_once = true;
+    for msg in [deleted, system, view_once] {
+        assert!(!is_editable(&msg));
+    }
+
+    // Polls, locations and contacts are structured, not text
+    let mut poll = plain.clone();
+    poll.poll = Some(Poll { question: "Lunch?".to_string(), options: Vec::new(), is_multiple: false, is_closed: false, created_at: DateTime::now() });
+    let mut location = plain.clone();
+    location.location = Some(Location { latitude: -0.5, longitude: 37.4, label: None, is_live: None, expires_at: None });
+    let mut contact = plain.clone();
+    contact.contact = Some(Contact { username: "wanjiru".to_string(), full_name: None, avatar_url: None });
+    for msg in [poll, location, contact] {
+        assert!(!is_editable(&msg));
+    }
+}
+
+#[test]
+fn test_edit_filter_is_a_compare_and_set_on_what_was_read() {
+    let sent = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+    let sender = ObjectId::new();
+    let msg = message(sender, sent);
+    let now = sent + Duration::minutes(5);
+    let filter = edit_filter(&msg, sender, now);
+
+    assert_eq!(filter.get_object_id("_id").ok(), msg.id);
+    assert_eq!(filter.get_object_id("sender_id").unwrap(), sender);
+    assert!(!filter.get_bool("is_deleted").unwrap());
+    // A concurrent edit changes the body, so the second writer matches nothing
+    assert_eq!(filter.get_str("content").unwrap(), "see you at 8");
+    assert_eq!(filter.get("encrypted_content"), Some(&bson::Bson::Null));
+    assert_eq!(
+        filter.get_document("created_at").unwrap().get_datetime("$gte").unwrap().to_chrono(),
+        now - Duration::minutes(EDIT_WINDOW_MINUTES),
+    );
+}
+
+#[test]
+fn test_edit_filter_stops_at_max_edits() {
+    let sent = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+    let sender = ObjectId::new();
+    let filter = edit_filter(&message(sender, sent), sender, sent);
+
+    // The history may hold MAX_EDITS - 1 revisions before this edit adds the last one
+    let slot = format!("edit_history.{}", MAX_EDITS - 1);
+    assert_eq!(filter.get_document(&slot).unwrap(), &doc! { "$exists": false });
+}
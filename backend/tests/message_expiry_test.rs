@@ -0,0 +1,134 @@
+// Disappearing and view-once messages: expiry stamping, what the purge may claim, who may
+// open a view-once message, and which attachment URLs we are allowed to delete.
+
+use bson::{doc, oid::ObjectId, DateTime};
+use chrono::{Duration, TimeZone, Utc};
+use karuteens_backend::features::infrastructure::media::r2_key_for_url;
+use karuteens_backend::features::social::message_expiry::{
+    all_recipients_viewed, attachment_users_filter, expires_at_for, may_open_view_once, purge_filter, ExpiryReason,
+};
+use karuteens_backend::models::Message;
+
+fn view_once(sender: ObjectId, chat: ObjectId) -> Message {
+    bson::from_document(doc! {
+        "_id": ObjectId::new(),
+        "chat_id": chat,
+        "sender_id": sender,
+        "content": "",
+        "encrypted_content": null,
+        "encryption_iv": null,
+        "attachment_url": "https://media.example.test/uploads/1/photo.jpg",
+        "attachment_type": "image",
+        "reply_to_id": null,
+        "reactions": [],
+        "is_deleted": false,
+        "deleted_at": null,
+        "read_at": null,
+        "poll": null,
+        "location": null,
+        "contact": null,
+        "is_view_once": true,
+        "is_system": false,
+        "is_announcement": false,
+        "viewed_at": null,
+        "expires_at": null,
+        "created_at": DateTime::now(),
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_expires_at_follows_the_chat_setting() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+    assert_eq!(
+        expires_at_for(Some(86_400), now).map(|dt| dt.to_chrono()),
+        Some(now + Duration::days(1)),
+    );
+    assert_eq!(expires_at_for(None, now), None);
+    assert_eq!(expires_at_for(Some(0), now), None, "zero turns disappearing messages off");
+    assert_eq!(expires_at_for(Some(-60), now), None);
+    assert_eq!(expires_at_for(Some(1), now).map(|dt| dt.to_chrono()), Some(now + Duration::seconds(1)));
+    // A year-long timer still lands on the right instant, to the millisecond BSON keeps
+    assert_eq!(
+        expires_at_for(Some(365 * 86_400), now).map(|dt| dt.timestamp_millis()),
+        Some((now + Duration::days(365)).timestamp_millis()),
+    );
+}
+
+#[test]
+fn test_r2_key_for_url() {
+    let base = "https://media.example.test/";
+    assert_eq!(
+        r2_key_for_url(base, "https://media.example.test/uploads/1697630000/photo.jpg-a1b2c3"),
+        Some("uploads/1697630000/photo.jpg-a1b2c3".to_string()),
+    );
+    assert_eq!(
+        r2_key_for_url(base, "https://media.example.test/files/doc.pdf?v=2"),
+        Some("files/doc.pdf".to_string()),
+    );
+    assert_eq!(r2_key_for_url(base, "https://res.cloudinary.com/demo/image.jpg"), None, "not our bucket");
+    assert_eq!(r2_key_for_url(base, "https://media.example.test.evil.test/x.jpg"), None);
+    assert_eq!(r2_key_for_url(base, "https://media.example.test/uploads/../secret"), None);
+    assert_eq!(r2_key_for_url(base, "https://media.example.test/"), None);
+}
+
+#[test]
+fn test_purge_filters_only_claim_what_is_due() {
+    let id = ObjectId::new();
+
+    let expired = purge_filter(id, ExpiryReason::Expired);
+    assert_eq!(expired.get_object_id("_id").unwrap(), id);
+    assert!(expired.get_document("expires_at").unwrap().contains_key("$lte"), "only once the timer has run out");
+    assert!(!expired.get_bool("is_deleted").unwrap(), "already wiped messages aren't claimed twice");
+
+    let viewed = purge_filter(id, ExpiryReason::Viewed);
+    assert!(viewed.get_bool("is_view_once").unwrap(), "an ordinary message is never wiped for being read");
+    assert_eq!(viewed.get_document("viewed_at").unwrap(), &doc! { "$ne": null }, "only once everyone has opened it");
+    assert_eq!(viewed.get_array("$or").unwrap().len(), 3, "something must be left to wipe");
+    assert!(!viewed.contains_key("expires_at"));
+}
+
+#[test]
+fn test_only_recipients_may_open_view_once() {
+    let (sender, reader, outsider) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+    let participants = [sender, reader];
+    let mut msg = view_once(sender, ObjectId::new());
+
+    assert!(may_open_view_once(&msg, &participants, reader));
+    assert!(!may_open_view_once(&msg, &participants, outsider), "knowing the id isn't enough");
+    assert!(!may_open_view_once(&msg, &participants, sender), "the sender's own view doesn't count");
+
+    msg.viewed_by.push(reader);
+    assert!(!may_open_view_once(&msg, &participants, reader), "once per recipient");
+
+    let mut ordinary = view_once(sender, ObjectId::new());
+    ordinary.is_view_once = false;
+    assert!(!may_open_view_once(&ordinary, &participants, reader));
+
+    let mut gone = view_once(sender, ObjectId::new());
+    gone.viewed_at = Some(DateTime::now());
+    assert!(!may_open_view_once(&gone, &participants, reader));
+}
+
+#[test]
+fn test_group_view_once_waits_for_every_recipient() {
+    let (sender, a, b) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+    let group = [sender, a, b];
+
+    assert!(!all_recipients_viewed(sender, &group, &[]));
+    assert!(!all_recipients_viewed(sender, &group, &[a]), "b hasn't seen it yet");
+    assert!(all_recipients_viewed(sender, &group, &[b, a]));
+    assert!(all_recipients_viewed(sender, &[sender, a], &[a]), "a DM is done after one view");
+    // Someone who has since left the chat isn't waited for
+    assert!(all_recipients_viewed(sender, &[sender, a], &[a, ObjectId::new()]));
+}
+
+#[test]
+fn test_attachment_kept_while_a_forwarded_copy_uses_it() {
+    let id = ObjectId::new();
+    let url = "https://media.example.test/uploads/1/photo.jpg";
+    let filter = attachment_users_filter(url, id);
+    assert_eq!(filter.get_str("attachment_url").unwrap(), url);
+    assert_eq!(filter.get_document("_id").unwrap(), &doc! { "$ne": id }, "the message being purged doesn't count");
+    assert_eq!(filter.get_document("is_deleted").unwrap(), &doc! { "$ne": true }, "deleted copies don't keep it alive");
+}
@@ -0,0 +1,221 @@
+// Payment providers: the deterministic mock's scenarios and the Daraja implementation's
+// callback and status parsing.
+
+use karuteens_backend::features::monetization::daraja::{stk_query_status, DarajaProvider, StkQueryResponse};
+use karuteens_backend::features::monetization::ledger::{reversal_lines, validate_lines};
+use karuteens_backend::features::monetization::mock_provider::{MockProvider, MockScenario};
+use karuteens_backend::features::monetization::provider::{
+    CallbackEvent, PaymentError, PaymentProvider, PaymentRequest, PaymentStatus, RefundRequest, RefundStatus,
+};
+use karuteens_backend::models::{LedgerLine, Transaction};
+
+fn request(phone: &str, amount_kes: i64) -> PaymentRequest {
+    PaymentRequest {
+        phone: phone.to_string(),
+        amount_kes,
+        description: "Account Verification".to_string(),
+        callback_url: "https://example.test/api/payments/callback".to_string(),
+    }
+}
+
+fn payment(event: CallbackEvent) -> (String, PaymentStatus) {
+    match event {
+        CallbackEvent::Payment(result) => (result.reference, result.status),
+        other => panic!("expected a payment result, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_mock_scenario_for_phone() {
+    assert_eq!(MockScenario::for_phone("254712340000"), MockScenario::Failure);
+    assert_eq!(MockScenario::for_phone("254712341111"), MockScenario::Timeout);
+    assert_eq!(MockScenario::for_phone("254712342222"), MockScenario::DuplicateCallback);
+    assert_eq!(MockScenario::for_phone("254712345678"), MockScenario::Success);
+}
+
+#[tokio::test]
+async fn test_mock_success_and_failure() {
+    let mock = MockProvider::new();
+
+    let paid = mock.initiate(&request("254712345678", 20)).await.unwrap();
+    let failed = mock.initiate(&request("254712340000", 20)).await.unwrap();
+    assert_ne!(paid, failed);
+
+    let callbacks = mock.take_callbacks();
+    assert_eq!(callbacks.len(), 2);
+    let (reference, status) = payment(mock.parse_callback(&callbacks[0]).unwrap());
+    assert_eq!(reference, paid);
+    assert!(matches!(status, PaymentStatus::Completed { receipt: Some(_) }));
+    match mock.parse_callback(&callbacks[0]).unwrap() {
+        CallbackEvent::Payment(result) => assert_eq!(result.amount, Some(2_000)),
+        _ => unreachable!(),
+    }
+
+    let (reference, status) = payment(mock.parse_callback(&callbacks[1]).unwrap());
+    assert_eq!(reference, failed);
+    assert_eq!(status, PaymentStatus::Failed { reason: "Request cancelled by user".to_string() });
+
+    assert!(matches!(mock.query_status(&paid).await.unwrap(), PaymentStatus::Completed { .. }));
+    assert!(matches!(mock.query_status(&failed).await.unwrap(), PaymentStatus::Failed { .. }));
+    assert!(mock.query_status("mock_ws_CO_999999").await.is_err());
+}
+
+#[tokio::test]
+async fn test_mock_timeout_and_duplicate_callbacks() {
+    let mock = MockProvider::new();
+
+    let stuck = mock.initiate(&request("254712341111", 20)).await.unwrap();
+    assert!(mock.take_callbacks().is_empty(), "a timed-out prompt never calls back");
+    assert_eq!(mock.query_status(&stuck).await.unwrap(), PaymentStatus::Pending);
+
+    let twice = mock.initiate(&request("254712342222", 20)).await.unwrap();
+    let callbacks = mock.take_callbacks();
+    assert_eq!(callbacks.len(), 2);
+    assert_eq!(callbacks[0], callbacks[1]);
+    assert_eq!(payment(mock.parse_callback(&callbacks[1]).unwrap()).0, twice);
+    assert!(mock.take_callbacks().is_empty());
+}
+
+#[tokio::test]
+async fn test_mock_rejects_bad_requests_and_foreign_callbacks() {
+    let mock = MockProvider::new();
+    assert!(matches!(mock.initiate(&request("254712345678", 0)).await, Err(PaymentError::Rejected(_))));
+
+    let daraja_shaped = serde_json::json!({ "Body": { "stkCallback": {} } });
+    assert!(matches!(mock.parse_callback(&daraja_shaped), Err(PaymentError::InvalidCallback(_))));
+}
+
+#[tokio::test]
+async fn test_mock_refund() {
+    let mock = MockProvider::new();
+    let paid = mock.initiate(&request("254712345678", 50)).await.unwrap();
+
+    let refund = RefundRequest {
+        reference: paid.clone(),
+        receipt: None,
+        phone: "254712345678".to_string(),
+        amount_kes: 50,
+        reason: "Duplicate purchase".to_string(),
+        callback_url: "https://example.test/api/payments/callback".to_string(),
+    };
+    assert!(matches!(mock.refund(&refund).await.unwrap(), RefundStatus::Completed { .. }));
+    assert_eq!(mock.refunds(), vec![refund.clone()]);
+
+    let unknown = RefundRequest { reference: "mock_ws_CO_999999".to_string(), ..refund };
+    assert!(mock.refund(&unknown).await.is_err());
+}
+
+fn daraja() -> DarajaProvider {
+    DarajaProvider::new(reqwest::Client::new(), Default::default())
+}
+
+#[test]
+fn test_daraja_parses_stk_callbacks() {
+    let provider = daraja();
+    let paid = serde_json::json!({
+        "Body": { "stkCallback": {
+            "MerchantRequestID": "29115-34620561-1",
+            "CheckoutRequestID": "ws_CO_191220191020363925",
+            "ResultCode": 0,
+            "ResultDesc": "The service request is processed successfully.",
+            "CallbackMetadata": { "Item": [
+                { "Name": "Amount", "Value": 20.00 },
+                { "Name": "MpesaReceiptNumber", "Value": "NLJ7RT61SV" },
+                { "Name": "PhoneNumber", "Value": 254708374149u64 }
+            ]}
+        }}
+    });
+    match provider.parse_callback(&paid).unwrap() {
+        CallbackEvent::Payment(result) => {
+            assert_eq!(result.reference, "ws_CO_191220191020363925");
+            assert_eq!(result.status, PaymentStatus::Completed { receipt: Some("NLJ7RT61SV".to_string()) });
+            assert_eq!(result.amount, Some(2_000));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let cancelled = serde_json::json!({
+        "Body": { "stkCallback": {
+            "MerchantRequestID": "29115-34620561-1",
+            "CheckoutRequestID": "ws_CO_191220191020363926",
+            "ResultCode": 1032,
+            "ResultDesc": "Request cancelled by user"
+        }}
+    });
+    let (_, status) = payment(provider.parse_callback(&cancelled).unwrap());
+    assert_eq!(status, PaymentStatus::Failed { reason: "Request cancelled by user".to_string() });
+
+    let reversal = serde_json::json!({
+        "Result": {
+            "ResultType": 0,
+            "ResultCode": 0,
+            "ResultDesc": "The service request is processed successfully.",
+            "ConversationID": "AG_20261018_reversal",
+            "TransactionID": "NLJ7RT61SW"
+        }
+    });
+    assert_eq!(
+        provider.parse_callback(&reversal).unwrap(),
+        CallbackEvent::Refund { reference: "AG_20261018_reversal".to_string(), succeeded: true, reason: "The service request is processed successfully.".to_string() },
+    );
+
+    let mock_shaped = serde_json::json!({ "provider": "mock", "reference": "mock_ws_CO_000001" });
+    assert!(matches!(provider.parse_callback(&mock_shaped), Err(PaymentError::InvalidCallback(_))));
+}
+
+fn query(response_code: &str, result_code: Option<&str>, result_desc: Option<&str>) -> StkQueryResponse {
+    StkQueryResponse {
+        response_code: response_code.to_string(),
+        response_description: "The service request has been accepted successsfully".to_string(),
+        merchant_request_id: "22205-34066-1".to_string(),
+        checkout_request_id: "ws_CO_13012021093521236557".to_string(),
+        result_code: result_code.map(|s| s.to_string()),
+        result_desc: result_desc.map(|s| s.to_string()),
+    }
+}
+
+#[test]
+fn test_daraja_stk_query_status() {
+    assert_eq!(stk_query_status(query("0", Some("0"), Some("Success"))), PaymentStatus::Completed { receipt: None });
+    assert_eq!(stk_query_status(query("0", Some("4999"), None)), PaymentStatus::Pending);
+    assert_eq!(stk_query_status(query("0", None, None)), PaymentStatus::Pending);
+    assert_eq!(
+        stk_query_status(query("0", Some("1037"), Some("DS timeout user cannot be reached"))),
+        PaymentStatus::Failed { reason: "DS timeout user cannot be reached".to_string() },
+    );
+    assert!(matches!(stk_query_status(query("1", None, None)), PaymentStatus::Failed { .. }));
+}
+
+#[test]
+fn test_refund_reverses_the_payment_journal() {
+    let lines = vec![
+        LedgerLine { account: "platform:mpesa".to_string(), amount: -10_000 },
+        LedgerLine { account: "user:abc:earnings".to_string(), amount: 7_000 },
+        LedgerLine { account: "platform:revenue".to_string(), amount: 3_000 },
+    ];
+    let reversed = reversal_lines(&lines);
+    assert!(validate_lines(&reversed).is_ok());
+    for (original, undo) in lines.iter().zip(&reversed) {
+        assert_eq!(original.account, undo.account);
+        assert_eq!(original.amount + undo.amount, 0);
+    }
+}
+
+#[test]
+fn test_transactions_without_a_provider_are_mpesa() {
+    let doc = bson::doc! {
+        "user_id": bson::oid::ObjectId::new(),
+        "phone_number": "254712345678",
+        "amount": 2_000i64,
+        "mpesa_receipt_number": null,
+        "checkout_request_id": "ws_CO_1",
+        "status": "completed",
+        "transaction_type": "verification",
+        "premium_duration": null,
+        "created_at": bson::DateTime::now(),
+        "updated_at": bson::DateTime::now(),
+    };
+    let tx: Transaction = bson::from_document(doc).unwrap();
+    assert_eq!(tx.provider, "mpesa");
+    assert_eq!(tx.refund_reference, None);
+}
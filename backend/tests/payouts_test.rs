@@ -0,0 +1,211 @@
+// Creator payouts: withdrawal rules, phone normalisation and the B2C round trip against a
+// local mock of Daraja's OAuth and B2C endpoints.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use std::sync::{Arc, Mutex};
+use karuteens_backend::features::monetization::daraja::{request_mpesa_token, send_b2c, B2cRequest};
+use karuteens_backend::features::monetization::mock_provider::MockProvider;
+use karuteens_backend::features::monetization::payouts::{normalize_phone, validate_withdrawal, B2cResultCallback, PayoutOutcome};
+use karuteens_backend::features::monetization::provider::{PaymentError, PaymentProvider, PayoutRequest};
+
+#[derive(Clone, Default)]
+struct MockDaraja {
+    b2c_requests: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+async fn mock_oauth(headers: HeaderMap) -> (StatusCode, Json<serde_json::Value>) {
+    // "key:secret" in base64
+    if headers.get("authorization").and_then(|h| h.to_str().ok()) != Some("Basic a2V5OnNlY3JldA==") {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "errorMessage": "Invalid credentials" })));
+    }
+    (StatusCode::OK, Json(serde_json::json!({ "access_token": "mock-token", "expires_in": "3599" })))
+}
+
+async fn mock_b2c(State(mock): State<MockDaraja>, headers: HeaderMap, Json(body): Json<serde_json::Value>) -> (StatusCode, Json<serde_json::Value>) {
+    if headers.get("authorization").and_then(|h| h.to_str().ok()) != Some("Bearer mock-token") {
+        return (StatusCode::UNAUTHORIZED, Json(serde_json::json!({ "errorMessage": "Invalid Access Token" })));
+    }
+    mock.b2c_requests.lock().unwrap().push(body.clone());
+    if body["Amount"] == 777 {
+        // A gateway timeout after the request got in: it may still be paid
+        return (StatusCode::GATEWAY_TIMEOUT, Json(serde_json::json!(null)));
+    }
+    if body["Amount"].as_i64().unwrap_or(0) > 150_000 {
+        return (StatusCode::OK, Json(serde_json::json!({
+            "ConversationID": "",
+            "OriginatorConversationID": body["OriginatorConversationID"],
+            "ResponseCode": "2001",
+            "ResponseDescription": "The initiator information is invalid.",
+        })));
+    }
+    (StatusCode::OK, Json(serde_json::json!({
+        "ConversationID": "AG_20261018_mock",
+        "OriginatorConversationID": body["OriginatorConversationID"],
+        "ResponseCode": "0",
+        "ResponseDescription": "Accept the service request successfully.",
+    })))
+}
+
+async fn spawn_mock_daraja() -> (String, MockDaraja) {
+    let mock = MockDaraja::default();
+    let app = Router::new()
+        .route("/oauth/v1/generate", get(mock_oauth))
+        .route("/mpesa/b2c/v1/paymentrequest", post(mock_b2c))
+        .with_state(mock.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}", addr), mock)
+}
+
+fn request(amount: i64) -> B2cRequest {
+    B2cRequest {
+        originator_conversation_id: "6712f0c1a2b3c4d5e6f70819".to_string(),
+        initiator_name: "testapi".to_string(),
+        security_credential: "credential".to_string(),
+        command_id: "BusinessPayment".to_string(),
+        amount,
+        party_a: "600000".to_string(),
+        party_b: "254712345678".to_string(),
+        remarks: "KaruTeens creator payout".to_string(),
+        queue_timeout_url: "https://example.test/api/payments/b2c/timeout".to_string(),
+        result_url: "https://example.test/api/payments/b2c/result".to_string(),
+        occasion: "Payout".to_string(),
+    }
+}
+
+#[test]
+fn test_validate_withdrawal() {
+    let min = 10_000;
+    assert!(validate_withdrawal(20_000, 50_000, min, true).is_ok());
+    assert!(validate_withdrawal(50_000, 50_000, min, true).is_ok());
+    assert!(validate_withdrawal(20_000, 50_000, min, false).is_err(), "unverified users can't withdraw");
+    assert!(validate_withdrawal(5_000, 50_000, min, true).is_err(), "below the minimum");
+    assert!(validate_withdrawal(60_000, 50_000, min, true).is_err(), "more than available");
+    assert!(validate_withdrawal(20_050, 50_000, min, true).is_err(), "B2C only pays whole shillings");
+}
+
+#[test]
+fn test_normalize_phone() {
+    assert_eq!(normalize_phone("0712345678").as_deref(), Some("254712345678"));
+    assert_eq!(normalize_phone("+254 712 345 678").as_deref(), Some("254712345678"));
+    assert_eq!(normalize_phone("254112345678").as_deref(), Some("254112345678"));
+    assert_eq!(normalize_phone("0112-345-678").as_deref(), Some("254112345678"));
+    assert_eq!(normalize_phone("071234567"), None);
+    assert_eq!(normalize_phone("0812345678"), None);
+    assert_eq!(normalize_phone("07123x5678"), None);
+}
+
+#[tokio::test]
+async fn test_b2c_round_trip_against_mock_daraja() {
+    let (base, mock) = spawn_mock_daraja().await;
+    let client = reqwest::Client::new();
+
+    assert!(request_mpesa_token(&client, &base, "key", "wrong").await.is_err());
+    let token = request_mpesa_token(&client, &base, "key", "secret").await.unwrap();
+    assert_eq!(token, "mock-token");
+
+    let accepted = send_b2c(&client, &base, &token, &request(500)).await.unwrap();
+    assert_eq!(accepted.conversation_id, "AG_20261018_mock");
+    assert_eq!(accepted.originator_conversation_id, "6712f0c1a2b3c4d5e6f70819");
+
+    let sent = mock.b2c_requests.lock().unwrap()[0].clone();
+    assert_eq!(sent["Amount"], 500);
+    assert_eq!(sent["PartyB"], "254712345678");
+    assert_eq!(sent["CommandID"], "BusinessPayment");
+    assert_eq!(sent["ResultURL"], "https://example.test/api/payments/b2c/result");
+}
+
+#[tokio::test]
+async fn test_b2c_rejections_surface_daraja_errors() {
+    let (base, _mock) = spawn_mock_daraja().await;
+    let client = reqwest::Client::new();
+
+    let rejected = send_b2c(&client, &base, "mock-token", &request(200_000)).await;
+    assert_eq!(rejected, Err(PaymentError::Rejected("The initiator information is invalid.".to_string())));
+
+    let unauthorized = send_b2c(&client, &base, "stale-token", &request(500)).await;
+    assert_eq!(unauthorized, Err(PaymentError::Rejected("Invalid Access Token".to_string())));
+}
+
+#[tokio::test]
+async fn test_b2c_ambiguous_failures_are_unknown() {
+    let (base, _mock) = spawn_mock_daraja().await;
+    let client = reqwest::Client::new();
+
+    let timed_out = send_b2c(&client, &base, "mock-token", &request(777)).await;
+    assert!(matches!(timed_out, Err(PaymentError::Indeterminate(_))), "{:?}", timed_out);
+
+    // Nothing listening: the request never left, so it is safe to release the hold
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let closed = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let refused = send_b2c(&client, &closed, "mock-token", &request(500)).await;
+    assert!(matches!(refused, Err(PaymentError::Unavailable(_))), "{:?}", refused);
+}
+
+#[test]
+fn test_b2c_result_outcomes() {
+    let paid: B2cResultCallback = serde_json::from_value(serde_json::json!({
+        "Result": {
+            "ResultType": 0,
+            "ResultCode": 0,
+            "ResultDesc": "The service request is processed successfully.",
+            "OriginatorConversationID": "6712f0c1a2b3c4d5e6f70819",
+            "ConversationID": "AG_20261018_mock",
+            "TransactionID": "SJI7RT61SV",
+            "ResultParameters": { "ResultParameter": [{ "Key": "TransactionAmount", "Value": 500 }] },
+            "ReferenceData": { "ReferenceItem": { "Key": "QueueTimeoutURL", "Value": "https://example.test" } }
+        }
+    }))
+    .unwrap();
+    assert_eq!(paid.result.outcome(), PayoutOutcome::Paid { receipt: Some("SJI7RT61SV".to_string()) });
+
+    let failed: B2cResultCallback = serde_json::from_value(serde_json::json!({
+        "Result": {
+            "ResultType": 0,
+            "ResultCode": 2001,
+            "ResultDesc": "The initiator information is invalid.",
+            "OriginatorConversationID": "6712f0c1a2b3c4d5e6f70819",
+            "ConversationID": "AG_20261018_mock",
+            "TransactionID": "SJI7RT61SW"
+        }
+    }))
+    .unwrap();
+    assert_eq!(failed.result.outcome(), PayoutOutcome::Failed { reason: "The initiator information is invalid.".to_string() });
+}
+
+fn payout(phone: &str, amount_kes: i64) -> PayoutRequest {
+    PayoutRequest {
+        reference: "6712f0c1a2b3c4d5e6f70819".to_string(),
+        phone: phone.to_string(),
+        amount_kes,
+        remarks: "KaruTeens creator payout".to_string(),
+        result_url: "https://example.test/api/payments/b2c/result".to_string(),
+        timeout_url: "https://example.test/api/payments/b2c/timeout".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_mock_provider_payouts() {
+    let mock = MockProvider::new();
+    assert_eq!(mock.send_b2c(&payout("254712345678", 0)).await, Err(PaymentError::Rejected("Invalid Amount".to_string())));
+
+    // Results come back in Daraja's shape, so the result URL handles both providers
+    let conversation_id = mock.send_b2c(&payout("254712345678", 500)).await.unwrap();
+    mock.send_b2c(&payout("254712340000", 500)).await.unwrap();
+    mock.send_b2c(&payout("254712341111", 500)).await.unwrap();
+    let results: Vec<B2cResultCallback> = mock.take_callbacks().into_iter().map(|c| serde_json::from_value(c).unwrap()).collect();
+    assert_eq!(results.len(), 2, "the 1111 payout never answers");
+
+    assert_eq!(results[0].result.conversation_id, conversation_id);
+    assert_eq!(results[0].result.originator_conversation_id, "6712f0c1a2b3c4d5e6f70819");
+    assert!(matches!(results[0].result.outcome(), PayoutOutcome::Paid { receipt: Some(_) }));
+    assert_eq!(results[1].result.outcome(), PayoutOutcome::Failed { reason: "The initiator information is invalid.".to_string() });
+    assert_eq!(mock.payouts().len(), 3);
+}
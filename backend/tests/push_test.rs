@@ -0,0 +1,209 @@
+// Web Push tests: VAPID signing, aes128gcm encryption and delivery against a mock push service
+// Run with: cargo test --test push_test
+
+use aes_gcm::{aead::{Aead, KeyInit}, Aes128Gcm, Nonce};
+use axum::{body::Bytes, extract::State, http::{HeaderMap, StatusCode}, routing::post, Router};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use bson::oid::ObjectId;
+use hkdf::Hkdf;
+use karuteens_backend::features::infrastructure::push::{
+    deliver, encrypt_payload, DeliveryOutcome, PushNotificationPayload, VapidConfig,
+};
+use karuteens_backend::models::{NotificationSettings, PushSubscription};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use sha2::Sha256;
+use std::sync::{Arc, Mutex};
+
+struct UserAgentKeys {
+    secret: SecretKey,
+    p256dh: String,
+    auth: Vec<u8>,
+}
+
+fn user_agent_keys() -> UserAgentKeys {
+    let secret = SecretKey::random(&mut rand::rngs::OsRng);
+    let p256dh = URL_SAFE_NO_PAD.encode(secret.public_key().to_encoded_point(false).as_bytes());
+    UserAgentKeys { secret, p256dh, auth: vec![7u8; 16] }
+}
+
+fn test_vapid() -> VapidConfig {
+    let secret = SecretKey::random(&mut rand::rngs::OsRng);
+    VapidConfig::from_private_key(&URL_SAFE_NO_PAD.encode(secret.to_bytes()), "mailto:test@karuteens.site").unwrap()
+}
+
+/// Receiver side of RFC 8291, as a browser would run it.
+fn decrypt(keys: &UserAgentKeys, body: &[u8]) -> Vec<u8> {
+    let salt = &body[..16];
+    let id_len = body[20] as usize;
+    let as_public_bytes = &body[21..21 + id_len];
+    let ciphertext = &body[21 + id_len..];
+
+    let as_public = PublicKey::from_sec1_bytes(as_public_bytes).unwrap();
+    let shared = p256::ecdh::diffie_hellman(keys.secret.to_nonzero_scalar(), as_public.as_affine());
+    let ua_public = keys.secret.public_key().to_encoded_point(false);
+
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(ua_public.as_bytes());
+    key_info.extend_from_slice(as_public_bytes);
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&keys.auth), shared.raw_secret_bytes()).expand(&key_info, &mut ikm).unwrap();
+
+    let prk = Hkdf::<Sha256>::new(Some(salt), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek).unwrap();
+    prk.expand(b"Content-Encoding: nonce\0", &mut nonce).unwrap();
+
+    let mut plain = Aes128Gcm::new_from_slice(&cek).unwrap().decrypt(Nonce::from_slice(&nonce), ciphertext).unwrap();
+    assert_eq!(plain.pop(), Some(0x02));
+    plain
+}
+
+fn sample_payload() -> PushNotificationPayload {
+    PushNotificationPayload {
+        title: "New Like".to_string(),
+        body: "alice liked your post".to_string(),
+        icon: None,
+        badge: None,
+        tag: Some("like-123".to_string()),
+        data: Some(serde_json::json!({ "type": "like" })),
+        actions: None,
+    }
+}
+
+#[test]
+fn test_encrypt_payload_round_trip() {
+    let keys = user_agent_keys();
+    let body = encrypt_payload(&keys.p256dh, &URL_SAFE_NO_PAD.encode(&keys.auth), b"hello karuteens").unwrap();
+
+    assert_eq!(u32::from_be_bytes([body[16], body[17], body[18], body[19]]), 4096);
+    assert_eq!(body[20], 65);
+    assert_eq!(decrypt(&keys, &body), b"hello karuteens");
+}
+
+#[test]
+fn test_encrypt_payload_rejects_bad_keys() {
+    let keys = user_agent_keys();
+    assert!(encrypt_payload(&keys.p256dh, &URL_SAFE_NO_PAD.encode([1u8; 8]), b"x").is_err());
+    assert!(encrypt_payload("not-a-key", &URL_SAFE_NO_PAD.encode(&keys.auth), b"x").is_err());
+}
+
+#[test]
+fn test_vapid_authorization_header() {
+    let vapid = test_vapid();
+    let header = vapid.authorization_header("https://fcm.googleapis.com/fcm/send/abc123").unwrap();
+
+    let (token, key) = header
+        .strip_prefix("vapid t=")
+        .and_then(|rest| rest.split_once(", k="))
+        .unwrap();
+    assert_eq!(key, vapid.public_key_base64());
+
+    let parts: Vec<&str> = token.split('.').collect();
+    assert_eq!(parts.len(), 3);
+    let claims: serde_json::Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+    assert_eq!(claims["aud"], "https://fcm.googleapis.com");
+    assert_eq!(claims["sub"], "mailto:test@karuteens.site");
+
+    let verifying_key = VerifyingKey::from_sec1_bytes(&URL_SAFE_NO_PAD.decode(key).unwrap()).unwrap();
+    let signature = Signature::from_slice(&URL_SAFE_NO_PAD.decode(parts[2]).unwrap()).unwrap();
+    assert!(verifying_key.verify(format!("{}.{}", parts[0], parts[1]).as_bytes(), &signature).is_ok());
+}
+
+#[test]
+fn test_notification_settings_gate_push_kinds() {
+    let settings = NotificationSettings {
+        messages: true,
+        likes: false,
+        comments: true,
+        follows: false,
+        mentions: true,
+        class_reminders: true,
+        template_updates: false,
+        ad_promotions: false,
+        email_digest: false,
+    };
+
+    assert!(!settings.allows("like"));
+    assert!(!settings.allows("follow"));
+    assert!(settings.allows("comment"));
+    assert!(settings.allows("system"));
+}
+
+#[derive(Clone, Default)]
+struct MockPushService {
+    received: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+}
+
+async fn mock_endpoint(State(svc): State<MockPushService>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    svc.received.lock().unwrap().push((headers, body));
+    StatusCode::CREATED
+}
+
+async fn spawn_mock_push_service() -> (String, MockPushService) {
+    let svc = MockPushService::default();
+    let app = Router::new()
+        .route("/push/live", post(mock_endpoint))
+        .route("/push/expired", post(|| async { StatusCode::GONE }))
+        .route("/push/broken", post(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+        .with_state(svc.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{}", addr), svc)
+}
+
+fn subscription(endpoint: String, keys: &UserAgentKeys) -> PushSubscription {
+    PushSubscription {
+        id: Some(ObjectId::new()),
+        user_id: ObjectId::new(),
+        endpoint,
+        p256dh: keys.p256dh.clone(),
+        auth: URL_SAFE_NO_PAD.encode(&keys.auth),
+        device_id: None,
+        user_agent: None,
+        created_at: bson::DateTime::now(),
+        last_success_at: None,
+    }
+}
+
+#[tokio::test]
+async fn test_deliver_to_mock_push_service() {
+    let (base, svc) = spawn_mock_push_service().await;
+    let client = reqwest::Client::new();
+    let vapid = test_vapid();
+    let keys = user_agent_keys();
+
+    let outcome = deliver(&client, &vapid, &subscription(format!("{}/push/live", base), &keys), &sample_payload(), 60).await;
+    assert_eq!(outcome, DeliveryOutcome::Delivered);
+
+    let received = svc.received.lock().unwrap();
+    let (headers, body) = &received[0];
+    assert_eq!(headers["content-encoding"], "aes128gcm");
+    assert_eq!(headers["ttl"], "60");
+    assert!(headers["authorization"].to_str().unwrap().starts_with("vapid t="));
+
+    let decoded: serde_json::Value = serde_json::from_slice(&decrypt(&keys, body)).unwrap();
+    assert_eq!(decoded["title"], "New Like");
+    assert_eq!(decoded["data"]["type"], "like");
+}
+
+#[tokio::test]
+async fn test_deliver_reports_expired_and_failed_subscriptions() {
+    let (base, _svc) = spawn_mock_push_service().await;
+    let client = reqwest::Client::new();
+    let vapid = test_vapid();
+    let keys = user_agent_keys();
+
+    let gone = deliver(&client, &vapid, &subscription(format!("{}/push/expired", base), &keys), &sample_payload(), 60).await;
+    assert_eq!(gone, DeliveryOutcome::Gone);
+
+    let missing = deliver(&client, &vapid, &subscription(format!("{}/push/unknown", base), &keys), &sample_payload(), 60).await;
+    assert_eq!(missing, DeliveryOutcome::Gone);
+
+    let failed = deliver(&client, &vapid, &subscription(format!("{}/push/broken", base), &keys), &sample_payload(), 60).await;
+    assert!(matches!(failed, DeliveryOutcome::Failed(_)));
+}
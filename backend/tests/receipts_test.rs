@@ -0,0 +1,117 @@
+// Read cursors: what a send and a read do to the counters, and how a message's receipt is
+// worked out.
+
+use std::collections::{HashMap, HashSet};
+use bson::{doc, oid::ObjectId, DateTime};
+use karuteens_backend::features::social::receipts::{message_status, read_update, sent_update, ReceiptStatus};
+use karuteens_backend::models::ChatCursor;
+
+fn cursor(delivered: Option<ObjectId>, read: Option<ObjectId>, read_at_ms: i64) -> ChatCursor {
+    ChatCursor {
+        unread_count: 0,
+        last_delivered_id: delivered,
+        delivered_at: delivered.map(|_| DateTime::from_millis(read_at_ms)),
+        last_read_id: read,
+        read_at: read.map(|_| DateTime::from_millis(read_at_ms)),
+    }
+}
+
+#[test]
+fn test_sent_update_bumps_everyone_but_the_sender() {
+    let (sender, a, b) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+    let msg = ObjectId::new();
+    let now = DateTime::from_millis(1_000);
+    let update = sent_update(sender, &[sender, a, b], msg, now);
+
+    let inc = update.get_document("$inc").unwrap();
+    assert_eq!(inc.len(), 2);
+    assert_eq!(inc.get_i64(format!("cursors.{}.unread_count", a.to_hex())).unwrap(), 1);
+    assert_eq!(inc.get_i64(format!("cursors.{}.unread_count", b.to_hex())).unwrap(), 1);
+
+    let s = sender.to_hex();
+    let max = update.get_document("$max").unwrap();
+    assert_eq!(max.get_object_id(format!("cursors.{}.last_read_id", s)).unwrap(), msg);
+    let set = update.get_document("$set").unwrap();
+    assert_eq!(set.get_i64(format!("cursors.{}.unread_count", s)).unwrap(), 0);
+
+    // Talking to yourself has no one to bump
+    let solo = sent_update(sender, &[sender], msg, now);
+    assert!(!solo.contains_key("$inc"));
+    assert_eq!(solo, doc! { "$max": max.clone(), "$set": set.clone() });
+}
+
+#[test]
+fn test_read_update_adjusts_the_counter_instead_of_overwriting_it() {
+    let (reader, up_to) = (ObjectId::new(), ObjectId::new());
+    let now = DateTime::from_millis(1_000);
+    let r = reader.to_hex();
+    let update = read_update(reader, up_to, 3, now);
+
+    // One pipeline update adjusts the counter in place, so a concurrent send's $inc isn't lost
+    assert_eq!(update.len(), 1);
+    let set = update[0].get_document("$set").unwrap();
+    assert_eq!(set.get_object_id(format!("cursors.{}.last_read_id", r)).unwrap(), up_to);
+    assert_eq!(set.get_datetime(format!("cursors.{}.read_at", r)).unwrap(), &now);
+    assert_eq!(
+        set.get_document(format!("cursors.{}.unread_count", r)).unwrap(),
+        &doc! { "$max": [0_i64, { "$subtract": [{ "$ifNull": [format!("$cursors.{}.unread_count", r), 0_i64] }, 3_i64] }] },
+    );
+}
+
+#[test]
+fn test_dm_status_follows_the_other_cursor() {
+    let (me, other) = (ObjectId::new(), ObjectId::new());
+    let earlier = ObjectId::new();
+    let msg = ObjectId::new();
+    let later = ObjectId::new();
+    let participants = [me, other];
+    let none = HashSet::new();
+
+    let mut cursors = HashMap::new();
+    assert_eq!(message_status(msg, me, &participants, &cursors, &none), (ReceiptStatus::Sent, None));
+
+    cursors.insert(other.to_hex(), cursor(Some(earlier), None, 0));
+    assert_eq!(message_status(msg, me, &participants, &cursors, &none).0, ReceiptStatus::Sent);
+
+    cursors.insert(other.to_hex(), cursor(Some(msg), Some(earlier), 0));
+    assert_eq!(message_status(msg, me, &participants, &cursors, &none), (ReceiptStatus::Delivered, None));
+
+    cursors.insert(other.to_hex(), cursor(Some(later), Some(later), 5_000));
+    assert_eq!(
+        message_status(msg, me, &participants, &cursors, &none),
+        (ReceiptStatus::Read, Some(DateTime::from_millis(5_000))),
+    );
+}
+
+#[test]
+fn test_hidden_readers_only_count_as_delivered() {
+    let (me, other) = (ObjectId::new(), ObjectId::new());
+    let msg = ObjectId::new();
+    let cursors = HashMap::from([(other.to_hex(), cursor(None, Some(msg), 1_000))]);
+    let hidden = HashSet::from([other]);
+    assert_eq!(message_status(msg, me, &[me, other], &cursors, &hidden), (ReceiptStatus::Delivered, None));
+}
+
+#[test]
+fn test_group_status_waits_for_everyone() {
+    let (me, a, b, c) = (ObjectId::new(), ObjectId::new(), ObjectId::new(), ObjectId::new());
+    let msg = ObjectId::new();
+    let participants = [me, a, b, c];
+    let none = HashSet::new();
+
+    let mut cursors = HashMap::from([
+        (a.to_hex(), cursor(Some(msg), Some(msg), 1_000)),
+        (b.to_hex(), cursor(Some(msg), Some(msg), 3_000)),
+    ]);
+    assert_eq!(message_status(msg, me, &participants, &cursors, &none).0, ReceiptStatus::Sent, "c hasn't got it yet");
+
+    cursors.insert(c.to_hex(), cursor(Some(msg), None, 0));
+    assert_eq!(message_status(msg, me, &participants, &cursors, &none), (ReceiptStatus::Delivered, None));
+
+    cursors.insert(c.to_hex(), cursor(Some(msg), Some(msg), 2_000));
+    assert_eq!(
+        message_status(msg, me, &participants, &cursors, &none),
+        (ReceiptStatus::Read, Some(DateTime::from_millis(3_000))),
+        "read when the last of them read it",
+    );
+}
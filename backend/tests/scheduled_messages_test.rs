@@ -0,0 +1,80 @@
+// Scheduled messages: which send times are accepted, what the sweep claims, where a
+// delivery attempt leaves a message, and what can still be cancelled.
+
+use axum::{http::StatusCode, Json};
+use bson::{doc, oid::ObjectId, DateTime};
+use chrono::{Duration, TimeZone, Utc};
+use karuteens_backend::features::social::scheduled_messages::{
+    cancel_filter, delivery_outcome, due_filter, stale_sending_filter, validate_send_at, DeliveryOutcome, MAX_SCHEDULE_DAYS,
+    SENDING_TIMEOUT_MINUTES,
+};
+
+#[test]
+fn test_validate_send_at() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+
+    assert_eq!(validate_send_at("2026-10-18T13:30:00Z", now), Ok(now + Duration::minutes(90)));
+    assert_eq!(
+        validate_send_at("2026-10-19T09:00:00+03:00", now),
+        Ok(Utc.with_ymd_and_hms(2026, 10, 19, 6, 0, 0).unwrap()),
+        "offsets are normalised to UTC",
+    );
+
+    assert!(validate_send_at("tomorrow at nine", now).is_err());
+    assert!(validate_send_at("2026-10-18T12:00:30Z", now).is_err(), "under a minute out");
+    assert!(validate_send_at("2026-10-18T15:00:00+03:00", now).is_err(), "that is now, in Nairobi time");
+    assert!(validate_send_at("2026-10-17T12:00:00Z", now).is_err(), "in the past");
+    let too_far = (now + Duration::days(MAX_SCHEDULE_DAYS) + Duration::minutes(1)).to_rfc3339();
+    assert!(validate_send_at(&too_far, now).is_err());
+    let furthest = now + Duration::days(MAX_SCHEDULE_DAYS);
+    assert_eq!(validate_send_at(&furthest.to_rfc3339(), now), Ok(furthest));
+}
+
+#[test]
+fn test_sweep_claims_due_pending_messages_only() {
+    let now = DateTime::from_chrono(Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap());
+    assert_eq!(due_filter(now), doc! { "status": "pending", "send_at": { "$lte": now } });
+}
+
+#[test]
+fn test_stale_sending_claims_are_reaped() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+    let filter = stale_sending_filter(now);
+    assert_eq!(filter.get_str("status").unwrap(), "sending");
+    // Only claims older than the timeout; a sweep still delivering keeps its message
+    assert_eq!(
+        filter.get_document("updated_at").unwrap().get_datetime("$lt").unwrap().to_chrono(),
+        now - Duration::minutes(SENDING_TIMEOUT_MINUTES),
+    );
+}
+
+#[test]
+fn test_delivery_outcomes() {
+    let message_id = ObjectId::new();
+    assert_eq!(delivery_outcome(Ok(message_id)), DeliveryOutcome::Sent(message_id));
+
+    // Server errors are ours: the message goes back to pending and the job retries
+    let outage = delivery_outcome(Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({ "error": "db down" })))));
+    assert!(matches!(outage, DeliveryOutcome::Retry(_)), "{:?}", outage);
+
+    // Refusals fail the message with the reason the sender will see
+    assert_eq!(
+        delivery_outcome(Err((StatusCode::FORBIDDEN, Json(serde_json::json!({ "error": "You can't message this user" }))))),
+        DeliveryOutcome::Failed("You can't message this user".to_string()),
+    );
+    assert_eq!(
+        delivery_outcome(Err((StatusCode::NOT_FOUND, Json(serde_json::json!(null))))),
+        DeliveryOutcome::Failed("Delivery was refused".to_string()),
+    );
+}
+
+#[test]
+fn test_only_the_sender_cancels_unsent_messages() {
+    let id = ObjectId::new();
+    let sender = ObjectId::new();
+    assert_eq!(
+        cancel_filter(id, sender),
+        doc! { "_id": id, "sender_id": sender, "status": { "$in": ["pending", "failed"] } },
+        "sending, sent and cancelled messages are left alone",
+    );
+}
@@ -0,0 +1,82 @@
+// Scheduled posts: accepted publish dates, what the sweep publishes, how edits reschedule or
+// cancel, and which followers a retried fan-out page skips.
+
+use bson::{oid::ObjectId, Bson, DateTime};
+use chrono::{Duration, TimeZone, Utc};
+use karuteens_backend::features::content::posts::{parse_schedule_date, schedule_edit};
+use karuteens_backend::features::content::scheduled_posts::{already_notified_filter, due_filter, publish_update};
+use karuteens_backend::features::infrastructure::error::AppError;
+
+#[test]
+fn test_parse_schedule_date() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+
+    assert_eq!(
+        parse_schedule_date("2026-10-18T13:00:00Z", now).unwrap(),
+        DateTime::from_chrono(now + Duration::hours(1)),
+    );
+    assert_eq!(
+        parse_schedule_date("2026-10-19T09:00:00+03:00", now).unwrap(),
+        DateTime::from_chrono(Utc.with_ymd_and_hms(2026, 10, 19, 6, 0, 0).unwrap()),
+        "offsets are normalised to UTC",
+    );
+
+    assert!(matches!(parse_schedule_date("next friday", now), Err(AppError::BadRequest(_))));
+    assert!(matches!(parse_schedule_date("2026-10-18T12:00:00Z", now), Err(AppError::BadRequest(_))), "now is not the future");
+    assert!(matches!(parse_schedule_date("2026-10-18T14:00:00+03:00", now), Err(AppError::BadRequest(_))), "in the past, in Nairobi time");
+}
+
+#[test]
+fn test_sweep_publishes_only_due_scheduled_posts() {
+    let now = DateTime::now();
+    let filter = due_filter(now);
+    // Matching on status is the compare-and-set: a post cancelled back to draft is left alone
+    assert_eq!(filter.get_str("status").unwrap(), "scheduled");
+    assert_eq!(filter.get_document("scheduled_publish_date").unwrap().get_datetime("$lte").unwrap(), &now);
+
+    let set = publish_update(now);
+    let set = set.get_document("$set").unwrap();
+    assert_eq!(set.get_str("status").unwrap(), "published");
+    assert_eq!(set.get_datetime("published_at").unwrap(), &now);
+}
+
+#[test]
+fn test_reschedule_through_an_edit() {
+    let at = DateTime::from_chrono(Utc.with_ymd_and_hms(2026, 11, 1, 8, 0, 0).unwrap());
+
+    for status in ["draft", "scheduled"] {
+        let edit = schedule_edit(status, false, Some(at)).unwrap().unwrap();
+        assert_eq!(edit.set.get_str("status").unwrap(), "scheduled");
+        assert_eq!(edit.set.get_datetime("scheduled_publish_date").unwrap(), &at);
+        // The sweep may publish it between the read and the write
+        assert_eq!(edit.filter.get_document("status").unwrap().get_str("$ne").unwrap(), "published");
+        assert!(edit.unset.is_empty());
+    }
+
+    assert!(matches!(schedule_edit("published", false, Some(at)), Err(AppError::Conflict(_))));
+    assert!(schedule_edit("published", false, None).unwrap().is_none(), "edits without a date leave the schedule alone");
+}
+
+#[test]
+fn test_cancel_through_an_edit() {
+    let edit = schedule_edit("scheduled", true, None).unwrap().unwrap();
+    assert_eq!(edit.filter.get_str("status").unwrap(), "scheduled");
+    assert_eq!(edit.set.get_str("status").unwrap(), "draft");
+    assert!(edit.unset.contains_key("scheduled_publish_date"));
+
+    assert!(matches!(schedule_edit("draft", true, None), Err(AppError::BadRequest(_))));
+    assert!(matches!(schedule_edit("published", true, None), Err(AppError::BadRequest(_))));
+}
+
+#[test]
+fn test_retried_fanout_page_finds_already_notified_followers() {
+    let (post, author) = (ObjectId::new(), ObjectId::new());
+    let followers = vec![ObjectId::new(), ObjectId::new()];
+    let filter = already_notified_filter(post, author, &followers);
+
+    let ids: Vec<Bson> = followers.iter().map(|id| Bson::ObjectId(*id)).collect();
+    assert_eq!(filter.get_document("user_id").unwrap().get_array("$in").unwrap(), &ids);
+    assert_eq!(filter.get_object_id("actor_id").unwrap(), author);
+    assert_eq!(filter.get_object_id("target_id").unwrap(), post);
+    assert_eq!(filter.get_str("notification_type").unwrap(), "new_post");
+}
@@ -0,0 +1,189 @@
+// Search index tests: analysis chain, ranking, filters, facets and cursor pagination
+// These run against the in-process index without needing the database
+
+use karuteens_backend::features::infrastructure::search::group_document;
+use karuteens_backend::features::infrastructure::search_index::{
+    edit_distance, stem, tokenize, DocKind, SearchDocument, SearchFilters, SearchIndex, SearchRequest, SortBy,
+};
+
+fn post(id: &str, title: &str, content: &str, likes: i64, has_media: bool, tags: &[&str]) -> SearchDocument {
+    SearchDocument {
+        kind: DocKind::Post,
+        id: id.to_string(),
+        fields: vec![(title.to_string(), 2.0), (content.to_string(), 1.0)],
+        school: None,
+        year_of_study: None,
+        category: Some("general".to_string()),
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+        has_media,
+        post_type: Some("text".to_string()),
+        language: Some("en".to_string()),
+        created_at: likes * 1000,
+        popularity: likes,
+        payload: serde_json::json!({ "id": id }),
+    }
+}
+
+fn user(id: &str, username: &str, school: &str, year: i32) -> SearchDocument {
+    SearchDocument {
+        kind: DocKind::User,
+        id: id.to_string(),
+        fields: vec![(username.to_string(), 3.0)],
+        school: Some(school.to_string()),
+        year_of_study: Some(year),
+        category: None,
+        tags: Vec::new(),
+        has_media: false,
+        post_type: None,
+        language: None,
+        created_at: 0,
+        popularity: 0,
+        payload: serde_json::json!({ "user_id": id }),
+    }
+}
+
+fn request(query: &str, kinds: Vec<DocKind>) -> SearchRequest {
+    SearchRequest {
+        query: query.to_string(),
+        kinds,
+        filters: SearchFilters::default(),
+        sort: SortBy::Relevance,
+        limit: 10,
+        cursor: None,
+    }
+}
+
+fn ids(index: &SearchIndex, req: &SearchRequest) -> Vec<String> {
+    index.search(req).hits.into_iter().map(|h| h.id).collect()
+}
+
+#[test]
+fn test_tokenize_stems_english_and_swahili() {
+    assert_eq!(stem("studies"), "study");
+    assert_eq!(stem("running"), "run");
+    assert_eq!(stem("notes"), "note");
+    assert_eq!(stem("somesha"), "soma");
+    assert_eq!(stem("pendana"), "penda");
+    assert_eq!(tokenize("The Exams na Revision"), vec!["exam", "revision"]);
+    // Sheng variants and elongated spellings fold together
+    assert_eq!(tokenize("mathree poaaa"), vec!["matatu", "poa"]);
+}
+
+#[test]
+fn test_edit_distance_handles_transpositions() {
+    assert_eq!(edit_distance("exam", "exam"), 0);
+    assert_eq!(edit_distance("exma", "exam"), 1);
+    assert_eq!(edit_distance("calculus", "calclus"), 1);
+}
+
+#[test]
+fn test_bm25_ranks_title_matches_higher() {
+    let index = SearchIndex::new();
+    index.upsert(post("a", "Campus life", "calculus revision notes are here", 0, false, &[]));
+    index.upsert(post("b", "Calculus revision", "past papers", 0, false, &[]));
+    index.upsert(post("c", "Football", "match today", 0, false, &[]));
+
+    assert_eq!(ids(&index, &request("calculus", vec![DocKind::Post])), vec!["b", "a"]);
+}
+
+#[test]
+fn test_prefix_and_typo_matching() {
+    let index = SearchIndex::new();
+    index.upsert(post("a", "Chemistry practicals", "lab manual", 0, false, &[]));
+    index.upsert(post("b", "Physics", "lab manual", 0, false, &[]));
+
+    assert_eq!(ids(&index, &request("chemi", vec![DocKind::Post])), vec!["a"]);
+    assert_eq!(ids(&index, &request("chemsitry", vec![DocKind::Post])), vec!["a"]);
+    // Every query term must match
+    assert!(ids(&index, &request("physics chemistry", vec![DocKind::Post])).is_empty());
+}
+
+#[test]
+fn test_upsert_and_remove_keep_index_consistent() {
+    let index = SearchIndex::new();
+    index.upsert(post("a", "Hostel keja", "near gate", 0, false, &[]));
+    assert_eq!(ids(&index, &request("nyumba", vec![DocKind::Post])), vec!["a"]);
+
+    index.upsert(post("a", "Library hours", "open late", 0, false, &[]));
+    assert!(ids(&index, &request("keja", vec![DocKind::Post])).is_empty());
+    assert_eq!(ids(&index, &request("library", vec![DocKind::Post])), vec!["a"]);
+
+    index.remove(DocKind::Post, "a");
+    assert!(index.is_empty());
+}
+
+#[test]
+fn test_filters_apply_per_kind_and_facets_count_matches() {
+    let index = SearchIndex::new();
+    index.upsert(user("u1", "karu_coder", "Karatina University", 2));
+    index.upsert(user("u2", "coder_ke", "Kenyatta University", 2));
+    index.upsert(post("p1", "Coder meetup", "bring laptops", 5, true, &["tech"]));
+    index.upsert(post("p2", "Coder jokes", "lol", 1, false, &["fun"]));
+
+    let mut req = request("coder", vec![DocKind::User, DocKind::Post]);
+    req.filters = SearchFilters { school: Some("karatina university".to_string()), ..Default::default() };
+    let page = index.search(&req);
+    let mut found: Vec<String> = page.hits.iter().map(|h| h.id.clone()).collect();
+    found.sort();
+    assert_eq!(found, vec!["p1", "p2", "u1"]);
+    assert_eq!(page.facets.school.get("Karatina University"), Some(&1));
+    assert_eq!(page.facets.has_media.get(&true), Some(&1));
+
+    req.filters = SearchFilters { has_media: Some(true), tags: vec!["tech".to_string()], ..Default::default() };
+    req.kinds = vec![DocKind::Post];
+    assert_eq!(ids(&index, &req), vec!["p1"]);
+}
+
+#[test]
+fn test_cursor_pagination_walks_all_results_once() {
+    let index = SearchIndex::new();
+    for i in 0..7 {
+        index.upsert(post(&format!("p{}", i), "Revision timetable", "exam week", i, false, &[]));
+    }
+
+    let mut req = request("revision", vec![DocKind::Post]);
+    req.sort = SortBy::Popular;
+    req.limit = 3;
+
+    let mut seen = Vec::new();
+    loop {
+        let page = index.search(&req);
+        assert_eq!(page.total, 7);
+        seen.extend(page.hits.into_iter().map(|h| h.id));
+        match page.next_cursor {
+            Some(cursor) => req.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(seen, vec!["p6", "p5", "p4", "p3", "p2", "p1", "p0"]);
+}
+
+#[test]
+fn test_group_made_private_leaves_the_index() {
+    let id = bson::oid::ObjectId::new();
+    let now = bson::DateTime::now();
+    let mut group = karuteens_backend::models::Group {
+        id: Some(id),
+        name: "Chess club".to_string(),
+        description: "Weekly games at the library".to_string(),
+        category: "clubs".to_string(),
+        avatar_url: None,
+        cover_url: None,
+        creator_id: bson::oid::ObjectId::new(),
+        admins: Vec::new(),
+        members: Vec::new(),
+        is_private: false,
+        max_members: None,
+        created_at: now,
+        updated_at: now,
+    };
+    let index = SearchIndex::new();
+    index.upsert(group_document(&group).expect("public groups are searchable"));
+    assert_eq!(ids(&index, &request("chess", vec![DocKind::Group])), vec![id.to_hex()]);
+
+    // What the update hook does once the re-read group has no document
+    group.is_private = true;
+    assert!(group_document(&group).is_none());
+    index.remove(DocKind::Group, &id.to_hex());
+    assert!(ids(&index, &request("chess", vec![DocKind::Group])).is_empty());
+}
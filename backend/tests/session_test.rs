@@ -0,0 +1,128 @@
+// Session token helpers: refresh token format, hashing, client address extraction, and
+// refresh token rotation with reuse detection
+
+use axum::http::HeaderMap;
+use chrono::{Duration, Utc};
+use karuteens_backend::features::auth::sessions::{
+    client_ip, forwarded_ip, hash_refresh_secret, rotation_failure, rotation_filter, rotation_update,
+    split_refresh_token, RotationFailure,
+};
+use karuteens_backend::models::Session;
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+
+fn session(current: &str, previous: &[&str], rotated_secs_ago: i64) -> Session {
+    let now = Utc::now();
+    Session {
+        id: Some(ObjectId::new()),
+        user_id: ObjectId::new(),
+        refresh_token_hash: hash_refresh_secret(current),
+        previous_token_hashes: previous.iter().map(|s| hash_refresh_secret(s)).collect(),
+        device_id: None,
+        device_name: None,
+        user_agent: None,
+        ip_address: None,
+        created_at: DateTime::from_chrono(now - Duration::days(1)),
+        last_seen_at: DateTime::from_chrono(now),
+        last_rotated_at: DateTime::from_chrono(now - Duration::seconds(rotated_secs_ago)),
+        expires_at: DateTime::from_chrono(now + Duration::days(30)),
+        revoked_at: None,
+        revoked_reason: None,
+    }
+}
+
+#[test]
+fn test_split_refresh_token() {
+    let sid = ObjectId::new();
+    let token = format!("{}.abc-DEF_123", sid.to_hex());
+    let (parsed, secret) = split_refresh_token(&token).expect("valid token");
+    assert_eq!(parsed, sid);
+    assert_eq!(secret, "abc-DEF_123");
+
+    assert!(split_refresh_token("not-a-token").is_none());
+    assert!(split_refresh_token("nothex.secret").is_none());
+    assert!(split_refresh_token(&format!("{}.", sid.to_hex())).is_none());
+}
+
+#[test]
+fn test_hash_refresh_secret_is_stable_hex() {
+    let a = hash_refresh_secret("secret");
+    assert_eq!(a, hash_refresh_secret("secret"));
+    assert_ne!(a, hash_refresh_secret("secret2"));
+    assert_eq!(a.len(), 64);
+    assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+}
+
+#[test]
+fn test_client_ip_prefers_first_forwarded_address() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(client_ip(&headers), None);
+
+    headers.insert("x-real-ip", "10.0.0.2".parse().unwrap());
+    assert_eq!(client_ip(&headers).as_deref(), Some("10.0.0.2"));
+
+    headers.insert("x-forwarded-for", "41.90.1.7, 10.0.0.1".parse().unwrap());
+    assert_eq!(client_ip(&headers).as_deref(), Some("41.90.1.7"));
+}
+
+#[test]
+fn test_forwarded_ip_only_trusts_proxy_hops() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-real-ip", "196.201.214.200".parse().unwrap());
+    assert_eq!(forwarded_ip(&headers, 1), None, "x-real-ip comes from the client");
+
+    // The client claims to be Safaricom; our one proxy appends what it actually saw
+    headers.insert("x-forwarded-for", "196.201.214.200, 41.90.1.7".parse().unwrap());
+    assert_eq!(forwarded_ip(&headers, 0), None, "no trusted proxies: ignore the header");
+    assert_eq!(forwarded_ip(&headers, 1).as_deref(), Some("41.90.1.7"));
+    assert_eq!(forwarded_ip(&headers, 2).as_deref(), Some("196.201.214.200"));
+    assert_eq!(forwarded_ip(&headers, 3), None, "fewer entries than hops");
+}
+
+#[test]
+fn test_rotation_only_matches_the_current_token_of_a_live_session() {
+    let sid = ObjectId::new();
+    let filter = rotation_filter(sid, &hash_refresh_secret("current"));
+    assert_eq!(filter.get_object_id("_id").unwrap(), sid);
+    assert_eq!(filter.get_str("refresh_token_hash").unwrap(), hash_refresh_secret("current"));
+    assert_eq!(filter.get("revoked_at"), Some(&Bson::Null));
+}
+
+#[test]
+fn test_rotation_swaps_the_token_and_remembers_the_old_one() {
+    let now = DateTime::now();
+    let expires = DateTime::from_chrono(Utc::now() + Duration::days(30));
+    let update = rotation_update("old-hash", "new-hash", now, expires, Some("41.90.1.7".to_string()));
+
+    let set = update.get_document("$set").unwrap();
+    assert_eq!(set.get_str("refresh_token_hash").unwrap(), "new-hash");
+    assert_eq!(set.get_datetime("last_rotated_at").unwrap(), &now);
+    assert_eq!(set.get_datetime("expires_at").unwrap(), &expires);
+    assert_eq!(set.get_str("ip_address").unwrap(), "41.90.1.7");
+
+    let pushed = update.get_document("$push").unwrap().get_document("previous_token_hashes").unwrap();
+    assert_eq!(pushed.get_array("$each").unwrap(), &vec![Bson::String("old-hash".to_string())]);
+    // Bounded history, newest kept
+    assert!(pushed.get_i32("$slice").unwrap() < 0);
+}
+
+#[test]
+fn test_replayed_token_revokes_the_family() {
+    // "t1" was rotated into "t2" a minute ago, then someone presents "t1" again
+    let s = session("t2", &["t0", "t1"], 60);
+    assert_eq!(rotation_failure(&s, &hash_refresh_secret("t1"), Utc::now()), RotationFailure::Reuse);
+    // Any older token in the family too
+    assert_eq!(rotation_failure(&s, &hash_refresh_secret("t0"), Utc::now()), RotationFailure::Reuse);
+}
+
+#[test]
+fn test_concurrent_refresh_within_grace_is_not_reuse() {
+    // Two tabs raced: one rotated "t1" two seconds ago, the other still holds it
+    let s = session("t2", &["t1"], 2);
+    assert_eq!(rotation_failure(&s, &hash_refresh_secret("t1"), Utc::now()), RotationFailure::InProgress);
+}
+
+#[test]
+fn test_unknown_token_is_just_invalid() {
+    let s = session("t2", &["t1"], 60);
+    assert_eq!(rotation_failure(&s, &hash_refresh_secret("forged"), Utc::now()), RotationFailure::Invalid);
+}
@@ -0,0 +1,119 @@
+// Spam classifier tests: rule compilation and the scoring heuristics, and what gets scored
+// on edits and confession comments, without Redis or Mongo.
+
+use karuteens_backend::features::content::posts::edited_spam_text;
+use karuteens_backend::features::infrastructure::spam::{compile_pattern, compile_rules, score_text, AuthorSignals, ContentKind};
+use karuteens_backend::features::social::confessions::ConfessionComment;
+use karuteens_backend::models::{Post, SpamDetectionRule};
+
+fn rule(name: &str, pattern: &str, score: f64, active: bool) -> SpamDetectionRule {
+    SpamDetectionRule {
+        id: None,
+        name: name.to_string(),
+        description: String::new(),
+        pattern: pattern.to_string(),
+        score,
+        category: "scam".to_string(),
+        is_active: active,
+        created_at: bson::DateTime::now(),
+        updated_at: bson::DateTime::now(),
+    }
+}
+
+fn established() -> AuthorSignals {
+    AuthorSignals { account_age_hours: 24 * 90, recent_writes: 1 }
+}
+
+#[test]
+fn test_compile_skips_inactive_and_broken_rules() {
+    let rules = compile_rules(&[
+        rule("loans", r"instant\s+loan", 0.6, true),
+        rule("off", r"crypto", 0.9, false),
+        rule("broken", r"(unclosed", 0.9, true),
+    ]);
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].name, "loans");
+    assert!(compile_pattern("(unclosed").is_err());
+}
+
+#[test]
+fn test_rule_scores_add_up_case_insensitively() {
+    let rules = compile_rules(&[rule("loans", r"instant\s+loan", 0.5, true), rule("mpesa", r"send\s+to\s+07\d{8}", 0.4, true)]);
+    let verdict = score_text("INSTANT LOAN approved, send to 0712345678 now", &rules, &established());
+    assert!((verdict.score - 0.9).abs() < 1e-9);
+    assert!(verdict.is_spam(0.7));
+    assert_eq!(verdict.reasons.len(), 2);
+
+    let clean = score_text("Anyone have notes for the stats CAT tomorrow?", &rules, &established());
+    assert_eq!(clean.score, 0.0);
+    assert!(clean.reasons.is_empty());
+}
+
+#[test]
+fn test_heuristics() {
+    let none: Vec<_> = vec![];
+    // Mostly links
+    let links = score_text("https://a.example https://b.example click", &none, &established());
+    assert!(links.score >= 0.4);
+    // One link in a normal sentence is fine
+    let one_link = score_text("The timetable for second years is up at https://karu.example/tt, check your unit codes", &none, &established());
+    assert_eq!(one_link.score, 0.0);
+    // Repetition
+    assert!(score_text("buy buy buy buy buy now", &none, &established()).score >= 0.3);
+    assert!(score_text("soooooooooooooooo good", &none, &established()).score >= 0.2);
+    // Scores are capped at 1
+    let rules = compile_rules(&[rule("a", "win", 0.9, true), rule("b", "free", 0.9, true)]);
+    assert_eq!(score_text("win free stuff", &rules, &established()).score, 1.0);
+}
+
+#[test]
+fn test_new_accounts_posting_fast_score_higher() {
+    let none: Vec<_> = vec![];
+    let text = "hello everyone";
+    let fresh_burst = AuthorSignals { account_age_hours: 2, recent_writes: 8 };
+    let old_burst = AuthorSignals { account_age_hours: 24 * 30, recent_writes: 8 };
+    let flood = AuthorSignals { account_age_hours: 24 * 30, recent_writes: 30 };
+    assert!(score_text(text, &none, &fresh_burst).score > 0.0);
+    assert_eq!(score_text(text, &none, &old_burst).score, 0.0);
+    assert!(score_text(text, &none, &flood).score > 0.0);
+}
+
+#[test]
+fn test_edits_are_rescored_with_the_edit_applied() {
+    let now = bson::DateTime::now();
+    let post: Post = bson::from_document(bson::doc! {
+        "_id": bson::oid::ObjectId::new(),
+        "author_id": bson::oid::ObjectId::new(),
+        "title": "Exam timetable",
+        "content": "Check the notice board",
+        "category": "general",
+        "status": "published",
+        "created_at": now,
+        "updated_at": now,
+    }).unwrap();
+
+    assert_eq!(edited_spam_text(&post, None, None), None);
+    assert_eq!(edited_spam_text(&post, Some("Exam timetable"), Some("Check the notice board")), None);
+    assert_eq!(
+        edited_spam_text(&post, None, Some("Send 500 to 0712345678 to pass")).as_deref(),
+        Some("Exam timetable\nSend 500 to 0712345678 to pass")
+    );
+    assert_eq!(
+        edited_spam_text(&post, Some("WIN CASH"), None).as_deref(),
+        Some("WIN CASH\nCheck the notice board")
+    );
+}
+
+#[test]
+fn test_confession_comments_have_their_own_review_kind() {
+    assert_eq!(ContentKind::ConfessionComment.as_str(), "confession_comment");
+    let legacy: ConfessionComment = bson::from_document(bson::doc! {
+        "confession_id": bson::oid::ObjectId::new(),
+        "author_id": bson::oid::ObjectId::new(),
+        "author_username": "kim",
+        "content": "same here",
+        "created_at": bson::DateTime::now(),
+    }).unwrap();
+    // Comments from before scoring are visible
+    assert!(legacy.status.is_none());
+}
@@ -0,0 +1,114 @@
+// Premium subscriptions: plans, renewal periods, the grace period and entitlements.
+
+use chrono::{Duration, TimeZone, Utc};
+use karuteens_backend::features::monetization::entitlements::{Entitlement, Entitlements};
+use karuteens_backend::features::monetization::subscriptions::{next_period, period_end_after_refund, Plan};
+use karuteens_backend::models::User;
+
+fn user(role: &str, is_premium: bool, expires_at: Option<chrono::DateTime<Utc>>) -> User {
+    let doc = bson::doc! {
+        "email": "student@karu.ac.ke",
+        "password_hash": "x",
+        "role": role,
+        "is_verified": true,
+        "is_premium": is_premium,
+        "premium_expires_at": expires_at.map(bson::DateTime::from_chrono),
+        "is_banned": false,
+        "banned_at": null,
+        "banned_by": null,
+        "created_at": bson::DateTime::now(),
+    };
+    bson::from_document(doc).unwrap()
+}
+
+#[test]
+fn test_plans() {
+    assert_eq!(Plan::parse("weekly"), Some(Plan::Weekly));
+    assert_eq!(Plan::parse("semester"), Some(Plan::Semester));
+    assert_eq!(Plan::parse("semi-annual"), Some(Plan::Semester), "older clients' name for the semester plan");
+    assert_eq!(Plan::parse("yearly"), None);
+    for plan in Plan::ALL {
+        assert_eq!(Plan::parse(plan.as_str()), Some(plan));
+        assert!(plan.price_kes() > 0);
+    }
+    assert_eq!(Plan::Monthly.duration(), Duration::days(30));
+}
+
+#[test]
+fn test_next_period_extends_running_and_grace_subscriptions() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+    let grace = Duration::days(3);
+
+    // First purchase starts now
+    assert_eq!(next_period(now, None, grace, Plan::Weekly), (now, now + Duration::days(7)));
+
+    // Renewing early stacks on top of the remaining time
+    let end = now + Duration::days(2);
+    assert_eq!(next_period(now, Some(end), grace, Plan::Monthly), (end, end + Duration::days(30)));
+
+    // Renewing in the grace period continues from the old end date
+    let end = now - Duration::days(1);
+    assert_eq!(next_period(now, Some(end), grace, Plan::Weekly), (end, end + Duration::days(7)));
+
+    // Lapsed for longer than the grace period: start over
+    let end = now - Duration::days(10);
+    assert_eq!(next_period(now, Some(end), grace, Plan::Weekly), (now, now + Duration::days(7)));
+}
+
+#[test]
+fn test_refund_takes_the_paid_period_back() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+
+    // A renewal stacked on remaining time: the earlier period is left
+    let end = now + Duration::days(32);
+    assert_eq!(period_end_after_refund(now, end, Plan::Monthly), Some(now + Duration::days(2)));
+
+    // The only payment: premium ends now, with no grace period
+    let end = now + Duration::days(25);
+    assert_eq!(period_end_after_refund(now, end, Plan::Monthly), None);
+    assert_eq!(period_end_after_refund(now, now + Duration::days(7), Plan::Weekly), None);
+}
+
+#[test]
+fn test_entitlements_follow_expiry_and_grace() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+    let grace = Duration::days(3);
+
+    let active = Entitlements::for_user(&user("premium", true, Some(now + Duration::days(5))), now, grace);
+    assert!(active.premium && !active.in_grace);
+    for e in Entitlement::ALL {
+        assert!(active.has(e), "{:?} should be granted", e);
+    }
+
+    let in_grace = Entitlements::for_user(&user("premium", true, Some(now - Duration::days(1))), now, grace);
+    assert!(in_grace.premium && in_grace.in_grace);
+    assert!(in_grace.has(Entitlement::StudyRooms));
+
+    let lapsed = Entitlements::for_user(&user("premium", true, Some(now - Duration::days(4))), now, grace);
+    assert!(!lapsed.premium);
+    assert!(lapsed.granted().is_empty());
+
+    let free = Entitlements::for_user(&user("student", false, None), now, grace);
+    assert!(!free.has(Entitlement::AdFree));
+    assert!(!free.has(Entitlement::HookupDiscovery));
+}
+
+#[test]
+fn test_staff_get_access_but_not_discounts() {
+    let now = Utc::now();
+    let admin = Entitlements::for_user(&user("admin", false, None), now, Duration::days(3));
+    assert!(admin.has(Entitlement::StudyRooms));
+    assert!(admin.has(Entitlement::AdFree));
+    assert!(!admin.has(Entitlement::MaterialDiscount));
+    assert_eq!(admin.material_price(10_000, 20), 10_000);
+}
+
+#[test]
+fn test_material_price_discount() {
+    let now = Utc::now();
+    let premium = Entitlements::for_user(&user("premium", true, Some(now + Duration::days(1))), now, Duration::days(3));
+    assert_eq!(premium.material_price(10_000, 20), 8_000);
+    assert_eq!(premium.material_price(10_000, 0), 10_000);
+    assert_eq!(premium.material_price(10_000, 150), 0, "discount is capped at 100%");
+    assert_eq!(Entitlements::none().material_price(10_000, 20), 10_000);
+}
@@ -0,0 +1,64 @@
+// Home timeline tests: the push/pull threshold, which posts get fanned out and
+// merging pushed and pulled entries into one cursor-paginated page.
+
+use bson::{doc, oid::ObjectId};
+use karuteens_backend::features::content::timeline::{fans_out_on_write, is_pushable, merge_entries};
+use karuteens_backend::models::Post;
+
+fn post(extra: bson::Document) -> Post {
+    let now = bson::DateTime::now();
+    let mut d = doc! {
+        "_id": ObjectId::new(),
+        "author_id": ObjectId::new(),
+        "category": "general",
+        "status": "published",
+        "created_at": now,
+        "updated_at": now,
+    };
+    d.extend(extra);
+    bson::from_document(d).unwrap()
+}
+
+#[test]
+fn test_fan_out_threshold() {
+    assert!(fans_out_on_write(0, 5000));
+    assert!(fans_out_on_write(5000, 5000));
+    assert!(!fans_out_on_write(5001, 5000));
+}
+
+#[test]
+fn test_only_public_profile_posts_are_pushed() {
+    assert!(is_pushable(&post(doc! {})));
+    assert!(!is_pushable(&post(doc! { "status": "draft" })));
+    assert!(!is_pushable(&post(doc! { "status": "scheduled" })));
+    assert!(!is_pushable(&post(doc! { "group_id": ObjectId::new() })));
+    assert!(!is_pushable(&post(doc! { "page_id": ObjectId::new() })));
+    assert!(!is_pushable(&post(doc! { "is_anonymous": true })));
+}
+
+#[test]
+fn test_merge_orders_dedupes_and_paginates() {
+    let ids: Vec<ObjectId> = (0..5).map(|_| ObjectId::new()).collect();
+    let pushed = vec![(500.0, ids[0]), (300.0, ids[2]), (100.0, ids[4])];
+    let pulled = vec![(400.0, ids[1]), (300.0, ids[2]), (200.0, ids[3])];
+
+    let (page, has_more) = merge_entries(vec![pushed.clone(), pulled.clone()], None, 3);
+    assert_eq!(page.iter().map(|e| e.1).collect::<Vec<_>>(), vec![ids[0], ids[1], ids[2]]);
+    assert!(has_more);
+
+    // The next page starts strictly after the last entry, without repeating it
+    let (page, has_more) = merge_entries(vec![pushed, pulled], page.last().copied(), 3);
+    assert_eq!(page.iter().map(|e| e.1).collect::<Vec<_>>(), vec![ids[3], ids[4]]);
+    assert!(!has_more);
+}
+
+#[test]
+fn test_merge_breaks_score_ties_by_id() {
+    let a = ObjectId::new();
+    let b = ObjectId::new(); // created later, so it sorts first on a tie
+    let (first, _) = merge_entries(vec![vec![(100.0, a)], vec![(100.0, b)]], None, 1);
+    assert_eq!(first, vec![(100.0, b)]);
+    let (rest, has_more) = merge_entries(vec![vec![(100.0, a)], vec![(100.0, b)]], Some(first[0]), 1);
+    assert_eq!(rest, vec![(100.0, a)]);
+    assert!(!has_more);
+}
@@ -0,0 +1,84 @@
+// TOTP and recovery code tests. Every check takes an explicit unix time, so the
+// clock is fixed and results are deterministic.
+
+use karuteens_backend::features::auth::two_factor::{
+    base32_encode, generate_recovery_codes, hash_recovery_code, hotp, open_secret, otpauth_uri,
+    seal_secret, totp_code, verify_totp,
+};
+
+// RFC 6238 Appendix B test secret (SHA1)
+const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+#[test]
+fn test_hotp_matches_rfc4226_vectors() {
+    let expected = [755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489];
+    for (counter, code) in expected.iter().enumerate() {
+        assert_eq!(hotp(RFC_SECRET, counter as u64), *code);
+    }
+}
+
+#[test]
+fn test_totp_matches_rfc6238_vectors() {
+    // Six-digit truncations of the RFC's eight-digit SHA1 values
+    assert_eq!(totp_code(RFC_SECRET, 59), "287082");
+    assert_eq!(totp_code(RFC_SECRET, 1111111109), "081804");
+    assert_eq!(totp_code(RFC_SECRET, 1234567890), "005924");
+    assert_eq!(totp_code(RFC_SECRET, 2000000000), "279037");
+}
+
+#[test]
+fn test_verify_totp_allows_one_step_of_drift() {
+    let now = 1_700_000_000;
+    let step = now / 30;
+    let current = totp_code(RFC_SECRET, now);
+    assert_eq!(verify_totp(RFC_SECRET, &current, now), Some(step));
+    assert_eq!(verify_totp(RFC_SECRET, &format!("{} {}", &current[..3], &current[3..]), now), Some(step));
+
+    let previous = totp_code(RFC_SECRET, now - 30);
+    assert_eq!(verify_totp(RFC_SECRET, &previous, now), Some(step - 1));
+
+    let stale = totp_code(RFC_SECRET, now - 90);
+    assert_eq!(verify_totp(RFC_SECRET, &stale, now), None);
+    assert_eq!(verify_totp(RFC_SECRET, "12345", now), None);
+    assert_eq!(verify_totp(RFC_SECRET, "abcdef", now), None);
+}
+
+#[test]
+fn test_base32_matches_rfc4648_vectors() {
+    assert_eq!(base32_encode(b""), "");
+    assert_eq!(base32_encode(b"f"), "MY");
+    assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+    assert_eq!(base32_encode(RFC_SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+}
+
+#[test]
+fn test_otpauth_uri_escapes_account() {
+    let uri = otpauth_uri("jane doe@karu.ac.ke", "MZXW6YTBOI");
+    assert_eq!(
+        uri,
+        "otpauth://totp/KaruTeens:jane%20doe%40karu.ac.ke?secret=MZXW6YTBOI&issuer=KaruTeens&algorithm=SHA1&digits=6&period=30"
+    );
+}
+
+#[test]
+fn test_recovery_codes_are_unique_and_hash_loosely() {
+    let codes = generate_recovery_codes();
+    assert_eq!(codes.len(), 10);
+    let mut unique = codes.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), codes.len());
+    assert!(codes.iter().all(|c| c.len() == 11 && c.chars().nth(5) == Some('-')));
+
+    assert_eq!(hash_recovery_code("abcde-fghjk"), hash_recovery_code(" ABCDEFGHJK "));
+    assert_ne!(hash_recovery_code("abcde-fghjk"), hash_recovery_code("abcde-fghjm"));
+}
+
+#[test]
+fn test_sealed_secret_round_trips_only_with_key() {
+    let key = [1u8; 32];
+    let sealed = seal_secret(&key, RFC_SECRET);
+    assert_ne!(sealed, seal_secret(&key, RFC_SECRET));
+    assert_eq!(open_secret(&key, &sealed).unwrap(), RFC_SECRET);
+    assert!(open_secret(&[2u8; 32], &sealed).is_none());
+}
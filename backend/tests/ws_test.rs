@@ -0,0 +1,93 @@
+// WebSocket gateway tests: topic naming and parsing, sequenced envelopes, replay gap
+// detection and presence transitions
+// Run with: cargo test --test ws_test
+
+use bson::oid::ObjectId;
+use karuteens_backend::features::social::ws::{chat_sender, is_ephemeral, presence_change, replay_gap, user_envelope, Topic, WsPayload};
+use karuteens_backend::models::ChatAlias;
+
+#[test]
+fn test_topic_round_trip() {
+    let id = ObjectId::new();
+    let topics = [
+        Topic::Chat(id),
+        Topic::StudyRoom(id),
+        Topic::Live(id),
+        Topic::PostComments(id),
+        Topic::Presence(id),
+    ];
+
+    for topic in topics {
+        assert_eq!(Topic::parse(&topic.name()), Some(topic));
+    }
+    assert_eq!(Topic::PostComments(id).name(), format!("post:{}:comments", id.to_hex()));
+}
+
+#[test]
+fn test_topic_parse_rejects_unknown_or_malformed() {
+    let id = ObjectId::new().to_hex();
+    assert_eq!(Topic::parse(&format!("room:{}", id)), None);
+    assert_eq!(Topic::parse("chat:not-an-id"), None);
+    assert_eq!(Topic::parse(&format!("post:{}", id)), None);
+    assert_eq!(Topic::parse(&format!("chat:{}:extra", id)), None);
+}
+
+#[test]
+fn test_durable_events_carry_their_sequence() {
+    let payload = WsPayload { r#type: "new_message".to_string(), data: serde_json::json!({ "id": "m1" }) };
+    let framed: serde_json::Value = serde_json::from_str(&user_envelope(&payload, Some(42))).unwrap();
+    assert_eq!(framed, serde_json::json!({ "type": "new_message", "data": { "id": "m1" }, "seq": 42 }));
+
+    // Sequencing failed: still delivered live, just not replayable
+    let framed: serde_json::Value = serde_json::from_str(&user_envelope(&payload, None)).unwrap();
+    assert!(framed.get("seq").is_none());
+}
+
+#[test]
+fn test_signaling_is_never_replayed() {
+    for kind in ["call-offer", "call-answer", "ice-candidate", "typing"] {
+        assert!(is_ephemeral(kind), "{} is stale by the time a client reconnects", kind);
+    }
+    for kind in ["new_message", "notification", "message_edited", "presence"] {
+        assert!(!is_ephemeral(kind));
+    }
+}
+
+#[test]
+fn test_replay_gap_detection() {
+    // Everything after 10 is still buffered
+    assert!(!replay_gap(10, Some(11), 15));
+    // Nothing new since the client went away
+    assert!(!replay_gap(15, None, 15));
+    // The buffer has rolled past the next event the client needs
+    assert!(replay_gap(10, Some(12), 40));
+    // The buffer expired while events were sent
+    assert!(replay_gap(10, None, 12));
+    // The counter was reset under a client holding an older, higher sequence
+    assert!(replay_gap(50, None, 3));
+    assert!(replay_gap(50, Some(1), 3));
+    // A fresh client starting at zero with a full buffer
+    assert!(!replay_gap(0, Some(1), 5));
+}
+
+#[test]
+fn test_presence_changes_on_first_and_last_connection() {
+    assert_eq!(presence_change(1, true), Some("online"));
+    // A second tab doesn't announce anything
+    assert_eq!(presence_change(2, true), None);
+    // Closing one of two tabs keeps the user online
+    assert_eq!(presence_change(1, false), None);
+    assert_eq!(presence_change(0, false), Some("offline"));
+    // The counter expired under a live socket: still treat the last close as going offline
+    assert_eq!(presence_change(-1, false), Some("offline"));
+}
+
+#[test]
+fn test_typing_in_anonymous_chats_shows_the_alias() {
+    let (user, alias) = (ObjectId::new(), ObjectId::new());
+    let aliases = vec![ChatAlias { user_id: user, alias_id: alias, alias_username: "quiet_fox".to_string() }];
+
+    assert_eq!(chat_sender(None, user), Some(user));
+    assert_eq!(chat_sender(Some(&aliases), user), Some(alias));
+    assert_eq!(chat_sender(Some(&aliases), ObjectId::new()), None, "no alias, nothing to show");
+}
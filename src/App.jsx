@@ -22,6 +22,7 @@ const BlogPage = lazy(() => import('./pages/BlogPage.jsx'));
 const BlogDetailPage = lazy(() => import('./pages/BlogDetailPage.jsx'));
 const VerificationPage = lazy(() => import('./pages/VerificationPage.jsx'));
 const ForgotPasswordPage = lazy(() => import('./pages/ForgotPasswordPage.jsx'));
+const VerifyEmailPage = lazy(() => import('./pages/VerifyEmailPage.jsx'));
 const GetAccountPage = lazy(() => import('./pages/GetAccountPage.jsx'));
 const PostDetailPage = lazy(() => import('./pages/PostDetailPage.jsx'));
 const LoginPage = lazy(() => import('./pages/LoginPage.jsx'));
@@ -90,6 +91,7 @@ function App() {
                                         path="/forgot-password"
                                         element={<ForgotPasswordPage />}
                                     />
+                                    <Route path="/verify-email" element={<VerifyEmailPage />} />
                                     <Route path="/get-account" element={<GetAccountPage />} />
                                 </Route>
 
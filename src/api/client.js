@@ -68,6 +68,53 @@ api.interceptors.request.use(
     },
 );
 
+// Access tokens are short-lived; one refresh is shared by every request that hit a 401 together
+let refreshPromise = null;
+
+const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+
+// Another tab rotated the same refresh token first (409); it writes the new pair to the
+// shared storage, so wait for that instead of treating the session as gone
+async function awaitOtherTabRefresh(presentedToken) {
+    for (let attempt = 0; attempt < 10; attempt++) {
+        await sleep(300);
+        const refreshToken = safeLocalStorage.getItem('refresh_token');
+        const token = safeLocalStorage.getItem('token');
+        if (refreshToken && token && refreshToken !== presentedToken) {
+            return token;
+        }
+    }
+    return null;
+}
+
+function refreshAccessToken() {
+    if (!refreshPromise) {
+        const refreshToken = safeLocalStorage.getItem('refresh_token');
+        refreshPromise = (
+            refreshToken
+                ? axios.post(`${baseUrl}/auth/refresh`, { refresh_token: refreshToken })
+                : Promise.reject(new Error('No refresh token'))
+        )
+            .then(({ data }) => {
+                safeLocalStorage.setItem('token', data.token);
+                safeLocalStorage.setItem('refresh_token', data.refresh_token);
+                window.dispatchEvent(new CustomEvent('auth:refreshed', { detail: data.token }));
+                return data.token;
+            })
+            .catch(async (refreshError) => {
+                if (refreshError.response?.status === 409) {
+                    const token = await awaitOtherTabRefresh(refreshToken);
+                    if (token) return token;
+                }
+                throw refreshError;
+            })
+            .finally(() => {
+                refreshPromise = null;
+            });
+    }
+    return refreshPromise;
+}
+
 // Response interceptor with enhanced error handling and BSON flattening
 api.interceptors.response.use(
     (response) => {
@@ -76,7 +123,26 @@ api.interceptors.response.use(
         }
         return response;
     },
-    (error) => {
+    async (error) => {
+        const original = error.config;
+        const isAuthCall = /\/auth\/(login|refresh|logout)$/.test(original?.url || '');
+        if (error.response?.status === 401 && original && !original._retried && !isAuthCall) {
+            original._retried = true;
+            try {
+                const token = await refreshAccessToken();
+                original.headers['Authorization'] = `Bearer ${token}`;
+                return api(original);
+            } catch (refreshError) {
+                // Only a rejected refresh token (or none at all) ends the session; a network
+                // error or a refresh race another tab never finished leaves it in place
+                const sessionGone =
+                    refreshError.response?.status === 401 || !safeLocalStorage.getItem('refresh_token');
+                if (!sessionGone) {
+                    return Promise.reject(parseApiError(refreshError));
+                }
+            }
+        }
+
         const appError = parseApiError(error);
 
         // Handle authentication errors
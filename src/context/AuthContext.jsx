@@ -50,6 +50,7 @@ export const AuthProvider = ({ children }) => {
             setUser(null);
             // Explicitly clear to avoid edge cases
             safeLocalStorage.removeItem('token');
+            safeLocalStorage.removeItem('refresh_token');
             safeLocalStorage.removeItem('user');
             logout.inProgress = false;
             // Reload to clear state and disconnect Ably
@@ -64,8 +65,8 @@ export const AuthProvider = ({ children }) => {
                 const decoded = jwtDecode(token);
                 const currentTime = Date.now() / 1000;
 
-                // If token is expired, logout
-                if (decoded.exp < currentTime) {
+                // An expired access token is renewed by the API client while a refresh token exists
+                if (decoded.exp < currentTime && !safeLocalStorage.getItem('refresh_token')) {
                     logout();
                 }
             } catch (err) {
@@ -85,6 +86,13 @@ export const AuthProvider = ({ children }) => {
         return () => window.removeEventListener('auth:unauthorized', handleUnauthorized);
     }, [logout]);
 
+    // Keep state in step with tokens rotated by the API client
+    useEffect(() => {
+        const handleRefreshed = (event) => setToken(event.detail);
+        window.addEventListener('auth:refreshed', handleRefreshed);
+        return () => window.removeEventListener('auth:refreshed', handleRefreshed);
+    }, []);
+
     const updateUser = (updatedUser) => {
         // Standardize user object from backend to ensure 'id' is always present
         const normalizedUser = {
@@ -94,7 +102,7 @@ export const AuthProvider = ({ children }) => {
         setUser(normalizedUser);
     };
 
-    const login = (newToken, newUser) => {
+    const login = (newToken, newUser, refreshToken) => {
         // Standardize user object from backend to ensure 'id' is always present
         const normalizedUser = {
             ...newUser,
@@ -104,6 +112,9 @@ export const AuthProvider = ({ children }) => {
         // Synchronously set local storage so immediate requests have the token
         safeLocalStorage.setItem('token', newToken);
         safeLocalStorage.setItem('user', JSON.stringify(normalizedUser));
+        if (refreshToken) {
+            safeLocalStorage.setItem('refresh_token', refreshToken);
+        }
 
         setToken(newToken);
         setUser(normalizedUser);
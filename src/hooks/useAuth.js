@@ -43,10 +43,9 @@ export const useLogin = () => {
         },
         onSuccess: (data) => {
             if (data.two_factor_required) {
-                showToast('2FA code sent to your email', 'info');
                 return;
             }
-            login(data.token, data.user);
+            login(data.token, data.user, data.refresh_token);
             showToast(`Welcome back, ${data.user.username}!`, 'success');
 
             const from = location.state?.from?.pathname || '/feed';
@@ -70,7 +69,7 @@ export const useVerify2FA = () => {
             return data;
         },
         onSuccess: (data) => {
-            login(data.token, data.user);
+            login(data.token, data.user, data.refresh_token);
             showToast(`Login verified! Welcome back, ${data.user.username}!`, 'success');
 
             const from = location.state?.from?.pathname || '/feed';
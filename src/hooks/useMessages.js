@@ -367,11 +367,12 @@ export const useToggleAdmin = (chatId) => {
 export const useSearchChatMessages = (chatId) => {
     return useMutation({
         mutationFn: async ({ q, before }) => {
-            if (!chatId || !q) return [];
-            const { data } = await api.get(`/messages/${chatId}/search`, {
+            if (!chatId || !q) return { results: [], scope: 'all' };
+            const { data, headers } = await api.get(`/messages/${chatId}/search`, {
                 params: { q, before },
             });
-            return data;
+            // Encrypted chats only search messages sent before encryption was turned on
+            return { results: data, scope: headers['x-search-scope'] || 'all' };
         },
     });
 };
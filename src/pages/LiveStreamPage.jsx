@@ -115,7 +115,7 @@ const LiveStreamPage = () => {
             const { data } = await api.post('/live/start', {
                 title: streamTitle || `${user?.username}'s Live`,
             });
-            setCurrentStream(data);
+            setCurrentStream(data.stream);
             setIsLive(true);
 
             if (ably) {
@@ -319,19 +319,17 @@ const LiveStreamPage = () => {
         setChatMessages([]);
     };
 
-    const sendMessage = (e) => {
+    const sendMessage = async (e) => {
         e.preventDefault();
-        if (!newMessage.trim() || !ably || !currentStream) return;
+        if (!newMessage.trim() || !currentStream) return;
 
-        const msg = {
-            user: user?.username || 'Guest',
-            text: newMessage,
-            timestamp: Date.now(),
-        };
-
-        const chatChannel = ably.channels.get(`live:${currentStream.user_id}:chat`);
-        chatChannel.publish('message', msg);
-        setNewMessage('');
+        // The server checks bans, slow mode and spam, then relays the line over Ably
+        try {
+            await api.post(`/live/${currentStream.id}/chat`, { content: newMessage });
+            setNewMessage('');
+        } catch (err) {
+            showToast(err.response?.data?.error || 'Failed to send message', 'error');
+        }
     };
 
     const sendHeartLocally = () => {
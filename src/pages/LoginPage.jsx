@@ -10,7 +10,7 @@ const LoginPage = () => {
     const [password, setPassword] = useState('');
     const [show2fa, setShow2fa] = useState(false);
     const [otp, setOtp] = useState('');
-    const [userIdFor2fa, setUserIdFor2fa] = useState(null);
+    const [challengeToken, setChallengeToken] = useState(null);
 
     const { mutate: login, isPending: isLoginPending } = useLogin();
     const { mutate: verify2fa, isPending: isVerifyPending } = useVerify2FA();
@@ -55,7 +55,7 @@ const LoginPage = () => {
                 return;
             }
             verify2fa(
-                { user_id: userIdFor2fa, code: otp },
+                { challenge_token: challengeToken, code: otp },
                 {
                     onError: (err) => {
                         const msg = err.response?.data?.error || 'Invalid verification code';
@@ -71,7 +71,7 @@ const LoginPage = () => {
                     onSuccess: (data) => {
                         if (data.two_factor_required) {
                             setShow2fa(true);
-                            setUserIdFor2fa(data.user_id);
+                            setChallengeToken(data.challenge_token);
                             showToast('Enter the 2FA code from your authenticator app', 'info');
                         }
                     },
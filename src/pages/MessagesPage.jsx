@@ -381,6 +381,7 @@ const MessagesPage = () => {
     const { mutate: searchChatMessages, isLoading: isSearchInChatLoading } =
         useSearchChatMessages(selectedChatId);
     const [chatSearchResults, setChatSearchResults] = useState([]);
+    const [chatSearchScope, setChatSearchScope] = useState('all');
 
     // WebRTC Integration
     const { user: currentUser } = useAuthContext();
@@ -453,14 +454,21 @@ const MessagesPage = () => {
                     if (!old) return old;
                     return old.map((m) => (m.id === msg.data.id ? msg.data : m));
                 });
-            } else if (msg.name === 'message_read') {
+            } else if (msg.name === 'message_edited') {
                 queryClient.setQueryData(['messages', selectedChatId], (old) => {
                     if (!old) return old;
                     return old.map((m) =>
-                        m.id === msg.data.id ? { ...m, read_at: msg.data.read_at } : m,
+                        m.id === msg.data.id
+                            ? { ...msg.data, is_me: m.is_me, status: m.status }
+                            : m,
                     );
                 });
+            } else if (msg.name === 'receipt') {
+                // In groups one reader's cursor isn't enough to decide the ticks, so refetch
+                queryClient.invalidateQueries({ queryKey: ['messages', selectedChatId] });
             } else if (msg.name === 'message_viewed') {
+                // In a group it stays visible to the members who haven't opened it yet
+                if (!msg.data.viewed_at && msg.data.viewer_id !== currentUser?.user_id) return;
                 queryClient.setQueryData(['messages', selectedChatId], (old) => {
                     if (!old) return old;
                     return old.map((m) =>
@@ -491,6 +499,19 @@ const MessagesPage = () => {
                         );
                     });
                 }
+            } else if (msg.name === 'message_expired') {
+                queryClient.setQueryData(['messages', selectedChatId], (old) => {
+                    if (!old) return old;
+                    // Viewed view-once media keeps its placeholder; timed-out messages go
+                    if (msg.data.reason === 'viewed') {
+                        return old.map((m) =>
+                            m.id === msg.data.id
+                                ? { ...m, content: 'Media viewed', attachment_url: null }
+                                : m,
+                        );
+                    }
+                    return old.filter((m) => m.id !== msg.data.id);
+                });
             }
             // Also invalidate chats to update the last message in sidebar
             queryClient.invalidateQueries({ queryKey: ['chats'] });
@@ -500,9 +521,11 @@ const MessagesPage = () => {
             [
                 'new_message',
                 'reaction_updated',
-                'message_read',
+                'receipt',
                 'message_viewed',
                 'message_deleted',
+                'message_expired',
+                'message_edited',
             ],
             subscription,
         );
@@ -511,9 +534,11 @@ const MessagesPage = () => {
                 [
                     'new_message',
                     'reaction_updated',
-                    'message_read',
+                    'receipt',
                     'message_viewed',
                     'message_deleted',
+                    'message_expired',
+                    'message_edited',
                 ],
                 subscription,
             );
@@ -662,6 +687,7 @@ const MessagesPage = () => {
     useEffect(() => {
         if (!chatSearchQuery.trim()) {
             setChatSearchResults([]);
+            setChatSearchScope('all');
             return;
         }
 
@@ -669,8 +695,9 @@ const MessagesPage = () => {
             searchChatMessages(
                 { q: chatSearchQuery },
                 {
-                    onSuccess: (data) => {
-                        setChatSearchResults(data);
+                    onSuccess: ({ results, scope }) => {
+                        setChatSearchResults(results);
+                        setChatSearchScope(scope);
                     },
                 },
             );
@@ -1384,14 +1411,26 @@ const MessagesPage = () => {
                                             {isSearchInChatLoading && (
                                                 <Loader2 size={14} className="animate-spin" />
                                             )}
-                                            {chatSearchResults.length > 0 && (
+                                            {(chatSearchResults.length > 0 ||
+                                                chatSearchScope === 'plaintext-only') && (
                                                 <div className="chat-search-results">
+                                                    {chatSearchScope === 'plaintext-only' && (
+                                                        <div className="chat-search-notice">
+                                                            Encrypted messages can only be
+                                                            searched on your device. Showing
+                                                            matches from before encryption was
+                                                            turned on.
+                                                        </div>
+                                                    )}
                                                     <div className="search-results-header">
                                                         <span>
                                                             {chatSearchResults.length} Results
                                                         </span>
                                                         <button
-                                                            onClick={() => setChatSearchResults([])}
+                                                            onClick={() => {
+                                                                setChatSearchResults([]);
+                                                                setChatSearchScope('all');
+                                                            }}
                                                         >
                                                             <X size={14} />
                                                         </button>
@@ -1717,6 +1756,9 @@ const MessagesPage = () => {
                                                     </div>
 
                                                     <div className="msg-footer">
+                                                        {msg.edited_at && !msg.is_deleted && (
+                                                            <span className="msg-edited">edited</span>
+                                                        )}
                                                         <span className="msg-time">
                                                             {new Date(
                                                                 msg.created_at,
@@ -1727,11 +1769,13 @@ const MessagesPage = () => {
                                                         </span>
                                                         {msg.is_me && (
                                                             <span
-                                                                className={`msg-status ${msg.read_at ? 'read' : ''} ${msg.id?.toString().startsWith('temp-') ? 'pending' : ''}`}
+                                                                className={`msg-status ${msg.status === 'read' || msg.read_at ? 'read' : ''} ${msg.id?.toString().startsWith('temp-') ? 'pending' : ''}`}
                                                             >
                                                                 {msg.id?.toString().startsWith('temp-') ? (
                                                                     <Clock size={12} className="status-pending" />
-                                                                ) : msg.read_at ? (
+                                                                ) : msg.status === 'read' ||
+                                                                  msg.status === 'delivered' ||
+                                                                  msg.read_at ? (
                                                                     <CheckCheck size={12} />
                                                                 ) : (
                                                                     <Check size={12} />
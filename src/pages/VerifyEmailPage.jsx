@@ -0,0 +1,63 @@
+import React, { useEffect, useRef, useState } from 'react';
+import { Link, useSearchParams } from 'react-router-dom';
+import { Loader2 } from 'lucide-react';
+import AuthLayout from '../layouts/AuthLayout.jsx';
+import api from '../api/client';
+
+const VerifyEmailPage = () => {
+    const [searchParams] = useSearchParams();
+    const [status, setStatus] = useState('pending'); // pending, success, error
+    const [error, setError] = useState('');
+    const requested = useRef(false);
+
+    useEffect(() => {
+        // Tokens are single-use, so guard against StrictMode's double effect
+        if (requested.current) return;
+        requested.current = true;
+
+        const token = searchParams.get('token');
+        if (!token) {
+            setStatus('error');
+            setError('This verification link is incomplete.');
+            return;
+        }
+        api.post('/auth/verify-email', { token })
+            .then(() => setStatus('success'))
+            .catch((err) => {
+                setStatus('error');
+                setError(err.response?.data?.error || err.message || 'Verification failed');
+            });
+    }, [searchParams]);
+
+    return (
+        <AuthLayout title="Confirm Email" subtitle="Confirming your email address.">
+            <div style={{ textAlign: 'center', padding: '2rem 0' }}>
+                {status === 'pending' && <Loader2 className="animate-spin" size={28} />}
+                {status === 'success' && (
+                    <>
+                        <h3>Email Confirmed</h3>
+                        <p>Thanks! Your email address is confirmed.</p>
+                    </>
+                )}
+                {status === 'error' && (
+                    <>
+                        <h3>Link Not Valid</h3>
+                        <p className="error-text">{error}</p>
+                        <p>You can request a new link from your account settings.</p>
+                    </>
+                )}
+                {status !== 'pending' && (
+                    <Link
+                        to="/login"
+                        className="btn btn-primary"
+                        style={{ marginTop: '1rem', display: 'inline-block' }}
+                    >
+                        Continue
+                    </Link>
+                )}
+            </div>
+        </AuthLayout>
+    );
+};
+
+export default VerifyEmailPage;